@@ -15,6 +15,7 @@
 // current directory is used to find the sources.
 
 use cranelift_codegen_meta as meta;
+use cranelift_isle as isle;
 
 use std::env;
 use std::process;
@@ -26,7 +27,7 @@ fn main() {
     let out_dir = env::var("OUT_DIR").expect("The OUT_DIR environment variable must be set");
     let target_triple = env::var("TARGET").expect("The TARGET environment variable must be set");
 
-    let new_backend_isas = if env::var("CARGO_FEATURE_X64").is_ok() {
+    let mut new_backend_isas = if env::var("CARGO_FEATURE_X64").is_ok() {
         // The x64 (new backend for x86_64) is a bit particular: it only requires generating
         // the shared meta code; the only ISA-specific code is for settings.
         vec![meta::isa::Isa::X86]
@@ -44,12 +45,26 @@ fn main() {
         })
         .collect::<Vec<_>>();
 
-    let old_backend_isas = if new_backend_isas.is_empty() && isa_targets.is_empty() {
+    // The riscv64 (new backend for RV64GC) is like x64: it only uses the shared meta code and
+    // the settings of the old RISC-V backend.
+    let has_riscv64 = env::var("CARGO_FEATURE_RISCV64").is_ok();
+    if has_riscv64 {
+        new_backend_isas.push(meta::isa::Isa::Riscv);
+    }
+
+    let old_backend_isas = if new_backend_isas.is_empty() && isa_targets.is_empty() && !has_riscv64
+    {
         // Try to match native target.
         let target_name = target_triple.split('-').next().unwrap();
-        let isa = meta::isa_from_arch(&target_name).expect("error when identifying target");
-        println!("cargo:rustc-cfg=feature=\"{}\"", isa);
-        vec![isa]
+        if target_name.starts_with("riscv64") {
+            println!("cargo:rustc-cfg=feature=\"riscv64\"");
+            new_backend_isas.push(meta::isa::Isa::Riscv);
+            vec![]
+        } else {
+            let isa = meta::isa_from_arch(&target_name).expect("error when identifying target");
+            println!("cargo:rustc-cfg=feature=\"{}\"", isa);
+            vec![isa]
+        }
     } else {
         isa_targets
     };
@@ -69,6 +84,37 @@ fn main() {
         process::exit(1);
     }
 
+    // Backends whose lowering is (partly) written in ISLE: the rules are compiled together with
+    // the shared prelude into one Rust file per backend.
+    let isle_backends = [(meta::isa::Isa::Arm64, "aarch64")];
+    for (isa, name) in isle_backends.iter() {
+        if !old_backend_isas.contains(isa) {
+            continue;
+        }
+        let inputs = [
+            std::path::PathBuf::from("src/prelude.isle"),
+            std::path::PathBuf::from(format!("src/isa/{}/lower.isle", name)),
+        ];
+        for input in inputs.iter() {
+            println!(
+                "cargo:rerun-if-changed={}",
+                crate_dir.join(input).to_str().unwrap()
+            );
+        }
+        let code = match isle::compile::compile_files(&inputs) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("Error compiling ISLE source:\n{}", err);
+                process::exit(1);
+            }
+        };
+        let out_file = std::path::Path::new(&out_dir).join(format!("isle_{}.rs", name));
+        if let Err(err) = std::fs::write(&out_file, code) {
+            eprintln!("Error writing {}: {}", out_file.display(), err);
+            process::exit(1);
+        }
+    }
+
     if env::var("CRANELIFT_VERBOSE").is_ok() {
         for isa in &old_backend_isas {
             println!(
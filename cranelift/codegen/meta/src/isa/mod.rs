@@ -5,7 +5,7 @@ use std::fmt;
 
 mod arm32;
 mod arm64;
-mod riscv;
+pub(crate) mod riscv;
 pub(crate) mod x86;
 
 /// Represents known ISA target.
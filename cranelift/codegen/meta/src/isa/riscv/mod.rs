@@ -11,7 +11,7 @@ use crate::shared::Definitions as SharedDefinitions;
 mod encodings;
 mod recipes;
 
-fn define_settings(shared: &SettingGroup) -> SettingGroup {
+pub(crate) fn define_settings(shared: &SettingGroup) -> SettingGroup {
     let mut setting = SettingGroupBuilder::new("riscv");
 
     let supports_m = setting.add_bool(
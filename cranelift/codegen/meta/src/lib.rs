@@ -116,7 +116,21 @@ pub fn generate(
             isa::Isa::Arm64 => {
                 // aarch64 doesn't have platform-specific settings.
             }
-            isa::Isa::Arm32 | isa::Isa::Riscv => todo!(),
+            isa::Isa::Riscv => {
+                // If the old backend ISAs contained riscv, this file has already been generated.
+                if old_backend_isas.iter().any(|isa| *isa == isa::Isa::Riscv) {
+                    continue;
+                }
+
+                let settings = crate::isa::riscv::define_settings(&shared_defs.settings);
+                gen_settings::generate(
+                    &settings,
+                    gen_settings::ParentGroup::Shared,
+                    "settings-riscv.rs",
+                    &out_dir,
+                )?;
+            }
+            isa::Isa::Arm32 => todo!(),
         }
     }
 
@@ -283,5 +283,49 @@ pub(crate) fn define() -> SettingGroup {
         true,
     );
 
+    settings.add_bool(
+        "enable_table_access_spectre_mitigation",
+        r#"
+        Enable Spectre mitigation on table bounds checks.
+
+        With this option, an out-of-bounds `table_addr` yields a null
+        address even when the bounds check is mispredicted, using a
+        conditional select that is never predicted.
+
+        This option is enabled by default because it is highly
+        recommended for secure sandboxing. The embedder should consider
+        the security implications carefully before disabling this option.
+        "#,
+        true,
+    );
+
+    settings.add_bool(
+        "enable_constant_time",
+        r#"
+        Generate branchless code for operations on possibly secret values.
+
+        With this option, `select`, `selectif`, `bint`, the integer and
+        floating-point min/max instructions and the saturating arithmetic
+        instructions are always lowered to conditional moves or selects
+        (`cmov`, `csel`) rather than to sequences containing conditional
+        branches, so that the control flow of the generated code does not
+        depend on their operands. This is meant for cryptographic code.
+
+        On x86-64, `fcvt_to_sint_sat` and `fcvt_to_uint_sat` are lowered to
+        conditional moves as well, and `udiv`, `urem` and `srem` rely on the
+        hardware exception to trap on division by zero instead of checking
+        the divisor first; `sdiv` computes whether its operands overflow
+        without branching and only branches to the trap on the result. The
+        division instructions themselves may still take a time that depends
+        on their operands. Branches to traps, such as this one, the division
+        checks on AArch64 and the checks of the trapping float-to-integer
+        conversions, are still emitted.
+
+        Only the new backends support this option; the legacy backends
+        refuse to compile functions when it is enabled.
+        "#,
+        false,
+    );
+
     settings.build()
 }
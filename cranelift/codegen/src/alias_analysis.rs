@@ -0,0 +1,232 @@
+//! Alias analysis and redundant-load elimination.
+//!
+//! Memory accesses are divided into disjoint *alias regions* by their `heap`, `table` and
+//! `vmctx` flags; accesses with none of these flags are in a fourth, "other" region. A store only
+//! clobbers the memory of its own region, while calls and atomic operations clobber all of them.
+//!
+//! The analysis first computes, for every program point and region, the *last store*: the most
+//! recent instruction that may have written memory of that region. Where control flow merges
+//! different last stores, the first instruction of the merge block stands in for all of them.
+//!
+//! Two accesses to the same address, offset and type that see the same last store for their
+//! region then access the same, unchanged, memory. So a load can be replaced by the value of a
+//! dominating load from the same location (redundant-load elimination), or by the value written
+//! by a dominating store to that location (store-to-load forwarding).
+//!
+//! Only accesses in the `heap`, `table` and `vmctx` regions are optimized. Memory in the "other"
+//! region may be shared with the embedder or with other threads, for example a flag that a loop
+//! polls while another thread sets it, so its loads are always kept. Stores to it are still
+//! tracked, as they clobber that region.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::SecondaryMap;
+use crate::flowgraph::ControlFlowGraph;
+use crate::fx::{FxHashMap, FxHashSet};
+use crate::inst_predicates::{has_memory_fence_semantics, inst_addr_offset_type, inst_store_data};
+use crate::ir::immediates::Offset32;
+use crate::ir::{Block, Function, Inst, Opcode, Type, Value};
+use crate::packed_option::PackedOption;
+use crate::timing;
+
+/// The last store of each alias region, at some program point.
+///
+/// `None` means that no store to the region was seen since the function entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct LastStores {
+    heap: PackedOption<Inst>,
+    table: PackedOption<Inst>,
+    vmctx: PackedOption<Inst>,
+    other: PackedOption<Inst>,
+}
+
+impl LastStores {
+    /// Update the last stores to the state after `inst`.
+    fn update(&mut self, func: &Function, inst: Inst) {
+        let opcode = func.dfg[inst].opcode();
+        if has_memory_fence_semantics(opcode) {
+            self.set_all(inst);
+        } else if opcode.can_store() {
+            match func.dfg[inst].memflags() {
+                Some(flags) if flags.heap() => self.heap = inst.into(),
+                Some(flags) if flags.table() => self.table = inst.into(),
+                Some(flags) if flags.vmctx() => self.vmctx = inst.into(),
+                Some(_) => self.other = inst.into(),
+                // A store without flags may write anywhere.
+                None => self.set_all(inst),
+            }
+        }
+    }
+
+    fn set_all(&mut self, inst: Inst) {
+        self.heap = inst.into();
+        self.table = inst.into();
+        self.vmctx = inst.into();
+        self.other = inst.into();
+    }
+
+    /// Get the last store of the region accessed by the load or store `inst`.
+    fn get_last_store(&self, func: &Function, inst: Inst) -> PackedOption<Inst> {
+        match func.dfg[inst].memflags() {
+            Some(flags) if flags.heap() => self.heap,
+            Some(flags) if flags.table() => self.table,
+            Some(flags) if flags.vmctx() => self.vmctx,
+            Some(_) => self.other,
+            // Without flags, the access can only be related to itself.
+            None => inst.into(),
+        }
+    }
+
+    /// Merge the last stores of another predecessor into these ones, at the start of a block
+    /// whose first instruction is `loc`. Differing last stores, including a store on one side
+    /// only, are replaced by `loc`.
+    fn meet_from(&mut self, other: &Self, loc: Inst) {
+        let meet = |a: PackedOption<Inst>, b: PackedOption<Inst>| -> PackedOption<Inst> {
+            if a == b {
+                a
+            } else {
+                loc.into()
+            }
+        };
+        self.heap = meet(self.heap, other.heap);
+        self.table = meet(self.table, other.table);
+        self.vmctx = meet(self.vmctx, other.vmctx);
+        self.other = meet(self.other, other.other);
+    }
+}
+
+/// A memory location, as accessed by a load or a store: two accesses with the same location
+/// access the same bytes, which hold the same value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct MemoryLoc {
+    last_store: PackedOption<Inst>,
+    address: Value,
+    offset: Offset32,
+    ty: Type,
+    /// The opcode of an extending load or truncating store; `None` for plain ones.
+    extending_opcode: Option<Opcode>,
+}
+
+fn extending_opcode(opcode: Opcode) -> Option<Opcode> {
+    match opcode {
+        Opcode::Load | Opcode::Store => None,
+        _ => Some(opcode),
+    }
+}
+
+/// Is the load or store `inst` in one of the `heap`, `table` or `vmctx` alias regions?
+fn has_alias_region(func: &Function, inst: Inst) -> bool {
+    func.dfg[inst].memflags().map_or(false, |flags| {
+        flags.heap() || flags.table() || flags.vmctx()
+    })
+}
+
+/// Compute the last stores at the entry of every block, iterating to a fixed point.
+///
+/// Blocks that are not reachable from the entry block are left as `None`.
+fn compute_block_input_states(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+) -> SecondaryMap<Block, Option<LastStores>> {
+    let mut block_input = SecondaryMap::new();
+    let entry = match func.layout.entry_block() {
+        Some(entry) => entry,
+        None => return block_input,
+    };
+
+    block_input[entry] = Some(LastStores::default());
+    let mut queue = vec![entry];
+    let mut queued = FxHashSet::default();
+    queued.insert(entry);
+
+    while let Some(block) = queue.pop() {
+        queued.remove(&block);
+        let mut state = block_input[block].unwrap();
+        for inst in func.layout.block_insts(block) {
+            state.update(func, inst);
+        }
+
+        for succ in cfg.succ_iter(block) {
+            let changed = match &mut block_input[succ] {
+                Some(succ_state) => {
+                    let old = *succ_state;
+                    succ_state.meet_from(&state, func.layout.first_inst(succ).unwrap());
+                    *succ_state != old
+                }
+                succ_state @ None => {
+                    *succ_state = Some(state);
+                    true
+                }
+            };
+            if changed && queued.insert(succ) {
+                queue.push(succ);
+            }
+        }
+    }
+
+    block_input
+}
+
+/// Replace redundant loads in `func` with the values loaded or stored earlier.
+pub fn do_alias_analysis(func: &mut Function, cfg: &ControlFlowGraph, domtree: &DominatorTree) {
+    let _tt = timing::alias_analysis();
+    debug_assert!(cfg.is_valid());
+    debug_assert!(domtree.is_valid());
+
+    let block_input = compute_block_input_states(func, cfg);
+
+    // The known contents of memory locations: the instruction that last accessed the location,
+    // and the value it loaded or stored.
+    let mut mem_values: FxHashMap<MemoryLoc, (Inst, Value)> = FxHashMap::default();
+
+    let mut pos = FuncCursor::new(func);
+    for &block in domtree.cfg_postorder().iter().rev() {
+        let mut state = block_input[block].unwrap();
+        pos.goto_top(block);
+        while let Some(inst) = pos.next_inst() {
+            // Resolve aliases, particularly those of the loads we removed.
+            pos.func.dfg.resolve_aliases_in_arguments(inst);
+
+            if let Some((address, offset, ty)) =
+                inst_addr_offset_type(pos.func, inst).filter(|_| has_alias_region(pos.func, inst))
+            {
+                let opcode = pos.func.dfg[inst].opcode();
+                if opcode.can_store() {
+                    let loc = MemoryLoc {
+                        last_store: inst.into(),
+                        address,
+                        offset,
+                        ty,
+                        extending_opcode: extending_opcode(opcode),
+                    };
+                    let data = inst_store_data(pos.func, inst).unwrap();
+                    mem_values.insert(loc, (inst, data));
+                } else if opcode.can_load() {
+                    let loc = MemoryLoc {
+                        last_store: state.get_last_store(pos.func, inst),
+                        address,
+                        offset,
+                        ty,
+                        extending_opcode: extending_opcode(opcode),
+                    };
+                    let result = pos.func.dfg.first_result(inst);
+                    match mem_values.get(&loc) {
+                        Some(&(def_inst, value))
+                            if domtree.dominates(def_inst, inst, &pos.func.layout) =>
+                        {
+                            pos.func.dfg.detach_results(inst);
+                            pos.func.dfg.change_to_alias(result, value);
+                            pos.remove_inst_and_step_back();
+                            continue;
+                        }
+                        _ => {
+                            mem_values.insert(loc, (inst, result));
+                        }
+                    }
+                }
+            }
+
+            state.update(pos.func, inst);
+        }
+    }
+}
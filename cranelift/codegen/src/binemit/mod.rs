@@ -14,7 +14,7 @@ pub use self::memorysink::{
 };
 pub use self::relaxation::relax_branches;
 pub use self::shrink::shrink_instructions;
-pub use self::stack_map::StackMap;
+pub use self::stack_map::{StackMap, UserStackMap};
 use crate::ir::entities::Value;
 use crate::ir::{
     ConstantOffset, ExternalName, Function, Inst, JumpTable, Opcode, SourceLoc, TrapCode,
@@ -58,14 +58,42 @@ pub enum Reloc {
     /// value is sign-extended, multiplied by 4, and added to the PC of
     /// the call instruction to form the destination address.
     Arm64Call,
+    /// Arm64 PC-relative page address of a symbol, for an `adrp`. Corresponds to ELF's
+    /// `R_AARCH64_ADR_PREL_PG_HI21`.
+    Arm64AdrPrelPgHi21,
+    /// Arm64 low 12 bits of a symbol address, for the `add` following an `adrp`. Corresponds to
+    /// ELF's `R_AARCH64_ADD_ABS_LO12_NC`.
+    Arm64AddAbsLo12Nc,
+    /// Arm64 PC-relative page address of the GOT entry of a symbol, for an `adrp`. Corresponds
+    /// to ELF's `R_AARCH64_ADR_GOT_PAGE`.
+    Arm64AdrGotPage21,
+    /// Arm64 low 12 bits of the address of the GOT entry of a symbol, for the 64-bit `ldr`
+    /// following an `adrp`. Corresponds to ELF's `R_AARCH64_LD64_GOT_LO12_NC`.
+    Arm64Ld64GotLo12Nc,
     /// RISC-V call target
     RiscvCall,
+    /// RISC-V call target, as an `auipc` / `jalr` pair. The 32-bit PC-relative
+    /// offset is split in a high 20-bit part (added to the `auipc`, rounded to
+    /// compensate for the sign-extended low part) and a low 12-bit part (the
+    /// `jalr` immediate). Corresponds to ELF's `R_RISCV_CALL_PLT`.
+    RiscvCallPlt,
 
     /// Elf x86_64 32 bit signed PC relative offset to two GOT entries for GD symbol.
     ElfX86_64TlsGd,
 
     /// Mach-O x86_64 32 bit signed PC relative offset to a `__thread_vars` entry.
     MachOX86_64Tlv,
+
+    /// Elf aarch64 PC-relative page address of the TLS descriptor of a symbol, for an `adrp`.
+    ElfAarch64TlsDescAdrPage21,
+    /// Elf aarch64 low 12 bits of the address of the TLS descriptor of a symbol, for the `ldr`
+    /// of the resolver function.
+    ElfAarch64TlsDescLd64Lo12,
+    /// Elf aarch64 low 12 bits of the address of the TLS descriptor of a symbol, for the `add`
+    /// computing the argument of the resolver function.
+    ElfAarch64TlsDescAddLo12,
+    /// Elf aarch64 marker for the `blr` calling the TLS descriptor resolver function.
+    ElfAarch64TlsDescCall,
 }
 
 impl fmt::Display for Reloc {
@@ -81,9 +109,18 @@ impl fmt::Display for Reloc {
             Self::X86CallPLTRel4 => write!(f, "CallPLTRel4"),
             Self::X86GOTPCRel4 => write!(f, "GOTPCRel4"),
             Self::Arm32Call | Self::Arm64Call | Self::RiscvCall => write!(f, "Call"),
+            Self::RiscvCallPlt => write!(f, "CallPlt"),
+            Self::Arm64AdrPrelPgHi21 => write!(f, "AdrPrelPgHi21"),
+            Self::Arm64AddAbsLo12Nc => write!(f, "AddAbsLo12Nc"),
+            Self::Arm64AdrGotPage21 => write!(f, "AdrGotPage21"),
+            Self::Arm64Ld64GotLo12Nc => write!(f, "Ld64GotLo12Nc"),
 
             Self::ElfX86_64TlsGd => write!(f, "ElfX86_64TlsGd"),
             Self::MachOX86_64Tlv => write!(f, "MachOX86_64Tlv"),
+            Self::ElfAarch64TlsDescAdrPage21 => write!(f, "ElfAarch64TlsDescAdrPage21"),
+            Self::ElfAarch64TlsDescLd64Lo12 => write!(f, "ElfAarch64TlsDescLd64Lo12"),
+            Self::ElfAarch64TlsDescAddLo12 => write!(f, "ElfAarch64TlsDescAddLo12"),
+            Self::ElfAarch64TlsDescCall => write!(f, "ElfAarch64TlsDescCall"),
         }
     }
 }
@@ -167,6 +167,32 @@ impl StackMap {
     }
 }
 
+/// The stack map of a safepoint for the values that a frontend declared as
+/// needing one, as described by `ir::UserStackMapEntry`.
+///
+/// Unlike a `StackMap`, which only tracks word-sized references, this lists
+/// each value's type along with its offset, in bytes, from the stack pointer at
+/// the safepoint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct UserStackMap {
+    entries: Vec<(ir::Type, u32)>,
+}
+
+impl UserStackMap {
+    /// Create a `UserStackMap` from the type and SP-relative offset of each
+    /// value.
+    pub fn new(entries: Vec<(ir::Type, u32)>) -> Self {
+        Self { entries }
+    }
+
+    /// Returns the type and SP-relative offset of each value in this stack
+    /// map.
+    pub fn entries(&self) -> &[(ir::Type, u32)] {
+        &self.entries
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -9,6 +9,7 @@
 //! contexts concurrently. Typically, you would have one context per compilation thread and only a
 //! single ISA instance.
 
+use crate::alias_analysis::do_alias_analysis;
 use crate::binemit::{
     relax_branches, shrink_instructions, CodeInfo, MemoryCodeSink, RelocSink, StackMapSink,
     TrapSink,
@@ -16,7 +17,7 @@ use crate::binemit::{
 use crate::dce::do_dce;
 use crate::dominator_tree::DominatorTree;
 use crate::flowgraph::ControlFlowGraph;
-use crate::ir::Function;
+use crate::ir::{Function, Opcode};
 use crate::isa::TargetIsa;
 use crate::legalize_function;
 use crate::legalizer::simple_legalize;
@@ -28,10 +29,11 @@ use crate::postopt::do_postopt;
 use crate::redundant_reload_remover::RedundantReloadRemover;
 use crate::regalloc;
 use crate::remove_constant_phis::do_remove_constant_phis;
-use crate::result::CodegenResult;
+use crate::result::{CodegenError, CodegenResult};
 use crate::settings::{FlagsOrIsa, OptLevel};
 use crate::simple_gvn::do_simple_gvn;
 use crate::simple_preopt::do_preopt;
+use crate::stats::CompileStats;
 use crate::timing;
 use crate::unreachable_code::eliminate_unreachable_code;
 use crate::value_label::{build_value_labels_ranges, ComparableSourceLoc, ValueLabelsRanges};
@@ -69,6 +71,9 @@ pub struct Context {
 
     /// Flag: do we want a disassembly with the MachCompileResult?
     pub want_disasm: bool,
+
+    /// Statistics about the last successful compilation of `func`, if any.
+    pub stats: Option<CompileStats>,
 }
 
 impl Context {
@@ -94,6 +99,7 @@ impl Context {
             redundant_reload_remover: RedundantReloadRemover::new(),
             mach_compile_result: None,
             want_disasm: false,
+            stats: None,
         }
     }
 
@@ -107,6 +113,7 @@ impl Context {
         self.redundant_reload_remover.clear();
         self.mach_compile_result = None;
         self.want_disasm = false;
+        self.stats = None;
     }
 
     /// Set the flag to request a disassembly when compiling with a
@@ -158,6 +165,22 @@ impl Context {
     ///
     /// Returns information about the function's code and read-only data.
     pub fn compile(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
+        // Time this compilation on its own, then add its timings back to those of the thread.
+        let outer_times = timing::take_current();
+        let result = self.compile_passes(isa);
+        let times = timing::take_current();
+        timing::add_to_current(&outer_times);
+        timing::add_to_current(&times);
+
+        self.stats = match &result {
+            Ok(info) => Some(self.collect_stats(info, &times)),
+            Err(_) => None,
+        };
+        result
+    }
+
+    /// Run all the compilation passes of `compile`.
+    fn compile_passes(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
         let _tt = timing::compile();
         self.verify_if(isa)?;
 
@@ -183,6 +206,9 @@ impl Context {
             self.compute_loop_analysis();
             self.licm(isa)?;
             self.simple_gvn(isa)?;
+            if opt_level == OptLevel::Speed || opt_level == OptLevel::SpeedAndSize {
+                self.replace_redundant_loads(isa)?;
+            }
         }
 
         self.compute_domtree();
@@ -199,6 +225,25 @@ impl Context {
             self.mach_compile_result = Some(result);
             Ok(info)
         } else {
+            // Only `MachBackend`s emit user stack maps.
+            let has_user_stack_maps = self.func.layout.blocks().any(|block| {
+                self.func
+                    .layout
+                    .block_insts(block)
+                    .any(|inst| self.func.dfg.user_stack_map_entries(inst).is_some())
+            });
+            if has_user_stack_maps {
+                return Err(CodegenError::Unsupported(
+                    "user stack maps on a legacy backend".into(),
+                ));
+            }
+            // The legacy backends lower selects and min/max operations to branches.
+            if isa.flags().enable_constant_time() {
+                return Err(CodegenError::Unsupported(
+                    "constant-time code generation on a legacy backend".into(),
+                ));
+            }
+
             self.regalloc(isa)?;
             self.prologue_epilogue(isa)?;
             if opt_level == OptLevel::Speed || opt_level == OptLevel::SpeedAndSize {
@@ -214,6 +259,32 @@ impl Context {
         }
     }
 
+    /// Gather the statistics of a compilation that produced `info` and took `times`.
+    fn collect_stats(&self, info: &CodeInfo, times: &timing::PassTimes) -> CompileStats {
+        let mut stats = CompileStats::from_pass_times(times);
+        stats.functions = 1;
+        stats.code_size = info.total_size;
+        if let Some(result) = &self.mach_compile_result {
+            stats.vcode_insts = result.stats.lowered_insts;
+            stats.spills = result.stats.spills;
+            stats.reloads = result.stats.reloads;
+            stats.regalloc_moves = result.stats.moves;
+        } else {
+            // The legacy register allocator inserts dedicated instructions.
+            for block in self.func.layout.blocks() {
+                for inst in self.func.layout.block_insts(block) {
+                    match self.func.dfg[inst].opcode() {
+                        Opcode::Spill | Opcode::Regspill => stats.spills += 1,
+                        Opcode::Fill | Opcode::Regfill => stats.reloads += 1,
+                        Opcode::Regmove => stats.regalloc_moves += 1,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        stats
+    }
+
     /// Emit machine code directly into raw memory.
     ///
     /// Write all of the function's machine code to the memory at `mem`. The size of the machine
@@ -405,6 +476,16 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Perform alias analysis on the function, and replace the loads whose value is already
+    /// known with that value.
+    pub fn replace_redundant_loads<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        do_alias_analysis(&mut self.func, &self.cfg, &self.domtree);
+        self.verify_if(fisa)
+    }
+
     /// Perform LICM on the function.
     pub fn licm(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         do_licm(
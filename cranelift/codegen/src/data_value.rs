@@ -19,6 +19,7 @@ pub enum DataValue {
     I16(i16),
     I32(i32),
     I64(i64),
+    I128(i128),
     U8(u8),
     U16(u16),
     U32(u32),
@@ -37,6 +38,7 @@ impl DataValue {
             types::I16 => Ok(DataValue::I16(imm as i16)),
             types::I32 => Ok(DataValue::I32(imm as i32)),
             types::I64 => Ok(DataValue::I64(imm)),
+            types::I128 => Ok(DataValue::I128(imm.into())),
             _ => Err(DataValueCastFailure::FromInteger(imm, ty)),
         }
     }
@@ -49,6 +51,7 @@ impl DataValue {
             DataValue::I16(_) | DataValue::U16(_) => types::I16,
             DataValue::I32(_) | DataValue::U32(_) => types::I32,
             DataValue::I64(_) | DataValue::U64(_) => types::I64,
+            DataValue::I128(_) => types::I128,
             DataValue::F32(_) => types::F32,
             DataValue::F64(_) => types::F64,
             DataValue::V128(_) => types::I8X16, // A default type.
@@ -71,6 +74,7 @@ impl DataValue {
             DataValue::I16(i) => ptr::write(p as *mut i16, *i),
             DataValue::I32(i) => ptr::write(p as *mut i32, *i),
             DataValue::I64(i) => ptr::write(p as *mut i64, *i),
+            DataValue::I128(i) => ptr::write(p as *mut i128, *i),
             DataValue::F32(f) => ptr::write(p as *mut Ieee32, *f),
             DataValue::F64(f) => ptr::write(p as *mut Ieee64, *f),
             DataValue::V128(b) => ptr::write(p as *mut [u8; 16], *b),
@@ -85,6 +89,7 @@ impl DataValue {
             types::I16 => DataValue::I16(ptr::read(p as *const i16)),
             types::I32 => DataValue::I32(ptr::read(p as *const i32)),
             types::I64 => DataValue::I64(ptr::read(p as *const i64)),
+            types::I128 => DataValue::I128(ptr::read(p as *const i128)),
             types::F32 => DataValue::F32(ptr::read(p as *const Ieee32)),
             types::F64 => DataValue::F64(ptr::read(p as *const Ieee64)),
             _ if ty.is_bool() => DataValue::B(ptr::read(p as *const bool)),
@@ -135,6 +140,7 @@ build_conversion_impl!(i8, I8, I8);
 build_conversion_impl!(i16, I16, I16);
 build_conversion_impl!(i32, I32, I32);
 build_conversion_impl!(i64, I64, I64);
+build_conversion_impl!(i128, I128, I128);
 build_conversion_impl!(u8, U8, I8);
 build_conversion_impl!(u16, U16, I16);
 build_conversion_impl!(u32, U32, I32);
@@ -156,6 +162,7 @@ impl Display for DataValue {
             DataValue::I16(dv) => write!(f, "{}", dv),
             DataValue::I32(dv) => write!(f, "{}", dv),
             DataValue::I64(dv) => write!(f, "{}", dv),
+            DataValue::I128(dv) => write!(f, "{}", dv),
             DataValue::U8(dv) => write!(f, "{}", dv),
             DataValue::U16(dv) => write!(f, "{}", dv),
             DataValue::U32(dv) => write!(f, "{}", dv),
@@ -1,6 +1,7 @@
 //! Instruction predicates/properties, shared by various analyses.
 
-use crate::ir::{DataFlowGraph, Function, Inst, InstructionData, Opcode};
+use crate::ir::immediates::Offset32;
+use crate::ir::{DataFlowGraph, Function, Inst, InstructionData, Opcode, Type, Value};
 use crate::machinst::ty_bits;
 use cranelift_entity::EntityRef;
 
@@ -57,7 +58,15 @@ pub fn is_constant_64bit(func: &Function, inst: Inst) -> Option<u64> {
         return Some(0);
     }
     match data {
-        &InstructionData::UnaryImm { imm, .. } => Some(imm.bits() as u64),
+        &InstructionData::UnaryImm { imm, .. } => {
+            // An `iconst` of a type wider than 64 bits sign-extends its immediate, so it isn't
+            // representable in 64 bits.
+            if ty_bits(func.dfg.value_type(func.dfg.inst_results(inst)[0])) > 64 {
+                None
+            } else {
+                Some(imm.bits() as u64)
+            }
+        }
         &InstructionData::UnaryIeee32 { imm, .. } => Some(imm.bits() as u64),
         &InstructionData::UnaryIeee64 { imm, .. } => Some(imm.bits()),
         &InstructionData::UnaryBool { imm, .. } => {
@@ -85,3 +94,41 @@ pub fn is_safepoint(func: &Function, inst: Inst) -> bool {
     let op = func.dfg[inst].opcode();
     op.is_resumable_trap() || op.is_call()
 }
+
+/// Does the given instruction order memory accesses with respect to other threads or callees,
+/// or access memory in ways not described by its flags? Alias analysis must assume such an
+/// instruction reads and writes all memory.
+pub fn has_memory_fence_semantics(op: Opcode) -> bool {
+    match op {
+        Opcode::AtomicRmw
+        | Opcode::AtomicCas
+        | Opcode::AtomicLoad
+        | Opcode::AtomicStore
+        | Opcode::Fence => true,
+        _ => op.is_call(),
+    }
+}
+
+/// If the given instruction is a load or store with a single address operand, returns the
+/// address, the immediate offset and the type of the accessed value.
+pub fn inst_addr_offset_type(func: &Function, inst: Inst) -> Option<(Value, Offset32, Type)> {
+    match func.dfg[inst] {
+        InstructionData::Load { arg, offset, .. } => {
+            let ty = func.dfg.value_type(func.dfg.inst_results(inst)[0]);
+            Some((arg, offset, ty))
+        }
+        InstructionData::Store { args, offset, .. } => {
+            let ty = func.dfg.value_type(args[0]);
+            Some((args[1], offset, ty))
+        }
+        _ => None,
+    }
+}
+
+/// If the given instruction is a store with a single address operand, returns the stored value.
+pub fn inst_store_data(func: &Function, inst: Inst) -> Option<Value> {
+    match func.dfg[inst] {
+        InstructionData::Store { args, .. } => Some(args[0]),
+        _ => None,
+    }
+}
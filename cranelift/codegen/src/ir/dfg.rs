@@ -7,8 +7,8 @@ use crate::ir::extfunc::ExtFuncData;
 use crate::ir::instructions::{BranchInfo, CallInfo, InstructionData};
 use crate::ir::{types, ConstantData, ConstantPool, Immediate};
 use crate::ir::{
-    Block, FuncRef, Inst, SigRef, Signature, Type, Value, ValueLabelAssignments, ValueList,
-    ValueListPool,
+    Block, FuncRef, Inst, SigRef, Signature, Type, UserStackMapEntry, Value, ValueLabelAssignments,
+    ValueList, ValueListPool,
 };
 use crate::isa::TargetIsa;
 use crate::packed_option::ReservedValue;
@@ -77,6 +77,9 @@ pub struct DataFlowGraph {
 
     /// Stores large immediates that otherwise will not fit on InstructionData
     pub immediates: PrimaryMap<Immediate, ConstantData>,
+
+    /// The user stack map entries of each safepoint instruction.
+    user_stack_maps: SecondaryMap<Inst, Vec<UserStackMapEntry>>,
 }
 
 impl DataFlowGraph {
@@ -94,6 +97,7 @@ impl DataFlowGraph {
             values_labels: None,
             constants: ConstantPool::new(),
             immediates: PrimaryMap::new(),
+            user_stack_maps: SecondaryMap::new(),
         }
     }
 
@@ -110,6 +114,7 @@ impl DataFlowGraph {
         self.values_labels = None;
         self.constants.clear();
         self.immediates.clear();
+        self.user_stack_maps.clear();
     }
 
     /// Get the total number of instructions created in this function, whether they are currently
@@ -701,6 +706,30 @@ impl DataFlowGraph {
         }
     }
 
+    /// Append an entry to the user stack map of the call instruction `inst`.
+    ///
+    /// The value in the entry's stack slot must be live across `inst`.
+    pub fn append_user_stack_map_entry(&mut self, inst: Inst, entry: UserStackMapEntry) {
+        debug_assert!(self[inst].opcode().is_call());
+        self.user_stack_maps[inst].push(entry);
+    }
+
+    /// Get the user stack map entries of `inst`, if it has any.
+    pub fn user_stack_map_entries(&self, inst: Inst) -> Option<&[UserStackMapEntry]> {
+        match self.user_stack_maps.get(inst) {
+            Some(entries) if !entries.is_empty() => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Remove the user stack map entries of `inst` and return them.
+    pub fn take_user_stack_map_entries(&mut self, inst: Inst) -> Vec<UserStackMapEntry> {
+        match self.user_stack_maps.get(inst) {
+            Some(_) => mem::replace(&mut self.user_stack_maps[inst], Vec::new()),
+            None => Vec::new(),
+        }
+    }
+
     /// Check if `inst` is a branch.
     pub fn analyze_branch(&self, inst: Inst) -> BranchInfo {
         self.insts[inst].analyze_branch(&self.value_lists)
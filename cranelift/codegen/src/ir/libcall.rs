@@ -32,6 +32,14 @@ pub enum LibCall {
     UremI64,
     /// srem.i64
     SremI64,
+    /// udiv.i128
+    UdivI128,
+    /// sdiv.i128
+    SdivI128,
+    /// urem.i128
+    UremI128,
+    /// srem.i128
+    SremI128,
     /// ishl.i64
     IshlI64,
     /// ushr.i64
@@ -81,6 +89,10 @@ impl FromStr for LibCall {
             "SdivI64" => Ok(Self::SdivI64),
             "UremI64" => Ok(Self::UremI64),
             "SremI64" => Ok(Self::SremI64),
+            "UdivI128" => Ok(Self::UdivI128),
+            "SdivI128" => Ok(Self::SdivI128),
+            "UremI128" => Ok(Self::UremI128),
+            "SremI128" => Ok(Self::SremI128),
             "IshlI64" => Ok(Self::IshlI64),
             "UshrI64" => Ok(Self::UshrI64),
             "SshrI64" => Ok(Self::SshrI64),
@@ -119,6 +131,13 @@ impl LibCall {
                 Opcode::Sshr => Self::SshrI64,
                 _ => return None,
             },
+            types::I128 => match opcode {
+                Opcode::Udiv => Self::UdivI128,
+                Opcode::Sdiv => Self::SdivI128,
+                Opcode::Urem => Self::UremI128,
+                Opcode::Srem => Self::SremI128,
+                _ => return None,
+            },
             types::F32 => match opcode {
                 Opcode::Ceil => Self::CeilF32,
                 Opcode::Floor => Self::FloorF32,
@@ -6,9 +6,12 @@ enum FlagBit {
     Notrap,
     Aligned,
     Readonly,
+    Heap,
+    Table,
+    Vmctx,
 }
 
-const NAMES: [&str; 3] = ["notrap", "aligned", "readonly"];
+const NAMES: [&str; 6] = ["notrap", "aligned", "readonly", "heap", "table", "vmctx"];
 
 /// Flags for memory operations like load/store.
 ///
@@ -103,6 +106,48 @@ impl MemFlags {
     pub fn set_readonly(&mut self) {
         self.set(FlagBit::Readonly)
     }
+
+    /// Test if the `heap` flag is set.
+    ///
+    /// The `heap`, `table` and `vmctx` flags put a memory access in a disjoint *alias region*:
+    /// accesses in one region never alias accesses in another region, nor accesses without any of
+    /// these flags. At most one of them may be set. Alias analysis uses them to keep a value
+    /// loaded from one region available across stores to the others.
+    ///
+    /// The `heap` region holds the (WebAssembly) heaps.
+    pub fn heap(self) -> bool {
+        self.read(FlagBit::Heap)
+    }
+
+    /// Set the `heap` flag.
+    pub fn set_heap(&mut self) {
+        self.set(FlagBit::Heap)
+    }
+
+    /// Test if the `table` flag is set.
+    ///
+    /// The `table` alias region holds the (WebAssembly) tables. See `heap()`.
+    pub fn table(self) -> bool {
+        self.read(FlagBit::Table)
+    }
+
+    /// Set the `table` flag.
+    pub fn set_table(&mut self) {
+        self.set(FlagBit::Table)
+    }
+
+    /// Test if the `vmctx` flag is set.
+    ///
+    /// The `vmctx` alias region holds the fields of the VM context, such as the values of
+    /// globals. See `heap()`.
+    pub fn vmctx(self) -> bool {
+        self.read(FlagBit::Vmctx)
+    }
+
+    /// Set the `vmctx` flag.
+    pub fn set_vmctx(&mut self) {
+        self.set(FlagBit::Vmctx)
+    }
 }
 
 impl fmt::Display for MemFlags {
@@ -22,6 +22,7 @@ pub mod stackslot;
 mod table;
 mod trapcode;
 pub mod types;
+mod user_stack_maps;
 mod valueloc;
 
 #[cfg(feature = "enable-serde")]
@@ -57,6 +58,7 @@ pub use crate::ir::stackslot::{StackLayoutInfo, StackSlotData, StackSlotKind, St
 pub use crate::ir::table::TableData;
 pub use crate::ir::trapcode::TrapCode;
 pub use crate::ir::types::Type;
+pub use crate::ir::user_stack_maps::UserStackMapEntry;
 pub use crate::ir::valueloc::{ArgumentLoc, ValueLoc};
 pub use cranelift_codegen_shared::condcodes;
 
@@ -0,0 +1,30 @@
+//! User-defined stack maps.
+//!
+//! Reference-typed values (`r32` and `r64`) get stack maps from the register
+//! allocator. Frontends with their own garbage collector can instead ask for
+//! stack maps of values of any type: `cranelift-frontend` stores such values to
+//! explicit stack slots around each safepoint, and records those slots on the
+//! safepoint with `DataFlowGraph::append_user_stack_map_entry`.
+//!
+//! Backends then report, for each safepoint, the offset of every such slot from
+//! the stack pointer in a `binemit::UserStackMap`.
+
+use crate::ir::{StackSlot, Type};
+
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// A value that is stored in a stack slot across a safepoint, and must be part
+/// of the safepoint's stack map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct UserStackMapEntry {
+    /// The type of the value.
+    pub ty: Type,
+
+    /// The stack slot the value is stored in.
+    pub slot: StackSlot,
+
+    /// The offset of the value within `slot`.
+    pub offset: u32,
+}
@@ -76,7 +76,7 @@ fn try_fill_baldrdash_reg(call_conv: isa::CallConv, param: &ir::AbiParam) -> Opt
             &ir::ArgumentPurpose::VMContext => {
                 // This is SpiderMonkey's `WasmTlsReg`.
                 Some(ABIArg::Reg(
-                    xreg(BALDRDASH_TLS_REG).to_real_reg(),
+                    ValueRegs::one(xreg(BALDRDASH_TLS_REG).to_real_reg()),
                     ir::types::I64,
                     param.extension,
                     param.purpose,
@@ -85,7 +85,7 @@ fn try_fill_baldrdash_reg(call_conv: isa::CallConv, param: &ir::AbiParam) -> Opt
             &ir::ArgumentPurpose::SignatureId => {
                 // This is SpiderMonkey's `WasmTableCallSigReg`.
                 Some(ABIArg::Reg(
-                    xreg(BALDRDASH_SIG_REG).to_real_reg(),
+                    ValueRegs::one(xreg(BALDRDASH_SIG_REG).to_real_reg()),
                     ir::types::I64,
                     param.extension,
                     param.purpose,
@@ -219,7 +219,15 @@ impl ABIMachineSpec for AArch64MachineDeps {
                 "Invalid type for AArch64: {:?}",
                 param.value_type
             );
-            let rc = Inst::rc_for_type(param.value_type).unwrap();
+            let (rcs, _) = Inst::rc_for_type(param.value_type).unwrap();
+            let rc = rcs[0];
+            let num_regs = rcs.len() as u8;
+            if num_regs > 1 && is_baldrdash {
+                return Err(CodegenError::Unsupported(format!(
+                    "I128 arguments and return values are not supported by the Baldrdash \
+                     calling conventions"
+                )));
+            }
 
             let next_reg = match rc {
                 RegClass::I64 => &mut next_xreg,
@@ -227,24 +235,42 @@ impl ABIMachineSpec for AArch64MachineDeps {
                 _ => panic!("Invalid register class: {:?}", rc),
             };
 
+            // A value split across two registers (i.e., an `I128`) goes in an
+            // even-numbered register pair (section 5.4.2, rule C.8).
+            if num_regs == 2 {
+                *next_reg += *next_reg & 1;
+            }
+
             if let Some(param) = try_fill_baldrdash_reg(call_conv, param) {
                 assert!(rc == RegClass::I64);
                 ret.push(param);
-            } else if *next_reg < max_per_class_reg_vals && remaining_reg_vals > 0 {
-                let reg = match rc {
-                    RegClass::I64 => xreg(*next_reg),
-                    RegClass::V128 => vreg(*next_reg),
+            } else if *next_reg + num_regs <= max_per_class_reg_vals
+                && remaining_reg_vals >= num_regs
+            {
+                let reg = |idx| match rc {
+                    RegClass::I64 => xreg(idx).to_real_reg(),
+                    RegClass::V128 => vreg(idx).to_real_reg(),
                     _ => unreachable!(),
                 };
+                let regs = if num_regs == 2 {
+                    ValueRegs::two(reg(*next_reg), reg(*next_reg + 1))
+                } else {
+                    ValueRegs::one(reg(*next_reg))
+                };
                 ret.push(ABIArg::Reg(
-                    reg.to_real_reg(),
+                    regs,
                     param.value_type,
                     param.extension,
                     param.purpose,
                 ));
-                *next_reg += 1;
-                remaining_reg_vals -= 1;
+                *next_reg += num_regs;
+                remaining_reg_vals -= num_regs;
             } else {
+                if num_regs == 2 {
+                    // Once a register pair has been spilled to the stack, no
+                    // further integer registers are allocated (rule C.11).
+                    *next_reg = max_per_class_reg_vals;
+                }
                 // Compute size. Every arg takes a minimum slot of 8 bytes. (16-byte
                 // stack alignment happens separately after all args.)
                 let size = (ty_bits(param.value_type) / 8) as u64;
@@ -270,7 +296,7 @@ impl ABIMachineSpec for AArch64MachineDeps {
             debug_assert!(args_or_rets == ArgsOrRets::Args);
             if next_xreg < max_per_class_reg_vals && remaining_reg_vals > 0 {
                 ret.push(ABIArg::Reg(
-                    xreg(next_xreg).to_real_reg(),
+                    ValueRegs::one(xreg(next_xreg).to_real_reg()),
                     I64,
                     ir::ArgumentExtension::None,
                     ir::ArgumentPurpose::Normal,
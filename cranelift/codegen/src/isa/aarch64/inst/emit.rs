@@ -290,6 +290,11 @@ fn enc_adr(off: i32, rd: Writable<Reg>) -> u32 {
     (0b00010000 << 24) | (immlo << 29) | (immhi << 5) | machreg_to_gpr(rd.to_reg())
 }
 
+/// Encode an `adrp` with a zero page offset, to be filled in by a relocation.
+fn enc_adrp(rd: Writable<Reg>) -> u32 {
+    (0b10010000 << 24) | machreg_to_gpr(rd.to_reg())
+}
+
 fn enc_csel(rd: Writable<Reg>, rn: Reg, rm: Reg, cond: Cond) -> u32 {
     0b100_11010100_00000_0000_00_00000_00000
         | (machreg_to_gpr(rm) << 16)
@@ -550,6 +555,10 @@ impl MachInstEmit for Inst {
                     ALUOp::AddS64 => 0b10101011_000,
                     ALUOp::SubS32 => 0b01101011_000,
                     ALUOp::SubS64 => 0b11101011_000,
+                    ALUOp::Adc64 => 0b10011010_000,
+                    ALUOp::AdcS64 => 0b10111010_000,
+                    ALUOp::Sbc64 => 0b11011010_000,
+                    ALUOp::SbcS64 => 0b11111010_000,
                     ALUOp::SDiv64 => 0b10011010_110,
                     ALUOp::UDiv64 => 0b10011010_110,
                     ALUOp::RotR32 | ALUOp::Lsr32 | ALUOp::Asr32 | ALUOp::Lsl32 => 0b00011010_110,
@@ -1238,6 +1247,9 @@ impl MachInstEmit for Inst {
             &Inst::Fence {} => {
                 sink.put4(enc_dmb_ish()); // dmb ish
             }
+            &Inst::Csdb => {
+                sink.put4(0xd503229f);
+            }
             &Inst::FpuMove64 { rd, rn } => {
                 sink.put4(enc_fpurr(0b000_11110_01_1_000000_10000, rd, rn));
             }
@@ -2207,6 +2219,60 @@ impl MachInstEmit for Inst {
                 // disable the worst-case-size check in this case.
                 start_off = sink.cur_offset();
             }
+            &Inst::LoadExtName {
+                rd,
+                ref name,
+                offset,
+            } if emit_info.flags().is_pic() => {
+                // adrp rd, :got:name
+                // ldr rd, [rd, :got_lo12:name]
+                let srcloc = state.cur_srcloc();
+                sink.add_reloc(srcloc, Reloc::Arm64AdrGotPage21, name, 0);
+                sink.put4(enc_adrp(rd));
+                sink.add_reloc(srcloc, Reloc::Arm64Ld64GotLo12Nc, name, 0);
+                sink.put4(enc_ldst_uimm12(
+                    0b1111100001,
+                    UImm12Scaled::zero(I64),
+                    rd.to_reg(),
+                    rd.to_reg(),
+                ));
+
+                // The GOT entry holds the address of the symbol itself, so the offset is added
+                // separately.
+                let abs_offset = if offset < 0 {
+                    -offset as u64
+                } else {
+                    offset as u64
+                };
+                let alu_op = if offset < 0 {
+                    ALUOp::Sub64
+                } else {
+                    ALUOp::Add64
+                };
+                if offset == 0 {
+                    // Nothing to add.
+                } else if let Some(imm12) = Imm12::maybe_from_u64(abs_offset) {
+                    let inst = Inst::AluRRImm12 {
+                        alu_op,
+                        rd,
+                        rn: rd.to_reg(),
+                        imm12,
+                    };
+                    inst.emit(sink, emit_info, state);
+                } else {
+                    let tmp = writable_spilltmp_reg();
+                    for inst in Inst::load_constant(tmp, abs_offset).into_iter() {
+                        inst.emit(sink, emit_info, state);
+                    }
+                    let inst = Inst::AluRRR {
+                        alu_op,
+                        rd,
+                        rn: rd.to_reg(),
+                        rm: tmp.to_reg(),
+                    };
+                    inst.emit(sink, emit_info, state);
+                }
+            }
             &Inst::LoadExtName {
                 rd,
                 ref name,
@@ -2229,6 +2295,55 @@ impl MachInstEmit for Inst {
                     sink.put8(0);
                 }
             }
+            &Inst::LoadExtNameNear {
+                rd,
+                ref name,
+                offset,
+            } => {
+                // adrp rd, name
+                // add rd, rd, :lo12:name
+                let srcloc = state.cur_srcloc();
+                sink.add_reloc(srcloc, Reloc::Arm64AdrPrelPgHi21, name, offset);
+                sink.put4(enc_adrp(rd));
+                sink.add_reloc(srcloc, Reloc::Arm64AddAbsLo12Nc, name, offset);
+                sink.put4(enc_arith_rr_imm12(0b100_10001, 0, 0, rd.to_reg(), rd));
+            }
+            &Inst::ElfTlsGetAddr { ref symbol } => {
+                // adrp x0, :tlsdesc:symbol
+                // ldr x1, [x0, :tlsdesc_lo12:symbol]
+                // add x0, x0, :tlsdesc_lo12:symbol
+                // blr x1
+                // mrs x1, tpidr_el0
+                // add x0, x0, x1
+                let srcloc = state.cur_srcloc();
+                sink.add_reloc(srcloc, Reloc::ElfAarch64TlsDescAdrPage21, symbol, 0);
+                sink.put4(enc_adrp(writable_xreg(0)));
+                sink.add_reloc(srcloc, Reloc::ElfAarch64TlsDescLd64Lo12, symbol, 0);
+                sink.put4(enc_ldst_uimm12(
+                    0b1111100001,
+                    UImm12Scaled::zero(I64),
+                    xreg(0),
+                    xreg(1),
+                ));
+                sink.add_reloc(srcloc, Reloc::ElfAarch64TlsDescAddLo12, symbol, 0);
+                sink.put4(enc_arith_rr_imm12(
+                    0b100_10001,
+                    0,
+                    0,
+                    xreg(0),
+                    writable_xreg(0),
+                ));
+                sink.add_reloc(srcloc, Reloc::ElfAarch64TlsDescCall, symbol, 0);
+                sink.put4(0b1101011_0001_11111_000000_00000_00000 | (machreg_to_gpr(xreg(1)) << 5));
+                sink.put4(0xd53bd041); // mrs x1, tpidr_el0
+                sink.put4(enc_arith_rrr(
+                    0b10001011_000,
+                    0,
+                    writable_xreg(0),
+                    xreg(0),
+                    xreg(1),
+                ));
+            }
             &Inst::LoadAddr { rd, ref mem } => {
                 let (mem_insts, mem) = mem_finalize(sink.cur_offset(), mem, state);
                 for inst in mem_insts.into_iter() {
@@ -2,7 +2,7 @@ use crate::ir::types::*;
 use crate::isa::aarch64::inst::*;
 use crate::isa::test_utils;
 use crate::isa::CallConv;
-use crate::settings;
+use crate::settings::{self, Configurable};
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
@@ -852,6 +852,46 @@ fn test_aarch64_binemit() {
         "417CC39B",
         "umulh x1, x2, x3",
     ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: ALUOp::Adc64,
+            rd: writable_xreg(1),
+            rn: xreg(2),
+            rm: xreg(3),
+        },
+        "4100039A",
+        "adc x1, x2, x3",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: ALUOp::Sbc64,
+            rd: writable_xreg(1),
+            rn: xreg(2),
+            rm: xreg(3),
+        },
+        "410003DA",
+        "sbc x1, x2, x3",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: ALUOp::AdcS64,
+            rd: writable_xreg(1),
+            rn: xreg(2),
+            rm: xreg(3),
+        },
+        "410003BA",
+        "adcs x1, x2, x3",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: ALUOp::SbcS64,
+            rd: writable_xreg(1),
+            rn: xreg(2),
+            rm: xreg(3),
+        },
+        "410003FA",
+        "sbcs x1, x2, x3",
+    ));
 
     insns.push((
         Inst::AluRRImmShift {
@@ -4214,6 +4254,24 @@ fn test_aarch64_binemit() {
         "adr x15, pc+1048572",
     ));
 
+    insns.push((
+        Inst::LoadExtNameNear {
+            rd: writable_xreg(5),
+            name: Box::new(ExternalName::user(0, 1)),
+            offset: 8,
+        },
+        "05000090A5000091",
+        "adrp x5, User { namespace: 0, index: 1 } + 8 ; add x5, x5, :lo12:User { namespace: 0, index: 1 } + 8",
+    ));
+
+    insns.push((
+        Inst::ElfTlsGetAddr {
+            symbol: ExternalName::user(0, 1),
+        },
+        "00000090010040F90000009120003FD641D03BD50000018B",
+        "elf_tls_get_addr x0, User { namespace: 0, index: 1 }",
+    ));
+
     insns.push((
         Inst::FpuMove64 {
             rd: writable_vreg(8),
@@ -5021,6 +5079,7 @@ fn test_aarch64_binemit() {
     ));
 
     insns.push((Inst::Fence {}, "BF3B03D5", "dmb ish"));
+    insns.push((Inst::Csdb, "9F2203D5", "csdb"));
 
     let flags = settings::Flags::new(settings::builder());
     let rru = create_reg_universe(&flags);
@@ -5045,6 +5104,37 @@ fn test_aarch64_binemit() {
     }
 }
 
+#[test]
+fn test_aarch64_pic_binemit() {
+    // With `is_pic`, symbol addresses are loaded from the GOT, and any offset is added afterwards.
+    let insns = vec![
+        (0, "0A0000904A0140F9"),
+        (16, "0A0000904A0140F94A410091"),
+        (-16, "0A0000904A0140F94A4100D1"),
+        (0x12345, "0A0000904A0140F9B06884523000A0724A01108B"),
+    ];
+
+    let mut builder = settings::builder();
+    builder.enable("is_pic").unwrap();
+    let flags = settings::Flags::new(builder);
+    let emit_info = EmitInfo::new(flags);
+    for (offset, expected_encoding) in insns {
+        let insn = Inst::LoadExtName {
+            rd: writable_xreg(10),
+            name: Box::new(ExternalName::user(0, 1)),
+            offset,
+        };
+
+        let mut sink = test_utils::TestCodeSink::new();
+        let mut buffer = MachBuffer::new();
+        insn.emit(&mut buffer, &emit_info, &mut Default::default());
+        let buffer = buffer.finish();
+        buffer.emit(&mut sink);
+        let actual_encoding = &sink.stringify();
+        assert_eq!(expected_encoding, actual_encoding);
+    }
+}
+
 #[test]
 fn test_cond_invert() {
     for cond in vec![
@@ -6,8 +6,8 @@
 use crate::binemit::CodeOffset;
 use crate::ir::types::{
     B1, B16, B16X4, B16X8, B32, B32X2, B32X4, B64, B64X2, B8, B8X16, B8X8, F32, F32X2, F32X4, F64,
-    F64X2, FFLAGS, I16, I16X4, I16X8, I32, I32X2, I32X4, I64, I64X2, I8, I8X16, I8X8, IFLAGS, R32,
-    R64,
+    F64X2, FFLAGS, I128, I16, I16X4, I16X8, I32, I32X2, I32X4, I64, I64X2, I8, I8X16, I8X8, IFLAGS,
+    R32, R64,
 };
 use crate::ir::{ExternalName, Opcode, SourceLoc, TrapCode, Type};
 use crate::isa::CallConv;
@@ -71,6 +71,14 @@ pub enum ALUOp {
     SubS32,
     /// Sub, setting flags
     SubS64,
+    /// Add with carry
+    Adc64,
+    /// Add with carry, setting flags
+    AdcS64,
+    /// Subtract with carry (i.e., borrow)
+    Sbc64,
+    /// Subtract with carry (i.e., borrow), setting flags
+    SbcS64,
     /// Signed multiply, high-word result
     SMulH,
     /// Unsigned multiply, high-word result
@@ -715,6 +723,10 @@ pub enum Inst {
     /// ish".  This instruction is sequentially consistent.
     Fence,
 
+    /// Consumption of Speculative Data Barrier: prevents the results of conditional selects and
+    /// conditional compares that precede it from being predicted, for Spectre guards.
+    Csdb,
+
     /// FPU move. Note that this is distinct from a vector-register
     /// move; moving just 64 bits seems to be significantly faster.
     FpuMove64 {
@@ -1128,13 +1140,30 @@ pub enum Inst {
         rtmp2: Writable<Reg>,
     },
 
-    /// Load an inline symbol reference.
+    /// Load an inline symbol reference. With `is_pic` set, the address is instead loaded from
+    /// the GOT.
     LoadExtName {
         rd: Writable<Reg>,
         name: Box<ExternalName>,
         offset: i64,
     },
 
+    /// Compute the address of a symbol that is colocated with this code, using a PC-relative
+    /// `adrp` / `add` pair. This is only used for position-independent code; in other code,
+    /// symbol addresses are always loaded with `LoadExtName`.
+    LoadExtNameNear {
+        rd: Writable<Reg>,
+        name: Box<ExternalName>,
+        offset: i64,
+    },
+
+    /// Compute the address of the ELF thread-local symbol `symbol` into `x0`, with a TLS
+    /// descriptor sequence. The descriptor resolver preserves all registers except `x0`, but
+    /// the sequence also uses `x1`, which is clobbered.
+    ElfTlsGetAddr {
+        symbol: ExternalName,
+    },
+
     /// Load address referenced by `mem` into `rd`.
     LoadAddr {
         rd: Writable<Reg>,
@@ -1625,6 +1654,7 @@ fn aarch64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
             collector.add_use(r_data);
         }
         &Inst::Fence {} => {}
+        &Inst::Csdb => {}
         &Inst::FpuMove64 { rd, rn } => {
             collector.add_def(rd);
             collector.add_use(rn);
@@ -1868,9 +1898,13 @@ fn aarch64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
             collector.add_def(rtmp1);
             collector.add_def(rtmp2);
         }
-        &Inst::LoadExtName { rd, .. } => {
+        &Inst::LoadExtName { rd, .. } | &Inst::LoadExtNameNear { rd, .. } => {
             collector.add_def(rd);
         }
+        &Inst::ElfTlsGetAddr { .. } => {
+            collector.add_def(writable_xreg(0));
+            collector.add_def(writable_xreg(1));
+        }
         &Inst::LoadAddr { rd, ref mem } => {
             collector.add_def(rd);
             memarg_regs(mem, collector);
@@ -2191,6 +2225,7 @@ fn aarch64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
             map_use(mapper, r_addr);
         }
         &mut Inst::Fence {} => {}
+        &mut Inst::Csdb => {}
         &mut Inst::FpuMove64 {
             ref mut rd,
             ref mut rn,
@@ -2601,9 +2636,11 @@ fn aarch64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
             map_def(mapper, rtmp1);
             map_def(mapper, rtmp2);
         }
-        &mut Inst::LoadExtName { ref mut rd, .. } => {
+        &mut Inst::LoadExtName { ref mut rd, .. }
+        | &mut Inst::LoadExtNameNear { ref mut rd, .. } => {
             map_def(mapper, rd);
         }
+        &mut Inst::ElfTlsGetAddr { .. } => {}
         &mut Inst::LoadAddr {
             ref mut rd,
             ref mut mem,
@@ -2751,14 +2788,28 @@ impl MachInst for Inst {
         None
     }
 
-    fn rc_for_type(ty: Type) -> CodegenResult<RegClass> {
+    fn rc_for_type(ty: Type) -> CodegenResult<(&'static [RegClass], &'static [Type])> {
         match ty {
-            I8 | I16 | I32 | I64 | B1 | B8 | B16 | B32 | B64 | R32 | R64 => Ok(RegClass::I64),
-            F32 | F64 => Ok(RegClass::V128),
-            IFLAGS | FFLAGS => Ok(RegClass::I64),
-            B8X8 | B8X16 | B16X4 | B16X8 | B32X2 | B32X4 | B64X2 => Ok(RegClass::V128),
-            F32X2 | I8X8 | I16X4 | I32X2 => Ok(RegClass::V128),
-            F32X4 | F64X2 | I8X16 | I16X8 | I32X4 | I64X2 => Ok(RegClass::V128),
+            I8 => Ok((&[RegClass::I64], &[I8])),
+            I16 => Ok((&[RegClass::I64], &[I16])),
+            I32 => Ok((&[RegClass::I64], &[I32])),
+            I64 => Ok((&[RegClass::I64], &[I64])),
+            B1 => Ok((&[RegClass::I64], &[B1])),
+            B8 => Ok((&[RegClass::I64], &[B8])),
+            B16 => Ok((&[RegClass::I64], &[B16])),
+            B32 => Ok((&[RegClass::I64], &[B32])),
+            B64 => Ok((&[RegClass::I64], &[B64])),
+            R32 => panic!("32-bit reftype pointer should never be seen on AArch64"),
+            R64 => Ok((&[RegClass::I64], &[R64])),
+            I128 => Ok((&[RegClass::I64, RegClass::I64], &[I64, I64])),
+            F32 => Ok((&[RegClass::V128], &[F32])),
+            F64 => Ok((&[RegClass::V128], &[F64])),
+            IFLAGS | FFLAGS => Ok((&[RegClass::I64], &[I64])),
+            B8X8 | B8X16 | B16X4 | B16X8 | B32X2 | B32X4 | B64X2 => {
+                Ok((&[RegClass::V128], &[I8X16]))
+            }
+            F32X2 | I8X8 | I16X4 | I32X2 => Ok((&[RegClass::V128], &[I8X16])),
+            F32X4 | F64X2 | I8X16 | I16X8 | I32X4 | I64X2 => Ok((&[RegClass::V128], &[I8X16])),
             _ => Err(CodegenError::Unsupported(format!(
                 "Unexpected SSA-value type: {}",
                 ty
@@ -2837,6 +2888,10 @@ impl Inst {
                 ALUOp::AddS64 => ("adds", OperandSize::Size64),
                 ALUOp::SubS32 => ("subs", OperandSize::Size32),
                 ALUOp::SubS64 => ("subs", OperandSize::Size64),
+                ALUOp::Adc64 => ("adc", OperandSize::Size64),
+                ALUOp::AdcS64 => ("adcs", OperandSize::Size64),
+                ALUOp::Sbc64 => ("sbc", OperandSize::Size64),
+                ALUOp::SbcS64 => ("sbcs", OperandSize::Size64),
                 ALUOp::SMulH => ("smulh", OperandSize::Size64),
                 ALUOp::UMulH => ("umulh", OperandSize::Size64),
                 ALUOp::SDiv64 => ("sdiv", OperandSize::Size64),
@@ -3143,6 +3198,7 @@ impl Inst {
             &Inst::Fence {} => {
                 format!("dmb ish")
             }
+            &Inst::Csdb => "csdb".to_string(),
             &Inst::FpuMove64 { rd, rn } => {
                 let rd = show_vreg_scalar(rd.to_reg(), mb_rru, ScalarSize::Size64);
                 let rn = show_vreg_scalar(rn, mb_rru, ScalarSize::Size64);
@@ -3785,6 +3841,18 @@ impl Inst {
                 let rd = rd.show_rru(mb_rru);
                 format!("ldr {}, 8 ; b 12 ; data {:?} + {}", rd, name, offset)
             }
+            &Inst::LoadExtNameNear {
+                rd,
+                ref name,
+                offset,
+            } => {
+                let rd = rd.show_rru(mb_rru);
+                format!(
+                    "adrp {}, {:?} + {} ; add {}, {}, :lo12:{:?} + {}",
+                    rd, name, offset, rd, rd, name, offset
+                )
+            }
+            &Inst::ElfTlsGetAddr { ref symbol } => format!("elf_tls_get_addr x0, {:?}", symbol),
             &Inst::LoadAddr { rd, ref mem } => {
                 // TODO: we really should find a better way to avoid duplication of
                 // this logic between `emit()` and `show_rru()` -- a separate 1-to-N
@@ -10,20 +10,26 @@
 use crate::ir::condcodes::{FloatCC, IntCC};
 use crate::ir::types::*;
 use crate::ir::Inst as IRInst;
-use crate::ir::{Opcode, Type};
+use crate::ir::{AbiParam, ArgumentPurpose, ExternalName, LibCall, Opcode, Signature, Type};
+use crate::isa::CallConv;
 use crate::machinst::lower::*;
 use crate::machinst::*;
+use crate::settings::Flags;
 use crate::CodegenResult;
 
+use crate::isa::aarch64::abi::AArch64ABICaller;
 use crate::isa::aarch64::inst::*;
 use crate::isa::aarch64::AArch64Backend;
 
 use super::lower_inst;
 
+pub(crate) mod isle;
+
 use crate::data_value::DataValue;
 use log::{debug, trace};
 use regalloc::{Reg, RegClass, Writable};
 use smallvec::SmallVec;
+use target_lexicon::Triple;
 
 //============================================================================
 // Result enum types.
@@ -78,25 +84,6 @@ impl ResultRSEImm12 {
     }
 }
 
-/// A lowering result: register, register-shift, or logical immediate form.
-/// An SSA value can always be lowered into one of these options; the register form is the
-/// fallback.
-#[derive(Clone, Debug)]
-pub(crate) enum ResultRSImmLogic {
-    Reg(Reg),
-    RegShift(Reg, ShiftOpAndAmt),
-    ImmLogic(ImmLogic),
-}
-
-impl ResultRSImmLogic {
-    fn from_rs(rse: ResultRS) -> ResultRSImmLogic {
-        match rse {
-            ResultRS::Reg(r) => ResultRSImmLogic::Reg(r),
-            ResultRS::RegShift(r, s) => ResultRSImmLogic::RegShift(r, s),
-        }
-    }
-}
-
 /// A lowering result: register or immediate shift amount (arg to a shift op).
 /// An SSA value can always be lowered into one of these options; the register form is the
 /// fallback.
@@ -179,7 +166,7 @@ pub(crate) fn put_input_in_reg<C: LowerCtx<I = Inst>>(
         } else {
             c
         };
-        let to_reg = ctx.alloc_tmp(Inst::rc_for_type(ty).unwrap(), ty);
+        let to_reg = ctx.alloc_tmp(Inst::rc_for_type(ty).unwrap().0[0], ty);
         for inst in Inst::gen_constant(to_reg, masked, ty, |reg_class, ty| {
             ctx.alloc_tmp(reg_class, ty)
         })
@@ -256,6 +243,20 @@ pub(crate) fn put_input_in_reg<C: LowerCtx<I = Inst>>(
     }
 }
 
+/// Lower an instruction input to as many registers as its type requires. `I128` values live in
+/// a pair of 64-bit registers, low part first.
+pub(crate) fn put_input_in_regs<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+) -> ValueRegs<Reg> {
+    let ty = ctx.input_ty(input.insn, input.input);
+    if ty == I128 {
+        ctx.put_input_in_regs(input.insn, input.input)
+    } else {
+        ValueRegs::one(put_input_in_reg(ctx, input, NarrowValueMode::None))
+    }
+}
+
 /// Lower an instruction input to a reg or reg/shift, or reg/extend operand.
 ///
 /// The `narrow_mode` flag indicates whether the consumer of this value needs
@@ -402,51 +403,6 @@ pub(crate) fn put_input_in_rse_imm12<C: LowerCtx<I = Inst>>(
     ResultRSEImm12::from_rse(put_input_in_rse(ctx, input, narrow_mode))
 }
 
-/// Like `put_input_in_rse_imm12` above, except is allowed to negate the
-/// argument (assuming a two's-complement representation with the given bit
-/// width) if this allows use of 12-bit immediate. Used to flip `add`s with
-/// negative immediates to `sub`s (and vice-versa).
-pub(crate) fn put_input_in_rse_imm12_maybe_negated<C: LowerCtx<I = Inst>>(
-    ctx: &mut C,
-    input: InsnInput,
-    twos_complement_bits: usize,
-    narrow_mode: NarrowValueMode,
-) -> (ResultRSEImm12, bool) {
-    assert!(twos_complement_bits <= 64);
-    if let Some(imm_value) = input_to_const(ctx, input) {
-        if let Some(i) = Imm12::maybe_from_u64(imm_value) {
-            return (ResultRSEImm12::Imm12(i), false);
-        }
-        let sign_extended =
-            ((imm_value as i64) << (64 - twos_complement_bits)) >> (64 - twos_complement_bits);
-        let inverted = sign_extended.wrapping_neg();
-        if let Some(i) = Imm12::maybe_from_u64(inverted as u64) {
-            return (ResultRSEImm12::Imm12(i), true);
-        }
-    }
-
-    (
-        ResultRSEImm12::from_rse(put_input_in_rse(ctx, input, narrow_mode)),
-        false,
-    )
-}
-
-pub(crate) fn put_input_in_rs_immlogic<C: LowerCtx<I = Inst>>(
-    ctx: &mut C,
-    input: InsnInput,
-    narrow_mode: NarrowValueMode,
-) -> ResultRSImmLogic {
-    if let Some(imm_value) = input_to_const(ctx, input) {
-        let ty = ctx.input_ty(input.insn, input.input);
-        let ty = if ty_bits(ty) < 32 { I32 } else { ty };
-        if let Some(i) = ImmLogic::maybe_from_u64(imm_value, ty) {
-            return ResultRSImmLogic::ImmLogic(i);
-        }
-    }
-
-    ResultRSImmLogic::from_rs(put_input_in_rs(ctx, input, narrow_mode))
-}
-
 pub(crate) fn put_input_in_reg_immshift<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
     input: InsnInput,
@@ -496,57 +452,6 @@ pub(crate) fn alu_inst_imm12(op: ALUOp, rd: Writable<Reg>, rn: Reg, rm: ResultRS
     }
 }
 
-pub(crate) fn alu_inst_immlogic(
-    op: ALUOp,
-    rd: Writable<Reg>,
-    rn: Reg,
-    rm: ResultRSImmLogic,
-) -> Inst {
-    match rm {
-        ResultRSImmLogic::ImmLogic(imml) => Inst::AluRRImmLogic {
-            alu_op: op,
-            rd,
-            rn,
-            imml,
-        },
-        ResultRSImmLogic::Reg(rm) => Inst::AluRRR {
-            alu_op: op,
-            rd,
-            rn,
-            rm,
-        },
-        ResultRSImmLogic::RegShift(rm, shiftop) => Inst::AluRRRShift {
-            alu_op: op,
-            rd,
-            rn,
-            rm,
-            shiftop,
-        },
-    }
-}
-
-pub(crate) fn alu_inst_immshift(
-    op: ALUOp,
-    rd: Writable<Reg>,
-    rn: Reg,
-    rm: ResultRegImmShift,
-) -> Inst {
-    match rm {
-        ResultRegImmShift::ImmShift(immshift) => Inst::AluRRImmShift {
-            alu_op: op,
-            rd,
-            rn,
-            immshift,
-        },
-        ResultRegImmShift::Reg(rm) => Inst::AluRRR {
-            alu_op: op,
-            rd,
-            rn,
-            rm,
-        },
-    }
-}
-
 //============================================================================
 // Lowering: addressing mode support. Takes instruction directly, rather
 // than an `InsnInput`, to do more introspection.
@@ -1111,6 +1016,17 @@ pub(crate) fn maybe_input_insn_via_conv<C: LowerCtx<I = Inst>>(
     None
 }
 
+/// Checks for an `icmp` feeding the given input, possibly via a `bint`, whose comparison can be
+/// lowered to flags for the user. Comparisons of `I128` values are excluded, as they don't reduce
+/// to a single condition.
+pub(crate) fn maybe_input_icmp<C: LowerCtx<I = Inst>>(
+    c: &mut C,
+    input: InsnInput,
+) -> Option<IRInst> {
+    maybe_input_insn_via_conv(c, input, Opcode::Icmp, Opcode::Bint)
+        .filter(|&icmp_insn| c.input_ty(icmp_insn, 0) != I128)
+}
+
 pub(crate) fn lower_icmp_or_ifcmp_to_flags<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
     insn: IRInst,
@@ -1152,6 +1068,239 @@ pub(crate) fn lower_fcmp_or_ffcmp_to_flags<C: LowerCtx<I = Inst>>(ctx: &mut C, i
     }
 }
 
+/// Lower a comparison of the `I128` values in `lhs` and `rhs` to a 0 / 1 result in `rd`.
+pub(crate) fn lower_icmp_i128<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    condcode: IntCC,
+    lhs: ValueRegs<Reg>,
+    rhs: ValueRegs<Reg>,
+    rd: Writable<Reg>,
+) {
+    let (lhs_lo, lhs_hi) = (lhs.regs()[0], lhs.regs()[1]);
+    let (rhs_lo, rhs_hi) = (rhs.regs()[0], rhs.regs()[1]);
+    match condcode {
+        IntCC::Equal | IntCC::NotEqual => {
+            // The values are equal iff (lhs_lo ^ rhs_lo) | (lhs_hi ^ rhs_hi) is zero.
+            let tmp_lo = ctx.alloc_tmp(RegClass::I64, I64);
+            let tmp_hi = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::Eor64,
+                rd: tmp_lo,
+                rn: lhs_lo,
+                rm: rhs_lo,
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::Eor64,
+                rd: tmp_hi,
+                rn: lhs_hi,
+                rm: rhs_hi,
+            });
+            // orrs isn't available, so compare the result with zero separately.
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::Orr64,
+                rd: tmp_lo,
+                rn: tmp_lo.to_reg(),
+                rm: tmp_hi.to_reg(),
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SubS64,
+                rd: writable_zero_reg(),
+                rn: tmp_lo.to_reg(),
+                rm: zero_reg(),
+            });
+            ctx.emit(Inst::CSet {
+                cond: lower_condcode(condcode),
+                rd,
+            });
+        }
+        IntCC::SignedLessThan
+        | IntCC::SignedLessThanOrEqual
+        | IntCC::SignedGreaterThan
+        | IntCC::SignedGreaterThanOrEqual
+        | IntCC::UnsignedLessThan
+        | IntCC::UnsignedLessThanOrEqual
+        | IntCC::UnsignedGreaterThan
+        | IntCC::UnsignedGreaterThanOrEqual => {
+            // The high parts decide, unless they are equal: then the low parts, compared as
+            // unsigned values, decide.
+            //
+            //   cmp lhs_lo, rhs_lo
+            //   cset tmp_lo, <unsigned cond>
+            //   cmp lhs_hi, rhs_hi
+            //   cset tmp_hi, <cond>
+            //   csel rd, tmp_lo, tmp_hi, eq
+            let tmp_lo = ctx.alloc_tmp(RegClass::I64, I64);
+            let tmp_hi = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SubS64,
+                rd: writable_zero_reg(),
+                rn: lhs_lo,
+                rm: rhs_lo,
+            });
+            ctx.emit(Inst::CSet {
+                cond: lower_condcode(condcode.unsigned()),
+                rd: tmp_lo,
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SubS64,
+                rd: writable_zero_reg(),
+                rn: lhs_hi,
+                rm: rhs_hi,
+            });
+            ctx.emit(Inst::CSet {
+                cond: lower_condcode(condcode),
+                rd: tmp_hi,
+            });
+            ctx.emit(Inst::CSel {
+                cond: Cond::Eq,
+                rd,
+                rn: tmp_lo.to_reg(),
+                rm: tmp_hi.to_reg(),
+            });
+        }
+        IntCC::Overflow | IntCC::NotOverflow => {
+            // The V flag of the full 128-bit subtraction tells whether it overflowed.
+            //
+            //   subs xzr, lhs_lo, rhs_lo
+            //   sbcs xzr, lhs_hi, rhs_hi
+            //   cset rd, <vs|vc>
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SubS64,
+                rd: writable_zero_reg(),
+                rn: lhs_lo,
+                rm: rhs_lo,
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SbcS64,
+                rd: writable_zero_reg(),
+                rn: lhs_hi,
+                rm: rhs_hi,
+            });
+            ctx.emit(Inst::CSet {
+                cond: lower_condcode(condcode),
+                rd,
+            });
+        }
+    }
+}
+
+/// Lower a shift of the `I128` value in `src` by the dynamic amount in `amt`, of which only the
+/// low 7 bits are used, into `dst`. `op` must be one of `Lsl64`, `Lsr64` or `Asr64`.
+pub(crate) fn lower_shift_i128<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    op: ALUOp,
+    src: ValueRegs<Reg>,
+    dst: ValueRegs<Writable<Reg>>,
+    amt: Reg,
+) {
+    let (src_lo, src_hi) = (src.regs()[0], src.regs()[1]);
+    let (dst_lo, dst_hi) = (dst.regs()[0], dst.regs()[1]);
+
+    // `from` is the part whose bits cross over into the other part, `into`, and `cross_op`
+    // shifts them the other way; the machine shifts only use the low 6 bits of the amount.
+    let (from, into, into_op, cross_op) = match op {
+        ALUOp::Lsl64 => (src_lo, src_hi, ALUOp::Lsl64, ALUOp::Lsr64),
+        ALUOp::Lsr64 => (src_hi, src_lo, ALUOp::Lsr64, ALUOp::Lsl64),
+        ALUOp::Asr64 => (src_hi, src_lo, ALUOp::Lsr64, ALUOp::Lsl64),
+        _ => unreachable!("not a 64-bit shift: {:?}", op),
+    };
+    let shifted = ctx.alloc_tmp(RegClass::I64, I64);
+    let merged = ctx.alloc_tmp(RegClass::I64, I64);
+    let inv_amt = ctx.alloc_tmp(RegClass::I64, I64);
+    let cross = ctx.alloc_tmp(RegClass::I64, I64);
+
+    //   shifted = from <op> amt
+    //   merged = into <into_op> amt
+    //   inv_amt = ~amt                       ; i.e. 63 - amt, modulo 64
+    //   cross = (from <cross_op> 1) <cross_op> inv_amt
+    //   merged = merged | cross
+    //
+    // Shifting by 1 and then by 63 - amt shifts by 64 - amt, except that it correctly yields
+    // zero when amt is a multiple of 64.
+    ctx.emit(Inst::AluRRR {
+        alu_op: op,
+        rd: shifted,
+        rn: from,
+        rm: amt,
+    });
+    ctx.emit(Inst::AluRRR {
+        alu_op: into_op,
+        rd: merged,
+        rn: into,
+        rm: amt,
+    });
+    ctx.emit(Inst::AluRRR {
+        alu_op: ALUOp::OrrNot64,
+        rd: inv_amt,
+        rn: zero_reg(),
+        rm: amt,
+    });
+    ctx.emit(Inst::AluRRImmShift {
+        alu_op: cross_op,
+        rd: cross,
+        rn: from,
+        immshift: ImmShift::maybe_from_u64(1).unwrap(),
+    });
+    ctx.emit(Inst::AluRRR {
+        alu_op: cross_op,
+        rd: cross,
+        rn: cross.to_reg(),
+        rm: inv_amt.to_reg(),
+    });
+    ctx.emit(Inst::AluRRR {
+        alu_op: ALUOp::Orr64,
+        rd: merged,
+        rn: merged.to_reg(),
+        rm: cross.to_reg(),
+    });
+
+    // The value of the vacated part when shifting by 64 or more.
+    let fill = if op == ALUOp::Asr64 {
+        let fill = ctx.alloc_tmp(RegClass::I64, I64);
+        ctx.emit(Inst::AluRRImmShift {
+            alu_op: ALUOp::Asr64,
+            rd: fill,
+            rn: src_hi,
+            immshift: ImmShift::maybe_from_u64(63).unwrap(),
+        });
+        fill.to_reg()
+    } else {
+        zero_reg()
+    };
+
+    // If the amount is at least 64, `shifted` lands in the other part and the shifted part
+    // gets `fill`.
+    let (dst_shifted, dst_merged) = match op {
+        ALUOp::Lsl64 => (dst_lo, dst_hi),
+        _ => (dst_hi, dst_lo),
+    };
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    ctx.emit(Inst::AluRRImmLogic {
+        alu_op: ALUOp::And64,
+        rd: tmp,
+        rn: amt,
+        imml: ImmLogic::maybe_from_u64(64, I64).unwrap(),
+    });
+    ctx.emit(Inst::AluRRR {
+        alu_op: ALUOp::SubS64,
+        rd: writable_zero_reg(),
+        rn: tmp.to_reg(),
+        rm: zero_reg(),
+    });
+    ctx.emit(Inst::CSel {
+        cond: Cond::Ne,
+        rd: dst_shifted,
+        rn: fill,
+        rm: shifted.to_reg(),
+    });
+    ctx.emit(Inst::CSel {
+        cond: Cond::Ne,
+        rd: dst_merged,
+        rn: shifted.to_reg(),
+        rm: merged.to_reg(),
+    });
+}
+
 /// Convert a 0 / 1 result, such as from a conditional-set instruction, into a 0
 /// / -1 (all-ones) result as expected for bool operations.
 pub(crate) fn normalize_bool_result<C: LowerCtx<I = Inst>>(
@@ -1170,6 +1319,77 @@ pub(crate) fn normalize_bool_result<C: LowerCtx<I = Inst>>(
     }
 }
 
+fn make_libcall_sig<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    insn: IRInst,
+    call_conv: CallConv,
+) -> Signature {
+    let mut sig = Signature::new(call_conv);
+    for i in 0..ctx.num_inputs(insn) {
+        sig.params.push(AbiParam::new(ctx.input_ty(insn, i)));
+    }
+    for i in 0..ctx.num_outputs(insn) {
+        sig.returns.push(AbiParam::new(ctx.output_ty(insn, i)));
+    }
+    if call_conv.extends_baldrdash() {
+        // Adds the special VMContext parameter to the signature.
+        sig.params
+            .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+    }
+    sig
+}
+
+/// Lower `insn` to a call to the runtime library routine `libcall`, which takes the
+/// instruction's inputs as arguments and returns its outputs.
+pub(crate) fn emit_vm_call<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    flags: &Flags,
+    triple: &Triple,
+    libcall: LibCall,
+    insn: IRInst,
+    inputs: &[InsnInput],
+    outputs: &[InsnOutput],
+) -> CodegenResult<()> {
+    let extname = ExternalName::LibCall(libcall);
+
+    let dist = if flags.use_colocated_libcalls() {
+        RelocDistance::Near
+    } else {
+        RelocDistance::Far
+    };
+
+    let call_conv = CallConv::for_libcall(flags, CallConv::triple_default(triple));
+    let sig = make_libcall_sig(ctx, insn, call_conv);
+    let caller_conv = ctx.abi().call_conv();
+
+    let mut abi = AArch64ABICaller::from_func(&sig, &extname, dist, caller_conv)?;
+
+    abi.emit_stack_pre_adjust(ctx);
+
+    let vm_context = if call_conv.extends_baldrdash() { 1 } else { 0 };
+    assert_eq!(inputs.len() + vm_context, abi.num_args());
+
+    for (i, input) in inputs.iter().enumerate() {
+        let arg_regs = put_input_in_regs(ctx, *input);
+        abi.emit_copy_regs_to_arg(ctx, i, arg_regs);
+    }
+    if call_conv.extends_baldrdash() {
+        let vm_context_vreg = ctx
+            .get_vm_context()
+            .expect("should have a VMContext to pass to libcall funcs");
+        abi.emit_copy_regs_to_arg(ctx, inputs.len(), ValueRegs::one(vm_context_vreg));
+    }
+
+    abi.emit_call(ctx);
+    for (i, output) in outputs.iter().enumerate() {
+        let retval_regs = get_output_regs(ctx, *output);
+        abi.emit_copy_retval_to_regs(ctx, i, retval_regs);
+    }
+    abi.emit_stack_post_adjust(ctx);
+
+    Ok(())
+}
+
 //=============================================================================
 // Lowering-backend trait implementation.
 
@@ -1177,7 +1397,10 @@ impl LowerBackend for AArch64Backend {
     type MInst = Inst;
 
     fn lower<C: LowerCtx<I = Inst>>(&self, ctx: &mut C, ir_inst: IRInst) -> CodegenResult<()> {
-        lower_inst::lower_insn_to_regs(ctx, ir_inst)
+        if isle::lower(ctx, ir_inst).is_some() {
+            return Ok(());
+        }
+        lower_inst::lower_insn_to_regs(ctx, ir_inst, &self.flags, &self.triple)
     }
 
     fn lower_branch_group<C: LowerCtx<I = Inst>>(
@@ -1192,4 +1415,17 @@ impl LowerBackend for AArch64Backend {
     fn maybe_pinned_reg(&self) -> Option<Reg> {
         Some(xreg(PINNED_REG))
     }
+
+    #[cfg(feature = "enable-peepmatic")]
+    fn optimize_vcode(
+        &self,
+        vcode: &mut VCode<Inst>,
+        stack_map_info: &mut regalloc::StackmapRequestInfo,
+    ) {
+        crate::machinst::peepmatic::optimize(
+            super::peepmatic::vcode_peepmatic(),
+            vcode,
+            stack_map_info,
+        );
+    }
 }
@@ -0,0 +1,109 @@
+//! ISLE integration glue code for aarch64 lowering.
+
+// Pull in the ISLE generated code.
+#[allow(
+    dead_code,
+    unreachable_code,
+    unused_variables,
+    irrefutable_let_patterns
+)]
+mod generated_code {
+    use super::*;
+    include!(concat!(env!("OUT_DIR"), "/isle_aarch64.rs"));
+}
+
+use super::{input_to_const, put_input_in_reg, NarrowValueMode};
+use crate::ir::types::*;
+use crate::ir::{Inst, Opcode};
+use crate::isa::aarch64::inst::{
+    zero_reg, ALUOp, ALUOp3, ExtendOp, Imm12, ImmLogic, ImmShift, Inst as MInst, ShiftOp,
+    ShiftOpAndAmt, ShiftOpShiftImm,
+};
+use crate::machinst::isle::*;
+use crate::machinst::{ty_bits, InsnInput, LowerCtx, MachInst};
+use regalloc::Reg;
+
+/// The main entry point for lowering with ISLE.
+///
+/// Returns `None` if no rule matches the instruction, which must then be
+/// lowered by the hand-written code. The rules put the result in temporary
+/// registers; they are moved to the instruction's output registers, and the
+/// register allocator coalesces the moves away.
+pub(crate) fn lower<C>(lower_ctx: &mut C, inst: Inst) -> Option<()>
+where
+    C: LowerCtx<I = MInst>,
+{
+    let mut isle_ctx = IsleContext { lower_ctx };
+    let temp_regs = generated_code::constructor_lower(&mut isle_ctx, inst)?;
+
+    let lower_ctx = isle_ctx.lower_ctx;
+    let ty = lower_ctx.output_ty(inst, 0);
+    let dst_regs = lower_ctx.get_output_regs(inst, 0);
+    debug_assert_eq!(temp_regs.len(), dst_regs.len());
+    for (&dst, &temp) in dst_regs.regs().iter().zip(temp_regs.regs()) {
+        lower_ctx.emit(MInst::gen_move(dst, temp, ty));
+    }
+    Some(())
+}
+
+/// The context passed to the generated code, implementing its external
+/// constructors and extractors.
+struct IsleContext<'a, C> {
+    lower_ctx: &'a mut C,
+}
+
+impl<'a, C> generated_code::Context for IsleContext<'a, C>
+where
+    C: LowerCtx<I = MInst>,
+{
+    crate::machinst::isle::isle_prelude_methods!();
+
+    fn put_in_reg(&mut self, val: InsnInput) -> Reg {
+        put_input_in_reg(self.lower_ctx, val, NarrowValueMode::None)
+    }
+
+    fn put_in_reg_zext32(&mut self, val: InsnInput) -> Reg {
+        put_input_in_reg(self.lower_ctx, val, NarrowValueMode::ZeroExtend32)
+    }
+
+    fn put_in_reg_sext32(&mut self, val: InsnInput) -> Reg {
+        put_input_in_reg(self.lower_ctx, val, NarrowValueMode::SignExtend32)
+    }
+
+    fn zero_reg(&mut self) -> Reg {
+        zero_reg()
+    }
+
+    fn imm12_from_value(&mut self, val: InsnInput) -> Option<Imm12> {
+        input_to_const(self.lower_ctx, val).and_then(Imm12::maybe_from_u64)
+    }
+
+    fn imm12_from_negated_value(&mut self, val: InsnInput) -> Option<Imm12> {
+        let imm_value = input_to_const(self.lower_ctx, val)?;
+        let bits = ty_bits(self.lower_ctx.input_ty(val.insn, val.input));
+        let sign_extended = ((imm_value as i64) << (64 - bits)) >> (64 - bits);
+        Imm12::maybe_from_u64(sign_extended.wrapping_neg() as u64)
+    }
+
+    fn imm_logic_from_value(&mut self, val: InsnInput) -> Option<ImmLogic> {
+        let imm_value = input_to_const(self.lower_ctx, val)?;
+        let ty = self.lower_ctx.input_ty(val.insn, val.input);
+        let ty = if ty_bits(ty) < 32 { I32 } else { ty };
+        ImmLogic::maybe_from_u64(imm_value, ty)
+    }
+
+    fn lshl_from_u64(&mut self, ty: Type, amt: u64) -> Option<ShiftOpAndAmt> {
+        let shiftimm = ShiftOpShiftImm::maybe_from_shift(amt)?;
+        let bits = ty_bits(ty);
+        if bits <= std::u8::MAX as usize {
+            let shiftimm = shiftimm.mask(bits as u8);
+            Some(ShiftOpAndAmt::new(ShiftOp::LSL, shiftimm))
+        } else {
+            None
+        }
+    }
+
+    fn imm_shift_from_u64(&mut self, ty: Type, amt: u64) -> Option<ImmShift> {
+        ImmShift::maybe_from_u64(amt & ((ty_bits(ty) - 1) as u64))
+    }
+}
@@ -4,20 +4,22 @@ use crate::binemit::CodeOffset;
 use crate::ir::condcodes::FloatCC;
 use crate::ir::types::*;
 use crate::ir::Inst as IRInst;
-use crate::ir::{InstructionData, Opcode, TrapCode};
+use crate::ir::{ExternalName, InstructionData, LibCall, Opcode, TrapCode};
 use crate::machinst::lower::*;
 use crate::machinst::*;
+use crate::settings::{Flags, TlsModel};
 use crate::{CodegenError, CodegenResult};
 
 use crate::isa::aarch64::abi::*;
 use crate::isa::aarch64::inst::*;
 
-use regalloc::{RegClass, Writable};
+use regalloc::{Reg, RegClass, Writable};
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use smallvec::SmallVec;
+use target_lexicon::Triple;
 
 use super::lower::*;
 
@@ -33,6 +35,8 @@ fn is_valid_atomic_transaction_ty(ty: Type) -> bool {
 pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
     insn: IRInst,
+    flags: &Flags,
+    triple: &Triple,
 ) -> CodegenResult<()> {
     let op = ctx.data(insn).opcode();
     let inputs: SmallVec<[InsnInput; 4]> = (0..ctx.num_inputs(insn))
@@ -48,6 +52,16 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
     };
 
     match op {
+        Opcode::Iconst if ty == Some(I128) => {
+            // The immediate is sign-extended to 128 bits.
+            let imm = match ctx.data(insn) {
+                &InstructionData::UnaryImm { imm, .. } => imm.bits(),
+                _ => unreachable!(),
+            };
+            let dst = get_output_regs(ctx, outputs[0]);
+            lower_constant_u64(ctx, dst.regs()[0], imm as u64);
+            lower_constant_u64(ctx, dst.regs()[1], (imm >> 63) as u64);
+        }
         Opcode::Iconst | Opcode::Bconst | Opcode::Null => {
             let value = ctx.get_constant(insn).unwrap();
             // Sign extend constant if necessary
@@ -72,89 +86,128 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let rd = get_output_reg(ctx, outputs[0]);
             lower_constant_f64(ctx, rd, value);
         }
+        Opcode::Iadd | Opcode::Isub if ty == Some(I128) => {
+            // The high part consumes the carry (or borrow) out of the low part.
+            let lhs = put_input_in_regs(ctx, inputs[0]);
+            let rhs = put_input_in_regs(ctx, inputs[1]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            let (lo_op, hi_op) = match op {
+                Opcode::Iadd => (ALUOp::AddS64, ALUOp::Adc64),
+                Opcode::Isub => (ALUOp::SubS64, ALUOp::Sbc64),
+                _ => unreachable!(),
+            };
+            ctx.emit(Inst::AluRRR {
+                alu_op: lo_op,
+                rd: dst.regs()[0],
+                rn: lhs.regs()[0],
+                rm: rhs.regs()[0],
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: hi_op,
+                rd: dst.regs()[1],
+                rn: lhs.regs()[1],
+                rm: rhs.regs()[1],
+            });
+        }
         Opcode::Iadd => {
+            // Scalar additions are lowered by the ISLE rules in `lower.isle`.
             let rd = get_output_reg(ctx, outputs[0]);
-            let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let mul_insn =
-                    if let Some(mul_insn) = maybe_input_insn(ctx, inputs[1], Opcode::Imul) {
-                        Some((mul_insn, 0))
-                    } else if let Some(mul_insn) = maybe_input_insn(ctx, inputs[0], Opcode::Imul) {
-                        Some((mul_insn, 1))
-                    } else {
-                        None
-                    };
-                // If possible combine mul + add into madd.
-                if let Some((insn, addend_idx)) = mul_insn {
-                    let alu_op = choose_32_64(ty, ALUOp3::MAdd32, ALUOp3::MAdd64);
-                    let rn_input = InsnInput { insn, input: 0 };
-                    let rm_input = InsnInput { insn, input: 1 };
-
-                    let rn = put_input_in_reg(ctx, rn_input, NarrowValueMode::None);
-                    let rm = put_input_in_reg(ctx, rm_input, NarrowValueMode::None);
-                    let ra = put_input_in_reg(ctx, inputs[addend_idx], NarrowValueMode::None);
-
-                    ctx.emit(Inst::AluRRRR {
-                        alu_op,
-                        rd,
-                        rn,
-                        rm,
-                        ra,
-                    });
-                } else {
-                    let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                    let (rm, negated) = put_input_in_rse_imm12_maybe_negated(
-                        ctx,
-                        inputs[1],
-                        ty_bits(ty),
-                        NarrowValueMode::None,
-                    );
-                    let alu_op = if !negated {
-                        choose_32_64(ty, ALUOp::Add32, ALUOp::Add64)
-                    } else {
-                        choose_32_64(ty, ALUOp::Sub32, ALUOp::Sub64)
-                    };
-                    ctx.emit(alu_inst_imm12(alu_op, rd, rn, rm));
-                }
-            } else {
-                let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
-                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                ctx.emit(Inst::VecRRR {
-                    rd,
-                    rn,
-                    rm,
-                    alu_op: VecALUOp::Add,
-                    size: VectorSize::from_ty(ty),
-                });
-            }
+            let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            ctx.emit(Inst::VecRRR {
+                rd,
+                rn,
+                rm,
+                alu_op: VecALUOp::Add,
+                size: VectorSize::from_ty(ty.unwrap()),
+            });
         }
         Opcode::Isub => {
+            // Scalar subtractions are lowered by the ISLE rules in `lower.isle`.
             let rd = get_output_reg(ctx, outputs[0]);
             let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-            let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let (rm, negated) = put_input_in_rse_imm12_maybe_negated(
-                    ctx,
-                    inputs[1],
-                    ty_bits(ty),
-                    NarrowValueMode::None,
-                );
-                let alu_op = if !negated {
-                    choose_32_64(ty, ALUOp::Sub32, ALUOp::Sub64)
-                } else {
-                    choose_32_64(ty, ALUOp::Add32, ALUOp::Add64)
-                };
-                ctx.emit(alu_inst_imm12(alu_op, rd, rn, rm));
-            } else {
-                let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
-                ctx.emit(Inst::VecRRR {
-                    rd,
-                    rn,
-                    rm,
-                    alu_op: VecALUOp::Sub,
-                    size: VectorSize::from_ty(ty),
-                });
-            }
+            let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            ctx.emit(Inst::VecRRR {
+                rd,
+                rn,
+                rm,
+                alu_op: VecALUOp::Sub,
+                size: VectorSize::from_ty(ty.unwrap()),
+            });
+        }
+        Opcode::UaddSat | Opcode::SaddSat | Opcode::UsubSat | Opcode::SsubSat
+            if ty == Some(I128) =>
+        {
+            // Compute the saturated value before the arithmetic sets the flags: the maximum
+            // value if `lhs` is non-negative and the minimum value otherwise for signed
+            // operations, and all ones or zero for unsigned ones.
+            let lhs = put_input_in_regs(ctx, inputs[0]);
+            let rhs = put_input_in_regs(ctx, inputs[1]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            let (sat_lo, sat_hi, cond) = match op {
+                Opcode::SaddSat | Opcode::SsubSat => {
+                    let sat_lo = ctx.alloc_tmp(RegClass::I64, I64);
+                    let sat_hi = ctx.alloc_tmp(RegClass::I64, I64);
+                    ctx.emit(Inst::AluRRImmShift {
+                        alu_op: ALUOp::Asr64,
+                        rd: sat_lo,
+                        rn: lhs.regs()[1],
+                        immshift: ImmShift::maybe_from_u64(63).unwrap(),
+                    });
+                    ctx.emit(Inst::AluRRImmLogic {
+                        alu_op: ALUOp::Eor64,
+                        rd: sat_hi,
+                        rn: sat_lo.to_reg(),
+                        imml: ImmLogic::maybe_from_u64(i64::max_value() as u64, I64).unwrap(),
+                    });
+                    ctx.emit(Inst::AluRRR {
+                        alu_op: ALUOp::OrrNot64,
+                        rd: sat_lo,
+                        rn: zero_reg(),
+                        rm: sat_lo.to_reg(),
+                    });
+                    (sat_lo.to_reg(), sat_hi.to_reg(), Cond::Vs)
+                }
+                Opcode::UaddSat => {
+                    let sat = ctx.alloc_tmp(RegClass::I64, I64);
+                    lower_constant_u64(ctx, sat, u64::max_value());
+                    (sat.to_reg(), sat.to_reg(), Cond::Hs)
+                }
+                Opcode::UsubSat => (zero_reg(), zero_reg(), Cond::Lo),
+                _ => unreachable!(),
+            };
+
+            // The high part consumes the carry (or borrow) out of the low part.
+            let (lo_op, hi_op) = match op {
+                Opcode::UaddSat | Opcode::SaddSat => (ALUOp::AddS64, ALUOp::AdcS64),
+                _ => (ALUOp::SubS64, ALUOp::SbcS64),
+            };
+            let lo = ctx.alloc_tmp(RegClass::I64, I64);
+            let hi = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::AluRRR {
+                alu_op: lo_op,
+                rd: lo,
+                rn: lhs.regs()[0],
+                rm: rhs.regs()[0],
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: hi_op,
+                rd: hi,
+                rn: lhs.regs()[1],
+                rm: rhs.regs()[1],
+            });
+            ctx.emit(Inst::CSel {
+                cond,
+                rd: dst.regs()[0],
+                rn: sat_lo,
+                rm: lo.to_reg(),
+            });
+            ctx.emit(Inst::CSel {
+                cond,
+                rd: dst.regs()[1],
+                rn: sat_hi,
+                rm: hi.to_reg(),
+            });
         }
         Opcode::UaddSat | Opcode::SaddSat | Opcode::UsubSat | Opcode::SsubSat => {
             // We use the scalar SIMD & FP saturating additions and subtractions
@@ -223,154 +276,197 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::Ineg if ty == Some(I128) => {
+            // Computes 0 - src, propagating the borrow from the low part.
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::SubS64,
+                rd: dst.regs()[0],
+                rn: zero_reg(),
+                rm: src.regs()[0],
+            });
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::Sbc64,
+                rd: dst.regs()[1],
+                rn: zero_reg(),
+                rm: src.regs()[1],
+            });
+        }
+
         Opcode::Ineg => {
+            // Scalar negations are lowered by the ISLE rules in `lower.isle`.
             let rd = get_output_reg(ctx, outputs[0]);
-            let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let rn = zero_reg();
-                let rm = put_input_in_rse_imm12(ctx, inputs[0], NarrowValueMode::None);
-                let alu_op = choose_32_64(ty, ALUOp::Sub32, ALUOp::Sub64);
-                ctx.emit(alu_inst_imm12(alu_op, rd, rn, rm));
-            } else {
-                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                ctx.emit(Inst::VecMisc {
-                    op: VecMisc2::Neg,
-                    rd,
-                    rn,
-                    size: VectorSize::from_ty(ty),
-                });
-            }
+            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            ctx.emit(Inst::VecMisc {
+                op: VecMisc2::Neg,
+                rd,
+                rn,
+                size: VectorSize::from_ty(ty.unwrap()),
+            });
+        }
+
+        Opcode::Imul if ty == Some(I128) => {
+            // Only the low 128 bits of the product are needed:
+            //
+            //   umulh dst_hi, lhs_lo, rhs_lo
+            //   madd dst_hi, lhs_lo, rhs_hi, dst_hi
+            //   madd dst_hi, lhs_hi, rhs_lo, dst_hi
+            //   mul dst_lo, lhs_lo, rhs_lo
+            let lhs = put_input_in_regs(ctx, inputs[0]);
+            let rhs = put_input_in_regs(ctx, inputs[1]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            let (lhs_lo, lhs_hi) = (lhs.regs()[0], lhs.regs()[1]);
+            let (rhs_lo, rhs_hi) = (rhs.regs()[0], rhs.regs()[1]);
+            let (dst_lo, dst_hi) = (dst.regs()[0], dst.regs()[1]);
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::UMulH,
+                rd: dst_hi,
+                rn: lhs_lo,
+                rm: rhs_lo,
+            });
+            ctx.emit(Inst::AluRRRR {
+                alu_op: ALUOp3::MAdd64,
+                rd: dst_hi,
+                rn: lhs_lo,
+                rm: rhs_hi,
+                ra: dst_hi.to_reg(),
+            });
+            ctx.emit(Inst::AluRRRR {
+                alu_op: ALUOp3::MAdd64,
+                rd: dst_hi,
+                rn: lhs_hi,
+                rm: rhs_lo,
+                ra: dst_hi.to_reg(),
+            });
+            ctx.emit(Inst::AluRRRR {
+                alu_op: ALUOp3::MAdd64,
+                rd: dst_lo,
+                rn: lhs_lo,
+                rm: rhs_lo,
+                ra: zero_reg(),
+            });
         }
 
         Opcode::Imul => {
+            // Scalar multiplications are lowered by the ISLE rules in `lower.isle`.
             let rd = get_output_reg(ctx, outputs[0]);
             let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
             let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
             let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let alu_op = choose_32_64(ty, ALUOp3::MAdd32, ALUOp3::MAdd64);
-                ctx.emit(Inst::AluRRRR {
-                    alu_op,
+            if ty == I64X2 {
+                let tmp1 = ctx.alloc_tmp(RegClass::V128, I64X2);
+                let tmp2 = ctx.alloc_tmp(RegClass::V128, I64X2);
+
+                // This I64X2 multiplication is performed with several 32-bit
+                // operations.
+
+                // 64-bit numbers x and y, can be represented as:
+                //   x = a + 2^32(b)
+                //   y = c + 2^32(d)
+
+                // A 64-bit multiplication is:
+                //   x * y = ac + 2^32(ad + bc) + 2^64(bd)
+                // note: `2^64(bd)` can be ignored, the value is too large to fit in
+                // 64 bits.
+
+                // This sequence implements a I64X2 multiply, where the registers
+                // `rn` and `rm` are split up into 32-bit components:
+                //   rn = |d|c|b|a|
+                //   rm = |h|g|f|e|
+                //
+                //   rn * rm = |cg + 2^32(ch + dg)|ae + 2^32(af + be)|
+                //
+                //  The sequence is:
+                //  rev64 rd.4s, rm.4s
+                //  mul rd.4s, rd.4s, rn.4s
+                //  xtn tmp1.2s, rn.2d
+                //  addp rd.4s, rd.4s, rd.4s
+                //  xtn tmp2.2s, rm.2d
+                //  shll rd.2d, rd.2s, #32
+                //  umlal rd.2d, tmp2.2s, tmp1.2s
+
+                // Reverse the 32-bit elements in the 64-bit words.
+                //   rd = |g|h|e|f|
+                ctx.emit(Inst::VecMisc {
+                    op: VecMisc2::Rev64,
                     rd,
-                    rn,
-                    rm,
-                    ra: zero_reg(),
+                    rn: rm,
+                    size: VectorSize::Size32x4,
                 });
-            } else {
-                if ty == I64X2 {
-                    let tmp1 = ctx.alloc_tmp(RegClass::V128, I64X2);
-                    let tmp2 = ctx.alloc_tmp(RegClass::V128, I64X2);
-
-                    // This I64X2 multiplication is performed with several 32-bit
-                    // operations.
-
-                    // 64-bit numbers x and y, can be represented as:
-                    //   x = a + 2^32(b)
-                    //   y = c + 2^32(d)
-
-                    // A 64-bit multiplication is:
-                    //   x * y = ac + 2^32(ad + bc) + 2^64(bd)
-                    // note: `2^64(bd)` can be ignored, the value is too large to fit in
-                    // 64 bits.
-
-                    // This sequence implements a I64X2 multiply, where the registers
-                    // `rn` and `rm` are split up into 32-bit components:
-                    //   rn = |d|c|b|a|
-                    //   rm = |h|g|f|e|
-                    //
-                    //   rn * rm = |cg + 2^32(ch + dg)|ae + 2^32(af + be)|
-                    //
-                    //  The sequence is:
-                    //  rev64 rd.4s, rm.4s
-                    //  mul rd.4s, rd.4s, rn.4s
-                    //  xtn tmp1.2s, rn.2d
-                    //  addp rd.4s, rd.4s, rd.4s
-                    //  xtn tmp2.2s, rm.2d
-                    //  shll rd.2d, rd.2s, #32
-                    //  umlal rd.2d, tmp2.2s, tmp1.2s
-
-                    // Reverse the 32-bit elements in the 64-bit words.
-                    //   rd = |g|h|e|f|
-                    ctx.emit(Inst::VecMisc {
-                        op: VecMisc2::Rev64,
-                        rd,
-                        rn: rm,
-                        size: VectorSize::Size32x4,
-                    });
 
-                    // Calculate the high half components.
-                    //   rd = |dg|ch|be|af|
-                    //
-                    // Note that this 32-bit multiply of the high half
-                    // discards the bits that would overflow, same as
-                    // if 64-bit operations were used. Also the Shll
-                    // below would shift out the overflow bits anyway.
-                    ctx.emit(Inst::VecRRR {
-                        alu_op: VecALUOp::Mul,
-                        rd,
-                        rn: rd.to_reg(),
-                        rm: rn,
-                        size: VectorSize::Size32x4,
-                    });
+                // Calculate the high half components.
+                //   rd = |dg|ch|be|af|
+                //
+                // Note that this 32-bit multiply of the high half
+                // discards the bits that would overflow, same as
+                // if 64-bit operations were used. Also the Shll
+                // below would shift out the overflow bits anyway.
+                ctx.emit(Inst::VecRRR {
+                    alu_op: VecALUOp::Mul,
+                    rd,
+                    rn: rd.to_reg(),
+                    rm: rn,
+                    size: VectorSize::Size32x4,
+                });
 
-                    // Extract the low half components of rn.
-                    //   tmp1 = |c|a|
-                    ctx.emit(Inst::VecMiscNarrow {
-                        op: VecMiscNarrowOp::Xtn,
-                        rd: tmp1,
-                        rn,
-                        size: VectorSize::Size32x2,
-                        high_half: false,
-                    });
+                // Extract the low half components of rn.
+                //   tmp1 = |c|a|
+                ctx.emit(Inst::VecMiscNarrow {
+                    op: VecMiscNarrowOp::Xtn,
+                    rd: tmp1,
+                    rn,
+                    size: VectorSize::Size32x2,
+                    high_half: false,
+                });
 
-                    // Sum the respective high half components.
-                    //   rd = |dg+ch|be+af||dg+ch|be+af|
-                    ctx.emit(Inst::VecRRR {
-                        alu_op: VecALUOp::Addp,
-                        rd: rd,
-                        rn: rd.to_reg(),
-                        rm: rd.to_reg(),
-                        size: VectorSize::Size32x4,
-                    });
+                // Sum the respective high half components.
+                //   rd = |dg+ch|be+af||dg+ch|be+af|
+                ctx.emit(Inst::VecRRR {
+                    alu_op: VecALUOp::Addp,
+                    rd: rd,
+                    rn: rd.to_reg(),
+                    rm: rd.to_reg(),
+                    size: VectorSize::Size32x4,
+                });
 
-                    // Extract the low half components of rm.
-                    //   tmp2 = |g|e|
-                    ctx.emit(Inst::VecMiscNarrow {
-                        op: VecMiscNarrowOp::Xtn,
-                        rd: tmp2,
-                        rn: rm,
-                        size: VectorSize::Size32x2,
-                        high_half: false,
-                    });
+                // Extract the low half components of rm.
+                //   tmp2 = |g|e|
+                ctx.emit(Inst::VecMiscNarrow {
+                    op: VecMiscNarrowOp::Xtn,
+                    rd: tmp2,
+                    rn: rm,
+                    size: VectorSize::Size32x2,
+                    high_half: false,
+                });
 
-                    // Shift the high half components, into the high half.
-                    //   rd = |dg+ch << 32|be+af << 32|
-                    ctx.emit(Inst::VecMisc {
-                        op: VecMisc2::Shll,
-                        rd,
-                        rn: rd.to_reg(),
-                        size: VectorSize::Size32x2,
-                    });
+                // Shift the high half components, into the high half.
+                //   rd = |dg+ch << 32|be+af << 32|
+                ctx.emit(Inst::VecMisc {
+                    op: VecMisc2::Shll,
+                    rd,
+                    rn: rd.to_reg(),
+                    size: VectorSize::Size32x2,
+                });
 
-                    // Multiply the low components together, and accumulate with the high
-                    // half.
-                    //   rd = |rd[1] + cg|rd[0] + ae|
-                    ctx.emit(Inst::VecRRR {
-                        alu_op: VecALUOp::Umlal,
-                        rd,
-                        rn: tmp2.to_reg(),
-                        rm: tmp1.to_reg(),
-                        size: VectorSize::Size32x2,
-                    });
-                } else {
-                    ctx.emit(Inst::VecRRR {
-                        alu_op: VecALUOp::Mul,
-                        rd,
-                        rn,
-                        rm,
-                        size: VectorSize::from_ty(ty),
-                    });
-                }
+                // Multiply the low components together, and accumulate with the high
+                // half.
+                //   rd = |rd[1] + cg|rd[0] + ae|
+                ctx.emit(Inst::VecRRR {
+                    alu_op: VecALUOp::Umlal,
+                    rd,
+                    rn: tmp2.to_reg(),
+                    rm: tmp1.to_reg(),
+                    size: VectorSize::Size32x2,
+                });
+            } else {
+                ctx.emit(Inst::VecRRR {
+                    alu_op: VecALUOp::Mul,
+                    rd,
+                    rn,
+                    rm,
+                    size: VectorSize::from_ty(ty),
+                });
             }
         }
 
@@ -432,6 +528,70 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem if ty == Some(I128) => {
+            // There's no 128-bit division instruction, so call into the runtime library. Its
+            // routines don't trap, hence the checks before the call.
+            let dividend = put_input_in_regs(ctx, inputs[0]);
+            let divisor = put_input_in_regs(ctx, inputs[1]);
+            let (dividend_lo, dividend_hi) = (dividend.regs()[0], dividend.regs()[1]);
+            let (divisor_lo, divisor_hi) = (divisor.regs()[0], divisor.regs()[1]);
+
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::AluRRR {
+                alu_op: ALUOp::Orr64,
+                rd: tmp,
+                rn: divisor_lo,
+                rm: divisor_hi,
+            });
+            ctx.emit(Inst::TrapIf {
+                trap_code: TrapCode::IntegerDivisionByZero,
+                kind: CondBrKind::Zero(tmp.to_reg()),
+            });
+
+            if op == Opcode::Sdiv {
+                // `INT_MIN / -1` overflows, i.e. when all of `!(divisor_lo & divisor_hi)`,
+                // `dividend_hi ^ INT_MIN` and `dividend_lo` are zero.
+                let int_min = ctx.alloc_tmp(RegClass::I64, I64);
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::And64,
+                    rd: tmp,
+                    rn: divisor_lo,
+                    rm: divisor_hi,
+                });
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::OrrNot64,
+                    rd: tmp,
+                    rn: zero_reg(),
+                    rm: tmp.to_reg(),
+                });
+                ctx.emit(Inst::AluRRImmLogic {
+                    alu_op: ALUOp::Eor64,
+                    rd: int_min,
+                    rn: dividend_hi,
+                    imml: ImmLogic::maybe_from_u64(1 << 63, I64).unwrap(),
+                });
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::Orr64,
+                    rd: tmp,
+                    rn: tmp.to_reg(),
+                    rm: dividend_lo,
+                });
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::Orr64,
+                    rd: tmp,
+                    rn: tmp.to_reg(),
+                    rm: int_min.to_reg(),
+                });
+                ctx.emit(Inst::TrapIf {
+                    trap_code: TrapCode::IntegerOverflow,
+                    kind: CondBrKind::Zero(tmp.to_reg()),
+                });
+            }
+
+            let libcall = LibCall::for_inst(op, I128).unwrap();
+            emit_vm_call(ctx, flags, triple, libcall, insn, &inputs, &outputs)?;
+        }
+
         Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem => {
             let is_signed = match op {
                 Opcode::Udiv | Opcode::Urem => false,
@@ -554,6 +714,25 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::Uextend | Opcode::Sextend if ty == Some(I128) => {
+            // Extend to 64 bits first, then fill the high part with zeroes or sign bits.
+            let dst = get_output_regs(ctx, outputs[0]);
+            if op == Opcode::Sextend {
+                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::SignExtend64);
+                ctx.emit(Inst::gen_move(dst.regs()[0], rn, I64));
+                ctx.emit(Inst::AluRRImmShift {
+                    alu_op: ALUOp::Asr64,
+                    rd: dst.regs()[1],
+                    rn,
+                    immshift: ImmShift::maybe_from_u64(63).unwrap(),
+                });
+            } else {
+                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+                ctx.emit(Inst::gen_move(dst.regs()[0], rn, I64));
+                lower_constant_u64(ctx, dst.regs()[1], 0);
+            }
+        }
+
         Opcode::Uextend | Opcode::Sextend => {
             let output_ty = ty.unwrap();
             let input_ty = ctx.input_ty(insn, 0);
@@ -608,122 +787,164 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
-        Opcode::Bnot => {
-            let rd = get_output_reg(ctx, outputs[0]);
-            let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let rm = put_input_in_rs_immlogic(ctx, inputs[0], NarrowValueMode::None);
-                let alu_op = choose_32_64(ty, ALUOp::OrrNot32, ALUOp::OrrNot64);
+        Opcode::Bnot if ty == Some(I128) => {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            for (&rd, &rm) in dst.regs().iter().zip(src.regs()) {
                 // NOT rd, rm ==> ORR_NOT rd, zero, rm
-                ctx.emit(alu_inst_immlogic(alu_op, rd, zero_reg(), rm));
-            } else {
-                let rm = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                ctx.emit(Inst::VecMisc {
-                    op: VecMisc2::Not,
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::OrrNot64,
                     rd,
-                    rn: rm,
-                    size: VectorSize::from_ty(ty),
+                    rn: zero_reg(),
+                    rm,
                 });
             }
         }
 
+        Opcode::Bnot => {
+            // Scalar cases are lowered by the ISLE rules in `lower.isle`.
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rm = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            ctx.emit(Inst::VecMisc {
+                op: VecMisc2::Not,
+                rd,
+                rn: rm,
+                size: VectorSize::from_ty(ty.unwrap()),
+            });
+        }
+
+        Opcode::Band
+        | Opcode::Bor
+        | Opcode::Bxor
+        | Opcode::BandNot
+        | Opcode::BorNot
+        | Opcode::BxorNot
+            if ty == Some(I128) =>
+        {
+            let lhs = put_input_in_regs(ctx, inputs[0]);
+            let rhs = put_input_in_regs(ctx, inputs[1]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            let alu_op = match op {
+                Opcode::Band => ALUOp::And64,
+                Opcode::Bor => ALUOp::Orr64,
+                Opcode::Bxor => ALUOp::Eor64,
+                Opcode::BandNot => ALUOp::AndNot64,
+                Opcode::BorNot => ALUOp::OrrNot64,
+                Opcode::BxorNot => ALUOp::EorNot64,
+                _ => unreachable!(),
+            };
+            for ((&rd, &rn), &rm) in dst.regs().iter().zip(lhs.regs()).zip(rhs.regs()) {
+                ctx.emit(Inst::AluRRR { alu_op, rd, rn, rm });
+            }
+        }
+
         Opcode::Band
         | Opcode::Bor
         | Opcode::Bxor
         | Opcode::BandNot
         | Opcode::BorNot
         | Opcode::BxorNot => {
+            // Scalar cases are lowered by the ISLE rules in `lower.isle`.
+            let alu_op = match op {
+                Opcode::Band => VecALUOp::And,
+                Opcode::BandNot => VecALUOp::Bic,
+                Opcode::Bor => VecALUOp::Orr,
+                Opcode::Bxor => VecALUOp::Eor,
+                _ => unreachable!(),
+            };
+
+            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
             let rd = get_output_reg(ctx, outputs[0]);
-            let ty = ty.unwrap();
-            if !ty.is_vector() {
-                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                let rm = put_input_in_rs_immlogic(ctx, inputs[1], NarrowValueMode::None);
-                let alu_op = match op {
-                    Opcode::Band => choose_32_64(ty, ALUOp::And32, ALUOp::And64),
-                    Opcode::Bor => choose_32_64(ty, ALUOp::Orr32, ALUOp::Orr64),
-                    Opcode::Bxor => choose_32_64(ty, ALUOp::Eor32, ALUOp::Eor64),
-                    Opcode::BandNot => choose_32_64(ty, ALUOp::AndNot32, ALUOp::AndNot64),
-                    Opcode::BorNot => choose_32_64(ty, ALUOp::OrrNot32, ALUOp::OrrNot64),
-                    Opcode::BxorNot => choose_32_64(ty, ALUOp::EorNot32, ALUOp::EorNot64),
-                    _ => unreachable!(),
-                };
-                ctx.emit(alu_inst_immlogic(alu_op, rd, rn, rm));
-            } else {
-                let alu_op = match op {
-                    Opcode::Band => VecALUOp::And,
-                    Opcode::BandNot => VecALUOp::Bic,
-                    Opcode::Bor => VecALUOp::Orr,
-                    Opcode::Bxor => VecALUOp::Eor,
-                    _ => unreachable!(),
-                };
 
-                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
-                let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::VecRRR {
+                alu_op,
+                rd,
+                rn,
+                rm,
+                size: VectorSize::from_ty(ty.unwrap()),
+            });
+        }
 
-                ctx.emit(Inst::VecRRR {
-                    alu_op,
-                    rd,
-                    rn,
-                    rm,
-                    size: VectorSize::from_ty(ty),
-                });
+        Opcode::Ishl | Opcode::Ushr | Opcode::Sshr | Opcode::Rotl | Opcode::Rotr
+            if ty == Some(I128) =>
+        {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            // Only the low 7 bits of the amount matter, so an `I128` amount can be truncated.
+            let amt = put_input_in_regs(ctx, inputs[1]).regs()[0];
+            let dst = get_output_regs(ctx, outputs[0]);
+            match op {
+                Opcode::Ishl => lower_shift_i128(ctx, ALUOp::Lsl64, src, dst, amt),
+                Opcode::Ushr => lower_shift_i128(ctx, ALUOp::Lsr64, src, dst, amt),
+                Opcode::Sshr => lower_shift_i128(ctx, ALUOp::Asr64, src, dst, amt),
+                Opcode::Rotl | Opcode::Rotr => {
+                    // rotl(x, n) = (x << n) | (x >> (128 - n)), and conversely for rotr.
+                    let (first_op, second_op) = if op == Opcode::Rotl {
+                        (ALUOp::Lsl64, ALUOp::Lsr64)
+                    } else {
+                        (ALUOp::Lsr64, ALUOp::Lsl64)
+                    };
+                    let neg_amt = ctx.alloc_tmp(RegClass::I64, I64);
+                    lower_constant_u64(ctx, neg_amt, 128);
+                    ctx.emit(Inst::AluRRR {
+                        alu_op: ALUOp::Sub64,
+                        rd: neg_amt,
+                        rn: neg_amt.to_reg(),
+                        rm: amt,
+                    });
+
+                    let tmp = ValueRegs::two(
+                        ctx.alloc_tmp(RegClass::I64, I64),
+                        ctx.alloc_tmp(RegClass::I64, I64),
+                    );
+                    lower_shift_i128(ctx, first_op, src, tmp, amt);
+                    lower_shift_i128(ctx, second_op, src, dst, neg_amt.to_reg());
+                    for (&rd, &rm) in dst.regs().iter().zip(tmp.regs()) {
+                        ctx.emit(Inst::AluRRR {
+                            alu_op: ALUOp::Orr64,
+                            rd,
+                            rn: rd.to_reg(),
+                            rm: rm.to_reg(),
+                        });
+                    }
+                }
+                _ => unreachable!(),
             }
         }
 
         Opcode::Ishl | Opcode::Ushr | Opcode::Sshr => {
+            // Scalar shifts are lowered by the ISLE rules in `lower.isle`.
             let ty = ty.unwrap();
             let rd = get_output_reg(ctx, outputs[0]);
-            if !ty.is_vector() {
-                let size = OperandSize::from_bits(ty_bits(ty));
-                let narrow_mode = match (op, size) {
-                    (Opcode::Ishl, _) => NarrowValueMode::None,
-                    (Opcode::Ushr, OperandSize::Size64) => NarrowValueMode::ZeroExtend64,
-                    (Opcode::Ushr, OperandSize::Size32) => NarrowValueMode::ZeroExtend32,
-                    (Opcode::Sshr, OperandSize::Size64) => NarrowValueMode::SignExtend64,
-                    (Opcode::Sshr, OperandSize::Size32) => NarrowValueMode::SignExtend32,
-                    _ => unreachable!(),
-                };
-                let rn = put_input_in_reg(ctx, inputs[0], narrow_mode);
-                let rm = put_input_in_reg_immshift(ctx, inputs[1], ty_bits(ty));
-                let alu_op = match op {
-                    Opcode::Ishl => choose_32_64(ty, ALUOp::Lsl32, ALUOp::Lsl64),
-                    Opcode::Ushr => choose_32_64(ty, ALUOp::Lsr32, ALUOp::Lsr64),
-                    Opcode::Sshr => choose_32_64(ty, ALUOp::Asr32, ALUOp::Asr64),
-                    _ => unreachable!(),
-                };
-                ctx.emit(alu_inst_immshift(alu_op, rd, rn, rm));
-            } else {
-                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-                let size = VectorSize::from_ty(ty);
-                let (alu_op, is_right_shift) = match op {
-                    Opcode::Ishl => (VecALUOp::Sshl, false),
-                    Opcode::Ushr => (VecALUOp::Ushl, true),
-                    Opcode::Sshr => (VecALUOp::Sshl, true),
-                    _ => unreachable!(),
-                };
+            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let size = VectorSize::from_ty(ty);
+            let (alu_op, is_right_shift) = match op {
+                Opcode::Ishl => (VecALUOp::Sshl, false),
+                Opcode::Ushr => (VecALUOp::Ushl, true),
+                Opcode::Sshr => (VecALUOp::Sshl, true),
+                _ => unreachable!(),
+            };
 
-                let rm = if is_right_shift {
-                    // Right shifts are implemented with a negative left shift.
-                    let tmp = ctx.alloc_tmp(RegClass::I64, I32);
-                    let rm = put_input_in_rse_imm12(ctx, inputs[1], NarrowValueMode::None);
-                    let rn = zero_reg();
-                    ctx.emit(alu_inst_imm12(ALUOp::Sub32, tmp, rn, rm));
-                    tmp.to_reg()
-                } else {
-                    put_input_in_reg(ctx, inputs[1], NarrowValueMode::None)
-                };
+            let rm = if is_right_shift {
+                // Right shifts are implemented with a negative left shift.
+                let tmp = ctx.alloc_tmp(RegClass::I64, I32);
+                let rm = put_input_in_rse_imm12(ctx, inputs[1], NarrowValueMode::None);
+                let rn = zero_reg();
+                ctx.emit(alu_inst_imm12(ALUOp::Sub32, tmp, rn, rm));
+                tmp.to_reg()
+            } else {
+                put_input_in_reg(ctx, inputs[1], NarrowValueMode::None)
+            };
 
-                ctx.emit(Inst::VecDup { rd, rn: rm, size });
+            ctx.emit(Inst::VecDup { rd, rn: rm, size });
 
-                ctx.emit(Inst::VecRRR {
-                    alu_op,
-                    rd,
-                    rn,
-                    rm: rd.to_reg(),
-                    size,
-                });
-            }
+            ctx.emit(Inst::VecRRR {
+                alu_op,
+                rd,
+                rn,
+                rm: rd.to_reg(),
+                size,
+            });
         }
 
         Opcode::Rotr | Opcode::Rotl => {
@@ -1173,6 +1394,16 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             };
             let is_float = ty_has_float_or_vec_representation(elem_ty);
 
+            if elem_ty == I128 {
+                // Load the two parts separately, low part first (little-endian).
+                let dst = get_output_regs(ctx, outputs[0]);
+                for (i, &rd) in dst.regs().iter().enumerate() {
+                    let mem = lower_address(ctx, I64, &inputs[..], off + 8 * i as i32);
+                    ctx.emit(Inst::ULoad64 { rd, mem });
+                }
+                return Ok(());
+            }
+
             let mem = lower_address(ctx, elem_ty, &inputs[..], off);
             let rd = get_output_reg(ctx, outputs[0]);
 
@@ -1253,6 +1484,16 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             };
             let is_float = ty_has_float_or_vec_representation(elem_ty);
 
+            if elem_ty == I128 {
+                // Store the two parts separately, low part first (little-endian).
+                let src = put_input_in_regs(ctx, inputs[0]);
+                for (i, &rd) in src.regs().iter().enumerate() {
+                    let mem = lower_address(ctx, I64, &inputs[1..], off + 8 * i as i32);
+                    ctx.emit(Inst::Store64 { rd, mem });
+                }
+                return Ok(());
+            }
+
             let mem = lower_address(ctx, elem_ty, &inputs[1..], off);
             let rd = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
 
@@ -1389,9 +1630,7 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
         Opcode::Select => {
             let flag_input = inputs[0];
-            let cond = if let Some(icmp_insn) =
-                maybe_input_insn_via_conv(ctx, flag_input, Opcode::Icmp, Opcode::Bint)
-            {
+            let cond = if let Some(icmp_insn) = maybe_input_icmp(ctx, flag_input) {
                 let condcode = ctx.data(icmp_insn).cond_code().unwrap();
                 let cond = lower_condcode(condcode);
                 let is_signed = condcode_is_signed(condcode);
@@ -1422,11 +1661,21 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 Cond::Ne
             };
 
+            let ty = ctx.output_ty(insn, 0);
+            if ty == I128 {
+                let lhs = put_input_in_regs(ctx, inputs[1]);
+                let rhs = put_input_in_regs(ctx, inputs[2]);
+                let dst = get_output_regs(ctx, outputs[0]);
+                for ((&rd, &rn), &rm) in dst.regs().iter().zip(lhs.regs()).zip(rhs.regs()) {
+                    ctx.emit(Inst::CSel { cond, rd, rn, rm });
+                }
+                return Ok(());
+            }
+
             // csel.cond rd, rn, rm
             let rd = get_output_reg(ctx, outputs[0]);
             let rn = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
             let rm = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
-            let ty = ctx.output_ty(insn, 0);
             let bits = ty_bits(ty);
             let is_float = ty_has_float_or_vec_representation(ty);
             if is_float && bits == 32 {
@@ -1463,6 +1712,10 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             } else {
                 ctx.emit(Inst::CSel { cond, rd, rn, rm });
             }
+            if op == Opcode::SelectifSpectreGuard {
+                // Keep the result of the select from being predicted.
+                ctx.emit(Inst::Csdb);
+            }
         }
 
         Opcode::Bitselect | Opcode::Vselect => {
@@ -1566,6 +1819,29 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             ctx.emit(Inst::gen_move(rd, rn, ty));
         }
 
+        Opcode::Ireduce if ctx.input_ty(insn, 0) == I128 => {
+            // Only the low part is needed.
+            let rn = put_input_in_regs(ctx, inputs[0]).regs()[0];
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::gen_move(rd, rn, I64));
+        }
+
+        Opcode::Iconcat => {
+            let lo = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let hi = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let dst = get_output_regs(ctx, outputs[0]);
+            ctx.emit(Inst::gen_move(dst.regs()[0], lo, I64));
+            ctx.emit(Inst::gen_move(dst.regs()[1], hi, I64));
+        }
+
+        Opcode::Isplit => {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let rd_lo = get_output_reg(ctx, outputs[0]);
+            let rd_hi = get_output_reg(ctx, outputs[1]);
+            ctx.emit(Inst::gen_move(rd_lo, src.regs()[0], I64));
+            ctx.emit(Inst::gen_move(rd_hi, src.regs()[1], I64));
+        }
+
         Opcode::Breduce | Opcode::Ireduce => {
             // Smaller integers/booleans are stored with high-order bits
             // undefined, so we can simply do a copy.
@@ -1710,10 +1986,16 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 // N.B.: according to the AArch64 ABI, the top bits of a register
                 // (above the bits for the value's type) are undefined, so we
                 // need not extend the return values.
-                let reg = put_input_in_reg(ctx, *input, NarrowValueMode::None);
-                let retval_reg = ctx.retval(i);
+                let src_regs = put_input_in_regs(ctx, *input);
+                let retval_regs = ctx.retval(i);
                 let ty = ctx.input_ty(insn, i);
-                ctx.emit(Inst::gen_move(retval_reg, reg, ty));
+                if let (Some(rd), Some(rn)) = (retval_regs.only_reg(), src_regs.only_reg()) {
+                    ctx.emit(Inst::gen_move(rd, rn, ty));
+                } else {
+                    for (&rd, &rn) in retval_regs.regs().iter().zip(src_regs.regs()) {
+                        ctx.emit(Inst::gen_move(rd, rn, I64));
+                    }
+                }
             }
             // N.B.: the Ret itself is generated by the ABI.
         }
@@ -1726,6 +2008,15 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             panic!("Should never reach ifcmp as isel root!");
         }
 
+        Opcode::Icmp if ctx.input_ty(insn, 0) == I128 => {
+            let condcode = ctx.data(insn).cond_code().unwrap();
+            let lhs = put_input_in_regs(ctx, inputs[0]);
+            let rhs = put_input_in_regs(ctx, inputs[1]);
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_icmp_i128(ctx, condcode, lhs, rhs, rd);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
         Opcode::Icmp => {
             let condcode = ctx.data(insn).cond_code().unwrap();
             let cond = lower_condcode(condcode);
@@ -1838,13 +2129,9 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
         Opcode::FuncAddr => {
             let rd = get_output_reg(ctx, outputs[0]);
-            let (extname, _) = ctx.call_target(insn).unwrap();
+            let (extname, dist) = ctx.call_target(insn).unwrap();
             let extname = extname.clone();
-            ctx.emit(Inst::LoadExtName {
-                rd,
-                name: Box::new(extname),
-                offset: 0,
-            });
+            lower_load_ext_name(ctx, flags, rd, extname, dist, 0);
         }
 
         Opcode::GlobalValue => {
@@ -1853,13 +2140,9 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
         Opcode::SymbolValue => {
             let rd = get_output_reg(ctx, outputs[0]);
-            let (extname, _, offset) = ctx.symbol_value(insn).unwrap();
+            let (extname, dist, offset) = ctx.symbol_value(insn).unwrap();
             let extname = extname.clone();
-            ctx.emit(Inst::LoadExtName {
-                rd,
-                name: Box::new(extname),
-                offset,
-            });
+            lower_load_ext_name(ctx, flags, rd, extname, dist, offset);
         }
 
         Opcode::Call | Opcode::CallIndirect => {
@@ -1892,13 +2175,13 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             abi.emit_stack_pre_adjust(ctx);
             assert!(inputs.len() == abi.num_args());
             for (i, input) in inputs.iter().enumerate() {
-                let arg_reg = put_input_in_reg(ctx, *input, NarrowValueMode::None);
-                abi.emit_copy_reg_to_arg(ctx, i, arg_reg);
+                let arg_regs = put_input_in_regs(ctx, *input);
+                abi.emit_copy_regs_to_arg(ctx, i, arg_regs);
             }
             abi.emit_call(ctx);
             for (i, output) in outputs.iter().enumerate() {
-                let retval_reg = get_output_reg(ctx, *output);
-                abi.emit_copy_retval_to_reg(ctx, i, retval_reg);
+                let retval_regs = get_output_regs(ctx, *output);
+                abi.emit_copy_retval_to_regs(ctx, i, retval_regs);
             }
             abi.emit_stack_post_adjust(ctx);
         }
@@ -2347,27 +2630,50 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             panic!("Vector ops not implemented.");
         }
 
-        Opcode::Isplit | Opcode::Iconcat => panic!("Vector ops not supported."),
-
         Opcode::Imax | Opcode::Umax | Opcode::Umin | Opcode::Imin => {
-            let alu_op = match op {
-                Opcode::Umin => VecALUOp::Umin,
-                Opcode::Imin => VecALUOp::Smin,
-                Opcode::Umax => VecALUOp::Umax,
-                Opcode::Imax => VecALUOp::Smax,
-                _ => unreachable!(),
-            };
-            let rd = get_output_reg(ctx, outputs[0]);
-            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-            let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
             let ty = ty.unwrap();
-            ctx.emit(Inst::VecRRR {
-                alu_op,
-                rd,
-                rn,
-                rm,
-                size: VectorSize::from_ty(ty),
-            });
+            let rd = get_output_reg(ctx, outputs[0]);
+            if !ty.is_vector() && ty_bits(ty) <= 64 {
+                // Compare and select, so that no branch depends on the operands.
+                let (narrow_mode, cond) = match (op, ty_bits(ty) <= 32) {
+                    (Opcode::Imin, true) => (NarrowValueMode::SignExtend32, Cond::Lt),
+                    (Opcode::Imin, false) => (NarrowValueMode::SignExtend64, Cond::Lt),
+                    (Opcode::Imax, true) => (NarrowValueMode::SignExtend32, Cond::Gt),
+                    (Opcode::Imax, false) => (NarrowValueMode::SignExtend64, Cond::Gt),
+                    (Opcode::Umin, true) => (NarrowValueMode::ZeroExtend32, Cond::Lo),
+                    (Opcode::Umin, false) => (NarrowValueMode::ZeroExtend64, Cond::Lo),
+                    (Opcode::Umax, true) => (NarrowValueMode::ZeroExtend32, Cond::Hi),
+                    (Opcode::Umax, false) => (NarrowValueMode::ZeroExtend64, Cond::Hi),
+                    _ => unreachable!(),
+                };
+                let rn = put_input_in_reg(ctx, inputs[0], narrow_mode);
+                let rm = put_input_in_reg(ctx, inputs[1], narrow_mode);
+                let alu_op = choose_32_64(ty, ALUOp::SubS32, ALUOp::SubS64);
+                ctx.emit(Inst::AluRRR {
+                    alu_op,
+                    rd: writable_zero_reg(),
+                    rn,
+                    rm,
+                });
+                ctx.emit(Inst::CSel { cond, rd, rn, rm });
+            } else {
+                let alu_op = match op {
+                    Opcode::Umin => VecALUOp::Umin,
+                    Opcode::Imin => VecALUOp::Smin,
+                    Opcode::Umax => VecALUOp::Umax,
+                    Opcode::Imax => VecALUOp::Smax,
+                    _ => unreachable!(),
+                };
+                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+                let rm = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                ctx.emit(Inst::VecRRR {
+                    alu_op,
+                    rd,
+                    rn,
+                    rm,
+                    size: VectorSize::from_ty(ty),
+                });
+            }
         }
 
         Opcode::WideningPairwiseDotProductS => {
@@ -2966,12 +3272,31 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
             // Now handle the iadd as above, except use an AddS opcode that sets
             // flags.
-            let rd = get_output_reg(ctx, outputs[0]);
-            let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
-            let rm = put_input_in_rse_imm12(ctx, inputs[1], NarrowValueMode::None);
             let ty = ty.unwrap();
-            let alu_op = choose_32_64(ty, ALUOp::AddS32, ALUOp::AddS64);
-            ctx.emit(alu_inst_imm12(alu_op, rd, rn, rm));
+            if ty == I128 {
+                // The carry out of the high part is the carry out of the whole addition.
+                let lhs = put_input_in_regs(ctx, inputs[0]);
+                let rhs = put_input_in_regs(ctx, inputs[1]);
+                let dst = get_output_regs(ctx, outputs[0]);
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::AddS64,
+                    rd: dst.regs()[0],
+                    rn: lhs.regs()[0],
+                    rm: rhs.regs()[0],
+                });
+                ctx.emit(Inst::AluRRR {
+                    alu_op: ALUOp::AdcS64,
+                    rd: dst.regs()[1],
+                    rn: lhs.regs()[1],
+                    rm: rhs.regs()[1],
+                });
+            } else {
+                let rd = get_output_reg(ctx, outputs[0]);
+                let rn = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+                let rm = put_input_in_rse_imm12(ctx, inputs[1], NarrowValueMode::None);
+                let alu_op = choose_32_64(ty, ALUOp::AddS32, ALUOp::AddS64);
+                ctx.emit(alu_inst_imm12(alu_op, rd, rn, rm));
+            }
         }
 
         Opcode::IaddImm
@@ -3127,12 +3452,41 @@ pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             });
         }
 
-        Opcode::TlsValue => unimplemented!("tls_value"),
+        Opcode::TlsValue => match flags.tls_model() {
+            TlsModel::ElfGd => {
+                let rd = get_output_reg(ctx, outputs[0]);
+                let (name, _, _) = ctx.symbol_value(insn).unwrap();
+                let symbol = name.clone();
+                ctx.emit(Inst::ElfTlsGetAddr { symbol });
+                ctx.emit(Inst::gen_move(rd, xreg(0), I64));
+            }
+            tls_model => unimplemented!("tls_value for tls model {:?}", tls_model),
+        },
     }
 
     Ok(())
 }
 
+/// Load the address of the symbol `name`, plus `offset`, into `rd`.
+///
+/// In position-independent code, the addresses of symbols that are not colocated with this code
+/// are loaded from the GOT; colocated ones are computed PC-relatively.
+fn lower_load_ext_name<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    flags: &Flags,
+    rd: Writable<Reg>,
+    name: ExternalName,
+    dist: RelocDistance,
+    offset: i64,
+) {
+    let name = Box::new(name);
+    if flags.is_pic() && dist == RelocDistance::Near {
+        ctx.emit(Inst::LoadExtNameNear { rd, name, offset });
+    } else {
+        ctx.emit(Inst::LoadExtName { rd, name, offset });
+    }
+}
+
 pub(crate) fn lower_branch<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
     branches: &[IRInst],
@@ -3165,9 +3519,7 @@ pub(crate) fn lower_branch<C: LowerCtx<I = Inst>>(
                     insn: branches[0],
                     input: 0,
                 };
-                if let Some(icmp_insn) =
-                    maybe_input_insn_via_conv(ctx, flag_input, Opcode::Icmp, Opcode::Bint)
-                {
+                if let Some(icmp_insn) = maybe_input_icmp(ctx, flag_input) {
                     let condcode = ctx.data(icmp_insn).cond_code().unwrap();
                     let cond = lower_condcode(condcode);
                     let is_signed = condcode_is_signed(condcode);
@@ -3195,14 +3547,20 @@ pub(crate) fn lower_branch<C: LowerCtx<I = Inst>>(
                         kind: CondBrKind::Cond(cond),
                     });
                 } else {
-                    let rt = put_input_in_reg(
-                        ctx,
-                        InsnInput {
-                            insn: branches[0],
-                            input: 0,
-                        },
-                        NarrowValueMode::ZeroExtend64,
-                    );
+                    let rt = if ctx.input_ty(branches[0], 0) == I128 {
+                        // The value is zero iff the bitwise or of its parts is.
+                        let src = put_input_in_regs(ctx, flag_input);
+                        let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                        ctx.emit(Inst::AluRRR {
+                            alu_op: ALUOp::Orr64,
+                            rd: tmp,
+                            rn: src.regs()[0],
+                            rm: src.regs()[1],
+                        });
+                        tmp.to_reg()
+                    } else {
+                        put_input_in_reg(ctx, flag_input, NarrowValueMode::ZeroExtend64)
+                    };
                     let kind = match op0 {
                         Opcode::Brz => CondBrKind::Zero(rt),
                         Opcode::Brnz => CondBrKind::NotZero(rt),
@@ -3215,6 +3573,30 @@ pub(crate) fn lower_branch<C: LowerCtx<I = Inst>>(
                     });
                 }
             }
+            Opcode::BrIcmp if ctx.input_ty(branches[0], 0) == I128 => {
+                let condcode = ctx.data(branches[0]).cond_code().unwrap();
+                let lhs = put_input_in_regs(
+                    ctx,
+                    InsnInput {
+                        insn: branches[0],
+                        input: 0,
+                    },
+                );
+                let rhs = put_input_in_regs(
+                    ctx,
+                    InsnInput {
+                        insn: branches[0],
+                        input: 1,
+                    },
+                );
+                let tmp = ctx.alloc_tmp(RegClass::I64, B1);
+                lower_icmp_i128(ctx, condcode, lhs, rhs, tmp);
+                ctx.emit(Inst::CondBr {
+                    taken,
+                    not_taken,
+                    kind: CondBrKind::NotZero(tmp.to_reg()),
+                });
+            }
             Opcode::BrIcmp => {
                 let condcode = ctx.data(branches[0]).cond_code().unwrap();
                 let cond = lower_condcode(condcode);
@@ -17,6 +17,8 @@ mod abi;
 pub(crate) mod inst;
 mod lower;
 mod lower_inst;
+#[cfg(feature = "enable-peepmatic")]
+mod peepmatic;
 
 use inst::create_reg_universe;
 
@@ -64,6 +66,7 @@ impl MachBackend for AArch64Backend {
 
         let buffer = vcode.emit();
         let frame_size = vcode.frame_size();
+        let stats = vcode.stats();
         let unwind_info = vcode.unwind_info()?;
 
         let disasm = if want_disasm {
@@ -79,6 +82,7 @@ impl MachBackend for AArch64Backend {
             frame_size,
             disasm,
             unwind_info,
+            stats,
         })
     }
 
@@ -0,0 +1,516 @@
+//! Peephole optimizations on AArch64 vcode, written in `vcode.peepmatic`.
+
+use crate::ir::types::{I32, I64};
+use crate::isa::aarch64::inst::*;
+use crate::machinst::peepmatic::MachInstPeepmatic;
+use crate::peepmatic::deserialize_once;
+use alloc::vec::Vec;
+use peepmatic_runtime::{
+    part::{Constant, Part},
+    r#type::BitWidth,
+    PeepholeOptimizations,
+};
+use regalloc::{Reg, Writable};
+use std::borrow::Cow;
+use std::ptr;
+use std::sync::atomic::AtomicPtr;
+
+peepmatic_traits::define_operator! {
+    /// The AArch64 instructions that `vcode.peepmatic` rules can match and
+    /// build.
+    ///
+    /// Operands and results are whole 64-bit registers. The 32-bit operators
+    /// zero the upper 32 bits of their result, like any write to a `W`
+    /// register.
+    VCodeOpcode {
+        add32 => Add32 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        add32_imm => Add32Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        add64 => Add64 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        add64_imm => Add64Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        add64_lsl => Add64Lsl {
+            immediates(iNN);
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        and32 => And32 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        and64 => And64 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        eor32 => Eor32 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        eor64 => Eor64 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        load32_rr => Load32RR {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        load32_scaled => Load32Scaled {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        load64_rr => Load64RR {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        load64_scaled => Load64Scaled {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        lsl32_imm => Lsl32Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        lsl64_imm => Lsl64Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        lsr32_imm => Lsr32Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        lsr64_imm => Lsr64Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        mov => Mov {
+            parameters(iNN);
+            result(iNN);
+        }
+        mov32 => Mov32 {
+            parameters(iNN);
+            result(iNN);
+        }
+        orr32 => Orr32 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        orr64 => Orr64 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        store32_rr => Store32RR {
+            parameters(iNN, iNN, iNN);
+            result(void);
+        }
+        store32_scaled => Store32Scaled {
+            parameters(iNN, iNN, iNN);
+            result(void);
+        }
+        store64_rr => Store64RR {
+            parameters(iNN, iNN, iNN);
+            result(void);
+        }
+        store64_scaled => Store64Scaled {
+            parameters(iNN, iNN, iNN);
+            result(void);
+        }
+        sub32 => Sub32 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        sub32_imm => Sub32Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        sub64 => Sub64 {
+            parameters(iNN, iNN);
+            result(iNN);
+        }
+        sub64_imm => Sub64Imm {
+            immediates(iNN);
+            parameters(iNN);
+            result(iNN);
+        }
+        uextend8 => Uextend8 {
+            parameters(iNN);
+            result(iNN);
+        }
+        uextend16 => Uextend16 {
+            parameters(iNN);
+            result(iNN);
+        }
+        uextend32 => Uextend32 {
+            parameters(iNN);
+            result(iNN);
+        }
+    }
+    parse_cfg(feature = "rebuild-peephole-optimizers");
+}
+
+/// Code required to rebuild the `vcode.peepmatic` peephole optimizer.
+#[cfg(feature = "rebuild-peephole-optimizers")]
+mod rebuild {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    /// Rebuild the `vcode.peepmatic` peephole optimizer.
+    ///
+    /// Saves and overwrites the old `vcode.serialized` build and returns a
+    /// copy of the result.
+    pub fn rebuild_vcode() -> Vec<u8> {
+        let codegen_path = Path::new(include_str!(concat!(
+            env!("OUT_DIR"),
+            "/CRANELIFT_CODEGEN_PATH"
+        )));
+        let isa_path = codegen_path.join("src").join("isa").join("aarch64");
+        let source_path = isa_path.join("vcode.peepmatic");
+
+        let vcode = peepmatic::compile_file::<VCodeOpcode>(&source_path)
+            .expect("failed to compile `src/isa/aarch64/vcode.peepmatic`");
+
+        let serialized_path = isa_path.join("vcode.serialized");
+        vcode
+            .serialize_to_file(&serialized_path)
+            .expect("failed to serialize peephole optimizer to `src/isa/aarch64/vcode.serialized`");
+        fs::read(&serialized_path).expect("failed to read `src/isa/aarch64/vcode.serialized`")
+    }
+}
+
+/// Get the `vcode.peepmatic` peephole optimizations.
+pub(crate) fn vcode_peepmatic() -> &'static PeepholeOptimizations<VCodeOpcode> {
+    #[cfg(feature = "rebuild-peephole-optimizers")]
+    fn get_serialized() -> Cow<'static, [u8]> {
+        rebuild::rebuild_vcode().into()
+    }
+
+    #[cfg(not(feature = "rebuild-peephole-optimizers"))]
+    fn get_serialized() -> Cow<'static, [u8]> {
+        static SERIALIZED: &[u8] = include_bytes!("vcode.serialized");
+        SERIALIZED.into()
+    }
+
+    static DESERIALIZED: AtomicPtr<PeepholeOptimizations<VCodeOpcode>> =
+        AtomicPtr::new(ptr::null_mut());
+    deserialize_once(&DESERIALIZED, get_serialized)
+}
+
+fn imm(value: u64) -> Part<Reg> {
+    Part::Constant(Constant::Int(value, BitWidth::Polymorphic))
+}
+
+fn reg(reg: Reg) -> Part<Reg> {
+    Part::Instruction(reg)
+}
+
+fn unwrap_imm(part: Part<Reg>) -> u64 {
+    match part {
+        Part::Constant(Constant::Int(value, _)) => value,
+        _ => panic!("expected an integer immediate, found {:?}", part),
+    }
+}
+
+fn unwrap_reg(part: Part<Reg>) -> Reg {
+    match part {
+        Part::Instruction(reg) => reg,
+        _ => panic!("expected a register, found {:?}", part),
+    }
+}
+
+impl MachInstPeepmatic for Inst {
+    type Operator = VCodeOpcode;
+
+    fn peepmatic_operator(
+        &self,
+        operands: &mut Vec<Part<Reg>>,
+    ) -> Option<(VCodeOpcode, Option<Writable<Reg>>)> {
+        let (operator, rd) = match *self {
+            Inst::AluRRR { alu_op, rd, rn, rm } => {
+                let operator = match alu_op {
+                    ALUOp::Add32 => VCodeOpcode::Add32,
+                    ALUOp::Add64 => VCodeOpcode::Add64,
+                    ALUOp::Sub32 => VCodeOpcode::Sub32,
+                    ALUOp::Sub64 => VCodeOpcode::Sub64,
+                    ALUOp::And32 => VCodeOpcode::And32,
+                    ALUOp::And64 => VCodeOpcode::And64,
+                    ALUOp::Orr32 => VCodeOpcode::Orr32,
+                    ALUOp::Orr64 => VCodeOpcode::Orr64,
+                    ALUOp::Eor32 => VCodeOpcode::Eor32,
+                    ALUOp::Eor64 => VCodeOpcode::Eor64,
+                    _ => return None,
+                };
+                operands.extend_from_slice(&[reg(rn), reg(rm)]);
+                (operator, Some(rd))
+            }
+            Inst::AluRRImm12 {
+                alu_op,
+                rd,
+                rn,
+                ref imm12,
+            } => {
+                let operator = match alu_op {
+                    ALUOp::Add32 => VCodeOpcode::Add32Imm,
+                    ALUOp::Add64 => VCodeOpcode::Add64Imm,
+                    ALUOp::Sub32 => VCodeOpcode::Sub32Imm,
+                    ALUOp::Sub64 => VCodeOpcode::Sub64Imm,
+                    _ => return None,
+                };
+                let value = u64::from(imm12.bits) << if imm12.shift12 { 12 } else { 0 };
+                operands.extend_from_slice(&[imm(value), reg(rn)]);
+                (operator, Some(rd))
+            }
+            Inst::AluRRImmShift {
+                alu_op,
+                rd,
+                rn,
+                ref immshift,
+            } => {
+                let operator = match alu_op {
+                    ALUOp::Lsl32 => VCodeOpcode::Lsl32Imm,
+                    ALUOp::Lsl64 => VCodeOpcode::Lsl64Imm,
+                    ALUOp::Lsr32 => VCodeOpcode::Lsr32Imm,
+                    ALUOp::Lsr64 => VCodeOpcode::Lsr64Imm,
+                    _ => return None,
+                };
+                operands.extend_from_slice(&[imm(immshift.value().into()), reg(rn)]);
+                (operator, Some(rd))
+            }
+            Inst::AluRRRShift {
+                alu_op: ALUOp::Add64,
+                rd,
+                rn,
+                rm,
+                ref shiftop,
+            } if matches!(shiftop.op(), ShiftOp::LSL) => {
+                operands.extend_from_slice(&[imm(shiftop.amt().value().into()), reg(rn), reg(rm)]);
+                (VCodeOpcode::Add64Lsl, Some(rd))
+            }
+            Inst::Mov64 { rd, rm } => {
+                operands.push(reg(rm));
+                (VCodeOpcode::Mov, Some(rd))
+            }
+            Inst::Mov32 { rd, rm } => {
+                operands.push(reg(rm));
+                (VCodeOpcode::Mov32, Some(rd))
+            }
+            Inst::Extend {
+                rd,
+                rn,
+                signed: false,
+                from_bits,
+                to_bits: _,
+            } => {
+                let operator = match from_bits {
+                    8 => VCodeOpcode::Uextend8,
+                    16 => VCodeOpcode::Uextend16,
+                    32 => VCodeOpcode::Uextend32,
+                    _ => return None,
+                };
+                operands.push(reg(rn));
+                (operator, Some(rd))
+            }
+            Inst::ULoad32 { rd, ref mem } | Inst::ULoad64 { rd, ref mem } => {
+                let is_64 = match self {
+                    Inst::ULoad64 { .. } => true,
+                    _ => false,
+                };
+                let operator = match (mem, is_64) {
+                    (&AMode::RegReg(..), false) => VCodeOpcode::Load32RR,
+                    (&AMode::RegReg(..), true) => VCodeOpcode::Load64RR,
+                    (&AMode::RegScaled(_, _, I32), false) => VCodeOpcode::Load32Scaled,
+                    (&AMode::RegScaled(_, _, I64), true) => VCodeOpcode::Load64Scaled,
+                    _ => return None,
+                };
+                match *mem {
+                    AMode::RegReg(rn, rm) | AMode::RegScaled(rn, rm, _) => {
+                        operands.extend_from_slice(&[reg(rn), reg(rm)])
+                    }
+                    _ => unreachable!(),
+                }
+                (operator, Some(rd))
+            }
+            Inst::Store32 { rd, ref mem } | Inst::Store64 { rd, ref mem } => {
+                let is_64 = match self {
+                    Inst::Store64 { .. } => true,
+                    _ => false,
+                };
+                let operator = match (mem, is_64) {
+                    (&AMode::RegReg(..), false) => VCodeOpcode::Store32RR,
+                    (&AMode::RegReg(..), true) => VCodeOpcode::Store64RR,
+                    (&AMode::RegScaled(_, _, I32), false) => VCodeOpcode::Store32Scaled,
+                    (&AMode::RegScaled(_, _, I64), true) => VCodeOpcode::Store64Scaled,
+                    _ => return None,
+                };
+                match *mem {
+                    AMode::RegReg(rn, rm) | AMode::RegScaled(rn, rm, _) => {
+                        operands.extend_from_slice(&[reg(rd), reg(rn), reg(rm)])
+                    }
+                    _ => unreachable!(),
+                }
+                (operator, None)
+            }
+            _ => return None,
+        };
+        Some((operator, rd))
+    }
+
+    fn peepmatic_is_pure(operator: VCodeOpcode) -> bool {
+        match operator {
+            VCodeOpcode::Load32RR
+            | VCodeOpcode::Load32Scaled
+            | VCodeOpcode::Load64RR
+            | VCodeOpcode::Load64Scaled
+            | VCodeOpcode::Store32RR
+            | VCodeOpcode::Store32Scaled
+            | VCodeOpcode::Store64RR
+            | VCodeOpcode::Store64Scaled => false,
+            _ => true,
+        }
+    }
+
+    fn peepmatic_make_inst(
+        _root: &Inst,
+        operator: VCodeOpcode,
+        rd: Option<Writable<Reg>>,
+        operands: &[Part<Reg>],
+    ) -> Inst {
+        log::trace!("peepmatic_make_inst: {:?}{:?}", operator, operands);
+
+        let alu_rrr = |alu_op| Inst::AluRRR {
+            alu_op,
+            rd: rd.unwrap(),
+            rn: unwrap_reg(operands[0]),
+            rm: unwrap_reg(operands[1]),
+        };
+        let alu_rr_imm12 = |alu_op| Inst::AluRRImm12 {
+            alu_op,
+            rd: rd.unwrap(),
+            rn: unwrap_reg(operands[1]),
+            imm12: Imm12::maybe_from_u64(unwrap_imm(operands[0])).unwrap(),
+        };
+        let alu_rr_imm_shift = |alu_op| Inst::AluRRImmShift {
+            alu_op,
+            rd: rd.unwrap(),
+            rn: unwrap_reg(operands[1]),
+            immshift: ImmShift::maybe_from_u64(unwrap_imm(operands[0])).unwrap(),
+        };
+        let extend = |from_bits| Inst::Extend {
+            rd: rd.unwrap(),
+            rn: unwrap_reg(operands[0]),
+            signed: false,
+            from_bits,
+            to_bits: if from_bits == 32 { 64 } else { 32 },
+        };
+        let reg_reg = |first: usize| {
+            AMode::RegReg(unwrap_reg(operands[first]), unwrap_reg(operands[first + 1]))
+        };
+        let reg_scaled = |first: usize, ty| {
+            AMode::RegScaled(
+                unwrap_reg(operands[first]),
+                unwrap_reg(operands[first + 1]),
+                ty,
+            )
+        };
+
+        match operator {
+            VCodeOpcode::Add32 => alu_rrr(ALUOp::Add32),
+            VCodeOpcode::Add64 => alu_rrr(ALUOp::Add64),
+            VCodeOpcode::Sub32 => alu_rrr(ALUOp::Sub32),
+            VCodeOpcode::Sub64 => alu_rrr(ALUOp::Sub64),
+            VCodeOpcode::And32 => alu_rrr(ALUOp::And32),
+            VCodeOpcode::And64 => alu_rrr(ALUOp::And64),
+            VCodeOpcode::Orr32 => alu_rrr(ALUOp::Orr32),
+            VCodeOpcode::Orr64 => alu_rrr(ALUOp::Orr64),
+            VCodeOpcode::Eor32 => alu_rrr(ALUOp::Eor32),
+            VCodeOpcode::Eor64 => alu_rrr(ALUOp::Eor64),
+            VCodeOpcode::Add32Imm => alu_rr_imm12(ALUOp::Add32),
+            VCodeOpcode::Add64Imm => alu_rr_imm12(ALUOp::Add64),
+            VCodeOpcode::Sub32Imm => alu_rr_imm12(ALUOp::Sub32),
+            VCodeOpcode::Sub64Imm => alu_rr_imm12(ALUOp::Sub64),
+            VCodeOpcode::Lsl32Imm => alu_rr_imm_shift(ALUOp::Lsl32),
+            VCodeOpcode::Lsl64Imm => alu_rr_imm_shift(ALUOp::Lsl64),
+            VCodeOpcode::Lsr32Imm => alu_rr_imm_shift(ALUOp::Lsr32),
+            VCodeOpcode::Lsr64Imm => alu_rr_imm_shift(ALUOp::Lsr64),
+            VCodeOpcode::Add64Lsl => {
+                let amt = ShiftOpShiftImm::maybe_from_shift(unwrap_imm(operands[0])).unwrap();
+                Inst::AluRRRShift {
+                    alu_op: ALUOp::Add64,
+                    rd: rd.unwrap(),
+                    rn: unwrap_reg(operands[1]),
+                    rm: unwrap_reg(operands[2]),
+                    shiftop: ShiftOpAndAmt::new(ShiftOp::LSL, amt),
+                }
+            }
+            VCodeOpcode::Mov => Inst::Mov64 {
+                rd: rd.unwrap(),
+                rm: unwrap_reg(operands[0]),
+            },
+            VCodeOpcode::Mov32 => Inst::Mov32 {
+                rd: rd.unwrap(),
+                rm: unwrap_reg(operands[0]),
+            },
+            VCodeOpcode::Uextend8 => extend(8),
+            VCodeOpcode::Uextend16 => extend(16),
+            VCodeOpcode::Uextend32 => extend(32),
+            VCodeOpcode::Load32RR => Inst::ULoad32 {
+                rd: rd.unwrap(),
+                mem: reg_reg(0),
+            },
+            VCodeOpcode::Load32Scaled => Inst::ULoad32 {
+                rd: rd.unwrap(),
+                mem: reg_scaled(0, I32),
+            },
+            VCodeOpcode::Load64RR => Inst::ULoad64 {
+                rd: rd.unwrap(),
+                mem: reg_reg(0),
+            },
+            VCodeOpcode::Load64Scaled => Inst::ULoad64 {
+                rd: rd.unwrap(),
+                mem: reg_scaled(0, I64),
+            },
+            VCodeOpcode::Store32RR => Inst::Store32 {
+                rd: unwrap_reg(operands[0]),
+                mem: reg_reg(1),
+            },
+            VCodeOpcode::Store32Scaled => Inst::Store32 {
+                rd: unwrap_reg(operands[0]),
+                mem: reg_scaled(1, I32),
+            },
+            VCodeOpcode::Store64RR => Inst::Store64 {
+                rd: unwrap_reg(operands[0]),
+                mem: reg_reg(1),
+            },
+            VCodeOpcode::Store64Scaled => Inst::Store64 {
+                rd: unwrap_reg(operands[0]),
+                mem: reg_scaled(1, I64),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_peepmatic_vcode() {
+        let _ = vcode_peepmatic();
+    }
+}
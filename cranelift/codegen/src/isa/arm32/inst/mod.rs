@@ -839,10 +839,16 @@ impl MachInst for Inst {
         None
     }
 
-    fn rc_for_type(ty: Type) -> CodegenResult<RegClass> {
+    fn rc_for_type(ty: Type) -> CodegenResult<(&'static [RegClass], &'static [Type])> {
         match ty {
-            I8 | I16 | I32 | B1 | B8 | B16 | B32 => Ok(RegClass::I32),
-            IFLAGS => Ok(RegClass::I32),
+            I8 => Ok((&[RegClass::I32], &[I8])),
+            I16 => Ok((&[RegClass::I32], &[I16])),
+            I32 => Ok((&[RegClass::I32], &[I32])),
+            B1 => Ok((&[RegClass::I32], &[B1])),
+            B8 => Ok((&[RegClass::I32], &[B8])),
+            B16 => Ok((&[RegClass::I32], &[B16])),
+            B32 => Ok((&[RegClass::I32], &[B32])),
+            IFLAGS => Ok((&[RegClass::I32], &[I32])),
             _ => Err(CodegenError::Unsupported(format!(
                 "Unexpected SSA-value type: {}",
                 ty
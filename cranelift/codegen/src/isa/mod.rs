@@ -90,6 +90,9 @@ mod arm32;
 #[cfg(feature = "arm64")]
 pub(crate) mod aarch64;
 
+#[cfg(feature = "riscv64")]
+mod riscv64;
+
 pub mod unwind;
 
 mod call_conv;
@@ -121,8 +124,13 @@ macro_rules! isa_builder {
 /// Return a builder that can create a corresponding `TargetIsa`.
 pub fn lookup(triple: Triple) -> Result<Builder, LookupError> {
     match triple.architecture {
-        Architecture::Riscv32 { .. } | Architecture::Riscv64 { .. } => {
-            isa_builder!(riscv, (feature = "riscv"), triple)
+        Architecture::Riscv32 { .. } => isa_builder!(riscv, (feature = "riscv"), triple),
+        Architecture::Riscv64 { .. } => {
+            if cfg!(feature = "riscv64") {
+                isa_builder!(riscv64, (feature = "riscv64"), triple)
+            } else {
+                isa_builder!(riscv, (feature = "riscv"), triple)
+            }
         }
         Architecture::X86_32 { .. } | Architecture::X86_64 => {
             if cfg!(feature = "x64") {
@@ -154,6 +154,8 @@ mod tests {
         }
     }
 
+    // With the new backend enabled, looking up riscv64 returns it instead of this one.
+    #[cfg(not(feature = "riscv64"))]
     #[test]
     fn test_64bitenc() {
         let shared_builder = settings::builder();
@@ -0,0 +1,593 @@
+//! Implementation of the standard RISC-V 64-bit (LP64D) ABI.
+
+use crate::ir;
+use crate::ir::types::*;
+use crate::isa;
+use crate::isa::riscv64::{inst::EmitState, inst::*};
+use crate::machinst::*;
+use crate::settings;
+use crate::{CodegenError, CodegenResult};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use regalloc::{RealReg, Reg, RegClass, Set, Writable};
+use smallvec::SmallVec;
+
+// We use the generic implementation that factors out the ABI commonalities of the machinst
+// backends.
+
+/// Support for the RISC-V 64 ABI from the callee side (within a function body).
+pub(crate) type Riscv64ABICallee = ABICalleeImpl<Riscv64MachineDeps>;
+
+/// Support for the RISC-V 64 ABI from the caller side (at a callsite).
+pub(crate) type Riscv64ABICaller = ABICallerImpl<Riscv64MachineDeps>;
+
+/// This is the limit for the size of argument and return-value areas on the
+/// stack. We place a reasonable limit here to avoid integer overflow issues
+/// with 32-bit arithmetic: for now, 128 MB.
+static STACK_ARG_RET_SIZE_LIMIT: u64 = 128 * 1024 * 1024;
+
+/// The first argument register of each class: a0 (x10) and fa0 (f10).
+const FIRST_ARG_REG: u8 = 10;
+
+impl Into<AMode> for StackAMode {
+    fn into(self) -> AMode {
+        match self {
+            StackAMode::FPOffset(off, ty) => AMode::FPOffset(off, ty),
+            StackAMode::NominalSPOffset(off, ty) => AMode::NominalSPOffset(off, ty),
+            StackAMode::SPOffset(off, ty) => AMode::SPOffset(off, ty),
+        }
+    }
+}
+
+// Returns the size of stack space needed to store the
+// `int_reg` and `float_reg`.
+fn saved_reg_stack_size(int_reg: &[Writable<RealReg>], float_reg: &[Writable<RealReg>]) -> usize {
+    // Every register takes 8 bytes; round the total up to keep 16-byte stack alignment.
+    let num_regs = int_reg.len() + float_reg.len();
+    (num_regs + (num_regs & 1)) * 8
+}
+
+/// RISC-V 64-specific ABI behavior. This struct just serves as an implementation
+/// point for the trait; it is never actually instantiated.
+pub(crate) struct Riscv64MachineDeps;
+
+impl ABIMachineSpec for Riscv64MachineDeps {
+    type I = Inst;
+
+    fn word_bits() -> u32 {
+        64
+    }
+
+    /// Return required stack alignment in bytes.
+    fn stack_align(_call_conv: isa::CallConv) -> u32 {
+        16
+    }
+
+    fn compute_arg_locs(
+        call_conv: isa::CallConv,
+        params: &[ir::AbiParam],
+        args_or_rets: ArgsOrRets,
+        add_ret_area_ptr: bool,
+    ) -> CodegenResult<(Vec<ABIArg>, i64, Option<usize>)> {
+        if call_conv.extends_baldrdash() {
+            return Err(CodegenError::Unsupported(format!(
+                "The Baldrdash calling conventions are not supported on RISC-V 64"
+            )));
+        }
+
+        // See the RISC-V ELF psABI, "Integer Calling Convention" and "Hardware Floating-point
+        // Calling Convention": arguments go in a0-a7 and fa0-fa7, return values in a0-a1 and
+        // fa0-fa1.
+        //
+        // N.B.: unlike the psABI, floating-point arguments that don't fit in the FP argument
+        // registers are passed on the stack rather than in the remaining integer registers.
+        let mut next_xreg = 0;
+        let mut next_freg = 0;
+        let mut next_stack: u64 = 0;
+        let mut ret = vec![];
+
+        let max_per_class_reg_vals = match args_or_rets {
+            ArgsOrRets::Args => 8,
+            ArgsOrRets::Rets => 2,
+        };
+
+        for param in params {
+            // Validate "purpose".
+            match &param.purpose {
+                &ir::ArgumentPurpose::VMContext
+                | &ir::ArgumentPurpose::Normal
+                | &ir::ArgumentPurpose::StackLimit
+                | &ir::ArgumentPurpose::SignatureId => {}
+                _ => panic!(
+                    "Unsupported argument purpose {:?} in signature: {:?}",
+                    param.purpose, params
+                ),
+            }
+
+            assert!(
+                legal_type_for_machine(param.value_type),
+                "Invalid type for RISC-V 64: {:?}",
+                param.value_type
+            );
+            let (rcs, _) = Inst::rc_for_type(param.value_type)?;
+            let rc = rcs[0];
+
+            let next_reg = match rc {
+                RegClass::I64 => &mut next_xreg,
+                RegClass::F64 => &mut next_freg,
+                _ => panic!("Invalid register class: {:?}", rc),
+            };
+
+            if *next_reg < max_per_class_reg_vals {
+                let reg = match rc {
+                    RegClass::I64 => xreg(FIRST_ARG_REG + *next_reg),
+                    RegClass::F64 => freg(FIRST_ARG_REG + *next_reg),
+                    _ => unreachable!(),
+                };
+                ret.push(ABIArg::Reg(
+                    ValueRegs::one(reg.to_real_reg()),
+                    param.value_type,
+                    param.extension,
+                    param.purpose,
+                ));
+                *next_reg += 1;
+            } else {
+                // Every arg takes a slot of 8 bytes. (16-byte stack alignment happens separately
+                // after all args.)
+                ret.push(ABIArg::Stack(
+                    next_stack as i64,
+                    param.value_type,
+                    param.extension,
+                    param.purpose,
+                ));
+                next_stack += 8;
+            }
+        }
+
+        let extra_arg = if add_ret_area_ptr {
+            debug_assert!(args_or_rets == ArgsOrRets::Args);
+            if next_xreg < max_per_class_reg_vals {
+                ret.push(ABIArg::Reg(
+                    ValueRegs::one(xreg(FIRST_ARG_REG + next_xreg).to_real_reg()),
+                    I64,
+                    ir::ArgumentExtension::None,
+                    ir::ArgumentPurpose::Normal,
+                ));
+            } else {
+                ret.push(ABIArg::Stack(
+                    next_stack as i64,
+                    I64,
+                    ir::ArgumentExtension::None,
+                    ir::ArgumentPurpose::Normal,
+                ));
+                next_stack += 8;
+            }
+            Some(ret.len() - 1)
+        } else {
+            None
+        };
+
+        next_stack = (next_stack + 15) & !15;
+
+        // To avoid overflow issues, limit the arg/return size to something
+        // reasonable -- here, 128 MB.
+        if next_stack > STACK_ARG_RET_SIZE_LIMIT {
+            return Err(CodegenError::ImplLimitExceeded);
+        }
+
+        Ok((ret, next_stack as i64, extra_arg))
+    }
+
+    fn fp_to_arg_offset(_call_conv: isa::CallConv, _flags: &settings::Flags) -> i64 {
+        16 // frame pointer + return address.
+    }
+
+    fn gen_load_stack(mem: StackAMode, into_reg: Writable<Reg>, ty: Type) -> Inst {
+        Inst::gen_load(into_reg, mem.into(), ty)
+    }
+
+    fn gen_store_stack(mem: StackAMode, from_reg: Reg, ty: Type) -> Inst {
+        Inst::gen_store(mem.into(), from_reg, ty)
+    }
+
+    fn gen_move(to_reg: Writable<Reg>, from_reg: Reg, ty: Type) -> Inst {
+        Inst::gen_move(to_reg, from_reg, ty)
+    }
+
+    fn gen_extend(
+        to_reg: Writable<Reg>,
+        from_reg: Reg,
+        signed: bool,
+        from_bits: u8,
+        to_bits: u8,
+    ) -> Inst {
+        assert!(from_bits < to_bits);
+        Inst::Extend {
+            rd: to_reg,
+            rn: from_reg,
+            signed,
+            from_bits,
+            to_bits,
+        }
+    }
+
+    fn gen_ret() -> Inst {
+        Inst::Ret
+    }
+
+    fn gen_add_imm(into_reg: Writable<Reg>, from_reg: Reg, imm: u32) -> SmallVec<[Inst; 4]> {
+        let mut insts = SmallVec::new();
+        if let Some(imm12) = Imm12::maybe_from_i64(imm as i64) {
+            insts.push(Inst::AluRRImm12 {
+                alu_op: AluOPRRI::Addi,
+                rd: into_reg,
+                rs: from_reg,
+                imm12,
+            });
+        } else {
+            let scratch2 = writable_tmp2_reg();
+            assert_ne!(scratch2.to_reg(), from_reg);
+            insts.extend(Inst::load_constant(scratch2, imm.into()));
+            insts.push(Inst::AluRRR {
+                alu_op: AluOPRRR::Add,
+                rd: into_reg,
+                rs1: from_reg,
+                rs2: scratch2.to_reg(),
+            });
+        }
+        insts
+    }
+
+    fn gen_stack_lower_bound_trap(limit_reg: Reg) -> SmallVec<[Inst; 2]> {
+        let mut insts = SmallVec::new();
+        insts.push(Inst::TrapIf {
+            cond: IntegerCompare {
+                kind: ir::condcodes::IntCC::UnsignedLessThan,
+                rs1: stack_reg(),
+                rs2: limit_reg,
+            },
+            trap_code: ir::TrapCode::StackOverflow,
+        });
+        insts
+    }
+
+    fn gen_epilogue_placeholder() -> Inst {
+        Inst::EpiloguePlaceholder
+    }
+
+    fn gen_get_stack_addr(mem: StackAMode, into_reg: Writable<Reg>, _ty: Type) -> Inst {
+        let mem = mem.into();
+        Inst::LoadAddr { rd: into_reg, mem }
+    }
+
+    fn get_stacklimit_reg() -> Reg {
+        spilltmp_reg()
+    }
+
+    fn gen_load_base_offset(into_reg: Writable<Reg>, base: Reg, offset: i32, ty: Type) -> Inst {
+        let mem = AMode::RegOffset(base, offset as i64, ty);
+        Inst::gen_load(into_reg, mem, ty)
+    }
+
+    fn gen_store_base_offset(base: Reg, offset: i32, from_reg: Reg, ty: Type) -> Inst {
+        let mem = AMode::RegOffset(base, offset as i64, ty);
+        Inst::gen_store(mem, from_reg, ty)
+    }
+
+    fn gen_sp_reg_adjust(amount: i32) -> SmallVec<[Inst; 2]> {
+        if amount == 0 {
+            return SmallVec::new();
+        }
+
+        let mut ret = SmallVec::new();
+        if let Some(imm12) = Imm12::maybe_from_i64(amount as i64) {
+            ret.push(Inst::AluRRImm12 {
+                alu_op: AluOPRRI::Addi,
+                rd: writable_stack_reg(),
+                rs: stack_reg(),
+                imm12,
+            });
+        } else {
+            let (amount, alu_op) = if amount > 0 {
+                (amount as u64, AluOPRRR::Add)
+            } else {
+                (-(amount as i64) as u64, AluOPRRR::Sub)
+            };
+            let tmp = writable_spilltmp_reg();
+            ret.extend(Inst::load_constant(tmp, amount));
+            ret.push(Inst::AluRRR {
+                alu_op,
+                rd: writable_stack_reg(),
+                rs1: stack_reg(),
+                rs2: tmp.to_reg(),
+            });
+        }
+        ret
+    }
+
+    fn gen_nominal_sp_adj(offset: i32) -> Inst {
+        Inst::VirtualSPOffsetAdj {
+            offset: offset as i64,
+        }
+    }
+
+    fn gen_prologue_frame_setup() -> SmallVec<[Inst; 2]> {
+        let mut insts = SmallVec::new();
+        // addi sp, sp, -16
+        insts.extend(Self::gen_sp_reg_adjust(-16));
+        // sd ra, 8(sp)
+        insts.push(Inst::gen_store(AMode::SPOffset(8, I64), link_reg(), I64));
+        // sd fp, 0(sp)
+        insts.push(Inst::gen_store(AMode::SPOffset(0, I64), fp_reg(), I64));
+        // mv fp, sp
+        insts.push(Inst::gen_move(writable_fp_reg(), stack_reg(), I64));
+        insts
+    }
+
+    fn gen_epilogue_frame_restore() -> SmallVec<[Inst; 2]> {
+        let mut insts = SmallVec::new();
+        // mv sp, fp
+        insts.push(Inst::gen_move(writable_stack_reg(), fp_reg(), I64));
+        // ld ra, 8(sp)
+        insts.push(Inst::gen_load(
+            writable_link_reg(),
+            AMode::SPOffset(8, I64),
+            I64,
+        ));
+        // ld fp, 0(sp)
+        insts.push(Inst::gen_load(
+            writable_fp_reg(),
+            AMode::SPOffset(0, I64),
+            I64,
+        ));
+        // addi sp, sp, 16
+        insts.extend(Self::gen_sp_reg_adjust(16));
+        insts
+    }
+
+    // Returns stack bytes used as well as instructions. Does not adjust
+    // nominal SP offset; abi_impl generic code will do that.
+    fn gen_clobber_save(
+        call_conv: isa::CallConv,
+        _: &settings::Flags,
+        clobbers: &Set<Writable<RealReg>>,
+        fixed_frame_storage_size: u32,
+        _outgoing_args_size: u32,
+    ) -> (u64, SmallVec<[Inst; 16]>) {
+        let mut insts = SmallVec::new();
+        let (clobbered_int, clobbered_float) = get_regs_saved_in_prologue(call_conv, clobbers);
+
+        let total_save_bytes = saved_reg_stack_size(&clobbered_int, &clobbered_float) as i32;
+        insts.extend(Self::gen_sp_reg_adjust(
+            -(total_save_bytes + fixed_frame_storage_size as i32),
+        ));
+
+        // sd / fsd r, (i * 8)(sp)
+        for (i, reg) in clobbered_int
+            .iter()
+            .chain(clobbered_float.iter())
+            .enumerate()
+        {
+            let reg = reg.to_reg().to_reg();
+            let ty = match reg.get_class() {
+                RegClass::I64 => I64,
+                _ => F64,
+            };
+            insts.push(Inst::gen_store(
+                AMode::SPOffset((i * 8) as i64, ty),
+                reg,
+                ty,
+            ));
+        }
+
+        (total_save_bytes as u64, insts)
+    }
+
+    fn gen_clobber_restore(
+        call_conv: isa::CallConv,
+        _: &settings::Flags,
+        clobbers: &Set<Writable<RealReg>>,
+        _fixed_frame_storage_size: u32,
+        _outgoing_args_size: u32,
+    ) -> SmallVec<[Inst; 16]> {
+        let mut insts = SmallVec::new();
+        let (clobbered_int, clobbered_float) = get_regs_saved_in_prologue(call_conv, clobbers);
+
+        // ld / fld r, (i * 8)(sp)
+        for (i, reg) in clobbered_int
+            .iter()
+            .chain(clobbered_float.iter())
+            .enumerate()
+        {
+            let reg = reg.map(|r| r.to_reg());
+            let ty = match reg.to_reg().get_class() {
+                RegClass::I64 => I64,
+                _ => F64,
+            };
+            insts.push(Inst::gen_load(reg, AMode::SPOffset((i * 8) as i64, ty), ty));
+        }
+
+        // The frame pointer will be moved into the stack pointer in the epilogue, so we can skip
+        // restoring the stack pointer value here.
+
+        insts
+    }
+
+    fn gen_call(
+        dest: &CallDest,
+        uses: Vec<Reg>,
+        defs: Vec<Writable<Reg>>,
+        opcode: ir::Opcode,
+        tmp: Writable<Reg>,
+        callee_conv: isa::CallConv,
+        caller_conv: isa::CallConv,
+    ) -> SmallVec<[(InstIsSafepoint, Inst); 2]> {
+        let mut insts = SmallVec::new();
+        match &dest {
+            &CallDest::ExtName(ref name, RelocDistance::Near) => insts.push((
+                InstIsSafepoint::Yes,
+                Inst::Call {
+                    info: Box::new(CallInfo {
+                        dest: name.clone(),
+                        uses,
+                        defs,
+                        opcode,
+                        caller_callconv: caller_conv,
+                        callee_callconv: callee_conv,
+                    }),
+                },
+            )),
+            &CallDest::ExtName(ref name, RelocDistance::Far) => {
+                insts.push((
+                    InstIsSafepoint::No,
+                    Inst::LoadExtName {
+                        rd: tmp,
+                        name: Box::new(name.clone()),
+                        offset: 0,
+                    },
+                ));
+                insts.push((
+                    InstIsSafepoint::Yes,
+                    Inst::CallInd {
+                        info: Box::new(CallIndInfo {
+                            rn: tmp.to_reg(),
+                            uses,
+                            defs,
+                            opcode,
+                            caller_callconv: caller_conv,
+                            callee_callconv: callee_conv,
+                        }),
+                    },
+                ));
+            }
+            &CallDest::Reg(reg) => insts.push((
+                InstIsSafepoint::Yes,
+                Inst::CallInd {
+                    info: Box::new(CallIndInfo {
+                        rn: *reg,
+                        uses,
+                        defs,
+                        opcode,
+                        caller_callconv: caller_conv,
+                        callee_callconv: callee_conv,
+                    }),
+                },
+            )),
+        }
+
+        insts
+    }
+
+    fn get_number_of_spillslots_for_value(rc: RegClass, _ty: Type) -> u32 {
+        // We allocate in terms of 8-byte slots.
+        match rc {
+            RegClass::I64 | RegClass::F64 => 1,
+            _ => panic!("Unexpected register class!"),
+        }
+    }
+
+    /// Get the current virtual-SP offset from an instruction-emission state.
+    fn get_virtual_sp_offset_from_state(s: &EmitState) -> i64 {
+        s.virtual_sp_offset
+    }
+
+    /// Get the nominal-SP-to-FP offset from an instruction-emission state.
+    fn get_nominal_sp_to_fp(s: &EmitState) -> i64 {
+        s.nominal_sp_to_fp
+    }
+
+    fn get_regs_clobbered_by_call(call_conv_of_callee: isa::CallConv) -> Vec<Writable<Reg>> {
+        let mut caller_saved = Vec::new();
+        for i in 0..32 {
+            let x = writable_xreg(i);
+            if is_reg_clobbered_by_call(call_conv_of_callee, x.to_reg().to_real_reg()) {
+                caller_saved.push(x);
+            }
+        }
+        for i in 0..32 {
+            let f = writable_freg(i);
+            if is_reg_clobbered_by_call(call_conv_of_callee, f.to_reg().to_real_reg()) {
+                caller_saved.push(f);
+            }
+        }
+        caller_saved
+    }
+
+    fn get_ext_mode(
+        _call_conv: isa::CallConv,
+        specified: ir::ArgumentExtension,
+    ) -> ir::ArgumentExtension {
+        // The psABI requires narrow integer arguments and return values to be extended to the
+        // full register width.
+        specified
+    }
+}
+
+/// Is this type supposed to be seen on this machine? E.g. references of the
+/// wrong width are invalid.
+fn legal_type_for_machine(ty: Type) -> bool {
+    match ty {
+        R32 => false,
+        _ => true,
+    }
+}
+
+/// Is the given register saved in the prologue if clobbered, i.e., is it a
+/// callee-save?
+fn is_reg_saved_in_prologue(_call_conv: isa::CallConv, r: RealReg) -> bool {
+    let enc = r.get_hw_encoding();
+    match r.get_class() {
+        RegClass::I64 => {
+            // s1 (x9) and s2 - s11 (x18 - x27) are callee-saves. The frame pointer (s0) is saved
+            // separately, by the frame setup sequence.
+            enc == 9 || (enc >= 18 && enc <= 27)
+        }
+        RegClass::F64 => {
+            // fs0 - fs1 (f8 - f9) and fs2 - fs11 (f18 - f27) are callee-saves.
+            enc == 8 || enc == 9 || (enc >= 18 && enc <= 27)
+        }
+        _ => panic!("Unexpected RegClass"),
+    }
+}
+
+/// Return the set of all integer and floating-point registers that must be saved in the
+/// prologue and restored in the epilogue, given the set of all registers
+/// written by the function's body.
+fn get_regs_saved_in_prologue(
+    call_conv: isa::CallConv,
+    regs: &Set<Writable<RealReg>>,
+) -> (Vec<Writable<RealReg>>, Vec<Writable<RealReg>>) {
+    let mut int_saves = vec![];
+    let mut float_saves = vec![];
+    for &reg in regs.iter() {
+        if is_reg_saved_in_prologue(call_conv, reg.to_reg()) {
+            match reg.to_reg().get_class() {
+                RegClass::I64 => int_saves.push(reg),
+                RegClass::F64 => float_saves.push(reg),
+                _ => panic!("Unexpected RegClass"),
+            }
+        }
+    }
+    // Sort registers for deterministic code output. We can do an unstable sort because the
+    // registers will be unique (there are no dups).
+    int_saves.sort_unstable_by_key(|r| r.to_reg().get_index());
+    float_saves.sort_unstable_by_key(|r| r.to_reg().get_index());
+    (int_saves, float_saves)
+}
+
+fn is_reg_clobbered_by_call(_call_conv_of_callee: isa::CallConv, r: RealReg) -> bool {
+    let enc = r.get_hw_encoding();
+    match r.get_class() {
+        RegClass::I64 => {
+            // ra (x1), t0 - t2 (x5 - x7), a0 - a7 (x10 - x17) and t3 - t6 (x28 - x31) are
+            // caller-saves.
+            enc == 1 || (enc >= 5 && enc <= 7) || (enc >= 10 && enc <= 17) || enc >= 28
+        }
+        RegClass::F64 => {
+            // ft0 - ft7 (f0 - f7), fa0 - fa7 (f10 - f17) and ft8 - ft11 (f28 - f31) are
+            // caller-saves.
+            enc <= 7 || (enc >= 10 && enc <= 17) || enc >= 28
+        }
+        _ => panic!("Unexpected RegClass"),
+    }
+}
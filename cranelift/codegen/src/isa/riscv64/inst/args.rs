@@ -0,0 +1,234 @@
+//! RISC-V 64-bit ISA definitions: instruction arguments.
+
+// Some variants are never constructed, but we still want them as options in the future.
+#![allow(dead_code)]
+
+use crate::ir::condcodes::{CondCode, IntCC};
+use crate::ir::Type;
+use crate::machinst::MachLabel;
+
+use regalloc::{PrettyPrint, RealRegUniverse, Reg};
+
+use std::string::{String, ToString};
+
+//=============================================================================
+// Instruction sub-components: immediates
+
+/// A signed 12-bit immediate, as used by I-type and S-type instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Imm12 {
+    bits: i16,
+}
+
+impl Imm12 {
+    /// The largest value representable as an `Imm12`.
+    pub const MAX: i64 = (1 << 11) - 1;
+    /// The smallest value representable as an `Imm12`.
+    pub const MIN: i64 = -(1 << 11);
+
+    /// Compute an `Imm12` from a signed value, if it is in range.
+    pub fn maybe_from_i64(value: i64) -> Option<Imm12> {
+        if value >= Self::MIN && value <= Self::MAX {
+            Some(Imm12 { bits: value as i16 })
+        } else {
+            None
+        }
+    }
+
+    /// Create a zero immediate.
+    pub fn zero() -> Imm12 {
+        Imm12 { bits: 0 }
+    }
+
+    /// The signed value of this immediate.
+    pub fn as_i64(self) -> i64 {
+        self.bits as i64
+    }
+
+    /// The encoded 12 bits of this immediate, in the low bits of the result.
+    pub fn bits(self) -> u32 {
+        (self.bits as u32) & 0xfff
+    }
+}
+
+/// Split a 32-bit signed value into the `hi20` and `lo12` parts used by `lui`/`auipc` and a
+/// following I-type instruction, such that `(hi20 << 12) + sext(lo12) == value` (modulo 2^32).
+pub fn split_hi20_lo12(value: i32) -> (u32, Imm12) {
+    let hi20 = ((value as i64 + 0x800) >> 12) as u32 & 0xfffff;
+    let lo12 = ((value << 20) >> 20) as i64;
+    (hi20, Imm12::maybe_from_i64(lo12).unwrap())
+}
+
+//=============================================================================
+// Instruction sub-components (memory addresses): definitions
+
+/// A memory argument to a load/store.
+///
+/// RISC-V has a single addressing mode, `reg + simm12`. The pseudo-modes below
+/// are lowered to it at emission time (see `mem_finalize`), using the spilltmp
+/// register when the offset does not fit.
+#[derive(Clone, Debug)]
+pub enum AMode {
+    /// Offset from an arbitrary register. The type is the type of the
+    /// accessed value.
+    RegOffset(Reg, i64, Type),
+    /// Offset from the stack pointer.
+    SPOffset(i64, Type),
+    /// Offset from the frame pointer.
+    FPOffset(i64, Type),
+    /// Offset from the "nominal stack pointer", which is where the real SP is
+    /// just after stack and spill slots are allocated in the function prologue.
+    /// At emission time, this is converted to `SPOffset` with a fixup added to
+    /// the offset constant. The fixup is a running value that is tracked as
+    /// emission iterates through instructions in linear order, and can be
+    /// adjusted up and down with [Inst::VirtualSPOffsetAdj].
+    ///
+    /// The standard ABI is in charge of handling this (by emitting the
+    /// adjustment meta-instructions). It maintains the invariant that "nominal
+    /// SP" is where the actual SP is after the function prologue and before
+    /// clobber pushes. See the diagram in the documentation for
+    /// [crate::isa::riscv64::abi](the ABI module) for more details.
+    NominalSPOffset(i64, Type),
+}
+
+impl AMode {
+    /// Memory reference using an address in a register.
+    pub fn reg(reg: Reg) -> AMode {
+        AMode::RegOffset(reg, 0, crate::ir::types::I64)
+    }
+}
+
+//=============================================================================
+// Instruction sub-components (conditions, branches, rounding modes)
+
+/// A comparison of two integer registers, as used by the conditional branch
+/// instructions. RISC-V only has `beq`, `bne`, `blt`, `bge`, `bltu` and
+/// `bgeu`; the remaining conditions are encoded by swapping the operands.
+#[derive(Clone, Copy, Debug)]
+pub struct IntegerCompare {
+    pub kind: IntCC,
+    pub rs1: Reg,
+    pub rs2: Reg,
+}
+
+impl IntegerCompare {
+    /// Invert the condition.
+    pub fn inverse(self) -> IntegerCompare {
+        IntegerCompare {
+            kind: self.kind.inverse(),
+            ..self
+        }
+    }
+
+    /// Returns the branch `funct3` and the (possibly swapped) source registers
+    /// encoding this comparison.
+    pub(crate) fn to_branch(self) -> (u32, Reg, Reg) {
+        let (rs1, rs2) = (self.rs1, self.rs2);
+        match self.kind {
+            IntCC::Equal => (0b000, rs1, rs2),
+            IntCC::NotEqual => (0b001, rs1, rs2),
+            IntCC::SignedLessThan => (0b100, rs1, rs2),
+            IntCC::SignedGreaterThanOrEqual => (0b101, rs1, rs2),
+            IntCC::SignedGreaterThan => (0b100, rs2, rs1),
+            IntCC::SignedLessThanOrEqual => (0b101, rs2, rs1),
+            IntCC::UnsignedLessThan => (0b110, rs1, rs2),
+            IntCC::UnsignedGreaterThanOrEqual => (0b111, rs1, rs2),
+            IntCC::UnsignedGreaterThan => (0b110, rs2, rs1),
+            IntCC::UnsignedLessThanOrEqual => (0b111, rs2, rs1),
+            IntCC::Overflow | IntCC::NotOverflow => {
+                panic!("Overflow conditions cannot be encoded as a RISC-V branch")
+            }
+        }
+    }
+}
+
+/// A reference to a branch target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchTarget {
+    /// An unresolved reference to a Label, as passed into
+    /// `lower_branch_group()`.
+    Label(MachLabel),
+    /// A fixed PC offset.
+    ResolvedOffset(i32),
+}
+
+impl BranchTarget {
+    /// Return the target's label, if it is a label-based target.
+    pub fn as_label(self) -> Option<MachLabel> {
+        match self {
+            BranchTarget::Label(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Return the target's offset, if specified, or zero if label-based.
+    pub fn as_offset_or_zero(self) -> i32 {
+        match self {
+            BranchTarget::ResolvedOffset(off) => off,
+            _ => 0,
+        }
+    }
+}
+
+/// A floating-point rounding mode, as encoded in the `rm` field of the F and D
+/// extension instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FRM {
+    /// Round to nearest, ties to even.
+    RNE = 0b000,
+    /// Round towards zero.
+    RTZ = 0b001,
+    /// Round down (towards negative infinity).
+    RDN = 0b010,
+    /// Round up (towards positive infinity).
+    RUP = 0b011,
+    /// Round to nearest, ties to max magnitude.
+    RMM = 0b100,
+    /// Use the dynamic rounding mode from the `frm` CSR.
+    DYN = 0b111,
+}
+
+impl FRM {
+    /// Get the encoding of this rounding mode.
+    pub fn bits(self) -> u32 {
+        self as u32
+    }
+}
+
+//=============================================================================
+// Instruction sub-components (memory addresses): printing
+
+impl PrettyPrint for AMode {
+    fn show_rru(&self, mb_rru: Option<&RealRegUniverse>) -> String {
+        match self {
+            &AMode::RegOffset(reg, off, _) => format!("{}({})", off, reg.show_rru(mb_rru)),
+            &AMode::SPOffset(off, _) => format!("{}(sp)", off),
+            &AMode::FPOffset(off, _) => format!("{}(fp)", off),
+            &AMode::NominalSPOffset(off, _) => format!("{}(nominal_sp)", off),
+        }
+    }
+}
+
+impl PrettyPrint for BranchTarget {
+    fn show_rru(&self, _mb_rru: Option<&RealRegUniverse>) -> String {
+        match self {
+            &BranchTarget::Label(label) => format!("label{:?}", label.get()),
+            &BranchTarget::ResolvedOffset(off) => format!("{}", off),
+        }
+    }
+}
+
+impl PrettyPrint for FRM {
+    fn show_rru(&self, _mb_rru: Option<&RealRegUniverse>) -> String {
+        match self {
+            FRM::RNE => "rne",
+            FRM::RTZ => "rtz",
+            FRM::RDN => "rdn",
+            FRM::RUP => "rup",
+            FRM::RMM => "rmm",
+            FRM::DYN => "dyn",
+        }
+        .to_string()
+    }
+}
@@ -0,0 +1,1054 @@
+//! RISC-V 64-bit ISA: binary code emission.
+
+use crate::binemit::{Reloc, StackMap};
+use crate::ir::condcodes::IntCC;
+use crate::ir::types::*;
+use crate::ir::{SourceLoc, TrapCode};
+use crate::isa::riscv64::inst::*;
+
+use regalloc::{Reg, RegClass, Writable};
+
+use log::debug;
+
+/// Memory addressing mode finalization: convert "special" modes (e.g.,
+/// generic arbitrary stack offset) into a base register and a 12-bit offset,
+/// possibly by emitting some helper instructions that come immediately before
+/// the use of this amode.
+pub fn mem_finalize(mem: &AMode, state: &EmitState) -> (SmallVec<[Inst; 4]>, Reg, Imm12) {
+    let (basereg, off) = match mem {
+        &AMode::RegOffset(reg, off, _) => (reg, off),
+        &AMode::SPOffset(off, _) => (stack_reg(), off),
+        &AMode::FPOffset(off, _) => (fp_reg(), off),
+        &AMode::NominalSPOffset(off, _) => {
+            debug!(
+                "mem_finalize: nominal SP offset {} + adj {} -> {}",
+                off,
+                state.virtual_sp_offset,
+                off + state.virtual_sp_offset
+            );
+            (stack_reg(), off + state.virtual_sp_offset)
+        }
+    };
+
+    if let Some(imm12) = Imm12::maybe_from_i64(off) {
+        (smallvec![], basereg, imm12)
+    } else {
+        let tmp = writable_spilltmp_reg();
+        let mut const_insts = Inst::load_constant(tmp, off as u64);
+        const_insts.push(Inst::AluRRR {
+            alu_op: AluOPRRR::Add,
+            rd: tmp,
+            rs1: basereg,
+            rs2: tmp.to_reg(),
+        });
+        (const_insts, tmp.to_reg(), Imm12::zero())
+    }
+}
+
+//=============================================================================
+// Instructions and subcomponents: emission
+
+fn machreg_to_gpr(m: Reg) -> u32 {
+    assert_eq!(m.get_class(), RegClass::I64);
+    m.to_real_reg().get_hw_encoding() as u32
+}
+
+fn machreg_to_fpr(m: Reg) -> u32 {
+    assert_eq!(m.get_class(), RegClass::F64);
+    m.to_real_reg().get_hw_encoding() as u32
+}
+
+/// Encode a register in either class; the instruction determines which register file it names.
+fn machreg_enc(m: Reg) -> u32 {
+    m.to_real_reg().get_hw_encoding() as u32
+}
+
+/// Encode an R-type instruction.
+pub(crate) fn enc_r_type(
+    opcode: u32,
+    funct3: u32,
+    funct7: u32,
+    rd: Writable<Reg>,
+    rs1: Reg,
+    rs2: Reg,
+) -> u32 {
+    (funct7 << 25)
+        | (machreg_enc(rs2) << 20)
+        | (machreg_enc(rs1) << 15)
+        | (funct3 << 12)
+        | (machreg_enc(rd.to_reg()) << 7)
+        | opcode
+}
+
+/// Encode an R4-type instruction (fused multiply-add).
+fn enc_r4_type(
+    opcode: u32,
+    funct3: u32,
+    fmt: u32,
+    rd: Writable<Reg>,
+    rs1: Reg,
+    rs2: Reg,
+    rs3: Reg,
+) -> u32 {
+    (machreg_enc(rs3) << 27)
+        | (fmt << 25)
+        | (machreg_enc(rs2) << 20)
+        | (machreg_enc(rs1) << 15)
+        | (funct3 << 12)
+        | (machreg_enc(rd.to_reg()) << 7)
+        | opcode
+}
+
+/// Encode an I-type instruction. `imm12` holds the 12-bit immediate in its low bits.
+pub(crate) fn enc_i_type(opcode: u32, funct3: u32, rd: Writable<Reg>, rs1: Reg, imm12: u32) -> u32 {
+    ((imm12 & 0xfff) << 20)
+        | (machreg_enc(rs1) << 15)
+        | (funct3 << 12)
+        | (machreg_enc(rd.to_reg()) << 7)
+        | opcode
+}
+
+/// Encode an S-type instruction (stores).
+fn enc_s_type(opcode: u32, funct3: u32, rs1: Reg, rs2: Reg, imm12: Imm12) -> u32 {
+    let imm = imm12.bits();
+    ((imm >> 5) << 25)
+        | (machreg_enc(rs2) << 20)
+        | (machreg_enc(rs1) << 15)
+        | (funct3 << 12)
+        | ((imm & 0x1f) << 7)
+        | opcode
+}
+
+/// Encode a U-type instruction (`lui`, `auipc`).
+pub(crate) fn enc_u_type(opcode: u32, rd: Writable<Reg>, imm20: u32) -> u32 {
+    ((imm20 & 0xfffff) << 12) | (machreg_enc(rd.to_reg()) << 7) | opcode
+}
+
+/// Encode the scattered immediate of a B-type instruction (conditional branches).
+pub(crate) fn enc_b_offset(off: i32) -> u32 {
+    let off = off as u32;
+    (((off >> 12) & 0x1) << 31)
+        | (((off >> 5) & 0x3f) << 25)
+        | (((off >> 1) & 0xf) << 8)
+        | (((off >> 11) & 0x1) << 7)
+}
+
+/// Encode the scattered immediate of a J-type instruction (`jal`).
+pub(crate) fn enc_jal_offset(off: i32) -> u32 {
+    let off = off as u32;
+    (((off >> 20) & 0x1) << 31)
+        | (((off >> 1) & 0x3ff) << 21)
+        | (((off >> 11) & 0x1) << 20)
+        | (((off >> 12) & 0xff) << 12)
+}
+
+/// Encode a conditional branch.
+fn enc_branch(cond: IntegerCompare, off: i32) -> u32 {
+    let (funct3, rs1, rs2) = cond.to_branch();
+    enc_b_offset(off)
+        | (machreg_to_gpr(rs2) << 20)
+        | (machreg_to_gpr(rs1) << 15)
+        | (funct3 << 12)
+        | 0b1100011
+}
+
+/// Encode a `jal` with the given link register.
+fn enc_jal(rd: Writable<Reg>, off: i32) -> u32 {
+    enc_jal_offset(off) | (machreg_to_gpr(rd.to_reg()) << 7) | 0b1101111
+}
+
+impl AluOPRRR {
+    /// The (opcode, funct3, funct7) triple encoding this operation.
+    fn enc(self) -> (u32, u32, u32) {
+        const OP: u32 = 0b0110011;
+        const OP_32: u32 = 0b0111011;
+        match self {
+            AluOPRRR::Add => (OP, 0b000, 0b0000000),
+            AluOPRRR::Sub => (OP, 0b000, 0b0100000),
+            AluOPRRR::Sll => (OP, 0b001, 0b0000000),
+            AluOPRRR::Slt => (OP, 0b010, 0b0000000),
+            AluOPRRR::Sltu => (OP, 0b011, 0b0000000),
+            AluOPRRR::Xor => (OP, 0b100, 0b0000000),
+            AluOPRRR::Srl => (OP, 0b101, 0b0000000),
+            AluOPRRR::Sra => (OP, 0b101, 0b0100000),
+            AluOPRRR::Or => (OP, 0b110, 0b0000000),
+            AluOPRRR::And => (OP, 0b111, 0b0000000),
+            AluOPRRR::Addw => (OP_32, 0b000, 0b0000000),
+            AluOPRRR::Subw => (OP_32, 0b000, 0b0100000),
+            AluOPRRR::Sllw => (OP_32, 0b001, 0b0000000),
+            AluOPRRR::Srlw => (OP_32, 0b101, 0b0000000),
+            AluOPRRR::Sraw => (OP_32, 0b101, 0b0100000),
+            AluOPRRR::Mul => (OP, 0b000, 0b0000001),
+            AluOPRRR::Mulh => (OP, 0b001, 0b0000001),
+            AluOPRRR::Mulhsu => (OP, 0b010, 0b0000001),
+            AluOPRRR::Mulhu => (OP, 0b011, 0b0000001),
+            AluOPRRR::Div => (OP, 0b100, 0b0000001),
+            AluOPRRR::Divu => (OP, 0b101, 0b0000001),
+            AluOPRRR::Rem => (OP, 0b110, 0b0000001),
+            AluOPRRR::Remu => (OP, 0b111, 0b0000001),
+            AluOPRRR::Mulw => (OP_32, 0b000, 0b0000001),
+            AluOPRRR::Divw => (OP_32, 0b100, 0b0000001),
+            AluOPRRR::Divuw => (OP_32, 0b101, 0b0000001),
+            AluOPRRR::Remw => (OP_32, 0b110, 0b0000001),
+            AluOPRRR::Remuw => (OP_32, 0b111, 0b0000001),
+        }
+    }
+}
+
+impl AluOPRRI {
+    /// The (opcode, funct3, immediate mask, immediate bits ORed in) encoding this operation. For
+    /// shifts, only the shift amount comes from the immediate; the rest of the field selects the
+    /// operation.
+    fn enc(self) -> (u32, u32, u32, u32) {
+        const OP_IMM: u32 = 0b0010011;
+        const OP_IMM_32: u32 = 0b0011011;
+        match self {
+            AluOPRRI::Addi => (OP_IMM, 0b000, 0xfff, 0),
+            AluOPRRI::Slti => (OP_IMM, 0b010, 0xfff, 0),
+            AluOPRRI::Sltiu => (OP_IMM, 0b011, 0xfff, 0),
+            AluOPRRI::Xori => (OP_IMM, 0b100, 0xfff, 0),
+            AluOPRRI::Ori => (OP_IMM, 0b110, 0xfff, 0),
+            AluOPRRI::Andi => (OP_IMM, 0b111, 0xfff, 0),
+            AluOPRRI::Slli => (OP_IMM, 0b001, 0x3f, 0),
+            AluOPRRI::Srli => (OP_IMM, 0b101, 0x3f, 0),
+            AluOPRRI::Srai => (OP_IMM, 0b101, 0x3f, 0x400),
+            AluOPRRI::Addiw => (OP_IMM_32, 0b000, 0xfff, 0),
+            AluOPRRI::Slliw => (OP_IMM_32, 0b001, 0x1f, 0),
+            AluOPRRI::Srliw => (OP_IMM_32, 0b101, 0x1f, 0),
+            AluOPRRI::Sraiw => (OP_IMM_32, 0b101, 0x1f, 0x400),
+        }
+    }
+}
+
+impl LoadOP {
+    fn enc(self) -> (u32, u32) {
+        const LOAD: u32 = 0b0000011;
+        const LOAD_FP: u32 = 0b0000111;
+        match self {
+            LoadOP::Lb => (LOAD, 0b000),
+            LoadOP::Lh => (LOAD, 0b001),
+            LoadOP::Lw => (LOAD, 0b010),
+            LoadOP::Ld => (LOAD, 0b011),
+            LoadOP::Lbu => (LOAD, 0b100),
+            LoadOP::Lhu => (LOAD, 0b101),
+            LoadOP::Lwu => (LOAD, 0b110),
+            LoadOP::Flw => (LOAD_FP, 0b010),
+            LoadOP::Fld => (LOAD_FP, 0b011),
+        }
+    }
+}
+
+impl StoreOP {
+    fn enc(self) -> (u32, u32) {
+        const STORE: u32 = 0b0100011;
+        const STORE_FP: u32 = 0b0100111;
+        match self {
+            StoreOP::Sb => (STORE, 0b000),
+            StoreOP::Sh => (STORE, 0b001),
+            StoreOP::Sw => (STORE, 0b010),
+            StoreOP::Sd => (STORE, 0b011),
+            StoreOP::Fsw => (STORE_FP, 0b010),
+            StoreOP::Fsd => (STORE_FP, 0b011),
+        }
+    }
+}
+
+/// The `fmt` field of the floating-point instructions.
+const FMT_S: u32 = 0b00;
+const FMT_D: u32 = 0b01;
+
+const OP_FP: u32 = 0b1010011;
+
+impl FpuOPRR {
+    /// The (funct7, rs2 field, fixed funct3) encoding this operation. A fixed funct3 of `None`
+    /// means the field holds the rounding mode.
+    fn enc(self) -> (u32, u32, Option<u32>) {
+        match self {
+            FpuOPRR::FsqrtS => (0b01011 << 2 | FMT_S, 0, None),
+            FpuOPRR::FsqrtD => (0b01011 << 2 | FMT_D, 0, None),
+            FpuOPRR::FcvtWS => (0b11000 << 2 | FMT_S, 0, None),
+            FpuOPRR::FcvtWuS => (0b11000 << 2 | FMT_S, 1, None),
+            FpuOPRR::FcvtLS => (0b11000 << 2 | FMT_S, 2, None),
+            FpuOPRR::FcvtLuS => (0b11000 << 2 | FMT_S, 3, None),
+            FpuOPRR::FcvtWD => (0b11000 << 2 | FMT_D, 0, None),
+            FpuOPRR::FcvtWuD => (0b11000 << 2 | FMT_D, 1, None),
+            FpuOPRR::FcvtLD => (0b11000 << 2 | FMT_D, 2, None),
+            FpuOPRR::FcvtLuD => (0b11000 << 2 | FMT_D, 3, None),
+            FpuOPRR::FcvtSW => (0b11010 << 2 | FMT_S, 0, None),
+            FpuOPRR::FcvtSWu => (0b11010 << 2 | FMT_S, 1, None),
+            FpuOPRR::FcvtSL => (0b11010 << 2 | FMT_S, 2, None),
+            FpuOPRR::FcvtSLu => (0b11010 << 2 | FMT_S, 3, None),
+            // Conversions from 32-bit integers to f64 are exact; the assembler encodes them with
+            // a zero rounding-mode field.
+            FpuOPRR::FcvtDW => (0b11010 << 2 | FMT_D, 0, Some(0b000)),
+            FpuOPRR::FcvtDWu => (0b11010 << 2 | FMT_D, 1, Some(0b000)),
+            FpuOPRR::FcvtDL => (0b11010 << 2 | FMT_D, 2, None),
+            FpuOPRR::FcvtDLu => (0b11010 << 2 | FMT_D, 3, None),
+            FpuOPRR::FcvtSD => (0b01000 << 2 | FMT_S, 1, None),
+            FpuOPRR::FcvtDS => (0b01000 << 2 | FMT_D, 0, Some(0b000)),
+            FpuOPRR::FmvXW => (0b11100 << 2 | FMT_S, 0, Some(0b000)),
+            FpuOPRR::FmvWX => (0b11110 << 2 | FMT_S, 0, Some(0b000)),
+            FpuOPRR::FmvXD => (0b11100 << 2 | FMT_D, 0, Some(0b000)),
+            FpuOPRR::FmvDX => (0b11110 << 2 | FMT_D, 0, Some(0b000)),
+        }
+    }
+}
+
+impl FpuOPRRR {
+    /// The (funct7, fixed funct3) encoding this operation. A fixed funct3 of `None` means the
+    /// field holds the rounding mode.
+    fn enc(self) -> (u32, Option<u32>) {
+        match self {
+            FpuOPRRR::FaddS => (0b00000 << 2 | FMT_S, None),
+            FpuOPRRR::FsubS => (0b00001 << 2 | FMT_S, None),
+            FpuOPRRR::FmulS => (0b00010 << 2 | FMT_S, None),
+            FpuOPRRR::FdivS => (0b00011 << 2 | FMT_S, None),
+            FpuOPRRR::FsgnjS => (0b00100 << 2 | FMT_S, Some(0b000)),
+            FpuOPRRR::FsgnjnS => (0b00100 << 2 | FMT_S, Some(0b001)),
+            FpuOPRRR::FsgnjxS => (0b00100 << 2 | FMT_S, Some(0b010)),
+            FpuOPRRR::FminS => (0b00101 << 2 | FMT_S, Some(0b000)),
+            FpuOPRRR::FmaxS => (0b00101 << 2 | FMT_S, Some(0b001)),
+            FpuOPRRR::FeqS => (0b10100 << 2 | FMT_S, Some(0b010)),
+            FpuOPRRR::FltS => (0b10100 << 2 | FMT_S, Some(0b001)),
+            FpuOPRRR::FleS => (0b10100 << 2 | FMT_S, Some(0b000)),
+            FpuOPRRR::FaddD => (0b00000 << 2 | FMT_D, None),
+            FpuOPRRR::FsubD => (0b00001 << 2 | FMT_D, None),
+            FpuOPRRR::FmulD => (0b00010 << 2 | FMT_D, None),
+            FpuOPRRR::FdivD => (0b00011 << 2 | FMT_D, None),
+            FpuOPRRR::FsgnjD => (0b00100 << 2 | FMT_D, Some(0b000)),
+            FpuOPRRR::FsgnjnD => (0b00100 << 2 | FMT_D, Some(0b001)),
+            FpuOPRRR::FsgnjxD => (0b00100 << 2 | FMT_D, Some(0b010)),
+            FpuOPRRR::FminD => (0b00101 << 2 | FMT_D, Some(0b000)),
+            FpuOPRRR::FmaxD => (0b00101 << 2 | FMT_D, Some(0b001)),
+            FpuOPRRR::FeqD => (0b10100 << 2 | FMT_D, Some(0b010)),
+            FpuOPRRR::FltD => (0b10100 << 2 | FMT_D, Some(0b001)),
+            FpuOPRRR::FleD => (0b10100 << 2 | FMT_D, Some(0b000)),
+        }
+    }
+}
+
+impl AtomicOP {
+    /// The (funct5, funct3) encoding this operation.
+    fn enc(self) -> (u32, u32) {
+        match self {
+            AtomicOP::LrW => (0b00010, 0b010),
+            AtomicOP::ScW => (0b00011, 0b010),
+            AtomicOP::AmoswapW => (0b00001, 0b010),
+            AtomicOP::AmoaddW => (0b00000, 0b010),
+            AtomicOP::AmoxorW => (0b00100, 0b010),
+            AtomicOP::AmoandW => (0b01100, 0b010),
+            AtomicOP::AmoorW => (0b01000, 0b010),
+            AtomicOP::AmominW => (0b10000, 0b010),
+            AtomicOP::AmomaxW => (0b10100, 0b010),
+            AtomicOP::AmominuW => (0b11000, 0b010),
+            AtomicOP::AmomaxuW => (0b11100, 0b010),
+            AtomicOP::LrD => (0b00010, 0b011),
+            AtomicOP::ScD => (0b00011, 0b011),
+            AtomicOP::AmoswapD => (0b00001, 0b011),
+            AtomicOP::AmoaddD => (0b00000, 0b011),
+            AtomicOP::AmoxorD => (0b00100, 0b011),
+            AtomicOP::AmoandD => (0b01100, 0b011),
+            AtomicOP::AmoorD => (0b01000, 0b011),
+            AtomicOP::AmominD => (0b10000, 0b011),
+            AtomicOP::AmomaxD => (0b10100, 0b011),
+            AtomicOP::AmominuD => (0b11000, 0b011),
+            AtomicOP::AmomaxuD => (0b11100, 0b011),
+        }
+    }
+}
+
+fn enc_atomic(op: AtomicOP, rd: Writable<Reg>, addr: Reg, src: Reg, aq: bool, rl: bool) -> u32 {
+    let (funct5, funct3) = op.enc();
+    let funct7 = (funct5 << 2) | (u32::from(aq) << 1) | u32::from(rl);
+    let src = match op {
+        AtomicOP::LrW | AtomicOP::LrD => zero_reg(),
+        _ => src,
+    };
+    enc_r_type(0b0101111, funct3, funct7, rd, addr, src)
+}
+
+/// The encoding of `unimp` (`csrrw zero, cycle, zero`), which raises an illegal-instruction
+/// exception.
+const UNIMP: u32 = 0xc000_1073;
+
+/// The CSR number of the accrued floating-point exception flags.
+const CSR_FFLAGS: u32 = 0x001;
+
+/// State carried between emissions of a sequence of instructions.
+#[derive(Default, Clone, Debug)]
+pub struct EmitState {
+    /// Addend to convert nominal-SP offsets to real-SP offsets at the current
+    /// program point.
+    pub(crate) virtual_sp_offset: i64,
+    /// Offset of FP from nominal-SP.
+    pub(crate) nominal_sp_to_fp: i64,
+    /// Safepoint stack map for upcoming instruction, as provided to `pre_safepoint()`.
+    stack_map: Option<StackMap>,
+    /// Current source-code location corresponding to instruction to be emitted.
+    cur_srcloc: SourceLoc,
+}
+
+impl MachInstEmitState<Inst> for EmitState {
+    fn new(abi: &dyn ABICallee<I = Inst>) -> Self {
+        EmitState {
+            virtual_sp_offset: 0,
+            nominal_sp_to_fp: abi.frame_size() as i64,
+            stack_map: None,
+            cur_srcloc: SourceLoc::default(),
+        }
+    }
+
+    fn pre_safepoint(&mut self, stack_map: StackMap) {
+        self.stack_map = Some(stack_map);
+    }
+
+    fn pre_sourceloc(&mut self, srcloc: SourceLoc) {
+        self.cur_srcloc = srcloc;
+    }
+}
+
+impl EmitState {
+    fn take_stack_map(&mut self) -> Option<StackMap> {
+        self.stack_map.take()
+    }
+
+    fn clear_post_insn(&mut self) {
+        self.stack_map = None;
+    }
+
+    fn cur_srcloc(&self) -> SourceLoc {
+        self.cur_srcloc
+    }
+}
+
+/// Constant state used during function compilation.
+pub struct EmitInfo(settings::Flags);
+
+impl EmitInfo {
+    pub(crate) fn new(flags: settings::Flags) -> Self {
+        Self(flags)
+    }
+}
+
+impl MachInstEmitInfo for EmitInfo {
+    fn flags(&self) -> &settings::Flags {
+        &self.0
+    }
+}
+
+impl MachInstEmit for Inst {
+    type State = EmitState;
+    type Info = EmitInfo;
+    type UnwindInfo = super::unwind::Riscv64UnwindInfo;
+
+    fn emit(&self, sink: &mut MachBuffer<Inst>, emit_info: &Self::Info, state: &mut EmitState) {
+        // N.B.: we *must* not exceed the "worst-case size" used to compute
+        // where to insert islands, except when islands are explicitly triggered
+        // (with an `EmitIsland`). We check this in debug builds. This is `mut`
+        // to allow disabling the check for `BrTable`, which is always
+        // emitted following an `EmitIsland`.
+        let mut start_off = sink.cur_offset();
+
+        match self {
+            &Inst::Nop0 => {}
+            &Inst::Nop4 => {
+                // addi zero, zero, 0
+                sink.put4(0x0000_0013);
+            }
+            &Inst::Lui { rd, imm20 } => {
+                sink.put4(enc_u_type(0b0110111, rd, imm20));
+            }
+            &Inst::AluRRR {
+                alu_op,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let (opcode, funct3, funct7) = alu_op.enc();
+                sink.put4(enc_r_type(opcode, funct3, funct7, rd, rs1, rs2));
+            }
+            &Inst::AluRRImm12 {
+                alu_op,
+                rd,
+                rs,
+                imm12,
+            } => {
+                let (opcode, funct3, mask, extra) = alu_op.enc();
+                if mask != 0xfff {
+                    debug_assert!(imm12.as_i64() >= 0 && imm12.as_i64() as u32 <= mask);
+                }
+                let imm = (imm12.bits() & mask) | extra;
+                sink.put4(enc_i_type(opcode, funct3, rd, rs, imm));
+            }
+            &Inst::Load { op, rd, ref from } => {
+                let (mem_insts, base, imm12) = mem_finalize(from, state);
+                for inst in mem_insts.into_iter() {
+                    inst.emit(sink, emit_info, state);
+                }
+
+                let srcloc = state.cur_srcloc();
+                if srcloc != SourceLoc::default() {
+                    // Register the offset at which the actual load instruction starts.
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+
+                let (opcode, funct3) = op.enc();
+                sink.put4(enc_i_type(opcode, funct3, rd, base, imm12.bits()));
+            }
+            &Inst::Store { op, src, ref to } => {
+                let (mem_insts, base, imm12) = mem_finalize(to, state);
+                for inst in mem_insts.into_iter() {
+                    inst.emit(sink, emit_info, state);
+                }
+
+                let srcloc = state.cur_srcloc();
+                if srcloc != SourceLoc::default() {
+                    // Register the offset at which the actual store instruction starts.
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+
+                let (opcode, funct3) = op.enc();
+                sink.put4(enc_s_type(opcode, funct3, base, src, imm12));
+            }
+            &Inst::FpuRR {
+                alu_op,
+                frm,
+                rd,
+                rs,
+            } => {
+                let (funct7, rs2, funct3) = alu_op.enc();
+                let funct3 = funct3.unwrap_or(frm.bits());
+                sink.put4(
+                    (funct7 << 25)
+                        | (rs2 << 20)
+                        | (machreg_enc(rs) << 15)
+                        | (funct3 << 12)
+                        | (machreg_enc(rd.to_reg()) << 7)
+                        | OP_FP,
+                );
+            }
+            &Inst::FpuRRR {
+                alu_op,
+                frm,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let (funct7, funct3) = alu_op.enc();
+                let funct3 = funct3.unwrap_or(frm.bits());
+                sink.put4(enc_r_type(OP_FP, funct3, funct7, rd, rs1, rs2));
+            }
+            &Inst::FpuRRRR {
+                alu_op,
+                frm,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+            } => {
+                let fmt = match alu_op {
+                    FpuOPRRRR::FmaddS => FMT_S,
+                    FpuOPRRRR::FmaddD => FMT_D,
+                };
+                sink.put4(enc_r4_type(0b1000011, frm.bits(), fmt, rd, rs1, rs2, rs3));
+            }
+            &Inst::Mov { rd, rm, .. } => {
+                assert!(rd.to_reg().get_class() == rm.get_class());
+                match rm.get_class() {
+                    RegClass::I64 => {
+                        // addi rd, rm, 0
+                        sink.put4(enc_i_type(0b0010011, 0b000, rd, rm, 0));
+                    }
+                    RegClass::F64 => {
+                        // fsgnj.d rd, rm, rm: a move that preserves the bits of NaN-boxed
+                        // single-precision values as well.
+                        let (funct7, funct3) = FpuOPRRR::FsgnjD.enc();
+                        sink.put4(enc_r_type(OP_FP, funct3.unwrap(), funct7, rd, rm, rm));
+                    }
+                    _ => panic!("Unexpected register class: {:?}", rm.get_class()),
+                }
+            }
+            &Inst::Extend {
+                rd,
+                rn,
+                signed,
+                from_bits,
+                ..
+            } => {
+                let mut emit_op = |alu_op, rs, imm: i64| {
+                    let inst = Inst::AluRRImm12 {
+                        alu_op,
+                        rd,
+                        rs,
+                        imm12: Imm12::maybe_from_i64(imm).unwrap(),
+                    };
+                    inst.emit(sink, emit_info, state);
+                };
+                match (signed, from_bits) {
+                    (false, 1) => emit_op(AluOPRRI::Andi, rn, 1),
+                    (false, 8) => emit_op(AluOPRRI::Andi, rn, 0xff),
+                    (true, 32) => emit_op(AluOPRRI::Addiw, rn, 0),
+                    (_, bits) if bits < 64 => {
+                        let shift = (64 - bits) as i64;
+                        emit_op(AluOPRRI::Slli, rn, shift);
+                        let right = if signed {
+                            AluOPRRI::Srai
+                        } else {
+                            AluOPRRI::Srli
+                        };
+                        emit_op(right, rd.to_reg(), shift);
+                    }
+                    _ => panic!("Unsupported extend variant: from {} bits", from_bits),
+                }
+            }
+            &Inst::Select {
+                rd,
+                cond,
+                rs_true,
+                rs_false,
+            } => {
+                // beqz cond, 12 ; mv rd, rs_true ; j 8 ; mv rd, rs_false
+                let kind = IntegerCompare {
+                    kind: IntCC::Equal,
+                    rs1: cond,
+                    rs2: zero_reg(),
+                };
+                sink.put4(enc_branch(kind, 12));
+                Inst::gen_move(rd, rs_true, I64).emit(sink, emit_info, state);
+                sink.put4(enc_jal(writable_zero_reg(), 8));
+                Inst::gen_move(rd, rs_false, I64).emit(sink, emit_info, state);
+            }
+            &Inst::Atomic {
+                op,
+                rd,
+                addr,
+                src,
+                aq,
+                rl,
+            } => {
+                let srcloc = state.cur_srcloc();
+                if srcloc != SourceLoc::default() {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                sink.put4(enc_atomic(op, rd, addr, src, aq, rl));
+            }
+            &Inst::AtomicCas {
+                ty,
+                rd,
+                tmp,
+                addr,
+                expected,
+                replacement,
+            } => {
+                let (lr, sc) = match ty {
+                    I32 => (AtomicOP::LrW, AtomicOP::ScW),
+                    I64 => (AtomicOP::LrD, AtomicOP::ScD),
+                    _ => panic!("Unsupported atomic_cas type: {}", ty),
+                };
+                let srcloc = state.cur_srcloc();
+                if srcloc != SourceLoc::default() {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                // again:
+                sink.put4(enc_atomic(lr, rd, addr.to_reg(), zero_reg(), true, true));
+                // bne rd, expected, out
+                let ne = IntegerCompare {
+                    kind: IntCC::NotEqual,
+                    rs1: rd.to_reg(),
+                    rs2: expected.to_reg(),
+                };
+                sink.put4(enc_branch(ne, 12));
+                sink.put4(enc_atomic(
+                    sc,
+                    tmp,
+                    addr.to_reg(),
+                    replacement.to_reg(),
+                    true,
+                    true,
+                ));
+                // bnez tmp, again
+                let retry = IntegerCompare {
+                    kind: IntCC::NotEqual,
+                    rs1: tmp.to_reg(),
+                    rs2: zero_reg(),
+                };
+                sink.put4(enc_branch(retry, -12));
+                // out:
+            }
+            &Inst::Fence { pred, succ } => {
+                sink.put4((u32::from(pred) << 24) | (u32::from(succ) << 20) | 0b0001111);
+            }
+            &Inst::ClearFflags => {
+                // csrrw zero, fflags, zero
+                sink.put4((CSR_FFLAGS << 20) | (0b001 << 12) | 0b1110011);
+            }
+            &Inst::ReadFflags { rd } => {
+                // csrrs rd, fflags, zero
+                sink.put4(
+                    (CSR_FFLAGS << 20)
+                        | (0b010 << 12)
+                        | (machreg_to_gpr(rd.to_reg()) << 7)
+                        | 0b1110011,
+                );
+            }
+            &Inst::Call { ref info } => {
+                if let Some(s) = state.take_stack_map() {
+                    sink.add_stack_map(StackMapExtent::UpcomingBytes(8), s);
+                }
+                let loc = state.cur_srcloc();
+                // auipc ra, 0 ; jalr ra, 0(ra), with the pair patched by the relocation.
+                sink.add_reloc(loc, Reloc::RiscvCallPlt, &info.dest, 0);
+                sink.put4(enc_u_type(0b0010111, writable_link_reg(), 0));
+                sink.put4(enc_i_type(
+                    0b1100111,
+                    0b000,
+                    writable_link_reg(),
+                    link_reg(),
+                    0,
+                ));
+                if info.opcode.is_call() {
+                    sink.add_call_site(loc, info.opcode);
+                }
+            }
+            &Inst::CallInd { ref info } => {
+                if let Some(s) = state.take_stack_map() {
+                    sink.add_stack_map(StackMapExtent::UpcomingBytes(4), s);
+                }
+                // jalr ra, 0(rn)
+                sink.put4(enc_i_type(
+                    0b1100111,
+                    0b000,
+                    writable_link_reg(),
+                    info.rn,
+                    0,
+                ));
+                let loc = state.cur_srcloc();
+                if info.opcode.is_call() {
+                    sink.add_call_site(loc, info.opcode);
+                }
+            }
+            &Inst::Ret => {
+                // jalr zero, 0(ra)
+                sink.put4(enc_i_type(
+                    0b1100111,
+                    0b000,
+                    writable_zero_reg(),
+                    link_reg(),
+                    0,
+                ));
+            }
+            &Inst::EpiloguePlaceholder => {
+                // Noop; this is just a placeholder for epilogues.
+            }
+            &Inst::Jal { dest } => {
+                let off = sink.cur_offset();
+                // Indicate that the jump uses a label, if so, so that a fixup can occur later.
+                if let Some(l) = dest.as_label() {
+                    sink.use_label_at_offset(off, l, LabelUse::Jal20);
+                    sink.add_uncond_branch(off, off + 4, l);
+                }
+                // Emit the jump itself.
+                sink.put4(enc_jal(writable_zero_reg(), dest.as_offset_or_zero()));
+            }
+            &Inst::CondBr {
+                taken,
+                not_taken,
+                kind,
+            } => {
+                // Conditional part first.
+                let cond_off = sink.cur_offset();
+                if let Some(l) = taken.as_label() {
+                    sink.use_label_at_offset(cond_off, l, LabelUse::B12);
+                    let inverted = enc_branch(kind.inverse(), 0).to_le_bytes();
+                    sink.add_cond_branch(cond_off, cond_off + 4, l, &inverted[..]);
+                }
+                sink.put4(enc_branch(kind, taken.as_offset_or_zero()));
+
+                // Unconditional part next.
+                let uncond_off = sink.cur_offset();
+                if let Some(l) = not_taken.as_label() {
+                    sink.use_label_at_offset(uncond_off, l, LabelUse::Jal20);
+                    sink.add_uncond_branch(uncond_off, uncond_off + 4, l);
+                }
+                sink.put4(enc_jal(writable_zero_reg(), not_taken.as_offset_or_zero()));
+            }
+            &Inst::TrapIf { cond, trap_code } => {
+                // b<inverted cond> 8 ; unimp
+                sink.put4(enc_branch(cond.inverse(), 8));
+                let trap = Inst::Udf { trap_code };
+                trap.emit(sink, emit_info, state);
+            }
+            &Inst::Udf { trap_code } => {
+                let srcloc = state.cur_srcloc();
+                sink.add_trap(srcloc, trap_code);
+                if let Some(s) = state.take_stack_map() {
+                    sink.add_stack_map(StackMapExtent::UpcomingBytes(4), s);
+                }
+                sink.put4(UNIMP);
+            }
+            &Inst::Ebreak => {
+                sink.put4(0x0010_0073);
+            }
+            &Inst::BrTable {
+                index,
+                tmp1,
+                tmp2,
+                ref info,
+            } => {
+                // This sequence is *one* instruction in the vcode, and is expanded only here at
+                // emission time, because we cannot allow the regalloc to insert spills/reloads in
+                // the middle; we depend on hardcoded PC-rel addressing below.
+
+                // Scale the index into a byte offset into the table, and compare it against the
+                // table size.
+                let inst = Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Slli,
+                    rd: tmp2,
+                    rs: index,
+                    imm12: Imm12::maybe_from_i64(2).unwrap(),
+                };
+                inst.emit(sink, emit_info, state);
+                for inst in Inst::load_constant(tmp1, info.targets.len() as u64 * 4) {
+                    inst.emit(sink, emit_info, state);
+                }
+
+                // Branch to default when the index is out of bounds. No need to inform the sink's
+                // branch folding logic about this branch, because it will not be merged with any
+                // other branch, flipped, or elided (it is not preceded or succeeded by any other
+                // branch). Just emit it with the label use.
+                let kind = IntegerCompare {
+                    kind: IntCC::UnsignedGreaterThanOrEqual,
+                    rs1: tmp2.to_reg(),
+                    rs2: tmp1.to_reg(),
+                };
+                let default_br_offset = sink.cur_offset();
+                if let BranchTarget::Label(l) = info.default_target {
+                    sink.use_label_at_offset(default_br_offset, l, LabelUse::B12);
+                }
+                sink.put4(enc_branch(kind, info.default_target.as_offset_or_zero()));
+
+                // auipc tmp1, 0 ; add tmp2, tmp2, tmp1 ; lw tmp2, 20(tmp2) ;
+                // add tmp1, tmp1, tmp2 ; jalr zero, 20(tmp1)
+                //
+                // The table starts 20 bytes after the `auipc`, and its entries are offsets from
+                // the start of the table.
+                sink.put4(enc_u_type(0b0010111, tmp1, 0));
+                let inst = Inst::AluRRR {
+                    alu_op: AluOPRRR::Add,
+                    rd: tmp2,
+                    rs1: tmp2.to_reg(),
+                    rs2: tmp1.to_reg(),
+                };
+                inst.emit(sink, emit_info, state);
+                sink.put4(enc_i_type(0b0000011, 0b010, tmp2, tmp2.to_reg(), 20));
+                let inst = Inst::AluRRR {
+                    alu_op: AluOPRRR::Add,
+                    rd: tmp1,
+                    rs1: tmp1.to_reg(),
+                    rs2: tmp2.to_reg(),
+                };
+                inst.emit(sink, emit_info, state);
+                sink.put4(enc_i_type(
+                    0b1100111,
+                    0b000,
+                    writable_zero_reg(),
+                    tmp1.to_reg(),
+                    20,
+                ));
+
+                // Emit jump table (table of 32-bit offsets).
+                let jt_off = sink.cur_offset();
+                for &target in info.targets.iter() {
+                    let word_off = sink.cur_offset();
+                    // off_into_table is an addend here embedded in the label to be later patched
+                    // at the end of codegen. The offset is initially relative to this jump table
+                    // entry; with the extra addend, it'll be relative to the jump table's start,
+                    // after patching.
+                    let off_into_table = word_off - jt_off;
+                    sink.use_label_at_offset(
+                        word_off,
+                        target.as_label().unwrap(),
+                        LabelUse::PCRel32,
+                    );
+                    sink.put4(off_into_table);
+                }
+
+                // Lowering produces an EmitIsland before using a BrTable, so we can safely
+                // disable the worst-case-size check in this case.
+                start_off = sink.cur_offset();
+            }
+            &Inst::LoadExtName {
+                rd,
+                ref name,
+                offset,
+            } => {
+                // auipc rd, 0 ; ld rd, 12(rd) ; j 12 ; .8byte name + offset
+                sink.put4(enc_u_type(0b0010111, rd, 0));
+                sink.put4(enc_i_type(0b0000011, 0b011, rd, rd.to_reg(), 12));
+                sink.put4(enc_jal(writable_zero_reg(), 12));
+                let srcloc = state.cur_srcloc();
+                sink.add_reloc(srcloc, Reloc::Abs8, name, offset);
+                if emit_info.flags().emit_all_ones_funcaddrs() {
+                    sink.put8(u64::max_value());
+                } else {
+                    sink.put8(0);
+                }
+            }
+            &Inst::LoadAddr { rd, ref mem } => {
+                let (mem_insts, base, imm12) = mem_finalize(mem, state);
+                for inst in mem_insts.into_iter() {
+                    inst.emit(sink, emit_info, state);
+                }
+                let inst = Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addi,
+                    rd,
+                    rs: base,
+                    imm12,
+                };
+                inst.emit(sink, emit_info, state);
+            }
+            &Inst::FloatRound {
+                op,
+                ty,
+                rd,
+                rs,
+                int_tmp,
+                f_tmp,
+            } => {
+                // Values whose magnitude is at least 2^(mantissa bits) are already integral (or
+                // are infinities or NaNs), and are passed through. All other values fit in an
+                // i64, so we round-trip them through an integer register with the rounding mode
+                // of the operation, and then restore the sign (for results that round to zero).
+                //
+                //   fmin rd, rs, rs              ; copy, quieting NaNs
+                //   fmv.x int_tmp, rd
+                //   slli int_tmp, int_tmp, 1 + (64 - bits)
+                //   srli int_tmp, int_tmp, 64 - exponent bits
+                //   addi int_tmp, int_tmp, -(bias + mantissa bits)
+                //   bge int_tmp, zero, out
+                //   fcvt.l int_tmp, rd, rm
+                //   fcvt.from_l f_tmp, int_tmp, rm
+                //   fsgnj rd, f_tmp, rd
+                // out:
+                let (fmin, fmv, fcvt_to, fcvt_from, fsgnj, shl, shr, adj) = match ty {
+                    F32 => (
+                        FpuOPRRR::FminS,
+                        FpuOPRR::FmvXW,
+                        FpuOPRR::FcvtLS,
+                        FpuOPRR::FcvtSL,
+                        FpuOPRRR::FsgnjS,
+                        33,
+                        56,
+                        -150,
+                    ),
+                    F64 => (
+                        FpuOPRRR::FminD,
+                        FpuOPRR::FmvXD,
+                        FpuOPRR::FcvtLD,
+                        FpuOPRR::FcvtDL,
+                        FpuOPRRR::FsgnjD,
+                        1,
+                        53,
+                        -1075,
+                    ),
+                    _ => panic!("Unsupported float_round type: {}", ty),
+                };
+                let frm = op.frm();
+                let insts = [
+                    Inst::FpuRRR {
+                        alu_op: fmin,
+                        frm: FRM::RNE,
+                        rd,
+                        rs1: rs,
+                        rs2: rs,
+                    },
+                    Inst::FpuRR {
+                        alu_op: fmv,
+                        frm: FRM::RNE,
+                        rd: int_tmp,
+                        rs: rd.to_reg(),
+                    },
+                    Inst::AluRRImm12 {
+                        alu_op: AluOPRRI::Slli,
+                        rd: int_tmp,
+                        rs: int_tmp.to_reg(),
+                        imm12: Imm12::maybe_from_i64(shl).unwrap(),
+                    },
+                    Inst::AluRRImm12 {
+                        alu_op: AluOPRRI::Srli,
+                        rd: int_tmp,
+                        rs: int_tmp.to_reg(),
+                        imm12: Imm12::maybe_from_i64(shr).unwrap(),
+                    },
+                    Inst::AluRRImm12 {
+                        alu_op: AluOPRRI::Addi,
+                        rd: int_tmp,
+                        rs: int_tmp.to_reg(),
+                        imm12: Imm12::maybe_from_i64(adj).unwrap(),
+                    },
+                ];
+                for inst in insts.iter() {
+                    inst.emit(sink, emit_info, state);
+                }
+                let passthrough = IntegerCompare {
+                    kind: IntCC::SignedGreaterThanOrEqual,
+                    rs1: int_tmp.to_reg(),
+                    rs2: zero_reg(),
+                };
+                sink.put4(enc_branch(passthrough, 16));
+                let insts = [
+                    Inst::FpuRR {
+                        alu_op: fcvt_to,
+                        frm,
+                        rd: int_tmp,
+                        rs: rd.to_reg(),
+                    },
+                    Inst::FpuRR {
+                        alu_op: fcvt_from,
+                        frm,
+                        rd: f_tmp,
+                        rs: int_tmp.to_reg(),
+                    },
+                    Inst::FpuRRR {
+                        alu_op: fsgnj,
+                        frm: FRM::RNE,
+                        rd,
+                        rs1: f_tmp.to_reg(),
+                        rs2: rd.to_reg(),
+                    },
+                ];
+                for inst in insts.iter() {
+                    inst.emit(sink, emit_info, state);
+                }
+            }
+            &Inst::VirtualSPOffsetAdj { offset } => {
+                debug!(
+                    "virtual sp offset adjusted by {} -> {}",
+                    offset,
+                    state.virtual_sp_offset + offset,
+                );
+                state.virtual_sp_offset += offset;
+            }
+            &Inst::EmitIsland { needed_space } => {
+                if sink.island_needed(needed_space + 4) {
+                    let jump_around_label = sink.get_label();
+                    let jmp = Inst::Jal {
+                        dest: BranchTarget::Label(jump_around_label),
+                    };
+                    jmp.emit(sink, emit_info, state);
+                    sink.emit_island();
+                    sink.bind_label(jump_around_label);
+                }
+            }
+        }
+
+        let end_off = sink.cur_offset();
+        debug_assert!((end_off - start_off) <= Inst::worst_case_size());
+
+        state.clear_post_insn();
+    }
+
+    fn pretty_print(&self, mb_rru: Option<&RealRegUniverse>, state: &mut EmitState) -> String {
+        self.print_with_state(mb_rru, state)
+    }
+}
@@ -0,0 +1,1579 @@
+use crate::ir::condcodes::IntCC;
+use crate::ir::types::*;
+use crate::ir::TrapCode;
+use crate::isa::riscv64::inst::*;
+use crate::isa::test_utils;
+use crate::settings;
+
+use alloc::vec::Vec;
+
+#[test]
+fn test_riscv64_binemit() {
+    let mut insns = Vec::<(Inst, &str, &str)>::new();
+
+    // N.B.: the architecture is little-endian, so when transcribing the 32-bit
+    // hex instructions from e.g. objdump disassembly, one must swap the bytes
+    // seen below. (E.g., a `ret` is normally written as the u32 `00008067`, but
+    // the byte content when little-endian is `67800000`.)
+    //
+    // Useful helper script to produce the encodings from the text:
+    //
+    //      #!/bin/sh
+    //      echo "$@" | llvm-mc -triple=riscv64 -mattr=+m,+a,+f,+d -show-encoding
+    insns.push((Inst::Ret, "67800000", "ret"));
+    insns.push((Inst::Nop0, "", "nop-zero-len"));
+    insns.push((Inst::Nop4, "13000000", "nop"));
+    insns.push((
+        Inst::Lui {
+            rd: writable_xreg(10),
+            imm20: 0x12345,
+        },
+        "37553412",
+        "lui a0,0x12345",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Add,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3385C500",
+        "add a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sub,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3385C540",
+        "sub a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sll,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3395C500",
+        "sll a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Slt,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33A5C500",
+        "slt a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sltu,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33B5C500",
+        "sltu a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Xor,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33C5C500",
+        "xor a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Srl,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33D5C500",
+        "srl a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sra,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33D5C540",
+        "sra a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Or,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33E5C500",
+        "or a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::And,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33F5C500",
+        "and a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Addw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3B85C500",
+        "addw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Subw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3B85C540",
+        "subw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sllw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3B95C500",
+        "sllw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Srlw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BD5C500",
+        "srlw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Sraw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BD5C540",
+        "sraw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Mul,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3385C502",
+        "mul a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Mulh,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3395C502",
+        "mulh a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Mulhsu,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33A5C502",
+        "mulhsu a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Mulhu,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33B5C502",
+        "mulhu a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Div,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33C5C502",
+        "div a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Divu,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33D5C502",
+        "divu a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Rem,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33E5C502",
+        "rem a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Remu,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "33F5C502",
+        "remu a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Mulw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3B85C502",
+        "mulw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Divw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BC5C502",
+        "divw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Divuw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BD5C502",
+        "divuw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Remw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BE5C502",
+        "remw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRR {
+            alu_op: AluOPRRR::Remuw,
+            rd: writable_xreg(10),
+            rs1: xreg(11),
+            rs2: xreg(12),
+        },
+        "3BF5C502",
+        "remuw a0,a1,a2",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Addi,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(-1).unwrap(),
+        },
+        "1385F5FF",
+        "addi a0,a1,-1",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Slti,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(2047).unwrap(),
+        },
+        "13A5F57F",
+        "slti a0,a1,2047",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Sltiu,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(1).unwrap(),
+        },
+        "13B51500",
+        "sltiu a0,a1,1",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Xori,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(-2048).unwrap(),
+        },
+        "13C50580",
+        "xori a0,a1,-2048",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Ori,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(127).unwrap(),
+        },
+        "13E5F507",
+        "ori a0,a1,127",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Andi,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(255).unwrap(),
+        },
+        "13F5F50F",
+        "andi a0,a1,255",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Slli,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(63).unwrap(),
+        },
+        "1395F503",
+        "slli a0,a1,63",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Srli,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(1).unwrap(),
+        },
+        "13D51500",
+        "srli a0,a1,1",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Srai,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(7).unwrap(),
+        },
+        "13D57540",
+        "srai a0,a1,7",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Addiw,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(-5).unwrap(),
+        },
+        "1B85B5FF",
+        "addiw a0,a1,-5",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Slliw,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(31).unwrap(),
+        },
+        "1B95F501",
+        "slliw a0,a1,31",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Srliw,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(3).unwrap(),
+        },
+        "1BD53500",
+        "srliw a0,a1,3",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Sraiw,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::maybe_from_i64(16).unwrap(),
+        },
+        "1BD50541",
+        "sraiw a0,a1,16",
+    ));
+    insns.push((
+        Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Addi,
+            rd: writable_xreg(10),
+            rs: xreg(11),
+            imm12: Imm12::zero(),
+        },
+        "13850500",
+        "mv a0,a1",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lb,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I8),
+        },
+        "03858500",
+        "lb a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lh,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I16),
+        },
+        "03958500",
+        "lh a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lw,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I32),
+        },
+        "03A58500",
+        "lw a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Ld,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I64),
+        },
+        "03B58500",
+        "ld a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lbu,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I8),
+        },
+        "03C58500",
+        "lbu a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lhu,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I16),
+        },
+        "03D58500",
+        "lhu a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Lwu,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 8, I32),
+        },
+        "03E58500",
+        "lwu a0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Flw,
+            rd: writable_freg(10),
+            from: AMode::RegOffset(xreg(11), 8, F32),
+        },
+        "07A58500",
+        "flw fa0,8(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Fld,
+            rd: writable_freg(10),
+            from: AMode::RegOffset(xreg(11), 8, F64),
+        },
+        "07B58500",
+        "fld fa0,8(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Sb,
+            src: xreg(10),
+            to: AMode::RegOffset(xreg(11), -16, I8),
+        },
+        "2388A5FE",
+        "sb a0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Sh,
+            src: xreg(10),
+            to: AMode::RegOffset(xreg(11), -16, I16),
+        },
+        "2398A5FE",
+        "sh a0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Sw,
+            src: xreg(10),
+            to: AMode::RegOffset(xreg(11), -16, I32),
+        },
+        "23A8A5FE",
+        "sw a0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Sd,
+            src: xreg(10),
+            to: AMode::RegOffset(xreg(11), -16, I64),
+        },
+        "23B8A5FE",
+        "sd a0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Fsw,
+            src: freg(10),
+            to: AMode::RegOffset(xreg(11), -16, F32),
+        },
+        "27A8A5FE",
+        "fsw fa0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Fsd,
+            src: freg(10),
+            to: AMode::RegOffset(xreg(11), -16, F64),
+        },
+        "27B8A5FE",
+        "fsd fa0,-16(a1)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Ld,
+            rd: writable_xreg(10),
+            from: AMode::RegOffset(xreg(11), 0x12345, I64),
+        },
+        "372F01001B0F5F34338FE50103350F00",
+        "lui t5,0x12 ; addiw t5,t5,837 ; add t5,a1,t5 ; ld a0,0(t5)",
+    ));
+    insns.push((
+        Inst::Load {
+            op: LoadOP::Ld,
+            rd: writable_xreg(10),
+            from: AMode::SPOffset(16, I64),
+        },
+        "03350101",
+        "ld a0,16(sp)",
+    ));
+    insns.push((
+        Inst::Store {
+            op: StoreOP::Sd,
+            src: xreg(10),
+            to: AMode::FPOffset(-8, I64),
+        },
+        "233CA4FE",
+        "sd a0,-8(fp)",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FsqrtS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: freg(11),
+        },
+        "53850558",
+        "fsqrt.s fa0,fa1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FsqrtD,
+            frm: FRM::DYN,
+            rd: writable_freg(10),
+            rs: freg(11),
+        },
+        "53F5055A",
+        "fsqrt.d fa0,fa1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtWS,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539505C0",
+        "fcvt.w.s a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtWuS,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539515C0",
+        "fcvt.wu.s a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtLS,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539525C0",
+        "fcvt.l.s a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtLuS,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539535C0",
+        "fcvt.lu.s a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtWD,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539505C2",
+        "fcvt.w.d a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtWuD,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539515C2",
+        "fcvt.wu.d a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtLD,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539525C2",
+        "fcvt.l.d a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtLuD,
+            frm: FRM::RTZ,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "539535C2",
+        "fcvt.lu.d a0,fa1,rtz",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtSW,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538505D0",
+        "fcvt.s.w fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtSWu,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538515D0",
+        "fcvt.s.wu fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtSL,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538525D0",
+        "fcvt.s.l fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtSLu,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538535D0",
+        "fcvt.s.lu fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtDW,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538505D2",
+        "fcvt.d.w fa0,a1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtDWu,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538515D2",
+        "fcvt.d.wu fa0,a1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtDL,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538525D2",
+        "fcvt.d.l fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtDLu,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538535D2",
+        "fcvt.d.lu fa0,a1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtSD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: freg(11),
+        },
+        "53851540",
+        "fcvt.s.d fa0,fa1,rne",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FcvtDS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: freg(11),
+        },
+        "53850542",
+        "fcvt.d.s fa0,fa1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FmvXW,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "538505E0",
+        "fmv.x.w a0,fa1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FmvWX,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538505F0",
+        "fmv.w.x fa0,a1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FmvXD,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs: freg(11),
+        },
+        "538505E2",
+        "fmv.x.d a0,fa1",
+    ));
+    insns.push((
+        Inst::FpuRR {
+            alu_op: FpuOPRR::FmvDX,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs: xreg(11),
+        },
+        "538505F2",
+        "fmv.d.x fa0,a1",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FaddS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C500",
+        "fadd.s fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsubS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C508",
+        "fsub.s fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FmulS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C510",
+        "fmul.s fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FdivS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C518",
+        "fdiv.s fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C520",
+        "fsgnj.s fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjnS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C520",
+        "fsgnjn.s fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjxS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "53A5C520",
+        "fsgnjx.s fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FminS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C528",
+        "fmin.s fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FmaxS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C528",
+        "fmax.s fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FeqS,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "53A5C5A0",
+        "feq.s a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FltS,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C5A0",
+        "flt.s a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FleS,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C5A0",
+        "fle.s a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FaddD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C502",
+        "fadd.d fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsubD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C50A",
+        "fsub.d fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FmulD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C512",
+        "fmul.d fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FdivD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C51A",
+        "fdiv.d fa0,fa1,fa2,rne",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C522",
+        "fsgnj.d fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjnD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C522",
+        "fsgnjn.d fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FsgnjxD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "53A5C522",
+        "fsgnjx.d fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FminD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C52A",
+        "fmin.d fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FmaxD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C52A",
+        "fmax.d fa0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FeqD,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "53A5C5A2",
+        "feq.d a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FltD,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5395C5A2",
+        "flt.d a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRR {
+            alu_op: FpuOPRRR::FleD,
+            frm: FRM::RNE,
+            rd: writable_xreg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+        },
+        "5385C5A2",
+        "fle.d a0,fa1,fa2",
+    ));
+    insns.push((
+        Inst::FpuRRRR {
+            alu_op: FpuOPRRRR::FmaddS,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+            rs3: freg(13),
+        },
+        "4385C568",
+        "fmadd.s fa0,fa1,fa2,fa3",
+    ));
+    insns.push((
+        Inst::FpuRRRR {
+            alu_op: FpuOPRRRR::FmaddD,
+            frm: FRM::RNE,
+            rd: writable_freg(10),
+            rs1: freg(11),
+            rs2: freg(12),
+            rs3: freg(13),
+        },
+        "4385C56A",
+        "fmadd.d fa0,fa1,fa2,fa3",
+    ));
+    insns.push((
+        Inst::Mov {
+            rd: writable_xreg(10),
+            rm: xreg(11),
+            ty: I64,
+        },
+        "13850500",
+        "mv a0,a1",
+    ));
+    insns.push((
+        Inst::Mov {
+            rd: writable_freg(10),
+            rm: freg(11),
+            ty: F64,
+        },
+        "5385B522",
+        "fmv.d fa0,fa1",
+    ));
+    insns.push((
+        Inst::Mov {
+            rd: writable_freg(10),
+            rm: freg(11),
+            ty: F32,
+        },
+        "5385B522",
+        "fmv.d fa0,fa1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: false,
+            from_bits: 8,
+            to_bits: 64,
+        },
+        "13F5F50F",
+        "zext.8 a0,a1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: true,
+            from_bits: 8,
+            to_bits: 64,
+        },
+        "1395850313558543",
+        "sext.8 a0,a1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: false,
+            from_bits: 16,
+            to_bits: 64,
+        },
+        "1395050313550503",
+        "zext.16 a0,a1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: true,
+            from_bits: 32,
+            to_bits: 64,
+        },
+        "1B850500",
+        "sext.32 a0,a1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: false,
+            from_bits: 32,
+            to_bits: 64,
+        },
+        "1395050213550502",
+        "zext.32 a0,a1",
+    ));
+    insns.push((
+        Inst::Extend {
+            rd: writable_xreg(10),
+            rn: xreg(11),
+            signed: true,
+            from_bits: 1,
+            to_bits: 64,
+        },
+        "1395F5031355F543",
+        "sext.1 a0,a1",
+    ));
+    insns.push((
+        Inst::Select {
+            rd: writable_xreg(10),
+            cond: xreg(11),
+            rs_true: xreg(12),
+            rs_false: xreg(13),
+        },
+        "63860500130506006F00800013850600",
+        "select a0,a1,a2,a3",
+    ));
+    insns.push((
+        Inst::Select {
+            rd: writable_freg(10),
+            cond: xreg(11),
+            rs_true: freg(12),
+            rs_false: freg(13),
+        },
+        "638605005305C6226F0080005385D622",
+        "select fa0,a1,fa2,fa3",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::AmoaddW,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: true,
+            rl: true,
+        },
+        "2FA5C506",
+        "amoadd.w.aqrl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::AmoswapD,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: true,
+            rl: true,
+        },
+        "2FB5C50E",
+        "amoswap.d.aqrl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::AmoxorW,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: true,
+            rl: true,
+        },
+        "2FA5C526",
+        "amoxor.w.aqrl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::AmoandD,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: true,
+            rl: true,
+        },
+        "2FB5C566",
+        "amoand.d.aqrl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::AmoorW,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: true,
+            rl: true,
+        },
+        "2FA5C546",
+        "amoor.w.aqrl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::LrD,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: zero_reg(),
+            aq: true,
+            rl: false,
+        },
+        "2FB50514",
+        "lr.d.aq a0,(a1)",
+    ));
+    insns.push((
+        Inst::Atomic {
+            op: AtomicOP::ScW,
+            rd: writable_xreg(10),
+            addr: xreg(11),
+            src: xreg(12),
+            aq: false,
+            rl: true,
+        },
+        "2FA5C51A",
+        "sc.w.rl a0,a2,(a1)",
+    ));
+    insns.push((
+        Inst::AtomicCas {
+            ty: I64,
+            rd: writable_xreg(10),
+            tmp: writable_xreg(5),
+            addr: writable_xreg(11),
+            expected: writable_xreg(12),
+            replacement: writable_xreg(13),
+        },
+        "2FB505166316C500AFB2D51EE39A02FE",
+        "atomic_cas.i64 a0,(a1),a2,a3 (tmp t0)",
+    ));
+    insns.push((
+        Inst::Fence {
+            pred: 0b0011,
+            succ: 0b0011,
+        },
+        "0F003003",
+        "fence rw,rw",
+    ));
+    insns.push((
+        Inst::Fence {
+            pred: 0b0010,
+            succ: 0b0011,
+        },
+        "0F003002",
+        "fence r,rw",
+    ));
+    insns.push((Inst::ClearFflags, "73101000", "fsflags zero"));
+    insns.push((
+        Inst::ReadFflags {
+            rd: writable_xreg(10),
+        },
+        "73251000",
+        "frflags a0",
+    ));
+    insns.push((
+        Inst::Jal {
+            dest: BranchTarget::ResolvedOffset(64),
+        },
+        "6F000004",
+        "j 64",
+    ));
+    insns.push((
+        Inst::Jal {
+            dest: BranchTarget::ResolvedOffset(-2048),
+        },
+        "6FF01F80",
+        "j -2048",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::Equal,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6300B5046F000008",
+        "beq a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::NotEqual,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6310B5046F000008",
+        "bne a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::SignedLessThan,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6340B5046F000008",
+        "blt a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::SignedGreaterThanOrEqual,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6350B5046F000008",
+        "bge a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::SignedGreaterThan,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "63C0A5046F000008",
+        "blt a1,a0,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::SignedLessThanOrEqual,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "63D0A5046F000008",
+        "bge a1,a0,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::UnsignedLessThan,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6360B5046F000008",
+        "bltu a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::UnsignedGreaterThanOrEqual,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "6370B5046F000008",
+        "bgeu a0,a1,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::UnsignedGreaterThan,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "63E0A5046F000008",
+        "bltu a1,a0,64 ; j 128",
+    ));
+    insns.push((
+        Inst::CondBr {
+            taken: BranchTarget::ResolvedOffset(64),
+            not_taken: BranchTarget::ResolvedOffset(128),
+            kind: IntegerCompare {
+                kind: IntCC::UnsignedLessThanOrEqual,
+                rs1: xreg(10),
+                rs2: xreg(11),
+            },
+        },
+        "63F0A5046F000008",
+        "bgeu a1,a0,64 ; j 128",
+    ));
+    insns.push((
+        Inst::TrapIf {
+            cond: IntegerCompare {
+                kind: IntCC::Equal,
+                rs1: xreg(10),
+                rs2: zero_reg(),
+            },
+            trap_code: TrapCode::IntegerDivisionByZero,
+        },
+        "63140500731000C0",
+        "bne a0,zero,8 ; unimp int_divz",
+    ));
+    insns.push((
+        Inst::Udf {
+            trap_code: TrapCode::Interrupt,
+        },
+        "731000C0",
+        "unimp interrupt",
+    ));
+    insns.push((Inst::Ebreak, "73001000", "ebreak"));
+    insns.push((
+        Inst::LoadAddr {
+            rd: writable_xreg(10),
+            mem: AMode::RegOffset(xreg(11), 100, I64),
+        },
+        "13854506",
+        "addi a0,a1,100",
+    ));
+    insns.push((
+        Inst::LoadAddr {
+            rd: writable_xreg(10),
+            mem: AMode::SPOffset(0x12345, I64),
+        },
+        "372F01001B0F5F34330FE10113050F00",
+        "lui t5,0x12 ; addiw t5,t5,837 ; add t5,sp,t5 ; mv a0,t5",
+    ));
+
+    let flags = settings::Flags::new(settings::builder());
+    let rru = create_reg_universe(&flags);
+    let emit_info = EmitInfo::new(flags);
+    for (insn, expected_encoding, expected_printing) in insns {
+        println!(
+            "RISC-V64: {:?}, {}, {}",
+            insn, expected_encoding, expected_printing
+        );
+
+        // Check the printed text is as expected.
+        let actual_printing = insn.show_rru(Some(&rru));
+        assert_eq!(expected_printing, actual_printing);
+
+        let mut sink = test_utils::TestCodeSink::new();
+        let mut buffer = MachBuffer::new();
+        insn.emit(&mut buffer, &emit_info, &mut Default::default());
+        let buffer = buffer.finish();
+        buffer.emit(&mut sink);
+        let actual_encoding = &sink.stringify();
+        assert_eq!(expected_encoding, actual_encoding);
+    }
+}
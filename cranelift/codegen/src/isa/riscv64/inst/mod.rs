@@ -0,0 +1,1845 @@
+//! This module defines riscv64-specific machine instruction types.
+
+// Some variants are not constructed, but we still want them as options in the future.
+#![allow(dead_code)]
+
+use crate::binemit::CodeOffset;
+use crate::ir::types::{B1, B16, B32, B64, B8, F32, F64, FFLAGS, I16, I32, I64, I8, IFLAGS, R64};
+use crate::ir::{ExternalName, Opcode, TrapCode, Type};
+use crate::isa::CallConv;
+use crate::machinst::*;
+use crate::{settings, CodegenError, CodegenResult};
+
+use regalloc::{PrettyPrint, RealRegUniverse, Reg, RegClass, SpillSlot, VirtualReg, Writable};
+use regalloc::{RegUsageCollector, RegUsageMapper};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use smallvec::{smallvec, SmallVec};
+use std::string::{String, ToString};
+
+pub mod regs;
+pub use self::regs::*;
+pub mod args;
+pub use self::args::*;
+pub mod emit;
+pub use self::emit::*;
+pub mod unwind;
+
+#[cfg(test)]
+mod emit_tests;
+
+//=============================================================================
+// Instructions (top level): definition
+
+/// An integer ALU operation with two register sources and a register destination (R-type).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AluOPRRR {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    /// 32-bit add; the result is sign-extended to 64 bits.
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    // M extension.
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
+}
+
+/// An integer ALU operation with a register source, a 12-bit signed immediate and a register
+/// destination (I-type). Shifts take their shift amount in the low bits of the immediate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AluOPRRI {
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
+}
+
+/// A load from memory into an integer or floating-point register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoadOP {
+    Lb,
+    Lh,
+    Lw,
+    Ld,
+    Lbu,
+    Lhu,
+    Lwu,
+    Flw,
+    Fld,
+}
+
+/// A store to memory from an integer or floating-point register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StoreOP {
+    Sb,
+    Sh,
+    Sw,
+    Sd,
+    Fsw,
+    Fsd,
+}
+
+/// A floating-point operation with one register source and a register destination. Conversions
+/// and moves between the register files are included here; the destination or source may be an
+/// integer register as implied by the operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FpuOPRR {
+    FsqrtS,
+    FsqrtD,
+    /// f32 to i32.
+    FcvtWS,
+    FcvtWuS,
+    FcvtLS,
+    FcvtLuS,
+    /// f64 to i32.
+    FcvtWD,
+    FcvtWuD,
+    FcvtLD,
+    FcvtLuD,
+    /// i32 to f32.
+    FcvtSW,
+    FcvtSWu,
+    FcvtSL,
+    FcvtSLu,
+    /// i32 to f64.
+    FcvtDW,
+    FcvtDWu,
+    FcvtDL,
+    FcvtDLu,
+    /// f64 to f32.
+    FcvtSD,
+    /// f32 to f64.
+    FcvtDS,
+    /// Move the bits of an f32 to an integer register (sign-extended).
+    FmvXW,
+    /// Move the low 32 bits of an integer register to an f32 (NaN-boxed).
+    FmvWX,
+    FmvXD,
+    FmvDX,
+}
+
+/// A floating-point operation with two register sources and a register destination. The
+/// comparisons write 0 or 1 to an integer register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FpuOPRRR {
+    FaddS,
+    FsubS,
+    FmulS,
+    FdivS,
+    FsgnjS,
+    FsgnjnS,
+    FsgnjxS,
+    FminS,
+    FmaxS,
+    FeqS,
+    FltS,
+    FleS,
+    FaddD,
+    FsubD,
+    FmulD,
+    FdivD,
+    FsgnjD,
+    FsgnjnD,
+    FsgnjxD,
+    FminD,
+    FmaxD,
+    FeqD,
+    FltD,
+    FleD,
+}
+
+/// A fused floating-point operation with three register sources and a register destination.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FpuOPRRRR {
+    /// rd = rs1 * rs2 + rs3
+    FmaddS,
+    FmaddD,
+}
+
+/// An atomic memory operation (A extension).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AtomicOP {
+    LrW,
+    ScW,
+    AmoswapW,
+    AmoaddW,
+    AmoxorW,
+    AmoandW,
+    AmoorW,
+    AmominW,
+    AmomaxW,
+    AmominuW,
+    AmomaxuW,
+    LrD,
+    ScD,
+    AmoswapD,
+    AmoaddD,
+    AmoxorD,
+    AmoandD,
+    AmoorD,
+    AmominD,
+    AmomaxD,
+    AmominuD,
+    AmomaxuD,
+}
+
+/// A floating-point rounding operation, expanded into a sequence at emission time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FloatRoundOP {
+    Ceil,
+    Floor,
+    Trunc,
+    Nearest,
+}
+
+impl FloatRoundOP {
+    /// The rounding mode implementing this operation.
+    pub fn frm(self) -> FRM {
+        match self {
+            FloatRoundOP::Ceil => FRM::RUP,
+            FloatRoundOP::Floor => FRM::RDN,
+            FloatRoundOP::Trunc => FRM::RTZ,
+            FloatRoundOP::Nearest => FRM::RNE,
+        }
+    }
+}
+
+/// Additional information for (direct) Call instructions, left out of line to lower the size of
+/// the Inst enum.
+#[derive(Clone, Debug)]
+pub struct CallInfo {
+    pub dest: ExternalName,
+    pub uses: Vec<Reg>,
+    pub defs: Vec<Writable<Reg>>,
+    pub opcode: Opcode,
+    pub caller_callconv: CallConv,
+    pub callee_callconv: CallConv,
+}
+
+/// Additional information for CallInd instructions, left out of line to lower the size of the Inst
+/// enum.
+#[derive(Clone, Debug)]
+pub struct CallIndInfo {
+    pub rn: Reg,
+    pub uses: Vec<Reg>,
+    pub defs: Vec<Writable<Reg>>,
+    pub opcode: Opcode,
+    pub caller_callconv: CallConv,
+    pub callee_callconv: CallConv,
+}
+
+/// Additional information for BrTable instructions, left out of line to lower the size of the
+/// Inst enum.
+#[derive(Clone, Debug)]
+pub struct BrTableInfo {
+    pub targets: Vec<BranchTarget>,
+    pub default_target: BranchTarget,
+    pub targets_for_term: Vec<MachLabel>, // needed for MachTerminator.
+}
+
+/// Instruction formats.
+#[derive(Clone, Debug)]
+pub enum Inst {
+    /// A no-op of zero size.
+    Nop0,
+
+    /// A no-op that is one instruction large.
+    Nop4,
+
+    /// Load the upper 20 bits of a register, sign-extending bit 31.
+    Lui { rd: Writable<Reg>, imm20: u32 },
+
+    /// An ALU operation with two register sources and a register destination.
+    AluRRR {
+        alu_op: AluOPRRR,
+        rd: Writable<Reg>,
+        rs1: Reg,
+        rs2: Reg,
+    },
+
+    /// An ALU operation with a register source and an immediate-12 source, and a register
+    /// destination.
+    AluRRImm12 {
+        alu_op: AluOPRRI,
+        rd: Writable<Reg>,
+        rs: Reg,
+        imm12: Imm12,
+    },
+
+    /// A load.
+    Load {
+        op: LoadOP,
+        rd: Writable<Reg>,
+        from: AMode,
+    },
+
+    /// A store.
+    Store { op: StoreOP, src: Reg, to: AMode },
+
+    /// A floating-point unary operation or conversion.
+    FpuRR {
+        alu_op: FpuOPRR,
+        frm: FRM,
+        rd: Writable<Reg>,
+        rs: Reg,
+    },
+
+    /// A floating-point binary operation or comparison.
+    FpuRRR {
+        alu_op: FpuOPRRR,
+        frm: FRM,
+        rd: Writable<Reg>,
+        rs1: Reg,
+        rs2: Reg,
+    },
+
+    /// A floating-point fused multiply-add.
+    FpuRRRR {
+        alu_op: FpuOPRRRR,
+        frm: FRM,
+        rd: Writable<Reg>,
+        rs1: Reg,
+        rs2: Reg,
+        rs3: Reg,
+    },
+
+    /// A register-to-register move, within either register class.
+    Mov {
+        rd: Writable<Reg>,
+        rm: Reg,
+        ty: Type,
+    },
+
+    /// A sign- or zero-extend operation. The value is always extended to 64 bits.
+    Extend {
+        rd: Writable<Reg>,
+        rn: Reg,
+        signed: bool,
+        from_bits: u8,
+        to_bits: u8,
+    },
+
+    /// A conditional select: `rd = if cond != 0 { rs_true } else { rs_false }`, emitted as a short
+    /// branch sequence.
+    Select {
+        rd: Writable<Reg>,
+        cond: Reg,
+        rs_true: Reg,
+        rs_false: Reg,
+    },
+
+    /// An atomic memory operation (`amo*` or `lr`/`sc`) on the address in `addr`.
+    Atomic {
+        op: AtomicOP,
+        rd: Writable<Reg>,
+        addr: Reg,
+        src: Reg,
+        aq: bool,
+        rl: bool,
+    },
+
+    /// An atomic compare-and-swap, as an `lr`/`sc` loop:
+    ///
+    ///   again:
+    ///     lr.{w,d}.aqrl rd, (addr)
+    ///     bne rd, expected, out
+    ///     sc.{w,d}.aqrl tmp, replacement, (addr)
+    ///     bnez tmp, again
+    ///   out:
+    ///
+    /// The inputs are read again after `rd` is written, so they are modified registers rather
+    /// than uses, which keeps the register allocator from assigning them the same register as
+    /// `rd` or `tmp`. For `I32`, `expected` must hold a sign-extended value.
+    AtomicCas {
+        ty: Type,
+        rd: Writable<Reg>,
+        tmp: Writable<Reg>,
+        addr: Writable<Reg>,
+        expected: Writable<Reg>,
+        replacement: Writable<Reg>,
+    },
+
+    /// A memory fence. `pred` and `succ` hold the I/O/R/W bits (in that order, from bit 3 down to
+    /// bit 0) of the predecessor and successor sets.
+    Fence { pred: u8, succ: u8 },
+
+    /// Clear the accrued floating-point exception flags.
+    ClearFflags,
+
+    /// Read the accrued floating-point exception flags.
+    ReadFflags { rd: Writable<Reg> },
+
+    /// A machine call instruction. N.B.: this allows only a +/- 2 GiB offset (it uses a relocation
+    /// of type `Reloc::RiscvCallPlt`); for longer-range calls, use a `CallInd` with a
+    /// `LoadExtName` to load the target address.
+    Call { info: Box<CallInfo> },
+
+    /// A machine indirect-call instruction.
+    CallInd { info: Box<CallIndInfo> },
+
+    // ---- branches (exactly one must appear at end of BB) ----
+    /// A machine return instruction.
+    Ret,
+
+    /// A placeholder instruction, generating no code, meaning that a function epilogue must be
+    /// inserted there.
+    EpiloguePlaceholder,
+
+    /// An unconditional branch.
+    Jal { dest: BranchTarget },
+
+    /// A conditional branch. Contains two targets; at emission time, both are emitted, but
+    /// the MachBuffer knows to truncate the trailing branch if fallthrough. We optimize the
+    /// choice of taken/not_taken (inverting the branch polarity as needed) based on the
+    /// fallthrough at the time of lowering.
+    CondBr {
+        taken: BranchTarget,
+        not_taken: BranchTarget,
+        kind: IntegerCompare,
+    },
+
+    /// A conditional trap: execute a `unimp` if the condition is true. This is
+    /// one VCode instruction because it uses embedded control flow; it is
+    /// logically a single-in, single-out region, but needs to appear as one
+    /// unit to the register allocator.
+    ///
+    /// The `IntegerCompare` gives the conditional-branch condition that will
+    /// *execute* the embedded `unimp`. The emitted code will branch over it if
+    /// the condition is false.
+    TrapIf {
+        cond: IntegerCompare,
+        trap_code: TrapCode,
+    },
+
+    /// An instruction guaranteed to always be undefined and to trigger an illegal instruction at
+    /// runtime.
+    Udf { trap_code: TrapCode },
+
+    /// A breakpoint.
+    Ebreak,
+
+    /// Jump-table sequence, as one compound instruction (see note in lower_inst.rs for rationale).
+    BrTable {
+        index: Reg,
+        tmp1: Writable<Reg>,
+        tmp2: Writable<Reg>,
+        info: Box<BrTableInfo>,
+    },
+
+    /// Load an inline symbol reference.
+    LoadExtName {
+        rd: Writable<Reg>,
+        name: Box<ExternalName>,
+        offset: i64,
+    },
+
+    /// Load address referenced by `mem` into `rd`.
+    LoadAddr { rd: Writable<Reg>, mem: AMode },
+
+    /// Round a floating-point value to an integral value, as a compound sequence (see `emit`).
+    /// `rd` may be the same register as `rs`.
+    FloatRound {
+        op: FloatRoundOP,
+        ty: Type,
+        rd: Writable<Reg>,
+        rs: Reg,
+        int_tmp: Writable<Reg>,
+        f_tmp: Writable<Reg>,
+    },
+
+    /// Marker, no-op in generated code: SP "virtual offset" is adjusted. This
+    /// controls how AMode::NominalSPOffset args are lowered.
+    VirtualSPOffsetAdj { offset: i64 },
+
+    /// Meta-insn, no-op in generated code: emit constant/branch veneer island
+    /// at this point (with a guard jump around it) if less than the needed
+    /// space is available before the next branch deadline. See the `MachBuffer`
+    /// implementation in `machinst/buffer.rs` for the overall algorithm.
+    EmitIsland { needed_space: CodeOffset },
+}
+
+#[test]
+fn inst_size_test() {
+    // This test will help with unintentionally growing the size
+    // of the Inst enum.
+    assert_eq!(32, std::mem::size_of::<Inst>());
+}
+
+impl Inst {
+    /// Create an instruction that loads a 64-bit integer constant.
+    ///
+    /// Values that fit in 32 bits use `lui`/`addiw`; larger values are built recursively from
+    /// their upper bits, then shifted into place and completed with an `addi`.
+    pub fn load_constant(rd: Writable<Reg>, value: u64) -> SmallVec<[Inst; 4]> {
+        let value = value as i64;
+        if let Some(imm12) = Imm12::maybe_from_i64(value) {
+            return smallvec![Inst::AluRRImm12 {
+                alu_op: AluOPRRI::Addi,
+                rd,
+                rs: zero_reg(),
+                imm12,
+            }];
+        }
+
+        if value == (value as i32) as i64 {
+            let (imm20, lo12) = split_hi20_lo12(value as i32);
+            let mut insts = smallvec![Inst::Lui { rd, imm20 }];
+            if lo12.as_i64() != 0 {
+                insts.push(Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addiw,
+                    rd,
+                    rs: rd.to_reg(),
+                    imm12: lo12,
+                });
+            }
+            return insts;
+        }
+
+        // Materialize the value without its sign-extended low 12 bits, then add those.
+        let lo12 = (value << 52) >> 52;
+        let hi = value.wrapping_sub(lo12) >> 12;
+        let shift = 12 + hi.trailing_zeros();
+        let hi = hi >> (shift - 12);
+        let mut insts = Inst::load_constant(rd, hi as u64);
+        insts.push(Inst::AluRRImm12 {
+            alu_op: AluOPRRI::Slli,
+            rd,
+            rs: rd.to_reg(),
+            imm12: Imm12::maybe_from_i64(shift as i64).unwrap(),
+        });
+        if lo12 != 0 {
+            insts.push(Inst::AluRRImm12 {
+                alu_op: AluOPRRI::Addi,
+                rd,
+                rs: rd.to_reg(),
+                imm12: Imm12::maybe_from_i64(lo12).unwrap(),
+            });
+        }
+        insts
+    }
+
+    /// Create instructions that load a 32-bit floating-point constant.
+    pub fn load_fp_constant32<F: FnMut(RegClass, Type) -> Writable<Reg>>(
+        rd: Writable<Reg>,
+        value: u32,
+        mut alloc_tmp: F,
+    ) -> SmallVec<[Inst; 4]> {
+        let tmp = alloc_tmp(RegClass::I64, I64);
+        let mut insts = Inst::load_constant(tmp, value as u64);
+        insts.push(Inst::FpuRR {
+            alu_op: FpuOPRR::FmvWX,
+            frm: FRM::RNE,
+            rd,
+            rs: tmp.to_reg(),
+        });
+        insts
+    }
+
+    /// Create instructions that load a 64-bit floating-point constant.
+    pub fn load_fp_constant64<F: FnMut(RegClass, Type) -> Writable<Reg>>(
+        rd: Writable<Reg>,
+        value: u64,
+        mut alloc_tmp: F,
+    ) -> SmallVec<[Inst; 4]> {
+        let tmp = alloc_tmp(RegClass::I64, I64);
+        let mut insts = Inst::load_constant(tmp, value);
+        insts.push(Inst::FpuRR {
+            alu_op: FpuOPRR::FmvDX,
+            frm: FRM::RNE,
+            rd,
+            rs: tmp.to_reg(),
+        });
+        insts
+    }
+
+    /// Generic constructor for a load (zero-extending where appropriate).
+    pub fn gen_load(into_reg: Writable<Reg>, mem: AMode, ty: Type) -> Inst {
+        let op = match ty {
+            B1 | B8 | I8 => LoadOP::Lbu,
+            B16 | I16 => LoadOP::Lhu,
+            B32 | I32 => LoadOP::Lwu,
+            B64 | I64 | R64 => LoadOP::Ld,
+            F32 => LoadOP::Flw,
+            F64 => LoadOP::Fld,
+            _ => unimplemented!("gen_load({})", ty),
+        };
+        Inst::Load {
+            op,
+            rd: into_reg,
+            from: mem,
+        }
+    }
+
+    /// Generic constructor for a store.
+    pub fn gen_store(mem: AMode, from_reg: Reg, ty: Type) -> Inst {
+        let op = match ty {
+            B1 | B8 | I8 => StoreOP::Sb,
+            B16 | I16 => StoreOP::Sh,
+            B32 | I32 => StoreOP::Sw,
+            B64 | I64 | R64 => StoreOP::Sd,
+            F32 => StoreOP::Fsw,
+            F64 => StoreOP::Fsd,
+            _ => unimplemented!("gen_store({})", ty),
+        };
+        Inst::Store {
+            op,
+            src: from_reg,
+            to: mem,
+        }
+    }
+}
+
+//=============================================================================
+// Instructions: get_regs
+
+fn memarg_regs(memarg: &AMode, collector: &mut RegUsageCollector) {
+    match memarg {
+        &AMode::RegOffset(r, ..) => {
+            collector.add_use(r);
+        }
+        &AMode::FPOffset(..) => {
+            collector.add_use(fp_reg());
+        }
+        &AMode::SPOffset(..) | &AMode::NominalSPOffset(..) => {
+            collector.add_use(stack_reg());
+        }
+    }
+}
+
+fn riscv64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
+    match inst {
+        &Inst::AluRRR { rd, rs1, rs2, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rs1);
+            collector.add_use(rs2);
+        }
+        &Inst::AluRRImm12 { rd, rs, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rs);
+        }
+        &Inst::Lui { rd, .. } => {
+            collector.add_def(rd);
+        }
+        &Inst::Load { rd, ref from, .. } => {
+            collector.add_def(rd);
+            memarg_regs(from, collector);
+        }
+        &Inst::Store { src, ref to, .. } => {
+            collector.add_use(src);
+            memarg_regs(to, collector);
+        }
+        &Inst::FpuRR { rd, rs, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rs);
+        }
+        &Inst::FpuRRR { rd, rs1, rs2, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rs1);
+            collector.add_use(rs2);
+        }
+        &Inst::FpuRRRR {
+            rd, rs1, rs2, rs3, ..
+        } => {
+            collector.add_def(rd);
+            collector.add_use(rs1);
+            collector.add_use(rs2);
+            collector.add_use(rs3);
+        }
+        &Inst::Mov { rd, rm, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rm);
+        }
+        &Inst::Extend { rd, rn, .. } => {
+            collector.add_def(rd);
+            collector.add_use(rn);
+        }
+        &Inst::Select {
+            rd,
+            cond,
+            rs_true,
+            rs_false,
+        } => {
+            collector.add_def(rd);
+            collector.add_use(cond);
+            collector.add_use(rs_true);
+            collector.add_use(rs_false);
+        }
+        &Inst::Atomic { rd, addr, src, .. } => {
+            collector.add_def(rd);
+            collector.add_use(addr);
+            collector.add_use(src);
+        }
+        &Inst::AtomicCas {
+            rd,
+            tmp,
+            addr,
+            expected,
+            replacement,
+            ..
+        } => {
+            collector.add_def(rd);
+            collector.add_def(tmp);
+            collector.add_mod(addr);
+            collector.add_mod(expected);
+            collector.add_mod(replacement);
+        }
+        &Inst::Fence { .. } | &Inst::ClearFflags => {}
+        &Inst::ReadFflags { rd } => {
+            collector.add_def(rd);
+        }
+        &Inst::Call { ref info, .. } => {
+            collector.add_uses(&*info.uses);
+            collector.add_defs(&*info.defs);
+        }
+        &Inst::CallInd { ref info, .. } => {
+            collector.add_uses(&*info.uses);
+            collector.add_defs(&*info.defs);
+            collector.add_use(info.rn);
+        }
+        &Inst::CondBr { ref kind, .. } | &Inst::TrapIf { cond: ref kind, .. } => {
+            collector.add_use(kind.rs1);
+            collector.add_use(kind.rs2);
+        }
+        &Inst::BrTable {
+            index, tmp1, tmp2, ..
+        } => {
+            collector.add_use(index);
+            collector.add_def(tmp1);
+            collector.add_def(tmp2);
+        }
+        &Inst::LoadExtName { rd, .. } => {
+            collector.add_def(rd);
+        }
+        &Inst::LoadAddr { rd, ref mem } => {
+            collector.add_def(rd);
+            memarg_regs(mem, collector);
+        }
+        &Inst::FloatRound {
+            rd,
+            rs,
+            int_tmp,
+            f_tmp,
+            ..
+        } => {
+            collector.add_def(rd);
+            collector.add_use(rs);
+            collector.add_def(int_tmp);
+            collector.add_def(f_tmp);
+        }
+        &Inst::Nop0
+        | &Inst::Nop4
+        | &Inst::Ret
+        | &Inst::EpiloguePlaceholder
+        | &Inst::Jal { .. }
+        | &Inst::Udf { .. }
+        | &Inst::Ebreak
+        | &Inst::VirtualSPOffsetAdj { .. }
+        | &Inst::EmitIsland { .. } => {}
+    }
+}
+
+//=============================================================================
+// Instructions: map_regs
+
+fn riscv64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
+    fn map_use<RUM: RegUsageMapper>(m: &RUM, r: &mut Reg) {
+        if r.is_virtual() {
+            let new = m.get_use(r.to_virtual_reg()).unwrap().to_reg();
+            *r = new;
+        }
+    }
+
+    fn map_def<RUM: RegUsageMapper>(m: &RUM, r: &mut Writable<Reg>) {
+        if r.to_reg().is_virtual() {
+            let new = m.get_def(r.to_reg().to_virtual_reg()).unwrap().to_reg();
+            *r = Writable::from_reg(new);
+        }
+    }
+
+    fn map_mod<RUM: RegUsageMapper>(m: &RUM, r: &mut Writable<Reg>) {
+        if r.to_reg().is_virtual() {
+            let new = m.get_mod(r.to_reg().to_virtual_reg()).unwrap().to_reg();
+            *r = Writable::from_reg(new);
+        }
+    }
+
+    fn map_mem<RUM: RegUsageMapper>(m: &RUM, mem: &mut AMode) {
+        match mem {
+            &mut AMode::RegOffset(ref mut r, ..) => map_use(m, r),
+            &mut AMode::SPOffset(..)
+            | &mut AMode::FPOffset(..)
+            | &mut AMode::NominalSPOffset(..) => {}
+        }
+    }
+
+    match inst {
+        &mut Inst::AluRRR {
+            ref mut rd,
+            ref mut rs1,
+            ref mut rs2,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs1);
+            map_use(mapper, rs2);
+        }
+        &mut Inst::AluRRImm12 {
+            ref mut rd,
+            ref mut rs,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs);
+        }
+        &mut Inst::Lui { ref mut rd, .. } => {
+            map_def(mapper, rd);
+        }
+        &mut Inst::Load {
+            ref mut rd,
+            ref mut from,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_mem(mapper, from);
+        }
+        &mut Inst::Store {
+            ref mut src,
+            ref mut to,
+            ..
+        } => {
+            map_use(mapper, src);
+            map_mem(mapper, to);
+        }
+        &mut Inst::FpuRR {
+            ref mut rd,
+            ref mut rs,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs);
+        }
+        &mut Inst::FpuRRR {
+            ref mut rd,
+            ref mut rs1,
+            ref mut rs2,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs1);
+            map_use(mapper, rs2);
+        }
+        &mut Inst::FpuRRRR {
+            ref mut rd,
+            ref mut rs1,
+            ref mut rs2,
+            ref mut rs3,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs1);
+            map_use(mapper, rs2);
+            map_use(mapper, rs3);
+        }
+        &mut Inst::Mov {
+            ref mut rd,
+            ref mut rm,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rm);
+        }
+        &mut Inst::Extend {
+            ref mut rd,
+            ref mut rn,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rn);
+        }
+        &mut Inst::Select {
+            ref mut rd,
+            ref mut cond,
+            ref mut rs_true,
+            ref mut rs_false,
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, cond);
+            map_use(mapper, rs_true);
+            map_use(mapper, rs_false);
+        }
+        &mut Inst::Atomic {
+            ref mut rd,
+            ref mut addr,
+            ref mut src,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, addr);
+            map_use(mapper, src);
+        }
+        &mut Inst::AtomicCas {
+            ref mut rd,
+            ref mut tmp,
+            ref mut addr,
+            ref mut expected,
+            ref mut replacement,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_def(mapper, tmp);
+            map_mod(mapper, addr);
+            map_mod(mapper, expected);
+            map_mod(mapper, replacement);
+        }
+        &mut Inst::Fence { .. } | &mut Inst::ClearFflags => {}
+        &mut Inst::ReadFflags { ref mut rd } => {
+            map_def(mapper, rd);
+        }
+        &mut Inst::Call { ref mut info } => {
+            for r in info.uses.iter_mut() {
+                map_use(mapper, r);
+            }
+            for r in info.defs.iter_mut() {
+                map_def(mapper, r);
+            }
+        }
+        &mut Inst::CallInd { ref mut info, .. } => {
+            for r in info.uses.iter_mut() {
+                map_use(mapper, r);
+            }
+            for r in info.defs.iter_mut() {
+                map_def(mapper, r);
+            }
+            map_use(mapper, &mut info.rn);
+        }
+        &mut Inst::CondBr { ref mut kind, .. }
+        | &mut Inst::TrapIf {
+            cond: ref mut kind, ..
+        } => {
+            map_use(mapper, &mut kind.rs1);
+            map_use(mapper, &mut kind.rs2);
+        }
+        &mut Inst::BrTable {
+            ref mut index,
+            ref mut tmp1,
+            ref mut tmp2,
+            ..
+        } => {
+            map_use(mapper, index);
+            map_def(mapper, tmp1);
+            map_def(mapper, tmp2);
+        }
+        &mut Inst::LoadExtName { ref mut rd, .. } => {
+            map_def(mapper, rd);
+        }
+        &mut Inst::LoadAddr {
+            ref mut rd,
+            ref mut mem,
+        } => {
+            map_def(mapper, rd);
+            map_mem(mapper, mem);
+        }
+        &mut Inst::FloatRound {
+            ref mut rd,
+            ref mut rs,
+            ref mut int_tmp,
+            ref mut f_tmp,
+            ..
+        } => {
+            map_def(mapper, rd);
+            map_use(mapper, rs);
+            map_def(mapper, int_tmp);
+            map_def(mapper, f_tmp);
+        }
+        &mut Inst::Nop0
+        | &mut Inst::Nop4
+        | &mut Inst::Ret
+        | &mut Inst::EpiloguePlaceholder
+        | &mut Inst::Jal { .. }
+        | &mut Inst::Udf { .. }
+        | &mut Inst::Ebreak
+        | &mut Inst::VirtualSPOffsetAdj { .. }
+        | &mut Inst::EmitIsland { .. } => {}
+    }
+}
+
+//=============================================================================
+// Instructions: misc functions and external interface
+
+impl MachInst for Inst {
+    type LabelUse = LabelUse;
+
+    fn get_regs(&self, collector: &mut RegUsageCollector) {
+        riscv64_get_regs(self, collector)
+    }
+
+    fn map_regs<RUM: RegUsageMapper>(&mut self, mapper: &RUM) {
+        riscv64_map_regs(self, mapper);
+    }
+
+    fn is_move(&self) -> Option<(Writable<Reg>, Reg)> {
+        match self {
+            &Inst::Mov { rd, rm, .. } => Some((rd, rm)),
+            _ => None,
+        }
+    }
+
+    fn is_epilogue_placeholder(&self) -> bool {
+        if let Inst::EpiloguePlaceholder = self {
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_term<'a>(&'a self) -> MachTerminator<'a> {
+        match self {
+            &Inst::Ret | &Inst::EpiloguePlaceholder => MachTerminator::Ret,
+            &Inst::Jal { dest } => MachTerminator::Uncond(dest.as_label().unwrap()),
+            &Inst::CondBr {
+                taken, not_taken, ..
+            } => MachTerminator::Cond(taken.as_label().unwrap(), not_taken.as_label().unwrap()),
+            &Inst::BrTable { ref info, .. } => MachTerminator::Indirect(&info.targets_for_term[..]),
+            _ => MachTerminator::None,
+        }
+    }
+
+    fn gen_move(to_reg: Writable<Reg>, from_reg: Reg, ty: Type) -> Inst {
+        assert!(ty.bits() <= 64);
+        assert!(to_reg.to_reg().get_class() == from_reg.get_class());
+        Inst::Mov {
+            rd: to_reg,
+            rm: from_reg,
+            ty,
+        }
+    }
+
+    fn gen_constant<F: FnMut(RegClass, Type) -> Writable<Reg>>(
+        to_reg: Writable<Reg>,
+        value: u64,
+        ty: Type,
+        alloc_tmp: F,
+    ) -> SmallVec<[Inst; 4]> {
+        if ty == F64 {
+            Inst::load_fp_constant64(to_reg, value, alloc_tmp)
+        } else if ty == F32 {
+            Inst::load_fp_constant32(to_reg, value as u32, alloc_tmp)
+        } else {
+            // Must be an integer type.
+            debug_assert!(
+                ty == B1
+                    || ty == I8
+                    || ty == B8
+                    || ty == I16
+                    || ty == B16
+                    || ty == I32
+                    || ty == B32
+                    || ty == I64
+                    || ty == B64
+                    || ty == R64
+            );
+            Inst::load_constant(to_reg, value)
+        }
+    }
+
+    fn gen_zero_len_nop() -> Inst {
+        Inst::Nop0
+    }
+
+    fn gen_nop(preferred_size: usize) -> Inst {
+        // We can't give a NOP (or any insn) < 4 bytes.
+        assert!(preferred_size >= 4);
+        Inst::Nop4
+    }
+
+    fn maybe_direct_reload(&self, _reg: VirtualReg, _slot: SpillSlot) -> Option<Inst> {
+        None
+    }
+
+    fn rc_for_type(ty: Type) -> CodegenResult<(&'static [RegClass], &'static [Type])> {
+        match ty {
+            I8 => Ok((&[RegClass::I64], &[I8])),
+            I16 => Ok((&[RegClass::I64], &[I16])),
+            I32 => Ok((&[RegClass::I64], &[I32])),
+            I64 => Ok((&[RegClass::I64], &[I64])),
+            B1 => Ok((&[RegClass::I64], &[B1])),
+            B8 => Ok((&[RegClass::I64], &[B8])),
+            B16 => Ok((&[RegClass::I64], &[B16])),
+            B32 => Ok((&[RegClass::I64], &[B32])),
+            B64 => Ok((&[RegClass::I64], &[B64])),
+            R64 => Ok((&[RegClass::I64], &[R64])),
+            F32 => Ok((&[RegClass::F64], &[F32])),
+            F64 => Ok((&[RegClass::F64], &[F64])),
+            IFLAGS | FFLAGS => Ok((&[RegClass::I64], &[I64])),
+            _ => Err(CodegenError::Unsupported(format!(
+                "Unexpected SSA-value type: {}",
+                ty
+            ))),
+        }
+    }
+
+    fn gen_jump(target: MachLabel) -> Inst {
+        Inst::Jal {
+            dest: BranchTarget::Label(target),
+        }
+    }
+
+    fn reg_universe(flags: &settings::Flags) -> RealRegUniverse {
+        create_reg_universe(flags)
+    }
+
+    fn worst_case_size() -> CodeOffset {
+        // The maximum size, in bytes, of any `Inst`'s emitted code. The longest sequences are a
+        // load or store whose offset needs a full 64-bit constant (up to eight instructions to
+        // build the constant, an add, and the access itself), and the float rounding sequence.
+        //
+        // Note that jump tables handle island insertion separately, so we do not need to account
+        // for them here.
+        44
+    }
+
+    fn ref_type_regclass(_: &settings::Flags) -> RegClass {
+        RegClass::I64
+    }
+}
+
+//=============================================================================
+// Pretty-printing of instructions.
+
+fn mem_finalize_for_show(
+    mem: &AMode,
+    mb_rru: Option<&RealRegUniverse>,
+    state: &EmitState,
+) -> (String, Reg, Imm12) {
+    let (mem_insts, base, imm12) = mem_finalize(mem, state);
+    let mut mem_str = mem_insts
+        .into_iter()
+        .map(|inst| inst.show_rru(mb_rru))
+        .collect::<Vec<_>>()
+        .join(" ; ");
+    if !mem_str.is_empty() {
+        mem_str += " ; ";
+    }
+
+    (mem_str, base, imm12)
+}
+
+impl PrettyPrint for Inst {
+    fn show_rru(&self, mb_rru: Option<&RealRegUniverse>) -> String {
+        self.pretty_print(mb_rru, &mut EmitState::default())
+    }
+}
+
+impl AluOPRRR {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            AluOPRRR::Add => "add",
+            AluOPRRR::Sub => "sub",
+            AluOPRRR::Sll => "sll",
+            AluOPRRR::Slt => "slt",
+            AluOPRRR::Sltu => "sltu",
+            AluOPRRR::Xor => "xor",
+            AluOPRRR::Srl => "srl",
+            AluOPRRR::Sra => "sra",
+            AluOPRRR::Or => "or",
+            AluOPRRR::And => "and",
+            AluOPRRR::Addw => "addw",
+            AluOPRRR::Subw => "subw",
+            AluOPRRR::Sllw => "sllw",
+            AluOPRRR::Srlw => "srlw",
+            AluOPRRR::Sraw => "sraw",
+            AluOPRRR::Mul => "mul",
+            AluOPRRR::Mulh => "mulh",
+            AluOPRRR::Mulhsu => "mulhsu",
+            AluOPRRR::Mulhu => "mulhu",
+            AluOPRRR::Div => "div",
+            AluOPRRR::Divu => "divu",
+            AluOPRRR::Rem => "rem",
+            AluOPRRR::Remu => "remu",
+            AluOPRRR::Mulw => "mulw",
+            AluOPRRR::Divw => "divw",
+            AluOPRRR::Divuw => "divuw",
+            AluOPRRR::Remw => "remw",
+            AluOPRRR::Remuw => "remuw",
+        }
+    }
+}
+
+impl AluOPRRI {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            AluOPRRI::Addi => "addi",
+            AluOPRRI::Slti => "slti",
+            AluOPRRI::Sltiu => "sltiu",
+            AluOPRRI::Xori => "xori",
+            AluOPRRI::Ori => "ori",
+            AluOPRRI::Andi => "andi",
+            AluOPRRI::Slli => "slli",
+            AluOPRRI::Srli => "srli",
+            AluOPRRI::Srai => "srai",
+            AluOPRRI::Addiw => "addiw",
+            AluOPRRI::Slliw => "slliw",
+            AluOPRRI::Srliw => "srliw",
+            AluOPRRI::Sraiw => "sraiw",
+        }
+    }
+}
+
+impl LoadOP {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            LoadOP::Lb => "lb",
+            LoadOP::Lh => "lh",
+            LoadOP::Lw => "lw",
+            LoadOP::Ld => "ld",
+            LoadOP::Lbu => "lbu",
+            LoadOP::Lhu => "lhu",
+            LoadOP::Lwu => "lwu",
+            LoadOP::Flw => "flw",
+            LoadOP::Fld => "fld",
+        }
+    }
+}
+
+impl StoreOP {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            StoreOP::Sb => "sb",
+            StoreOP::Sh => "sh",
+            StoreOP::Sw => "sw",
+            StoreOP::Sd => "sd",
+            StoreOP::Fsw => "fsw",
+            StoreOP::Fsd => "fsd",
+        }
+    }
+}
+
+impl FpuOPRR {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            FpuOPRR::FsqrtS => "fsqrt.s",
+            FpuOPRR::FsqrtD => "fsqrt.d",
+            FpuOPRR::FcvtWS => "fcvt.w.s",
+            FpuOPRR::FcvtWuS => "fcvt.wu.s",
+            FpuOPRR::FcvtLS => "fcvt.l.s",
+            FpuOPRR::FcvtLuS => "fcvt.lu.s",
+            FpuOPRR::FcvtWD => "fcvt.w.d",
+            FpuOPRR::FcvtWuD => "fcvt.wu.d",
+            FpuOPRR::FcvtLD => "fcvt.l.d",
+            FpuOPRR::FcvtLuD => "fcvt.lu.d",
+            FpuOPRR::FcvtSW => "fcvt.s.w",
+            FpuOPRR::FcvtSWu => "fcvt.s.wu",
+            FpuOPRR::FcvtSL => "fcvt.s.l",
+            FpuOPRR::FcvtSLu => "fcvt.s.lu",
+            FpuOPRR::FcvtDW => "fcvt.d.w",
+            FpuOPRR::FcvtDWu => "fcvt.d.wu",
+            FpuOPRR::FcvtDL => "fcvt.d.l",
+            FpuOPRR::FcvtDLu => "fcvt.d.lu",
+            FpuOPRR::FcvtSD => "fcvt.s.d",
+            FpuOPRR::FcvtDS => "fcvt.d.s",
+            FpuOPRR::FmvXW => "fmv.x.w",
+            FpuOPRR::FmvWX => "fmv.w.x",
+            FpuOPRR::FmvXD => "fmv.x.d",
+            FpuOPRR::FmvDX => "fmv.d.x",
+        }
+    }
+
+    /// Does this operation take a rounding mode?
+    pub fn has_frm(self) -> bool {
+        match self {
+            FpuOPRR::FmvXW
+            | FpuOPRR::FmvWX
+            | FpuOPRR::FmvXD
+            | FpuOPRR::FmvDX
+            | FpuOPRR::FcvtDS
+            | FpuOPRR::FcvtDW
+            | FpuOPRR::FcvtDWu => false,
+            _ => true,
+        }
+    }
+}
+
+impl FpuOPRRR {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            FpuOPRRR::FaddS => "fadd.s",
+            FpuOPRRR::FsubS => "fsub.s",
+            FpuOPRRR::FmulS => "fmul.s",
+            FpuOPRRR::FdivS => "fdiv.s",
+            FpuOPRRR::FsgnjS => "fsgnj.s",
+            FpuOPRRR::FsgnjnS => "fsgnjn.s",
+            FpuOPRRR::FsgnjxS => "fsgnjx.s",
+            FpuOPRRR::FminS => "fmin.s",
+            FpuOPRRR::FmaxS => "fmax.s",
+            FpuOPRRR::FeqS => "feq.s",
+            FpuOPRRR::FltS => "flt.s",
+            FpuOPRRR::FleS => "fle.s",
+            FpuOPRRR::FaddD => "fadd.d",
+            FpuOPRRR::FsubD => "fsub.d",
+            FpuOPRRR::FmulD => "fmul.d",
+            FpuOPRRR::FdivD => "fdiv.d",
+            FpuOPRRR::FsgnjD => "fsgnj.d",
+            FpuOPRRR::FsgnjnD => "fsgnjn.d",
+            FpuOPRRR::FsgnjxD => "fsgnjx.d",
+            FpuOPRRR::FminD => "fmin.d",
+            FpuOPRRR::FmaxD => "fmax.d",
+            FpuOPRRR::FeqD => "feq.d",
+            FpuOPRRR::FltD => "flt.d",
+            FpuOPRRR::FleD => "fle.d",
+        }
+    }
+
+    /// Does this operation take a rounding mode?
+    pub fn has_frm(self) -> bool {
+        match self {
+            FpuOPRRR::FaddS
+            | FpuOPRRR::FsubS
+            | FpuOPRRR::FmulS
+            | FpuOPRRR::FdivS
+            | FpuOPRRR::FaddD
+            | FpuOPRRR::FsubD
+            | FpuOPRRR::FmulD
+            | FpuOPRRR::FdivD => true,
+            _ => false,
+        }
+    }
+}
+
+impl FpuOPRRRR {
+    /// The assembler mnemonic of this operation.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            FpuOPRRRR::FmaddS => "fmadd.s",
+            FpuOPRRRR::FmaddD => "fmadd.d",
+        }
+    }
+}
+
+impl AtomicOP {
+    /// The assembler mnemonic of this operation, without ordering suffixes.
+    pub fn op_name(self) -> &'static str {
+        match self {
+            AtomicOP::LrW => "lr.w",
+            AtomicOP::ScW => "sc.w",
+            AtomicOP::AmoswapW => "amoswap.w",
+            AtomicOP::AmoaddW => "amoadd.w",
+            AtomicOP::AmoxorW => "amoxor.w",
+            AtomicOP::AmoandW => "amoand.w",
+            AtomicOP::AmoorW => "amoor.w",
+            AtomicOP::AmominW => "amomin.w",
+            AtomicOP::AmomaxW => "amomax.w",
+            AtomicOP::AmominuW => "amominu.w",
+            AtomicOP::AmomaxuW => "amomaxu.w",
+            AtomicOP::LrD => "lr.d",
+            AtomicOP::ScD => "sc.d",
+            AtomicOP::AmoswapD => "amoswap.d",
+            AtomicOP::AmoaddD => "amoadd.d",
+            AtomicOP::AmoxorD => "amoxor.d",
+            AtomicOP::AmoandD => "amoand.d",
+            AtomicOP::AmoorD => "amoor.d",
+            AtomicOP::AmominD => "amomin.d",
+            AtomicOP::AmomaxD => "amomax.d",
+            AtomicOP::AmominuD => "amominu.d",
+            AtomicOP::AmomaxuD => "amomaxu.d",
+        }
+    }
+}
+
+fn show_branch(kind: IntegerCompare, mb_rru: Option<&RealRegUniverse>) -> String {
+    let (funct3, rs1, rs2) = kind.to_branch();
+    let op = match funct3 {
+        0b000 => "beq",
+        0b001 => "bne",
+        0b100 => "blt",
+        0b101 => "bge",
+        0b110 => "bltu",
+        0b111 => "bgeu",
+        _ => unreachable!(),
+    };
+    format!("{} {},{}", op, rs1.show_rru(mb_rru), rs2.show_rru(mb_rru))
+}
+
+fn show_fence_set(bits: u8) -> String {
+    let mut s = String::new();
+    for &(bit, c) in &[(8, 'i'), (4, 'o'), (2, 'r'), (1, 'w')] {
+        if bits & bit != 0 {
+            s.push(c);
+        }
+    }
+    s
+}
+
+impl Inst {
+    fn print_with_state(&self, mb_rru: Option<&RealRegUniverse>, state: &mut EmitState) -> String {
+        match self {
+            &Inst::Nop0 => "nop-zero-len".to_string(),
+            &Inst::Nop4 => "nop".to_string(),
+            &Inst::Lui { rd, imm20 } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                format!("lui {},{:#x}", rd, imm20)
+            }
+            &Inst::AluRRR {
+                alu_op,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs1 = rs1.show_rru(mb_rru);
+                let rs2 = rs2.show_rru(mb_rru);
+                format!("{} {},{},{}", alu_op.op_name(), rd, rs1, rs2)
+            }
+            &Inst::AluRRImm12 {
+                alu_op,
+                rd,
+                rs,
+                imm12,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs = rs.show_rru(mb_rru);
+                if alu_op == AluOPRRI::Addi && imm12.as_i64() == 0 {
+                    format!("mv {},{}", rd, rs)
+                } else {
+                    format!("{} {},{},{}", alu_op.op_name(), rd, rs, imm12.as_i64())
+                }
+            }
+            &Inst::Load { op, rd, ref from } => {
+                let (mem_str, base, imm12) = mem_finalize_for_show(from, mb_rru, state);
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let base = base.show_rru(mb_rru);
+                format!(
+                    "{}{} {},{}({})",
+                    mem_str,
+                    op.op_name(),
+                    rd,
+                    imm12.as_i64(),
+                    base
+                )
+            }
+            &Inst::Store { op, src, ref to } => {
+                let (mem_str, base, imm12) = mem_finalize_for_show(to, mb_rru, state);
+                let src = src.show_rru(mb_rru);
+                let base = base.show_rru(mb_rru);
+                format!(
+                    "{}{} {},{}({})",
+                    mem_str,
+                    op.op_name(),
+                    src,
+                    imm12.as_i64(),
+                    base
+                )
+            }
+            &Inst::FpuRR {
+                alu_op,
+                frm,
+                rd,
+                rs,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs = rs.show_rru(mb_rru);
+                if alu_op.has_frm() && frm != FRM::DYN {
+                    format!(
+                        "{} {},{},{}",
+                        alu_op.op_name(),
+                        rd,
+                        rs,
+                        frm.show_rru(mb_rru)
+                    )
+                } else {
+                    format!("{} {},{}", alu_op.op_name(), rd, rs)
+                }
+            }
+            &Inst::FpuRRR {
+                alu_op,
+                frm,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs1 = rs1.show_rru(mb_rru);
+                let rs2 = rs2.show_rru(mb_rru);
+                if alu_op.has_frm() && frm != FRM::DYN {
+                    format!(
+                        "{} {},{},{},{}",
+                        alu_op.op_name(),
+                        rd,
+                        rs1,
+                        rs2,
+                        frm.show_rru(mb_rru)
+                    )
+                } else {
+                    format!("{} {},{},{}", alu_op.op_name(), rd, rs1, rs2)
+                }
+            }
+            &Inst::FpuRRRR {
+                alu_op,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                ..
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs1 = rs1.show_rru(mb_rru);
+                let rs2 = rs2.show_rru(mb_rru);
+                let rs3 = rs3.show_rru(mb_rru);
+                format!("{} {},{},{},{}", alu_op.op_name(), rd, rs1, rs2, rs3)
+            }
+            &Inst::Mov { rd, rm, .. } => {
+                let op = if rm.get_class() == RegClass::I64 {
+                    "mv"
+                } else {
+                    "fmv.d"
+                };
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rm = rm.show_rru(mb_rru);
+                format!("{} {},{}", op, rd, rm)
+            }
+            &Inst::Extend {
+                rd,
+                rn,
+                signed,
+                from_bits,
+                ..
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rn = rn.show_rru(mb_rru);
+                let op = if signed { "sext" } else { "zext" };
+                format!("{}.{} {},{}", op, from_bits, rd, rn)
+            }
+            &Inst::Select {
+                rd,
+                cond,
+                rs_true,
+                rs_false,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let cond = cond.show_rru(mb_rru);
+                let rs_true = rs_true.show_rru(mb_rru);
+                let rs_false = rs_false.show_rru(mb_rru);
+                format!("select {},{},{},{}", rd, cond, rs_true, rs_false)
+            }
+            &Inst::Atomic {
+                op,
+                rd,
+                addr,
+                src,
+                aq,
+                rl,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let addr = addr.show_rru(mb_rru);
+                let src = src.show_rru(mb_rru);
+                let order = match (aq, rl) {
+                    (true, true) => ".aqrl",
+                    (true, false) => ".aq",
+                    (false, true) => ".rl",
+                    (false, false) => "",
+                };
+                match op {
+                    AtomicOP::LrW | AtomicOP::LrD => {
+                        format!("{}{} {},({})", op.op_name(), order, rd, addr)
+                    }
+                    _ => format!("{}{} {},{},({})", op.op_name(), order, rd, src, addr),
+                }
+            }
+            &Inst::AtomicCas {
+                ty,
+                rd,
+                tmp,
+                addr,
+                expected,
+                replacement,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let tmp = tmp.to_reg().show_rru(mb_rru);
+                let addr = addr.to_reg().show_rru(mb_rru);
+                let expected = expected.to_reg().show_rru(mb_rru);
+                let replacement = replacement.to_reg().show_rru(mb_rru);
+                format!(
+                    "atomic_cas.{} {},({}),{},{} (tmp {})",
+                    ty, rd, addr, expected, replacement, tmp
+                )
+            }
+            &Inst::Fence { pred, succ } => {
+                format!("fence {},{}", show_fence_set(pred), show_fence_set(succ))
+            }
+            &Inst::ClearFflags => "fsflags zero".to_string(),
+            &Inst::ReadFflags { rd } => format!("frflags {}", rd.to_reg().show_rru(mb_rru)),
+            &Inst::Call { .. } => format!("call 0"),
+            &Inst::CallInd { ref info, .. } => {
+                let rn = info.rn.show_rru(mb_rru);
+                format!("jalr {}", rn)
+            }
+            &Inst::Ret => "ret".to_string(),
+            &Inst::EpiloguePlaceholder => "epilogue placeholder".to_string(),
+            &Inst::Jal { ref dest } => {
+                let dest = dest.show_rru(mb_rru);
+                format!("j {}", dest)
+            }
+            &Inst::CondBr {
+                ref taken,
+                ref not_taken,
+                ref kind,
+            } => {
+                let taken = taken.show_rru(mb_rru);
+                let not_taken = not_taken.show_rru(mb_rru);
+                let branch = show_branch(*kind, mb_rru);
+                format!("{},{} ; j {}", branch, taken, not_taken)
+            }
+            &Inst::TrapIf {
+                ref cond,
+                trap_code,
+            } => {
+                let branch = show_branch(cond.inverse(), mb_rru);
+                format!("{},8 ; unimp {}", branch, trap_code)
+            }
+            &Inst::Udf { trap_code } => format!("unimp {}", trap_code),
+            &Inst::Ebreak => "ebreak".to_string(),
+            &Inst::BrTable {
+                index,
+                tmp1,
+                tmp2,
+                ref info,
+            } => {
+                let index = index.show_rru(mb_rru);
+                let tmp1 = tmp1.to_reg().show_rru(mb_rru);
+                let tmp2 = tmp2.to_reg().show_rru(mb_rru);
+                let default_target = info.default_target.show_rru(mb_rru);
+                format!(
+                    concat!(
+                        "slli {tmp2},{index},2 ; ",
+                        "li {tmp1},{size} ; ",
+                        "bgeu {tmp2},{tmp1},{default_target} ; ",
+                        "auipc {tmp1},0 ; ",
+                        "add {tmp2},{tmp2},{tmp1} ; ",
+                        "lw {tmp2},20({tmp2}) ; ",
+                        "add {tmp1},{tmp1},{tmp2} ; ",
+                        "jr 20({tmp1}) ; ",
+                        "jt_entries {targets:?}"
+                    ),
+                    index = index,
+                    tmp1 = tmp1,
+                    tmp2 = tmp2,
+                    size = info.targets.len() * 4,
+                    default_target = default_target,
+                    targets = info.targets
+                )
+            }
+            &Inst::LoadExtName {
+                rd,
+                ref name,
+                offset,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                format!("ld {},0f ; j 12 ; 0: .8byte {} + {}", rd, name, offset)
+            }
+            &Inst::LoadAddr { rd, ref mem } => {
+                // TODO: we really should find a better way to avoid duplication of
+                // this logic between `emit()` and `show_rru()` -- a separate 1-to-N
+                // expansion stage (i.e., legalization, but without the slow edit-in-place
+                // of the existing legalization framework).
+                let (mem_insts, base, imm12) = mem_finalize(mem, state);
+                let mut ret = String::new();
+                for inst in mem_insts.into_iter() {
+                    ret.push_str(&inst.show_rru(mb_rru));
+                    ret.push_str(" ; ");
+                }
+                let inst = Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addi,
+                    rd,
+                    rs: base,
+                    imm12,
+                };
+                ret.push_str(&inst.show_rru(mb_rru));
+                ret
+            }
+            &Inst::FloatRound {
+                op,
+                ty,
+                rd,
+                rs,
+                int_tmp,
+                f_tmp,
+            } => {
+                let rd = rd.to_reg().show_rru(mb_rru);
+                let rs = rs.show_rru(mb_rru);
+                let int_tmp = int_tmp.to_reg().show_rru(mb_rru);
+                let f_tmp = f_tmp.to_reg().show_rru(mb_rru);
+                let op = match op {
+                    FloatRoundOP::Ceil => "ceil",
+                    FloatRoundOP::Floor => "floor",
+                    FloatRoundOP::Trunc => "trunc",
+                    FloatRoundOP::Nearest => "nearest",
+                };
+                format!("{}.{} {},{} (tmps {},{})", op, ty, rd, rs, int_tmp, f_tmp)
+            }
+            &Inst::VirtualSPOffsetAdj { offset } => {
+                state.virtual_sp_offset += offset;
+                format!("virtual_sp_offset_adjust {}", offset)
+            }
+            &Inst::EmitIsland { needed_space } => format!("emit_island {}", needed_space),
+        }
+    }
+}
+
+//=============================================================================
+// Label fixups and jump veneers.
+
+/// Different forms of label references for different instruction formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelUse {
+    /// 13-bit branch offset (conditional branches). PC-rel, offset is imm << 1. Immediate is 12
+    /// signed bits, scattered over bits 31:25 and 11:7. Used by the `b<cc>` instructions.
+    B12,
+    /// 21-bit branch offset (unconditional branches). PC-rel, offset is imm << 1. Immediate is 20
+    /// signed bits, scattered over bits 31:12. Used by `jal`.
+    Jal20,
+    /// 32-bit PC-relative offset split over an `auipc` and a following I-type instruction
+    /// (`jalr`, `addi` or a load), as `hi20` and `lo12`. Used by branch veneers.
+    PCRelHi20Lo12,
+    /// 32-bit PC relative constant offset (from address of constant itself),
+    /// signed. Used in jump tables.
+    PCRel32,
+}
+
+impl MachInstLabelUse for LabelUse {
+    /// Alignment for veneer code. Every RISC-V instruction we emit is 4 bytes long.
+    const ALIGN: CodeOffset = 4;
+
+    /// Maximum PC-relative range (positive), inclusive.
+    fn max_pos_range(self) -> CodeOffset {
+        match self {
+            LabelUse::B12 => (1 << 12) - 1,
+            LabelUse::Jal20 => (1 << 20) - 1,
+            // The `lo12` part is sign-extended, so `hi20` is rounded up; leave room for that.
+            LabelUse::PCRelHi20Lo12 => 0x7fff_f7ff,
+            LabelUse::PCRel32 => 0x7fff_ffff,
+        }
+    }
+
+    /// Maximum PC-relative range (negative).
+    fn max_neg_range(self) -> CodeOffset {
+        match self {
+            LabelUse::PCRelHi20Lo12 => 0x8000_0000,
+            // All other forms are twos-complement signed offsets, so negative limit is one more
+            // than positive limit.
+            _ => self.max_pos_range() + 1,
+        }
+    }
+
+    /// Size of window into code needed to do the patch.
+    fn patch_size(self) -> CodeOffset {
+        match self {
+            LabelUse::PCRelHi20Lo12 => 8,
+            _ => 4,
+        }
+    }
+
+    /// Perform the patch.
+    fn patch(self, buffer: &mut [u8], use_offset: CodeOffset, label_offset: CodeOffset) {
+        let pc_rel = (label_offset as i64) - (use_offset as i64);
+        debug_assert!(pc_rel <= self.max_pos_range() as i64);
+        debug_assert!(pc_rel >= -(self.max_neg_range() as i64));
+        let pc_rel = pc_rel as i32;
+        let insn_word = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        match self {
+            LabelUse::B12 => {
+                debug_assert!(pc_rel & 1 == 0);
+                let insn_word = (insn_word & !0xfe00_0f80) | enc_b_offset(pc_rel);
+                buffer[0..4].clone_from_slice(&u32::to_le_bytes(insn_word));
+            }
+            LabelUse::Jal20 => {
+                debug_assert!(pc_rel & 1 == 0);
+                let insn_word = (insn_word & !0xffff_f000) | enc_jal_offset(pc_rel);
+                buffer[0..4].clone_from_slice(&u32::to_le_bytes(insn_word));
+            }
+            LabelUse::PCRelHi20Lo12 => {
+                let (hi20, lo12) = split_hi20_lo12(pc_rel);
+                let auipc = (insn_word & 0xfff) | (hi20 << 12);
+                buffer[0..4].clone_from_slice(&u32::to_le_bytes(auipc));
+                let insn_word = u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
+                let insn_word = (insn_word & 0x000f_ffff) | (lo12.bits() << 20);
+                buffer[4..8].clone_from_slice(&u32::to_le_bytes(insn_word));
+            }
+            LabelUse::PCRel32 => {
+                let insn_word = insn_word.wrapping_add(pc_rel as u32);
+                buffer[0..4].clone_from_slice(&u32::to_le_bytes(insn_word));
+            }
+        }
+    }
+
+    /// Is a veneer supported for this label reference type?
+    fn supports_veneer(self) -> bool {
+        match self {
+            LabelUse::B12 | LabelUse::Jal20 => true, // veneer is an auipc/jalr pair
+            _ => false,
+        }
+    }
+
+    /// How large is the veneer, if supported?
+    fn veneer_size(self) -> CodeOffset {
+        8
+    }
+
+    /// Generate a veneer into the buffer, given that this veneer is at `veneer_offset`, and return
+    /// an offset and label-use for the veneer's use of the original label.
+    fn generate_veneer(
+        self,
+        buffer: &mut [u8],
+        veneer_offset: CodeOffset,
+    ) -> (CodeOffset, LabelUse) {
+        match self {
+            LabelUse::B12 | LabelUse::Jal20 => {
+                // The veneer is `auipc spilltmp, 0 ; jalr zero, 0(spilltmp)`. The spilltmp
+                // register is never live across a branch, so it is free to clobber here.
+                let tmp = spilltmp_reg();
+                let auipc = enc_u_type(0b0010111, Writable::from_reg(tmp), 0);
+                let jalr = enc_i_type(0b1100111, 0b000, writable_zero_reg(), tmp, 0);
+                buffer[0..4].clone_from_slice(&u32::to_le_bytes(auipc));
+                buffer[4..8].clone_from_slice(&u32::to_le_bytes(jalr));
+                (veneer_offset, LabelUse::PCRelHi20Lo12)
+            }
+            _ => panic!("Unsupported label-reference type for veneer generation!"),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! RISC-V 64-bit ISA definitions: registers.
+
+use crate::settings;
+
+use regalloc::{RealRegUniverse, Reg, RegClass, RegClassInfo, Writable, NUM_REG_CLASSES};
+
+use std::string::ToString;
+
+//=============================================================================
+// Registers, the Universe thereof, and printing
+
+/// The pinned register on this architecture (s11).
+pub const PINNED_REG: u8 = 27;
+
+#[rustfmt::skip]
+const XREG_INDICES: [u8; 32] = [
+    // x0 (zero), x1 (ra), x2 (sp), x3 (gp), x4 (tp): not allocatable.
+    60, 59, 61, 62, 63,
+    // x5 - x7 (t0 - t2)
+    40, 41, 42,
+    // x8 (s0/fp): not allocatable.
+    58,
+    // x9 (s1)
+    45,
+    // x10 - x17 (a0 - a7)
+    32, 33, 34, 35, 36, 37, 38, 39,
+    // x18 - x26 (s2 - s10)
+    46, 47, 48, 49, 50, 51, 52, 53, 54,
+    // x27 (s11), put aside because it's the pinned register.
+    55,
+    // x28, x29 (t3, t4)
+    43, 44,
+    // x30, x31 (t5, t6): reserved as scratch registers.
+    56, 57,
+];
+
+/// ABI names of the integer registers, indexed by hardware encoding.
+#[rustfmt::skip]
+const XREG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "fp", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// ABI names of the floating-point registers, indexed by hardware encoding.
+#[rustfmt::skip]
+const FREG_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7",
+    "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7",
+    "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// Get a reference to an X-register (integer register).
+pub fn xreg(num: u8) -> Reg {
+    assert!(num < 32);
+    Reg::new_real(
+        RegClass::I64,
+        /* enc = */ num,
+        /* index = */ XREG_INDICES[num as usize],
+    )
+}
+
+/// Get a writable reference to an X-register.
+pub fn writable_xreg(num: u8) -> Writable<Reg> {
+    Writable::from_reg(xreg(num))
+}
+
+/// Get a reference to an F-register (floating-point register).
+pub fn freg(num: u8) -> Reg {
+    assert!(num < 32);
+    Reg::new_real(RegClass::F64, /* enc = */ num, /* index = */ num)
+}
+
+/// Get a writable reference to an F-register.
+pub fn writable_freg(num: u8) -> Writable<Reg> {
+    Writable::from_reg(freg(num))
+}
+
+/// Get a reference to the zero-register.
+pub fn zero_reg() -> Reg {
+    xreg(0)
+}
+
+/// Get a writable reference to the zero-register (this discards a result).
+pub fn writable_zero_reg() -> Writable<Reg> {
+    Writable::from_reg(zero_reg())
+}
+
+/// Get a reference to the stack-pointer register.
+pub fn stack_reg() -> Reg {
+    xreg(2)
+}
+
+/// Get a writable reference to the stack-pointer register.
+pub fn writable_stack_reg() -> Writable<Reg> {
+    Writable::from_reg(stack_reg())
+}
+
+/// Get a reference to the link register (ra, x1).
+pub fn link_reg() -> Reg {
+    xreg(1)
+}
+
+/// Get a writable reference to the link register.
+pub fn writable_link_reg() -> Writable<Reg> {
+    Writable::from_reg(link_reg())
+}
+
+/// Get a reference to the frame pointer (s0, x8).
+pub fn fp_reg() -> Reg {
+    xreg(8)
+}
+
+/// Get a writable reference to the frame pointer.
+pub fn writable_fp_reg() -> Writable<Reg> {
+    Writable::from_reg(fp_reg())
+}
+
+/// Get a reference to the first temporary, sometimes "spill temporary", register. This register is
+/// used to compute the address of a spill slot when a direct offset addressing mode from FP is not
+/// sufficient (+/- 2^11 bytes), to materialize far addresses in branch veneers, and as the
+/// stack-limit register in the prologue. We exclude this register from regalloc and reserve it
+/// for these purposes.
+///
+/// We use t5 (x30) for this: it is a caller-saved temporary that is not used to pass arguments.
+pub fn spilltmp_reg() -> Reg {
+    xreg(30)
+}
+
+/// Get a writable reference to the spilltmp reg.
+pub fn writable_spilltmp_reg() -> Writable<Reg> {
+    Writable::from_reg(spilltmp_reg())
+}
+
+/// Get a reference to the second temp register. We need this in some edge cases
+/// where we need both the spilltmp and another temporary.
+///
+/// We use t6 (x31), the other temporary that is not handed to the register allocator.
+pub fn tmp2_reg() -> Reg {
+    xreg(31)
+}
+
+/// Get a writable reference to the tmp2 reg.
+pub fn writable_tmp2_reg() -> Writable<Reg> {
+    Writable::from_reg(tmp2_reg())
+}
+
+/// Create the register universe for RISC-V 64.
+pub fn create_reg_universe(flags: &settings::Flags) -> RealRegUniverse {
+    let mut regs = vec![];
+    let mut allocable_by_class = [None; NUM_REG_CLASSES];
+
+    // Numbering Scheme: we put F-regs first, then X-regs. The X-regs exclude several registers:
+    // x0 (zero), x1 (return address), x2 (stack pointer), x3 (global pointer), x4 (thread
+    // pointer), x8 (frame pointer), x27 (pinned register), and x30/x31 (scratch registers).
+
+    let f_reg_base = 0u8; // in contiguous real-register index space
+    let f_reg_count = 32;
+    for i in 0u8..f_reg_count {
+        let reg = freg(i).to_real_reg();
+        regs.push((reg, FREG_NAMES[i as usize].to_string()));
+    }
+    let f_reg_last = f_reg_base + f_reg_count - 1;
+
+    // Add the X registers. N.B.: the order here must match the order implied
+    // by XREG_INDICES above: caller-saved registers first (arguments, then
+    // temporaries), then callee-saved ones.
+
+    let x_reg_base = 32u8; // in contiguous real-register index space
+    let allocable_xregs = (10..18)
+        .chain(5..8)
+        .chain(28..30)
+        .chain(9..10)
+        .chain(18..27);
+    let mut x_reg_count = 0;
+    for i in allocable_xregs {
+        let reg = xreg(i).to_real_reg();
+        debug_assert_eq!(reg.get_index(), (x_reg_base + x_reg_count) as usize);
+        regs.push((reg, XREG_NAMES[i as usize].to_string()));
+        x_reg_count += 1;
+    }
+    let x_reg_last = x_reg_base + x_reg_count - 1;
+
+    allocable_by_class[RegClass::I64.rc_to_usize()] = Some(RegClassInfo {
+        first: x_reg_base as usize,
+        last: x_reg_last as usize,
+        suggested_scratch: Some(XREG_INDICES[29] as usize),
+    });
+    allocable_by_class[RegClass::F64.rc_to_usize()] = Some(RegClassInfo {
+        first: f_reg_base as usize,
+        last: f_reg_last as usize,
+        suggested_scratch: Some(/* ft11: */ 31),
+    });
+
+    let allocable = regs.len();
+
+    // Other regs, not available to the allocator.
+    let pinned_name = if flags.enable_pinned_reg() {
+        "s11/pinned_reg"
+    } else {
+        "s11"
+    };
+    regs.push((xreg(PINNED_REG).to_real_reg(), pinned_name.to_string()));
+    for &i in &[30u8, 31, 8, 1, 0, 2, 3, 4] {
+        regs.push((xreg(i).to_real_reg(), XREG_NAMES[i as usize].to_string()));
+    }
+
+    // Assert sanity: the indices in the register structs must match their
+    // actual indices in the array.
+    for (i, reg) in regs.iter().enumerate() {
+        assert_eq!(i, reg.0.get_index());
+    }
+
+    RealRegUniverse {
+        regs,
+        allocable,
+        allocable_by_class,
+    }
+}
@@ -0,0 +1,211 @@
+use super::*;
+use crate::isa::riscv64::inst::{regs, AluOPRRI, AluOPRRR, Inst};
+use crate::isa::unwind::input::{UnwindCode, UnwindInfo};
+use crate::machinst::UnwindInfoContext;
+use crate::result::CodegenResult;
+use alloc::vec::Vec;
+use regalloc::Reg;
+
+#[cfg(feature = "unwind")]
+pub(crate) mod systemv;
+
+pub struct Riscv64UnwindInfo;
+
+/// Returns the SP-relative offset of a prologue store, if `mem` addresses the stack pointer.
+fn sp_store_offset(mem: &AMode) -> Option<i64> {
+    match mem {
+        &AMode::SPOffset(off, _) => Some(off),
+        &AMode::RegOffset(reg, off, _) if reg == regs::stack_reg() => Some(off),
+        _ => None,
+    }
+}
+
+impl UnwindInfoGenerator<Inst> for Riscv64UnwindInfo {
+    fn create_unwind_info(
+        context: UnwindInfoContext<Inst>,
+    ) -> CodegenResult<Option<UnwindInfo<Reg>>> {
+        let word_size = 8u8;
+        let mut codes = Vec::new();
+
+        // Large stack adjustments first materialize their amount in the spilltmp register (see
+        // `gen_sp_reg_adjust` in the ABI implementation); track the value it holds so the
+        // following `sub sp, sp, spilltmp` can be described.
+        let mut spilltmp_value: Option<i64> = None;
+
+        for i in context.prologue.clone() {
+            let i = i as usize;
+            let inst = &context.insts[i];
+            let offset = context.insts_layout[i];
+
+            match inst {
+                Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addi,
+                    rd,
+                    rs,
+                    imm12,
+                } if *rd == regs::writable_stack_reg()
+                    && *rs == regs::stack_reg()
+                    && imm12.as_i64() < 0 =>
+                {
+                    // addi sp, sp, -N
+                    codes.push((
+                        offset,
+                        UnwindCode::StackAlloc {
+                            size: (-imm12.as_i64()) as u32,
+                        },
+                    ));
+                }
+                Inst::AluRRR {
+                    alu_op: AluOPRRR::Sub,
+                    rd,
+                    rs1,
+                    rs2,
+                } if *rd == regs::writable_stack_reg()
+                    && *rs1 == regs::stack_reg()
+                    && *rs2 == regs::spilltmp_reg()
+                    && spilltmp_value.is_some() =>
+                {
+                    // sub sp, sp, spilltmp
+                    codes.push((
+                        offset,
+                        UnwindCode::StackAlloc {
+                            size: spilltmp_value.unwrap() as u32,
+                        },
+                    ));
+                }
+                Inst::Store { src, to, .. } if sp_store_offset(to).is_some() => {
+                    // sd ra, 8(sp) / sd fp, 0(sp) / clobber saves.
+                    codes.push((
+                        offset,
+                        UnwindCode::SaveRegister {
+                            reg: *src,
+                            stack_offset: sp_store_offset(to).unwrap() as u32,
+                        },
+                    ));
+                }
+                Inst::Mov { rd, rm, .. }
+                    if *rd == regs::writable_fp_reg() && *rm == regs::stack_reg() =>
+                {
+                    // mv fp, sp
+                    codes.push((offset, UnwindCode::SetFramePointer { reg: rd.to_reg() }));
+                }
+                Inst::Lui { rd, imm20 } if *rd == regs::writable_spilltmp_reg() => {
+                    spilltmp_value = Some(((*imm20 << 12) as i32) as i64);
+                }
+                Inst::AluRRImm12 {
+                    alu_op,
+                    rd,
+                    rs,
+                    imm12,
+                } if *rd == regs::writable_spilltmp_reg() => {
+                    let imm = imm12.as_i64();
+                    spilltmp_value = match (alu_op, spilltmp_value) {
+                        (AluOPRRI::Addi, _) if *rs == regs::zero_reg() => Some(imm),
+                        (AluOPRRI::Addi, Some(v)) => Some(v.wrapping_add(imm)),
+                        (AluOPRRI::Addiw, Some(v)) => Some((v.wrapping_add(imm) as i32) as i64),
+                        (AluOPRRI::Slli, Some(v)) => Some(v << imm),
+                        _ => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        // TODO epilogues
+
+        let prologue_size = if context.prologue.is_empty() {
+            0
+        } else {
+            context.insts_layout[context.prologue.end as usize - 1]
+        };
+
+        Ok(Some(UnwindInfo {
+            prologue_size,
+            prologue_unwind_codes: codes,
+            epilogues_unwind_codes: vec![],
+            function_size: context.len,
+            word_size,
+            initial_sp_offset: 0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{ExternalName, Function, InstBuilder, Signature, StackSlotData, StackSlotKind};
+    use crate::isa::{lookup, CallConv};
+    use crate::settings::{builder, Flags};
+    use crate::Context;
+    use std::str::FromStr;
+    use target_lexicon::triple;
+
+    #[test]
+    fn test_simple_func() {
+        let isa = lookup(triple!("riscv64"))
+            .expect("expect riscv64 ISA")
+            .finish(Flags::new(builder()));
+
+        let mut context = Context::for_function(create_function(
+            CallConv::SystemV,
+            Some(StackSlotData::new(StackSlotKind::ExplicitSlot, 64)),
+        ));
+
+        context.compile(&*isa).expect("expected compilation");
+
+        let result = context.mach_compile_result.unwrap();
+        let unwind_info = result.unwind_info.unwrap();
+
+        assert_eq!(
+            unwind_info,
+            UnwindInfo {
+                prologue_size: 20,
+                prologue_unwind_codes: vec![
+                    (4, UnwindCode::StackAlloc { size: 16 }),
+                    (
+                        8,
+                        UnwindCode::SaveRegister {
+                            reg: regs::link_reg(),
+                            stack_offset: 8
+                        }
+                    ),
+                    (
+                        12,
+                        UnwindCode::SaveRegister {
+                            reg: regs::fp_reg(),
+                            stack_offset: 0
+                        }
+                    ),
+                    (
+                        16,
+                        UnwindCode::SetFramePointer {
+                            reg: regs::fp_reg()
+                        }
+                    ),
+                    (20, UnwindCode::StackAlloc { size: 64 }),
+                ],
+                epilogues_unwind_codes: vec![],
+                function_size: 40,
+                word_size: 8,
+                initial_sp_offset: 0,
+            }
+        );
+    }
+
+    fn create_function(call_conv: CallConv, stack_slot: Option<StackSlotData>) -> Function {
+        let mut func =
+            Function::with_name_signature(ExternalName::user(0, 0), Signature::new(call_conv));
+
+        let block0 = func.dfg.make_block();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(block0);
+        pos.ins().return_(&[]);
+
+        if let Some(stack_slot) = stack_slot {
+            func.stack_slots.push(stack_slot);
+        }
+
+        func
+    }
+}
@@ -0,0 +1,632 @@
+//! Lowering rules for RISC-V 64.
+//!
+//! TODO: opportunities for better code generation:
+//!
+//! - Use the `W` forms of the ALU instructions for 32-bit operations, rather
+//!   than extending operands to 64 bits.
+//!
+//! - Fold comparisons into `select` and `trueif`, rather than materializing
+//!   them in a register first.
+
+use crate::ir::condcodes::{FloatCC, IntCC};
+use crate::ir::types::*;
+use crate::ir::Inst as IRInst;
+use crate::ir::{Opcode, Type};
+use crate::machinst::lower::*;
+use crate::machinst::*;
+use crate::CodegenResult;
+
+use crate::isa::riscv64::inst::*;
+use crate::isa::riscv64::Riscv64Backend;
+
+use super::lower_inst;
+
+use log::debug;
+use regalloc::{Reg, RegClass, Writable};
+
+//============================================================================
+// Lowering: convert instruction inputs to forms that we can use.
+
+/// Lower an instruction input to a 64-bit constant, if possible.
+pub(crate) fn input_to_const<C: LowerCtx<I = Inst>>(ctx: &mut C, input: InsnInput) -> Option<u64> {
+    let input = ctx.get_input(input.insn, input.input);
+    input.constant
+}
+
+/// Lower an instruction input to a 12-bit signed immediate, if it is a constant that fits.
+pub(crate) fn input_to_imm12<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+) -> Option<Imm12> {
+    let ty = ctx.input_ty(input.insn, input.input);
+    let bits = ty_bits(ty);
+    input_to_const(ctx, input).and_then(|c| {
+        // Sign-extend the constant from its type's width.
+        let c = if bits < 64 {
+            ((c as i64) << (64 - bits)) >> (64 - bits)
+        } else {
+            c as i64
+        };
+        Imm12::maybe_from_i64(c)
+    })
+}
+
+/// How to handle narrow values loaded into registers; see note on `narrow_mode`
+/// parameter to `put_input_in_*` below.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NarrowValueMode {
+    None,
+    /// Zero-extend to 64 bits if original is < 64 bits.
+    ZeroExtend64,
+    /// Sign-extend to 64 bits if original is < 64 bits.
+    SignExtend64,
+}
+
+/// Lower an instruction input to a reg.
+///
+/// The `narrow_mode` flag indicates whether the consumer of this value needs
+/// the high bits clear. For many operations, such as an add/sub/mul or any
+/// bitwise logical operation, the low-bit results depend only on the low-bit
+/// inputs, so e.g. we can do an 8 bit add on 64 bit registers where the 8-bit
+/// value is stored in the low 8 bits of the register and the high 56 bits are
+/// undefined. If the op truly needs the high N bits clear (such as for a
+/// divide or a right-shift or a compare), `narrow_mode` should be set to
+/// `ZeroExtend64` or `SignExtend64` as appropriate, and the resulting register
+/// will be provided the extended value.
+pub(crate) fn put_input_in_reg<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+    narrow_mode: NarrowValueMode,
+) -> Reg {
+    debug!("put_input_in_reg: input {:?}", input);
+    let ty = ctx.input_ty(input.insn, input.input);
+    let from_bits = ty_bits(ty) as u8;
+    let inputs = ctx.get_input(input.insn, input.input);
+    let in_reg = if let Some(c) = inputs.constant {
+        // Generate constants fresh at each use to minimize long-range register pressure.
+        let masked = if from_bits < 64 {
+            c & ((1u64 << from_bits) - 1)
+        } else {
+            c
+        };
+        let to_reg = ctx.alloc_tmp(Inst::rc_for_type(ty).unwrap().0[0], ty);
+        for inst in Inst::gen_constant(to_reg, masked, ty, |reg_class, ty| {
+            ctx.alloc_tmp(reg_class, ty)
+        })
+        .into_iter()
+        {
+            ctx.emit(inst);
+        }
+        to_reg.to_reg()
+    } else {
+        ctx.use_input_reg(inputs);
+        inputs.reg
+    };
+
+    match (narrow_mode, from_bits) {
+        (NarrowValueMode::None, _) => in_reg,
+        (NarrowValueMode::ZeroExtend64, n) if n < 64 => {
+            if inputs.constant.is_some() {
+                // Constants are zero-extended to full 64-bit width on load already.
+                in_reg
+            } else {
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                ctx.emit(Inst::Extend {
+                    rd: tmp,
+                    rn: in_reg,
+                    signed: false,
+                    from_bits,
+                    to_bits: 64,
+                });
+                tmp.to_reg()
+            }
+        }
+        (NarrowValueMode::SignExtend64, n) if n < 64 => {
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::Extend {
+                rd: tmp,
+                rn: in_reg,
+                signed: true,
+                from_bits,
+                to_bits: 64,
+            });
+            tmp.to_reg()
+        }
+        (_, 64) => in_reg,
+
+        _ => panic!(
+            "Unsupported input width: input ty {} bits {} mode {:?}",
+            ty, from_bits, narrow_mode
+        ),
+    }
+}
+
+/// Lower an instruction input to a reg, using the zero register for a zero constant.
+pub(crate) fn put_input_in_reg_or_zero<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+    narrow_mode: NarrowValueMode,
+) -> Reg {
+    if input_to_const(ctx, input) == Some(0) {
+        zero_reg()
+    } else {
+        put_input_in_reg(ctx, input, narrow_mode)
+    }
+}
+
+/// Lower an address made of the sum of the given addends plus a constant offset.
+pub(crate) fn lower_address<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    elem_ty: Type,
+    addends: &[InsnInput],
+    offset: i32,
+) -> AMode {
+    // TODO: fold constant addends into the offset.
+    let mut base = put_input_in_reg(ctx, addends[0], NarrowValueMode::ZeroExtend64);
+    for &addend in &addends[1..] {
+        let reg = put_input_in_reg(ctx, addend, NarrowValueMode::ZeroExtend64);
+        let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+        ctx.emit(Inst::AluRRR {
+            alu_op: AluOPRRR::Add,
+            rd: tmp,
+            rs1: base,
+            rs2: reg,
+        });
+        base = tmp.to_reg();
+    }
+    AMode::RegOffset(base, offset as i64, elem_ty)
+}
+
+pub(crate) fn lower_constant_u64<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    rd: Writable<Reg>,
+    value: u64,
+) {
+    for inst in Inst::load_constant(rd, value) {
+        ctx.emit(inst);
+    }
+}
+
+pub(crate) fn lower_constant_f32<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    rd: Writable<Reg>,
+    value: f32,
+) {
+    let alloc_tmp = |class, ty| ctx.alloc_tmp(class, ty);
+
+    for inst in Inst::load_fp_constant32(rd, value.to_bits(), alloc_tmp) {
+        ctx.emit(inst);
+    }
+}
+
+pub(crate) fn lower_constant_f64<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    rd: Writable<Reg>,
+    value: f64,
+) {
+    let alloc_tmp = |class, ty| ctx.alloc_tmp(class, ty);
+
+    for inst in Inst::load_fp_constant64(rd, value.to_bits(), alloc_tmp) {
+        ctx.emit(inst);
+    }
+}
+
+pub(crate) fn condcode_is_signed(cc: IntCC) -> bool {
+    match cc {
+        IntCC::Equal => false,
+        IntCC::NotEqual => false,
+        IntCC::SignedGreaterThanOrEqual => true,
+        IntCC::SignedGreaterThan => true,
+        IntCC::SignedLessThanOrEqual => true,
+        IntCC::SignedLessThan => true,
+        IntCC::UnsignedGreaterThanOrEqual => false,
+        IntCC::UnsignedGreaterThan => false,
+        IntCC::UnsignedLessThanOrEqual => false,
+        IntCC::UnsignedLessThan => false,
+        IntCC::Overflow => true,
+        IntCC::NotOverflow => true,
+    }
+}
+
+// Helpers for instruction lowering.
+
+pub(crate) fn alu_rrr<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    alu_op: AluOPRRR,
+    rd: Writable<Reg>,
+    rs1: Reg,
+    rs2: Reg,
+) {
+    ctx.emit(Inst::AluRRR {
+        alu_op,
+        rd,
+        rs1,
+        rs2,
+    });
+}
+
+pub(crate) fn alu_rr_imm12<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    alu_op: AluOPRRI,
+    rd: Writable<Reg>,
+    rs: Reg,
+    imm: i64,
+) {
+    ctx.emit(Inst::AluRRImm12 {
+        alu_op,
+        rd,
+        rs,
+        imm12: Imm12::maybe_from_i64(imm).unwrap(),
+    });
+}
+
+/// Checks for an instance of `op` feeding the given input.
+pub(crate) fn maybe_input_insn<C: LowerCtx<I = Inst>>(
+    c: &mut C,
+    input: InsnInput,
+    op: Opcode,
+) -> Option<IRInst> {
+    let inputs = c.get_input(input.insn, input.input);
+    debug!(
+        "maybe_input_insn: input {:?} has options {:?}; looking for op {:?}",
+        input, inputs, op
+    );
+    if let Some((src_inst, _)) = inputs.inst {
+        let data = c.data(src_inst);
+        debug!(" -> input inst {:?}", data);
+        if data.opcode() == op {
+            return Some(src_inst);
+        }
+    }
+    None
+}
+
+/// Checks for an instance of `op` feeding the given input, possibly via a conversion `conv` (e.g.,
+/// Bint or a bitcast).
+pub(crate) fn maybe_input_insn_via_conv<C: LowerCtx<I = Inst>>(
+    c: &mut C,
+    input: InsnInput,
+    op: Opcode,
+    conv: Opcode,
+) -> Option<IRInst> {
+    let inputs = c.get_input(input.insn, input.input);
+    if let Some((src_inst, _)) = inputs.inst {
+        let data = c.data(src_inst);
+        if data.opcode() == op {
+            return Some(src_inst);
+        }
+        if data.opcode() == conv {
+            let inputs = c.get_input(src_inst, 0);
+            if let Some((src_inst, _)) = inputs.inst {
+                let data = c.data(src_inst);
+                if data.opcode() == op {
+                    return Some(src_inst);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Lower the comparison `cc` of the two integer inputs `lhs` and `rhs` to an `IntegerCompare`,
+/// extending narrow operands as the condition requires. The overflow conditions are first
+/// computed into a register.
+pub(crate) fn lower_icmp<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    cc: IntCC,
+    lhs: InsnInput,
+    rhs: InsnInput,
+) -> IntegerCompare {
+    let ty = ctx.input_ty(lhs.insn, lhs.input);
+    let narrow_mode = if condcode_is_signed(cc) {
+        NarrowValueMode::SignExtend64
+    } else {
+        NarrowValueMode::ZeroExtend64
+    };
+    let rs1 = put_input_in_reg_or_zero(ctx, lhs, narrow_mode);
+    let rs2 = put_input_in_reg_or_zero(ctx, rhs, narrow_mode);
+
+    match cc {
+        IntCC::Overflow | IntCC::NotOverflow => {
+            let overflow = lower_sub_overflow(ctx, ty, rs1, rs2);
+            IntegerCompare {
+                kind: if cc == IntCC::Overflow {
+                    IntCC::NotEqual
+                } else {
+                    IntCC::Equal
+                },
+                rs1: overflow,
+                rs2: zero_reg(),
+            }
+        }
+        _ => IntegerCompare { kind: cc, rs1, rs2 },
+    }
+}
+
+/// Compute whether the signed subtraction `rs1 - rs2` of two sign-extended values of type `ty`
+/// overflows, as a register that is non-zero iff it does.
+fn lower_sub_overflow<C: LowerCtx<I = Inst>>(ctx: &mut C, ty: Type, rs1: Reg, rs2: Reg) -> Reg {
+    let bits = ty_bits(ty) as u8;
+    let diff = ctx.alloc_tmp(RegClass::I64, I64);
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    alu_rrr(ctx, AluOPRRR::Sub, diff, rs1, rs2);
+    if bits < 64 {
+        // The difference is exact in 64 bits; it overflows iff it doesn't fit in `bits` bits.
+        ctx.emit(Inst::Extend {
+            rd: tmp,
+            rn: diff.to_reg(),
+            signed: true,
+            from_bits: bits,
+            to_bits: 64,
+        });
+        alu_rrr(ctx, AluOPRRR::Xor, tmp, tmp.to_reg(), diff.to_reg());
+    } else {
+        // The operands have different signs, and the result's sign differs from the first one.
+        let tmp2 = ctx.alloc_tmp(RegClass::I64, I64);
+        alu_rrr(ctx, AluOPRRR::Xor, tmp, rs1, rs2);
+        alu_rrr(ctx, AluOPRRR::Xor, tmp2, rs1, diff.to_reg());
+        alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), tmp2.to_reg());
+        alu_rrr(ctx, AluOPRRR::Slt, tmp, tmp.to_reg(), zero_reg());
+    }
+    tmp.to_reg()
+}
+
+/// Materialize the result of an integer comparison as 0 or 1 in `rd`.
+pub(crate) fn lower_int_compare_to_reg<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    cmp: IntegerCompare,
+    rd: Writable<Reg>,
+) {
+    let IntegerCompare { kind, rs1, rs2 } = cmp;
+    let (alu_op, rs1, rs2, invert) = match kind {
+        IntCC::Equal => {
+            alu_rrr(ctx, AluOPRRR::Xor, rd, rs1, rs2);
+            alu_rr_imm12(ctx, AluOPRRI::Sltiu, rd, rd.to_reg(), 1);
+            return;
+        }
+        IntCC::NotEqual => {
+            alu_rrr(ctx, AluOPRRR::Xor, rd, rs1, rs2);
+            alu_rrr(ctx, AluOPRRR::Sltu, rd, zero_reg(), rd.to_reg());
+            return;
+        }
+        IntCC::SignedLessThan => (AluOPRRR::Slt, rs1, rs2, false),
+        IntCC::SignedGreaterThanOrEqual => (AluOPRRR::Slt, rs1, rs2, true),
+        IntCC::SignedGreaterThan => (AluOPRRR::Slt, rs2, rs1, false),
+        IntCC::SignedLessThanOrEqual => (AluOPRRR::Slt, rs2, rs1, true),
+        IntCC::UnsignedLessThan => (AluOPRRR::Sltu, rs1, rs2, false),
+        IntCC::UnsignedGreaterThanOrEqual => (AluOPRRR::Sltu, rs1, rs2, true),
+        IntCC::UnsignedGreaterThan => (AluOPRRR::Sltu, rs2, rs1, false),
+        IntCC::UnsignedLessThanOrEqual => (AluOPRRR::Sltu, rs2, rs1, true),
+        IntCC::Overflow | IntCC::NotOverflow => {
+            panic!("Overflow conditions must be lowered with lower_icmp")
+        }
+    };
+    alu_rrr(ctx, alu_op, rd, rs1, rs2);
+    if invert {
+        alu_rr_imm12(ctx, AluOPRRI::Xori, rd, rd.to_reg(), 1);
+    }
+}
+
+/// Materialize the result of the floating-point comparison `cc` of `rs1` and `rs2` as 0 or 1 in
+/// `rd`.
+pub(crate) fn lower_fcmp_to_reg<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    cc: FloatCC,
+    ty: Type,
+    rs1: Reg,
+    rs2: Reg,
+    rd: Writable<Reg>,
+) {
+    let (feq, flt, fle) = match ty {
+        F32 => (FpuOPRRR::FeqS, FpuOPRRR::FltS, FpuOPRRR::FleS),
+        F64 => (FpuOPRRR::FeqD, FpuOPRRR::FltD, FpuOPRRR::FleD),
+        _ => panic!("Unexpected fcmp type: {}", ty),
+    };
+    let fcmp = |ctx: &mut C, alu_op, rd, rs1, rs2| {
+        ctx.emit(Inst::FpuRRR {
+            alu_op,
+            frm: FRM::RNE,
+            rd,
+            rs1,
+            rs2,
+        })
+    };
+
+    // Compute the condition, or its inverse.
+    let invert = match cc {
+        FloatCC::Ordered | FloatCC::Unordered => {
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            fcmp(ctx, feq, rd, rs1, rs1);
+            fcmp(ctx, feq, tmp, rs2, rs2);
+            alu_rrr(ctx, AluOPRRR::And, rd, rd.to_reg(), tmp.to_reg());
+            cc == FloatCC::Unordered
+        }
+        FloatCC::OrderedNotEqual | FloatCC::UnorderedOrEqual => {
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            fcmp(ctx, flt, rd, rs1, rs2);
+            fcmp(ctx, flt, tmp, rs2, rs1);
+            alu_rrr(ctx, AluOPRRR::Or, rd, rd.to_reg(), tmp.to_reg());
+            cc == FloatCC::UnorderedOrEqual
+        }
+        FloatCC::Equal => {
+            fcmp(ctx, feq, rd, rs1, rs2);
+            false
+        }
+        FloatCC::NotEqual => {
+            fcmp(ctx, feq, rd, rs1, rs2);
+            true
+        }
+        FloatCC::LessThan => {
+            fcmp(ctx, flt, rd, rs1, rs2);
+            false
+        }
+        FloatCC::LessThanOrEqual => {
+            fcmp(ctx, fle, rd, rs1, rs2);
+            false
+        }
+        FloatCC::GreaterThan => {
+            fcmp(ctx, flt, rd, rs2, rs1);
+            false
+        }
+        FloatCC::GreaterThanOrEqual => {
+            fcmp(ctx, fle, rd, rs2, rs1);
+            false
+        }
+        FloatCC::UnorderedOrLessThan => {
+            fcmp(ctx, fle, rd, rs2, rs1);
+            true
+        }
+        FloatCC::UnorderedOrLessThanOrEqual => {
+            fcmp(ctx, flt, rd, rs2, rs1);
+            true
+        }
+        FloatCC::UnorderedOrGreaterThan => {
+            fcmp(ctx, fle, rd, rs1, rs2);
+            true
+        }
+        FloatCC::UnorderedOrGreaterThanOrEqual => {
+            fcmp(ctx, flt, rd, rs1, rs2);
+            true
+        }
+    };
+    if invert {
+        alu_rr_imm12(ctx, AluOPRRI::Xori, rd, rd.to_reg(), 1);
+    }
+}
+
+/// Lower the floating-point comparison performed by `insn` (an `fcmp` or `ffcmp`) with
+/// condition `cc`, into a register holding 0 or 1.
+pub(crate) fn lower_fcmp_insn_to_reg<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    insn: IRInst,
+    cc: FloatCC,
+) -> Reg {
+    let ty = ctx.input_ty(insn, 0);
+    let rs1 = put_input_in_reg(ctx, InsnInput { insn, input: 0 }, NarrowValueMode::None);
+    let rs2 = put_input_in_reg(ctx, InsnInput { insn, input: 1 }, NarrowValueMode::None);
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    lower_fcmp_to_reg(ctx, cc, ty, rs1, rs2, tmp);
+    tmp.to_reg()
+}
+
+/// Load a 64-bit constant into a fresh temporary.
+fn constant_in_tmp<C: LowerCtx<I = Inst>>(ctx: &mut C, value: u64) -> Reg {
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    lower_constant_u64(ctx, tmp, value);
+    tmp.to_reg()
+}
+
+/// Count the set bits of the 64-bit value in `rs` into `rd`.
+pub(crate) fn lower_popcnt64<C: LowerCtx<I = Inst>>(ctx: &mut C, rd: Writable<Reg>, rs: Reg) {
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+
+    // rd = rs - ((rs >> 1) & 0x5555...)
+    let m1 = constant_in_tmp(ctx, 0x5555_5555_5555_5555);
+    alu_rr_imm12(ctx, AluOPRRI::Srli, tmp, rs, 1);
+    alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), m1);
+    alu_rrr(ctx, AluOPRRR::Sub, rd, rs, tmp.to_reg());
+
+    // rd = (rd & 0x3333...) + ((rd >> 2) & 0x3333...)
+    let m2 = constant_in_tmp(ctx, 0x3333_3333_3333_3333);
+    alu_rr_imm12(ctx, AluOPRRI::Srli, tmp, rd.to_reg(), 2);
+    alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), m2);
+    alu_rrr(ctx, AluOPRRR::And, rd, rd.to_reg(), m2);
+    alu_rrr(ctx, AluOPRRR::Add, rd, rd.to_reg(), tmp.to_reg());
+
+    // rd = (rd + (rd >> 4)) & 0x0f0f...
+    let m4 = constant_in_tmp(ctx, 0x0f0f_0f0f_0f0f_0f0f);
+    alu_rr_imm12(ctx, AluOPRRI::Srli, tmp, rd.to_reg(), 4);
+    alu_rrr(ctx, AluOPRRR::Add, rd, rd.to_reg(), tmp.to_reg());
+    alu_rrr(ctx, AluOPRRR::And, rd, rd.to_reg(), m4);
+
+    // rd = (rd * 0x0101...) >> 56
+    let h01 = constant_in_tmp(ctx, 0x0101_0101_0101_0101);
+    alu_rrr(ctx, AluOPRRR::Mul, rd, rd.to_reg(), h01);
+    alu_rr_imm12(ctx, AluOPRRI::Srli, rd, rd.to_reg(), 56);
+}
+
+/// Count the leading zeros of the 64-bit value in `rs` into `rd`.
+pub(crate) fn lower_clz64<C: LowerCtx<I = Inst>>(ctx: &mut C, rd: Writable<Reg>, rs: Reg) {
+    // Smear the highest set bit into all lower bits, then count the remaining zeros.
+    let smeared = ctx.alloc_tmp(RegClass::I64, I64);
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    let mut src = rs;
+    for &shift in &[1, 2, 4, 8, 16, 32] {
+        alu_rr_imm12(ctx, AluOPRRI::Srli, tmp, src, shift);
+        alu_rrr(ctx, AluOPRRR::Or, smeared, src, tmp.to_reg());
+        src = smeared.to_reg();
+    }
+    alu_rr_imm12(ctx, AluOPRRI::Xori, smeared, smeared.to_reg(), -1);
+    lower_popcnt64(ctx, rd, smeared.to_reg());
+}
+
+/// Count the trailing zeros of the 64-bit value in `rs` into `rd`.
+pub(crate) fn lower_ctz64<C: LowerCtx<I = Inst>>(ctx: &mut C, rd: Writable<Reg>, rs: Reg) {
+    // The trailing zeros are the set bits of `(rs & -rs) - 1`.
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    alu_rrr(ctx, AluOPRRR::Sub, tmp, zero_reg(), rs);
+    alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), rs);
+    alu_rr_imm12(ctx, AluOPRRI::Addi, tmp, tmp.to_reg(), -1);
+    lower_popcnt64(ctx, rd, tmp.to_reg());
+}
+
+/// Reverse the bits of the 64-bit value in `rs` into `rd`.
+pub(crate) fn lower_bitrev64<C: LowerCtx<I = Inst>>(ctx: &mut C, rd: Writable<Reg>, rs: Reg) {
+    // Swap adjacent groups of 1, 2, 4, ..., 32 bits.
+    let tmp1 = ctx.alloc_tmp(RegClass::I64, I64);
+    let tmp2 = ctx.alloc_tmp(RegClass::I64, I64);
+    let mut src = rs;
+    for &(shift, mask) in &[
+        (1, 0x5555_5555_5555_5555),
+        (2, 0x3333_3333_3333_3333),
+        (4, 0x0f0f_0f0f_0f0f_0f0f),
+        (8, 0x00ff_00ff_00ff_00ff),
+        (16, 0x0000_ffff_0000_ffff),
+        (32, 0x0000_0000_ffff_ffff),
+    ] {
+        let mask = constant_in_tmp(ctx, mask);
+        alu_rr_imm12(ctx, AluOPRRI::Srli, tmp1, src, shift);
+        alu_rrr(ctx, AluOPRRR::And, tmp1, tmp1.to_reg(), mask);
+        alu_rrr(ctx, AluOPRRR::And, tmp2, src, mask);
+        alu_rr_imm12(ctx, AluOPRRI::Slli, tmp2, tmp2.to_reg(), shift);
+        alu_rrr(ctx, AluOPRRR::Or, rd, tmp1.to_reg(), tmp2.to_reg());
+        src = rd.to_reg();
+    }
+}
+
+/// Convert a 0 / 1 result, such as from a comparison, into a 0 / -1
+/// (all-ones) result as expected for bool operations.
+pub(crate) fn normalize_bool_result<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    insn: IRInst,
+    rd: Writable<Reg>,
+) {
+    // A boolean is 0 / -1; if output width is > 1, negate.
+    if ty_bits(ctx.output_ty(insn, 0)) > 1 {
+        alu_rrr(ctx, AluOPRRR::Sub, rd, zero_reg(), rd.to_reg());
+    }
+}
+
+//=============================================================================
+// Lowering-backend trait implementation.
+
+impl LowerBackend for Riscv64Backend {
+    type MInst = Inst;
+
+    fn lower<C: LowerCtx<I = Inst>>(&self, ctx: &mut C, ir_inst: IRInst) -> CodegenResult<()> {
+        lower_inst::lower_insn_to_regs(ctx, ir_inst)
+    }
+
+    fn lower_branch_group<C: LowerCtx<I = Inst>>(
+        &self,
+        ctx: &mut C,
+        branches: &[IRInst],
+        targets: &[MachLabel],
+    ) -> CodegenResult<()> {
+        lower_inst::lower_branch(ctx, branches, targets)
+    }
+
+    fn maybe_pinned_reg(&self) -> Option<Reg> {
+        Some(xreg(PINNED_REG))
+    }
+}
@@ -0,0 +1,2015 @@
+//! Lower a single Cranelift instruction into vcode.
+
+use crate::binemit::CodeOffset;
+use crate::ir::condcodes::{CondCode, IntCC};
+use crate::ir::types::*;
+use crate::ir::Inst as IRInst;
+use crate::ir::{AtomicRmwOp, InstructionData, Opcode, TrapCode};
+use crate::machinst::lower::*;
+use crate::machinst::*;
+use crate::{CodegenError, CodegenResult};
+
+use crate::isa::riscv64::abi::*;
+use crate::isa::riscv64::inst::*;
+
+use regalloc::{Reg, RegClass, Writable};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use smallvec::SmallVec;
+
+use super::lower::*;
+
+/// This is target-word-size dependent.  And it excludes booleans and reftypes.
+fn is_valid_atomic_transaction_ty(ty: Type) -> bool {
+    match ty {
+        I8 | I16 | I32 | I64 => true,
+        _ => false,
+    }
+}
+
+/// Pick the single- or double-precision variant of a floating-point operation.
+fn choose_f32_f64<T>(ty: Type, op32: T, op64: T) -> T {
+    match ty {
+        F32 => op32,
+        F64 => op64,
+        _ => panic!("choose_f32_f64 on non-float type {}", ty),
+    }
+}
+
+/// Copy `reg` into a fresh temporary, for instructions that modify their inputs.
+fn copy_to_tmp<C: LowerCtx<I = Inst>>(ctx: &mut C, reg: Reg) -> Writable<Reg> {
+    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+    ctx.emit(Inst::gen_move(tmp, reg, I64));
+    tmp
+}
+
+/// Lower the carry flag produced by an `iadd_ifcout`, or the borrow flag produced by an
+/// `isub_ifbout`, feeding `input` to an `IntegerCompare` that holds for the condition `cc`.
+fn lower_carry_flag_compare<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+    cc: IntCC,
+    is_borrow: bool,
+) -> CodegenResult<IntegerCompare> {
+    // The flags are materialized as 0 / 1 by the producing instruction. A set carry is signalled
+    // with `UnsignedGreaterThanOrEqual` and a set borrow with `UnsignedLessThan`; see
+    // `unsigned_add_overflow_condition` and `unsigned_sub_overflow_condition`.
+    let flag = put_input_in_reg(ctx, input, NarrowValueMode::None);
+    let set_cc = if is_borrow {
+        IntCC::UnsignedLessThan
+    } else {
+        IntCC::UnsignedGreaterThanOrEqual
+    };
+    let kind = if cc == set_cc {
+        IntCC::NotEqual
+    } else if cc == set_cc.inverse() {
+        IntCC::Equal
+    } else {
+        return Err(CodegenError::Unsupported(format!(
+            "Unsupported condition on carry flag: {}",
+            cc
+        )));
+    };
+    Ok(IntegerCompare {
+        kind,
+        rs1: flag,
+        rs2: zero_reg(),
+    })
+}
+
+/// Lower the flags input `input` of a `brif`, `trapif`, `trueif` or `selectif` with condition
+/// `cc` to an `IntegerCompare`. The flags must come from an `ifcmp`, an `iadd_ifcout` or an
+/// `isub_ifbout`.
+fn lower_iflags_compare<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    input: InsnInput,
+    cc: IntCC,
+) -> CodegenResult<IntegerCompare> {
+    if let Some(ifcmp_insn) = maybe_input_insn(ctx, input, Opcode::Ifcmp) {
+        let lhs = InsnInput {
+            insn: ifcmp_insn,
+            input: 0,
+        };
+        let rhs = InsnInput {
+            insn: ifcmp_insn,
+            input: 1,
+        };
+        Ok(lower_icmp(ctx, cc, lhs, rhs))
+    } else if maybe_input_insn(ctx, input, Opcode::IaddIfcout).is_some() {
+        lower_carry_flag_compare(ctx, input, cc, false)
+    } else if maybe_input_insn(ctx, input, Opcode::IsubIfbout).is_some() {
+        lower_carry_flag_compare(ctx, input, cc, true)
+    } else {
+        Err(CodegenError::Unsupported(
+            "Integer flags not produced by ifcmp, iadd_ifcout or isub_ifbout".into(),
+        ))
+    }
+}
+
+/// Actually codegen an instruction's results into registers.
+pub(crate) fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    insn: IRInst,
+) -> CodegenResult<()> {
+    let op = ctx.data(insn).opcode();
+    let inputs: SmallVec<[InsnInput; 4]> = (0..ctx.num_inputs(insn))
+        .map(|i| InsnInput { insn, input: i })
+        .collect();
+    let outputs: SmallVec<[InsnOutput; 2]> = (0..ctx.num_outputs(insn))
+        .map(|i| InsnOutput { insn, output: i })
+        .collect();
+    let ty = if outputs.len() > 0 {
+        Some(ctx.output_ty(insn, 0))
+    } else {
+        None
+    };
+
+    // The V extension is not supported yet, and neither are 128-bit integers.
+    let unsupported_ty = |ty: Type| ty.is_vector() || ty == I128;
+    if ty.map_or(false, unsupported_ty)
+        || (inputs.len() > 0 && unsupported_ty(ctx.input_ty(insn, 0)))
+    {
+        return Err(CodegenError::Unsupported(format!(
+            "Unsupported type for {}: {}",
+            op,
+            ty.unwrap_or_else(|| ctx.input_ty(insn, 0))
+        )));
+    }
+
+    match op {
+        Opcode::Iconst | Opcode::Bconst | Opcode::Null => {
+            let value = ctx.get_constant(insn).unwrap();
+            // Sign extend constant if necessary
+            let value = match ty.unwrap() {
+                I8 => (((value as i64) << 56) >> 56) as u64,
+                I16 => (((value as i64) << 48) >> 48) as u64,
+                I32 => (((value as i64) << 32) >> 32) as u64,
+                I64 | R64 => value,
+                ty if ty.is_bool() => value,
+                ty => unreachable!("Unknown type for const: {}", ty),
+            };
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_constant_u64(ctx, rd, value);
+        }
+
+        Opcode::F32const => {
+            let value = f32::from_bits(ctx.get_constant(insn).unwrap() as u32);
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_constant_f32(ctx, rd, value);
+        }
+
+        Opcode::F64const => {
+            let value = f64::from_bits(ctx.get_constant(insn).unwrap());
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_constant_f64(ctx, rd, value);
+        }
+
+        Opcode::Iadd => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            if let Some(imm12) = input_to_imm12(ctx, inputs[1]) {
+                ctx.emit(Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addi,
+                    rd,
+                    rs: rs1,
+                    imm12,
+                });
+            } else {
+                let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                alu_rrr(ctx, AluOPRRR::Add, rd, rs1, rs2);
+            }
+        }
+
+        Opcode::Isub => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let neg_imm12 = input_to_imm12(ctx, inputs[1])
+                .and_then(|imm12| Imm12::maybe_from_i64(-imm12.as_i64()));
+            if let Some(imm12) = neg_imm12 {
+                ctx.emit(Inst::AluRRImm12 {
+                    alu_op: AluOPRRI::Addi,
+                    rd,
+                    rs: rs1,
+                    imm12,
+                });
+            } else {
+                let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                alu_rrr(ctx, AluOPRRR::Sub, rd, rs1, rs2);
+            }
+        }
+
+        Opcode::Ineg => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            alu_rrr(ctx, AluOPRRR::Sub, rd, zero_reg(), rs);
+        }
+
+        Opcode::Imul => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            alu_rrr(ctx, AluOPRRR::Mul, rd, rs1, rs2);
+        }
+
+        Opcode::Umulhi | Opcode::Smulhi => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let is_signed = op == Opcode::Smulhi;
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty);
+            if bits == 64 {
+                let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+                let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                let alu_op = if is_signed {
+                    AluOPRRR::Mulh
+                } else {
+                    AluOPRRR::Mulhu
+                };
+                alu_rrr(ctx, alu_op, rd, rs1, rs2);
+            } else {
+                // The full product of two extended narrow values fits in 64 bits; shift the high
+                // half down.
+                let narrow_mode = if is_signed {
+                    NarrowValueMode::SignExtend64
+                } else {
+                    NarrowValueMode::ZeroExtend64
+                };
+                let rs1 = put_input_in_reg(ctx, inputs[0], narrow_mode);
+                let rs2 = put_input_in_reg(ctx, inputs[1], narrow_mode);
+                alu_rrr(ctx, AluOPRRR::Mul, rd, rs1, rs2);
+                let shift_op = if is_signed {
+                    AluOPRRI::Srai
+                } else {
+                    AluOPRRI::Srli
+                };
+                alu_rr_imm12(ctx, shift_op, rd, rd.to_reg(), bits as i64);
+            }
+        }
+
+        Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem => {
+            let is_signed = match op {
+                Opcode::Udiv | Opcode::Urem => false,
+                Opcode::Sdiv | Opcode::Srem => true,
+                _ => unreachable!(),
+            };
+            let is_rem = match op {
+                Opcode::Udiv | Opcode::Sdiv => false,
+                Opcode::Urem | Opcode::Srem => true,
+                _ => unreachable!(),
+            };
+            let narrow_mode = if is_signed {
+                NarrowValueMode::SignExtend64
+            } else {
+                NarrowValueMode::ZeroExtend64
+            };
+            let alu_op = match (is_signed, is_rem) {
+                (false, false) => AluOPRRR::Divu,
+                (true, false) => AluOPRRR::Div,
+                (false, true) => AluOPRRR::Remu,
+                (true, true) => AluOPRRR::Rem,
+            };
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty);
+            let mask = if bits < 64 {
+                (1u64 << bits) - 1
+            } else {
+                u64::MAX
+            };
+            let divisor = input_to_const(ctx, inputs[1]).map(|c| c & mask);
+
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], narrow_mode);
+            let rs2 = put_input_in_reg(ctx, inputs[1], narrow_mode);
+
+            // RISC-V division doesn't trap: division by zero returns all ones (or the dividend, for
+            // the remainder), so check for it explicitly.
+            if divisor.map_or(true, |c| c == 0) {
+                ctx.emit_safepoint(Inst::TrapIf {
+                    cond: IntegerCompare {
+                        kind: IntCC::Equal,
+                        rs1: rs2,
+                        rs2: zero_reg(),
+                    },
+                    trap_code: TrapCode::IntegerDivisionByZero,
+                });
+            }
+
+            // The signed division `INT_MIN / -1` overflows; RISC-V returns `INT_MIN` (and a
+            // remainder of 0, which is what we want for `srem`).
+            if op == Opcode::Sdiv && divisor.map_or(true, |c| c == mask) {
+                // tmp = (rs1 ^ INT_MIN) | (rs2 + 1), which is zero exactly in the overflowing case
+                // (both operands are sign-extended).
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                let tmp2 = ctx.alloc_tmp(RegClass::I64, I64);
+                let int_min = (i64::MIN >> (64 - bits)) as u64;
+                lower_constant_u64(ctx, tmp, int_min);
+                alu_rrr(ctx, AluOPRRR::Xor, tmp, tmp.to_reg(), rs1);
+                alu_rr_imm12(ctx, AluOPRRI::Addi, tmp2, rs2, 1);
+                alu_rrr(ctx, AluOPRRR::Or, tmp, tmp.to_reg(), tmp2.to_reg());
+                ctx.emit_safepoint(Inst::TrapIf {
+                    cond: IntegerCompare {
+                        kind: IntCC::Equal,
+                        rs1: tmp.to_reg(),
+                        rs2: zero_reg(),
+                    },
+                    trap_code: TrapCode::IntegerOverflow,
+                });
+            }
+
+            alu_rrr(ctx, alu_op, rd, rs1, rs2);
+        }
+
+        Opcode::Band | Opcode::Bor | Opcode::Bxor => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let (alu_op, alu_op_imm) = match op {
+                Opcode::Band => (AluOPRRR::And, AluOPRRI::Andi),
+                Opcode::Bor => (AluOPRRR::Or, AluOPRRI::Ori),
+                Opcode::Bxor => (AluOPRRR::Xor, AluOPRRI::Xori),
+                _ => unreachable!(),
+            };
+            if let Some(imm12) = input_to_imm12(ctx, inputs[1]) {
+                ctx.emit(Inst::AluRRImm12 {
+                    alu_op: alu_op_imm,
+                    rd,
+                    rs: rs1,
+                    imm12,
+                });
+            } else {
+                let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                alu_rrr(ctx, alu_op, rd, rs1, rs2);
+            }
+        }
+
+        Opcode::BandNot | Opcode::BorNot | Opcode::BxorNot => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let alu_op = match op {
+                Opcode::BandNot => AluOPRRR::And,
+                Opcode::BorNot => AluOPRRR::Or,
+                Opcode::BxorNot => AluOPRRR::Xor,
+                _ => unreachable!(),
+            };
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            alu_rr_imm12(ctx, AluOPRRI::Xori, tmp, rs2, -1);
+            alu_rrr(ctx, alu_op, rd, rs1, tmp.to_reg());
+        }
+
+        Opcode::Bnot => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            alu_rr_imm12(ctx, AluOPRRI::Xori, rd, rs, -1);
+        }
+
+        Opcode::Ishl | Opcode::Ushr | Opcode::Sshr => {
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let narrow_mode = match op {
+                Opcode::Ishl => NarrowValueMode::None,
+                Opcode::Ushr => NarrowValueMode::ZeroExtend64,
+                Opcode::Sshr => NarrowValueMode::SignExtend64,
+                _ => unreachable!(),
+            };
+            // Shifts of 32- and 64-bit values have native forms that take the shift amount modulo
+            // the type width; narrower values are shifted as (extended) 64-bit values with the
+            // amount masked explicitly.
+            let (alu_op, alu_op_imm, narrow_mode) = match (op, bits) {
+                (Opcode::Ishl, 64) => (AluOPRRR::Sll, AluOPRRI::Slli, NarrowValueMode::None),
+                (Opcode::Ushr, 64) => (AluOPRRR::Srl, AluOPRRI::Srli, NarrowValueMode::None),
+                (Opcode::Sshr, 64) => (AluOPRRR::Sra, AluOPRRI::Srai, NarrowValueMode::None),
+                (Opcode::Ishl, 32) => (AluOPRRR::Sllw, AluOPRRI::Slliw, NarrowValueMode::None),
+                (Opcode::Ushr, 32) => (AluOPRRR::Srlw, AluOPRRI::Srliw, NarrowValueMode::None),
+                (Opcode::Sshr, 32) => (AluOPRRR::Sraw, AluOPRRI::Sraiw, NarrowValueMode::None),
+                (Opcode::Ishl, _) => (AluOPRRR::Sll, AluOPRRI::Slli, narrow_mode),
+                (Opcode::Ushr, _) => (AluOPRRR::Srl, AluOPRRI::Srli, narrow_mode),
+                (Opcode::Sshr, _) => (AluOPRRR::Sra, AluOPRRI::Srai, narrow_mode),
+                _ => unreachable!(),
+            };
+            let rs1 = put_input_in_reg(ctx, inputs[0], narrow_mode);
+            if let Some(amt) = input_to_const(ctx, inputs[1]) {
+                let amt = (amt & (bits as u64 - 1)) as i64;
+                alu_rr_imm12(ctx, alu_op_imm, rd, rs1, amt);
+            } else {
+                let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+                let rs2 = if bits < 32 {
+                    let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                    alu_rr_imm12(ctx, AluOPRRI::Andi, tmp, rs2, bits as i64 - 1);
+                    tmp.to_reg()
+                } else {
+                    rs2
+                };
+                alu_rrr(ctx, alu_op, rd, rs1, rs2);
+            }
+        }
+
+        Opcode::Rotl | Opcode::Rotr => {
+            // rotl(x, n) = (x << n) | (x >> (bits - n)), with both amounts taken modulo the type
+            // width; rotr is the same with the shifts swapped.
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty) as i64;
+            let rd = get_output_reg(ctx, outputs[0]);
+            let (left, right) = match bits {
+                64 => (AluOPRRR::Sll, AluOPRRR::Srl),
+                32 => (AluOPRRR::Sllw, AluOPRRR::Srlw),
+                _ => (AluOPRRR::Sll, AluOPRRR::Srl),
+            };
+            let (left, right) = if op == Opcode::Rotl {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+            let amt = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let tmp1 = ctx.alloc_tmp(RegClass::I64, I64);
+            let tmp2 = ctx.alloc_tmp(RegClass::I64, I64);
+            let amt = if bits < 32 {
+                let masked = ctx.alloc_tmp(RegClass::I64, I64);
+                alu_rr_imm12(ctx, AluOPRRI::Andi, masked, amt, bits - 1);
+                masked.to_reg()
+            } else {
+                amt
+            };
+            alu_rrr(ctx, left, tmp1, rs, amt);
+            alu_rrr(ctx, AluOPRRR::Sub, tmp2, zero_reg(), amt);
+            if bits < 32 {
+                alu_rr_imm12(ctx, AluOPRRI::Andi, tmp2, tmp2.to_reg(), bits - 1);
+            }
+            alu_rrr(ctx, right, tmp2, rs, tmp2.to_reg());
+            alu_rrr(ctx, AluOPRRR::Or, rd, tmp1.to_reg(), tmp2.to_reg());
+        }
+
+        Opcode::Bitrev => {
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty) as i64;
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            lower_bitrev64(ctx, rd, rs);
+            if bits < 64 {
+                alu_rr_imm12(ctx, AluOPRRI::Srli, rd, rd.to_reg(), 64 - bits);
+            }
+        }
+
+        Opcode::Clz => {
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty) as i64;
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+            lower_clz64(ctx, rd, rs);
+            if bits < 64 {
+                alu_rr_imm12(ctx, AluOPRRI::Addi, rd, rd.to_reg(), -(64 - bits));
+            }
+        }
+
+        Opcode::Cls => {
+            // cls(x) = clz(x ^ (x >> 63)) - 1, on the sign-extended value.
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty) as i64;
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::SignExtend64);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            alu_rr_imm12(ctx, AluOPRRI::Srai, tmp, rs, 63);
+            alu_rrr(ctx, AluOPRRR::Xor, tmp, tmp.to_reg(), rs);
+            lower_clz64(ctx, rd, tmp.to_reg());
+            alu_rr_imm12(ctx, AluOPRRI::Addi, rd, rd.to_reg(), -1 - (64 - bits));
+        }
+
+        Opcode::Ctz => {
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs = if bits < 64 {
+                // Set the bit just above the value, so that a zero input counts `bits` zeros.
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                lower_constant_u64(ctx, tmp, 1u64 << bits);
+                alu_rrr(ctx, AluOPRRR::Or, tmp, tmp.to_reg(), rs);
+                tmp.to_reg()
+            } else {
+                rs
+            };
+            lower_ctz64(ctx, rd, rs);
+        }
+
+        Opcode::Popcnt => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+            lower_popcnt64(ctx, rd, rs);
+        }
+
+        Opcode::Iabs => {
+            // iabs(x) = (x ^ s) - s, where s = x >> 63 on the sign-extended value.
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::SignExtend64);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            alu_rr_imm12(ctx, AluOPRRI::Srai, tmp, rs, 63);
+            alu_rrr(ctx, AluOPRRR::Xor, rd, rs, tmp.to_reg());
+            alu_rrr(ctx, AluOPRRR::Sub, rd, rd.to_reg(), tmp.to_reg());
+        }
+
+        Opcode::Imin | Opcode::Umin | Opcode::Imax | Opcode::Umax => {
+            let is_signed = op == Opcode::Imin || op == Opcode::Imax;
+            let is_min = op == Opcode::Imin || op == Opcode::Umin;
+            let (narrow_mode, alu_op) = if is_signed {
+                (NarrowValueMode::SignExtend64, AluOPRRR::Slt)
+            } else {
+                (NarrowValueMode::ZeroExtend64, AluOPRRR::Sltu)
+            };
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs1 = put_input_in_reg(ctx, inputs[0], narrow_mode);
+            let rs2 = put_input_in_reg(ctx, inputs[1], narrow_mode);
+            let cond = ctx.alloc_tmp(RegClass::I64, I64);
+            alu_rrr(ctx, alu_op, cond, rs1, rs2);
+            let (rs_true, rs_false) = if is_min { (rs1, rs2) } else { (rs2, rs1) };
+            ctx.emit(Inst::Select {
+                rd,
+                cond: cond.to_reg(),
+                rs_true,
+                rs_false,
+            });
+        }
+
+        Opcode::IaddCin
+        | Opcode::IaddIfcin
+        | Opcode::IaddCout
+        | Opcode::IaddIfcout
+        | Opcode::IaddCarry
+        | Opcode::IaddIfcarry
+        | Opcode::IsubBin
+        | Opcode::IsubIfbin
+        | Opcode::IsubBout
+        | Opcode::IsubIfbout
+        | Opcode::IsubBorrow
+        | Opcode::IsubIfborrow => {
+            // The carry and borrow flags are kept as 0 / 1 in integer registers.
+            let is_add = match op {
+                Opcode::IaddCin
+                | Opcode::IaddIfcin
+                | Opcode::IaddCout
+                | Opcode::IaddIfcout
+                | Opcode::IaddCarry
+                | Opcode::IaddIfcarry => true,
+                _ => false,
+            };
+            let has_cin = match op {
+                Opcode::IaddCin
+                | Opcode::IaddIfcin
+                | Opcode::IaddCarry
+                | Opcode::IaddIfcarry
+                | Opcode::IsubBin
+                | Opcode::IsubIfbin
+                | Opcode::IsubBorrow
+                | Opcode::IsubIfborrow => true,
+                _ => false,
+            };
+            let has_cout = outputs.len() > 1;
+
+            if has_cin {
+                if let Some(src) = ctx.get_input(insn, 2).inst {
+                    if ctx.data(src.0).opcode() == Opcode::Ifcmp {
+                        return Err(CodegenError::Unsupported(format!(
+                            "{} with flags from ifcmp",
+                            op
+                        )));
+                    }
+                }
+            }
+
+            let ty = ty.unwrap();
+            let bits = ty_bits(ty);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let narrow_mode = if has_cout {
+                NarrowValueMode::ZeroExtend64
+            } else {
+                NarrowValueMode::None
+            };
+            let rs1 = put_input_in_reg(ctx, inputs[0], narrow_mode);
+            let rs2 = put_input_in_reg(ctx, inputs[1], narrow_mode);
+            let cin = if has_cin {
+                // A `b1` carry is extended by masking it; flags are already 0 / 1.
+                let cin_ty = ctx.input_ty(insn, 2);
+                let cin_mode = if cin_ty.is_bool() {
+                    NarrowValueMode::ZeroExtend64
+                } else {
+                    NarrowValueMode::None
+                };
+                Some(put_input_in_reg(ctx, inputs[2], cin_mode))
+            } else {
+                None
+            };
+            let alu_op = if is_add { AluOPRRR::Add } else { AluOPRRR::Sub };
+
+            if !has_cout {
+                alu_rrr(ctx, alu_op, rd, rs1, rs2);
+                if let Some(cin) = cin {
+                    alu_rrr(ctx, alu_op, rd, rd.to_reg(), cin);
+                }
+            } else if bits < 64 {
+                // The operation on the zero-extended operands is exact in 64 bits: the carry is
+                // the bit just above the result, and a borrow makes the result negative.
+                let cout = get_output_reg(ctx, outputs[1]);
+                alu_rrr(ctx, alu_op, rd, rs1, rs2);
+                if let Some(cin) = cin {
+                    alu_rrr(ctx, alu_op, rd, rd.to_reg(), cin);
+                }
+                if is_add {
+                    alu_rr_imm12(ctx, AluOPRRI::Srli, cout, rd.to_reg(), bits as i64);
+                } else {
+                    alu_rr_imm12(ctx, AluOPRRI::Srli, cout, rd.to_reg(), 63);
+                }
+            } else {
+                let cout = get_output_reg(ctx, outputs[1]);
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                alu_rrr(ctx, alu_op, tmp, rs1, rs2);
+                if is_add {
+                    // The sum wrapped iff it is below an operand.
+                    alu_rrr(ctx, AluOPRRR::Sltu, cout, tmp.to_reg(), rs1);
+                } else {
+                    alu_rrr(ctx, AluOPRRR::Sltu, cout, rs1, rs2);
+                }
+                if let Some(cin) = cin {
+                    let cout2 = ctx.alloc_tmp(RegClass::I64, I64);
+                    alu_rrr(ctx, alu_op, rd, tmp.to_reg(), cin);
+                    if is_add {
+                        alu_rrr(ctx, AluOPRRR::Sltu, cout2, rd.to_reg(), tmp.to_reg());
+                    } else {
+                        alu_rrr(ctx, AluOPRRR::Sltu, cout2, tmp.to_reg(), cin);
+                    }
+                    alu_rrr(ctx, AluOPRRR::Or, cout, cout.to_reg(), cout2.to_reg());
+                } else {
+                    ctx.emit(Inst::gen_move(rd, tmp.to_reg(), I64));
+                }
+            }
+        }
+
+        Opcode::Icmp => {
+            let cc = ctx.data(insn).cond_code().unwrap();
+            let cmp = lower_icmp(ctx, cc, inputs[0], inputs[1]);
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_int_compare_to_reg(ctx, cmp, rd);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
+        Opcode::Fcmp => {
+            let cc = ctx.data(insn).fp_cond_code().unwrap();
+            let ty = ctx.input_ty(insn, 0);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_fcmp_to_reg(ctx, cc, ty, rs1, rs2, rd);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
+        Opcode::Ifcmp | Opcode::Ffcmp => {
+            // An Ifcmp/Ffcmp must always be seen as a use of a brif/brff or trueif/trueff
+            // instruction. This will always be the case as long as the IR uses an Ifcmp/Ffcmp from
+            // the same block, or a dominating block. In other words, it cannot pass through a BB
+            // param (phi). The flags pass of the verifier will ensure this.
+            panic!("Should never reach ifcmp as isel root!");
+        }
+
+        Opcode::Trueif => {
+            let cc = ctx.data(insn).cond_code().unwrap();
+            let cmp = lower_iflags_compare(ctx, inputs[0], cc)?;
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_int_compare_to_reg(ctx, cmp, rd);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
+        Opcode::Trueff => {
+            let cc = ctx.data(insn).fp_cond_code().unwrap();
+            let ffcmp_insn = maybe_input_insn(ctx, inputs[0], Opcode::Ffcmp).unwrap();
+            let ty = ctx.input_ty(ffcmp_insn, 0);
+            let rs1 = put_input_in_reg(
+                ctx,
+                InsnInput {
+                    insn: ffcmp_insn,
+                    input: 0,
+                },
+                NarrowValueMode::None,
+            );
+            let rs2 = put_input_in_reg(
+                ctx,
+                InsnInput {
+                    insn: ffcmp_insn,
+                    input: 1,
+                },
+                NarrowValueMode::None,
+            );
+            let rd = get_output_reg(ctx, outputs[0]);
+            lower_fcmp_to_reg(ctx, cc, ty, rs1, rs2, rd);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
+        Opcode::Select => {
+            let cond = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+            let rs_true = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rs_false = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::Select {
+                rd,
+                cond,
+                rs_true,
+                rs_false,
+            });
+        }
+
+        Opcode::Selectif | Opcode::SelectifSpectreGuard => {
+            let cc = ctx.data(insn).cond_code().unwrap();
+            let cmp = lower_iflags_compare(ctx, inputs[0], cc)?;
+            let cond = ctx.alloc_tmp(RegClass::I64, I64);
+            lower_int_compare_to_reg(ctx, cmp, cond);
+            let rs_true = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rs_false = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let ty = ty.unwrap();
+
+            if op == Opcode::SelectifSpectreGuard && !ty_has_float_or_vec_representation(ty) {
+                // A Spectre guard must not be predicted, so select without branching:
+                // rd = rs_false ^ ((rs_true ^ rs_false) & -cond).
+                let mask = ctx.alloc_tmp(RegClass::I64, I64);
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                alu_rrr(ctx, AluOPRRR::Sub, mask, zero_reg(), cond.to_reg());
+                alu_rrr(ctx, AluOPRRR::Xor, tmp, rs_true, rs_false);
+                alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), mask.to_reg());
+                alu_rrr(ctx, AluOPRRR::Xor, rd, rs_false, tmp.to_reg());
+            } else {
+                ctx.emit(Inst::Select {
+                    rd,
+                    cond: cond.to_reg(),
+                    rs_true,
+                    rs_false,
+                });
+            }
+        }
+
+        Opcode::Bitselect => {
+            // rd = y ^ ((x ^ y) & c)
+            let ty = ty.unwrap();
+            if ty_has_float_or_vec_representation(ty) {
+                return Err(CodegenError::Unsupported(format!(
+                    "Unsupported type for bitselect: {}",
+                    ty
+                )));
+            }
+            let rc = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rx = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let ry = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            alu_rrr(ctx, AluOPRRR::Xor, tmp, rx, ry);
+            alu_rrr(ctx, AluOPRRR::And, tmp, tmp.to_reg(), rc);
+            alu_rrr(ctx, AluOPRRR::Xor, rd, ry, tmp.to_reg());
+        }
+
+        Opcode::Bint => {
+            // Booleans are stored as all-zeroes (0) or all-ones (-1). We AND out
+            // the LSB to give a 0 / 1-valued integer result.
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            alu_rr_imm12(ctx, AluOPRRI::Andi, rd, rs, 1);
+        }
+
+        Opcode::Bmask => {
+            // Bool is {0, 1}, so we can subtract from 0 to get all-1s.
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            alu_rr_imm12(ctx, AluOPRRI::Andi, rd, rs, 1);
+            alu_rrr(ctx, AluOPRRR::Sub, rd, zero_reg(), rd.to_reg());
+        }
+
+        Opcode::Bextend => {
+            let from_ty = ctx.input_ty(insn, 0);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            if from_ty == B1 {
+                // A `b1` true is 1; widen it to all ones.
+                alu_rr_imm12(ctx, AluOPRRI::Andi, rd, rs, 1);
+                alu_rrr(ctx, AluOPRRR::Sub, rd, zero_reg(), rd.to_reg());
+            } else {
+                let from_bits = ty_bits(from_ty) as u8;
+                if from_bits < 64 {
+                    ctx.emit(Inst::Extend {
+                        rd,
+                        rn: rs,
+                        signed: true,
+                        from_bits,
+                        to_bits: 64,
+                    });
+                } else {
+                    ctx.emit(Inst::gen_move(rd, rs, I64));
+                }
+            }
+        }
+
+        Opcode::Breduce | Opcode::Ireduce => {
+            // Smaller integers/booleans are stored with high-order bits
+            // undefined, so we can simply do a copy.
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::gen_move(rd, rs, I64));
+        }
+
+        Opcode::Uextend | Opcode::Sextend => {
+            let from_bits = ty_bits(ctx.input_ty(insn, 0)) as u8;
+            let signed = op == Opcode::Sextend;
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            if from_bits < 64 {
+                ctx.emit(Inst::Extend {
+                    rd,
+                    rn: rs,
+                    signed,
+                    from_bits,
+                    to_bits: 64,
+                });
+            } else {
+                ctx.emit(Inst::gen_move(rd, rs, I64));
+            }
+        }
+
+        Opcode::IsNull | Opcode::IsInvalid => {
+            // Null references are represented by the constant value 0; invalid references are
+            // represented by the constant value -1.
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = if op == Opcode::IsInvalid {
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                alu_rr_imm12(ctx, AluOPRRI::Addi, tmp, rs, 1);
+                tmp.to_reg()
+            } else {
+                rs
+            };
+            alu_rr_imm12(ctx, AluOPRRI::Sltiu, rd, rs, 1);
+            normalize_bool_result(ctx, insn, rd);
+        }
+
+        Opcode::Copy => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let ty = ctx.input_ty(insn, 0);
+            ctx.emit(Inst::gen_move(rd, rs, ty));
+        }
+
+        Opcode::Bitcast | Opcode::RawBitcast => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let ity = ctx.input_ty(insn, 0);
+            let oty = ty.unwrap();
+            let ity_is_float = ty_has_float_or_vec_representation(ity);
+            let oty_is_float = ty_has_float_or_vec_representation(oty);
+            match (ity_is_float, oty_is_float) {
+                (true, true) | (false, false) => {
+                    ctx.emit(Inst::gen_move(rd, rs, oty));
+                }
+                (true, false) => {
+                    ctx.emit(Inst::FpuRR {
+                        alu_op: choose_f32_f64(ity, FpuOPRR::FmvXW, FpuOPRR::FmvXD),
+                        frm: FRM::RNE,
+                        rd,
+                        rs,
+                    });
+                }
+                (false, true) => {
+                    ctx.emit(Inst::FpuRR {
+                        alu_op: choose_f32_f64(oty, FpuOPRR::FmvWX, FpuOPRR::FmvDX),
+                        frm: FRM::RNE,
+                        rd,
+                        rs,
+                    });
+                }
+            }
+        }
+
+        Opcode::Fadd | Opcode::Fsub | Opcode::Fmul | Opcode::Fdiv => {
+            let ty = ty.unwrap();
+            let alu_op = match op {
+                Opcode::Fadd => choose_f32_f64(ty, FpuOPRRR::FaddS, FpuOPRRR::FaddD),
+                Opcode::Fsub => choose_f32_f64(ty, FpuOPRRR::FsubS, FpuOPRRR::FsubD),
+                Opcode::Fmul => choose_f32_f64(ty, FpuOPRRR::FmulS, FpuOPRRR::FmulD),
+                Opcode::Fdiv => choose_f32_f64(ty, FpuOPRRR::FdivS, FpuOPRRR::FdivD),
+                _ => unreachable!(),
+            };
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRRR {
+                alu_op,
+                frm: FRM::RNE,
+                rd,
+                rs1,
+                rs2,
+            });
+        }
+
+        Opcode::Fneg | Opcode::Fabs | Opcode::Fcopysign => {
+            // These are all sign-injections: fneg.s rd, rs = fsgnjn.s rd, rs, rs and
+            // fabs.s rd, rs = fsgnjx.s rd, rs, rs.
+            let ty = ty.unwrap();
+            let alu_op = match op {
+                Opcode::Fneg => choose_f32_f64(ty, FpuOPRRR::FsgnjnS, FpuOPRRR::FsgnjnD),
+                Opcode::Fabs => choose_f32_f64(ty, FpuOPRRR::FsgnjxS, FpuOPRRR::FsgnjxD),
+                Opcode::Fcopysign => choose_f32_f64(ty, FpuOPRRR::FsgnjS, FpuOPRRR::FsgnjD),
+                _ => unreachable!(),
+            };
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = if op == Opcode::Fcopysign {
+                put_input_in_reg(ctx, inputs[1], NarrowValueMode::None)
+            } else {
+                rs1
+            };
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRRR {
+                alu_op,
+                frm: FRM::RNE,
+                rd,
+                rs1,
+                rs2,
+            });
+        }
+
+        Opcode::Fmin | Opcode::Fmax => {
+            // `fmin`/`fmax` return the non-NaN operand when only one of them is a NaN, but the
+            // Cranelift semantics are to propagate NaNs; `rs1 + rs2` is a NaN in that case.
+            let ty = ty.unwrap();
+            let alu_op = match op {
+                Opcode::Fmin => choose_f32_f64(ty, FpuOPRRR::FminS, FpuOPRRR::FminD),
+                Opcode::Fmax => choose_f32_f64(ty, FpuOPRRR::FmaxS, FpuOPRRR::FmaxD),
+                _ => unreachable!(),
+            };
+            let feq = choose_f32_f64(ty, FpuOPRRR::FeqS, FpuOPRRR::FeqD);
+            let fadd = choose_f32_f64(ty, FpuOPRRR::FaddS, FpuOPRRR::FaddD);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let result = ctx.alloc_tmp(RegClass::F64, ty);
+            let nan = ctx.alloc_tmp(RegClass::F64, ty);
+            let ordered = ctx.alloc_tmp(RegClass::I64, I64);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::FpuRRR {
+                alu_op,
+                frm: FRM::RNE,
+                rd: result,
+                rs1,
+                rs2,
+            });
+            ctx.emit(Inst::FpuRRR {
+                alu_op: fadd,
+                frm: FRM::RNE,
+                rd: nan,
+                rs1,
+                rs2,
+            });
+            ctx.emit(Inst::FpuRRR {
+                alu_op: feq,
+                frm: FRM::RNE,
+                rd: ordered,
+                rs1,
+                rs2: rs1,
+            });
+            ctx.emit(Inst::FpuRRR {
+                alu_op: feq,
+                frm: FRM::RNE,
+                rd: tmp,
+                rs1: rs2,
+                rs2,
+            });
+            alu_rrr(ctx, AluOPRRR::And, ordered, ordered.to_reg(), tmp.to_reg());
+            ctx.emit(Inst::Select {
+                rd,
+                cond: ordered.to_reg(),
+                rs_true: result.to_reg(),
+                rs_false: nan.to_reg(),
+            });
+        }
+
+        Opcode::FminPseudo | Opcode::FmaxPseudo => {
+            // fmin_pseudo(a, b) = b < a ? b : a; fmax_pseudo(a, b) = a < b ? b : a.
+            let ty = ty.unwrap();
+            let flt = choose_f32_f64(ty, FpuOPRRR::FltS, FpuOPRRR::FltD);
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let cond = ctx.alloc_tmp(RegClass::I64, I64);
+            let (lhs, rhs) = if op == Opcode::FminPseudo {
+                (rs2, rs1)
+            } else {
+                (rs1, rs2)
+            };
+            ctx.emit(Inst::FpuRRR {
+                alu_op: flt,
+                frm: FRM::RNE,
+                rd: cond,
+                rs1: lhs,
+                rs2: rhs,
+            });
+            ctx.emit(Inst::Select {
+                rd,
+                cond: cond.to_reg(),
+                rs_true: rs2,
+                rs_false: rs1,
+            });
+        }
+
+        Opcode::Sqrt => {
+            let ty = ty.unwrap();
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRR {
+                alu_op: choose_f32_f64(ty, FpuOPRR::FsqrtS, FpuOPRR::FsqrtD),
+                frm: FRM::RNE,
+                rd,
+                rs,
+            });
+        }
+
+        Opcode::Fma => {
+            let ty = ty.unwrap();
+            let rs1 = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rs2 = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rs3 = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRRRR {
+                alu_op: choose_f32_f64(ty, FpuOPRRRR::FmaddS, FpuOPRRRR::FmaddD),
+                frm: FRM::RNE,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+            });
+        }
+
+        Opcode::Ceil | Opcode::Floor | Opcode::Trunc | Opcode::Nearest => {
+            let op = match op {
+                Opcode::Ceil => FloatRoundOP::Ceil,
+                Opcode::Floor => FloatRoundOP::Floor,
+                Opcode::Trunc => FloatRoundOP::Trunc,
+                Opcode::Nearest => FloatRoundOP::Nearest,
+                _ => unreachable!(),
+            };
+            let ty = ty.unwrap();
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let int_tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            let f_tmp = ctx.alloc_tmp(RegClass::F64, ty);
+            ctx.emit(Inst::FloatRound {
+                op,
+                ty,
+                rd,
+                rs,
+                int_tmp,
+                f_tmp,
+            });
+        }
+
+        Opcode::Fpromote => {
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRR {
+                alu_op: FpuOPRR::FcvtDS,
+                frm: FRM::RNE,
+                rd,
+                rs,
+            });
+        }
+
+        Opcode::Fdemote => {
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRR {
+                alu_op: FpuOPRR::FcvtSD,
+                frm: FRM::RNE,
+                rd,
+                rs,
+            });
+        }
+
+        Opcode::FcvtFromSint | Opcode::FcvtFromUint => {
+            let signed = op == Opcode::FcvtFromSint;
+            let in_bits = ty_bits(ctx.input_ty(insn, 0));
+            let out_ty = ty.unwrap();
+            // Narrow inputs are extended and converted from 64 bits.
+            let narrow_mode = match (in_bits, signed) {
+                (32, _) | (64, _) => NarrowValueMode::None,
+                (_, true) => NarrowValueMode::SignExtend64,
+                (_, false) => NarrowValueMode::ZeroExtend64,
+            };
+            let alu_op = match (in_bits, signed) {
+                (32, true) => choose_f32_f64(out_ty, FpuOPRR::FcvtSW, FpuOPRR::FcvtDW),
+                (32, false) => choose_f32_f64(out_ty, FpuOPRR::FcvtSWu, FpuOPRR::FcvtDWu),
+                (_, true) => choose_f32_f64(out_ty, FpuOPRR::FcvtSL, FpuOPRR::FcvtDL),
+                (_, false) => choose_f32_f64(out_ty, FpuOPRR::FcvtSLu, FpuOPRR::FcvtDLu),
+            };
+            let rs = put_input_in_reg(ctx, inputs[0], narrow_mode);
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::FpuRR {
+                alu_op,
+                frm: FRM::RNE,
+                rd,
+                rs,
+            });
+        }
+
+        Opcode::FcvtToSint | Opcode::FcvtToUint => {
+            let signed = op == Opcode::FcvtToSint;
+            let in_ty = ctx.input_ty(insn, 0);
+            let out_ty = ty.unwrap();
+            let out_bits = ty_bits(out_ty);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+
+            // Check for NaN.
+            ctx.emit(Inst::FpuRRR {
+                alu_op: choose_f32_f64(in_ty, FpuOPRRR::FeqS, FpuOPRRR::FeqD),
+                frm: FRM::RNE,
+                rd: tmp,
+                rs1: rs,
+                rs2: rs,
+            });
+            ctx.emit_safepoint(Inst::TrapIf {
+                cond: IntegerCompare {
+                    kind: IntCC::Equal,
+                    rs1: tmp.to_reg(),
+                    rs2: zero_reg(),
+                },
+                trap_code: TrapCode::BadConversionToInteger,
+            });
+
+            // Convert, and check the invalid-operation flag for out-of-range values. Narrow
+            // results are converted to 64 bits and range-checked below.
+            let alu_op = match (out_bits, signed) {
+                (32, true) => choose_f32_f64(in_ty, FpuOPRR::FcvtWS, FpuOPRR::FcvtWD),
+                (32, false) => choose_f32_f64(in_ty, FpuOPRR::FcvtWuS, FpuOPRR::FcvtWuD),
+                (_, true) => choose_f32_f64(in_ty, FpuOPRR::FcvtLS, FpuOPRR::FcvtLD),
+                (_, false) => choose_f32_f64(in_ty, FpuOPRR::FcvtLuS, FpuOPRR::FcvtLuD),
+            };
+            ctx.emit(Inst::ClearFflags);
+            ctx.emit(Inst::FpuRR {
+                alu_op,
+                frm: FRM::RTZ,
+                rd,
+                rs,
+            });
+            ctx.emit(Inst::ReadFflags { rd: tmp });
+            // NV is bit 4 of fflags.
+            alu_rr_imm12(ctx, AluOPRRI::Andi, tmp, tmp.to_reg(), 0x10);
+            ctx.emit_safepoint(Inst::TrapIf {
+                cond: IntegerCompare {
+                    kind: IntCC::NotEqual,
+                    rs1: tmp.to_reg(),
+                    rs2: zero_reg(),
+                },
+                trap_code: TrapCode::IntegerOverflow,
+            });
+
+            if out_bits < 32 {
+                ctx.emit(Inst::Extend {
+                    rd: tmp,
+                    rn: rd.to_reg(),
+                    signed,
+                    from_bits: out_bits as u8,
+                    to_bits: 64,
+                });
+                ctx.emit_safepoint(Inst::TrapIf {
+                    cond: IntegerCompare {
+                        kind: IntCC::NotEqual,
+                        rs1: tmp.to_reg(),
+                        rs2: rd.to_reg(),
+                    },
+                    trap_code: TrapCode::IntegerOverflow,
+                });
+            }
+        }
+
+        Opcode::FcvtToSintSat | Opcode::FcvtToUintSat => {
+            let signed = op == Opcode::FcvtToSintSat;
+            let in_ty = ctx.input_ty(insn, 0);
+            let out_ty = ty.unwrap();
+            let out_bits = ty_bits(out_ty);
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let rd = get_output_reg(ctx, outputs[0]);
+
+            // The conversions saturate out-of-range values, but convert NaN to the maximum value:
+            // mask the result with the all-ones `-(rs == rs)` to get 0 instead.
+            let alu_op = match (out_bits, signed) {
+                (32, true) => choose_f32_f64(in_ty, FpuOPRR::FcvtWS, FpuOPRR::FcvtWD),
+                (32, false) => choose_f32_f64(in_ty, FpuOPRR::FcvtWuS, FpuOPRR::FcvtWuD),
+                (_, true) => choose_f32_f64(in_ty, FpuOPRR::FcvtLS, FpuOPRR::FcvtLD),
+                (_, false) => choose_f32_f64(in_ty, FpuOPRR::FcvtLuS, FpuOPRR::FcvtLuD),
+            };
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::FpuRR {
+                alu_op,
+                frm: FRM::RTZ,
+                rd,
+                rs,
+            });
+            ctx.emit(Inst::FpuRRR {
+                alu_op: choose_f32_f64(in_ty, FpuOPRRR::FeqS, FpuOPRRR::FeqD),
+                frm: FRM::RNE,
+                rd: tmp,
+                rs1: rs,
+                rs2: rs,
+            });
+            alu_rrr(ctx, AluOPRRR::Sub, tmp, zero_reg(), tmp.to_reg());
+            alu_rrr(ctx, AluOPRRR::And, rd, rd.to_reg(), tmp.to_reg());
+
+            if out_bits < 32 {
+                // Clamp the 64-bit result to the range of the output type.
+                let (min, max) = if signed {
+                    (-(1i64 << (out_bits - 1)), (1i64 << (out_bits - 1)) - 1)
+                } else {
+                    (0, (1i64 << out_bits) - 1)
+                };
+                let bound = ctx.alloc_tmp(RegClass::I64, I64);
+                let cmp_op = if signed {
+                    AluOPRRR::Slt
+                } else {
+                    AluOPRRR::Sltu
+                };
+                lower_constant_u64(ctx, bound, max as u64);
+                alu_rrr(ctx, cmp_op, tmp, bound.to_reg(), rd.to_reg());
+                ctx.emit(Inst::Select {
+                    rd,
+                    cond: tmp.to_reg(),
+                    rs_true: bound.to_reg(),
+                    rs_false: rd.to_reg(),
+                });
+                if signed {
+                    let bound = ctx.alloc_tmp(RegClass::I64, I64);
+                    lower_constant_u64(ctx, bound, min as u64);
+                    alu_rrr(ctx, cmp_op, tmp, rd.to_reg(), bound.to_reg());
+                    ctx.emit(Inst::Select {
+                        rd,
+                        cond: tmp.to_reg(),
+                        rs_true: bound.to_reg(),
+                        rs_false: rd.to_reg(),
+                    });
+                }
+            }
+        }
+
+        Opcode::Load
+        | Opcode::Uload8
+        | Opcode::Sload8
+        | Opcode::Uload16
+        | Opcode::Sload16
+        | Opcode::Uload32
+        | Opcode::Sload32
+        | Opcode::LoadComplex
+        | Opcode::Uload8Complex
+        | Opcode::Sload8Complex
+        | Opcode::Uload16Complex
+        | Opcode::Sload16Complex
+        | Opcode::Uload32Complex
+        | Opcode::Sload32Complex => {
+            let off = ctx.data(insn).load_store_offset().unwrap();
+            let elem_ty = match op {
+                Opcode::Sload8 | Opcode::Uload8 | Opcode::Sload8Complex | Opcode::Uload8Complex => {
+                    I8
+                }
+                Opcode::Sload16
+                | Opcode::Uload16
+                | Opcode::Sload16Complex
+                | Opcode::Uload16Complex => I16,
+                Opcode::Sload32
+                | Opcode::Uload32
+                | Opcode::Sload32Complex
+                | Opcode::Uload32Complex => I32,
+                Opcode::Load | Opcode::LoadComplex => ctx.output_ty(insn, 0),
+                _ => unreachable!(),
+            };
+            let sign_extend = match op {
+                Opcode::Sload8
+                | Opcode::Sload8Complex
+                | Opcode::Sload16
+                | Opcode::Sload16Complex
+                | Opcode::Sload32
+                | Opcode::Sload32Complex => true,
+                _ => false,
+            };
+            let is_float = ty_has_float_or_vec_representation(elem_ty);
+
+            let from = lower_address(ctx, elem_ty, &inputs[..], off);
+            let rd = get_output_reg(ctx, outputs[0]);
+
+            let op = match (ty_bits(elem_ty), sign_extend, is_float) {
+                (1, _, _) => LoadOP::Lbu,
+                (8, false, _) => LoadOP::Lbu,
+                (8, true, _) => LoadOP::Lb,
+                (16, false, _) => LoadOP::Lhu,
+                (16, true, _) => LoadOP::Lh,
+                (32, false, false) => LoadOP::Lwu,
+                (32, true, false) => LoadOP::Lw,
+                (32, _, true) => LoadOP::Flw,
+                (64, _, false) => LoadOP::Ld,
+                (64, _, true) => LoadOP::Fld,
+                _ => panic!("Unsupported size in load"),
+            };
+            ctx.emit(Inst::Load { op, rd, from });
+        }
+
+        Opcode::Store
+        | Opcode::Istore8
+        | Opcode::Istore16
+        | Opcode::Istore32
+        | Opcode::StoreComplex
+        | Opcode::Istore8Complex
+        | Opcode::Istore16Complex
+        | Opcode::Istore32Complex => {
+            let off = ctx.data(insn).load_store_offset().unwrap();
+            let elem_ty = match op {
+                Opcode::Istore8 | Opcode::Istore8Complex => I8,
+                Opcode::Istore16 | Opcode::Istore16Complex => I16,
+                Opcode::Istore32 | Opcode::Istore32Complex => I32,
+                Opcode::Store | Opcode::StoreComplex => ctx.input_ty(insn, 0),
+                _ => unreachable!(),
+            };
+            let is_float = ty_has_float_or_vec_representation(elem_ty);
+
+            let to = lower_address(ctx, elem_ty, &inputs[1..], off);
+            let src = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+
+            let op = match (ty_bits(elem_ty), is_float) {
+                (1, _) | (8, _) => StoreOP::Sb,
+                (16, _) => StoreOP::Sh,
+                (32, false) => StoreOP::Sw,
+                (32, true) => StoreOP::Fsw,
+                (64, false) => StoreOP::Sd,
+                (64, true) => StoreOP::Fsd,
+                _ => panic!("Unsupported size in store"),
+            };
+            ctx.emit(Inst::Store { op, src, to });
+        }
+
+        Opcode::StackAddr => {
+            let (stack_slot, offset) = match *ctx.data(insn) {
+                InstructionData::StackLoad {
+                    opcode: Opcode::StackAddr,
+                    stack_slot,
+                    offset,
+                } => (stack_slot, offset),
+                _ => unreachable!(),
+            };
+            let rd = get_output_reg(ctx, outputs[0]);
+            let offset: i32 = offset.into();
+            let inst = ctx
+                .abi()
+                .stackslot_addr(stack_slot, u32::try_from(offset).unwrap(), rd);
+            ctx.emit(inst);
+        }
+
+        Opcode::AtomicRmw => {
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            if ty_bits(ty_access) < 32 {
+                // The A extension only has word and doubleword AMOs; narrow accesses would need an
+                // `lr`/`sc` loop on the containing word.
+                return Err(CodegenError::Unsupported(format!(
+                    "Unsupported type for atomic_rmw: {}",
+                    ty_access
+                )));
+            }
+            let rd = get_output_reg(ctx, outputs[0]);
+            let addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let src = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let rmw_op = ctx.data(insn).atomic_rmw_op().unwrap();
+            let is_64 = ty_access == I64;
+            let src = if rmw_op == AtomicRmwOp::Sub {
+                let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+                alu_rrr(ctx, AluOPRRR::Sub, tmp, zero_reg(), src);
+                tmp.to_reg()
+            } else {
+                src
+            };
+            let op = match (rmw_op, is_64) {
+                (AtomicRmwOp::Add, false) | (AtomicRmwOp::Sub, false) => AtomicOP::AmoaddW,
+                (AtomicRmwOp::Add, true) | (AtomicRmwOp::Sub, true) => AtomicOP::AmoaddD,
+                (AtomicRmwOp::And, false) => AtomicOP::AmoandW,
+                (AtomicRmwOp::And, true) => AtomicOP::AmoandD,
+                (AtomicRmwOp::Or, false) => AtomicOP::AmoorW,
+                (AtomicRmwOp::Or, true) => AtomicOP::AmoorD,
+                (AtomicRmwOp::Xor, false) => AtomicOP::AmoxorW,
+                (AtomicRmwOp::Xor, true) => AtomicOP::AmoxorD,
+                (AtomicRmwOp::Xchg, false) => AtomicOP::AmoswapW,
+                (AtomicRmwOp::Xchg, true) => AtomicOP::AmoswapD,
+            };
+            ctx.emit(Inst::Atomic {
+                op,
+                rd,
+                addr,
+                src,
+                aq: true,
+                rl: true,
+            });
+        }
+
+        Opcode::AtomicCas => {
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            if ty_bits(ty_access) < 32 {
+                return Err(CodegenError::Unsupported(format!(
+                    "Unsupported type for atomic_cas: {}",
+                    ty_access
+                )));
+            }
+            let rd = get_output_reg(ctx, outputs[0]);
+            // The loop reads its inputs after writing `rd`, so they must live in temporaries that
+            // the instruction is allowed to modify; see `Inst::AtomicCas`.
+            let addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let expected = put_input_in_reg(ctx, inputs[1], NarrowValueMode::SignExtend64);
+            let replacement = put_input_in_reg(ctx, inputs[2], NarrowValueMode::None);
+            let addr = copy_to_tmp(ctx, addr);
+            let expected = copy_to_tmp(ctx, expected);
+            let replacement = copy_to_tmp(ctx, replacement);
+            let tmp = ctx.alloc_tmp(RegClass::I64, I64);
+            ctx.emit(Inst::AtomicCas {
+                ty: ty_access,
+                rd,
+                tmp,
+                addr,
+                expected,
+                replacement,
+            });
+        }
+
+        Opcode::AtomicLoad => {
+            // A sequentially consistent load: fence rw,rw ; load ; fence r,rw.
+            let ty_access = ty.unwrap();
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let rd = get_output_reg(ctx, outputs[0]);
+            let addr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let op = match ty_bits(ty_access) {
+                8 => LoadOP::Lbu,
+                16 => LoadOP::Lhu,
+                32 => LoadOP::Lwu,
+                64 => LoadOP::Ld,
+                _ => unreachable!(),
+            };
+            ctx.emit(Inst::Fence {
+                pred: 0b0011,
+                succ: 0b0011,
+            });
+            ctx.emit(Inst::Load {
+                op,
+                rd,
+                from: AMode::RegOffset(addr, 0, ty_access),
+            });
+            ctx.emit(Inst::Fence {
+                pred: 0b0010,
+                succ: 0b0011,
+            });
+        }
+
+        Opcode::AtomicStore => {
+            // A sequentially consistent store: fence rw,w ; store ; fence rw,rw.
+            let ty_access = ctx.input_ty(insn, 0);
+            assert!(is_valid_atomic_transaction_ty(ty_access));
+            let src = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            let addr = put_input_in_reg(ctx, inputs[1], NarrowValueMode::None);
+            let op = match ty_bits(ty_access) {
+                8 => StoreOP::Sb,
+                16 => StoreOP::Sh,
+                32 => StoreOP::Sw,
+                64 => StoreOP::Sd,
+                _ => unreachable!(),
+            };
+            ctx.emit(Inst::Fence {
+                pred: 0b0011,
+                succ: 0b0001,
+            });
+            ctx.emit(Inst::Store {
+                op,
+                src,
+                to: AMode::RegOffset(addr, 0, ty_access),
+            });
+            ctx.emit(Inst::Fence {
+                pred: 0b0011,
+                succ: 0b0011,
+            });
+        }
+
+        Opcode::Fence => {
+            ctx.emit(Inst::Fence {
+                pred: 0b0011,
+                succ: 0b0011,
+            });
+        }
+
+        Opcode::StackLoad | Opcode::StackStore => {
+            panic!("Direct stack memory access not supported; should not be used by Wasm");
+        }
+
+        Opcode::HeapAddr => {
+            panic!("heap_addr should have been removed by legalization!");
+        }
+
+        Opcode::TableAddr => {
+            panic!("table_addr should have been removed by legalization!");
+        }
+
+        Opcode::Nop => {
+            // Nothing.
+        }
+
+        Opcode::FallthroughReturn | Opcode::Return => {
+            for (i, input) in inputs.iter().enumerate() {
+                // N.B.: the upper bits of narrow return values are not used by callers, so we
+                // need not extend them.
+                let reg = put_input_in_reg(ctx, *input, NarrowValueMode::None);
+                let retval_reg = ctx.retval(i).only_reg().unwrap();
+                let ty = ctx.input_ty(insn, i);
+                ctx.emit(Inst::gen_move(retval_reg, reg, ty));
+            }
+            // N.B.: the Ret itself is generated by the ABI.
+        }
+
+        Opcode::Debugtrap => {
+            ctx.emit(Inst::Ebreak);
+        }
+
+        Opcode::Trap | Opcode::ResumableTrap => {
+            let trap_code = ctx.data(insn).trap_code().unwrap();
+            ctx.emit_safepoint(Inst::Udf { trap_code });
+        }
+
+        Opcode::Trapif => {
+            let trap_code = ctx.data(insn).trap_code().unwrap();
+            let cc = ctx.data(insn).cond_code().unwrap();
+            let cond = lower_iflags_compare(ctx, inputs[0], cc)?;
+            ctx.emit_safepoint(Inst::TrapIf { cond, trap_code });
+        }
+
+        Opcode::Trapff => {
+            let trap_code = ctx.data(insn).trap_code().unwrap();
+            let cc = ctx.data(insn).fp_cond_code().unwrap();
+            let ffcmp_insn = maybe_input_insn(ctx, inputs[0], Opcode::Ffcmp).unwrap();
+            let rs = lower_fcmp_insn_to_reg(ctx, ffcmp_insn, cc);
+            ctx.emit_safepoint(Inst::TrapIf {
+                cond: IntegerCompare {
+                    kind: IntCC::NotEqual,
+                    rs1: rs,
+                    rs2: zero_reg(),
+                },
+                trap_code,
+            });
+        }
+
+        Opcode::Safepoint => {
+            panic!("safepoint instructions not used by new backend's safepoints!");
+        }
+
+        Opcode::Trapz | Opcode::Trapnz | Opcode::ResumableTrapnz => {
+            panic!("trapz / trapnz / resumable_trapnz should have been removed by legalization!");
+        }
+
+        Opcode::FuncAddr => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let (extname, _) = ctx.call_target(insn).unwrap();
+            let extname = extname.clone();
+            ctx.emit(Inst::LoadExtName {
+                rd,
+                name: Box::new(extname),
+                offset: 0,
+            });
+        }
+
+        Opcode::GlobalValue => {
+            panic!("global_value should have been removed by legalization!");
+        }
+
+        Opcode::SymbolValue => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            let (extname, _, offset) = ctx.symbol_value(insn).unwrap();
+            let extname = extname.clone();
+            ctx.emit(Inst::LoadExtName {
+                rd,
+                name: Box::new(extname),
+                offset,
+            });
+        }
+
+        Opcode::Call | Opcode::CallIndirect => {
+            let caller_conv = ctx.abi().call_conv();
+            let (mut abi, inputs) = match op {
+                Opcode::Call => {
+                    let (extname, dist) = ctx.call_target(insn).unwrap();
+                    let extname = extname.clone();
+                    let sig = ctx.call_sig(insn).unwrap();
+                    assert!(inputs.len() == sig.params.len());
+                    assert!(outputs.len() == sig.returns.len());
+                    (
+                        Riscv64ABICaller::from_func(sig, &extname, dist, caller_conv)?,
+                        &inputs[..],
+                    )
+                }
+                Opcode::CallIndirect => {
+                    let ptr = put_input_in_reg(ctx, inputs[0], NarrowValueMode::ZeroExtend64);
+                    let sig = ctx.call_sig(insn).unwrap();
+                    assert!(inputs.len() - 1 == sig.params.len());
+                    assert!(outputs.len() == sig.returns.len());
+                    (
+                        Riscv64ABICaller::from_ptr(sig, ptr, op, caller_conv)?,
+                        &inputs[1..],
+                    )
+                }
+                _ => unreachable!(),
+            };
+
+            abi.emit_stack_pre_adjust(ctx);
+            assert!(inputs.len() == abi.num_args());
+            for (i, input) in inputs.iter().enumerate() {
+                let arg_reg = put_input_in_reg(ctx, *input, NarrowValueMode::None);
+                abi.emit_copy_regs_to_arg(ctx, i, ValueRegs::one(arg_reg));
+            }
+            abi.emit_call(ctx);
+            for (i, output) in outputs.iter().enumerate() {
+                let retval_reg = get_output_reg(ctx, *output);
+                abi.emit_copy_retval_to_regs(ctx, i, ValueRegs::one(retval_reg));
+            }
+            abi.emit_stack_post_adjust(ctx);
+        }
+
+        Opcode::GetPinnedReg => {
+            let rd = get_output_reg(ctx, outputs[0]);
+            ctx.emit(Inst::gen_move(rd, xreg(PINNED_REG), I64));
+        }
+
+        Opcode::SetPinnedReg => {
+            let rs = put_input_in_reg(ctx, inputs[0], NarrowValueMode::None);
+            ctx.emit(Inst::gen_move(writable_xreg(PINNED_REG), rs, I64));
+        }
+
+        Opcode::Spill
+        | Opcode::Fill
+        | Opcode::FillNop
+        | Opcode::Regmove
+        | Opcode::CopySpecial
+        | Opcode::CopyToSsa
+        | Opcode::CopyNop
+        | Opcode::AdjustSpDown
+        | Opcode::AdjustSpUpImm
+        | Opcode::AdjustSpDownImm
+        | Opcode::IfcmpSp
+        | Opcode::Regspill
+        | Opcode::Regfill => {
+            panic!("Unused opcode should not be encountered.");
+        }
+
+        Opcode::Jump
+        | Opcode::Fallthrough
+        | Opcode::Brz
+        | Opcode::Brnz
+        | Opcode::BrIcmp
+        | Opcode::Brif
+        | Opcode::Brff
+        | Opcode::IndirectJumpTableBr
+        | Opcode::BrTable => {
+            panic!("Branch opcode reached non-branch lowering logic!");
+        }
+
+        Opcode::JumpTableEntry | Opcode::JumpTableBase => {
+            panic!("Should not appear: we handle BrTable directly");
+        }
+
+        Opcode::IaddImm
+        | Opcode::ImulImm
+        | Opcode::UdivImm
+        | Opcode::SdivImm
+        | Opcode::UremImm
+        | Opcode::SremImm
+        | Opcode::IrsubImm
+        | Opcode::BandImm
+        | Opcode::BorImm
+        | Opcode::BxorImm
+        | Opcode::RotlImm
+        | Opcode::RotrImm
+        | Opcode::IshlImm
+        | Opcode::UshrImm
+        | Opcode::SshrImm
+        | Opcode::IcmpImm
+        | Opcode::IfcmpImm => {
+            panic!("ALU+imm ops should not appear here!");
+        }
+
+        #[cfg(feature = "x86")]
+        Opcode::X86Udivmodx
+        | Opcode::X86Sdivmodx
+        | Opcode::X86Umulx
+        | Opcode::X86Smulx
+        | Opcode::X86Cvtt2si
+        | Opcode::X86Fmin
+        | Opcode::X86Fmax
+        | Opcode::X86Push
+        | Opcode::X86Pop
+        | Opcode::X86Bsr
+        | Opcode::X86Bsf
+        | Opcode::X86Pblendw
+        | Opcode::X86Pshufd
+        | Opcode::X86Pshufb
+        | Opcode::X86Pextr
+        | Opcode::X86Pinsr
+        | Opcode::X86Insertps
+        | Opcode::X86Movsd
+        | Opcode::X86Movlhps
+        | Opcode::X86Palignr
+        | Opcode::X86Psll
+        | Opcode::X86Psrl
+        | Opcode::X86Psra
+        | Opcode::X86Ptest
+        | Opcode::X86Pmaxs
+        | Opcode::X86Pmaxu
+        | Opcode::X86Pmins
+        | Opcode::X86Pminu
+        | Opcode::X86Pmullq
+        | Opcode::X86Pmuludq
+        | Opcode::X86Punpckh
+        | Opcode::X86Punpckl
+        | Opcode::X86Vcvtudq2ps
+        | Opcode::X86ElfTlsGetAddr
+        | Opcode::X86MachoTlsGetAddr => {
+            panic!("x86-specific opcode in supposedly arch-neutral IR!");
+        }
+
+        Opcode::DummySargT => unreachable!(),
+
+        Opcode::Vconst
+        | Opcode::Shuffle
+        | Opcode::Splat
+        | Opcode::Swizzle
+        | Opcode::Insertlane
+        | Opcode::Extractlane
+        | Opcode::ScalarToVector
+        | Opcode::Vsplit
+        | Opcode::Vconcat
+        | Opcode::Vselect
+        | Opcode::VanyTrue
+        | Opcode::VallTrue
+        | Opcode::VhighBits
+        | Opcode::UaddSat
+        | Opcode::SaddSat
+        | Opcode::UsubSat
+        | Opcode::SsubSat
+        | Opcode::AvgRound
+        | Opcode::Snarrow
+        | Opcode::Unarrow
+        | Opcode::SwidenLow
+        | Opcode::SwidenHigh
+        | Opcode::UwidenLow
+        | Opcode::UwidenHigh
+        | Opcode::WideningPairwiseDotProductS
+        | Opcode::Uload8x8
+        | Opcode::Sload8x8
+        | Opcode::Uload16x4
+        | Opcode::Sload16x4
+        | Opcode::Uload32x2
+        | Opcode::Sload32x2
+        | Opcode::Uload8x8Complex
+        | Opcode::Sload8x8Complex
+        | Opcode::Uload16x4Complex
+        | Opcode::Sload16x4Complex
+        | Opcode::Uload32x2Complex
+        | Opcode::Sload32x2Complex
+        | Opcode::LoadSplat => {
+            // The V extension is not supported yet.
+            return Err(CodegenError::Unsupported(format!(
+                "Unsupported SIMD opcode: {}",
+                op
+            )));
+        }
+
+        Opcode::Isplit | Opcode::Iconcat => {
+            return Err(CodegenError::Unsupported(format!(
+                "Unsupported I128 opcode: {}",
+                op
+            )));
+        }
+
+        Opcode::ConstAddr | Opcode::TlsValue => {
+            return Err(CodegenError::Unsupported(format!(
+                "Unimplemented lowering: {}",
+                op
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn lower_branch<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    branches: &[IRInst],
+    targets: &[MachLabel],
+) -> CodegenResult<()> {
+    // A block should end with at most two branches. The first may be a
+    // conditional branch; a conditional branch can be followed only by an
+    // unconditional branch or fallthrough. Otherwise, if only one branch,
+    // it may be an unconditional branch, a fallthrough, a return, or a
+    // trap. These conditions are verified by `is_ebb_basic()` during the
+    // verifier pass.
+    assert!(branches.len() <= 2);
+
+    if branches.len() == 2 {
+        // Must be a conditional branch followed by an unconditional branch.
+        let op0 = ctx.data(branches[0]).opcode();
+        let op1 = ctx.data(branches[1]).opcode();
+
+        assert!(op1 == Opcode::Jump || op1 == Opcode::Fallthrough);
+        let taken = BranchTarget::Label(targets[0]);
+        // not_taken target is the target of the second branch, even if it is a Fallthrough
+        // instruction: because we reorder blocks while we lower, the fallthrough in the new
+        // order is not (necessarily) the same as the fallthrough in CLIF. So we use the
+        // explicitly-provided target.
+        let not_taken = BranchTarget::Label(targets[1]);
+
+        if ctx.input_ty(branches[0], 0) == I128 {
+            return Err(CodegenError::Unsupported(format!(
+                "Unsupported type for {}: {}",
+                op0, I128
+            )));
+        }
+
+        let kind = match op0 {
+            Opcode::Brz | Opcode::Brnz => {
+                let negated = op0 == Opcode::Brz;
+                let flag_input = InsnInput {
+                    insn: branches[0],
+                    input: 0,
+                };
+                if let Some(icmp_insn) =
+                    maybe_input_insn_via_conv(ctx, flag_input, Opcode::Icmp, Opcode::Bint)
+                {
+                    let cc = ctx.data(icmp_insn).cond_code().unwrap();
+                    let lhs = InsnInput {
+                        insn: icmp_insn,
+                        input: 0,
+                    };
+                    let rhs = InsnInput {
+                        insn: icmp_insn,
+                        input: 1,
+                    };
+                    let cmp = lower_icmp(ctx, cc, lhs, rhs);
+                    if negated {
+                        cmp.inverse()
+                    } else {
+                        cmp
+                    }
+                } else if let Some(fcmp_insn) =
+                    maybe_input_insn_via_conv(ctx, flag_input, Opcode::Fcmp, Opcode::Bint)
+                {
+                    let cc = ctx.data(fcmp_insn).fp_cond_code().unwrap();
+                    let rs = lower_fcmp_insn_to_reg(ctx, fcmp_insn, cc);
+                    IntegerCompare {
+                        kind: if negated {
+                            IntCC::Equal
+                        } else {
+                            IntCC::NotEqual
+                        },
+                        rs1: rs,
+                        rs2: zero_reg(),
+                    }
+                } else {
+                    let rs = put_input_in_reg(ctx, flag_input, NarrowValueMode::ZeroExtend64);
+                    IntegerCompare {
+                        kind: if negated {
+                            IntCC::Equal
+                        } else {
+                            IntCC::NotEqual
+                        },
+                        rs1: rs,
+                        rs2: zero_reg(),
+                    }
+                }
+            }
+
+            Opcode::BrIcmp => {
+                let cc = ctx.data(branches[0]).cond_code().unwrap();
+                let lhs = InsnInput {
+                    insn: branches[0],
+                    input: 0,
+                };
+                let rhs = InsnInput {
+                    insn: branches[0],
+                    input: 1,
+                };
+                lower_icmp(ctx, cc, lhs, rhs)
+            }
+
+            Opcode::Brif => {
+                let cc = ctx.data(branches[0]).cond_code().unwrap();
+                let flag_input = InsnInput {
+                    insn: branches[0],
+                    input: 0,
+                };
+                lower_iflags_compare(ctx, flag_input, cc)?
+            }
+
+            Opcode::Brff => {
+                let cc = ctx.data(branches[0]).fp_cond_code().unwrap();
+                let flag_input = InsnInput {
+                    insn: branches[0],
+                    input: 0,
+                };
+                let ffcmp_insn = maybe_input_insn(ctx, flag_input, Opcode::Ffcmp).unwrap();
+                let rs = lower_fcmp_insn_to_reg(ctx, ffcmp_insn, cc);
+                IntegerCompare {
+                    kind: IntCC::NotEqual,
+                    rs1: rs,
+                    rs2: zero_reg(),
+                }
+            }
+
+            _ => unimplemented!(),
+        };
+
+        ctx.emit(Inst::CondBr {
+            taken,
+            not_taken,
+            kind,
+        });
+    } else {
+        // Must be an unconditional branch or an indirect branch.
+        let op = ctx.data(branches[0]).opcode();
+        match op {
+            Opcode::Jump | Opcode::Fallthrough => {
+                assert!(branches.len() == 1);
+                // In the Fallthrough case, the machine-independent driver
+                // fills in `targets[0]` with our fallthrough block, so this
+                // is valid for both Jump and Fallthrough.
+                ctx.emit(Inst::Jal {
+                    dest: BranchTarget::Label(targets[0]),
+                });
+            }
+
+            Opcode::BrTable => {
+                // Expand `br_table index, default, JT` to:
+                //
+                //   emit_island  // this forces an island at this point
+                //                // if the jumptable would push us past
+                //                // the deadline
+                //   br_table index, tmp1, tmp2
+                //
+                // The compound instruction does:
+                //
+                //   slli tmp2, index, 2
+                //   li tmp1, jt_size * 4
+                //   bgeu tmp2, tmp1, default
+                //   auipc tmp1, 0
+                //   add tmp2, tmp2, tmp1
+                //   lw tmp2, 20(tmp2)
+                //   add tmp1, tmp1, tmp2
+                //   jalr zero, 20(tmp1)
+                //   [jumptable offsets relative to JT base]
+                //
+                // This must be *one* instruction in the vcode because
+                // we cannot allow regalloc to insert any spills/fills
+                // in the middle of the sequence; otherwise, the AUIPC's
+                // PC-rel offset to the jumptable would be incorrect.
+                let jt_size = targets.len() - 1;
+                assert!(jt_size <= std::u32::MAX as usize);
+
+                // The constant load takes at most 8 instructions.
+                ctx.emit(Inst::EmitIsland {
+                    needed_space: 4 * (16 + jt_size) as CodeOffset,
+                });
+
+                let index = put_input_in_reg(
+                    ctx,
+                    InsnInput {
+                        insn: branches[0],
+                        input: 0,
+                    },
+                    NarrowValueMode::ZeroExtend64,
+                );
+
+                let tmp1 = ctx.alloc_tmp(RegClass::I64, I64);
+                let tmp2 = ctx.alloc_tmp(RegClass::I64, I64);
+
+                let jt_targets: Vec<BranchTarget> = targets
+                    .iter()
+                    .skip(1)
+                    .map(|bix| BranchTarget::Label(*bix))
+                    .collect();
+                let default_target = BranchTarget::Label(targets[0]);
+                let targets_for_term: Vec<MachLabel> = targets.to_vec();
+                ctx.emit(Inst::BrTable {
+                    index,
+                    tmp1,
+                    tmp2,
+                    info: Box::new(BrTableInfo {
+                        targets: jt_targets,
+                        default_target,
+                        targets_for_term,
+                    }),
+                });
+            }
+
+            _ => panic!("Unknown branch type!"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! RISC-V 64-bit Instruction Set Architecture (RV64GC).
+
+use crate::ir::condcodes::IntCC;
+use crate::ir::Function;
+use crate::isa::riscv64::settings as riscv64_settings;
+use crate::isa::Builder as IsaBuilder;
+use crate::machinst::{compile, MachBackend, MachCompileResult, TargetIsaAdapter, VCode};
+use crate::result::CodegenResult;
+use crate::settings as shared_settings;
+
+use alloc::boxed::Box;
+
+use regalloc::{PrettyPrint, RealRegUniverse};
+use target_lexicon::{Architecture, Triple};
+
+// New backend:
+mod abi;
+pub(crate) mod inst;
+mod lower;
+mod lower_inst;
+mod settings;
+
+use inst::create_reg_universe;
+
+use self::inst::EmitInfo;
+
+/// A RISC-V 64-bit backend.
+pub struct Riscv64Backend {
+    triple: Triple,
+    flags: shared_settings::Flags,
+    reg_universe: RealRegUniverse,
+}
+
+impl Riscv64Backend {
+    /// Create a new RISC-V 64-bit backend with the given (shared) flags.
+    pub fn new_with_flags(triple: Triple, flags: shared_settings::Flags) -> Riscv64Backend {
+        let reg_universe = create_reg_universe(&flags);
+        Riscv64Backend {
+            triple,
+            flags,
+            reg_universe,
+        }
+    }
+
+    /// This performs lowering to VCode, register-allocates the code, computes block layout and
+    /// finalizes branches. The result is ready for binary emission.
+    fn compile_vcode(
+        &self,
+        func: &Function,
+        flags: shared_settings::Flags,
+    ) -> CodegenResult<VCode<inst::Inst>> {
+        let emit_info = EmitInfo::new(flags.clone());
+        let abi = Box::new(abi::Riscv64ABICallee::new(func, flags)?);
+        compile::compile::<Riscv64Backend>(func, self, abi, emit_info)
+    }
+}
+
+impl MachBackend for Riscv64Backend {
+    fn compile_function(
+        &self,
+        func: &Function,
+        want_disasm: bool,
+    ) -> CodegenResult<MachCompileResult> {
+        let flags = self.flags();
+        let vcode = self.compile_vcode(func, flags.clone())?;
+
+        let buffer = vcode.emit();
+        let frame_size = vcode.frame_size();
+        let stats = vcode.stats();
+        let unwind_info = vcode.unwind_info()?;
+
+        let disasm = if want_disasm {
+            Some(vcode.show_rru(Some(&create_reg_universe(flags))))
+        } else {
+            None
+        };
+
+        let buffer = buffer.finish();
+
+        Ok(MachCompileResult {
+            buffer,
+            frame_size,
+            disasm,
+            unwind_info,
+            stats,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "riscv64"
+    }
+
+    fn triple(&self) -> Triple {
+        self.triple.clone()
+    }
+
+    fn flags(&self) -> &shared_settings::Flags {
+        &self.flags
+    }
+
+    fn reg_universe(&self) -> &RealRegUniverse {
+        &self.reg_universe
+    }
+
+    fn unsigned_add_overflow_condition(&self) -> IntCC {
+        // RISC-V has no flags; `iadd_ifcout` materializes the carry as 0 / 1, and unsigned `>=`
+        // is lowered as a test of the carry being set.
+        IntCC::UnsignedGreaterThanOrEqual
+    }
+
+    fn unsigned_sub_overflow_condition(&self) -> IntCC {
+        // Likewise, `isub_ifbout` materializes the borrow as 0 / 1, and unsigned `<` is lowered as
+        // a test of the borrow being set.
+        IntCC::UnsignedLessThan
+    }
+
+    #[cfg(feature = "unwind")]
+    fn emit_unwind_info(
+        &self,
+        result: &MachCompileResult,
+        kind: crate::machinst::UnwindInfoKind,
+    ) -> CodegenResult<Option<crate::isa::unwind::UnwindInfo>> {
+        use crate::isa::unwind::UnwindInfo;
+        use crate::machinst::UnwindInfoKind;
+        Ok(match (result.unwind_info.as_ref(), kind) {
+            (Some(info), UnwindInfoKind::SystemV) => {
+                inst::unwind::systemv::create_unwind_info(info.clone())?.map(UnwindInfo::SystemV)
+            }
+            _ => None,
+        })
+    }
+
+    #[cfg(feature = "unwind")]
+    fn create_systemv_cie(&self) -> Option<gimli::write::CommonInformationEntry> {
+        Some(inst::unwind::systemv::create_cie())
+    }
+}
+
+/// Create a new `isa::Builder`.
+pub fn isa_builder(triple: Triple) -> IsaBuilder {
+    assert!(match triple.architecture {
+        Architecture::Riscv64 { .. } => true,
+        _ => false,
+    });
+    IsaBuilder {
+        triple,
+        // The settings of the old RISC-V backend are accepted so that existing targets keep
+        // working, but this backend always assumes the G extensions and ignores them.
+        setup: riscv64_settings::builder(),
+        constructor: |triple, shared_flags, _| {
+            let backend = Riscv64Backend::new_with_flags(triple, shared_flags);
+            Box::new(TargetIsaAdapter::new(backend))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::*;
+    use crate::ir::{AbiParam, ExternalName, Function, InstBuilder, Signature};
+    use crate::isa::CallConv;
+    use crate::settings;
+    use crate::settings::Configurable;
+    use core::str::FromStr;
+    use target_lexicon::Triple;
+
+    #[test]
+    fn test_compile_function() {
+        let name = ExternalName::testcase("test0");
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(name, sig);
+
+        let bb0 = func.dfg.make_block();
+        let arg0 = func.dfg.append_block_param(bb0, I32);
+
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_block(bb0);
+        let v0 = pos.ins().iconst(I32, 0x1234);
+        let v1 = pos.ins().iadd(arg0, v0);
+        pos.ins().return_(&[v1]);
+
+        let mut shared_flags = settings::builder();
+        shared_flags.set("opt_level", "none").unwrap();
+        let backend = Riscv64Backend::new_with_flags(
+            Triple::from_str("riscv64").unwrap(),
+            settings::Flags::new(shared_flags),
+        );
+        let buffer = backend.compile_function(&mut func, false).unwrap().buffer;
+        let code = &buffer.data[..];
+
+        let golden = vec![
+            0x13, 0x01, 0x01, 0xff, // addi sp,sp,-16
+            0x23, 0x34, 0x11, 0x00, // sd ra,8(sp)
+            0x23, 0x30, 0x81, 0x00, // sd fp,0(sp)
+            0x13, 0x04, 0x01, 0x00, // mv fp,sp
+            0xb7, 0x15, 0x00, 0x00, // lui a1,0x1
+            0x9b, 0x85, 0x45, 0x23, // addiw a1,a1,564
+            0x33, 0x05, 0xb5, 0x00, // add a0,a0,a1
+            0x13, 0x01, 0x04, 0x00, // mv sp,fp
+            0x83, 0x30, 0x81, 0x00, // ld ra,8(sp)
+            0x03, 0x34, 0x01, 0x00, // ld fp,0(sp)
+            0x13, 0x01, 0x01, 0x01, // addi sp,sp,16
+            0x67, 0x80, 0x00, 0x00, // ret
+        ];
+
+        assert_eq!(code, &golden[..]);
+    }
+}
@@ -32,7 +32,7 @@ fn try_fill_baldrdash_reg(call_conv: CallConv, param: &ir::AbiParam) -> Option<A
             &ir::ArgumentPurpose::VMContext => {
                 // This is SpiderMonkey's `WasmTlsReg`.
                 Some(ABIArg::Reg(
-                    regs::r14().to_real_reg(),
+                    ValueRegs::one(regs::r14().to_real_reg()),
                     types::I64,
                     param.extension,
                     param.purpose,
@@ -41,7 +41,7 @@ fn try_fill_baldrdash_reg(call_conv: CallConv, param: &ir::AbiParam) -> Option<A
             &ir::ArgumentPurpose::SignatureId => {
                 // This is SpiderMonkey's `WasmTableCallSigReg`.
                 Some(ABIArg::Reg(
-                    regs::r10().to_real_reg(),
+                    ValueRegs::one(regs::r10().to_real_reg()),
                     types::I64,
                     param.extension,
                     param.purpose,
@@ -143,13 +143,37 @@ impl ABIMachineSpec for X64ABIMachineSpec {
             debug_assert!(intreg || vecreg);
             debug_assert!(!(intreg && vecreg));
 
+            // An `I128` is passed in two consecutive GPRs, low half first, or
+            // entirely on the stack if there aren't two GPRs left.
+            let num_regs = if param.value_type == types::I128 {
+                if is_baldrdash {
+                    return Err(CodegenError::Unsupported(format!(
+                        "I128 arguments and return values are not supported by the Baldrdash \
+                         calling conventions"
+                    )));
+                }
+                2
+            } else {
+                1
+            };
+
             let (next_reg, candidate) = if intreg {
-                let candidate = match args_or_rets {
-                    ArgsOrRets::Args => get_intreg_for_arg_systemv(&call_conv, next_gpr),
-                    ArgsOrRets::Rets => get_intreg_for_retval_systemv(&call_conv, next_gpr, i),
+                let get_intreg = |idx| match args_or_rets {
+                    ArgsOrRets::Args => get_intreg_for_arg_systemv(&call_conv, idx),
+                    ArgsOrRets::Rets => get_intreg_for_retval_systemv(&call_conv, idx, i),
+                };
+                let candidate = if num_regs == 2 {
+                    match (get_intreg(next_gpr), get_intreg(next_gpr + 1)) {
+                        (Some(lo), Some(hi)) => {
+                            Some(ValueRegs::two(lo.to_real_reg(), hi.to_real_reg()))
+                        }
+                        _ => None,
+                    }
+                } else {
+                    get_intreg(next_gpr).map(|r| ValueRegs::one(r.to_real_reg()))
                 };
                 debug_assert!(candidate
-                    .map(|r| r.get_class() == RegClass::I64)
+                    .map(|regs| regs.regs().iter().all(|r| r.get_class() == RegClass::I64))
                     .unwrap_or(true));
                 (&mut next_gpr, candidate)
             } else {
@@ -160,20 +184,23 @@ impl ABIMachineSpec for X64ABIMachineSpec {
                 debug_assert!(candidate
                     .map(|r| r.get_class() == RegClass::V128)
                     .unwrap_or(true));
-                (&mut next_vreg, candidate)
+                (
+                    &mut next_vreg,
+                    candidate.map(|r| ValueRegs::one(r.to_real_reg())),
+                )
             };
 
             if let Some(param) = try_fill_baldrdash_reg(call_conv, param) {
                 assert!(intreg);
                 ret.push(param);
-            } else if let Some(reg) = candidate {
+            } else if let Some(regs) = candidate {
                 ret.push(ABIArg::Reg(
-                    reg.to_real_reg(),
+                    regs,
                     param.value_type,
                     param.extension,
                     param.purpose,
                 ));
-                *next_reg += 1;
+                *next_reg += num_regs;
             } else {
                 // Compute size. Every arg takes a minimum slot of 8 bytes. (16-byte
                 // stack alignment happens separately after all args.)
@@ -200,7 +227,7 @@ impl ABIMachineSpec for X64ABIMachineSpec {
             debug_assert!(args_or_rets == ArgsOrRets::Args);
             if let Some(reg) = get_intreg_for_arg_systemv(&call_conv, next_gpr) {
                 ret.push(ABIArg::Reg(
-                    reg.to_real_reg(),
+                    ValueRegs::one(reg.to_real_reg()),
                     types::I64,
                     ir::ArgumentExtension::None,
                     ir::ArgumentPurpose::Normal,
@@ -651,7 +678,8 @@ fn in_int_reg(ty: types::Type) -> bool {
         | types::B16
         | types::B32
         | types::B64
-        | types::R64 => true,
+        | types::R64
+        | types::I128 => true,
         types::R32 => panic!("unexpected 32-bits refs on x64!"),
         _ => false,
     }
@@ -54,6 +54,17 @@ impl Amode {
         Self::RipRelative { target }
     }
 
+    /// Returns the same address, displaced by `offset` bytes.
+    pub(crate) fn offset(&self, offset: u32) -> Self {
+        let mut ret = self.clone();
+        match &mut ret {
+            &mut Amode::ImmReg { ref mut simm32, .. }
+            | &mut Amode::ImmRegRegShift { ref mut simm32, .. } => *simm32 += offset,
+            &mut Amode::RipRelative { .. } => panic!("Cannot offset a RIP-relative amode"),
+        }
+        ret
+    }
+
     /// Add the regs mentioned by `self` to `collector`.
     pub(crate) fn get_regs_as_uses(&self, collector: &mut RegUsageCollector) {
         match self {
@@ -298,7 +309,11 @@ impl PrettyPrintSized for RegMem {
 #[derive(Copy, Clone, PartialEq)]
 pub enum AluRmiROpcode {
     Add,
+    /// Add with carry.
+    Adc,
     Sub,
+    /// Subtract with borrow.
+    Sbb,
     And,
     Or,
     Xor,
@@ -310,7 +325,9 @@ impl fmt::Debug for AluRmiROpcode {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let name = match self {
             AluRmiROpcode::Add => "add",
+            AluRmiROpcode::Adc => "adc",
             AluRmiROpcode::Sub => "sub",
+            AluRmiROpcode::Sbb => "sbb",
             AluRmiROpcode::And => "and",
             AluRmiROpcode::Or => "or",
             AluRmiROpcode::Xor => "xor",
@@ -355,6 +372,12 @@ pub(crate) enum InstructionSet {
     SSSE3,
     SSE41,
     SSE42,
+    AVX,
+    AVX2,
+    /// AVX-512 Foundation; since only 128-bit vectors are used, this also requires AVX-512VL.
+    AVX512F,
+    /// AVX-512 Doubleword and Quadword; this also requires AVX-512VL.
+    AVX512DQ,
 }
 
 /// Some SSE operations requiring 2 operands r/m and r.
@@ -817,6 +840,83 @@ impl fmt::Display for SseOpcode {
     }
 }
 
+/// VEX-encoded operations with no legacy SSE counterpart.
+///
+/// The VEX forms of the SSE operations in [SseOpcode] are expressed with `Inst::XmmRmRVex`
+/// instead, which reuses the SSE opcode.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AvxOpcode {
+    Vpbroadcastb,
+    Vpbroadcastw,
+    Vpbroadcastd,
+    Vpbroadcastq,
+}
+
+impl AvxOpcode {
+    /// Which `InstructionSet` is the first supporting this opcode?
+    pub(crate) fn available_from(&self) -> InstructionSet {
+        match self {
+            AvxOpcode::Vpbroadcastb
+            | AvxOpcode::Vpbroadcastw
+            | AvxOpcode::Vpbroadcastd
+            | AvxOpcode::Vpbroadcastq => InstructionSet::AVX2,
+        }
+    }
+}
+
+impl fmt::Debug for AvxOpcode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AvxOpcode::Vpbroadcastb => "vpbroadcastb",
+            AvxOpcode::Vpbroadcastw => "vpbroadcastw",
+            AvxOpcode::Vpbroadcastd => "vpbroadcastd",
+            AvxOpcode::Vpbroadcastq => "vpbroadcastq",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+impl fmt::Display for AvxOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// EVEX-encoded (AVX-512) operations. Only the 128-bit forms are used.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Avx512Opcode {
+    Vcvtudq2ps,
+    Vpabsq,
+    Vpmullq,
+}
+
+impl Avx512Opcode {
+    /// Which `InstructionSet` is the first supporting this opcode?
+    pub(crate) fn available_from(&self) -> InstructionSet {
+        match self {
+            Avx512Opcode::Vcvtudq2ps | Avx512Opcode::Vpabsq => InstructionSet::AVX512F,
+            Avx512Opcode::Vpmullq => InstructionSet::AVX512DQ,
+        }
+    }
+}
+
+impl fmt::Debug for Avx512Opcode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Avx512Opcode::Vcvtudq2ps => "vcvtudq2ps",
+            Avx512Opcode::Vpabsq => "vpabsq",
+            Avx512Opcode::Vpmullq => "vpmullq",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+impl fmt::Display for Avx512Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 /// This defines the ways a value can be extended: either signed- or zero-extension, or none for
 /// types that are not extended. Contrast with [ExtMode], which defines the widths from and to which
 /// values can be extended.
@@ -894,7 +994,7 @@ impl fmt::Display for ExtMode {
 }
 
 /// These indicate the form of a scalar shift/rotate: left, signed right, unsigned right.
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum ShiftKind {
     ShiftLeft,
     /// Inserts zeros in the most significant bits.
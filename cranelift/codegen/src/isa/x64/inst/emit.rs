@@ -119,6 +119,7 @@ impl RexFlags {
 
 /// We may need to include one or more legacy prefix bytes before the REX prefix.  This enum
 /// covers only the small set of possibilities that we actually need.
+#[derive(Clone, Copy)]
 enum LegacyPrefixes {
     /// No prefix bytes
     None,
@@ -151,6 +152,19 @@ impl LegacyPrefixes {
             LegacyPrefixes::None => (),
         }
     }
+
+    /// The `pp` field of a VEX or EVEX prefix, which stands for the mandatory prefix of the
+    /// equivalent legacy encoding.
+    #[inline(always)]
+    fn vex_pp(&self) -> u8 {
+        match self {
+            LegacyPrefixes::None => 0b00,
+            LegacyPrefixes::_66 => 0b01,
+            LegacyPrefixes::_F3 => 0b10,
+            LegacyPrefixes::_F2 => 0b11,
+            _ => panic!("VEX and EVEX prefixes can't encode a lock prefix"),
+        }
+    }
 }
 
 /// This is the core 'emit' function for instructions that reference memory.
@@ -201,17 +215,61 @@ fn emit_std_enc_mem(
     prefixes.emit(sink);
 
     match mem_e {
-        Amode::ImmReg { simm32, base } => {
+        Amode::ImmReg { base, .. } => {
             // First, the REX byte.
             let enc_e = int_reg_enc(*base);
             rex.emit_two_op(sink, enc_g, enc_e);
+        }
 
-            // Now the opcode(s).  These include any other prefixes the caller
-            // hands to us.
-            while num_opcodes > 0 {
-                num_opcodes -= 1;
-                sink.put1(((opcodes >> (num_opcodes << 3)) & 0xFF) as u8);
-            }
+        Amode::ImmRegRegShift {
+            base: reg_base,
+            index: reg_index,
+            ..
+        } => {
+            let enc_base = int_reg_enc(*reg_base);
+            let enc_index = int_reg_enc(*reg_index);
+
+            // The rex byte.
+            rex.emit_three_op(sink, enc_g, enc_index, enc_base);
+        }
+
+        Amode::RipRelative { .. } => {
+            // First, the REX byte, with REX.B = 0.
+            rex.emit_two_op(sink, enc_g, 0);
+        }
+    }
+
+    // Now the opcode(s).  These include any other prefixes the caller
+    // hands to us.
+    while num_opcodes > 0 {
+        num_opcodes -= 1;
+        sink.put1(((opcodes >> (num_opcodes << 3)) & 0xFF) as u8);
+    }
+
+    emit_modrm_sib_disp(sink, enc_g, mem_e, 1);
+}
+
+/// Emits the mod/rm byte, and the SIB byte and displacement if needed, for an instruction that
+/// has as operands a reg encoding `enc_g` and a memory address `mem_e`. This is shared by all the
+/// encodings (legacy, VEX and EVEX), which only differ in what precedes the opcode.
+///
+/// `disp8_scale` is the factor by which an 8-bit displacement is implicitly multiplied: it is 1
+/// except for EVEX encodings, which use a "compressed" displacement scaled by the operand size.
+fn emit_modrm_sib_disp(sink: &mut MachBuffer<Inst>, enc_g: u8, mem_e: &Amode, disp8_scale: u32) {
+    // Returns the 8-bit (scaled) displacement for `simm32`, if there's one.
+    let disp8 = |simm32: u32| -> Option<u8> {
+        let simm32 = simm32 as i32;
+        let scale = disp8_scale as i32;
+        if simm32 % scale == 0 && low8_will_sign_extend_to_32((simm32 / scale) as u32) {
+            Some((simm32 / scale) as u8)
+        } else {
+            None
+        }
+    };
+
+    match mem_e {
+        Amode::ImmReg { simm32, base } => {
+            let enc_e = int_reg_enc(*base);
 
             // Now the mod/rm and associated immediates.  This is
             // significantly complicated due to the multiple special cases.
@@ -228,22 +286,20 @@ fn emit_std_enc_mem(
             } else if *simm32 == 0 && (enc_e == regs::ENC_RSP || enc_e == regs::ENC_R12) {
                 sink.put1(encode_modrm(0, enc_g & 7, 4));
                 sink.put1(0x24);
-            } else if low8_will_sign_extend_to_32(*simm32)
-                && enc_e != regs::ENC_RSP
-                && enc_e != regs::ENC_R12
-            {
+            } else if let (Some(disp8), false) = (
+                disp8(*simm32),
+                enc_e == regs::ENC_RSP || enc_e == regs::ENC_R12,
+            ) {
                 sink.put1(encode_modrm(1, enc_g & 7, enc_e & 7));
-                sink.put1((simm32 & 0xFF) as u8);
+                sink.put1(disp8);
             } else if enc_e != regs::ENC_RSP && enc_e != regs::ENC_R12 {
                 sink.put1(encode_modrm(2, enc_g & 7, enc_e & 7));
                 sink.put4(*simm32);
-            } else if (enc_e == regs::ENC_RSP || enc_e == regs::ENC_R12)
-                && low8_will_sign_extend_to_32(*simm32)
-            {
+            } else if let Some(disp8) = disp8(*simm32) {
                 // REX.B distinguishes RSP from R12
                 sink.put1(encode_modrm(1, enc_g & 7, 4));
                 sink.put1(0x24);
-                sink.put1((simm32 & 0xFF) as u8);
+                sink.put1(disp8);
             } else if enc_e == regs::ENC_R12 || enc_e == regs::ENC_RSP {
                 //.. wait for test case for RSP case
                 // REX.B distinguishes RSP from R12
@@ -264,20 +320,11 @@ fn emit_std_enc_mem(
             let enc_base = int_reg_enc(*reg_base);
             let enc_index = int_reg_enc(*reg_index);
 
-            // The rex byte.
-            rex.emit_three_op(sink, enc_g, enc_index, enc_base);
-
-            // All other prefixes and opcodes.
-            while num_opcodes > 0 {
-                num_opcodes -= 1;
-                sink.put1(((opcodes >> (num_opcodes << 3)) & 0xFF) as u8);
-            }
-
             // modrm, SIB, immediates.
-            if low8_will_sign_extend_to_32(*simm32) && enc_index != regs::ENC_RSP {
+            if let (Some(disp8), true) = (disp8(*simm32), enc_index != regs::ENC_RSP) {
                 sink.put1(encode_modrm(1, enc_g & 7, 4));
                 sink.put1(encode_sib(*shift, enc_index & 7, enc_base & 7));
-                sink.put1(*simm32 as u8);
+                sink.put1(disp8);
             } else if enc_index != regs::ENC_RSP {
                 sink.put1(encode_modrm(2, enc_g & 7, 4));
                 sink.put1(encode_sib(*shift, enc_index & 7, enc_base & 7));
@@ -288,16 +335,6 @@ fn emit_std_enc_mem(
         }
 
         Amode::RipRelative { ref target } => {
-            // First, the REX byte, with REX.B = 0.
-            rex.emit_two_op(sink, enc_g, 0);
-
-            // Now the opcode(s).  These include any other prefixes the caller
-            // hands to us.
-            while num_opcodes > 0 {
-                num_opcodes -= 1;
-                sink.put1(((opcodes >> (num_opcodes << 3)) & 0xFF) as u8);
-            }
-
             // RIP-relative is mod=00, rm=101.
             sink.put1(encode_modrm(0, enc_g & 7, 0b101));
 
@@ -383,6 +420,239 @@ fn emit_std_reg_reg(
     emit_std_enc_enc(sink, prefixes, opcodes, num_opcodes, enc_g, enc_e, rex);
 }
 
+/// The opcode map of a VEX- or EVEX-encoded instruction, which stands for the escape bytes of
+/// the equivalent legacy encoding.
+#[derive(Clone, Copy, PartialEq)]
+enum OpcodeMap {
+    _0F = 0b01,
+    _0F38 = 0b10,
+    _0F3A = 0b11,
+}
+
+impl OpcodeMap {
+    /// Splits a legacy opcode, as passed to `emit_std_enc_enc`, into its opcode map and final
+    /// opcode byte.
+    fn from_legacy(opcodes: u32, num_opcodes: usize) -> (Self, u8) {
+        let map = match (num_opcodes, opcodes >> 8) {
+            (2, 0x0F) => OpcodeMap::_0F,
+            (3, 0x0F38) => OpcodeMap::_0F38,
+            (3, 0x0F3A) => OpcodeMap::_0F3A,
+            _ => panic!("opcode {:#x} has no VEX encoding", opcodes),
+        };
+        (map, opcodes as u8)
+    }
+}
+
+/// The E operand of a VEX- or EVEX-encoded instruction, with its address finalized.
+enum VexRm {
+    Reg(u8),
+    Mem(Amode),
+}
+
+impl VexRm {
+    fn new(sink: &mut MachBuffer<Inst>, state: &mut EmitState, src_e: &RegMem) -> Self {
+        match src_e {
+            RegMem::Reg { reg } => VexRm::Reg(reg_enc(*reg)),
+            RegMem::Mem { addr } => {
+                let srcloc = state.cur_srcloc();
+                if srcloc != SourceLoc::default() {
+                    sink.add_trap(srcloc, TrapCode::HeapOutOfBounds);
+                }
+                VexRm::Mem(addr.finalize(state, sink))
+            }
+        }
+    }
+
+    /// The REX-like X and B extension bits for this operand.
+    fn xb(&self) -> (u8, u8) {
+        match self {
+            VexRm::Reg(enc_e) => (0, (enc_e >> 3) & 1),
+            VexRm::Mem(Amode::ImmReg { base, .. }) => (0, (int_reg_enc(*base) >> 3) & 1),
+            VexRm::Mem(Amode::ImmRegRegShift { base, index, .. }) => (
+                (int_reg_enc(*index) >> 3) & 1,
+                (int_reg_enc(*base) >> 3) & 1,
+            ),
+            VexRm::Mem(Amode::RipRelative { .. }) => (0, 0),
+        }
+    }
+
+    /// Emits the mod/rm byte and what follows it.
+    fn emit(&self, sink: &mut MachBuffer<Inst>, enc_g: u8, disp8_scale: u32) {
+        match self {
+            VexRm::Reg(enc_e) => sink.put1(encode_modrm(3, enc_g & 7, enc_e & 7)),
+            VexRm::Mem(addr) => emit_modrm_sib_disp(sink, enc_g, addr, disp8_scale),
+        }
+    }
+}
+
+/// Emits a 128-bit VEX-encoded instruction: the VEX prefix (replacing the legacy and REX
+/// prefixes and the escape bytes), then the opcode byte, then the mod/rm byte and its optional SIB
+/// byte and displacement.
+///
+/// `reg_v` is the additional (non-destructive) source operand encoded in the VEX.vvvv field, if
+/// the instruction has one.
+fn emit_vex(
+    sink: &mut MachBuffer<Inst>,
+    state: &mut EmitState,
+    prefix: LegacyPrefixes,
+    map: OpcodeMap,
+    w: bool,
+    opcode: u8,
+    reg_g: Reg,
+    reg_v: Option<Reg>,
+    src_e: &RegMem,
+) {
+    let rm = VexRm::new(sink, state, src_e);
+    let enc_g = reg_enc(reg_g);
+    let r = (enc_g >> 3) & 1;
+    let (x, b) = rm.xb();
+    // The register fields are stored inverted.
+    let vvvv = !reg_v.map_or(0, |reg| reg_enc(reg)) & 0b1111;
+    let w = w as u8;
+    // Only 128-bit vectors are used, so VEX.L is always 0.
+    let l = 0;
+    let pp = prefix.vex_pp();
+
+    if x == 0 && b == 0 && w == 0 && map == OpcodeMap::_0F {
+        // The two-byte form.
+        sink.put1(0xC5);
+        sink.put1(((r ^ 1) << 7) | (vvvv << 3) | (l << 2) | pp);
+    } else {
+        sink.put1(0xC4);
+        sink.put1(((r ^ 1) << 7) | ((x ^ 1) << 6) | ((b ^ 1) << 5) | map as u8);
+        sink.put1((w << 7) | (vvvv << 3) | (l << 2) | pp);
+    }
+
+    sink.put1(opcode);
+    rm.emit(sink, enc_g, 1);
+}
+
+/// Emits a 128-bit EVEX-encoded (AVX-512) instruction, without masking or broadcast. This is
+/// laid out as for `emit_vex`, with the four-byte EVEX prefix instead.
+fn emit_evex(
+    sink: &mut MachBuffer<Inst>,
+    state: &mut EmitState,
+    prefix: LegacyPrefixes,
+    map: OpcodeMap,
+    w: bool,
+    opcode: u8,
+    reg_g: Reg,
+    reg_v: Option<Reg>,
+    src_e: &RegMem,
+) {
+    let rm = VexRm::new(sink, state, src_e);
+    let enc_g = reg_enc(reg_g);
+    let r = (enc_g >> 3) & 1;
+    let (x, b) = rm.xb();
+    // As for VEX, the register fields are stored inverted. The fifth bit of each register
+    // number (R', V') is always zero, since only xmm0 to xmm15 are used.
+    let vvvv = !reg_v.map_or(0, |reg| reg_enc(reg)) & 0b1111;
+    let pp = prefix.vex_pp();
+
+    sink.put1(0x62);
+    sink.put1(((r ^ 1) << 7) | ((x ^ 1) << 6) | ((b ^ 1) << 5) | (1 << 4) | map as u8);
+    sink.put1(((w as u8) << 7) | (vvvv << 3) | (1 << 2) | pp);
+    // z = 0, L'L = 0b00 (128 bits), b = 0, V' = 0 (inverted), aaa = 0b000 (no masking).
+    sink.put1(0b0000_1000);
+
+    sink.put1(opcode);
+    // The displacement of a full 128-bit memory operand is compressed by its size.
+    rm.emit(sink, enc_g, 16);
+}
+
+/// The legacy prefix, opcode bytes and number of opcode bytes of a binary SSE instruction, as
+/// used by `Inst::XmmRmR` (and, with a VEX prefix instead, by `Inst::XmmRmRVex`).
+fn xmm_rm_r_encoding(op: SseOpcode) -> (LegacyPrefixes, u32, usize) {
+    match op {
+        SseOpcode::Addps => (LegacyPrefixes::None, 0x0F58, 2),
+        SseOpcode::Addpd => (LegacyPrefixes::_66, 0x0F58, 2),
+        SseOpcode::Addss => (LegacyPrefixes::_F3, 0x0F58, 2),
+        SseOpcode::Addsd => (LegacyPrefixes::_F2, 0x0F58, 2),
+        SseOpcode::Andps => (LegacyPrefixes::None, 0x0F54, 2),
+        SseOpcode::Andpd => (LegacyPrefixes::_66, 0x0F54, 2),
+        SseOpcode::Andnps => (LegacyPrefixes::None, 0x0F55, 2),
+        SseOpcode::Andnpd => (LegacyPrefixes::_66, 0x0F55, 2),
+        SseOpcode::Cvttps2dq => (LegacyPrefixes::_F3, 0x0F5B, 2),
+        SseOpcode::Cvtdq2ps => (LegacyPrefixes::None, 0x0F5B, 2),
+        SseOpcode::Divps => (LegacyPrefixes::None, 0x0F5E, 2),
+        SseOpcode::Divpd => (LegacyPrefixes::_66, 0x0F5E, 2),
+        SseOpcode::Divss => (LegacyPrefixes::_F3, 0x0F5E, 2),
+        SseOpcode::Divsd => (LegacyPrefixes::_F2, 0x0F5E, 2),
+        SseOpcode::Maxps => (LegacyPrefixes::None, 0x0F5F, 2),
+        SseOpcode::Maxpd => (LegacyPrefixes::_66, 0x0F5F, 2),
+        SseOpcode::Maxss => (LegacyPrefixes::_F3, 0x0F5F, 2),
+        SseOpcode::Maxsd => (LegacyPrefixes::_F2, 0x0F5F, 2),
+        SseOpcode::Minps => (LegacyPrefixes::None, 0x0F5D, 2),
+        SseOpcode::Minpd => (LegacyPrefixes::_66, 0x0F5D, 2),
+        SseOpcode::Minss => (LegacyPrefixes::_F3, 0x0F5D, 2),
+        SseOpcode::Minsd => (LegacyPrefixes::_F2, 0x0F5D, 2),
+        SseOpcode::Movlhps => (LegacyPrefixes::None, 0x0F16, 2),
+        SseOpcode::Movsd => (LegacyPrefixes::_F2, 0x0F10, 2),
+        SseOpcode::Mulps => (LegacyPrefixes::None, 0x0F59, 2),
+        SseOpcode::Mulpd => (LegacyPrefixes::_66, 0x0F59, 2),
+        SseOpcode::Mulss => (LegacyPrefixes::_F3, 0x0F59, 2),
+        SseOpcode::Mulsd => (LegacyPrefixes::_F2, 0x0F59, 2),
+        SseOpcode::Orpd => (LegacyPrefixes::_66, 0x0F56, 2),
+        SseOpcode::Orps => (LegacyPrefixes::None, 0x0F56, 2),
+        SseOpcode::Packsswb => (LegacyPrefixes::_66, 0x0F63, 2),
+        SseOpcode::Paddb => (LegacyPrefixes::_66, 0x0FFC, 2),
+        SseOpcode::Paddd => (LegacyPrefixes::_66, 0x0FFE, 2),
+        SseOpcode::Paddq => (LegacyPrefixes::_66, 0x0FD4, 2),
+        SseOpcode::Paddw => (LegacyPrefixes::_66, 0x0FFD, 2),
+        SseOpcode::Paddsb => (LegacyPrefixes::_66, 0x0FEC, 2),
+        SseOpcode::Paddsw => (LegacyPrefixes::_66, 0x0FED, 2),
+        SseOpcode::Paddusb => (LegacyPrefixes::_66, 0x0FDC, 2),
+        SseOpcode::Paddusw => (LegacyPrefixes::_66, 0x0FDD, 2),
+        SseOpcode::Pand => (LegacyPrefixes::_66, 0x0FDB, 2),
+        SseOpcode::Pandn => (LegacyPrefixes::_66, 0x0FDF, 2),
+        SseOpcode::Pavgb => (LegacyPrefixes::_66, 0x0FE0, 2),
+        SseOpcode::Pavgw => (LegacyPrefixes::_66, 0x0FE3, 2),
+        SseOpcode::Pcmpeqb => (LegacyPrefixes::_66, 0x0F74, 2),
+        SseOpcode::Pcmpeqw => (LegacyPrefixes::_66, 0x0F75, 2),
+        SseOpcode::Pcmpeqd => (LegacyPrefixes::_66, 0x0F76, 2),
+        SseOpcode::Pcmpeqq => (LegacyPrefixes::_66, 0x0F3829, 3),
+        SseOpcode::Pcmpgtb => (LegacyPrefixes::_66, 0x0F64, 2),
+        SseOpcode::Pcmpgtw => (LegacyPrefixes::_66, 0x0F65, 2),
+        SseOpcode::Pcmpgtd => (LegacyPrefixes::_66, 0x0F66, 2),
+        SseOpcode::Pcmpgtq => (LegacyPrefixes::_66, 0x0F3837, 3),
+        SseOpcode::Pmaxsb => (LegacyPrefixes::_66, 0x0F383C, 3),
+        SseOpcode::Pmaxsw => (LegacyPrefixes::_66, 0x0FEE, 2),
+        SseOpcode::Pmaxsd => (LegacyPrefixes::_66, 0x0F383D, 3),
+        SseOpcode::Pmaxub => (LegacyPrefixes::_66, 0x0FDE, 2),
+        SseOpcode::Pmaxuw => (LegacyPrefixes::_66, 0x0F383E, 3),
+        SseOpcode::Pmaxud => (LegacyPrefixes::_66, 0x0F383F, 3),
+        SseOpcode::Pminsb => (LegacyPrefixes::_66, 0x0F3838, 3),
+        SseOpcode::Pminsw => (LegacyPrefixes::_66, 0x0FEA, 2),
+        SseOpcode::Pminsd => (LegacyPrefixes::_66, 0x0F3839, 3),
+        SseOpcode::Pminub => (LegacyPrefixes::_66, 0x0FDA, 2),
+        SseOpcode::Pminuw => (LegacyPrefixes::_66, 0x0F383A, 3),
+        SseOpcode::Pminud => (LegacyPrefixes::_66, 0x0F383B, 3),
+        SseOpcode::Pmulld => (LegacyPrefixes::_66, 0x0F3840, 3),
+        SseOpcode::Pmullw => (LegacyPrefixes::_66, 0x0FD5, 2),
+        SseOpcode::Pmuludq => (LegacyPrefixes::_66, 0x0FF4, 2),
+        SseOpcode::Por => (LegacyPrefixes::_66, 0x0FEB, 2),
+        SseOpcode::Pshufb => (LegacyPrefixes::_66, 0x0F3800, 3),
+        SseOpcode::Psubb => (LegacyPrefixes::_66, 0x0FF8, 2),
+        SseOpcode::Psubd => (LegacyPrefixes::_66, 0x0FFA, 2),
+        SseOpcode::Psubq => (LegacyPrefixes::_66, 0x0FFB, 2),
+        SseOpcode::Psubw => (LegacyPrefixes::_66, 0x0FF9, 2),
+        SseOpcode::Psubsb => (LegacyPrefixes::_66, 0x0FE8, 2),
+        SseOpcode::Psubsw => (LegacyPrefixes::_66, 0x0FE9, 2),
+        SseOpcode::Psubusb => (LegacyPrefixes::_66, 0x0FD8, 2),
+        SseOpcode::Psubusw => (LegacyPrefixes::_66, 0x0FD9, 2),
+        SseOpcode::Punpckhbw => (LegacyPrefixes::_66, 0x0F68, 2),
+        SseOpcode::Punpcklbw => (LegacyPrefixes::_66, 0x0F60, 2),
+        SseOpcode::Pxor => (LegacyPrefixes::_66, 0x0FEF, 2),
+        SseOpcode::Subps => (LegacyPrefixes::None, 0x0F5C, 2),
+        SseOpcode::Subpd => (LegacyPrefixes::_66, 0x0F5C, 2),
+        SseOpcode::Subss => (LegacyPrefixes::_F3, 0x0F5C, 2),
+        SseOpcode::Subsd => (LegacyPrefixes::_F2, 0x0F5C, 2),
+        SseOpcode::Xorps => (LegacyPrefixes::None, 0x0F57, 2),
+        SseOpcode::Xorpd => (LegacyPrefixes::_66, 0x0F57, 2),
+        _ => unimplemented!("Opcode {:?} not implemented", op),
+    }
+}
+
 /// Write a suitable number of bits from an imm64 to the sink.
 fn emit_simm(sink: &mut MachBuffer<Inst>, size: u8, simm32: u32) {
     match size {
@@ -500,6 +770,14 @@ pub(crate) fn emit(
             InstructionSet::SSSE3 => assert!(info.isa_flags.has_ssse3()),
             InstructionSet::SSE41 => assert!(info.isa_flags.has_sse41()),
             InstructionSet::SSE42 => assert!(info.isa_flags.has_sse42()),
+            InstructionSet::AVX => assert!(info.isa_flags.has_avx()),
+            InstructionSet::AVX2 => assert!(info.isa_flags.has_avx2()),
+            InstructionSet::AVX512F => {
+                assert!(info.isa_flags.has_avx512f() && info.isa_flags.has_avx512vl())
+            }
+            InstructionSet::AVX512DQ => {
+                assert!(info.isa_flags.has_avx512dq() && info.isa_flags.has_avx512vl())
+            }
         }
     }
 
@@ -565,7 +843,9 @@ pub(crate) fn emit(
             } else {
                 let (opcode_r, opcode_m, subopcode_i) = match op {
                     AluRmiROpcode::Add => (0x01, 0x03, 0),
+                    AluRmiROpcode::Adc => (0x11, 0x13, 2),
                     AluRmiROpcode::Sub => (0x29, 0x2B, 5),
+                    AluRmiROpcode::Sbb => (0x19, 0x1B, 3),
                     AluRmiROpcode::And => (0x21, 0x23, 4),
                     AluRmiROpcode::Or => (0x09, 0x0B, 1),
                     AluRmiROpcode::Xor => (0x31, 0x33, 6),
@@ -1749,94 +2029,7 @@ pub(crate) fn emit(
             dst: reg_g,
         } => {
             let rex = RexFlags::clear_w();
-            let (prefix, opcode, length) = match op {
-                SseOpcode::Addps => (LegacyPrefixes::None, 0x0F58, 2),
-                SseOpcode::Addpd => (LegacyPrefixes::_66, 0x0F58, 2),
-                SseOpcode::Addss => (LegacyPrefixes::_F3, 0x0F58, 2),
-                SseOpcode::Addsd => (LegacyPrefixes::_F2, 0x0F58, 2),
-                SseOpcode::Andps => (LegacyPrefixes::None, 0x0F54, 2),
-                SseOpcode::Andpd => (LegacyPrefixes::_66, 0x0F54, 2),
-                SseOpcode::Andnps => (LegacyPrefixes::None, 0x0F55, 2),
-                SseOpcode::Andnpd => (LegacyPrefixes::_66, 0x0F55, 2),
-                SseOpcode::Cvttps2dq => (LegacyPrefixes::_F3, 0x0F5B, 2),
-                SseOpcode::Cvtdq2ps => (LegacyPrefixes::None, 0x0F5B, 2),
-                SseOpcode::Divps => (LegacyPrefixes::None, 0x0F5E, 2),
-                SseOpcode::Divpd => (LegacyPrefixes::_66, 0x0F5E, 2),
-                SseOpcode::Divss => (LegacyPrefixes::_F3, 0x0F5E, 2),
-                SseOpcode::Divsd => (LegacyPrefixes::_F2, 0x0F5E, 2),
-                SseOpcode::Maxps => (LegacyPrefixes::None, 0x0F5F, 2),
-                SseOpcode::Maxpd => (LegacyPrefixes::_66, 0x0F5F, 2),
-                SseOpcode::Maxss => (LegacyPrefixes::_F3, 0x0F5F, 2),
-                SseOpcode::Maxsd => (LegacyPrefixes::_F2, 0x0F5F, 2),
-                SseOpcode::Minps => (LegacyPrefixes::None, 0x0F5D, 2),
-                SseOpcode::Minpd => (LegacyPrefixes::_66, 0x0F5D, 2),
-                SseOpcode::Minss => (LegacyPrefixes::_F3, 0x0F5D, 2),
-                SseOpcode::Minsd => (LegacyPrefixes::_F2, 0x0F5D, 2),
-                SseOpcode::Movlhps => (LegacyPrefixes::None, 0x0F16, 2),
-                SseOpcode::Movsd => (LegacyPrefixes::_F2, 0x0F10, 2),
-                SseOpcode::Mulps => (LegacyPrefixes::None, 0x0F59, 2),
-                SseOpcode::Mulpd => (LegacyPrefixes::_66, 0x0F59, 2),
-                SseOpcode::Mulss => (LegacyPrefixes::_F3, 0x0F59, 2),
-                SseOpcode::Mulsd => (LegacyPrefixes::_F2, 0x0F59, 2),
-                SseOpcode::Orpd => (LegacyPrefixes::_66, 0x0F56, 2),
-                SseOpcode::Orps => (LegacyPrefixes::None, 0x0F56, 2),
-                SseOpcode::Packsswb => (LegacyPrefixes::_66, 0x0F63, 2),
-                SseOpcode::Paddb => (LegacyPrefixes::_66, 0x0FFC, 2),
-                SseOpcode::Paddd => (LegacyPrefixes::_66, 0x0FFE, 2),
-                SseOpcode::Paddq => (LegacyPrefixes::_66, 0x0FD4, 2),
-                SseOpcode::Paddw => (LegacyPrefixes::_66, 0x0FFD, 2),
-                SseOpcode::Paddsb => (LegacyPrefixes::_66, 0x0FEC, 2),
-                SseOpcode::Paddsw => (LegacyPrefixes::_66, 0x0FED, 2),
-                SseOpcode::Paddusb => (LegacyPrefixes::_66, 0x0FDC, 2),
-                SseOpcode::Paddusw => (LegacyPrefixes::_66, 0x0FDD, 2),
-                SseOpcode::Pand => (LegacyPrefixes::_66, 0x0FDB, 2),
-                SseOpcode::Pandn => (LegacyPrefixes::_66, 0x0FDF, 2),
-                SseOpcode::Pavgb => (LegacyPrefixes::_66, 0x0FE0, 2),
-                SseOpcode::Pavgw => (LegacyPrefixes::_66, 0x0FE3, 2),
-                SseOpcode::Pcmpeqb => (LegacyPrefixes::_66, 0x0F74, 2),
-                SseOpcode::Pcmpeqw => (LegacyPrefixes::_66, 0x0F75, 2),
-                SseOpcode::Pcmpeqd => (LegacyPrefixes::_66, 0x0F76, 2),
-                SseOpcode::Pcmpeqq => (LegacyPrefixes::_66, 0x0F3829, 3),
-                SseOpcode::Pcmpgtb => (LegacyPrefixes::_66, 0x0F64, 2),
-                SseOpcode::Pcmpgtw => (LegacyPrefixes::_66, 0x0F65, 2),
-                SseOpcode::Pcmpgtd => (LegacyPrefixes::_66, 0x0F66, 2),
-                SseOpcode::Pcmpgtq => (LegacyPrefixes::_66, 0x0F3837, 3),
-                SseOpcode::Pmaxsb => (LegacyPrefixes::_66, 0x0F383C, 3),
-                SseOpcode::Pmaxsw => (LegacyPrefixes::_66, 0x0FEE, 2),
-                SseOpcode::Pmaxsd => (LegacyPrefixes::_66, 0x0F383D, 3),
-                SseOpcode::Pmaxub => (LegacyPrefixes::_66, 0x0FDE, 2),
-                SseOpcode::Pmaxuw => (LegacyPrefixes::_66, 0x0F383E, 3),
-                SseOpcode::Pmaxud => (LegacyPrefixes::_66, 0x0F383F, 3),
-                SseOpcode::Pminsb => (LegacyPrefixes::_66, 0x0F3838, 3),
-                SseOpcode::Pminsw => (LegacyPrefixes::_66, 0x0FEA, 2),
-                SseOpcode::Pminsd => (LegacyPrefixes::_66, 0x0F3839, 3),
-                SseOpcode::Pminub => (LegacyPrefixes::_66, 0x0FDA, 2),
-                SseOpcode::Pminuw => (LegacyPrefixes::_66, 0x0F383A, 3),
-                SseOpcode::Pminud => (LegacyPrefixes::_66, 0x0F383B, 3),
-                SseOpcode::Pmulld => (LegacyPrefixes::_66, 0x0F3840, 3),
-                SseOpcode::Pmullw => (LegacyPrefixes::_66, 0x0FD5, 2),
-                SseOpcode::Pmuludq => (LegacyPrefixes::_66, 0x0FF4, 2),
-                SseOpcode::Por => (LegacyPrefixes::_66, 0x0FEB, 2),
-                SseOpcode::Pshufb => (LegacyPrefixes::_66, 0x0F3800, 3),
-                SseOpcode::Psubb => (LegacyPrefixes::_66, 0x0FF8, 2),
-                SseOpcode::Psubd => (LegacyPrefixes::_66, 0x0FFA, 2),
-                SseOpcode::Psubq => (LegacyPrefixes::_66, 0x0FFB, 2),
-                SseOpcode::Psubw => (LegacyPrefixes::_66, 0x0FF9, 2),
-                SseOpcode::Psubsb => (LegacyPrefixes::_66, 0x0FE8, 2),
-                SseOpcode::Psubsw => (LegacyPrefixes::_66, 0x0FE9, 2),
-                SseOpcode::Psubusb => (LegacyPrefixes::_66, 0x0FD8, 2),
-                SseOpcode::Psubusw => (LegacyPrefixes::_66, 0x0FD9, 2),
-                SseOpcode::Punpckhbw => (LegacyPrefixes::_66, 0x0F68, 2),
-                SseOpcode::Punpcklbw => (LegacyPrefixes::_66, 0x0F60, 2),
-                SseOpcode::Pxor => (LegacyPrefixes::_66, 0x0FEF, 2),
-                SseOpcode::Subps => (LegacyPrefixes::None, 0x0F5C, 2),
-                SseOpcode::Subpd => (LegacyPrefixes::_66, 0x0F5C, 2),
-                SseOpcode::Subss => (LegacyPrefixes::_F3, 0x0F5C, 2),
-                SseOpcode::Subsd => (LegacyPrefixes::_F2, 0x0F5C, 2),
-                SseOpcode::Xorps => (LegacyPrefixes::None, 0x0F57, 2),
-                SseOpcode::Xorpd => (LegacyPrefixes::_66, 0x0F57, 2),
-                _ => unimplemented!("Opcode {:?} not implemented", op),
-            };
+            let (prefix, opcode, length) = xmm_rm_r_encoding(*op);
 
             match src_e {
                 RegMem::Reg { reg: reg_e } => {
@@ -1858,6 +2051,78 @@ pub(crate) fn emit(
             }
         }
 
+        Inst::XmmRmRVex {
+            op,
+            src1,
+            src2,
+            dst,
+        } => {
+            let (prefix, opcode, length) = xmm_rm_r_encoding(*op);
+            let (map, opcode) = OpcodeMap::from_legacy(opcode, length);
+            emit_vex(
+                sink,
+                state,
+                prefix,
+                map,
+                false,
+                opcode,
+                dst.to_reg(),
+                Some(*src1),
+                src2,
+            );
+        }
+
+        Inst::XmmUnaryRmRVex { op, src, dst } => {
+            let (prefix, map, opcode) = match op {
+                AvxOpcode::Vpbroadcastb => (LegacyPrefixes::_66, OpcodeMap::_0F38, 0x78),
+                AvxOpcode::Vpbroadcastw => (LegacyPrefixes::_66, OpcodeMap::_0F38, 0x79),
+                AvxOpcode::Vpbroadcastd => (LegacyPrefixes::_66, OpcodeMap::_0F38, 0x58),
+                AvxOpcode::Vpbroadcastq => (LegacyPrefixes::_66, OpcodeMap::_0F38, 0x59),
+            };
+            emit_vex(
+                sink,
+                state,
+                prefix,
+                map,
+                false,
+                opcode,
+                dst.to_reg(),
+                None,
+                src,
+            );
+        }
+
+        Inst::XmmUnaryRmREvex { op, src, dst } => {
+            let (prefix, map, w, opcode) = match op {
+                Avx512Opcode::Vcvtudq2ps => (LegacyPrefixes::_F2, OpcodeMap::_0F, false, 0x7A),
+                Avx512Opcode::Vpabsq => (LegacyPrefixes::_66, OpcodeMap::_0F38, true, 0x1F),
+                _ => unimplemented!("Opcode {:?} not implemented", op),
+            };
+            emit_evex(sink, state, prefix, map, w, opcode, dst.to_reg(), None, src);
+        }
+
+        Inst::XmmRmREvex {
+            op,
+            src1,
+            src2,
+            dst,
+        } => {
+            let (prefix, map, w, opcode) = match op {
+                Avx512Opcode::Vpmullq => (LegacyPrefixes::_66, OpcodeMap::_0F38, true, 0x40),
+                _ => unimplemented!("Opcode {:?} not implemented", op),
+            };
+            emit_evex(
+                sink,
+                state,
+                prefix,
+                map,
+                w,
+                opcode,
+                dst.to_reg(),
+                Some(*src1),
+                src2,
+            );
+        }
         Inst::XmmMinMaxSeq {
             size,
             is_min,
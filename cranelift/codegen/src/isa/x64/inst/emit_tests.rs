@@ -1015,6 +1015,26 @@ fn test_x64_emit() {
         "4C29FA",
         "subq    %r15, %rdx",
     ));
+    insns.push((
+        Inst::alu_rmi_r(true, AluRmiROpcode::Adc, RegMemImm::reg(r15), w_rdx),
+        "4C11FA",
+        "adcq    %r15, %rdx",
+    ));
+    insns.push((
+        Inst::alu_rmi_r(true, AluRmiROpcode::Adc, RegMemImm::imm(1), w_rdx),
+        "4883D201",
+        "adcq    $1, %rdx",
+    ));
+    insns.push((
+        Inst::alu_rmi_r(true, AluRmiROpcode::Sbb, RegMemImm::reg(r15), w_rdx),
+        "4C19FA",
+        "sbbq    %r15, %rdx",
+    ));
+    insns.push((
+        Inst::alu_rmi_r(true, AluRmiROpcode::Sbb, RegMemImm::imm(1), w_rdx),
+        "4883DA01",
+        "sbbq    $1, %rdx",
+    ));
     insns.push((
         Inst::alu_rmi_r(true, AluRmiROpcode::And, RegMemImm::reg(r15), w_rdx),
         "4C21FA",
@@ -3376,6 +3396,167 @@ fn test_x64_emit() {
         "cvtsi2sd %rsi, %xmm1",
     ));
 
+    // ========================================================
+    // XmmRmRVex
+    insns.push((
+        Inst::xmm_rm_r_vex(SseOpcode::Paddd, xmm1, RegMem::reg(xmm2), w_xmm0),
+        "C5F1FEC2",
+        "vpaddd  %xmm2, %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(SseOpcode::Paddd, xmm11, RegMem::reg(xmm10), w_xmm12),
+        "C44121FEE2",
+        "vpaddd  %xmm10, %xmm11, %xmm12",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(SseOpcode::Addps, xmm9, RegMem::reg(xmm3), w_xmm1),
+        "C5B058CB",
+        "vaddps  %xmm3, %xmm9, %xmm1",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(SseOpcode::Pmulld, xmm4, RegMem::reg(xmm5), w_xmm3),
+        "C4E25940DD",
+        "vpmulld %xmm5, %xmm4, %xmm3",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(
+            SseOpcode::Paddd,
+            xmm1,
+            RegMem::mem(Amode::imm_reg_reg_shift(123, r10, rdx, 2)),
+            w_xmm0,
+        ),
+        "C4C171FE44927B",
+        "vpaddd  123(%r10,%rdx,4), %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(
+            SseOpcode::Subpd,
+            xmm15,
+            RegMem::mem(Amode::imm_reg_reg_shift(16, rax, r9, 0)),
+            w_xmm8,
+        ),
+        "C421015C440810",
+        "vsubpd  16(%rax,%r9,1), %xmm15, %xmm8",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_vex(
+            SseOpcode::Pcmpeqq,
+            xmm6,
+            RegMem::mem(Amode::imm_reg(0, rsp)),
+            w_xmm7,
+        ),
+        "C4E249293C24",
+        "vpcmpeqq 0(%rsp), %xmm6, %xmm7",
+    ));
+
+    // ========================================================
+    // XmmUnaryRmRVex
+    insns.push((
+        Inst::xmm_unary_rm_r_vex(AvxOpcode::Vpbroadcastb, RegMem::reg(xmm1), w_xmm0),
+        "C4E27978C1",
+        "vpbroadcastb %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_vex(AvxOpcode::Vpbroadcastw, RegMem::reg(xmm9), w_xmm2),
+        "C4C27979D1",
+        "vpbroadcastw %xmm9, %xmm2",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_vex(AvxOpcode::Vpbroadcastd, RegMem::reg(xmm3), w_xmm13),
+        "C4627958EB",
+        "vpbroadcastd %xmm3, %xmm13",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_vex(
+            AvxOpcode::Vpbroadcastq,
+            RegMem::mem(Amode::imm_reg(0, rsi)),
+            w_xmm12,
+        ),
+        "C462795926",
+        "vpbroadcastq 0(%rsi), %xmm12",
+    ));
+
+    // ========================================================
+    // XmmRmREvex
+    insns.push((
+        Inst::xmm_rm_r_evex(Avx512Opcode::Vpmullq, xmm1, RegMem::reg(xmm2), w_xmm0),
+        "62F2F50840C2",
+        "vpmullq %xmm2, %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_evex(Avx512Opcode::Vpmullq, xmm10, RegMem::reg(xmm13), w_xmm11),
+        "6252AD0840DD",
+        "vpmullq %xmm13, %xmm10, %xmm11",
+    ));
+    // The 8-bit displacement is compressed (scaled by 16).
+    insns.push((
+        Inst::xmm_rm_r_evex(
+            Avx512Opcode::Vpmullq,
+            xmm1,
+            RegMem::mem(Amode::imm_reg(64, rax)),
+            w_xmm0,
+        ),
+        "62F2F508404004",
+        "vpmullq 64(%rax), %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_evex(
+            Avx512Opcode::Vpmullq,
+            xmm1,
+            RegMem::mem(Amode::imm_reg(20, rax)),
+            w_xmm0,
+        ),
+        "62F2F508408014000000",
+        "vpmullq 20(%rax), %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_evex(
+            Avx512Opcode::Vpmullq,
+            xmm1,
+            RegMem::mem(Amode::imm_reg(2048, r12)),
+            w_xmm0,
+        ),
+        "62D2F50840842400080000",
+        "vpmullq 2048(%r12), %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_rm_r_evex(
+            Avx512Opcode::Vpmullq,
+            xmm1,
+            RegMem::mem(Amode::imm_reg_reg_shift(-16i32 as u32, rbp, r9, 3)),
+            w_xmm0,
+        ),
+        "62B2F5084044CDFF",
+        "vpmullq -16(%rbp,%r9,8), %xmm1, %xmm0",
+    ));
+
+    // ========================================================
+    // XmmUnaryRmREvex
+    insns.push((
+        Inst::xmm_unary_rm_r_evex(Avx512Opcode::Vpabsq, RegMem::reg(xmm1), w_xmm0),
+        "62F2FD081FC1",
+        "vpabsq  %xmm1, %xmm0",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_evex(Avx512Opcode::Vpabsq, RegMem::reg(xmm14), w_xmm9),
+        "6252FD081FCE",
+        "vpabsq  %xmm14, %xmm9",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_evex(Avx512Opcode::Vcvtudq2ps, RegMem::reg(xmm3), w_xmm2),
+        "62F17F087AD3",
+        "vcvtudq2ps %xmm3, %xmm2",
+    ));
+    insns.push((
+        Inst::xmm_unary_rm_r_evex(
+            Avx512Opcode::Vcvtudq2ps,
+            RegMem::mem(Amode::imm_reg(-32i32 as u32, rbp)),
+            w_xmm10,
+        ),
+        "62717F087A55FE",
+        "vcvtudq2ps -32(%rbp), %xmm10",
+    ));
+
     // ========================================================
     // XmmRmi
     insns.push((
@@ -3580,6 +3761,11 @@ fn test_x64_emit() {
     let mut isa_flag_builder = x64::settings::builder();
     isa_flag_builder.enable("has_ssse3").unwrap();
     isa_flag_builder.enable("has_sse41").unwrap();
+    isa_flag_builder.enable("has_avx").unwrap();
+    isa_flag_builder.enable("has_avx2").unwrap();
+    isa_flag_builder.enable("has_avx512dq").unwrap();
+    isa_flag_builder.enable("has_avx512vl").unwrap();
+    isa_flag_builder.enable("has_avx512f").unwrap();
     let isa_flags = x64::settings::Flags::new(&flags, isa_flag_builder);
 
     let rru = regs::create_reg_universe_systemv(&flags);
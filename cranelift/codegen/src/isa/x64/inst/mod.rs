@@ -325,6 +325,38 @@ pub enum Inst {
         is64: bool,
     },
 
+    /// XMM (vector) binary op using the non-destructive VEX encoding of an SSE operation:
+    /// `dst = src1 op src2`.
+    XmmRmRVex {
+        op: SseOpcode,
+        src1: Reg,
+        src2: RegMem,
+        dst: Writable<Reg>,
+    },
+
+    /// XMM (vector) unary op with a VEX-only encoding: vpbroadcast.
+    XmmUnaryRmRVex {
+        op: AvxOpcode,
+        src: RegMem,
+        dst: Writable<Reg>,
+    },
+
+    /// XMM (vector) unary op with an EVEX (AVX-512) encoding: vcvtudq2ps, vpabsq.
+    XmmUnaryRmREvex {
+        op: Avx512Opcode,
+        src: RegMem,
+        dst: Writable<Reg>,
+    },
+
+    /// XMM (vector) binary op with an EVEX (AVX-512) encoding: `dst = src1 op src2`, e.g.
+    /// vpmullq.
+    XmmRmREvex {
+        op: Avx512Opcode,
+        src1: Reg,
+        src2: RegMem,
+        dst: Writable<Reg>,
+    },
+
     // =====================================
     // Control flow instructions.
     /// Direct call: call simm32.
@@ -538,6 +570,13 @@ impl Inst {
             | Inst::XmmRmRImm { op, .. }
             | Inst::XmmToGpr { op, .. }
             | Inst::XmmUnaryRmR { op, .. } => Some(op.available_from()),
+
+            // VEX-encoded forms of SSE operations only require AVX.
+            Inst::XmmRmRVex { .. } => Some(InstructionSet::AVX),
+            Inst::XmmUnaryRmRVex { op, .. } => Some(op.available_from()),
+            Inst::XmmUnaryRmREvex { op, .. } | Inst::XmmRmREvex { op, .. } => {
+                Some(op.available_from())
+            }
         }
     }
 }
@@ -674,6 +713,47 @@ impl Inst {
         Inst::XmmRmR { op, src, dst }
     }
 
+    pub(crate) fn xmm_rm_r_vex(op: SseOpcode, src1: Reg, src2: RegMem, dst: Writable<Reg>) -> Self {
+        debug_assert!(src1.get_class() == RegClass::V128);
+        src2.assert_regclass_is(RegClass::V128);
+        debug_assert!(dst.to_reg().get_class() == RegClass::V128);
+        Inst::XmmRmRVex {
+            op,
+            src1,
+            src2,
+            dst,
+        }
+    }
+
+    pub(crate) fn xmm_unary_rm_r_vex(op: AvxOpcode, src: RegMem, dst: Writable<Reg>) -> Self {
+        src.assert_regclass_is(RegClass::V128);
+        debug_assert!(dst.to_reg().get_class() == RegClass::V128);
+        Inst::XmmUnaryRmRVex { op, src, dst }
+    }
+
+    pub(crate) fn xmm_unary_rm_r_evex(op: Avx512Opcode, src: RegMem, dst: Writable<Reg>) -> Self {
+        src.assert_regclass_is(RegClass::V128);
+        debug_assert!(dst.to_reg().get_class() == RegClass::V128);
+        Inst::XmmUnaryRmREvex { op, src, dst }
+    }
+
+    pub(crate) fn xmm_rm_r_evex(
+        op: Avx512Opcode,
+        src1: Reg,
+        src2: RegMem,
+        dst: Writable<Reg>,
+    ) -> Self {
+        debug_assert!(src1.get_class() == RegClass::V128);
+        src2.assert_regclass_is(RegClass::V128);
+        debug_assert!(dst.to_reg().get_class() == RegClass::V128);
+        Inst::XmmRmREvex {
+            op,
+            src1,
+            src2,
+            dst,
+        }
+    }
+
     pub(crate) fn xmm_uninit_value(dst: Writable<Reg>) -> Self {
         debug_assert!(dst.to_reg().get_class() == RegClass::V128);
         Inst::XmmUninitializedValue { dst }
@@ -1342,6 +1422,46 @@ impl PrettyPrint for Inst {
                 show_ireg_sized(dst.to_reg(), mb_rru, 8),
             ),
 
+            Inst::XmmRmRVex {
+                op,
+                src1,
+                src2,
+                dst,
+            } => format!(
+                "{} {}, {}, {}",
+                ljustify(format!("v{}", op)),
+                src2.show_rru_sized(mb_rru, 8),
+                show_ireg_sized(*src1, mb_rru, 8),
+                show_ireg_sized(dst.to_reg(), mb_rru, 8),
+            ),
+
+            Inst::XmmUnaryRmRVex { op, src, dst } => format!(
+                "{} {}, {}",
+                ljustify(op.to_string()),
+                src.show_rru_sized(mb_rru, 8),
+                show_ireg_sized(dst.to_reg(), mb_rru, 8),
+            ),
+
+            Inst::XmmUnaryRmREvex { op, src, dst } => format!(
+                "{} {}, {}",
+                ljustify(op.to_string()),
+                src.show_rru_sized(mb_rru, 8),
+                show_ireg_sized(dst.to_reg(), mb_rru, 8),
+            ),
+
+            Inst::XmmRmREvex {
+                op,
+                src1,
+                src2,
+                dst,
+            } => format!(
+                "{} {}, {}, {}",
+                ljustify(op.to_string()),
+                src2.show_rru_sized(mb_rru, 8),
+                show_ireg_sized(*src1, mb_rru, 8),
+                show_ireg_sized(dst.to_reg(), mb_rru, 8),
+            ),
+
             Inst::XmmMinMaxSeq {
                 lhs,
                 rhs_dst,
@@ -1809,6 +1929,20 @@ fn x64_get_regs(inst: &Inst, collector: &mut RegUsageCollector) {
                 collector.add_mod(*dst);
             }
         }
+        Inst::XmmRmRVex {
+            src1, src2, dst, ..
+        }
+        | Inst::XmmRmREvex {
+            src1, src2, dst, ..
+        } => {
+            collector.add_use(*src1);
+            src2.get_regs_as_uses(collector);
+            collector.add_def(*dst);
+        }
+        Inst::XmmUnaryRmRVex { src, dst, .. } | Inst::XmmUnaryRmREvex { src, dst, .. } => {
+            src.get_regs_as_uses(collector);
+            collector.add_def(*dst);
+        }
         Inst::XmmUninitializedValue { dst } => collector.add_def(*dst),
         Inst::XmmLoadConst { dst, .. } => collector.add_def(*dst),
         Inst::XmmMinMaxSeq { lhs, rhs_dst, .. } => {
@@ -2144,6 +2278,35 @@ fn x64_map_regs<RUM: RegUsageMapper>(inst: &mut Inst, mapper: &RUM) {
             src.map_uses(mapper);
             map_mod(mapper, dst);
         }
+        Inst::XmmRmRVex {
+            ref mut src1,
+            ref mut src2,
+            ref mut dst,
+            ..
+        }
+        | Inst::XmmRmREvex {
+            ref mut src1,
+            ref mut src2,
+            ref mut dst,
+            ..
+        } => {
+            map_use(mapper, src1);
+            src2.map_uses(mapper);
+            map_def(mapper, dst);
+        }
+        Inst::XmmUnaryRmRVex {
+            ref mut src,
+            ref mut dst,
+            ..
+        }
+        | Inst::XmmUnaryRmREvex {
+            ref mut src,
+            ref mut dst,
+            ..
+        } => {
+            src.map_uses(mapper);
+            map_def(mapper, dst);
+        }
         Inst::XmmUninitializedValue { ref mut dst, .. } => {
             map_def(mapper, dst);
         }
@@ -2461,22 +2624,25 @@ impl MachInst for Inst {
         None
     }
 
-    fn rc_for_type(ty: Type) -> CodegenResult<RegClass> {
+    fn rc_for_type(ty: Type) -> CodegenResult<(&'static [RegClass], &'static [Type])> {
         match ty {
-            types::I8
-            | types::I16
-            | types::I32
-            | types::I64
-            | types::B1
-            | types::B8
-            | types::B16
-            | types::B32
-            | types::B64
-            | types::R32
-            | types::R64 => Ok(RegClass::I64),
-            types::F32 | types::F64 => Ok(RegClass::V128),
-            _ if ty.bits() == 128 => Ok(RegClass::V128),
-            types::IFLAGS | types::FFLAGS => Ok(RegClass::I64),
+            types::I8 => Ok((&[RegClass::I64], &[types::I8])),
+            types::I16 => Ok((&[RegClass::I64], &[types::I16])),
+            types::I32 => Ok((&[RegClass::I64], &[types::I32])),
+            types::I64 => Ok((&[RegClass::I64], &[types::I64])),
+            types::B1 => Ok((&[RegClass::I64], &[types::B1])),
+            types::B8 => Ok((&[RegClass::I64], &[types::B8])),
+            types::B16 => Ok((&[RegClass::I64], &[types::B16])),
+            types::B32 => Ok((&[RegClass::I64], &[types::B32])),
+            types::B64 => Ok((&[RegClass::I64], &[types::B64])),
+            types::R32 => panic!("32-bit reftype pointer should never be seen on x86-64"),
+            types::R64 => Ok((&[RegClass::I64], &[types::R64])),
+            types::I128 => Ok((&[RegClass::I64, RegClass::I64], &[types::I64, types::I64])),
+            types::F32 => Ok((&[RegClass::V128], &[types::F32])),
+            types::F64 => Ok((&[RegClass::V128], &[types::F64])),
+            types::B128 => Ok((&[RegClass::V128], &[types::B128])),
+            _ if ty.is_vector() && ty.bits() == 128 => Ok((&[RegClass::V128], &[types::I8X16])),
+            types::IFLAGS | types::FFLAGS => Ok((&[RegClass::I64], &[types::I64])),
             _ => Err(CodegenError::Unsupported(format!(
                 "Unexpected SSA-value type: {}",
                 ty
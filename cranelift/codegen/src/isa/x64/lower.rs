@@ -3,12 +3,12 @@
 use crate::data_value::DataValue;
 use crate::ir::{
     condcodes::FloatCC, condcodes::IntCC, types, AbiParam, ArgumentPurpose, ExternalName,
-    Inst as IRInst, InstructionData, LibCall, Opcode, Signature, Type,
+    Inst as IRInst, InstructionData, LibCall, Opcode, Signature, TrapCode, Type,
 };
 use crate::isa::x64::abi::*;
 use crate::isa::x64::inst::args::*;
 use crate::isa::x64::inst::*;
-use crate::isa::{x64::X64Backend, CallConv};
+use crate::isa::{x64::settings as x64_settings, x64::X64Backend, CallConv};
 use crate::machinst::lower::*;
 use crate::machinst::*;
 use crate::result::CodegenResult;
@@ -94,6 +94,13 @@ fn lowerinput_to_reg(ctx: Ctx, input: LowerInput) -> Reg {
     input.reg
 }
 
+/// Returns the `icmp` instruction that produces the given input, if any, and if its comparison
+/// can be merged into the user. Comparisons of `I128` values are never merged, since they don't
+/// reduce to a single condition code.
+fn matches_icmp<C: LowerCtx<I = Inst>>(ctx: &mut C, input: InsnInput) -> Option<IRInst> {
+    matches_input(ctx, input, Opcode::Icmp).filter(|&icmp| ctx.input_ty(icmp, 0) != types::I128)
+}
+
 /// Put the given input into a register, and mark it as used (side-effect).
 fn put_input_in_reg(ctx: Ctx, spec: InsnInput) -> Reg {
     let input = ctx.get_input(spec.insn, spec.input);
@@ -108,7 +115,7 @@ fn put_input_in_reg(ctx: Ctx, spec: InsnInput) -> Reg {
             c
         };
 
-        let cst_copy = ctx.alloc_tmp(Inst::rc_for_type(ty).unwrap(), ty);
+        let cst_copy = ctx.alloc_tmp(Inst::rc_for_type(ty).unwrap().0[0], ty);
         for inst in Inst::gen_constant(cst_copy, masked, ty, |reg_class, ty| {
             ctx.alloc_tmp(reg_class, ty)
         })
@@ -122,6 +129,243 @@ fn put_input_in_reg(ctx: Ctx, spec: InsnInput) -> Reg {
     }
 }
 
+/// Put the given input into as many registers as its type requires, marking it as used. `I128`
+/// values live in a pair of 64-bit registers, low part first.
+fn put_input_in_regs(ctx: Ctx, spec: InsnInput) -> ValueRegs<Reg> {
+    let ty = ctx.input_ty(spec.insn, spec.input);
+    if ty == types::I128 {
+        ctx.put_input_in_regs(spec.insn, spec.input)
+    } else {
+        ValueRegs::one(put_input_in_reg(ctx, spec))
+    }
+}
+
+/// Copy a value that may live in several registers; the parts of an `I128` are moved as `I64`s.
+fn emit_moves(ctx: Ctx, dst: ValueRegs<Writable<Reg>>, src: ValueRegs<Reg>, ty: Type) {
+    if let (Some(dst), Some(src)) = (dst.only_reg(), src.only_reg()) {
+        ctx.emit(Inst::gen_move(dst, src, ty));
+    } else {
+        assert_eq!(dst.len(), src.len());
+        for (&dst, &src) in dst.regs().iter().zip(src.regs().iter()) {
+            ctx.emit(Inst::gen_move(dst, src, types::I64));
+        }
+    }
+}
+
+/// Emits a shift of the `I128` in `src` by the dynamic amount in `amt`, of which only the low 7
+/// bits are used, into `dst`. `kind` must be one of the non-rotating shifts.
+fn emit_shift_i128(
+    ctx: Ctx,
+    src: ValueRegs<Reg>,
+    dst: ValueRegs<Writable<Reg>>,
+    amt: Reg,
+    kind: ShiftKind,
+) {
+    let (src_lo, src_hi) = (src.regs()[0], src.regs()[1]);
+    let (dst_lo, dst_hi) = (dst.regs()[0], dst.regs()[1]);
+
+    // The part that is shifted by the amount, the part that receives the bits crossing the
+    // 64-bit boundary, and the value that fills the vacated part when shifting by 64 or more.
+    let (from, into) = match kind {
+        ShiftKind::ShiftLeft => (src_lo, src_hi),
+        ShiftKind::ShiftRightLogical | ShiftKind::ShiftRightArithmetic => (src_hi, src_lo),
+        _ => unreachable!("not a shift: {:?}", kind),
+    };
+    let cross_kind = match kind {
+        ShiftKind::ShiftLeft => ShiftKind::ShiftRightLogical,
+        _ => ShiftKind::ShiftLeft,
+    };
+    let into_kind = match kind {
+        ShiftKind::ShiftRightArithmetic => ShiftKind::ShiftRightLogical,
+        _ => kind,
+    };
+
+    let w_rcx = Writable::from_reg(regs::rcx());
+    let shifted = ctx.alloc_tmp(RegClass::I64, types::I64);
+    let merged = ctx.alloc_tmp(RegClass::I64, types::I64);
+    let cross = ctx.alloc_tmp(RegClass::I64, types::I64);
+    let fill = ctx.alloc_tmp(RegClass::I64, types::I64);
+    let zero = ctx.alloc_tmp(RegClass::I64, types::I64);
+    let tmp = ctx.alloc_tmp(RegClass::I64, types::I64);
+
+    // shifted = from << amt (resp. >>); merged = into << amt (resp. >>). The machine shifts
+    // only use the low 6 bits of %cl.
+    ctx.emit(Inst::mov_r_r(true, amt, w_rcx));
+    ctx.emit(Inst::mov_r_r(true, from, shifted));
+    ctx.emit(Inst::shift_r(8, kind, None, shifted));
+    ctx.emit(Inst::mov_r_r(true, into, merged));
+    ctx.emit(Inst::shift_r(8, into_kind, None, merged));
+
+    // cross = the bits of `from` that cross into the other part, i.e. `from` shifted the other
+    // way by 64 - amt.
+    ctx.emit(Inst::imm(OperandSize::Size64, 64, tmp));
+    ctx.emit(Inst::alu_rmi_r(
+        true,
+        AluRmiROpcode::Sub,
+        RegMemImm::reg(amt),
+        tmp,
+    ));
+    ctx.emit(Inst::mov_r_r(true, tmp.to_reg(), w_rcx));
+    ctx.emit(Inst::mov_r_r(true, from, cross));
+    ctx.emit(Inst::shift_r(8, cross_kind, None, cross));
+
+    // The value of the vacated part for shift amounts of 64 or more.
+    if kind == ShiftKind::ShiftRightArithmetic {
+        ctx.emit(Inst::mov_r_r(true, src_hi, fill));
+        ctx.emit(Inst::shift_r(
+            8,
+            ShiftKind::ShiftRightArithmetic,
+            Some(63),
+            fill,
+        ));
+    } else {
+        ctx.emit(Inst::imm(OperandSize::Size64, 0, fill));
+    }
+
+    // A shift by 64 - 0 is a shift by 0 at the machine level, so no bits cross over in that
+    // case; clear `cross` when the amount is a multiple of 64.
+    ctx.emit(Inst::imm(OperandSize::Size64, 0, zero));
+    ctx.emit(Inst::mov_r_r(true, amt, tmp));
+    ctx.emit(Inst::alu_rmi_r(
+        true,
+        AluRmiROpcode::And,
+        RegMemImm::imm(63),
+        tmp,
+    ));
+    ctx.emit(Inst::cmove(8, CC::Z, RegMem::reg(zero.to_reg()), cross));
+    ctx.emit(Inst::alu_rmi_r(
+        true,
+        AluRmiROpcode::Or,
+        RegMemImm::reg(cross.to_reg()),
+        merged,
+    ));
+
+    // Select the results, depending on whether the amount is at least 64: if so, `shifted`
+    // lands in the other part and the shifted part gets `fill`.
+    let (dst_shifted, dst_merged) = match kind {
+        ShiftKind::ShiftLeft => (dst_lo, dst_hi),
+        _ => (dst_hi, dst_lo),
+    };
+    ctx.emit(Inst::mov_r_r(true, amt, tmp));
+    ctx.emit(Inst::alu_rmi_r(
+        true,
+        AluRmiROpcode::And,
+        RegMemImm::imm(64),
+        tmp,
+    ));
+    ctx.emit(Inst::mov_r_r(true, fill.to_reg(), dst_shifted));
+    ctx.emit(Inst::cmove(
+        8,
+        CC::Z,
+        RegMem::reg(shifted.to_reg()),
+        dst_shifted,
+    ));
+    ctx.emit(Inst::mov_r_r(true, shifted.to_reg(), dst_merged));
+    ctx.emit(Inst::cmove(
+        8,
+        CC::Z,
+        RegMem::reg(merged.to_reg()),
+        dst_merged,
+    ));
+}
+
+/// Emits a comparison of the `I128`s in `lhs` and `rhs`, writing the boolean result to `dst`.
+fn emit_icmp_i128(
+    ctx: Ctx,
+    condcode: IntCC,
+    lhs: ValueRegs<Reg>,
+    rhs: ValueRegs<Reg>,
+    dst: Writable<Reg>,
+) {
+    let (lhs_lo, lhs_hi) = (lhs.regs()[0], lhs.regs()[1]);
+    let (rhs_lo, rhs_hi) = (rhs.regs()[0], rhs.regs()[1]);
+    match condcode {
+        IntCC::Equal | IntCC::NotEqual => {
+            // The values are equal iff (lhs_lo ^ rhs_lo) | (lhs_hi ^ rhs_hi) is zero.
+            let tmp_lo = ctx.alloc_tmp(RegClass::I64, types::I64);
+            let tmp_hi = ctx.alloc_tmp(RegClass::I64, types::I64);
+            ctx.emit(Inst::mov_r_r(true, lhs_lo, tmp_lo));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Xor,
+                RegMemImm::reg(rhs_lo),
+                tmp_lo,
+            ));
+            ctx.emit(Inst::mov_r_r(true, lhs_hi, tmp_hi));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Xor,
+                RegMemImm::reg(rhs_hi),
+                tmp_hi,
+            ));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Or,
+                RegMemImm::reg(tmp_lo.to_reg()),
+                tmp_hi,
+            ));
+            ctx.emit(Inst::setcc(CC::from_intcc(condcode), dst));
+        }
+        IntCC::SignedLessThan
+        | IntCC::SignedLessThanOrEqual
+        | IntCC::SignedGreaterThan
+        | IntCC::SignedGreaterThanOrEqual
+        | IntCC::UnsignedLessThan
+        | IntCC::UnsignedLessThanOrEqual
+        | IntCC::UnsignedGreaterThan
+        | IntCC::UnsignedGreaterThanOrEqual => {
+            // The high parts decide, unless they are equal: then the low parts, which are
+            // compared as unsigned values, decide. That is:
+            //   dst = (lhs_hi <strict cc> rhs_hi) | ((lhs_hi == rhs_hi) & (lhs_lo <unsigned cc> rhs_lo))
+            let tmp_hi_cc = ctx.alloc_tmp(RegClass::I64, types::I64);
+            let tmp_hi_eq = ctx.alloc_tmp(RegClass::I64, types::I64);
+            let tmp_lo_cc = ctx.alloc_tmp(RegClass::I64, types::I64);
+            ctx.emit(Inst::cmp_rmi_r(8, RegMemImm::reg(rhs_hi), lhs_hi));
+            ctx.emit(Inst::setcc(
+                CC::from_intcc(condcode.without_equal()),
+                tmp_hi_cc,
+            ));
+            ctx.emit(Inst::setcc(CC::Z, tmp_hi_eq));
+            ctx.emit(Inst::cmp_rmi_r(8, RegMemImm::reg(rhs_lo), lhs_lo));
+            ctx.emit(Inst::setcc(CC::from_intcc(condcode.unsigned()), tmp_lo_cc));
+            ctx.emit(Inst::alu_rmi_r(
+                false,
+                AluRmiROpcode::And,
+                RegMemImm::reg(tmp_hi_eq.to_reg()),
+                tmp_lo_cc,
+            ));
+            ctx.emit(Inst::mov_r_r(false, tmp_hi_cc.to_reg(), dst));
+            ctx.emit(Inst::alu_rmi_r(
+                false,
+                AluRmiROpcode::Or,
+                RegMemImm::reg(tmp_lo_cc.to_reg()),
+                dst,
+            ));
+        }
+        IntCC::Overflow | IntCC::NotOverflow => {
+            // The OF flag of the full 128-bit subtraction tells whether it overflowed; there is
+            // no `cmp` with borrow, so the differences go to scratch registers.
+            let tmp_lo = ctx.alloc_tmp(RegClass::I64, types::I64);
+            let tmp_hi = ctx.alloc_tmp(RegClass::I64, types::I64);
+            ctx.emit(Inst::mov_r_r(true, lhs_lo, tmp_lo));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Sub,
+                RegMemImm::reg(rhs_lo),
+                tmp_lo,
+            ));
+            ctx.emit(Inst::mov_r_r(true, lhs_hi, tmp_hi));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Sbb,
+                RegMemImm::reg(rhs_hi),
+                tmp_hi,
+            ));
+            ctx.emit(Inst::setcc(CC::from_intcc(condcode), dst));
+        }
+    }
+}
+
 /// An extension specification for `extend_input_to_reg`.
 #[derive(Clone, Copy)]
 enum ExtSpec {
@@ -216,6 +460,26 @@ fn input_to_reg_mem_imm(ctx: Ctx, spec: InsnInput) -> RegMemImm {
     }
 }
 
+/// Emits a packed (vector) SSE binary operation, `dst = lhs op rhs`. With AVX this uses the
+/// non-destructive VEX encoding; otherwise `lhs` is first moved to `dst`.
+fn emit_packed_binop<C: LowerCtx<I = Inst>>(
+    ctx: &mut C,
+    isa_flags: &x64_settings::Flags,
+    op: SseOpcode,
+    lhs: Reg,
+    rhs: RegMem,
+    dst: Writable<Reg>,
+    ty: Type,
+) {
+    debug_assert!(ty.is_vector());
+    if isa_flags.use_avx_simd() {
+        ctx.emit(Inst::xmm_rm_r_vex(op, lhs, rhs, dst));
+    } else {
+        ctx.emit(Inst::gen_move(dst, lhs, ty));
+        ctx.emit(Inst::xmm_rm_r(op, rhs, dst));
+    }
+}
+
 /// Emit an instruction to insert a value `src` into a lane of `dst`.
 fn emit_insert_lane<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
@@ -328,6 +592,208 @@ fn emit_cmp(ctx: Ctx, insn: IRInst) {
     ctx.emit(Inst::cmp_rmi_r(ty.bytes() as u8, rhs, lhs));
 }
 
+/// Emits a move of the F32 or F64 value `src` into `dst` for each of the condition codes `ccs`
+/// which is set.
+///
+/// `XmmCmove` branches around the move, so for constant-time code the values take a detour
+/// through integer registers to use `cmov` instead. The moves between register classes don't
+/// modify the flags.
+fn emit_xmm_cmoves(
+    ctx: Ctx,
+    flags: &Flags,
+    is_64: bool,
+    ccs: &[CC],
+    src: RegMem,
+    dst: Writable<Reg>,
+) {
+    if !flags.enable_constant_time() {
+        for &cc in ccs {
+            ctx.emit(Inst::xmm_cmove(is_64, cc, src.clone(), dst));
+        }
+        return;
+    }
+
+    let (mov_op, size) = if is_64 {
+        (SseOpcode::Movq, OperandSize::Size64)
+    } else {
+        (SseOpcode::Movd, OperandSize::Size32)
+    };
+    let src_bits = ctx.alloc_tmp(RegClass::I64, types::I64);
+    match src {
+        RegMem::Reg { reg } => ctx.emit(Inst::xmm_to_gpr(mov_op, reg, src_bits, size)),
+        RegMem::Mem { addr } if is_64 => ctx.emit(Inst::mov64_m_r(addr, src_bits)),
+        RegMem::Mem { addr } => {
+            ctx.emit(Inst::movzx_rm_r(ExtMode::LQ, RegMem::mem(addr), src_bits))
+        }
+    }
+    let dst_bits = ctx.alloc_tmp(RegClass::I64, types::I64);
+    ctx.emit(Inst::xmm_to_gpr(mov_op, dst.to_reg(), dst_bits, size));
+    for &cc in ccs {
+        ctx.emit(Inst::cmove(
+            size.to_bytes(),
+            cc,
+            RegMem::reg(src_bits.to_reg()),
+            dst_bits,
+        ));
+    }
+    ctx.emit(Inst::gpr_to_xmm(
+        mov_op,
+        RegMem::reg(dst_bits.to_reg()),
+        size,
+        dst,
+    ));
+}
+
+/// Emits a saturating conversion of the float `src` of type `input_ty` to an integer of type
+/// `output_ty`, without any branch.
+///
+/// `cvttss2si` and `cvttsd2si` return INT_MIN for NaN and out-of-range inputs; the correct value
+/// is then selected with `cmov`s, based on comparisons of the input against the bounds of the
+/// output range. Unsigned conversions also truncate `src - 2^(N-1)`, to handle the upper half of
+/// the range.
+fn emit_cvt_float_to_int_sat_branchless(
+    ctx: Ctx,
+    to_signed: bool,
+    src: Reg,
+    dst: Writable<Reg>,
+    input_ty: Type,
+    output_ty: Type,
+) {
+    let (cast_op, cmp_op, sub_op, trunc_op) = if input_ty == types::F64 {
+        (
+            SseOpcode::Movq,
+            SseOpcode::Ucomisd,
+            SseOpcode::Subsd,
+            SseOpcode::Cvttsd2si,
+        )
+    } else {
+        (
+            SseOpcode::Movd,
+            SseOpcode::Ucomiss,
+            SseOpcode::Subss,
+            SseOpcode::Cvttss2si,
+        )
+    };
+    let src_size = OperandSize::from_bytes(input_ty.bytes());
+    let dst_size = OperandSize::from_bytes(output_ty.bytes());
+    let bits = output_ty.bits() as i32;
+
+    // Materializes the float 2^exp in a new XMM register.
+    let load_pow2 = |ctx: Ctx, exp: i32| {
+        let value = 2f64.powi(exp);
+        let value_bits = if input_ty == types::F64 {
+            value.to_bits()
+        } else {
+            (value as f32).to_bits() as u64
+        };
+        let tmp_gpr = ctx.alloc_tmp(RegClass::I64, types::I64);
+        ctx.emit(Inst::imm(src_size, value_bits, tmp_gpr));
+        let tmp_xmm = ctx.alloc_tmp(RegClass::V128, input_ty);
+        ctx.emit(Inst::gpr_to_xmm(
+            cast_op,
+            RegMem::reg(tmp_gpr.to_reg()),
+            src_size,
+            tmp_xmm,
+        ));
+        tmp_xmm.to_reg()
+    };
+
+    // Materializes the integer `value` in a new register.
+    let load_int = |ctx: Ctx, value: u64| {
+        let tmp = ctx.alloc_tmp(RegClass::I64, output_ty);
+        ctx.emit(Inst::imm(dst_size, value, tmp));
+        tmp.to_reg()
+    };
+
+    let int_min = 1u64 << (bits - 1);
+    let int_max = int_min - 1;
+    let uint_max = int_max | int_min;
+
+    ctx.emit(Inst::xmm_to_gpr(trunc_op, src, dst, dst_size));
+
+    if to_signed {
+        // Positive overflows saturate to INT_MAX; negative ones already gave INT_MIN.
+        let int_max = load_int(ctx, int_max);
+        let upper_bound = load_pow2(ctx, bits - 1);
+        ctx.emit(Inst::xmm_cmp_rm_r(cmp_op, RegMem::reg(upper_bound), src));
+        ctx.emit(Inst::cmove(
+            dst_size.to_bytes(),
+            CC::NB,
+            RegMem::reg(int_max),
+            dst,
+        ));
+
+        // NaN converts to 0.
+        let zero = load_int(ctx, 0);
+        ctx.emit(Inst::xmm_cmp_rm_r(cmp_op, RegMem::reg(src), src));
+        ctx.emit(Inst::cmove(
+            dst_size.to_bytes(),
+            CC::P,
+            RegMem::reg(zero),
+            dst,
+        ));
+    } else {
+        // Inputs in [2^(N-1), 2^N) are converted as `src - 2^(N-1)`, with the top bit set back.
+        let half = load_pow2(ctx, bits - 1);
+        let upper_half = ctx.alloc_tmp(RegClass::V128, input_ty);
+        ctx.emit(Inst::gen_move(upper_half, src, input_ty));
+        ctx.emit(Inst::xmm_rm_r(sub_op, RegMem::reg(half), upper_half));
+        let upper_half_int = ctx.alloc_tmp(RegClass::I64, output_ty);
+        ctx.emit(Inst::xmm_to_gpr(
+            trunc_op,
+            upper_half.to_reg(),
+            upper_half_int,
+            dst_size,
+        ));
+        let top_bit = load_int(ctx, int_min);
+        ctx.emit(Inst::alu_rmi_r(
+            dst_size == OperandSize::Size64,
+            AluRmiROpcode::Xor,
+            RegMemImm::reg(top_bit),
+            upper_half_int,
+        ));
+        ctx.emit(Inst::xmm_cmp_rm_r(cmp_op, RegMem::reg(half), src));
+        ctx.emit(Inst::cmove(
+            dst_size.to_bytes(),
+            CC::NB,
+            RegMem::reg(upper_half_int.to_reg()),
+            dst,
+        ));
+
+        // Inputs from 2^N saturate to UINT_MAX.
+        let uint_max = load_int(ctx, uint_max);
+        let upper_bound = load_pow2(ctx, bits);
+        ctx.emit(Inst::xmm_cmp_rm_r(cmp_op, RegMem::reg(upper_bound), src));
+        ctx.emit(Inst::cmove(
+            dst_size.to_bytes(),
+            CC::NB,
+            RegMem::reg(uint_max),
+            dst,
+        ));
+
+        // Negative inputs and NaN convert to 0; the comparison is unordered for NaN, which sets
+        // the carry flag.
+        let zero = load_int(ctx, 0);
+        let zero_xmm = ctx.alloc_tmp(RegClass::V128, input_ty);
+        ctx.emit(Inst::xmm_rm_r(
+            SseOpcode::Xorpd,
+            RegMem::reg(zero_xmm.to_reg()),
+            zero_xmm,
+        ));
+        ctx.emit(Inst::xmm_cmp_rm_r(
+            cmp_op,
+            RegMem::reg(zero_xmm.to_reg()),
+            src,
+        ));
+        ctx.emit(Inst::cmove(
+            dst_size.to_bytes(),
+            CC::B,
+            RegMem::reg(zero),
+            dst,
+        ));
+    }
+}
+
 /// A specification for a fcmp emission.
 enum FcmpSpec {
     /// Normal flow.
@@ -462,20 +928,20 @@ fn emit_vm_call<C: LowerCtx<I = Inst>>(
     assert_eq!(inputs.len() + vm_context, abi.num_args());
 
     for (i, input) in inputs.iter().enumerate() {
-        let arg_reg = put_input_in_reg(ctx, *input);
-        abi.emit_copy_reg_to_arg(ctx, i, arg_reg);
+        let arg_regs = put_input_in_regs(ctx, *input);
+        abi.emit_copy_regs_to_arg(ctx, i, arg_regs);
     }
     if call_conv.extends_baldrdash() {
         let vm_context_vreg = ctx
             .get_vm_context()
             .expect("should have a VMContext to pass to libcall funcs");
-        abi.emit_copy_reg_to_arg(ctx, inputs.len(), vm_context_vreg);
+        abi.emit_copy_regs_to_arg(ctx, inputs.len(), ValueRegs::one(vm_context_vreg));
     }
 
     abi.emit_call(ctx);
     for (i, output) in outputs.iter().enumerate() {
-        let retval_reg = get_output_reg(ctx, *output);
-        abi.emit_copy_retval_to_reg(ctx, i, retval_reg);
+        let retval_regs = get_output_regs(ctx, *output);
+        abi.emit_copy_retval_to_regs(ctx, i, retval_regs);
     }
     abi.emit_stack_post_adjust(ctx);
 
@@ -604,6 +1070,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
     ctx: &mut C,
     insn: IRInst,
     flags: &Flags,
+    isa_flags: &x64_settings::Flags,
     triple: &Triple,
 ) -> CodegenResult<()> {
     let op = ctx.data(insn).opcode();
@@ -622,6 +1089,23 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
     };
 
     match op {
+        Opcode::Iconst if ty == Some(types::I128) => {
+            // The immediate is sign-extended to 128 bits.
+            let imm = match ctx.data(insn) {
+                &InstructionData::UnaryImm { imm, .. } => imm.bits(),
+                _ => unreachable!(),
+            };
+            let dst = get_output_regs(ctx, outputs[0]);
+            let parts = [imm as u64, (imm >> 63) as u64];
+            for (&dst, &value) in dst.regs().iter().zip(parts.iter()) {
+                for inst in Inst::gen_constant(dst, value, types::I64, |reg_class, ty| {
+                    ctx.alloc_tmp(reg_class, ty)
+                }) {
+                    ctx.emit(inst);
+                }
+            }
+        }
+
         Opcode::Iconst | Opcode::Bconst | Opcode::Null => {
             let value = ctx
                 .get_constant(insn)
@@ -634,6 +1118,85 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::SaddSat | Opcode::UaddSat | Opcode::SsubSat | Opcode::UsubSat
+            if ty.map_or(false, |ty| ty.is_int() && ty != types::I128) =>
+        {
+            // Do the arithmetic on the operands shifted into the upper bits of a 32- or 64-bit
+            // register, so that it overflows exactly when the narrow operation does, and replace
+            // an overflowed result with the saturated value using a cmove.
+            let ty = ty.unwrap();
+            let is_64 = ty == types::I64;
+            let (size, op_size, reg_bits) = if is_64 {
+                (8, OperandSize::Size64, 64)
+            } else {
+                (4, OperandSize::Size32, 32)
+            };
+            let shift = reg_bits - ty.bits() as u8;
+            let is_signed = op == Opcode::SaddSat || op == Opcode::SsubSat;
+
+            let lhs = put_input_in_reg(ctx, inputs[0]);
+            let rhs = put_input_in_reg(ctx, inputs[1]);
+            let dst = get_output_reg(ctx, outputs[0]);
+            let shifted_rhs = ctx.alloc_tmp(RegClass::I64, types::I64);
+            ctx.emit(Inst::mov_r_r(is_64, lhs, dst));
+            ctx.emit(Inst::mov_r_r(is_64, rhs, shifted_rhs));
+            if shift > 0 {
+                for &reg in &[dst, shifted_rhs] {
+                    ctx.emit(Inst::shift_r(size, ShiftKind::ShiftLeft, Some(shift), reg));
+                }
+            }
+
+            // Compute the saturated value before the arithmetic sets the flags: the maximum
+            // value if `lhs` is non-negative and the minimum value otherwise for signed
+            // operations, and all ones or zero for unsigned ones.
+            let sat = ctx.alloc_tmp(RegClass::I64, types::I64);
+            let max = u64::max_value() >> (64 - reg_bits);
+            if is_signed {
+                let signed_max = ctx.alloc_tmp(RegClass::I64, types::I64);
+                ctx.emit(Inst::imm(op_size, max >> 1, signed_max));
+                ctx.emit(Inst::mov_r_r(is_64, dst.to_reg(), sat));
+                ctx.emit(Inst::shift_r(
+                    size,
+                    ShiftKind::ShiftRightArithmetic,
+                    Some(reg_bits - 1),
+                    sat,
+                ));
+                ctx.emit(Inst::alu_rmi_r(
+                    is_64,
+                    AluRmiROpcode::Xor,
+                    RegMemImm::reg(signed_max.to_reg()),
+                    sat,
+                ));
+            } else {
+                let value = if op == Opcode::UaddSat { max } else { 0 };
+                ctx.emit(Inst::imm(op_size, value, sat));
+            }
+
+            let (alu_op, cc) = match op {
+                Opcode::SaddSat => (AluRmiROpcode::Add, CC::O),
+                Opcode::UaddSat => (AluRmiROpcode::Add, CC::B),
+                Opcode::SsubSat => (AluRmiROpcode::Sub, CC::O),
+                Opcode::UsubSat => (AluRmiROpcode::Sub, CC::B),
+                _ => unreachable!(),
+            };
+            ctx.emit(Inst::alu_rmi_r(
+                is_64,
+                alu_op,
+                RegMemImm::reg(shifted_rhs.to_reg()),
+                dst,
+            ));
+            ctx.emit(Inst::cmove(size, cc, RegMem::reg(sat.to_reg()), dst));
+
+            if shift > 0 {
+                let kind = if is_signed {
+                    ShiftKind::ShiftRightArithmetic
+                } else {
+                    ShiftKind::ShiftRightLogical
+                };
+                ctx.emit(Inst::shift_r(size, kind, Some(shift), dst));
+            }
+        }
+
         Opcode::Iadd
         | Opcode::IaddIfcout
         | Opcode::SaddSat
@@ -686,6 +1249,15 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                     Opcode::Imul => match ty {
                         types::I16X8 => SseOpcode::Pmullw,
                         types::I32X4 => SseOpcode::Pmulld,
+                        types::I64X2
+                            if isa_flags.use_avx512dq_simd() && isa_flags.use_avx512vl_simd() =>
+                        {
+                            let lhs = put_input_in_reg(ctx, inputs[0]);
+                            let rhs = input_to_reg_mem(ctx, inputs[1]);
+                            let dst = get_output_reg(ctx, outputs[0]);
+                            ctx.emit(Inst::xmm_rm_r_evex(Avx512Opcode::Vpmullq, lhs, rhs, dst));
+                            return Ok(());
+                        }
                         types::I64X2 => {
                             // Note for I64X2 we describe a lane A as being composed of a
                             // 32-bit upper half "Ah" and a 32-bit lower half "Al".
@@ -814,10 +1386,118 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 let lhs = put_input_in_reg(ctx, inputs[0]);
                 let rhs = input_to_reg_mem(ctx, inputs[1]);
                 let dst = get_output_reg(ctx, outputs[0]);
+                emit_packed_binop(ctx, isa_flags, sse_op, lhs, rhs, dst, ty);
+            } else if ty == types::I128 {
+                let lhs = put_input_in_regs(ctx, inputs[0]);
+                let rhs = put_input_in_regs(ctx, inputs[1]);
+                let dst = get_output_regs(ctx, outputs[0]);
+                let (lhs_lo, lhs_hi) = (lhs.regs()[0], lhs.regs()[1]);
+                let (rhs_lo, rhs_hi) = (rhs.regs()[0], rhs.regs()[1]);
+                let (dst_lo, dst_hi) = (dst.regs()[0], dst.regs()[1]);
+
+                if op == Opcode::Imul {
+                    // Only the low 128 bits of the product are needed:
+                    //   lo = lo64(lhs_lo * rhs_lo)
+                    //   hi = hi64(lhs_lo * rhs_lo) + lhs_lo * rhs_hi + lhs_hi * rhs_lo
+                    let tmp = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    ctx.emit(Inst::mov_r_r(true, lhs_lo, dst_hi));
+                    ctx.emit(Inst::alu_rmi_r(
+                        true,
+                        AluRmiROpcode::Mul,
+                        RegMemImm::reg(rhs_hi),
+                        dst_hi,
+                    ));
+                    ctx.emit(Inst::mov_r_r(true, lhs_hi, tmp));
+                    ctx.emit(Inst::alu_rmi_r(
+                        true,
+                        AluRmiROpcode::Mul,
+                        RegMemImm::reg(rhs_lo),
+                        tmp,
+                    ));
+                    ctx.emit(Inst::alu_rmi_r(
+                        true,
+                        AluRmiROpcode::Add,
+                        RegMemImm::reg(tmp.to_reg()),
+                        dst_hi,
+                    ));
 
-                // Move the `lhs` to the same register as `dst`.
-                ctx.emit(Inst::gen_move(dst, lhs, ty));
-                ctx.emit(Inst::xmm_rm_r(sse_op, rhs, dst));
+                    // The full 64x64 -> 128 product of the low parts goes to %rdx:%rax.
+                    ctx.emit(Inst::mov_r_r(true, lhs_lo, Writable::from_reg(regs::rax())));
+                    ctx.emit(Inst::mul_hi(8, false, RegMem::reg(rhs_lo)));
+                    ctx.emit(Inst::alu_rmi_r(
+                        true,
+                        AluRmiROpcode::Add,
+                        RegMemImm::reg(regs::rdx()),
+                        dst_hi,
+                    ));
+                    ctx.emit(Inst::mov_r_r(true, regs::rax(), dst_lo));
+                } else {
+                    // The high part consumes the carry (or borrow) out of the low part; the
+                    // moves in between don't affect the flags.
+                    let (lo_op, hi_op) = match op {
+                        Opcode::Iadd | Opcode::IaddIfcout | Opcode::SaddSat | Opcode::UaddSat => {
+                            (AluRmiROpcode::Add, AluRmiROpcode::Adc)
+                        }
+                        Opcode::Isub | Opcode::SsubSat | Opcode::UsubSat => {
+                            (AluRmiROpcode::Sub, AluRmiROpcode::Sbb)
+                        }
+                        Opcode::Band => (AluRmiROpcode::And, AluRmiROpcode::And),
+                        Opcode::Bor => (AluRmiROpcode::Or, AluRmiROpcode::Or),
+                        Opcode::Bxor => (AluRmiROpcode::Xor, AluRmiROpcode::Xor),
+                        _ => unreachable!("{} on I128 values", op),
+                    };
+
+                    // Compute the saturated value before the arithmetic sets the flags: the
+                    // maximum value if `lhs` is non-negative and the minimum value otherwise for
+                    // signed operations, and all ones or zero for unsigned ones.
+                    let saturated = match op {
+                        Opcode::SaddSat | Opcode::SsubSat => {
+                            let sat_lo = ctx.alloc_tmp(RegClass::I64, types::I64);
+                            let sat_hi = ctx.alloc_tmp(RegClass::I64, types::I64);
+                            ctx.emit(Inst::mov_r_r(true, lhs_hi, sat_lo));
+                            ctx.emit(Inst::shift_r(
+                                8,
+                                ShiftKind::ShiftRightArithmetic,
+                                Some(63),
+                                sat_lo,
+                            ));
+                            ctx.emit(Inst::imm(
+                                OperandSize::Size64,
+                                i64::max_value() as u64,
+                                sat_hi,
+                            ));
+                            ctx.emit(Inst::alu_rmi_r(
+                                true,
+                                AluRmiROpcode::Xor,
+                                RegMemImm::reg(sat_lo.to_reg()),
+                                sat_hi,
+                            ));
+                            ctx.emit(Inst::not(8, sat_lo));
+                            Some((CC::O, sat_lo.to_reg(), sat_hi.to_reg()))
+                        }
+                        Opcode::UaddSat | Opcode::UsubSat => {
+                            let value = if op == Opcode::UaddSat {
+                                u64::max_value()
+                            } else {
+                                0
+                            };
+                            let sat = ctx.alloc_tmp(RegClass::I64, types::I64);
+                            ctx.emit(Inst::imm(OperandSize::Size64, value, sat));
+                            Some((CC::B, sat.to_reg(), sat.to_reg()))
+                        }
+                        _ => None,
+                    };
+
+                    ctx.emit(Inst::mov_r_r(true, lhs_lo, dst_lo));
+                    ctx.emit(Inst::mov_r_r(true, lhs_hi, dst_hi));
+                    ctx.emit(Inst::alu_rmi_r(true, lo_op, RegMemImm::reg(rhs_lo), dst_lo));
+                    ctx.emit(Inst::alu_rmi_r(true, hi_op, RegMemImm::reg(rhs_hi), dst_hi));
+
+                    if let Some((cc, sat_lo, sat_hi)) = saturated {
+                        ctx.emit(Inst::cmove(8, cc, RegMem::reg(sat_lo), dst_lo));
+                        ctx.emit(Inst::cmove(8, cc, RegMem::reg(sat_hi), dst_hi));
+                    }
+                }
             } else {
                 let is_64 = ty == types::I64;
                 let alu_op = match op {
@@ -882,7 +1562,9 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let src = input_to_reg_mem(ctx, inputs[0]);
             let dst = get_output_reg(ctx, outputs[0]);
             let ty = ty.unwrap();
-            if ty.is_vector() {
+            if ty == types::I64X2 && isa_flags.use_avx512f_simd() && isa_flags.use_avx512vl_simd() {
+                ctx.emit(Inst::xmm_unary_rm_r_evex(Avx512Opcode::Vpabsq, src, dst));
+            } else if ty.is_vector() {
                 let opcode = match ty {
                     types::I8X16 => SseOpcode::Pabsb,
                     types::I16X8 => SseOpcode::Pabsw,
@@ -897,10 +1579,10 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
         Opcode::Imax | Opcode::Umax | Opcode::Imin | Opcode::Umin => {
             let lhs = put_input_in_reg(ctx, inputs[0]);
-            let rhs = input_to_reg_mem(ctx, inputs[1]);
             let dst = get_output_reg(ctx, outputs[0]);
             let ty = ty.unwrap();
             if ty.is_vector() {
+                let rhs = input_to_reg_mem(ctx, inputs[1]);
                 let sse_op = match op {
                     Opcode::Imax => match ty {
                         types::I8X16 => SseOpcode::Pmaxsb,
@@ -928,15 +1610,36 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                     },
                     _ => unreachable!("This is a bug: the external and internal `match op` should be over the same opcodes."),
                 };
-
-                // Move the `lhs` to the same register as `dst`.
-                ctx.emit(Inst::gen_move(dst, lhs, ty));
-                ctx.emit(Inst::xmm_rm_r(sse_op, rhs, dst));
+                emit_packed_binop(ctx, isa_flags, sse_op, lhs, rhs, dst, ty);
+            } else if is_int_or_ref_ty(ty) && ty != types::I128 {
+                // Keep `rhs` unless `lhs` compares the right way against it. Since the higher
+                // bits are undefined per CLIF semantics, narrow types can use a 32-bit cmove.
+                let cc = match op {
+                    Opcode::Imin => CC::L,
+                    Opcode::Umin => CC::B,
+                    Opcode::Imax => CC::NLE,
+                    Opcode::Umax => CC::NBE,
+                    _ => unreachable!(),
+                };
+                let size = ty.bytes() as u8;
+                let rhs = put_input_in_reg(ctx, inputs[1]);
+                ctx.emit(Inst::gen_move(dst, rhs, ty));
+                ctx.emit(Inst::cmp_rmi_r(size, RegMemImm::reg(rhs), lhs));
+                ctx.emit(Inst::cmove(u8::max(size, 4), cc, RegMem::reg(lhs), dst));
             } else {
                 panic!("Unsupported type for {} instruction: {}", op, ty);
             }
         }
 
+        Opcode::Bnot if ty == Some(types::I128) => {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            for (&dst, &src) in dst.regs().iter().zip(src.regs().iter()) {
+                ctx.emit(Inst::mov_r_r(true, src, dst));
+                ctx.emit(Inst::not(8, dst));
+            }
+        }
+
         Opcode::Bnot => {
             let ty = ty.unwrap();
             let size = ty.bytes() as u8;
@@ -978,6 +1681,55 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::Ishl | Opcode::Ushr | Opcode::Sshr | Opcode::Rotl | Opcode::Rotr
+            if ty == Some(types::I128) =>
+        {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            // Only the low 7 bits of the amount matter, so an `I128` amount can be truncated.
+            let amt = put_input_in_regs(ctx, inputs[1]).regs()[0];
+            let dst = get_output_regs(ctx, outputs[0]);
+
+            match op {
+                Opcode::Ishl => emit_shift_i128(ctx, src, dst, amt, ShiftKind::ShiftLeft),
+                Opcode::Ushr => emit_shift_i128(ctx, src, dst, amt, ShiftKind::ShiftRightLogical),
+                Opcode::Sshr => {
+                    emit_shift_i128(ctx, src, dst, amt, ShiftKind::ShiftRightArithmetic)
+                }
+                Opcode::Rotl | Opcode::Rotr => {
+                    // rotl(x, n) = (x << n) | (x >> (128 - n)), and conversely for rotr.
+                    let (first_kind, second_kind) = if op == Opcode::Rotl {
+                        (ShiftKind::ShiftLeft, ShiftKind::ShiftRightLogical)
+                    } else {
+                        (ShiftKind::ShiftRightLogical, ShiftKind::ShiftLeft)
+                    };
+                    let neg_amt = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    ctx.emit(Inst::imm(OperandSize::Size64, 128, neg_amt));
+                    ctx.emit(Inst::alu_rmi_r(
+                        true,
+                        AluRmiROpcode::Sub,
+                        RegMemImm::reg(amt),
+                        neg_amt,
+                    ));
+
+                    let tmp = ValueRegs::two(
+                        ctx.alloc_tmp(RegClass::I64, types::I64),
+                        ctx.alloc_tmp(RegClass::I64, types::I64),
+                    );
+                    emit_shift_i128(ctx, src, tmp, amt, first_kind);
+                    emit_shift_i128(ctx, src, dst, neg_amt.to_reg(), second_kind);
+                    for (&dst, &tmp) in dst.regs().iter().zip(tmp.regs().iter()) {
+                        ctx.emit(Inst::alu_rmi_r(
+                            true,
+                            AluRmiROpcode::Or,
+                            RegMemImm::reg(tmp.to_reg()),
+                            dst,
+                        ));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
         Opcode::Ishl | Opcode::Ushr | Opcode::Sshr | Opcode::Rotl | Opcode::Rotr => {
             let dst_ty = ctx.output_ty(insn, 0);
             debug_assert_eq!(ctx.input_ty(insn, 0), dst_ty);
@@ -1316,6 +2068,19 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             }
         }
 
+        Opcode::Ineg if ty == Some(types::I128) => {
+            // Computes 0 - src, propagating the borrow from the low part.
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            for &dst in dst.regs() {
+                ctx.emit(Inst::imm(OperandSize::Size64, 0, dst));
+            }
+            let ops = [AluRmiROpcode::Sub, AluRmiROpcode::Sbb];
+            for ((&dst, &src), &op) in dst.regs().iter().zip(src.regs().iter()).zip(ops.iter()) {
+                ctx.emit(Inst::alu_rmi_r(true, op, RegMemImm::reg(src), dst));
+            }
+        }
+
         Opcode::Ineg => {
             let dst = get_output_reg(ctx, outputs[0]);
             let ty = ty.unwrap();
@@ -1750,6 +2515,37 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let src_ty = ctx.input_ty(insn, 0);
             let dst_ty = ctx.output_ty(insn, 0);
 
+            if dst_ty == types::I128 {
+                // Extend to 64 bits first, then fill the high part with zeroes or sign bits.
+                let dst = get_output_regs(ctx, outputs[0]);
+                let (dst_lo, dst_hi) = (dst.regs()[0], dst.regs()[1]);
+                if op == Opcode::Sextend {
+                    let src = extend_input_to_reg(ctx, inputs[0], ExtSpec::SignExtendTo64);
+                    ctx.emit(Inst::mov_r_r(true, src, dst_lo));
+                    ctx.emit(Inst::mov_r_r(true, src, dst_hi));
+                    ctx.emit(Inst::shift_r(
+                        8,
+                        ShiftKind::ShiftRightArithmetic,
+                        Some(63),
+                        dst_hi,
+                    ));
+                } else {
+                    let src = extend_input_to_reg(ctx, inputs[0], ExtSpec::ZeroExtendTo64);
+                    ctx.emit(Inst::mov_r_r(true, src, dst_lo));
+                    ctx.emit(Inst::imm(OperandSize::Size64, 0, dst_hi));
+                }
+                return Ok(());
+            }
+
+            if src_ty == types::I128 {
+                // Reductions from 128 bits only need the low part.
+                assert_eq!(op, Opcode::Ireduce);
+                let src = put_input_in_regs(ctx, inputs[0]).regs()[0];
+                let dst = get_output_reg(ctx, outputs[0]);
+                ctx.emit(Inst::gen_move(dst, src, types::I64));
+                return Ok(());
+            }
+
             // Sextend requires a sign-extended move, but all the other opcodes are simply a move
             // from a zero-extended source. Here is why this works, in each case:
             //
@@ -1814,7 +2610,11 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let condcode = ctx.data(insn).cond_code().unwrap();
             let dst = get_output_reg(ctx, outputs[0]);
             let ty = ctx.input_ty(insn, 0);
-            if !ty.is_vector() {
+            if ty == types::I128 {
+                let lhs = put_input_in_regs(ctx, inputs[0]);
+                let rhs = put_input_in_regs(ctx, inputs[1]);
+                emit_icmp_i128(ctx, condcode, lhs, rhs, dst);
+            } else if !ty.is_vector() {
                 emit_cmp(ctx, insn);
                 let cc = CC::from_intcc(condcode);
                 ctx.emit(Inst::setcc(cc, dst));
@@ -1868,32 +2668,29 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                     ),
                 };
 
-                // Here we decide which operand to use as the read/write `dst` (ModRM reg field)
-                // and which to use as the read `input` (ModRM r/m field). In the normal case we
-                // use Cranelift's first operand, the `lhs`, as `dst` but we flip the operands for
-                // the less-than cases so that we can reuse the greater-than implementation.
-                let input = match condcode {
+                // Here we decide which operand to use as the first operand of the comparison,
+                // which will become the read/write `dst` (ModRM reg field), and which to use as
+                // the read `input` (ModRM r/m field). In the normal case we use Cranelift's first
+                // operand, the `lhs`, but we flip the operands for the less-than cases so that we
+                // can reuse the greater-than implementation.
+                let (lhs, input) = match condcode {
                     IntCC::SignedLessThan
                     | IntCC::SignedLessThanOrEqual
                     | IntCC::UnsignedLessThan
-                    | IntCC::UnsignedLessThanOrEqual => {
-                        let lhs = input_to_reg_mem(ctx, inputs[0]);
-                        let rhs = put_input_in_reg(ctx, inputs[1]);
-                        ctx.emit(Inst::gen_move(dst, rhs, ty));
-                        lhs
-                    }
-                    _ => {
-                        let lhs = put_input_in_reg(ctx, inputs[0]);
-                        let rhs = input_to_reg_mem(ctx, inputs[1]);
-                        ctx.emit(Inst::gen_move(dst, lhs, ty));
-                        rhs
-                    }
+                    | IntCC::UnsignedLessThanOrEqual => (
+                        put_input_in_reg(ctx, inputs[1]),
+                        input_to_reg_mem(ctx, inputs[0]),
+                    ),
+                    _ => (
+                        put_input_in_reg(ctx, inputs[0]),
+                        input_to_reg_mem(ctx, inputs[1]),
+                    ),
                 };
 
                 match condcode {
-                    IntCC::Equal => ctx.emit(Inst::xmm_rm_r(eq(ty), input, dst)),
+                    IntCC::Equal => emit_packed_binop(ctx, isa_flags, eq(ty), lhs, input, dst, ty),
                     IntCC::NotEqual => {
-                        ctx.emit(Inst::xmm_rm_r(eq(ty), input, dst));
+                        emit_packed_binop(ctx, isa_flags, eq(ty), lhs, input, dst, ty);
                         // Emit all 1s into the `tmp` register.
                         let tmp = ctx.alloc_tmp(RegClass::V128, ty);
                         ctx.emit(Inst::xmm_rm_r(eq(ty), RegMem::from(tmp), tmp));
@@ -1901,14 +2698,14 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                         ctx.emit(Inst::xmm_rm_r(SseOpcode::Pxor, RegMem::from(tmp), dst));
                     }
                     IntCC::SignedGreaterThan | IntCC::SignedLessThan => {
-                        ctx.emit(Inst::xmm_rm_r(gt(ty), input, dst))
+                        emit_packed_binop(ctx, isa_flags, gt(ty), lhs, input, dst, ty)
                     }
                     IntCC::SignedGreaterThanOrEqual | IntCC::SignedLessThanOrEqual => {
-                        ctx.emit(Inst::xmm_rm_r(mins(ty), input.clone(), dst));
+                        emit_packed_binop(ctx, isa_flags, mins(ty), lhs, input.clone(), dst, ty);
                         ctx.emit(Inst::xmm_rm_r(eq(ty), input, dst))
                     }
                     IntCC::UnsignedGreaterThan | IntCC::UnsignedLessThan => {
-                        ctx.emit(Inst::xmm_rm_r(maxu(ty), input.clone(), dst));
+                        emit_packed_binop(ctx, isa_flags, maxu(ty), lhs, input.clone(), dst, ty);
                         ctx.emit(Inst::xmm_rm_r(eq(ty), input, dst));
                         // Emit all 1s into the `tmp` register.
                         let tmp = ctx.alloc_tmp(RegClass::V128, ty);
@@ -1917,7 +2714,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                         ctx.emit(Inst::xmm_rm_r(SseOpcode::Pxor, RegMem::from(tmp), dst));
                     }
                     IntCC::UnsignedGreaterThanOrEqual | IntCC::UnsignedLessThanOrEqual => {
-                        ctx.emit(Inst::xmm_rm_r(minu(ty), input.clone(), dst));
+                        emit_packed_binop(ctx, isa_flags, minu(ty), lhs, input.clone(), dst, ty);
                         ctx.emit(Inst::xmm_rm_r(eq(ty), input, dst))
                     }
                     _ => unimplemented!("Unimplemented comparison code for icmp: {}", condcode),
@@ -2022,10 +2819,10 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
 
         Opcode::FallthroughReturn | Opcode::Return => {
             for i in 0..ctx.num_inputs(insn) {
-                let src_reg = put_input_in_reg(ctx, inputs[i]);
-                let retval_reg = ctx.retval(i);
+                let src_regs = put_input_in_regs(ctx, inputs[i]);
+                let retval_regs = ctx.retval(i);
                 let ty = ctx.input_ty(insn, i);
-                ctx.emit(Inst::gen_move(retval_reg, src_reg, ty));
+                emit_moves(ctx, retval_regs, src_regs, ty);
             }
             // N.B.: the Ret itself is generated by the ABI.
         }
@@ -2061,13 +2858,13 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             abi.emit_stack_pre_adjust(ctx);
             assert_eq!(inputs.len(), abi.num_args());
             for (i, input) in inputs.iter().enumerate() {
-                let arg_reg = put_input_in_reg(ctx, *input);
-                abi.emit_copy_reg_to_arg(ctx, i, arg_reg);
+                let arg_regs = put_input_in_regs(ctx, *input);
+                abi.emit_copy_regs_to_arg(ctx, i, arg_regs);
             }
             abi.emit_call(ctx);
             for (i, output) in outputs.iter().enumerate() {
-                let retval_reg = get_output_reg(ctx, *output);
-                abi.emit_copy_retval_to_reg(ctx, i, retval_reg);
+                let retval_regs = get_output_regs(ctx, *output);
+                abi.emit_copy_retval_to_regs(ctx, i, retval_regs);
             }
             abi.emit_stack_post_adjust(ctx);
         }
@@ -2166,11 +2963,6 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let dst = get_output_reg(ctx, outputs[0]);
             let ty = ty.unwrap();
 
-            // Move the `lhs` to the same register as `dst`; this may not emit an actual move
-            // but ensures that the registers are the same to match x86's read-write operand
-            // encoding.
-            ctx.emit(Inst::gen_move(dst, lhs, ty));
-
             // Note: min and max can't be handled here, because of the way Cranelift defines them:
             // if any operand is a NaN, they must return the NaN operand, while the x86 machine
             // instruction will return the second operand if either operand is a NaN.
@@ -2208,7 +3000,15 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                     ty
                 ),
             };
-            ctx.emit(Inst::xmm_rm_r(sse_op, rhs, dst));
+            if ty.is_vector() {
+                emit_packed_binop(ctx, isa_flags, sse_op, lhs, rhs, dst, ty);
+            } else {
+                // Move the `lhs` to the same register as `dst`; this may not emit an actual move
+                // but ensures that the registers are the same to match x86's read-write operand
+                // encoding.
+                ctx.emit(Inst::gen_move(dst, lhs, ty));
+                ctx.emit(Inst::xmm_rm_r(sse_op, rhs, dst));
+            }
         }
 
         Opcode::Fmin | Opcode::Fmax => {
@@ -2218,7 +3018,9 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             let is_min = op == Opcode::Fmin;
             let output_ty = ty.unwrap();
             ctx.emit(Inst::gen_move(dst, rhs, output_ty));
-            if !output_ty.is_vector() {
+            // The scalar sequence branches on its operands, so constant-time code uses the
+            // packed sequence below for scalars too, ignoring all lanes but the first.
+            if !output_ty.is_vector() && !flags.enable_constant_time() {
                 let op_size = match output_ty {
                     types::F32 => OperandSize::Size32,
                     types::F64 => OperandSize::Size64,
@@ -2271,7 +3073,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 if is_min {
                     let (mov_op, min_op, or_op, cmp_op, shift_op, shift_by, andn_op) =
                         match output_ty {
-                            types::F32X4 => (
+                            types::F32X4 | types::F32 => (
                                 SseOpcode::Movaps,
                                 SseOpcode::Minps,
                                 SseOpcode::Orps,
@@ -2280,7 +3082,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                                 10,
                                 SseOpcode::Andnps,
                             ),
-                            types::F64X2 => (
+                            types::F64X2 | types::F64 => (
                                 SseOpcode::Movapd,
                                 SseOpcode::Minpd,
                                 SseOpcode::Orpd,
@@ -2344,7 +3146,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                         shift_by,
                         andn_op,
                     ) = match output_ty {
-                        types::F32X4 => (
+                        types::F32X4 | types::F32 => (
                             SseOpcode::Movaps,
                             SseOpcode::Maxps,
                             SseOpcode::Xorps,
@@ -2355,7 +3157,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                             10,
                             SseOpcode::Andnps,
                         ),
-                        types::F64X2 => (
+                        types::F64X2 | types::F64 => (
                             SseOpcode::Movapd,
                             SseOpcode::Maxpd,
                             SseOpcode::Xorpd,
@@ -2568,6 +3370,17 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 // -> dst = Ah + Al // Add the two floats together
 
                 assert_eq!(ctx.input_ty(insn, 0), types::I32X4);
+                if isa_flags.use_avx512f_simd() && isa_flags.use_avx512vl_simd() {
+                    // AVX-512 has a direct conversion from unsigned integers.
+                    let src = input_to_reg_mem(ctx, inputs[0]);
+                    ctx.emit(Inst::xmm_unary_rm_r_evex(
+                        Avx512Opcode::Vcvtudq2ps,
+                        src,
+                        dst,
+                    ));
+                    return Ok(());
+                }
+
                 let src = put_input_in_reg(ctx, inputs[0]);
                 let dst = get_output_reg(ctx, outputs[0]);
 
@@ -2632,6 +3445,13 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 let to_signed = op == Opcode::FcvtToSint || op == Opcode::FcvtToSintSat;
                 let is_sat = op == Opcode::FcvtToUintSat || op == Opcode::FcvtToSintSat;
 
+                if is_sat && flags.enable_constant_time() {
+                    emit_cvt_float_to_int_sat_branchless(
+                        ctx, to_signed, src, dst, input_ty, output_ty,
+                    );
+                    return Ok(());
+                }
+
                 let src_copy = ctx.alloc_tmp(RegClass::V128, input_ty);
                 ctx.emit(Inst::gen_move(src_copy, src, input_ty));
 
@@ -2998,6 +3818,14 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 _ => unreachable!(),
             };
 
+            if elem_ty == types::I128 {
+                // Load the two parts separately, low part first (little-endian).
+                let dst = get_output_regs(ctx, outputs[0]);
+                ctx.emit(Inst::mov64_m_r(amode.clone(), dst.regs()[0]));
+                ctx.emit(Inst::mov64_m_r(amode.offset(8), dst.regs()[1]));
+                return Ok(());
+            }
+
             let dst = get_output_reg(ctx, outputs[0]);
             let is_xmm = elem_ty.is_float() || elem_ty.is_vector();
             match (sign_extend, is_xmm) {
@@ -3070,6 +3898,14 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 _ => unreachable!(),
             };
 
+            if elem_ty == types::I128 {
+                // Store the two parts separately, low part first (little-endian).
+                let src = put_input_in_regs(ctx, inputs[0]);
+                ctx.emit(Inst::mov_r_m(8, src.regs()[0], addr.clone()));
+                ctx.emit(Inst::mov_r_m(8, src.regs()[1], addr.offset(8)));
+                return Ok(());
+            }
+
             let src = put_input_in_reg(ctx, inputs[0]);
 
             ctx.emit(match elem_ty {
@@ -3234,6 +4070,31 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             ctx.emit(inst);
         }
 
+        Opcode::Select if ty == Some(types::I128) => {
+            let flag_input = inputs[0];
+            let lhs = put_input_in_regs(ctx, inputs[1]);
+            let rhs = put_input_in_regs(ctx, inputs[2]);
+            let dst = get_output_regs(ctx, outputs[0]);
+
+            let cc = if let Some(icmp) = matches_icmp(ctx, flag_input) {
+                emit_cmp(ctx, icmp);
+                let cond_code = ctx.data(icmp).cond_code().unwrap();
+                CC::from_intcc(cond_code)
+            } else {
+                // The input is a boolean value, compare it against zero.
+                let size = ctx.input_ty(insn, 0).bytes() as u8;
+                let test = put_input_in_reg(ctx, flag_input);
+                ctx.emit(Inst::cmp_rmi_r(size, RegMemImm::imm(0), test));
+                CC::NZ
+            };
+
+            // The moves don't affect the flags.
+            for ((&dst, &lhs), &rhs) in dst.regs().iter().zip(lhs.regs()).zip(rhs.regs()) {
+                ctx.emit(Inst::mov_r_r(true, rhs, dst));
+                ctx.emit(Inst::cmove(8, cc, RegMem::reg(lhs), dst));
+            }
+        }
+
         Opcode::Select => {
             let flag_input = inputs[0];
             if let Some(fcmp) = matches_input(ctx, flag_input, Opcode::Fcmp) {
@@ -3278,7 +4139,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                             let size = u8::max(ty.bytes() as u8, 4);
                             ctx.emit(Inst::cmove(size, cc, lhs, dst));
                         } else {
-                            ctx.emit(Inst::xmm_cmove(ty == types::F64, cc, lhs, dst));
+                            emit_xmm_cmoves(ctx, flags, ty == types::F64, &[cc], lhs, dst);
                         }
                     }
                     FcmpCondResult::AndConditions(_, _) => {
@@ -3293,8 +4154,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                             ctx.emit(Inst::cmove(size, cc1, lhs.clone(), dst));
                             ctx.emit(Inst::cmove(size, cc2, lhs, dst));
                         } else {
-                            ctx.emit(Inst::xmm_cmove(ty == types::F64, cc1, lhs.clone(), dst));
-                            ctx.emit(Inst::xmm_cmove(ty == types::F64, cc2, lhs, dst));
+                            emit_xmm_cmoves(ctx, flags, ty == types::F64, &[cc1, cc2], lhs, dst);
                         }
                     }
                 }
@@ -3319,7 +4179,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 let rhs = put_input_in_reg(ctx, inputs[2]);
                 let dst = get_output_reg(ctx, outputs[0]);
 
-                let cc = if let Some(icmp) = matches_input(ctx, flag_input, Opcode::Icmp) {
+                let cc = if let Some(icmp) = matches_icmp(ctx, flag_input) {
                     emit_cmp(ctx, icmp);
                     let cond_code = ctx.data(icmp).cond_code().unwrap();
                     CC::from_intcc(cond_code)
@@ -3338,7 +4198,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                     ctx.emit(Inst::cmove(size, cc, lhs, dst));
                 } else {
                     debug_assert!(ty == types::F32 || ty == types::F64);
-                    ctx.emit(Inst::xmm_cmove(ty == types::F64, cc, lhs, dst));
+                    emit_xmm_cmoves(ctx, flags, ty == types::F64, &[cc], lhs, dst);
                 }
             }
         }
@@ -3375,10 +4235,82 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             } else {
                 debug_assert!(ty == types::F32 || ty == types::F64);
                 ctx.emit(Inst::gen_move(dst, rhs, ty));
-                ctx.emit(Inst::xmm_cmove(ty == types::F64, cc, lhs, dst));
+                emit_xmm_cmoves(ctx, flags, ty == types::F64, &[cc], lhs, dst);
             }
         }
 
+        Opcode::Udiv | Opcode::Urem | Opcode::Sdiv | Opcode::Srem if ty == Some(types::I128) => {
+            // There's no 128-bit division instruction, so call into the runtime library. Its
+            // routines don't trap, hence the checks before the call.
+            let dividend = put_input_in_regs(ctx, inputs[0]);
+            let divisor = put_input_in_regs(ctx, inputs[1]);
+            let (dividend_lo, dividend_hi) = (dividend.regs()[0], dividend.regs()[1]);
+            let (divisor_lo, divisor_hi) = (divisor.regs()[0], divisor.regs()[1]);
+
+            let tmp = ctx.alloc_tmp(RegClass::I64, types::I64);
+            ctx.emit(Inst::mov_r_r(true, divisor_lo, tmp));
+            ctx.emit(Inst::alu_rmi_r(
+                true,
+                AluRmiROpcode::Or,
+                RegMemImm::reg(divisor_hi),
+                tmp,
+            ));
+            ctx.emit(Inst::trap_if(CC::Z, TrapCode::IntegerDivisionByZero));
+
+            if op == Opcode::Sdiv {
+                // `INT_MIN / -1` overflows, i.e. when all of `!(divisor_lo & divisor_hi)`,
+                // `dividend_hi ^ INT_MIN` and `dividend_lo` are zero.
+                let int_min = ctx.alloc_tmp(RegClass::I64, types::I64);
+                ctx.emit(Inst::mov_r_r(true, divisor_lo, tmp));
+                ctx.emit(Inst::alu_rmi_r(
+                    true,
+                    AluRmiROpcode::And,
+                    RegMemImm::reg(divisor_hi),
+                    tmp,
+                ));
+                ctx.emit(Inst::not(8, tmp));
+                ctx.emit(Inst::imm(OperandSize::Size64, 1 << 63, int_min));
+                ctx.emit(Inst::alu_rmi_r(
+                    true,
+                    AluRmiROpcode::Xor,
+                    RegMemImm::reg(dividend_hi),
+                    int_min,
+                ));
+                ctx.emit(Inst::alu_rmi_r(
+                    true,
+                    AluRmiROpcode::Or,
+                    RegMemImm::reg(dividend_lo),
+                    tmp,
+                ));
+                ctx.emit(Inst::alu_rmi_r(
+                    true,
+                    AluRmiROpcode::Or,
+                    RegMemImm::reg(int_min.to_reg()),
+                    tmp,
+                ));
+                ctx.emit(Inst::trap_if(CC::Z, TrapCode::IntegerOverflow));
+            }
+
+            let libcall = LibCall::for_inst(op, types::I128).unwrap();
+            emit_vm_call(ctx, flags, triple, libcall, insn, inputs, outputs)?;
+        }
+
+        Opcode::Iconcat => {
+            let lo = put_input_in_reg(ctx, inputs[0]);
+            let hi = put_input_in_reg(ctx, inputs[1]);
+            let dst = get_output_regs(ctx, outputs[0]);
+            ctx.emit(Inst::gen_move(dst.regs()[0], lo, types::I64));
+            ctx.emit(Inst::gen_move(dst.regs()[1], hi, types::I64));
+        }
+
+        Opcode::Isplit => {
+            let src = put_input_in_regs(ctx, inputs[0]);
+            let dst_lo = get_output_reg(ctx, outputs[0]);
+            let dst_hi = get_output_reg(ctx, outputs[1]);
+            ctx.emit(Inst::gen_move(dst_lo, src.regs()[0], types::I64));
+            ctx.emit(Inst::gen_move(dst_hi, src.regs()[1], types::I64));
+        }
+
         Opcode::Udiv | Opcode::Urem | Opcode::Sdiv | Opcode::Srem => {
             let kind = match op {
                 Opcode::Udiv => DivOrRemKind::UnsignedDiv,
@@ -3401,7 +4333,7 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 input_ty,
             ));
 
-            if flags.avoid_div_traps() {
+            if flags.avoid_div_traps() && !flags.enable_constant_time() {
                 // A vcode meta-instruction is used to lower the inline checks, since they embed
                 // pc-relative offsets that must not change, thus requiring regalloc to not
                 // interfere by introducing spills and reloads.
@@ -3427,7 +4359,57 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
                 ));
                 ctx.emit(Inst::checked_div_or_rem_seq(kind, size, divisor_copy, tmp));
             } else {
-                let divisor = input_to_reg_mem(ctx, inputs[1]);
+                let divisor = if op == Opcode::Srem && flags.enable_constant_time() {
+                    // Without the checked sequence, division by zero is caught by the hardware
+                    // exception, but `INT_MIN % -1` would raise it too. Since `x % -1` is always
+                    // 0, like `x % 1`, divide by 1 instead without branching.
+                    let divisor = put_input_in_reg(ctx, inputs[1]);
+                    let divisor_copy = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    ctx.emit(Inst::gen_move(divisor_copy, divisor, types::I64));
+                    let one = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    ctx.emit(Inst::imm(OperandSize::Size32, 1, one));
+                    ctx.emit(Inst::cmp_rmi_r(
+                        size,
+                        RegMemImm::imm(0xffffffff),
+                        divisor_copy.to_reg(),
+                    ));
+                    ctx.emit(Inst::cmove(
+                        size.max(4),
+                        CC::Z,
+                        RegMem::reg(one.to_reg()),
+                        divisor_copy,
+                    ));
+                    RegMem::reg(divisor_copy.to_reg())
+                } else if op == Opcode::Sdiv && flags.enable_constant_time() {
+                    // `INT_MIN / -1` raises the same hardware exception as a division by zero.
+                    // Compute whether the operands are exactly these without branching, and only
+                    // branch to a trap on the result, so that it reports an integer overflow.
+                    let divisor = put_input_in_reg(ctx, inputs[1]);
+                    let is_minus_one = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    let is_int_min = ctx.alloc_tmp(RegClass::I64, types::I64);
+                    ctx.emit(Inst::cmp_rmi_r(size, RegMemImm::imm(0xffffffff), divisor));
+                    ctx.emit(Inst::setcc(CC::Z, is_minus_one));
+                    // Subtracting 1 only overflows for `INT_MIN`.
+                    ctx.emit(Inst::cmp_rmi_r(size, RegMemImm::imm(1), dividend));
+                    ctx.emit(Inst::setcc(CC::O, is_int_min));
+                    // `setcc` only writes the low byte; clearing the rest of one operand is
+                    // enough for the `and` below.
+                    ctx.emit(Inst::movzx_rm_r(
+                        ExtMode::BL,
+                        RegMem::reg(is_int_min.to_reg()),
+                        is_int_min,
+                    ));
+                    ctx.emit(Inst::alu_rmi_r(
+                        false,
+                        AluRmiROpcode::And,
+                        RegMemImm::reg(is_minus_one.to_reg()),
+                        is_int_min,
+                    ));
+                    ctx.emit(Inst::trap_if(CC::NZ, TrapCode::IntegerOverflow));
+                    RegMem::reg(divisor)
+                } else {
+                    input_to_reg_mem(ctx, inputs[1])
+                };
 
                 // Fill in the high parts:
                 if kind.is_signed() {
@@ -3688,13 +4670,37 @@ fn lower_insn_to_regs<C: LowerCtx<I = Inst>>(
             };
             let dst = get_output_reg(ctx, outputs[0]);
 
+            if isa_flags.use_avx2_simd() {
+                // VPBROADCAST* takes its source in an XMM register or in memory.
+                let src = match src {
+                    RegMem::Reg { reg } if reg.get_class() == RegClass::I64 => {
+                        let tmp = ctx.alloc_tmp(RegClass::V128, ty);
+                        let (mov_op, size) = if ty.lane_bits() == 64 {
+                            (SseOpcode::Movq, OperandSize::Size64)
+                        } else {
+                            (SseOpcode::Movd, OperandSize::Size32)
+                        };
+                        ctx.emit(Inst::gpr_to_xmm(mov_op, RegMem::reg(reg), size, tmp));
+                        RegMem::from(tmp)
+                    }
+                    src => src,
+                };
+                let avx_op = match ty.lane_bits() {
+                    8 => AvxOpcode::Vpbroadcastb,
+                    16 => AvxOpcode::Vpbroadcastw,
+                    32 => AvxOpcode::Vpbroadcastd,
+                    64 => AvxOpcode::Vpbroadcastq,
+                    _ => panic!("Invalid type to splat: {}", ty),
+                };
+                ctx.emit(Inst::xmm_unary_rm_r_vex(avx_op, src, dst));
+                return Ok(());
+            }
+
             // We know that splat will overwrite all of the lanes of `dst` but it takes several
             // instructions to do so. Because of the multiple instructions, there is no good way to
             // declare `dst` a `def` except with the following pseudo-instruction.
             ctx.emit(Inst::xmm_uninit_value(dst));
 
-            // TODO: eventually many of these sequences could be optimized with AVX's VBROADCAST*
-            // and VPBROADCAST*.
             match ty.lane_bits() {
                 8 => {
                     emit_insert_lane(ctx, src, dst, 0, ty.lane_type());
@@ -3864,7 +4870,7 @@ impl LowerBackend for X64Backend {
     type MInst = Inst;
 
     fn lower<C: LowerCtx<I = Inst>>(&self, ctx: &mut C, ir_inst: IRInst) -> CodegenResult<()> {
-        lower_insn_to_regs(ctx, ir_inst, &self.flags, &self.triple)
+        lower_insn_to_regs(ctx, ir_inst, &self.flags, &self.x64_flags, &self.triple)
     }
 
     fn lower_branch_group<C: LowerCtx<I = Inst>>(
@@ -3909,7 +4915,7 @@ impl LowerBackend for X64Backend {
 
                     let src_ty = ctx.input_ty(branches[0], 0);
 
-                    if let Some(icmp) = matches_input(ctx, flag_input, Opcode::Icmp) {
+                    if let Some(icmp) = matches_icmp(ctx, flag_input) {
                         emit_cmp(ctx, icmp);
 
                         let cond_code = ctx.data(icmp).cond_code().unwrap();
@@ -3958,6 +4964,23 @@ impl LowerBackend for X64Backend {
                         let size_bytes = src_ty.bytes() as u8;
                         ctx.emit(Inst::cmp_rmi_r(size_bytes, RegMemImm::imm(0), src));
                         ctx.emit(Inst::jmp_cond(cc, taken, not_taken));
+                    } else if src_ty == types::I128 {
+                        // The value is zero iff the bitwise or of its parts is.
+                        let src = put_input_in_regs(ctx, flag_input);
+                        let tmp = ctx.alloc_tmp(RegClass::I64, types::I64);
+                        ctx.emit(Inst::mov_r_r(true, src.regs()[0], tmp));
+                        ctx.emit(Inst::alu_rmi_r(
+                            true,
+                            AluRmiROpcode::Or,
+                            RegMemImm::reg(src.regs()[1]),
+                            tmp,
+                        ));
+                        let cc = match op0 {
+                            Opcode::Brz => CC::Z,
+                            Opcode::Brnz => CC::NZ,
+                            _ => unreachable!(),
+                        };
+                        ctx.emit(Inst::jmp_cond(cc, taken, not_taken));
                     } else {
                         unimplemented!("brz/brnz with non-int type {:?}", src_ty);
                     }
@@ -3986,6 +5009,26 @@ impl LowerBackend for X64Backend {
                         // us dst - src at the machine instruction level, so invert operands.
                         ctx.emit(Inst::cmp_rmi_r(byte_size, rhs, lhs));
                         ctx.emit(Inst::jmp_cond(cc, taken, not_taken));
+                    } else if src_ty == types::I128 {
+                        let lhs = put_input_in_regs(
+                            ctx,
+                            InsnInput {
+                                insn: branches[0],
+                                input: 0,
+                            },
+                        );
+                        let rhs = put_input_in_regs(
+                            ctx,
+                            InsnInput {
+                                insn: branches[0],
+                                input: 1,
+                            },
+                        );
+                        let cond_code = ctx.data(branches[0]).cond_code().unwrap();
+                        let tmp = ctx.alloc_tmp(RegClass::I64, types::B1);
+                        emit_icmp_i128(ctx, cond_code, lhs, rhs, tmp);
+                        ctx.emit(Inst::cmp_rmi_r(1, RegMemImm::imm(0), tmp.to_reg()));
+                        ctx.emit(Inst::jmp_cond(CC::NZ, taken, not_taken));
                     } else {
                         unimplemented!("bricmp with non-int type {:?}", src_ty);
                     }
@@ -4128,4 +5171,17 @@ impl LowerBackend for X64Backend {
     fn maybe_pinned_reg(&self) -> Option<Reg> {
         Some(regs::pinned_reg())
     }
+
+    #[cfg(feature = "enable-peepmatic")]
+    fn optimize_vcode(
+        &self,
+        vcode: &mut VCode<Inst>,
+        stack_map_info: &mut regalloc::StackmapRequestInfo,
+    ) {
+        crate::machinst::peepmatic::optimize(
+            super::peepmatic::vcode_peepmatic(),
+            vcode,
+            stack_map_info,
+        );
+    }
 }
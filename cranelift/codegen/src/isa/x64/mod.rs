@@ -16,6 +16,8 @@ use target_lexicon::Triple;
 mod abi;
 mod inst;
 mod lower;
+#[cfg(feature = "enable-peepmatic")]
+mod peepmatic;
 mod settings;
 
 /// An X64 backend.
@@ -59,6 +61,7 @@ impl MachBackend for X64Backend {
         let buffer = vcode.emit();
         let buffer = buffer.finish();
         let frame_size = vcode.frame_size();
+        let stats = vcode.stats();
         let unwind_info = vcode.unwind_info()?;
 
         let disasm = if want_disasm {
@@ -72,6 +75,7 @@ impl MachBackend for X64Backend {
             frame_size,
             disasm,
             unwind_info,
+            stats,
         })
     }
 
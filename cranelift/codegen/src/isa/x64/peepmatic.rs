@@ -0,0 +1,312 @@
+//! Peephole optimizations on x64 vcode, written in `vcode.peepmatic`.
+
+use crate::isa::x64::inst::args::*;
+use crate::isa::x64::inst::*;
+use crate::machinst::peepmatic::MachInstPeepmatic;
+use crate::peepmatic::deserialize_once;
+use alloc::vec::Vec;
+use peepmatic_runtime::{
+    part::{Constant, Part},
+    r#type::BitWidth,
+    PeepholeOptimizations,
+};
+use regalloc::{Reg, Writable};
+use std::borrow::Cow;
+use std::ptr;
+use std::sync::atomic::AtomicPtr;
+
+peepmatic_traits::define_operator! {
+    /// The x64 instructions that `vcode.peepmatic` rules can match and build.
+    ///
+    /// Operands and results are whole 64-bit registers. The zero-extensions
+    /// and 32-bit operators zero the upper bits of their result, like any
+    /// write to a 32-bit register. Loads and stores only address memory
+    /// through a base register without displacement, or through an offset
+    /// from the nominal SP.
+    VCodeOpcode {
+        lea_sp => LeaSp {
+            immediates(iNN);
+            result(iNN);
+        }
+        load32 => Load32 {
+            parameters(iNN);
+            result(iNN);
+        }
+        load32_sp => Load32Sp {
+            immediates(iNN);
+            result(iNN);
+        }
+        load64 => Load64 {
+            parameters(iNN);
+            result(iNN);
+        }
+        load64_sp => Load64Sp {
+            immediates(iNN);
+            result(iNN);
+        }
+        mov32 => Mov32 {
+            parameters(iNN);
+            result(iNN);
+        }
+        mov64 => Mov64 {
+            parameters(iNN);
+            result(iNN);
+        }
+        store32 => Store32 {
+            parameters(iNN, iNN);
+            result(void);
+        }
+        store32_sp => Store32Sp {
+            immediates(iNN);
+            parameters(iNN);
+            result(void);
+        }
+        store64 => Store64 {
+            parameters(iNN, iNN);
+            result(void);
+        }
+        store64_sp => Store64Sp {
+            immediates(iNN);
+            parameters(iNN);
+            result(void);
+        }
+        uextend8 => Uextend8 {
+            parameters(iNN);
+            result(iNN);
+        }
+        uextend16 => Uextend16 {
+            parameters(iNN);
+            result(iNN);
+        }
+        uextend32 => Uextend32 {
+            parameters(iNN);
+            result(iNN);
+        }
+    }
+    parse_cfg(feature = "rebuild-peephole-optimizers");
+}
+
+/// Code required to rebuild the `vcode.peepmatic` peephole optimizer.
+#[cfg(feature = "rebuild-peephole-optimizers")]
+mod rebuild {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    /// Rebuild the `vcode.peepmatic` peephole optimizer.
+    ///
+    /// Saves and overwrites the old `vcode.serialized` build and returns a
+    /// copy of the result.
+    pub fn rebuild_vcode() -> Vec<u8> {
+        let codegen_path = Path::new(include_str!(concat!(
+            env!("OUT_DIR"),
+            "/CRANELIFT_CODEGEN_PATH"
+        )));
+        let isa_path = codegen_path.join("src").join("isa").join("x64");
+        let source_path = isa_path.join("vcode.peepmatic");
+
+        let vcode = peepmatic::compile_file::<VCodeOpcode>(&source_path)
+            .expect("failed to compile `src/isa/x64/vcode.peepmatic`");
+
+        let serialized_path = isa_path.join("vcode.serialized");
+        vcode
+            .serialize_to_file(&serialized_path)
+            .expect("failed to serialize peephole optimizer to `src/isa/x64/vcode.serialized`");
+        fs::read(&serialized_path).expect("failed to read `src/isa/x64/vcode.serialized`")
+    }
+}
+
+/// Get the `vcode.peepmatic` peephole optimizations.
+pub(crate) fn vcode_peepmatic() -> &'static PeepholeOptimizations<VCodeOpcode> {
+    #[cfg(feature = "rebuild-peephole-optimizers")]
+    fn get_serialized() -> Cow<'static, [u8]> {
+        rebuild::rebuild_vcode().into()
+    }
+
+    #[cfg(not(feature = "rebuild-peephole-optimizers"))]
+    fn get_serialized() -> Cow<'static, [u8]> {
+        static SERIALIZED: &[u8] = include_bytes!("vcode.serialized");
+        SERIALIZED.into()
+    }
+
+    static DESERIALIZED: AtomicPtr<PeepholeOptimizations<VCodeOpcode>> =
+        AtomicPtr::new(ptr::null_mut());
+    deserialize_once(&DESERIALIZED, get_serialized)
+}
+
+fn imm(value: u32) -> Part<Reg> {
+    Part::Constant(Constant::Int(value.into(), BitWidth::Polymorphic))
+}
+
+fn reg(reg: Reg) -> Part<Reg> {
+    Part::Instruction(reg)
+}
+
+fn unwrap_imm(part: Part<Reg>) -> u32 {
+    match part {
+        Part::Constant(Constant::Int(value, _)) => value as u32,
+        _ => panic!("expected an integer immediate, found {:?}", part),
+    }
+}
+
+fn unwrap_reg(part: Part<Reg>) -> Reg {
+    match part {
+        Part::Instruction(reg) => reg,
+        _ => panic!("expected a register, found {:?}", part),
+    }
+}
+
+/// The base register of `addr`, if it is a plain register without
+/// displacement, or the offset from the nominal SP.
+fn base_or_sp_offset(addr: &SyntheticAmode) -> Option<Result<Reg, u32>> {
+    match *addr {
+        SyntheticAmode::Real(Amode::ImmReg { simm32: 0, base }) => Some(Ok(base)),
+        SyntheticAmode::NominalSPOffset { simm32 } => Some(Err(simm32)),
+        _ => None,
+    }
+}
+
+impl MachInstPeepmatic for Inst {
+    type Operator = VCodeOpcode;
+
+    fn peepmatic_operator(
+        &self,
+        operands: &mut Vec<Part<Reg>>,
+    ) -> Option<(VCodeOpcode, Option<Writable<Reg>>)> {
+        let (operator, rd) = match *self {
+            Inst::MovRR { is_64, src, dst } => {
+                operands.push(reg(src));
+                let operator = if is_64 {
+                    VCodeOpcode::Mov64
+                } else {
+                    VCodeOpcode::Mov32
+                };
+                (operator, Some(dst))
+            }
+            Inst::MovzxRmR {
+                ref ext_mode,
+                src: RegMem::Reg { reg: src },
+                dst,
+            } => {
+                let operator = match ext_mode {
+                    ExtMode::BL | ExtMode::BQ => VCodeOpcode::Uextend8,
+                    ExtMode::WL | ExtMode::WQ => VCodeOpcode::Uextend16,
+                    ExtMode::LQ => VCodeOpcode::Uextend32,
+                };
+                operands.push(reg(src));
+                (operator, Some(dst))
+            }
+            Inst::MovzxRmR {
+                ext_mode: ExtMode::LQ,
+                src: RegMem::Mem { ref addr },
+                dst,
+            } => match base_or_sp_offset(addr)? {
+                Ok(base) => {
+                    operands.push(reg(base));
+                    (VCodeOpcode::Load32, Some(dst))
+                }
+                Err(offset) => {
+                    operands.push(imm(offset));
+                    (VCodeOpcode::Load32Sp, Some(dst))
+                }
+            },
+            Inst::Mov64MR { ref src, dst } => match base_or_sp_offset(src)? {
+                Ok(base) => {
+                    operands.push(reg(base));
+                    (VCodeOpcode::Load64, Some(dst))
+                }
+                Err(offset) => {
+                    operands.push(imm(offset));
+                    (VCodeOpcode::Load64Sp, Some(dst))
+                }
+            },
+            Inst::MovRM { size, src, ref dst } if size == 4 || size == 8 => {
+                match (base_or_sp_offset(dst)?, size) {
+                    (Ok(base), 4) => {
+                        operands.extend_from_slice(&[reg(src), reg(base)]);
+                        (VCodeOpcode::Store32, None)
+                    }
+                    (Ok(base), _) => {
+                        operands.extend_from_slice(&[reg(src), reg(base)]);
+                        (VCodeOpcode::Store64, None)
+                    }
+                    (Err(offset), 4) => {
+                        operands.extend_from_slice(&[imm(offset), reg(src)]);
+                        (VCodeOpcode::Store32Sp, None)
+                    }
+                    (Err(offset), _) => {
+                        operands.extend_from_slice(&[imm(offset), reg(src)]);
+                        (VCodeOpcode::Store64Sp, None)
+                    }
+                }
+            }
+            Inst::LoadEffectiveAddress {
+                addr: SyntheticAmode::NominalSPOffset { simm32 },
+                dst,
+            } => {
+                operands.push(imm(simm32));
+                (VCodeOpcode::LeaSp, Some(dst))
+            }
+            _ => return None,
+        };
+        Some((operator, rd))
+    }
+
+    fn peepmatic_is_pure(operator: VCodeOpcode) -> bool {
+        match operator {
+            VCodeOpcode::Load32
+            | VCodeOpcode::Load32Sp
+            | VCodeOpcode::Load64
+            | VCodeOpcode::Load64Sp
+            | VCodeOpcode::Store32
+            | VCodeOpcode::Store32Sp
+            | VCodeOpcode::Store64
+            | VCodeOpcode::Store64Sp => false,
+            _ => true,
+        }
+    }
+
+    fn peepmatic_make_inst(
+        _root: &Inst,
+        operator: VCodeOpcode,
+        rd: Option<Writable<Reg>>,
+        operands: &[Part<Reg>],
+    ) -> Inst {
+        log::trace!("peepmatic_make_inst: {:?}{:?}", operator, operands);
+
+        let base = |i: usize| Amode::imm_reg(0, unwrap_reg(operands[i]));
+        let sp_offset = |i: usize| SyntheticAmode::nominal_sp_offset(unwrap_imm(operands[i]));
+        let uextend = |ext_mode| {
+            Inst::movzx_rm_r(ext_mode, RegMem::reg(unwrap_reg(operands[0])), rd.unwrap())
+        };
+
+        match operator {
+            VCodeOpcode::Mov32 => Inst::mov_r_r(false, unwrap_reg(operands[0]), rd.unwrap()),
+            VCodeOpcode::Mov64 => Inst::mov_r_r(true, unwrap_reg(operands[0]), rd.unwrap()),
+            VCodeOpcode::Uextend8 => uextend(ExtMode::BL),
+            VCodeOpcode::Uextend16 => uextend(ExtMode::WL),
+            VCodeOpcode::Uextend32 => uextend(ExtMode::LQ),
+            VCodeOpcode::LeaSp => Inst::lea(sp_offset(0), rd.unwrap()),
+            VCodeOpcode::Load32 => Inst::movzx_rm_r(ExtMode::LQ, RegMem::mem(base(0)), rd.unwrap()),
+            VCodeOpcode::Load32Sp => {
+                Inst::movzx_rm_r(ExtMode::LQ, RegMem::mem(sp_offset(0)), rd.unwrap())
+            }
+            VCodeOpcode::Load64 => Inst::mov64_m_r(base(0), rd.unwrap()),
+            VCodeOpcode::Load64Sp => Inst::mov64_m_r(sp_offset(0), rd.unwrap()),
+            VCodeOpcode::Store32 => Inst::mov_r_m(4, unwrap_reg(operands[0]), base(1)),
+            VCodeOpcode::Store32Sp => Inst::mov_r_m(4, unwrap_reg(operands[1]), sp_offset(0)),
+            VCodeOpcode::Store64 => Inst::mov_r_m(8, unwrap_reg(operands[0]), base(1)),
+            VCodeOpcode::Store64Sp => Inst::mov_r_m(8, unwrap_reg(operands[1]), sp_offset(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_peepmatic_vcode() {
+        let _ = vcode_peepmatic();
+    }
+}
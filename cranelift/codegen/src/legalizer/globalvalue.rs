@@ -109,11 +109,15 @@ fn load_addr(
         pos.ins().global_value(ptr_ty, base)
     };
 
-    // Global-value loads are always notrap and aligned. They may be readonly.
+    // Global-value loads are always notrap and aligned. They may be readonly. Loads from the
+    // VM context, directly or through pointers stored in it, read VM data structures.
     let mut mflags = ir::MemFlags::trusted();
     if readonly {
         mflags.set_readonly();
     }
+    if is_vmctx_based(pos.func, base) {
+        mflags.set_vmctx();
+    }
 
     // Perform the load.
     pos.func
@@ -122,6 +126,20 @@ fn load_addr(
         .load(global_type, mflags, base_addr, offset);
 }
 
+/// Is `gv` the VM context, or computed from it by loads and additions?
+fn is_vmctx_based(func: &ir::Function, mut gv: ir::GlobalValue) -> bool {
+    // The verifier rejects cycles of global values, so this terminates.
+    loop {
+        match func.global_values[gv] {
+            ir::GlobalValueData::VMContext => return true,
+            ir::GlobalValueData::Load { base, .. } | ir::GlobalValueData::IAddImm { base, .. } => {
+                gv = base
+            }
+            ir::GlobalValueData::Symbol { .. } => return false,
+        }
+    }
+}
+
 /// Expand a `global_value` instruction for a symbolic name global.
 fn symbol(
     inst: ir::Inst,
@@ -80,6 +80,7 @@ pub mod loop_analysis;
 pub mod machinst;
 pub mod print_errors;
 pub mod settings;
+pub mod stats;
 pub mod timing;
 pub mod verifier;
 pub mod write;
@@ -87,6 +88,7 @@ pub mod write;
 pub use crate::entity::packed_option;
 
 mod abi;
+mod alias_analysis;
 mod bitset;
 mod constant_hash;
 mod context;
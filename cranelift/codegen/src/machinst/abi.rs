@@ -1,12 +1,13 @@
 //! ABI definitions.
 
-use crate::binemit::StackMap;
-use crate::ir::StackSlot;
+use crate::binemit::{StackMap, UserStackMap};
+use crate::ir::{StackSlot, UserStackMapEntry};
 use crate::isa::CallConv;
 use crate::machinst::*;
 use crate::settings;
 
 use regalloc::{Reg, Set, SpillSlot, Writable};
+use smallvec::SmallVec;
 
 /// Trait implemented by an object that tracks ABI-related state (e.g., stack
 /// layout) and can generate code while emitting the *body* of a function.
@@ -50,9 +51,13 @@ pub trait ABICallee {
     /// Number of stack slots (not spill slots).
     fn num_stackslots(&self) -> usize;
 
-    /// Generate an instruction which copies an argument to a destination
-    /// register.
-    fn gen_copy_arg_to_reg(&self, idx: usize, into_reg: Writable<Reg>) -> Self::I;
+    /// Generate instructions which copy an argument to the destination
+    /// register(s).
+    fn gen_copy_arg_to_regs(
+        &self,
+        idx: usize,
+        into_regs: ValueRegs<Writable<Reg>>,
+    ) -> SmallVec<[Self::I; 2]>;
 
     /// Is the given argument needed in the body (as opposed to, e.g., serving
     /// only as a special ABI-specific placeholder)? This controls whether
@@ -66,8 +71,13 @@ pub trait ABICallee {
     /// pointer to that memory.
     fn gen_retval_area_setup(&self) -> Option<Self::I>;
 
-    /// Generate an instruction which copies a source register to a return value slot.
-    fn gen_copy_reg_to_retval(&self, idx: usize, from_reg: Writable<Reg>) -> Vec<Self::I>;
+    /// Generate instructions which copy the source register(s) to a return
+    /// value slot.
+    fn gen_copy_regs_to_retval(
+        &self,
+        idx: usize,
+        from_regs: ValueRegs<Writable<Reg>>,
+    ) -> Vec<Self::I>;
 
     /// Generate a return instruction.
     fn gen_ret(&self) -> Self::I;
@@ -120,6 +130,15 @@ pub trait ABICallee {
         state: &<Self::I as MachInstEmit>::State,
     ) -> StackMap;
 
+    /// Generate a user stack map, given the user stack map entries of a
+    /// safepoint and the emission state just before the safepointing
+    /// instruction.
+    fn user_stack_map_entries_to_stack_map(
+        &self,
+        entries: &[UserStackMapEntry],
+        state: &<Self::I as MachInstEmit>::State,
+    ) -> UserStackMap;
+
     /// Generate a prologue, post-regalloc. This should include any stack
     /// frame or other setup necessary to use the other methods (`load_arg`,
     /// `store_retval`, and spillslot accesses.)  `self` is mutable so that we
@@ -187,20 +206,22 @@ pub trait ABICaller {
     /// Get the number of arguments expected.
     fn num_args(&self) -> usize;
 
-    /// Emit a copy of an argument value from a source register, prior to the call.
-    fn emit_copy_reg_to_arg<C: LowerCtx<I = Self::I>>(
+    /// Emit a copy of an argument value from the source register(s), prior to
+    /// the call.
+    fn emit_copy_regs_to_arg<C: LowerCtx<I = Self::I>>(
         &self,
         ctx: &mut C,
         idx: usize,
-        from_reg: Reg,
+        from_regs: ValueRegs<Reg>,
     );
 
-    /// Emit a copy a return value into a destination register, after the call returns.
-    fn emit_copy_retval_to_reg<C: LowerCtx<I = Self::I>>(
+    /// Emit a copy of a return value into the destination register(s), after
+    /// the call returns.
+    fn emit_copy_retval_to_regs<C: LowerCtx<I = Self::I>>(
         &self,
         ctx: &mut C,
         idx: usize,
-        into_reg: Writable<Reg>,
+        into_regs: ValueRegs<Writable<Reg>>,
     );
 
     /// Emit code to pre-adjust the stack, prior to argument copies and call.
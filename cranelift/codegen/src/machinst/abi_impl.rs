@@ -109,9 +109,9 @@
 //!   - Return v0 in memory at `[P+16]`.
 
 use super::abi::*;
-use crate::binemit::StackMap;
+use crate::binemit::{StackMap, UserStackMap};
 use crate::ir::types::*;
-use crate::ir::{ArgumentExtension, StackSlot};
+use crate::ir::{ArgumentExtension, StackSlot, UserStackMapEntry};
 use crate::machinst::*;
 use crate::settings;
 use crate::CodegenResult;
@@ -119,6 +119,7 @@ use crate::{ir, isa};
 use alloc::vec::Vec;
 use log::{debug, trace};
 use regalloc::{RealReg, Reg, RegClass, Set, SpillSlot, Writable};
+use smallvec::{smallvec, SmallVec};
 use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::mem;
@@ -126,14 +127,17 @@ use std::mem;
 /// A location for an argument or return value.
 #[derive(Clone, Copy, Debug)]
 pub enum ABIArg {
-    /// In a real register.
+    /// In a real register, or in several real registers for values that are
+    /// split across registers (see `MachInst::rc_for_type()`).
     Reg(
-        RealReg,
+        ValueRegs<RealReg>,
         ir::Type,
         ir::ArgumentExtension,
         ir::ArgumentPurpose,
     ),
-    /// Arguments only: on stack, at given offset from SP at entry.
+    /// Arguments only: on stack, at given offset from SP at entry. A value that
+    /// is split across several registers is stored as consecutive words, low
+    /// part first.
     Stack(i64, ir::Type, ir::ArgumentExtension, ir::ArgumentPurpose),
 }
 
@@ -147,6 +151,25 @@ impl ABIArg {
     }
 }
 
+/// Split a stack argument or return value of the given type into the
+/// `(offset, type)` pairs of the parts that are loaded or stored separately:
+/// values that are held in several registers occupy consecutive words on the
+/// stack, low part first.
+fn stack_parts<M: ABIMachineSpec>(off: i64, ty: Type) -> SmallVec<[(i64, Type); 2]> {
+    let (regclasses, tys) = M::I::rc_for_type(ty).unwrap();
+    if regclasses.len() == 1 {
+        smallvec![(off, ty)]
+    } else {
+        let mut parts = SmallVec::new();
+        let mut part_off = off;
+        for &part_ty in tys {
+            parts.push((part_off, part_ty));
+            part_off += i64::from(part_ty.bytes());
+        }
+        parts
+    }
+}
+
 /// Are we computing information about arguments or return values? Much of the
 /// handling is factored out into common routines; this enum allows us to
 /// distinguish which case we're handling.
@@ -492,7 +515,7 @@ fn get_special_purpose_param_register(
 ) -> Option<Reg> {
     let idx = f.signature.special_param_index(purpose)?;
     match abi.args[idx] {
-        ABIArg::Reg(reg, ..) => Some(reg.to_reg()),
+        ABIArg::Reg(regs, ..) => Some(regs.only_reg().unwrap().to_reg()),
         ABIArg::Stack(..) => None,
     }
 }
@@ -721,8 +744,10 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
     fn liveins(&self) -> Set<RealReg> {
         let mut set: Set<RealReg> = Set::empty();
         for &arg in &self.sig.args {
-            if let ABIArg::Reg(r, ..) = arg {
-                set.insert(r);
+            if let ABIArg::Reg(regs, ..) = arg {
+                for &r in regs.regs() {
+                    set.insert(r);
+                }
             }
         }
         set
@@ -731,8 +756,10 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
     fn liveouts(&self) -> Set<RealReg> {
         let mut set: Set<RealReg> = Set::empty();
         for &ret in &self.sig.rets {
-            if let ABIArg::Reg(r, ..) = ret {
-                set.insert(r);
+            if let ABIArg::Reg(regs, ..) = ret {
+                for &r in regs.regs() {
+                    set.insert(r);
+                }
             }
         }
         set
@@ -750,17 +777,37 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
         self.stackslots.len()
     }
 
-    fn gen_copy_arg_to_reg(&self, idx: usize, into_reg: Writable<Reg>) -> Self::I {
+    fn gen_copy_arg_to_regs(
+        &self,
+        idx: usize,
+        into_regs: ValueRegs<Writable<Reg>>,
+    ) -> SmallVec<[Self::I; 2]> {
+        let mut insts = SmallVec::new();
         match &self.sig.args[idx] {
             // Extension mode doesn't matter (we're copying out, not in; we
             // ignore high bits by convention).
-            &ABIArg::Reg(r, ty, ..) => M::gen_move(into_reg, r.to_reg(), ty),
-            &ABIArg::Stack(off, ty, ..) => M::gen_load_stack(
-                StackAMode::FPOffset(M::fp_to_arg_offset(self.call_conv, &self.flags) + off, ty),
-                into_reg,
-                ty,
-            ),
+            &ABIArg::Reg(regs, ty, ..) => {
+                let (_, tys) = M::I::rc_for_type(ty).unwrap();
+                assert_eq!(regs.len(), into_regs.len());
+                for (i, (&from, &into)) in regs.regs().iter().zip(into_regs.regs()).enumerate() {
+                    let part_ty = if regs.len() == 1 { ty } else { tys[i] };
+                    insts.push(M::gen_move(into, from.to_reg(), part_ty));
+                }
+            }
+            &ABIArg::Stack(off, ty, ..) => {
+                let base = M::fp_to_arg_offset(self.call_conv, &self.flags);
+                let parts = stack_parts::<M>(off, ty);
+                assert_eq!(parts.len(), into_regs.len());
+                for (&(off, ty), &into) in parts.iter().zip(into_regs.regs()) {
+                    insts.push(M::gen_load_stack(
+                        StackAMode::FPOffset(base + off, ty),
+                        into,
+                        ty,
+                    ));
+                }
+            }
         }
+        insts
     }
 
     fn arg_is_needed_in_body(&self, idx: usize) -> bool {
@@ -773,13 +820,31 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
         }
     }
 
-    fn gen_copy_reg_to_retval(&self, idx: usize, from_reg: Writable<Reg>) -> Vec<Self::I> {
+    fn gen_copy_regs_to_retval(
+        &self,
+        idx: usize,
+        from_regs: ValueRegs<Writable<Reg>>,
+    ) -> Vec<Self::I> {
         let mut ret = Vec::new();
         let word_bits = M::word_bits() as u8;
         match &self.sig.rets[idx] {
-            &ABIArg::Reg(r, ty, ext, ..) => {
+            &ABIArg::Reg(regs, ty, ext, ..) if regs.len() > 1 => {
+                // Values split across registers are never narrower than a
+                // word, so they never need to be extended.
+                let (_, tys) = M::I::rc_for_type(ty).unwrap();
+                assert_eq!(regs.len(), from_regs.len());
+                for ((&r, &from_reg), &part_ty) in
+                    regs.regs().iter().zip(from_regs.regs()).zip(tys.iter())
+                {
+                    debug_assert_eq!(ext, ArgumentExtension::None);
+                    let dest_reg = Writable::from_reg(r.to_reg());
+                    ret.push(M::gen_move(dest_reg, from_reg.to_reg(), part_ty));
+                }
+            }
+            &ABIArg::Reg(regs, ty, ext, ..) => {
+                let from_reg = from_regs.only_reg().unwrap();
                 let from_bits = ty_bits(ty) as u8;
-                let dest_reg = Writable::from_reg(r.to_reg());
+                let dest_reg = Writable::from_reg(regs.only_reg().unwrap().to_reg());
                 let ext = M::get_ext_mode(self.sig.call_conv, ext);
                 match (ext, from_bits) {
                     (ArgumentExtension::Uext, n) | (ArgumentExtension::Sext, n)
@@ -797,7 +862,23 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
                     _ => ret.push(M::gen_move(dest_reg, from_reg.to_reg(), ty)),
                 };
             }
+            &ABIArg::Stack(off, ty, _, _) if from_regs.len() > 1 => {
+                let parts = stack_parts::<M>(off, ty);
+                assert_eq!(parts.len(), from_regs.len());
+                for (&(off, part_ty), &from_reg) in parts.iter().zip(from_regs.regs()) {
+                    let off = i32::try_from(off).expect(
+                        "Argument stack offset greater than 2GB; should hit impl limit first",
+                    );
+                    ret.push(M::gen_store_base_offset(
+                        self.ret_area_ptr.unwrap().to_reg(),
+                        off,
+                        from_reg.to_reg(),
+                        part_ty,
+                    ));
+                }
+            }
             &ABIArg::Stack(off, mut ty, ext, ..) => {
+                let from_reg = from_regs.only_reg().unwrap();
                 let from_bits = ty_bits(ty) as u8;
                 // A machine ABI implementation should ensure that stack frames
                 // have "reasonable" size. All current ABIs for machinst
@@ -837,7 +918,8 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
 
     fn gen_retval_area_setup(&self) -> Option<Self::I> {
         if let Some(i) = self.sig.stack_ret_arg {
-            let inst = self.gen_copy_arg_to_reg(i, self.ret_area_ptr.unwrap());
+            let insts = self.gen_copy_arg_to_regs(i, ValueRegs::one(self.ret_area_ptr.unwrap()));
+            let inst = insts.into_iter().next().unwrap();
             trace!(
                 "gen_retval_area_setup: inst {:?}; ptr reg is {:?}",
                 inst,
@@ -951,6 +1033,25 @@ impl<M: ABIMachineSpec> ABICallee for ABICalleeImpl<M> {
         StackMap::from_slice(&bits[..])
     }
 
+    fn user_stack_map_entries_to_stack_map(
+        &self,
+        entries: &[UserStackMapEntry],
+        state: &<Self::I as MachInstEmit>::State,
+    ) -> UserStackMap {
+        // Stackslots are at nominal SP, which is `virtual_sp_offset` above SP.
+        let virtual_sp_offset = M::get_virtual_sp_offset_from_state(state);
+        assert!(virtual_sp_offset >= 0);
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let stack_off = self.stackslots[entry.slot.as_u32() as usize];
+                let sp_off = virtual_sp_offset as u32 + stack_off + entry.offset;
+                (entry.ty, sp_off)
+            })
+            .collect();
+        UserStackMap::new(entries)
+    }
+
     fn gen_prologue(&mut self) -> Vec<Self::I> {
         let mut insts = vec![];
         if !self.call_conv.extends_baldrdash() {
@@ -1086,7 +1187,7 @@ fn abisig_to_uses_and_defs<M: ABIMachineSpec>(sig: &ABISig) -> (Vec<Reg>, Vec<Wr
     let mut uses = Vec::new();
     for arg in &sig.args {
         match arg {
-            &ABIArg::Reg(reg, ..) => uses.push(reg.to_reg()),
+            &ABIArg::Reg(regs, ..) => uses.extend(regs.regs().iter().map(|r| r.to_reg())),
             _ => {}
         }
     }
@@ -1095,7 +1196,9 @@ fn abisig_to_uses_and_defs<M: ABIMachineSpec>(sig: &ABISig) -> (Vec<Reg>, Vec<Wr
     let mut defs = M::get_regs_clobbered_by_call(sig.call_conv);
     for ret in &sig.rets {
         match ret {
-            &ABIArg::Reg(reg, ..) => defs.push(Writable::from_reg(reg.to_reg())),
+            &ABIArg::Reg(regs, ..) => {
+                defs.extend(regs.regs().iter().map(|r| Writable::from_reg(r.to_reg())))
+            }
             _ => {}
         }
     }
@@ -1214,16 +1317,33 @@ impl<M: ABIMachineSpec> ABICaller for ABICallerImpl<M> {
         adjust_stack_and_nominal_sp::<M, C>(ctx, off as i32, /* is_sub = */ false)
     }
 
-    fn emit_copy_reg_to_arg<C: LowerCtx<I = Self::I>>(
+    fn emit_copy_regs_to_arg<C: LowerCtx<I = Self::I>>(
         &self,
         ctx: &mut C,
         idx: usize,
-        from_reg: Reg,
+        from_regs: ValueRegs<Reg>,
     ) {
         let word_rc = M::word_reg_class();
         let word_bits = M::word_bits() as usize;
         match &self.sig.args[idx] {
-            &ABIArg::Reg(reg, ty, ext, _) => {
+            &ABIArg::Reg(regs, ty, _, _) if regs.len() > 1 => {
+                // Values split across registers are never narrower than a
+                // word, so they never need to be extended.
+                let (_, tys) = M::I::rc_for_type(ty).unwrap();
+                assert_eq!(regs.len(), from_regs.len());
+                for ((&reg, &from_reg), &part_ty) in
+                    regs.regs().iter().zip(from_regs.regs()).zip(tys.iter())
+                {
+                    ctx.emit(M::gen_move(
+                        Writable::from_reg(reg.to_reg()),
+                        from_reg,
+                        part_ty,
+                    ));
+                }
+            }
+            &ABIArg::Reg(regs, ty, ext, _) => {
+                let reg = regs.only_reg().unwrap();
+                let from_reg = from_regs.only_reg().unwrap();
                 let ext = M::get_ext_mode(self.sig.call_conv, ext);
                 if ext != ir::ArgumentExtension::None && ty_bits(ty) < word_bits {
                     assert_eq!(word_rc, reg.get_class());
@@ -1243,7 +1363,19 @@ impl<M: ABIMachineSpec> ABICaller for ABICallerImpl<M> {
                     ctx.emit(M::gen_move(Writable::from_reg(reg.to_reg()), from_reg, ty));
                 }
             }
+            &ABIArg::Stack(off, ty, _, _) if from_regs.len() > 1 => {
+                let parts = stack_parts::<M>(off, ty);
+                assert_eq!(parts.len(), from_regs.len());
+                for (&(off, part_ty), &from_reg) in parts.iter().zip(from_regs.regs()) {
+                    ctx.emit(M::gen_store_stack(
+                        StackAMode::SPOffset(off, part_ty),
+                        from_reg,
+                        part_ty,
+                    ));
+                }
+            }
             &ABIArg::Stack(off, mut ty, ext, _) => {
+                let from_reg = from_regs.only_reg().unwrap();
                 let ext = M::get_ext_mode(self.sig.call_conv, ext);
                 if ext != ir::ArgumentExtension::None && ty_bits(ty) < word_bits {
                     assert_eq!(word_rc, from_reg.get_class());
@@ -1274,23 +1406,34 @@ impl<M: ABIMachineSpec> ABICaller for ABICallerImpl<M> {
         }
     }
 
-    fn emit_copy_retval_to_reg<C: LowerCtx<I = Self::I>>(
+    fn emit_copy_retval_to_regs<C: LowerCtx<I = Self::I>>(
         &self,
         ctx: &mut C,
         idx: usize,
-        into_reg: Writable<Reg>,
+        into_regs: ValueRegs<Writable<Reg>>,
     ) {
         match &self.sig.rets[idx] {
             // Extension mode doesn't matter because we're copying out, not in,
             // and we ignore high bits in our own registers by convention.
-            &ABIArg::Reg(reg, ty, _, _) => ctx.emit(M::gen_move(into_reg, reg.to_reg(), ty)),
+            &ABIArg::Reg(regs, ty, _, _) => {
+                let (_, tys) = M::I::rc_for_type(ty).unwrap();
+                assert_eq!(regs.len(), into_regs.len());
+                for (i, (&reg, &into_reg)) in regs.regs().iter().zip(into_regs.regs()).enumerate() {
+                    let part_ty = if regs.len() == 1 { ty } else { tys[i] };
+                    ctx.emit(M::gen_move(into_reg, reg.to_reg(), part_ty));
+                }
+            }
             &ABIArg::Stack(off, ty, _, _) => {
                 let ret_area_base = self.sig.stack_arg_space;
-                ctx.emit(M::gen_load_stack(
-                    StackAMode::SPOffset(off + ret_area_base, ty),
-                    into_reg,
-                    ty,
-                ));
+                let parts = stack_parts::<M>(off + ret_area_base, ty);
+                assert_eq!(parts.len(), into_regs.len());
+                for (&(off, part_ty), &into_reg) in parts.iter().zip(into_regs.regs()) {
+                    ctx.emit(M::gen_load_stack(
+                        StackAMode::SPOffset(off, part_ty),
+                        into_reg,
+                        part_ty,
+                    ));
+                }
             }
         }
     }
@@ -1310,7 +1453,7 @@ impl<M: ABIMachineSpec> ABICaller for ABICallerImpl<M> {
                 rd,
                 I8,
             ));
-            self.emit_copy_reg_to_arg(ctx, i, rd.to_reg());
+            self.emit_copy_regs_to_arg(ctx, i, ValueRegs::one(rd.to_reg()));
         }
         let tmp = ctx.alloc_tmp(word_rc, word_type);
         for (is_safepoint, inst) in M::gen_call(
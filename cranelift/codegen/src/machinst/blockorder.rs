@@ -68,6 +68,11 @@
 //! Furthermore, the [MachBuffer] machine-code sink performs final peephole-like
 //! branch editing that in practice elides empty blocks and simplifies some of
 //! the other redundancies that this scheme produces.
+//!
+//! Lowered blocks containing a CLIF block marked cold in the layout, and edge
+//! blocks leading from or to one, are themselves cold. The lowering order is
+//! left unchanged, but the cold blocks are emitted after all the other ones,
+//! out of the way of the hot code.
 
 use crate::entity::SecondaryMap;
 use crate::fx::{FxHashMap, FxHashSet};
@@ -98,6 +103,8 @@ pub struct BlockLoweringOrder {
     /// some CLIF BBs may not be lowered; in particular, we skip unreachable
     /// blocks.
     orig_map: SecondaryMap<Block, Option<BlockIndex>>,
+    /// Cold blocks, which are emitted after all the other ones.
+    cold_blocks: FxHashSet<BlockIndex>,
 }
 
 /// The origin of a block in the lowered block-order: either an original CLIF
@@ -397,11 +404,23 @@ impl BlockLoweringOrder {
             .collect();
 
         let mut orig_map = SecondaryMap::with_default(None);
+        let mut cold_blocks = FxHashSet::default();
         for (i, lb) in lowered_order.iter().enumerate() {
             let i = i as BlockIndex;
             if let Some(b) = lb.orig_block() {
                 orig_map[b] = Some(i);
             }
+            let is_cold = match lb.orig_block() {
+                Some(b) => f.layout.is_cold(b),
+                None => {
+                    let (pred, _, succ) = lb.out_edge().unwrap();
+                    f.layout.is_cold(pred) || f.layout.is_cold(succ)
+                }
+            };
+            // The entry block must come first, even if it is cold.
+            if is_cold && i != 0 {
+                cold_blocks.insert(i);
+            }
         }
 
         let result = BlockLoweringOrder {
@@ -410,6 +429,7 @@ impl BlockLoweringOrder {
             lowered_succ_indices,
             lowered_succ_ranges,
             orig_map,
+            cold_blocks,
         };
         debug!("BlockLoweringOrder: {:?}", result);
         result
@@ -439,6 +459,11 @@ impl BlockLoweringOrder {
     pub fn lowered_block_for_bb(&self, bb: Block) -> Option<BlockIndex> {
         self.orig_map[bb]
     }
+
+    /// Is the lowered block cold?
+    pub fn is_cold(&self, block: BlockIndex) -> bool {
+        self.cold_blocks.contains(&block)
+    }
 }
 
 #[cfg(test)]
@@ -526,6 +551,18 @@ mod test {
         assert!(order.lowered_order[5].out_edge().is_none());
     }
 
+    #[test]
+    fn test_blockorder_cold() {
+        let mut func = build_test_func(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        func.layout.set_cold(Block::from_u32(1));
+        let order = BlockLoweringOrder::new(&func);
+
+        assert_eq!(order.lowered_order.len(), 6);
+        // Block 0, edge 0->1 + block 1, edge 1->3, edge 0->2 + block 2, edge 2->3, block 3.
+        let cold = (0..6).map(|i| order.is_cold(i)).collect::<Vec<_>>();
+        assert_eq!(cold, vec![false, true, true, false, false, false]);
+    }
+
     #[test]
     fn test_blockorder_critedge() {
         //            0
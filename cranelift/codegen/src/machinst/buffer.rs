@@ -140,7 +140,7 @@
 //! Given these invariants, we argue why each optimization preserves execution
 //! semantics below (grep for "Preserves execution semantics").
 
-use crate::binemit::{Addend, CodeOffset, CodeSink, Reloc, StackMap};
+use crate::binemit::{Addend, CodeOffset, CodeSink, Reloc, StackMap, UserStackMap};
 use crate::ir::{ExternalName, Opcode, SourceLoc, TrapCode};
 use crate::machinst::{BlockIndex, MachInstLabelUse, VCodeConstant, VCodeConstants, VCodeInst};
 use crate::timing;
@@ -173,6 +173,8 @@ pub struct MachBuffer<I: VCodeInst> {
     srclocs: SmallVec<[MachSrcLoc; 64]>,
     /// Any stack maps referring to this code.
     stack_maps: SmallVec<[MachStackMap; 8]>,
+    /// Any user stack maps referring to this code.
+    user_stack_maps: SmallVec<[MachUserStackMap; 8]>,
     /// The current source location in progress (after `start_srcloc()` and
     /// before `end_srcloc()`).  This is a (start_offset, src_loc) tuple.
     cur_srcloc: Option<(CodeOffset, SourceLoc)>,
@@ -240,6 +242,8 @@ pub struct MachBufferFinalized {
     srclocs: SmallVec<[MachSrcLoc; 64]>,
     /// Any stack maps referring to this code.
     stack_maps: SmallVec<[MachStackMap; 8]>,
+    /// Any user stack maps referring to this code.
+    user_stack_maps: SmallVec<[MachUserStackMap; 8]>,
 }
 
 static UNKNOWN_LABEL_OFFSET: CodeOffset = 0xffff_ffff;
@@ -299,6 +303,7 @@ impl<I: VCodeInst> MachBuffer<I> {
             call_sites: SmallVec::new(),
             srclocs: SmallVec::new(),
             stack_maps: SmallVec::new(),
+            user_stack_maps: SmallVec::new(),
             cur_srcloc: None,
             label_offsets: SmallVec::new(),
             label_aliases: SmallVec::new(),
@@ -674,6 +679,18 @@ impl<I: VCodeInst> MachBuffer<I> {
         //    (end of buffer)
         self.data.truncate(b.start as usize);
         self.fixup_records.truncate(b.fixup);
+        // Source location ranges that covered the branch must not extend past the truncated
+        // end of the buffer.
+        while let Some(srcloc) = self.srclocs.last_mut() {
+            if srcloc.end <= b.start {
+                break;
+            }
+            if srcloc.start < b.start {
+                srcloc.end = b.start;
+                break;
+            }
+            self.srclocs.pop();
+        }
         // State:
         //    [PRE CODE]
         //  cur_off, Offset b.start, b.labels_at_this_branch:
@@ -1191,6 +1208,7 @@ impl<I: VCodeInst> MachBuffer<I> {
             call_sites: self.call_sites,
             srclocs: self.srclocs,
             stack_maps: self.stack_maps,
+            user_stack_maps: self.user_stack_maps,
         }
     }
 
@@ -1275,6 +1293,13 @@ impl<I: VCodeInst> MachBuffer<I> {
             stack_map,
         });
     }
+
+    /// Add user stack map metadata for the safepoint whose return address is
+    /// `offset`.
+    pub fn add_user_stack_map(&mut self, offset: CodeOffset, stack_map: UserStackMap) {
+        self.user_stack_maps
+            .push(MachUserStackMap { offset, stack_map });
+    }
 }
 
 impl MachBufferFinalized {
@@ -1338,6 +1363,11 @@ impl MachBufferFinalized {
     pub fn stack_maps(&self) -> &[MachStackMap] {
         &self.stack_maps[..]
     }
+
+    /// Get the user stack map metadata for this code.
+    pub fn user_stack_maps(&self) -> &[MachUserStackMap] {
+        &self.user_stack_maps[..]
+    }
 }
 
 /// A constant that is deferred to the next constant-pool opportunity.
@@ -1430,6 +1460,16 @@ pub struct MachStackMap {
     pub stack_map: StackMap,
 }
 
+/// Record of user stack map metadata: the stack offsets of the values that a
+/// frontend declared as needing a stack map.
+#[derive(Clone, Debug)]
+pub struct MachUserStackMap {
+    /// The code offset just past the safepoint, i.e. its return address.
+    pub offset: CodeOffset,
+    /// The stack map itself.
+    pub stack_map: UserStackMap,
+}
+
 /// Record of branch instruction in the buffer, to facilitate editing.
 #[derive(Clone, Debug)]
 struct MachBranch {
@@ -1464,6 +1504,7 @@ mod test {
     use crate::machinst::MachInstEmit;
     use crate::settings;
     use std::default::Default;
+    use std::vec::Vec;
 
     fn label(n: u32) -> MachLabel {
         MachLabel::from_block(n)
@@ -1487,6 +1528,35 @@ mod test {
         assert_eq!(0, buf.total_size());
     }
 
+    #[test]
+    fn test_elide_jump_to_next_srcloc() {
+        let info = EmitInfo::new(settings::Flags::new(settings::builder()));
+        let mut buf = MachBuffer::new();
+        let mut state = Default::default();
+
+        buf.reserve_labels_for_blocks(2);
+        buf.bind_label(label(0));
+        buf.start_srcloc(SourceLoc::new(1));
+        let inst = Inst::Nop4;
+        inst.emit(&mut buf, &info, &mut state);
+        let inst = Inst::Jump { dest: target(1) };
+        inst.emit(&mut buf, &info, &mut state);
+        buf.end_srcloc();
+        buf.start_srcloc(SourceLoc::new(2));
+        let inst = Inst::Jump { dest: target(1) };
+        inst.emit(&mut buf, &info, &mut state);
+        buf.end_srcloc();
+        buf.bind_label(label(1));
+        let buf = buf.finish();
+        assert_eq!(4, buf.total_size());
+        let srclocs: Vec<_> = buf
+            .get_srclocs_sorted()
+            .iter()
+            .map(|srcloc| (srcloc.start, srcloc.end, srcloc.loc))
+            .collect();
+        assert_eq!(srclocs, vec![(0, 4, SourceLoc::new(1))]);
+    }
+
     #[test]
     fn test_elide_trivial_jump_blocks() {
         let info = EmitInfo::new(settings::Flags::new(settings::builder()));
@@ -24,7 +24,8 @@ where
     // Build the lowering context.
     let lower = Lower::new(f, abi, emit_info, block_order)?;
     // Lower the IR.
-    let (mut vcode, stack_map_request_info) = {
+    #[cfg_attr(not(feature = "enable-peepmatic"), allow(unused_mut))]
+    let (mut vcode, mut stack_map_request_info) = {
         let _tt = timing::vcode_lower();
         lower.lower(b)?
     };
@@ -34,6 +35,19 @@ where
         vcode.show_rru(Some(b.reg_universe()))
     );
 
+    // Run peephole optimizations on the lowered vcode.
+    #[cfg(feature = "enable-peepmatic")]
+    {
+        if vcode.flags().opt_level() != settings::OptLevel::None {
+            let _tt = timing::vcode_peepmatic();
+            b.optimize_vcode(&mut vcode, &mut stack_map_request_info);
+            debug!(
+                "vcode after peephole optimization: \n{}",
+                vcode.show_rru(Some(b.reg_universe()))
+            );
+        }
+    }
+
     // Perform register allocation.
     let (run_checker, algorithm) = match vcode.flags().regalloc() {
         settings::Regalloc::Backtracking => (false, Algorithm::Backtracking(Default::default())),
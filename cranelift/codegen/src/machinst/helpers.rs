@@ -1,6 +1,6 @@
 //! Miscellaneous helpers for machine backends.
 
-use super::{InsnOutput, LowerCtx, VCodeInst};
+use super::{InsnOutput, LowerCtx, VCodeInst, ValueRegs};
 use crate::ir::Type;
 use regalloc::{Reg, Writable};
 
@@ -26,3 +26,11 @@ pub(crate) fn get_output_reg<I: VCodeInst, C: LowerCtx<I = I>>(
 ) -> Writable<Reg> {
     ctx.get_output(spec.insn, spec.output)
 }
+
+/// Get the register(s) for an instruction output, as many as its type requires.
+pub(crate) fn get_output_regs<I: VCodeInst, C: LowerCtx<I = I>>(
+    ctx: &mut C,
+    spec: InsnOutput,
+) -> ValueRegs<Writable<Reg>> {
+    ctx.get_output_regs(spec.insn, spec.output)
+}
@@ -0,0 +1,107 @@
+//! Shared support for backend lowering written in ISLE.
+//!
+//! ISLE rules are compiled by the build script into a `generated_code`
+//! module per backend, which calls back into the backend through its
+//! `Context` trait. The types and methods below back the declarations of
+//! `src/prelude.isle`, common to all backends.
+
+use regalloc::{Reg, Writable};
+
+/// The ISLE `Unit` type, for constructors with side effects only.
+pub type Unit = ();
+
+/// The ISLE `WritableReg` type.
+pub type WritableReg = Writable<Reg>;
+
+/// The ISLE `ValueRegs` type.
+pub type ValueRegs = crate::machinst::ValueRegs<Reg>;
+
+/// Implements the `Context` methods declared in `src/prelude.isle`, except
+/// for `put_in_reg`, which is backend-specific.
+///
+/// The macro is meant to be expanded inside the backend's `impl Context`
+/// block, for a context type holding a `LowerCtx` in a `lower_ctx` field and
+/// with `MInst` naming the backend's machine instruction type.
+macro_rules! isle_prelude_methods {
+    () => {
+        fn has_type(&mut self, inst: Inst) -> Option<(Type, Inst)> {
+            if self.lower_ctx.num_outputs(inst) > 0 {
+                Some((self.lower_ctx.output_ty(inst, 0), inst))
+            } else {
+                None
+            }
+        }
+
+        fn def_inst(&mut self, val: InsnInput) -> Option<Inst> {
+            match self.lower_ctx.get_input(val.insn, val.input).inst {
+                Some((inst, 0)) => Some(inst),
+                _ => None,
+            }
+        }
+
+        fn value_type(&mut self, val: InsnInput) -> Option<Type> {
+            Some(self.lower_ctx.input_ty(val.insn, val.input))
+        }
+
+        fn u64_from_value(&mut self, val: InsnInput) -> Option<u64> {
+            self.lower_ctx.get_input(val.insn, val.input).constant
+        }
+
+        fn fits_in_32(&mut self, ty: Type) -> Option<Type> {
+            if !ty.is_vector() && ty.bits() <= 32 {
+                Some(ty)
+            } else {
+                None
+            }
+        }
+
+        fn fits_in_64(&mut self, ty: Type) -> Option<Type> {
+            if !ty.is_vector() && ty.bits() <= 64 {
+                Some(ty)
+            } else {
+                None
+            }
+        }
+
+        fn unary_op(&mut self, inst: Inst) -> Option<(Opcode, InsnInput)> {
+            if self.lower_ctx.num_inputs(inst) == 1 {
+                let op = self.lower_ctx.data(inst).opcode();
+                Some((op, InsnInput { insn: inst, input: 0 }))
+            } else {
+                None
+            }
+        }
+
+        fn binary_op(&mut self, inst: Inst) -> Option<(Opcode, InsnInput, InsnInput)> {
+            if self.lower_ctx.num_inputs(inst) == 2 {
+                let op = self.lower_ctx.data(inst).opcode();
+                Some((
+                    op,
+                    InsnInput { insn: inst, input: 0 },
+                    InsnInput { insn: inst, input: 1 },
+                ))
+            } else {
+                None
+            }
+        }
+
+        fn value_reg(&mut self, reg: Reg) -> ValueRegs {
+            ValueRegs::one(reg)
+        }
+
+        fn writable_reg_to_reg(&mut self, reg: WritableReg) -> Reg {
+            reg.to_reg()
+        }
+
+        fn temp_writable_reg(&mut self, ty: Type) -> WritableReg {
+            let rc = MInst::rc_for_type(ty).unwrap().0[0];
+            self.lower_ctx.alloc_tmp(rc, ty)
+        }
+
+        fn emit(&mut self, inst: MInst) -> Unit {
+            self.lower_ctx.emit(inst);
+        }
+    };
+}
+
+pub(crate) use isle_prelude_methods;
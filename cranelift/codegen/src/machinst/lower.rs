@@ -12,12 +12,12 @@ use crate::ir::{
     InstructionData, MemFlags, Opcode, Signature, SourceLoc, Type, Value, ValueDef,
 };
 use crate::machinst::{
-    ABICallee, BlockIndex, BlockLoweringOrder, LoweredBlock, MachLabel, VCode, VCodeBuilder,
-    VCodeConstant, VCodeConstantData, VCodeConstants, VCodeInst,
+    writable_value_regs, ABICallee, BlockIndex, BlockLoweringOrder, LoweredBlock, MachLabel, VCode,
+    VCodeBuilder, VCodeConstant, VCodeConstantData, VCodeConstants, VCodeInst, ValueRegs,
 };
 use crate::CodegenResult;
 
-use regalloc::{Reg, RegClass, StackmapRequestInfo, VirtualReg, Writable};
+use regalloc::{Reg, RegClass, StackmapRequestInfo, Writable};
 
 use crate::data_value::DataValue;
 use alloc::boxed::Box;
@@ -64,11 +64,11 @@ pub trait LowerCtx {
 
     /// Get the `ABICallee`.
     fn abi(&mut self) -> &mut dyn ABICallee<I = Self::I>;
-    /// Get the (virtual) register that receives the return value. A return
-    /// instruction should lower into a sequence that fills this register. (Why
+    /// Get the (virtual) register(s) that receive the return value. A return
+    /// instruction should lower into a sequence that fills these registers. (Why
     /// not allow the backend to specify its own result register for the return?
     /// Because there may be multiple return points.)
-    fn retval(&self, idx: usize) -> Writable<Reg>;
+    fn retval(&self, idx: usize) -> ValueRegs<Writable<Reg>>;
     /// Returns the vreg containing the VmContext parameter, if there's one.
     fn get_vm_context(&self) -> Option<Reg>;
 
@@ -133,7 +133,16 @@ pub trait LowerCtx {
     /// For correctness, backends should thus wrap `get_input()` and
     /// `use_input_regs()` with helpers that return a register only after
     /// ensuring it is marked as used.
+    ///
+    /// Values that are split across several registers (see
+    /// `put_input_in_regs()`) are never available in a single register: the
+    /// returned `reg` is then invalid, and no constant is provided.
     fn get_input(&self, ir_inst: Inst, idx: usize) -> LowerInput;
+    /// Get the register(s) holding the given input, marking the producing
+    /// instruction as used. This is the only way to get at the registers of a
+    /// value that is split across several registers, such as an `I128` on a
+    /// 64-bit machine; the parts are returned low part first.
+    fn put_input_in_regs(&mut self, ir_inst: Inst, idx: usize) -> ValueRegs<Reg>;
     /// Get the `idx`th output register of the given IR instruction. When
     /// `backend.lower_inst_to_regs(ctx, inst)` is called, it is expected that
     /// the backend will write results to these output register(s).  This
@@ -142,6 +151,10 @@ pub trait LowerCtx {
     /// the lowered instruction sequence, as long as its final value is the
     /// result of the computation.
     fn get_output(&self, ir_inst: Inst, idx: usize) -> Writable<Reg>;
+    /// Get the `idx`th output register(s) of the given IR instruction. This is
+    /// like `get_output()`, but also supports values that are split across
+    /// several registers, which are returned low part first.
+    fn get_output_regs(&self, ir_inst: Inst, idx: usize) -> ValueRegs<Writable<Reg>>;
 
     // Codegen primitives: allocate temps, emit instructions, set result registers,
     // ask for an input to be gen'd into a register.
@@ -178,8 +191,9 @@ pub trait LowerCtx {
 /// details.
 #[derive(Clone, Copy, Debug)]
 pub struct LowerInput {
-    /// The value is live in a register. This option is always available. Call
-    /// [LowerCtx::use_input_reg()] if the register is used.
+    /// The value is live in a register. This option is always available for
+    /// values that fit in one register. Call [LowerCtx::use_input_reg()] if the
+    /// register is used.
     pub reg: Reg,
     /// An instruction produces this value; the instruction's result index that
     /// produces this value is given.
@@ -217,13 +231,24 @@ pub trait LowerBackend {
     fn maybe_pinned_reg(&self) -> Option<Reg> {
         None
     }
+
+    /// Run this backend's `peepmatic`-based peephole optimizations on lowered
+    /// vcode, before register allocation.
+    #[cfg(feature = "enable-peepmatic")]
+    fn optimize_vcode(
+        &self,
+        _vcode: &mut VCode<Self::MInst>,
+        _stack_map_info: &mut StackmapRequestInfo,
+    ) {
+    }
 }
 
-/// A pending instruction to insert and auxiliary information about it: its source location and
-/// whether it is a safepoint.
+/// A pending instruction to insert and auxiliary information about it: its source location,
+/// whether it is a safepoint, and the IR instruction whose user stack map applies to it, if any.
 struct InstTuple<I: VCodeInst> {
     loc: SourceLoc,
     is_safepoint: bool,
+    user_stack_map: Option<Inst>,
     inst: I,
 }
 
@@ -236,11 +261,11 @@ pub struct Lower<'func, I: VCodeInst> {
     /// Lowered machine instructions.
     vcode: VCodeBuilder<I>,
 
-    /// Mapping from `Value` (SSA value in IR) to virtual register.
-    value_regs: SecondaryMap<Value, Reg>,
+    /// Mapping from `Value` (SSA value in IR) to virtual register(s).
+    value_regs: SecondaryMap<Value, ValueRegs<Reg>>,
 
     /// Return-value vregs.
-    retval_regs: Vec<Reg>,
+    retval_regs: Vec<ValueRegs<Reg>>,
 
     /// Instruction colors.
     inst_colors: SecondaryMap<Inst, InstColor>,
@@ -290,20 +315,29 @@ pub enum RelocDistance {
     Far,
 }
 
-fn alloc_vreg(
-    value_regs: &mut SecondaryMap<Value, Reg>,
-    regclass: RegClass,
-    value: Value,
+/// Allocate the vreg(s) to hold a value of the given type, recording their
+/// types in the VCode.
+fn alloc_vregs<I: VCodeInst>(
+    ty: Type,
     next_vreg: &mut u32,
-) -> VirtualReg {
-    if value_regs[value].is_invalid() {
-        // default value in map.
-        let v = *next_vreg;
+    vcode: &mut VCodeBuilder<I>,
+) -> CodegenResult<ValueRegs<Reg>> {
+    let (regclasses, tys) = I::rc_for_type(ty)?;
+    let mut regs = SmallVec::<[Reg; 2]>::new();
+    for (&regclass, &part_ty) in regclasses.iter().zip(tys.iter()) {
+        // A value held in a single register keeps its own type; the part types
+        // only matter for values split across registers.
+        let part_ty = if regclasses.len() == 1 { ty } else { part_ty };
+        let vreg = Reg::new_virtual(regclass, *next_vreg);
         *next_vreg += 1;
-        value_regs[value] = Reg::new_virtual(regclass, v);
-        debug!("value {} gets vreg {:?}", value, v);
+        vcode.set_vreg_type(vreg.as_virtual_reg().unwrap(), part_ty);
+        regs.push(vreg);
     }
-    value_regs[value].as_virtual_reg().unwrap()
+    Ok(match regs.len() {
+        1 => ValueRegs::one(regs[0]),
+        2 => ValueRegs::two(regs[0], regs[1]),
+        _ => panic!("value of type {} split across too many registers", ty),
+    })
 }
 
 enum GenerateReturn {
@@ -324,26 +358,29 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
 
         let mut next_vreg: u32 = 0;
 
-        let mut value_regs = SecondaryMap::with_default(Reg::invalid());
+        let mut value_regs = SecondaryMap::with_default(ValueRegs::invalid());
 
         // Assign a vreg to each block param and each inst result.
         for bb in f.layout.blocks() {
             for &param in f.dfg.block_params(bb) {
                 let ty = f.dfg.value_type(param);
-                let vreg = alloc_vreg(&mut value_regs, I::rc_for_type(ty)?, param, &mut next_vreg);
-                vcode.set_vreg_type(vreg, ty);
-                debug!("bb {} param {}: vreg {:?}", bb, param, vreg);
+                if value_regs[param].is_invalid() {
+                    let regs = alloc_vregs(ty, &mut next_vreg, &mut vcode)?;
+                    value_regs[param] = regs;
+                    debug!("bb {} param {}: regs {:?}", bb, param, regs);
+                }
             }
             for inst in f.layout.block_insts(bb) {
                 for &result in f.dfg.inst_results(inst) {
                     let ty = f.dfg.value_type(result);
-                    let vreg =
-                        alloc_vreg(&mut value_regs, I::rc_for_type(ty)?, result, &mut next_vreg);
-                    vcode.set_vreg_type(vreg, ty);
-                    debug!(
-                        "bb {} inst {} ({:?}): result vreg {:?}",
-                        bb, inst, f.dfg[inst], vreg
-                    );
+                    if value_regs[result].is_invalid() {
+                        let regs = alloc_vregs(ty, &mut next_vreg, &mut vcode)?;
+                        value_regs[result] = regs;
+                        debug!(
+                            "bb {} inst {} ({:?}): result regs {:?}",
+                            bb, inst, f.dfg[inst], regs
+                        );
+                    }
                 }
             }
         }
@@ -354,18 +391,15 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             .map(|vm_context_index| {
                 let entry_block = f.layout.entry_block().unwrap();
                 let param = f.dfg.block_params(entry_block)[vm_context_index];
-                value_regs[param]
+                value_regs[param].only_reg().unwrap()
             });
 
-        // Assign a vreg to each return value.
+        // Assign vreg(s) to each return value.
         let mut retval_regs = vec![];
         for ret in &f.signature.returns {
-            let v = next_vreg;
-            next_vreg += 1;
-            let regclass = I::rc_for_type(ret.value_type)?;
-            let vreg = Reg::new_virtual(regclass, v);
-            retval_regs.push(vreg);
-            vcode.set_vreg_type(vreg.as_virtual_reg().unwrap(), ret.value_type);
+            let regs = alloc_vregs(ret.value_type, &mut next_vreg, &mut vcode)?;
+            retval_regs.push(regs);
+            debug!("retval gets regs {:?}", regs);
         }
 
         // Compute instruction colors, find constant instructions, and find instructions with
@@ -428,9 +462,10 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
                 if !self.vcode.abi().arg_is_needed_in_body(i) {
                     continue;
                 }
-                let reg = Writable::from_reg(self.value_regs[*param]);
-                let insn = self.vcode.abi().gen_copy_arg_to_reg(i, reg);
-                self.emit(insn);
+                let regs = writable_value_regs(self.value_regs[*param]);
+                for insn in self.vcode.abi().gen_copy_arg_to_regs(i, regs).into_iter() {
+                    self.emit(insn);
+                }
             }
             if let Some(insn) = self.vcode.abi().gen_retval_area_setup() {
                 self.emit(insn);
@@ -440,9 +475,9 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
 
     fn gen_retval_setup(&mut self, gen_ret_inst: GenerateReturn) {
         let retval_regs = self.retval_regs.clone();
-        for (i, reg) in retval_regs.into_iter().enumerate() {
-            let reg = Writable::from_reg(reg);
-            let insns = self.vcode.abi().gen_copy_reg_to_retval(i, reg);
+        for (i, regs) in retval_regs.into_iter().enumerate() {
+            let regs = writable_value_regs(regs);
+            let insns = self.vcode.abi().gen_copy_regs_to_retval(i, regs);
             for insn in insns {
                 self.emit(insn);
             }
@@ -487,22 +522,33 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             let ty = self.f.dfg.value_type(src_val);
 
             debug_assert!(ty == self.f.dfg.value_type(*dst_val));
-            let dst_reg = self.value_regs[*dst_val];
+            let dst_regs = self.value_regs[*dst_val];
 
             let input = self.get_input_for_val(inst, src_val);
             debug!("jump arg {} is {}, reg {:?}", i, src_val, input.reg);
             i += 1;
 
             if let Some(c) = input.constant {
+                let dst_reg = dst_regs.only_reg().unwrap();
                 const_bundles.push((ty, Writable::from_reg(dst_reg), c));
             } else {
-                self.use_input_reg(input);
-                let src_reg = input.reg;
-                // Skip self-assignments.  Not only are they pointless, they falsely trigger the
-                // overlap-check below and hence can cause a lot of unnecessary copying through
-                // temporaries.
-                if dst_reg != src_reg {
-                    var_bundles.push((ty, Writable::from_reg(dst_reg), src_reg));
+                let src_regs = self.value_regs[src_val];
+                self.mark_regs_needed(src_regs);
+                // Values split across several registers are moved one part at a time.
+                let (_, part_tys) = I::rc_for_type(ty)?;
+                for (j, (&dst_reg, &src_reg)) in dst_regs
+                    .regs()
+                    .iter()
+                    .zip(src_regs.regs().iter())
+                    .enumerate()
+                {
+                    let part_ty = if dst_regs.len() == 1 { ty } else { part_tys[j] };
+                    // Skip self-assignments.  Not only are they pointless, they falsely trigger
+                    // the overlap-check below and hence can cause a lot of unnecessary copying
+                    // through temporaries.
+                    if dst_reg != src_reg {
+                        var_bundles.push((part_ty, Writable::from_reg(dst_reg), src_reg));
+                    }
                 }
             }
         }
@@ -534,7 +580,7 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             // There's some overlap, so play safe and copy via temps.
             let mut tmp_regs = SmallVec::<[Writable<Reg>; 16]>::new();
             for (ty, _, _) in &var_bundles {
-                tmp_regs.push(self.alloc_tmp(I::rc_for_type(*ty)?, *ty));
+                tmp_regs.push(self.alloc_tmp(I::rc_for_type(*ty)?.0[0], *ty));
             }
             for ((ty, _, src_reg), tmp_reg) in var_bundles.iter().zip(tmp_regs.iter()) {
                 self.emit(I::gen_move(*tmp_reg, *src_reg, *ty));
@@ -582,12 +628,12 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
         // end of the BB (in the toplevel driver `lower()`).
         for inst in self.f.layout.block_insts(block).rev() {
             let data = &self.f.dfg[inst];
-            let value_needed = self
-                .f
-                .dfg
-                .inst_results(inst)
-                .iter()
-                .any(|&result| self.vreg_needed[self.value_regs[result].get_index()]);
+            let value_needed = self.f.dfg.inst_results(inst).iter().any(|&result| {
+                self.value_regs[result]
+                    .regs()
+                    .iter()
+                    .any(|reg| self.vreg_needed[reg.get_index()])
+            });
             debug!(
                 "lower_clif_block: block {} inst {} ({:?}) is_branch {} inst_needed {} value_needed {}",
                 block,
@@ -616,6 +662,11 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
                 };
                 self.gen_retval_setup(gen_ret);
             }
+            if self.f.dfg.user_stack_map_entries(inst).is_some() {
+                for tuple in self.ir_insts.iter_mut().filter(|tuple| tuple.is_safepoint) {
+                    tuple.user_stack_map = Some(inst);
+                }
+            }
 
             let loc = self.srcloc(inst);
             self.finish_ir_inst(loc);
@@ -646,11 +697,16 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
             for &InstTuple {
                 loc,
                 is_safepoint,
+                user_stack_map,
                 ref inst,
             } in &self.block_insts[start..end]
             {
                 self.vcode.set_srcloc(loc);
                 self.vcode.push(inst.clone(), is_safepoint);
+                if let Some(ir_inst) = user_stack_map {
+                    let entries = self.f.dfg.user_stack_map_entries(ir_inst).unwrap();
+                    self.vcode.add_user_stack_map(entries);
+                }
             }
             self.vcode.end_bb();
         }
@@ -786,14 +842,26 @@ impl<'func, I: VCodeInst> Lower<'func, I> {
         Ok((vcode, stack_map_info))
     }
 
+    /// Mark the given vregs as needed, so that their producer is lowered.
+    fn mark_regs_needed(&mut self, regs: ValueRegs<Reg>) {
+        for reg in regs.regs() {
+            if reg.is_virtual() {
+                self.vreg_needed[reg.get_index()] = true;
+            }
+        }
+    }
+
     /// Get the actual inputs for a value. This is the implementation for
     /// `get_input()` but starting from the SSA value, which is not exposed to
     /// the backend.
     fn get_input_for_val(&self, at_inst: Inst, val: Value) -> LowerInput {
         debug!("get_input_for_val: val {} at inst {}", val, at_inst);
-        let mut reg = self.value_regs[val];
-        debug!(" -> reg {:?}", reg);
-        assert!(reg.is_valid());
+        let regs = self.value_regs[val];
+        debug!(" -> regs {:?}", regs);
+        assert!(regs.is_valid());
+        // Values split across several registers are only available through
+        // `put_input_in_regs()`.
+        let mut reg = regs.only_reg().unwrap_or(Reg::invalid());
         let mut inst = match self.f.dfg.value_def(val) {
             // OK to merge source instruction if (i) we have a source
             // instruction, and either (ii-a) it has no side effects, or (ii-b)
@@ -850,8 +918,8 @@ impl<'func, I: VCodeInst> LowerCtx for Lower<'func, I> {
         self.vcode.abi()
     }
 
-    fn retval(&self, idx: usize) -> Writable<Reg> {
-        Writable::from_reg(self.retval_regs[idx])
+    fn retval(&self, idx: usize) -> ValueRegs<Writable<Reg>> {
+        writable_value_regs(self.retval_regs[idx])
     }
 
     fn get_vm_context(&self) -> Option<Reg> {
@@ -911,17 +979,7 @@ impl<'func, I: VCodeInst> LowerCtx for Lower<'func, I> {
     }
 
     fn memflags(&self, ir_inst: Inst) -> Option<MemFlags> {
-        match &self.f.dfg[ir_inst] {
-            &InstructionData::AtomicCas { flags, .. } => Some(flags),
-            &InstructionData::AtomicRmw { flags, .. } => Some(flags),
-            &InstructionData::Load { flags, .. }
-            | &InstructionData::LoadComplex { flags, .. }
-            | &InstructionData::LoadNoOffset { flags, .. }
-            | &InstructionData::Store { flags, .. }
-            | &InstructionData::StoreComplex { flags, .. } => Some(flags),
-            &InstructionData::StoreNoOffset { flags, .. } => Some(flags),
-            _ => None,
-        }
+        self.f.dfg[ir_inst].memflags()
     }
 
     fn srcloc(&self, ir_inst: Inst) -> SourceLoc {
@@ -960,9 +1018,34 @@ impl<'func, I: VCodeInst> LowerCtx for Lower<'func, I> {
         self.get_input_for_val(ir_inst, val)
     }
 
+    fn put_input_in_regs(&mut self, ir_inst: Inst, idx: usize) -> ValueRegs<Reg> {
+        let val = self.f.dfg.inst_args(ir_inst)[idx];
+        let val = self.f.dfg.resolve_aliases(val);
+        let input = self.get_input_for_val(ir_inst, val);
+        if input.reg.is_valid() && input.reg.is_real() {
+            // Pinned-reg hack: see `get_input_for_val()`.
+            return ValueRegs::one(input.reg);
+        }
+        let regs = self.value_regs[val];
+        self.mark_regs_needed(regs);
+        regs
+    }
+
     fn get_output(&self, ir_inst: Inst, idx: usize) -> Writable<Reg> {
         let val = self.f.dfg.inst_results(ir_inst)[idx];
-        Writable::from_reg(self.value_regs[val])
+        let regs = self.value_regs[val];
+        match regs.only_reg() {
+            Some(reg) => Writable::from_reg(reg),
+            None => panic!(
+                "get_output() on {}, which is split across several registers; use get_output_regs()",
+                val
+            ),
+        }
+    }
+
+    fn get_output_regs(&self, ir_inst: Inst, idx: usize) -> ValueRegs<Writable<Reg>> {
+        let val = self.f.dfg.inst_results(ir_inst)[idx];
+        writable_value_regs(self.value_regs[val])
     }
 
     fn alloc_tmp(&mut self, rc: RegClass, ty: Type) -> Writable<Reg> {
@@ -977,6 +1060,7 @@ impl<'func, I: VCodeInst> LowerCtx for Lower<'func, I> {
         self.ir_insts.push(InstTuple {
             loc: SourceLoc::default(),
             is_safepoint: false,
+            user_stack_map: None,
             inst: mach_inst,
         });
     }
@@ -985,12 +1069,17 @@ impl<'func, I: VCodeInst> LowerCtx for Lower<'func, I> {
         self.ir_insts.push(InstTuple {
             loc: SourceLoc::default(),
             is_safepoint: true,
+            user_stack_map: None,
             inst: mach_inst,
         });
     }
 
     fn use_input_reg(&mut self, input: LowerInput) {
         debug!("use_input_reg: vreg {:?} is needed", input.reg);
+        debug_assert!(
+            input.reg.is_valid(),
+            "use_input_reg() on a value split across several registers"
+        );
         // We may directly return a real (machine) register when we know that register holds the
         // result of an opcode (e.g. GetPinnedReg).
         if input.reg.is_virtual() {
@@ -135,6 +135,13 @@ pub mod helpers;
 pub use helpers::*;
 pub mod inst_common;
 pub use inst_common::*;
+pub mod valueregs;
+pub use valueregs::*;
+// Only the backends whose lowering is written in ISLE use this module.
+#[cfg(feature = "arm64")]
+pub mod isle;
+#[cfg(feature = "enable-peepmatic")]
+pub mod peepmatic;
 
 /// A machine instruction.
 pub trait MachInst: Clone + Debug {
@@ -180,9 +187,12 @@ pub trait MachInst: Clone + Debug {
     /// (e.g., add directly from or directly to memory), like x86.
     fn maybe_direct_reload(&self, reg: VirtualReg, slot: SpillSlot) -> Option<Self>;
 
-    /// Determine a register class to store the given Cranelift type.
+    /// Determine the register class(es) needed to store the given Cranelift
+    /// type, along with the type of the value held in each register. A type
+    /// that is too wide for a single register (e.g. `I128` on a 64-bit
+    /// machine) is split across several registers, low part first.
     /// May return an error if the type isn't supported by this backend.
-    fn rc_for_type(ty: Type) -> CodegenResult<RegClass>;
+    fn rc_for_type(ty: Type) -> CodegenResult<(&'static [RegClass], &'static [Type])>;
 
     /// Generate a jump to another target. Used during lowering of
     /// control flow.
@@ -318,6 +328,8 @@ pub struct MachCompileResult {
     pub disasm: Option<String>,
     /// Unwind info.
     pub unwind_info: Option<unwind_input::UnwindInfo<Reg>>,
+    /// Statistics about lowering and register allocation.
+    pub stats: VCodeStats,
 }
 
 impl MachCompileResult {
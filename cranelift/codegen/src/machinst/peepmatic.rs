@@ -0,0 +1,461 @@
+//! Peepmatic-based peephole optimizations on vcode.
+//!
+//! These run after lowering and before register allocation, on the machine
+//! instructions of a backend. Each backend describes the instructions that its
+//! rules may match and build by implementing `MachInstPeepmatic`, and compiles
+//! its rules ahead of time, like the `preopt.peepmatic` rules for the IR.
+//!
+//! Vcode isn't in SSA form, so matching only looks through a virtual register
+//! to its defining instruction when that is the register's single definition,
+//! comes earlier in the same block as the instruction being rewritten, has no
+//! side effects, and itself only reads singly-defined virtual registers. All
+//! the values a rule matches are then still available, unchanged, at the
+//! instruction being rewritten. Lowering puts a move between most pairs of
+//! instructions, so matching also looks through moves between such registers
+//! to the instruction defining the moved value.
+//!
+//! The rewritten instruction keeps defining the same register: the root of the
+//! right-hand side is built in its place, with that register as destination,
+//! and the other instructions of the right-hand side are inserted just before
+//! it and define fresh virtual registers. Instructions that have no side
+//! effects and whose results end up unused are removed at the end.
+
+use crate::ir::types;
+use crate::machinst::{InsnIndex, MachInst, VCode, VCodeInst};
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::num::NonZeroU32;
+use peepmatic_runtime::{
+    instruction_set::InstructionSet,
+    part::{Constant, Part},
+    r#type::Type,
+    PeepholeOptimizations,
+};
+use regalloc::Function as RegallocFunction;
+use regalloc::{BlockIx, Reg, RegUsageCollector, StackmapRequestInfo, VirtualReg, Writable};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// A machine instruction that `peepmatic` rules can match and build.
+///
+/// Rules treat every register as holding a 64-bit integer.
+pub trait MachInstPeepmatic: VCodeInst {
+    /// The operators that the backend's rules are written with.
+    type Operator: 'static + Copy + Debug + Eq + Hash + Into<NonZeroU32>;
+
+    /// Get this instruction's operator, if it has one, along with the register
+    /// it defines, if any.
+    ///
+    /// The instruction's operands are pushed in order into `operands`:
+    /// immediates first, then registers.
+    fn peepmatic_operator(
+        &self,
+        operands: &mut Vec<Part<Reg>>,
+    ) -> Option<(Self::Operator, Option<Writable<Reg>>)>;
+
+    /// Does the given operator only compute its result, without accessing
+    /// memory or trapping?
+    fn peepmatic_is_pure(operator: Self::Operator) -> bool;
+
+    /// Make an instruction for `operator` with the given operands, defining
+    /// `rd` if the operator has a result.
+    ///
+    /// `root` is the instruction being rewritten, for operators that need
+    /// more than what rules describe.
+    fn peepmatic_make_inst(
+        root: &Self,
+        operator: Self::Operator,
+        rd: Option<Writable<Reg>>,
+        operands: &[Part<Reg>],
+    ) -> Self;
+}
+
+/// Apply the peephole optimizations in `peep_opts` to `vcode`, which has not
+/// been register allocated yet.
+pub fn optimize<I: MachInstPeepmatic>(
+    peep_opts: &PeepholeOptimizations<I::Operator>,
+    vcode: &mut VCode<I>,
+    stack_map_info: &mut StackmapRequestInfo,
+) {
+    let mut optimizer = peep_opts.optimizer(VCodeInstructionSet(PhantomData));
+
+    let num_insts = vcode.insns().len();
+    let mut ctx = PeepholeContext {
+        insts: vcode.insns().iter().cloned().map(Some).collect(),
+        inserted_before: vec![vec![]; num_insts],
+        pending: vec![],
+        def_counts: vec![],
+        defs: HashMap::new(),
+        vcode,
+    };
+    ctx.count_defs();
+
+    for block in ctx.vcode.blocks() {
+        // Definitions are only followed within a block.
+        ctx.defs.clear();
+        for root in ctx.vcode.block_insns(block) {
+            let root = root.get();
+            optimizer.apply_all(&mut ctx, Inst(root));
+            for new in ctx.inserted_before[root as usize].clone() {
+                ctx.record_def(new);
+            }
+            ctx.record_def(root);
+        }
+    }
+
+    ctx.remove_unused_pure_insts();
+
+    let PeepholeContext {
+        mut insts,
+        mut inserted_before,
+        vcode,
+        ..
+    } = ctx;
+    vcode.rewrite_insns(stack_map_info, |old, replacement| {
+        for new in inserted_before[old as usize].drain(..) {
+            replacement.extend(insts[new as usize].take());
+        }
+        replacement.extend(insts[old as usize].take());
+    });
+}
+
+/// An instruction that rules operate on.
+///
+/// Indices below the number of instructions in the vcode refer to those
+/// instructions; greater ones refer to instructions created by rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RegOrInst {
+    /// The instruction defining the given register.
+    Reg(Reg),
+    /// An instruction.
+    Inst(u32),
+}
+
+use RegOrInst::Inst;
+
+struct PeepholeContext<'a, I: MachInstPeepmatic> {
+    vcode: &'a mut VCode<I>,
+    /// All instructions, `None` once removed or replaced.
+    insts: Vec<Option<I>>,
+    /// Instructions created by rules, to insert before each vcode instruction.
+    inserted_before: Vec<Vec<u32>>,
+    /// Instructions created by the rule being applied, with the operator and
+    /// operands they were made with.
+    pending: Vec<(u32, I::Operator, Vec<Part<Reg>>)>,
+    /// The number of definitions of each virtual register.
+    def_counts: Vec<u32>,
+    /// The instructions defining virtual registers that can be looked through
+    /// at the current root.
+    defs: HashMap<VirtualReg, u32>,
+}
+
+/// Get the registers that `inst` uses, defines and modifies.
+fn inst_regs<I: MachInst>(inst: &I) -> (Vec<Reg>, Vec<Reg>, Vec<Reg>) {
+    // This is the only way `regalloc` offers to get at an instruction's
+    // registers outside of register allocation.
+    let mut reg_vecs = RegUsageCollector::get_empty_reg_vecs_test_framework_only(false);
+    let mut collector = RegUsageCollector::new(&mut reg_vecs);
+    inst.get_regs(&mut collector);
+    collector.get_use_def_mod_vecs_test_framework_only()
+}
+
+impl<'a, I: MachInstPeepmatic> PeepholeContext<'a, I> {
+    fn count_defs(&mut self) {
+        self.def_counts = vec![0; self.vcode.get_num_vregs()];
+        for inst in 0..self.insts.len() as u32 {
+            self.add_def_counts(inst, 1);
+        }
+    }
+
+    /// Add `delta` to the definition counts of the registers that `inst`
+    /// defines or modifies.
+    fn add_def_counts(&mut self, inst: u32, delta: i32) {
+        let inst = match &self.insts[inst as usize] {
+            Some(inst) => inst,
+            None => return,
+        };
+        let (_, defs, mods) = inst_regs(inst);
+        for reg in defs.iter().chain(&mods) {
+            if let Some(vreg) = reg.as_virtual_reg() {
+                let count = &mut self.def_counts[vreg.get_index()];
+                *count = (*count as i32 + delta) as u32;
+            }
+        }
+    }
+
+    fn is_single_def(&self, reg: Reg) -> bool {
+        match reg.as_virtual_reg() {
+            Some(vreg) => self.def_counts[vreg.get_index()] == 1,
+            None => false,
+        }
+    }
+
+    /// Let later roots in the block look through `inst`'s definition, if that
+    /// is safe.
+    fn record_def(&mut self, inst: u32) {
+        let i = match &self.insts[inst as usize] {
+            Some(i) => i,
+            None => return,
+        };
+        if let Some((dst, src)) = i.is_move() {
+            let dst = dst.to_reg();
+            if self.is_single_def(dst) && self.is_single_def(src) {
+                if let Some(&def) = self.defs.get(&src.to_virtual_reg()) {
+                    self.defs.insert(dst.to_virtual_reg(), def);
+                    return;
+                }
+            }
+        }
+        let rd = match i.peepmatic_operator(&mut vec![]) {
+            Some((op, Some(rd))) if I::peepmatic_is_pure(op) => rd.to_reg(),
+            _ => return,
+        };
+        let (uses, _, mods) = inst_regs(self.insts[inst as usize].as_ref().unwrap());
+        if self.is_single_def(rd) && mods.is_empty() && uses.iter().all(|&r| self.is_single_def(r))
+        {
+            self.defs.insert(rd.to_virtual_reg(), inst);
+        }
+    }
+
+    fn resolve(&self, inst: RegOrInst) -> Option<u32> {
+        match inst {
+            Inst(i) => Some(i),
+            RegOrInst::Reg(r) => self.defs.get(&r.as_virtual_reg()?).copied(),
+        }
+    }
+
+    fn def_reg(&self, inst: RegOrInst) -> Reg {
+        match inst {
+            RegOrInst::Reg(r) => r,
+            Inst(i) => {
+                let inst = self.insts[i as usize].as_ref().unwrap();
+                let (_, rd) = inst.peepmatic_operator(&mut vec![]).unwrap();
+                rd.unwrap().to_reg()
+            }
+        }
+    }
+
+    fn to_reg_part(&self, part: Part<RegOrInst>) -> Part<Reg> {
+        match part {
+            Part::Instruction(i) => Part::Instruction(self.def_reg(i)),
+            Part::Constant(c) => Part::Constant(c),
+            Part::ConditionCode(cc) => Part::ConditionCode(cc),
+        }
+    }
+
+    fn make_inst(
+        &mut self,
+        root: RegOrInst,
+        operator: I::Operator,
+        operands: Vec<Part<Reg>>,
+    ) -> RegOrInst {
+        let (rc, ty) = word_class_and_type::<I>();
+        let rd = self.vcode.alloc_vreg(rc, ty);
+
+        let root = self.resolve(root).unwrap();
+        let root = self.insts[root as usize].as_ref().unwrap();
+        let inst = I::peepmatic_make_inst(root, operator, Some(rd), &operands);
+
+        let new = self.insts.len() as u32;
+        self.insts.push(Some(inst));
+        self.pending.push((new, operator, operands));
+        Inst(new)
+    }
+
+    /// Remove instructions without side effects whose results are unused.
+    fn remove_unused_pure_insts(&mut self) {
+        let mut use_counts = vec![0u32; self.def_counts.len()];
+        for inst in self.insts.iter().flatten() {
+            let (uses, _, mods) = inst_regs(inst);
+            for reg in uses.iter().chain(&mods) {
+                if let Some(vreg) = reg.as_virtual_reg() {
+                    use_counts[vreg.get_index()] += 1;
+                }
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for inst in (0..self.insts.len() as u32).rev() {
+                let rd = match &self.insts[inst as usize] {
+                    Some(i) => match i.peepmatic_operator(&mut vec![]) {
+                        Some((op, Some(rd))) if I::peepmatic_is_pure(op) => rd.to_reg(),
+                        _ => continue,
+                    },
+                    None => continue,
+                };
+                if !self.is_single_def(rd) || use_counts[rd.get_index()] != 0 {
+                    continue;
+                }
+                let (uses, _, _) = inst_regs(self.insts[inst as usize].as_ref().unwrap());
+                for reg in uses {
+                    if let Some(vreg) = reg.as_virtual_reg() {
+                        use_counts[vreg.get_index()] -= 1;
+                    }
+                }
+                self.insts[inst as usize] = None;
+                changed = true;
+            }
+        }
+    }
+}
+
+/// The register class and type of the fresh registers that rules define.
+fn word_class_and_type<I: MachInst>() -> (regalloc::RegClass, crate::ir::Type) {
+    let (classes, _) = I::rc_for_type(types::I64).unwrap();
+    (classes[0], types::I64)
+}
+
+struct VCodeInstructionSet<I>(PhantomData<I>);
+
+unsafe impl<'a, I: MachInstPeepmatic + 'a> InstructionSet<'a> for VCodeInstructionSet<I> {
+    type Context = PeepholeContext<'a, I>;
+
+    type Operator = I::Operator;
+
+    type Instruction = RegOrInst;
+
+    fn replace_instruction(
+        &self,
+        ctx: &mut PeepholeContext<'a, I>,
+        old: RegOrInst,
+        new: Part<RegOrInst>,
+    ) -> RegOrInst {
+        log::trace!("replace {:?} with {:?}", old, new);
+
+        let root = ctx.resolve(old).unwrap();
+        let (_, rd) = ctx.insts[root as usize]
+            .as_ref()
+            .unwrap()
+            .peepmatic_operator(&mut vec![])
+            .unwrap();
+        let mut pending = std::mem::replace(&mut ctx.pending, vec![]);
+        let mut inserted: Vec<u32> = vec![];
+        ctx.add_def_counts(root, -1);
+
+        let replacement = match new {
+            // The right-hand side's root was just made: make it again, defining
+            // the old root's register, and drop the first version.
+            Part::Instruction(Inst(new)) if pending.iter().any(|p| p.0 == new) => {
+                let i = pending.iter().position(|p| p.0 == new).unwrap();
+                let (_, operator, operands) = pending.remove(i);
+                ctx.insts[new as usize] = None;
+                let root_inst = ctx.insts[root as usize].as_ref().unwrap();
+                I::peepmatic_make_inst(root_inst, operator, rd, &operands)
+            }
+            Part::Instruction(value) => {
+                let from = ctx.def_reg(value);
+                I::gen_move(rd.unwrap(), from, types::I64)
+            }
+            Part::Constant(Constant::Int(value, _)) => {
+                let vcode = &mut *ctx.vcode;
+                let mut insts = I::gen_constant(rd.unwrap(), value, types::I64, |rc, ty| {
+                    vcode.alloc_vreg(rc, ty)
+                });
+                let last = insts.pop().unwrap();
+                for inst in insts {
+                    let new = ctx.insts.len() as u32;
+                    ctx.insts.push(Some(inst));
+                    inserted.push(new);
+                }
+                last
+            }
+            Part::Constant(Constant::Bool(..)) | Part::ConditionCode(_) => {
+                unreachable!("vcode rules only produce integers")
+            }
+        };
+
+        ctx.insts[root as usize] = Some(replacement);
+        ctx.def_counts.resize(ctx.vcode.get_num_vregs(), 0);
+        ctx.add_def_counts(root, 1);
+        inserted.extend(pending.into_iter().map(|(new, _, _)| new));
+        for new in inserted {
+            ctx.add_def_counts(new, 1);
+            ctx.inserted_before[root as usize].push(new);
+        }
+        Inst(root)
+    }
+
+    fn operator<E>(
+        &self,
+        ctx: &mut PeepholeContext<'a, I>,
+        inst: RegOrInst,
+        operands: &mut E,
+    ) -> Option<I::Operator>
+    where
+        E: Extend<Part<RegOrInst>>,
+    {
+        let inst = ctx.resolve(inst)?;
+        let mut inst_operands = vec![];
+        let (operator, _) = ctx.insts[inst as usize]
+            .as_ref()?
+            .peepmatic_operator(&mut inst_operands)?;
+        operands.extend(inst_operands.into_iter().map(|part| match part {
+            Part::Instruction(reg) => Part::Instruction(RegOrInst::Reg(reg)),
+            Part::Constant(c) => Part::Constant(c),
+            Part::ConditionCode(cc) => Part::ConditionCode(cc),
+        }));
+        Some(operator)
+    }
+
+    fn make_inst_1(
+        &self,
+        ctx: &mut PeepholeContext<'a, I>,
+        root: RegOrInst,
+        operator: I::Operator,
+        _: Type,
+        a: Part<RegOrInst>,
+    ) -> RegOrInst {
+        let operands = vec![ctx.to_reg_part(a)];
+        ctx.make_inst(root, operator, operands)
+    }
+
+    fn make_inst_2(
+        &self,
+        ctx: &mut PeepholeContext<'a, I>,
+        root: RegOrInst,
+        operator: I::Operator,
+        _: Type,
+        a: Part<RegOrInst>,
+        b: Part<RegOrInst>,
+    ) -> RegOrInst {
+        let operands = vec![ctx.to_reg_part(a), ctx.to_reg_part(b)];
+        ctx.make_inst(root, operator, operands)
+    }
+
+    fn make_inst_3(
+        &self,
+        ctx: &mut PeepholeContext<'a, I>,
+        root: RegOrInst,
+        operator: I::Operator,
+        _: Type,
+        a: Part<RegOrInst>,
+        b: Part<RegOrInst>,
+        c: Part<RegOrInst>,
+    ) -> RegOrInst {
+        let operands = vec![ctx.to_reg_part(a), ctx.to_reg_part(b), ctx.to_reg_part(c)];
+        ctx.make_inst(root, operator, operands)
+    }
+
+    fn instruction_to_constant(
+        &self,
+        _ctx: &mut PeepholeContext<'a, I>,
+        _inst: RegOrInst,
+    ) -> Option<Constant> {
+        None
+    }
+
+    fn instruction_result_bit_width(
+        &self,
+        _ctx: &mut PeepholeContext<'a, I>,
+        _inst: RegOrInst,
+    ) -> u8 {
+        64
+    }
+
+    fn native_word_size_in_bits(&self, _ctx: &mut PeepholeContext<'a, I>) -> u8 {
+        64
+    }
+}
@@ -45,6 +45,21 @@ pub type BlockIndex = u32;
 /// Range of an instructions in VCode.
 pub type InsnRange = core::ops::Range<InsnIndex>;
 
+/// Statistics about the lowering and register allocation of a function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VCodeStats {
+    /// Number of instructions produced by lowering and peephole optimization,
+    /// before register allocation.
+    pub lowered_insts: u32,
+    /// Number of spills inserted by the register allocator.
+    pub spills: u32,
+    /// Number of reloads inserted by the register allocator.
+    pub reloads: u32,
+    /// Number of moves inserted by the register allocator, not counting the
+    /// ones elided afterwards as redundant.
+    pub moves: u32,
+}
+
 /// VCodeInst wraps all requirements for a MachInst to be in VCode: it must be
 /// a `MachInst` and it must be able to emit itself at least to a `SizeCodeSink`.
 pub trait VCodeInst: MachInst + MachInstEmit {}
@@ -107,6 +122,10 @@ pub struct VCode<I: VCodeInst> {
     /// post-regalloc.
     safepoint_slots: Vec<Vec<SpillSlot>>,
 
+    /// User stack map entries of safepoint instructions, sorted by instruction
+    /// index. These are turned into stack offsets at emission.
+    user_stack_maps: Vec<(InsnIndex, Vec<ir::UserStackMapEntry>)>,
+
     /// Ranges for prologue and epilogue instructions.
     prologue_epilogue_ranges: Option<(InsnRange, Box<[InsnRange]>)>,
 
@@ -115,6 +134,9 @@ pub struct VCode<I: VCodeInst> {
 
     /// Constants.
     constants: VCodeConstants,
+
+    /// Statistics. Filled in post-regalloc.
+    stats: VCodeStats,
 }
 
 /// A builder for a VCode function body. This builder is designed for the
@@ -251,6 +273,17 @@ impl<I: VCodeInst> VCodeBuilder<I> {
         }
     }
 
+    /// Attach the user stack map `entries` to the last pushed instruction,
+    /// which must be a safepoint.
+    pub fn add_user_stack_map(&mut self, entries: &[ir::UserStackMapEntry]) {
+        let insn = (self.vcode.insts.len() - 1) as InsnIndex;
+        debug_assert_eq!(
+            self.stack_map_info.safepoint_insns.last().map(|i| i.get()),
+            Some(insn)
+        );
+        self.vcode.user_stack_maps.push((insn, entries.to_vec()));
+    }
+
     /// Get the current source location.
     pub fn get_srcloc(&self) -> SourceLoc {
         self.cur_srcloc
@@ -284,6 +317,24 @@ fn is_redundant_move<I: VCodeInst>(insn: &I) -> bool {
     }
 }
 
+/// Count `insn`, which the register allocator inserted, as a move, a spill (a
+/// store that defines no register) or a reload.
+fn count_inserted_insn<I: VCodeInst>(insn: &I, stats: &mut VCodeStats) {
+    if insn.is_move().is_some() {
+        stats.moves += 1;
+        return;
+    }
+    let mut reg_vecs = RegUsageCollector::get_empty_reg_vecs_test_framework_only(false);
+    let mut collector = RegUsageCollector::new(&mut reg_vecs);
+    insn.get_regs(&mut collector);
+    let (uses, defs, _) = collector.get_use_def_mod_vecs_test_framework_only();
+    if !defs.is_empty() {
+        stats.reloads += 1;
+    } else if !uses.is_empty() {
+        stats.spills += 1;
+    }
+}
+
 /// Is this type a reference type?
 fn is_reftype(ty: Type) -> bool {
     ty == types::R64 || ty == types::R32
@@ -313,9 +364,11 @@ impl<I: VCodeInst> VCode<I> {
             emit_info,
             safepoint_insns: vec![],
             safepoint_slots: vec![],
+            user_stack_maps: vec![],
             prologue_epilogue_ranges: None,
             insts_layout: RefCell::new((vec![], 0)),
             constants,
+            stats: VCodeStats::default(),
         }
     }
 
@@ -345,6 +398,11 @@ impl<I: VCodeInst> VCode<I> {
         self.block_ranges.len()
     }
 
+    /// Statistics about the lowering and register allocation of this function.
+    pub fn stats(&self) -> VCodeStats {
+        self.stats
+    }
+
     /// Stack frame size for the full function's body.
     pub fn frame_size(&self) -> u32 {
         self.abi.frame_size()
@@ -361,6 +419,59 @@ impl<I: VCodeInst> VCode<I> {
         &self.block_succs[start..end]
     }
 
+    /// Allocate a new virtual register after lowering, for passes that rewrite
+    /// the vcode before register allocation.
+    pub fn alloc_vreg(&mut self, rc: RegClass, ty: Type) -> Writable<Reg> {
+        debug_assert!(!is_reftype(ty));
+        let vreg = Reg::new_virtual(rc, self.vreg_types.len() as u32);
+        self.vreg_types.push(ty);
+        Writable::from_reg(vreg)
+    }
+
+    /// Rewrite the instruction sequence before register allocation.
+    ///
+    /// `f` is called once per instruction, in order, and pushes the
+    /// instructions that take its place onto the given vector. New instructions
+    /// inherit the source location of the instruction they replace. A
+    /// safepoint must be pushed last in its own replacement, so that the stack
+    /// map request can keep pointing at it.
+    pub fn rewrite_insns<F>(&mut self, stack_map_info: &mut StackmapRequestInfo, mut f: F)
+    where
+        F: FnMut(InsnIndex, &mut Vec<I>),
+    {
+        let old_len = self.insts.len();
+        let old_srclocs = std::mem::replace(&mut self.srclocs, Vec::with_capacity(old_len));
+        self.insts.clear();
+
+        // For each old instruction, the new index just past its replacement.
+        let mut new_ends = Vec::with_capacity(old_len);
+        let mut replacement = vec![];
+        for range in self.block_ranges.iter_mut() {
+            let (start, end) = *range;
+            let new_start = self.insts.len() as InsnIndex;
+            for old in start..end {
+                f(old, &mut replacement);
+                for inst in replacement.drain(..) {
+                    self.insts.push(inst);
+                    self.srclocs.push(old_srclocs[old as usize]);
+                }
+                new_ends.push(self.insts.len() as InsnIndex);
+            }
+            *range = (new_start, self.insts.len() as InsnIndex);
+        }
+
+        for insn in stack_map_info.safepoint_insns.iter_mut() {
+            let old = insn.get() as usize;
+            let new_start = if old == 0 { 0 } else { new_ends[old - 1] };
+            let new_end = new_ends[old];
+            debug_assert!(new_end > new_start, "safepoints must not be removed");
+            *insn = InstIx::new(new_end - 1);
+        }
+        for (insn, _) in self.user_stack_maps.iter_mut() {
+            *insn = new_ends[*insn as usize] - 1;
+        }
+    }
+
     /// Take the results of register allocation, with a sequence of
     /// instructions including spliced fill/reload/move instructions, and replace
     /// the VCode with them.
@@ -371,11 +482,17 @@ impl<I: VCodeInst> VCode<I> {
         self.abi
             .set_clobbered(result.clobbered_registers.map(|r| Writable::from_reg(*r)));
 
+        let mut stats = VCodeStats {
+            lowered_insts: self.insts.len() as u32,
+            ..VCodeStats::default()
+        };
+
         let mut final_insns = vec![];
         let mut final_block_ranges = vec![(0, 0); self.num_blocks()];
         let mut final_srclocs = vec![];
         let mut final_safepoint_insns = vec![];
         let mut safept_idx = 0;
+        let mut final_user_stack_maps = vec![];
 
         let mut prologue_start = None;
         let mut prologue_end = None;
@@ -416,6 +533,7 @@ impl<I: VCodeInst> VCode<I> {
                 // an inserted load/spill/move).
                 let orig_iix = result.orig_insn_map[InstIx::new(i as u32)];
                 let srcloc = if orig_iix.is_invalid() {
+                    count_inserted_insn(insn, &mut stats);
                     SourceLoc::default()
                 } else {
                     self.srclocs[orig_iix.get() as usize]
@@ -445,6 +563,18 @@ impl<I: VCodeInst> VCode<I> {
                     final_safepoint_insns.push(idx as InsnIndex);
                     safept_idx += 1;
                 }
+
+                // Carry over any user stack map of the original instruction.
+                if !orig_iix.is_invalid() {
+                    if let Ok(idx) = self
+                        .user_stack_maps
+                        .binary_search_by_key(&orig_iix.get(), |&(iix, _)| iix)
+                    {
+                        let entries = std::mem::take(&mut self.user_stack_maps[idx].1);
+                        let idx = final_insns.len() - 1;
+                        final_user_stack_maps.push((idx as InsnIndex, entries));
+                    }
+                }
             }
 
             let final_end = final_insns.len() as InsnIndex;
@@ -457,6 +587,8 @@ impl<I: VCodeInst> VCode<I> {
         self.srclocs = final_srclocs;
         self.block_ranges = final_block_ranges;
         self.safepoint_insns = final_safepoint_insns;
+        self.user_stack_maps = final_user_stack_maps;
+        self.stats = stats;
 
         // Save safepoint slot-lists. These will be passed to the `EmitState`
         // for the machine backend during emission so that it can do
@@ -469,6 +601,16 @@ impl<I: VCodeInst> VCode<I> {
         ));
     }
 
+    /// The order in which the blocks are emitted: the lowered order, except for the cold blocks,
+    /// which come last.
+    fn emission_order(&self) -> Vec<BlockIndex> {
+        let blocks = 0..self.num_blocks() as BlockIndex;
+        let (mut order, cold_blocks): (Vec<BlockIndex>, Vec<BlockIndex>) =
+            blocks.partition(|&block| !self.block_order.is_cold(block));
+        order.extend(cold_blocks);
+        order
+    }
+
     /// Emit the instructions to a `MachBuffer`, containing fixed-up code and external
     /// reloc/trap/etc. records ready for use.
     pub fn emit(&self) -> MachBuffer<I>
@@ -486,10 +628,10 @@ impl<I: VCodeInst> VCode<I> {
 
         let mut insts_layout = vec![0; self.insts.len()];
 
-        let mut safepoint_idx = 0;
+        let final_order = self.emission_order();
+
         let mut cur_srcloc = None;
-        for block in 0..self.num_blocks() {
-            let block = block as BlockIndex;
+        for (i, &block) in final_order.iter().enumerate() {
             let new_offset = I::align_basic_block(buffer.cur_offset());
             while new_offset > buffer.cur_offset() {
                 // Pad with NOPs up to the aligned block offset.
@@ -511,9 +653,8 @@ impl<I: VCodeInst> VCode<I> {
                 }
                 state.pre_sourceloc(cur_srcloc.unwrap_or(SourceLoc::default()));
 
-                if safepoint_idx < self.safepoint_insns.len()
-                    && self.safepoint_insns[safepoint_idx] == iix
-                {
+                // The blocks aren't necessarily emitted in order, so look the safepoint up.
+                if let Ok(safepoint_idx) = self.safepoint_insns.binary_search(&iix) {
                     if self.safepoint_slots[safepoint_idx].len() > 0 {
                         let stack_map = self.abi.spillslots_to_stack_map(
                             &self.safepoint_slots[safepoint_idx][..],
@@ -521,11 +662,25 @@ impl<I: VCodeInst> VCode<I> {
                         );
                         state.pre_safepoint(stack_map);
                     }
-                    safepoint_idx += 1;
                 }
+                let user_stack_map = self
+                    .user_stack_maps
+                    .binary_search_by_key(&iix, |&(iix, _)| iix)
+                    .ok()
+                    .map(|idx| {
+                        self.abi.user_stack_map_entries_to_stack_map(
+                            &self.user_stack_maps[idx].1,
+                            &state,
+                        )
+                    });
 
                 self.insts[iix as usize].emit(&mut buffer, &self.emit_info, &mut state);
 
+                // A user stack map applies at the return address of its call.
+                if let Some(stack_map) = user_stack_map {
+                    buffer.add_user_stack_map(buffer.cur_offset(), stack_map);
+                }
+
                 insts_layout[iix as usize] = buffer.cur_offset();
             }
 
@@ -536,8 +691,7 @@ impl<I: VCodeInst> VCode<I> {
 
             // Do we need an island? Get the worst-case size of the next BB and see if, having
             // emitted that many bytes, we will be beyond the deadline.
-            if block < (self.num_blocks() - 1) as BlockIndex {
-                let next_block = block + 1;
+            if let Some(&next_block) = final_order.get(i + 1) {
                 let next_block_range = self.block_ranges[next_block as usize];
                 let next_block_size = next_block_range.1 - next_block_range.0;
                 let worst_case_next_bb = I::worst_case_size() * next_block_size;
@@ -564,12 +718,15 @@ impl<I: VCodeInst> VCode<I> {
     ) -> crate::result::CodegenResult<Option<crate::isa::unwind::input::UnwindInfo<Reg>>> {
         let layout = &self.insts_layout.borrow();
         let (prologue, epilogues) = self.prologue_epilogue_ranges.as_ref().unwrap();
+        // Epilogues in cold blocks are emitted after the other ones, so sort them by offset.
+        let mut epilogues = epilogues.to_vec();
+        epilogues.sort_by_key(|epilogue| layout.0[epilogue.start as usize]);
         let context = UnwindInfoContext {
             insts: &self.insts,
             insts_layout: &layout.0,
             len: layout.1,
             prologue: prologue.clone(),
-            epilogues,
+            epilogues: &epilogues,
         };
         I::UnwindInfo::create_unwind_info(context)
     }
@@ -717,31 +874,29 @@ impl<I: VCodeInst> PrettyPrint for VCode<I> {
         write!(&mut s, "VCode_ShowWithRRU {{{{\n").unwrap();
         write!(&mut s, "  Entry block: {}\n", self.entry).unwrap();
 
+        // Show the blocks in the order they are emitted in.
         let mut state = Default::default();
-        let mut safepoint_idx = 0;
-        for i in 0..self.num_blocks() {
-            let block = i as BlockIndex;
-
+        for block in self.emission_order() {
             write!(&mut s, "Block {}:\n", block).unwrap();
             if let Some(bb) = self.bindex_to_bb(block) {
                 write!(&mut s, "  (original IR block: {})\n", bb).unwrap();
             }
+            if self.block_order.is_cold(block) {
+                write!(&mut s, "  (cold)\n").unwrap();
+            }
             for succ in self.succs(block) {
                 write!(&mut s, "  (successor: Block {})\n", succ.get()).unwrap();
             }
             let (start, end) = self.block_ranges[block as usize];
             write!(&mut s, "  (instruction range: {} .. {})\n", start, end).unwrap();
             for inst in start..end {
-                if safepoint_idx < self.safepoint_insns.len()
-                    && self.safepoint_insns[safepoint_idx] == inst
-                {
+                if let Ok(safepoint_idx) = self.safepoint_insns.binary_search(&inst) {
                     write!(
                         &mut s,
                         "      (safepoint: slots {:?} with EmitState {:?})\n",
                         self.safepoint_slots[safepoint_idx], state,
                     )
                     .unwrap();
-                    safepoint_idx += 1;
                 }
                 write!(
                     &mut s,
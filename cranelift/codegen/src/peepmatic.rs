@@ -9,6 +9,8 @@ use crate::ir::{
     types, InstBuilder,
 };
 use crate::isa::TargetIsa;
+use core::fmt::Debug;
+use core::hash::Hash;
 use cranelift_codegen_shared::condcodes::IntCC;
 use peepmatic_runtime::{
     cc::ConditionCode,
@@ -17,6 +19,7 @@ use peepmatic_runtime::{
     r#type::{BitWidth, Kind, Type},
     PeepholeOptimizations, PeepholeOptimizer,
 };
+use serde::de::DeserializeOwned;
 use std::borrow::Cow;
 use std::boxed::Box;
 use std::convert::{TryFrom, TryInto};
@@ -289,45 +292,55 @@ pub(crate) fn preopt<'a, 'b>(
         SERIALIZED.into()
     }
 
-    // Once initialized, this must never be re-assigned. The initialized value
-    // is semantically "static data" and is intentionally leaked for the whole
-    // program's lifetime.
     static DESERIALIZED: AtomicPtr<PeepholeOptimizations<Opcode>> = AtomicPtr::new(ptr::null_mut());
+    deserialize_once(&DESERIALIZED, get_serialized).optimizer(isa)
+}
 
-    // If `DESERIALIZED` has already been initialized, then just use it.
-    let ptr = DESERIALIZED.load(Ordering::SeqCst);
+/// Get the peephole optimizations cached in `deserialized`, deserializing the
+/// result of `get_serialized` into it on first use.
+pub(crate) fn deserialize_once<TOperator>(
+    deserialized: &'static AtomicPtr<PeepholeOptimizations<TOperator>>,
+    get_serialized: fn() -> Cow<'static, [u8]>,
+) -> &'static PeepholeOptimizations<TOperator>
+where
+    TOperator: 'static + Copy + Debug + Eq + Hash + DeserializeOwned,
+{
+    // Once initialized, `deserialized` must never be re-assigned. The
+    // initialized value is semantically "static data" and is intentionally
+    // leaked for the whole program's lifetime.
+
+    // If `deserialized` has already been initialized, then just use it.
+    let ptr = deserialized.load(Ordering::SeqCst);
     if let Some(peep_opts) = unsafe { ptr.as_ref() } {
-        return peep_opts.optimizer(isa);
+        return peep_opts;
     }
 
-    // Otherwise, if `DESERIALIZED` hasn't been initialized, then we need to
+    // Otherwise, if `deserialized` hasn't been initialized, then we need to
     // deserialize the peephole optimizations and initialize it. However,
     // another thread could be doing the same thing concurrently, so there is a
-    // race to see who initializes `DESERIALIZED` first, and we need to be
+    // race to see who initializes `deserialized` first, and we need to be
     // prepared to both win or lose that race.
     let peep_opts = PeepholeOptimizations::deserialize(&get_serialized())
-        .expect("should always be able to deserialize `preopt.serialized`");
+        .expect("should always be able to deserialize peephole optimizations");
     let peep_opts = Box::into_raw(Box::new(peep_opts));
 
-    // Only update `DESERIALIZE` if it is still null, attempting to perform the
-    // one-time transition from null -> non-null.
-    if DESERIALIZED
+    // Only update `deserialized` if it is still null, attempting to perform
+    // the one-time transition from null -> non-null.
+    if deserialized
         .compare_and_swap(ptr::null_mut(), peep_opts, Ordering::SeqCst)
         .is_null()
     {
-        // We won the race to initialize `DESERIALIZED`.
-        debug_assert_eq!(DESERIALIZED.load(Ordering::SeqCst), peep_opts);
-        let peep_opts = unsafe { &*peep_opts };
-        return peep_opts.optimizer(isa);
+        // We won the race to initialize `deserialized`.
+        debug_assert_eq!(deserialized.load(Ordering::SeqCst), peep_opts);
+        return unsafe { &*peep_opts };
     }
 
-    // We lost the race to initialize `DESERIALIZED`. Drop our no-longer-needed
+    // We lost the race to initialize `deserialized`. Drop our no-longer-needed
     // instance of `peep_opts` and get the pointer to the instance that won the
     // race.
     let _ = unsafe { Box::from_raw(peep_opts) };
-    let peep_opts = DESERIALIZED.load(Ordering::SeqCst);
-    let peep_opts = unsafe { peep_opts.as_ref().unwrap() };
-    peep_opts.optimizer(isa)
+    let peep_opts = deserialized.load(Ordering::SeqCst);
+    unsafe { peep_opts.as_ref().unwrap() }
 }
 
 /// Either a `Value` or an `Inst`.
@@ -403,6 +403,8 @@ enable_probestack = true
 probestack_func_adjusts_sp = false
 enable_jump_tables = true
 enable_heap_access_spectre_mitigation = true
+enable_table_access_spectre_mitigation = true
+enable_constant_time = false
 "#
         );
         assert_eq!(f.opt_level(), super::OptLevel::None);
@@ -0,0 +1,164 @@
+//! Compilation statistics.
+//!
+//! After compiling a function, `Context::stats` holds a `CompileStats` describing the
+//! compilation: the time spent in each pass, the size of the generated code and how much work
+//! register allocation had to do. Statistics of several functions can be summed up with
+//! `CompileStats::add`.
+
+use crate::timing::PassTimes;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::time::Duration;
+
+#[cfg(feature = "enable-serde")]
+use serde::{Deserialize, Serialize};
+
+/// The time spent in a single compilation pass.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct PassStats {
+    /// The snake_case name of the pass, as defined in the `timing` module.
+    pub name: String,
+
+    /// Total time spent running this pass, including its child passes.
+    pub total: Duration,
+
+    /// Time spent running this pass, excluding its child passes.
+    pub self_time: Duration,
+}
+
+/// Statistics about the compilation of one function, or the sum over several functions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+pub struct CompileStats {
+    /// Number of compiled functions.
+    pub functions: u32,
+
+    /// Time spent in each compilation pass that ran. Empty when pass timing is unavailable,
+    /// as in `no_std` builds.
+    pub pass_times: Vec<PassStats>,
+
+    /// Size of the generated code, including jump tables and read-only data, in bytes.
+    pub code_size: u32,
+
+    /// Number of VCode instructions produced by lowering. Always zero for the legacy backends,
+    /// which don't use VCode.
+    pub vcode_insts: u32,
+
+    /// Number of spills inserted by the register allocator.
+    pub spills: u32,
+
+    /// Number of reloads inserted by the register allocator.
+    pub reloads: u32,
+
+    /// Number of register-to-register moves inserted by the register allocator.
+    pub regalloc_moves: u32,
+}
+
+impl CompileStats {
+    /// Statistics for no function, with the pass timings of `times`.
+    pub fn from_pass_times(times: &PassTimes) -> Self {
+        Self {
+            pass_times: times
+                .iter()
+                .map(|timing| PassStats {
+                    name: timing.name.to_string(),
+                    total: timing.total,
+                    self_time: timing.self_time,
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Add the statistics of `other` to these.
+    pub fn add(&mut self, other: &Self) {
+        self.functions += other.functions;
+        for pass in &other.pass_times {
+            match self.pass_times.iter_mut().find(|p| p.name == pass.name) {
+                Some(p) => {
+                    p.total += pass.total;
+                    p.self_time += pass.self_time;
+                }
+                None => self.pass_times.push(pass.clone()),
+            }
+        }
+        self.code_size += other.code_size;
+        self.vcode_insts += other.vcode_insts;
+        self.spills += other.spills;
+        self.reloads += other.reloads;
+        self.regalloc_moves += other.regalloc_moves;
+    }
+
+    /// Render these statistics as a JSON object, with pass times in nanoseconds.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"functions\":{},\"code_size\":{},\"vcode_insts\":{},\"spills\":{},\
+             \"reloads\":{},\"regalloc_moves\":{},\"pass_times\":[",
+            self.functions,
+            self.code_size,
+            self.vcode_insts,
+            self.spills,
+            self.reloads,
+            self.regalloc_moves
+        )
+        .unwrap();
+        for (i, pass) in self.pass_times.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"name\":\"{}\",\"total_ns\":{},\"self_ns\":{}}}",
+                pass.name,
+                pass.total.as_nanos(),
+                pass.self_time.as_nanos()
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn add_and_json() {
+        let mut stats = CompileStats {
+            functions: 1,
+            pass_times: vec![PassStats {
+                name: "regalloc".to_string(),
+                total: Duration::from_nanos(300),
+                self_time: Duration::from_nanos(200),
+            }],
+            code_size: 16,
+            vcode_insts: 5,
+            spills: 1,
+            reloads: 2,
+            regalloc_moves: 3,
+        };
+        let other = stats.clone();
+        stats.add(&other);
+        assert_eq!(stats.functions, 2);
+        assert_eq!(stats.pass_times.len(), 1);
+        assert_eq!(stats.pass_times[0].total, Duration::from_nanos(600));
+        assert_eq!(
+            stats.to_json(),
+            "{\"functions\":2,\"code_size\":32,\"vcode_insts\":10,\"spills\":2,\
+             \"reloads\":4,\"regalloc_moves\":6,\"pass_times\":\
+             [{\"name\":\"regalloc\",\"total_ns\":600,\"self_ns\":400}]}"
+        );
+        assert_eq!(
+            CompileStats::default().to_json(),
+            "{\"functions\":0,\"code_size\":0,\"vcode_insts\":0,\"spills\":0,\
+             \"reloads\":0,\"regalloc_moves\":0,\"pass_times\":[]}"
+        );
+    }
+}
@@ -3,9 +3,26 @@
 //! This modules provides facilities for timing the execution of individual compilation passes.
 
 use core::fmt;
+use core::time::Duration;
 
 pub use self::details::{add_to_current, take_current, PassTimes, TimingToken};
 
+/// The time spent in a single compilation pass, as reported by `PassTimes::iter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassTiming {
+    /// The snake_case name of the pass, such as `regalloc`.
+    pub name: &'static str,
+
+    /// A plain text description of the pass.
+    pub description: &'static str,
+
+    /// Total time spent running this pass, including its child passes.
+    pub total: Duration,
+
+    /// Time spent running this pass, excluding its child passes.
+    pub self_time: Duration,
+}
+
 // Each pass that can be timed is predefined with the `define_passes!` macro. Each pass has a
 // snake_case name and a plain text description used when printing out the timing report.
 //
@@ -13,10 +30,11 @@ pub use self::details::{add_to_current, take_current, PassTimes, TimingToken};
 //
 // - A C-style enum containing all the pass names and a `None` variant.
 // - A usize constant with the number of defined passes.
+// - A const array of pass names.
 // - A const array of pass descriptions.
 // - A public function per pass used to start the timing of that pass.
 macro_rules! define_passes {
-    { $enum:ident, $num_passes:ident, $descriptions:ident;
+    { $enum:ident, $num_passes:ident, $names:ident, $descriptions:ident;
       $($pass:ident: $desc:expr,)+
     } => {
         #[allow(non_camel_case_types)]
@@ -25,6 +43,8 @@ macro_rules! define_passes {
 
         const $num_passes: usize = $enum::None as usize;
 
+        const $names: [&str; $num_passes] = [ $(stringify!($pass)),+ ];
+
         const $descriptions: [&str; $num_passes] = [ $($desc),+ ];
 
         $(
@@ -38,7 +58,7 @@ macro_rules! define_passes {
 
 // Pass definitions.
 define_passes! {
-    Pass, NUM_PASSES, DESCRIPTIONS;
+    Pass, NUM_PASSES, NAMES, DESCRIPTIONS;
 
     process_file: "Processing test file",
     parse_text: "Parsing textual Cranelift IR",
@@ -61,10 +81,12 @@ define_passes! {
     legalize: "Legalization",
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
+    alias_analysis: "Alias analysis and redundant-load elimination",
     unreachable_code: "Remove unreachable blocks",
     remove_constant_phis: "Remove constant phi-nodes",
 
     vcode_lower: "VCode lowering",
+    vcode_peepmatic: "VCode peephole optimization",
     vcode_post_ra: "VCode post-register allocation finalization",
     vcode_emit: "VCode emission",
     vcode_emit_finish: "VCode emission finalization",
@@ -107,7 +129,7 @@ impl fmt::Display for Pass {
 /// `TimingToken` and `PassTimes` types and `take_current`, `add_to_current`, and `start_pass` funcs
 #[cfg(feature = "std")]
 mod details {
-    use super::{Pass, DESCRIPTIONS, NUM_PASSES};
+    use super::{Pass, PassTiming, DESCRIPTIONS, NAMES, NUM_PASSES};
     use log::debug;
     use std::cell::{Cell, RefCell};
     use std::fmt;
@@ -141,6 +163,7 @@ mod details {
     }
 
     /// Accumulated timing for all passes.
+    #[derive(Clone)]
     pub struct PassTimes {
         pass: [PassTime; NUM_PASSES],
     }
@@ -153,6 +176,37 @@ mod details {
         }
     }
 
+    impl PassTimes {
+        /// Iterate over the timings of the passes that have run, in the order the passes are
+        /// defined.
+        pub fn iter(&self) -> impl Iterator<Item = PassTiming> + '_ {
+            self.pass
+                .iter()
+                .enumerate()
+                .filter(|(_, time)| time.total != Duration::default())
+                .map(|(idx, time)| PassTiming {
+                    name: NAMES[idx],
+                    description: DESCRIPTIONS[idx],
+                    total: time.total,
+                    self_time: time.total.checked_sub(time.child).unwrap_or_default(),
+                })
+        }
+
+        /// Add the timings of `other` to these.
+        pub fn add(&mut self, other: &Self) {
+            for (a, b) in self.pass.iter_mut().zip(&other.pass[..]) {
+                a.total += b.total;
+                a.child += b.child;
+            }
+        }
+    }
+
+    impl fmt::Debug for PassTimes {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_list().entries(self.iter()).finish()
+        }
+    }
+
     impl fmt::Display for PassTimes {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             writeln!(f, "======== ========  ==================================")?;
@@ -225,29 +279,33 @@ mod details {
 
     /// Add `timings` to the accumulated timings for the current thread.
     pub fn add_to_current(times: &PassTimes) {
-        PASS_TIME.with(|rc| {
-            for (a, b) in rc.borrow_mut().pass.iter_mut().zip(&times.pass[..]) {
-                a.total += b.total;
-                a.child += b.child;
-            }
-        })
+        PASS_TIME.with(|rc| rc.borrow_mut().add(times))
     }
 }
 
 /// Dummy `debug` implementation
 #[cfg(not(feature = "std"))]
 mod details {
-    use super::Pass;
+    use super::{Pass, PassTiming};
     /// Dummy `TimingToken`
     pub struct TimingToken;
     /// Dummy `PassTimes`
+    #[derive(Clone, Debug, Default)]
     pub struct PassTimes;
+    impl PassTimes {
+        /// Returns no timings
+        pub fn iter(&self) -> impl Iterator<Item = PassTiming> + '_ {
+            core::iter::empty()
+        }
+        /// does nothing
+        pub fn add(&mut self, _other: &Self) {}
+    }
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
     }
     /// does nothing
-    pub fn add_to_current(_times: PassTimes) {}
+    pub fn add_to_current(_times: &PassTimes) {}
 
     /// does nothing
     pub(super) fn start_pass(_pass: Pass) -> TimingToken {
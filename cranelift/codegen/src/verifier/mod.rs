@@ -1904,6 +1904,18 @@ impl<'a> Verifier<'a> {
     ) -> VerifierStepResult<()> {
         let inst_data = &self.func.dfg[inst];
 
+        if let Some(flags) = inst_data.memflags() {
+            let regions = [flags.heap(), flags.table(), flags.vmctx()];
+            if regions.iter().filter(|&&region| region).count() > 1 {
+                return errors.fatal((
+                    inst,
+                    self.context(inst),
+                    "A memory access can be in at most one of the `heap`, `table` and `vmctx` \
+                     alias regions",
+                ));
+            }
+        }
+
         match *inst_data {
             ir::InstructionData::Store { flags, .. }
             | ir::InstructionData::StoreComplex { flags, .. } => {
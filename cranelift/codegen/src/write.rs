@@ -263,19 +263,21 @@ pub fn write_block_header(
     let regs = regs.as_ref();
 
     let mut args = func.dfg.block_params(block).iter().cloned();
-    match args.next() {
-        None => return writeln!(w, ":"),
-        Some(arg) => {
-            write!(w, "(")?;
+    if let Some(arg) = args.next() {
+        write!(w, "(")?;
+        write_arg(w, func, regs, arg)?;
+        // Remaining arguments.
+        for arg in args {
+            write!(w, ", ")?;
             write_arg(w, func, regs, arg)?;
         }
+        write!(w, ")")?;
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, regs, arg)?;
+
+    if func.layout.is_cold(block) {
+        write!(w, " cold")?;
     }
-    writeln!(w, "):")
+    writeln!(w, ":")
 }
 
 fn write_valueloc(w: &mut dyn Write, loc: ValueLoc, regs: &RegInfo) -> fmt::Result {
@@ -622,7 +624,12 @@ pub fn write_operands(
             ..
         } => write!(w, " {}, {}{}", arg, stack_slot, offset),
         HeapAddr { heap, arg, imm, .. } => write!(w, " {}, {}, {}", heap, arg, imm),
-        TableAddr { table, arg, .. } => write!(w, " {}, {}", table, arg),
+        TableAddr {
+            table, arg, offset, ..
+        } => {
+            let offset: i32 = offset.into();
+            write!(w, " {}, {}, {:+}", table, arg, offset)
+        }
         Load {
             flags, arg, offset, ..
         } => write!(w, "{} {}{}", flags, arg, offset),
@@ -1,9 +1,14 @@
 //! Provides functionality for compiling and running CLIF IR for `run` tests.
 use core::{mem, ptr};
-use cranelift_codegen::binemit::{NullRelocSink, NullStackMapSink, NullTrapSink};
+use cranelift_codegen::binemit::{
+    Addend, CodeOffset, NullStackMapSink, Reloc, RelocSink, TrapSink,
+};
 use cranelift_codegen::data_value::DataValue;
 use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
-use cranelift_codegen::ir::{condcodes::IntCC, Function, InstBuilder, Signature, Type};
+use cranelift_codegen::ir::{
+    condcodes::IntCC, ConstantOffset, ExternalName, Function, InstBuilder, JumpTable, LibCall,
+    Signature, SourceLoc, TrapCode, Type,
+};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{ir, settings, CodegenError, Context};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
@@ -20,7 +25,8 @@ use thiserror::Error;
 /// [SingleFunctionCompiler] provides a way for compiling Cranelift [Function]s to
 /// `CompiledFunction`s and subsequently calling them through the use of a `Trampoline`. As its
 /// name indicates, this compiler is limited: any functionality that requires knowledge of things
-/// outside the [Function] will likely not work (e.g. global values, calls). For an example of this
+/// outside the [Function] will likely not work (e.g. global values, calls), except for the calls
+/// to the runtime library that lower `i128` divisions. For an example of this
 /// "outside-of-function" functionality, see `cranelift_simplejit::backend::SimpleJITBackend`.
 ///
 /// ```
@@ -61,6 +67,11 @@ impl SingleFunctionCompiler {
         Self::with_host_isa(flags)
     }
 
+    /// Return the [TargetIsa] that functions are compiled for.
+    pub fn isa(&self) -> &dyn TargetIsa {
+        self.isa.as_ref()
+    }
+
     /// Compile the passed [Function] to a `CompiledFunction`. This function will:
     ///  - check that the default ISA calling convention is used (to ensure it can be called)
     ///  - compile the [Function]
@@ -73,7 +84,7 @@ impl SingleFunctionCompiler {
         }
 
         // Compile the function itself.
-        let code_page = compile(function, self.isa.as_ref())?;
+        let (code_page, traps) = compile(function, self.isa.as_ref())?;
 
         // Compile the trampoline to call it, if necessary (it may be cached).
         let isa = self.isa.as_ref();
@@ -82,11 +93,13 @@ impl SingleFunctionCompiler {
             .entry(signature.clone())
             .or_insert_with(|| {
                 let ir = make_trampoline(&signature, isa);
-                let code = compile(ir, isa).expect("failed to compile trampoline");
+                let (code, _) = compile(ir, isa).expect("failed to compile trampoline");
                 Trampoline::new(code)
             });
 
-        Ok(CompiledFunction::new(code_page, signature, trampoline))
+        let mut compiled = CompiledFunction::new(code_page, signature, trampoline);
+        compiled.traps = traps.0;
+        Ok(compiled)
     }
 }
 
@@ -99,6 +112,8 @@ pub enum CompilationError {
     CodegenError(#[from] CodegenError),
     #[error("Memory mapping error")]
     IoError(#[from] std::io::Error),
+    #[error("Unsupported call to {0} with a {1} relocation")]
+    UnsupportedLibCall(LibCall, Reloc),
 }
 
 /// Contains the compiled code to move memory-allocated [DataValue]s to the correct location (e.g.
@@ -142,6 +157,7 @@ pub struct CompiledFunction<'a> {
     page: Mmap,
     signature: Signature,
     trampoline: &'a Trampoline,
+    traps: Vec<(CodeOffset, TrapCode)>,
 }
 
 impl<'a> CompiledFunction<'a> {
@@ -151,6 +167,7 @@ impl<'a> CompiledFunction<'a> {
             page,
             signature,
             trampoline,
+            traps: vec![],
         }
     }
 
@@ -159,6 +176,16 @@ impl<'a> CompiledFunction<'a> {
         self.page.as_ptr()
     }
 
+    /// Return the trap code of the instruction at `pc`, if `pc` points to an instruction of the
+    /// compiled code that can trap.
+    pub fn trap_code_at(&self, pc: *const u8) -> Option<TrapCode> {
+        let offset = (pc as usize).checked_sub(self.as_ptr() as usize)?;
+        self.traps
+            .iter()
+            .find(|(trap_offset, _)| *trap_offset as usize == offset)
+            .map(|(_, code)| *code)
+    }
+
     /// Call the [CompiledFunction], passing in [DataValue]s using a compiled [Trampoline].
     pub fn call(&self, arguments: &[DataValue]) -> Vec<DataValue> {
         let mut values = UnboxedValues::make_arguments(arguments, &self.signature);
@@ -234,6 +261,7 @@ impl UnboxedValues {
             DataValue::I16(i) => ptr::write(p as *mut i16, *i),
             DataValue::I32(i) => ptr::write(p as *mut i32, *i),
             DataValue::I64(i) => ptr::write(p as *mut i64, *i),
+            DataValue::I128(i) => ptr::write(p as *mut i128, *i),
             DataValue::F32(f) => ptr::write(p as *mut Ieee32, *f),
             DataValue::F64(f) => ptr::write(p as *mut Ieee64, *f),
             DataValue::V128(b) => ptr::write(p as *mut [u8; 16], *b),
@@ -248,6 +276,7 @@ impl UnboxedValues {
             ir::types::I16 => DataValue::I16(ptr::read(p as *const i16)),
             ir::types::I32 => DataValue::I32(ptr::read(p as *const i32)),
             ir::types::I64 => DataValue::I64(ptr::read(p as *const i64)),
+            ir::types::I128 => DataValue::I128(ptr::read(p as *const i128)),
             ir::types::F32 => DataValue::F32(ptr::read(p as *const Ieee32)),
             ir::types::F64 => DataValue::F64(ptr::read(p as *const Ieee64)),
             _ if ty.is_bool() => DataValue::B(ptr::read(p as *const bool)),
@@ -259,26 +288,104 @@ impl UnboxedValues {
     }
 }
 
-/// Compile a [Function] to its executable bytes in memory.
+/// Records the offset and code of each instruction that can trap.
+#[derive(Default)]
+struct TrapSites(Vec<(CodeOffset, TrapCode)>);
+
+impl TrapSink for TrapSites {
+    fn trap(&mut self, offset: CodeOffset, _srcloc: SourceLoc, code: TrapCode) {
+        self.0.push((offset, code));
+    }
+}
+
+/// Records the relocations of calls to the runtime library, which are resolved to host functions
+/// (see [libcall_address]). Other relocations are ignored.
+#[derive(Default)]
+struct LibCallRelocs(Vec<(CodeOffset, Reloc, LibCall, Addend)>);
+
+impl RelocSink for LibCallRelocs {
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        _srcloc: SourceLoc,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        if let ExternalName::LibCall(libcall) = *name {
+            self.0.push((offset, reloc, libcall, addend));
+        }
+    }
+    fn reloc_constant(&mut self, _: CodeOffset, _: Reloc, _: ConstantOffset) {}
+    fn reloc_jt(&mut self, _: CodeOffset, _: Reloc, _: JumpTable) {}
+}
+
+/// Return the address of the host function implementing `libcall`, if any.
+fn libcall_address(libcall: LibCall) -> Option<usize> {
+    Some(match libcall {
+        LibCall::UdivI128 => udivti3 as *const u8 as usize,
+        LibCall::SdivI128 => divti3 as *const u8 as usize,
+        LibCall::UremI128 => umodti3 as *const u8 as usize,
+        LibCall::SremI128 => modti3 as *const u8 as usize,
+        _ => return None,
+    })
+}
+
+// The generated code checks for division by zero and overflow before calling these.
+#[allow(improper_ctypes_definitions)]
+extern "C" fn udivti3(a: u128, b: u128) -> u128 {
+    a / b
+}
+#[allow(improper_ctypes_definitions)]
+extern "C" fn divti3(a: i128, b: i128) -> i128 {
+    a.wrapping_div(b)
+}
+#[allow(improper_ctypes_definitions)]
+extern "C" fn umodti3(a: u128, b: u128) -> u128 {
+    a % b
+}
+#[allow(improper_ctypes_definitions)]
+extern "C" fn modti3(a: i128, b: i128) -> i128 {
+    a.wrapping_rem(b)
+}
+
+/// Compile a [Function] to its executable bytes in memory, along with its trap sites.
 ///
 /// This currently returns a [Mmap], a type from an external crate, so we wrap this up before
 /// exposing it in public APIs.
-fn compile(function: Function, isa: &dyn TargetIsa) -> Result<Mmap, CompilationError> {
+fn compile(function: Function, isa: &dyn TargetIsa) -> Result<(Mmap, TrapSites), CompilationError> {
     // Set up the context.
     let mut context = Context::new();
     context.func = function;
 
     // Compile and encode the result to machine code.
-    let relocs = &mut NullRelocSink {};
-    let traps = &mut NullTrapSink {};
+    let mut relocs = LibCallRelocs::default();
+    let mut traps = TrapSites::default();
     let stack_maps = &mut NullStackMapSink {};
     let code_info = context.compile(isa)?;
     let mut code_page = MmapMut::map_anon(code_info.total_size as usize)?;
 
     unsafe {
-        context.emit_to_memory(isa, code_page.as_mut_ptr(), relocs, traps, stack_maps);
+        context.emit_to_memory(
+            isa,
+            code_page.as_mut_ptr(),
+            &mut relocs,
+            &mut traps,
+            stack_maps,
+        );
     };
 
+    for (offset, reloc, libcall, addend) in relocs.0 {
+        let address = match (reloc, libcall_address(libcall)) {
+            (Reloc::Abs8, Some(address)) => address as i64 + addend,
+            _ => return Err(CompilationError::UnsupportedLibCall(libcall, reloc)),
+        };
+        unsafe {
+            let at = code_page.as_mut_ptr().add(offset as usize);
+            ptr::write_unaligned(at as *mut i64, address);
+        }
+    }
+
     let code_page = code_page.make_exec()?;
     trace!(
         "Compiled function {} with signature {} at: {:p}",
@@ -287,7 +394,7 @@ fn compile(function: Function, isa: &dyn TargetIsa) -> Result<Mmap, CompilationE
         code_page.as_ptr()
     );
 
-    Ok(code_page)
+    Ok((code_page, traps))
 }
 
 /// Build the Cranelift IR for moving the memory-allocated [DataValue]s to their correct location
@@ -416,6 +523,34 @@ mod test {
         assert_eq!(returned, vec![DataValue::B(true)])
     }
 
+    #[test]
+    fn trap_sites() {
+        let code = String::from(
+            "
+            test run
+            function %test(i32) -> i32 {
+            block0(v0: i32):
+                trapz v0, int_divz
+                return v0
+            }",
+        );
+        let test_file = parse_test(code.as_str(), ParseOptions::default()).unwrap();
+        let function = test_file.functions[0].0.clone();
+
+        let mut compiler = SingleFunctionCompiler::with_default_host_isa();
+        let compiled_function = compiler.compile(function).unwrap();
+        let code = TrapCode::IntegerDivisionByZero;
+        let &(offset, _) = compiled_function
+            .traps
+            .iter()
+            .find(|(_, c)| *c == code)
+            .unwrap();
+        let pc = compiled_function.as_ptr().wrapping_add(offset as usize);
+        assert_eq!(compiled_function.trap_code_at(pc), Some(code));
+        assert_eq!(compiled_function.trap_code_at(pc.wrapping_add(1)), None);
+        assert_eq!(compiled_function.trap_code_at(ptr::null()), None);
+    }
+
     #[test]
     fn trampolines() {
         let function = parse(
@@ -22,7 +22,7 @@
     )
 )]
 
-pub use crate::function_runner::SingleFunctionCompiler;
+pub use crate::function_runner::{CompiledFunction, SingleFunctionCompiler};
 use crate::runner::TestRunner;
 use cranelift_codegen::timing;
 use cranelift_reader::TestCommand;
@@ -36,6 +36,7 @@ mod runner;
 mod runone;
 mod subtest;
 
+mod test_alias_analysis;
 mod test_binemit;
 mod test_cat;
 mod test_compile;
@@ -57,6 +58,7 @@ mod test_simple_gvn;
 mod test_simple_preopt;
 mod test_stack_maps;
 mod test_unwind;
+mod test_vcode_peepmatic;
 mod test_verifier;
 
 /// Main entry point for `clif-util test`.
@@ -117,6 +119,7 @@ pub fn run_passes(
 /// a `.clif` test file.
 fn new_subtest(parsed: &TestCommand) -> anyhow::Result<Box<dyn subtest::SubTest>> {
     match parsed.command {
+        "alias-analysis" => test_alias_analysis::subtest(parsed),
         "binemit" => test_binemit::subtest(parsed),
         "cat" => test_cat::subtest(parsed),
         "compile" => test_compile::subtest(parsed),
@@ -138,6 +141,7 @@ fn new_subtest(parsed: &TestCommand) -> anyhow::Result<Box<dyn subtest::SubTest>
         "simple_preopt" => test_simple_preopt::subtest(parsed),
         "stack_maps" => test_stack_maps::subtest(parsed),
         "unwind" => test_unwind::subtest(parsed),
+        "vcode_peepmatic" => test_vcode_peepmatic::subtest(parsed),
         "verifier" => test_verifier::subtest(parsed),
         _ => anyhow::bail!("unknown test command '{}'", parsed.command),
     }
@@ -23,12 +23,14 @@ use std::time;
 fn skip_feature_mismatches(testfile: &TestFile) -> Option<&'static str> {
     let mut has_experimental_x64 = false;
     let mut has_experimental_arm32 = false;
+    let mut has_experimental_riscv64 = false;
 
     for feature in &testfile.features {
         if let Feature::With(name) = feature {
             match *name {
                 "experimental_x64" => has_experimental_x64 = true,
                 "experimental_arm32" => has_experimental_arm32 = true,
+                "experimental_riscv64" => has_experimental_riscv64 = true,
                 _ => {}
             }
         }
@@ -55,6 +57,19 @@ fn skip_feature_mismatches(testfile: &TestFile) -> Option<&'static str> {
         return Some("missing support for experimental_arm32");
     }
 
+    // Likewise for the experimental riscv64 backend, which replaces the old one for riscv64.
+    #[cfg(feature = "experimental_riscv64")]
+    if let IsaSpec::Some(ref isas) = testfile.isa_spec {
+        if isas.iter().any(|isa| isa.name() == "riscv64") && !has_experimental_riscv64 {
+            return Some("test requiring riscv64 not marked with experimental_riscv64");
+        }
+    }
+
+    #[cfg(not(feature = "experimental_riscv64"))]
+    if has_experimental_riscv64 {
+        return Some("missing support for experimental_riscv64");
+    }
+
     None
 }
 
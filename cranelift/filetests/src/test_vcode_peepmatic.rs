@@ -0,0 +1,64 @@
+//! Test command for `peepmatic`-generated peephole optimizers on lowered vcode.
+//!
+//! The `vcode_peepmatic` test command compiles each function and runs the
+//! disassembly through filecheck, like the `compile` test command.
+
+use crate::subtest::{run_filecheck, Context, SubTest};
+use cranelift_codegen;
+use cranelift_codegen::ir::Function;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+
+struct TestVCodePeepmatic;
+
+pub fn subtest(parsed: &TestCommand) -> anyhow::Result<Box<dyn SubTest>> {
+    assert_eq!(parsed.command, "vcode_peepmatic");
+    if parsed.options.is_empty() {
+        Ok(Box::new(TestVCodePeepmatic))
+    } else {
+        anyhow::bail!("No options allowed on {}", parsed);
+    }
+}
+
+impl SubTest for TestVCodePeepmatic {
+    fn name(&self) -> &'static str {
+        "vcode_peepmatic"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> anyhow::Result<()> {
+        let isa = context.isa.expect("vcode_peepmatic needs an ISA");
+        if isa.get_mach_backend().is_none() {
+            anyhow::bail!("vcode_peepmatic needs a `MachBackend` ISA");
+        }
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+        comp_ctx.set_disasm(true);
+
+        comp_ctx
+            .compile(isa)
+            .map_err(|e| crate::pretty_anyhow_error(&comp_ctx.func, context.isa, e))?;
+        let disasm = comp_ctx
+            .mach_compile_result
+            .as_ref()
+            .unwrap()
+            .disasm
+            .as_ref()
+            .unwrap();
+        log::debug!("After peepmatic-based vcode optimizations:\n{}", disasm);
+
+        // Only actually run the filecheck if peepmatic is enabled; otherwise
+        // there is nothing to check beyond the function compiling.
+        if cfg!(feature = "enable-peepmatic") {
+            run_filecheck(&disasm, context)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -1,4 +1,5 @@
 //! A frontend for building Cranelift IR from other languages.
+use crate::safepoints::insert_user_stack_maps;
 use crate::ssa::{SSABuilder, SideEffects};
 use crate::variable::Variable;
 use cranelift_codegen::cursor::{Cursor, FuncCursor};
@@ -23,6 +24,8 @@ pub struct FunctionBuilderContext {
     ssa: SSABuilder,
     blocks: SecondaryMap<Block, BlockData>,
     types: SecondaryMap<Variable, Type>,
+    stack_map_vars: EntitySet<Variable>,
+    stack_map_values: EntitySet<Value>,
 }
 
 /// Temporary object used to build a single Cranelift IR `Function`.
@@ -62,6 +65,8 @@ impl FunctionBuilderContext {
             ssa: SSABuilder::new(),
             blocks: SecondaryMap::new(),
             types: SecondaryMap::new(),
+            stack_map_vars: EntitySet::new(),
+            stack_map_values: EntitySet::new(),
         }
     }
 
@@ -69,10 +74,16 @@ impl FunctionBuilderContext {
         self.ssa.clear();
         self.blocks.clear();
         self.types.clear();
+        self.stack_map_vars.clear();
+        self.stack_map_values.clear();
     }
 
     fn is_empty(&self) -> bool {
-        self.ssa.is_empty() && self.blocks.is_empty() && self.types.is_empty()
+        self.ssa.is_empty()
+            && self.blocks.is_empty()
+            && self.types.is_empty()
+            && self.stack_map_vars.is_empty()
+            && self.stack_map_values.is_empty()
     }
 }
 
@@ -216,6 +227,12 @@ impl<'a> FunctionBuilder<'a> {
         self.srcloc = srcloc;
     }
 
+    /// Mark `block` as cold: it is rarely executed, e.g. because it only leads to a trap, so
+    /// the code generator may move it out of the way of the hot code.
+    pub fn set_cold_block(&mut self, block: Block) {
+        self.func.layout.set_cold(block);
+    }
+
     /// Creates a new `Block` and returns its reference.
     pub fn create_block(&mut self) -> Block {
         let block = self.func.dfg.make_block();
@@ -291,6 +308,24 @@ impl<'a> FunctionBuilder<'a> {
         self.func_ctx.types[var] = ty;
     }
 
+    /// Declare that all values of the variable `var` need to be included in user stack maps.
+    ///
+    /// See [`declare_value_needs_stack_map`](#method.declare_value_needs_stack_map).
+    pub fn declare_var_needs_stack_map(&mut self, var: Variable) {
+        self.func_ctx.stack_map_vars.insert(var);
+    }
+
+    /// Declare that `val` is a reference managed by the embedder's garbage collector and needs to
+    /// be included in user stack maps.
+    ///
+    /// When the function is finalized, each such value that is live across a call is given a stack
+    /// slot of its own. It is stored to the slot after its definition and reloaded from it before
+    /// every use, and the call records the slot in its user stack map entries. Values passed to
+    /// or received from block parameters are treated the same way.
+    pub fn declare_value_needs_stack_map(&mut self, val: Value) {
+        self.func_ctx.stack_map_values.insert(val);
+    }
+
     /// Returns the Cranelift IR value corresponding to the utilization at the current program
     /// position of a previously defined user variable.
     pub fn use_var(&mut self, var: Variable) -> Value {
@@ -312,6 +347,9 @@ impl<'a> FunctionBuilder<'a> {
                 .use_var(self.func, var, ty, self.position.unwrap())
         };
         self.handle_ssa_side_effects(side_effects);
+        if self.func_ctx.stack_map_vars.contains(var) {
+            self.declare_value_needs_stack_map(val);
+        }
         val
     }
 
@@ -329,6 +367,10 @@ impl<'a> FunctionBuilder<'a> {
             val
         );
 
+        if self.func_ctx.stack_map_vars.contains(var) {
+            self.declare_value_needs_stack_map(val);
+        }
+
         self.func_ctx.ssa.def_var(var, val, self.position.unwrap());
     }
 
@@ -485,6 +527,10 @@ impl<'a> FunctionBuilder<'a> {
             }
         }
 
+        if !self.func_ctx.stack_map_values.is_empty() {
+            insert_user_stack_maps(self.func, &mut self.func_ctx.stack_map_values);
+        }
+
         // Clear the state (but preserve the allocated buffers) in preparation
         // for translation another function.
         self.func_ctx.clear();
@@ -847,9 +893,13 @@ mod tests {
     use crate::frontend::{FunctionBuilder, FunctionBuilderContext};
     use crate::Variable;
     use alloc::string::ToString;
+    use alloc::vec::Vec;
     use cranelift_codegen::entity::EntityRef;
     use cranelift_codegen::ir::types::*;
-    use cranelift_codegen::ir::{AbiParam, ExternalName, Function, InstBuilder, Signature};
+    use cranelift_codegen::ir::{
+        AbiParam, ExtFuncData, ExternalName, Function, InstBuilder, Signature, StackSlot,
+        UserStackMapEntry,
+    };
     use cranelift_codegen::isa::CallConv;
     use cranelift_codegen::settings;
     use cranelift_codegen::verifier::verify_function;
@@ -1305,6 +1355,139 @@ block0:
         );
     }
 
+    #[test]
+    fn user_stack_maps() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I64));
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I64));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_block();
+            builder.append_block_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+
+            let gc_ref = builder.block_params(block0)[0];
+            let int = builder.block_params(block0)[1];
+            builder.declare_value_needs_stack_map(gc_ref);
+
+            let signature = builder.import_signature(Signature::new(CallConv::SystemV));
+            let name = ExternalName::testcase("gc");
+            let callee = builder.import_function(ExtFuncData {
+                name,
+                signature,
+                colocated: false,
+            });
+            builder.ins().call(callee, &[]);
+            let sum = builder.ins().iadd(gc_ref, int);
+            builder.ins().return_(&[sum]);
+
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i64, i64) -> i64 system_v {
+    ss0 = explicit_slot 8
+    sig0 = () system_v
+    fn0 = %gc sig0
+
+block0(v0: i64, v1: i64):
+    stack_store v0, ss0
+    call fn0()
+    v3 = stack_load.i64 ss0
+    v2 = iadd v3, v1
+    return v2
+}
+"
+        );
+
+        let call = func
+            .layout
+            .block_insts(func.layout.entry_block().unwrap())
+            .nth(1)
+            .unwrap();
+        assert_eq!(
+            func.dfg.user_stack_map_entries(call),
+            Some(
+                &[UserStackMapEntry {
+                    ty: I64,
+                    slot: StackSlot::new(0),
+                    offset: 0,
+                }][..]
+            )
+        );
+    }
+
+    #[test]
+    fn user_stack_maps_vars() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I64));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_block();
+            let block1 = builder.create_block();
+            let block2 = builder.create_block();
+            let x = Variable::new(0);
+            builder.declare_var(x, I64);
+            builder.declare_var_needs_stack_map(x);
+
+            builder.append_block_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let param = builder.block_params(block0)[0];
+            builder.def_var(x, param);
+            builder.ins().jump(block1, &[]);
+
+            // A loop which calls a function and passes `x` through unchanged.
+            builder.switch_to_block(block1);
+            let signature = builder.import_signature(Signature::new(CallConv::SystemV));
+            let name = ExternalName::testcase("gc");
+            let callee = builder.import_function(ExtFuncData {
+                name,
+                signature,
+                colocated: false,
+            });
+            builder.ins().call(callee, &[]);
+            let cond = builder.ins().iconst(I32, 0);
+            builder.ins().brnz(cond, block1, &[]);
+            builder.ins().jump(block2, &[]);
+            builder.seal_block(block1);
+
+            builder.switch_to_block(block2);
+            builder.seal_block(block2);
+            let val = builder.use_var(x);
+            builder.ins().return_(&[val]);
+
+            builder.finalize();
+        }
+
+        // The value of `x` is passed through the loop header's block parameter, which is live
+        // across the call even though `use_var` never returned it.
+        let calls: Vec<_> = func
+            .layout
+            .blocks()
+            .flat_map(|block| func.layout.block_insts(block))
+            .filter(|&inst| func.dfg[inst].opcode().is_call())
+            .collect();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            func.dfg.user_stack_map_entries(calls[0]).map(|e| e.len()),
+            Some(1)
+        );
+        verify_function(&func, &settings::Flags::new(settings::builder())).unwrap();
+    }
+
     #[test]
     fn test_greatest_divisible_power_of_two() {
         assert_eq!(64, greatest_divisible_power_of_two(64));
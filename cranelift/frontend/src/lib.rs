@@ -198,6 +198,7 @@ pub use crate::switch::Switch;
 pub use crate::variable::Variable;
 
 mod frontend;
+mod safepoints;
 mod ssa;
 mod switch;
 mod variable;
@@ -0,0 +1,181 @@
+//! Spilling of values that need user stack maps.
+//!
+//! Values declared with `FunctionBuilder::declare_value_needs_stack_map` must be found by the
+//! embedder's garbage collector whenever they are live across a safepoint (a call). Rather than
+//! teaching the register allocator about them, this pass gives every such value that is live
+//! across some call its own stack slot:
+//!
+//! - the value is stored to its slot right after its definition,
+//! - every use of the value is replaced by a fresh load from the slot, and
+//! - every call the value is live across records the slot in its user stack map entries.
+//!
+//! The collector may therefore update the slot during the call, and all later uses observe the
+//! updated value.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::entity::{EntitySet, SecondaryMap};
+use cranelift_codegen::flowgraph::ControlFlowGraph;
+use cranelift_codegen::ir::instructions::BranchInfo;
+use cranelift_codegen::ir::{
+    Block, Function, Inst, InstBuilder, StackSlot, StackSlotData, StackSlotKind, UserStackMapEntry,
+    Value, ValueDef,
+};
+
+/// Spill the values in `needs_stack_map` that are live across calls, and attach user stack map
+/// entries to those calls.
+pub(crate) fn insert_user_stack_maps(func: &mut Function, needs_stack_map: &mut EntitySet<Value>) {
+    propagate_through_block_params(func, needs_stack_map);
+
+    let cfg = ControlFlowGraph::with_function(func);
+    let live_in = compute_live_in(func, &cfg, needs_stack_map);
+
+    // Find the values live across each call.
+    let mut safepoints: Vec<(Inst, Vec<Value>)> = Vec::new();
+    let mut spilled = BTreeSet::new();
+    for block in func.layout.blocks() {
+        let mut live = live_out(&cfg, &live_in, block);
+        for inst in func.layout.block_insts(block).rev() {
+            for result in func.dfg.inst_results(inst) {
+                live.remove(result);
+            }
+            if func.dfg[inst].opcode().is_call() && !live.is_empty() {
+                spilled.extend(live.iter().copied());
+                safepoints.push((inst, live.iter().copied().collect()));
+            }
+            for &arg in func.dfg.inst_args(inst) {
+                if needs_stack_map.contains(arg) {
+                    live.insert(arg);
+                }
+            }
+        }
+    }
+
+    if spilled.is_empty() {
+        return;
+    }
+
+    let mut slots = SecondaryMap::<Value, Option<StackSlot>>::new();
+    for &val in &spilled {
+        let size = func.dfg.value_type(val).bytes();
+        slots[val] =
+            Some(func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size)));
+    }
+
+    // Reload every use of a spilled value from its slot.
+    let mut pos = FuncCursor::new(func);
+    while let Some(_block) = pos.next_block() {
+        while let Some(inst) = pos.next_inst() {
+            let num_args = pos.func.dfg.inst_args(inst).len();
+            let mut reloaded: Vec<(Value, Value)> = Vec::new();
+            for i in 0..num_args {
+                let arg = pos.func.dfg.inst_args(inst)[i];
+                let slot = match slots[arg] {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                let new_arg = match reloaded.iter().find(|&&(old, _)| old == arg) {
+                    Some(&(_, new)) => new,
+                    None => {
+                        let ty = pos.func.dfg.value_type(arg);
+                        let new = pos.ins().stack_load(ty, slot, 0);
+                        reloaded.push((arg, new));
+                        new
+                    }
+                };
+                pos.func.dfg.inst_args_mut(inst)[i] = new_arg;
+            }
+        }
+    }
+
+    // Store every spilled value to its slot right after its definition.
+    for &val in &spilled {
+        let slot = slots[val].unwrap();
+        match pos.func.dfg.value_def(val) {
+            ValueDef::Result(inst, _) => pos.goto_after_inst(inst),
+            ValueDef::Param(block, _) => pos.goto_first_insertion_point(block),
+        }
+        pos.ins().stack_store(val, slot, 0);
+    }
+
+    for (inst, live) in safepoints {
+        for val in live {
+            let entry = UserStackMapEntry {
+                ty: func.dfg.value_type(val),
+                slot: slots[val].unwrap(),
+                offset: 0,
+            };
+            func.dfg.append_user_stack_map_entry(inst, entry);
+        }
+    }
+}
+
+/// A value flowing into a block parameter is the same object as the parameter, so make sure both
+/// ends of every branch argument agree on whether they need a stack map.
+fn propagate_through_block_params(func: &Function, needs_stack_map: &mut EntitySet<Value>) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                if let BranchInfo::SingleDest(dest, args) = func.dfg.analyze_branch(inst) {
+                    for (&arg, &param) in args.iter().zip(func.dfg.block_params(dest)) {
+                        if needs_stack_map.contains(arg) {
+                            changed |= needs_stack_map.insert(param);
+                        } else if needs_stack_map.contains(param) {
+                            changed |= needs_stack_map.insert(arg);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Compute the set of values needing stack maps that are live on entry to each block.
+fn compute_live_in(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    needs_stack_map: &EntitySet<Value>,
+) -> SecondaryMap<Block, BTreeSet<Value>> {
+    let blocks: Vec<Block> = func.layout.blocks().collect();
+    let mut live_in = SecondaryMap::<Block, BTreeSet<Value>>::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in blocks.iter().rev() {
+            let mut live = live_out(cfg, &live_in, block);
+            for inst in func.layout.block_insts(block).rev() {
+                for result in func.dfg.inst_results(inst) {
+                    live.remove(result);
+                }
+                for &arg in func.dfg.inst_args(inst) {
+                    if needs_stack_map.contains(arg) {
+                        live.insert(arg);
+                    }
+                }
+            }
+            for param in func.dfg.block_params(block) {
+                live.remove(param);
+            }
+            if live != live_in[block] {
+                live_in[block] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+fn live_out(
+    cfg: &ControlFlowGraph,
+    live_in: &SecondaryMap<Block, BTreeSet<Value>>,
+    block: Block,
+) -> BTreeSet<Value> {
+    let mut live = BTreeSet::new();
+    for succ in cfg.succ_iter(block) {
+        live.extend(live_in[succ].iter().copied());
+    }
+    live
+}
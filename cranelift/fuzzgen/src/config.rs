@@ -0,0 +1,35 @@
+use std::ops::RangeInclusive;
+
+/// Ranges controlling the shape of the generated test cases.
+///
+/// Each range is inclusive; the generator picks a value within it using the fuzzer's input.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The number of argument sets to call the generated function with.
+    pub test_case_inputs: RangeInclusive<usize>,
+    /// The number of parameters in the generated function's signature.
+    pub signature_params: RangeInclusive<usize>,
+    /// The number of return values in the generated function's signature.
+    pub signature_rets: RangeInclusive<usize>,
+    /// The number of blocks in the generated function.
+    pub blocks_per_function: RangeInclusive<usize>,
+    /// The number of non-terminator instructions in each block.
+    pub instructions_per_block: RangeInclusive<usize>,
+    /// The number of variables of each type, in addition to those holding the parameters.
+    pub vars_per_type: RangeInclusive<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            test_case_inputs: 1..=10,
+            signature_params: 0..=8,
+            // Keep the return values in registers; returning more values requires a return area,
+            // which not all of the native backends support yet.
+            signature_rets: 0..=2,
+            blocks_per_function: 1..=10,
+            instructions_per_block: 0..=16,
+            vars_per_type: 1..=4,
+        }
+    }
+}
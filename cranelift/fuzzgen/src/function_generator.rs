@@ -0,0 +1,332 @@
+use crate::config::Config;
+use arbitrary::{Result, Unstructured};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::types::*;
+use cranelift_codegen::ir::{
+    Block, ExternalName, Function, InstBuilder, Opcode, Signature, Type, Value,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+
+/// The integer types that the generated functions operate on.
+pub(crate) const INT_TYPES: &[Type] = &[I8, I16, I32, I64];
+
+/// The binary integer opcodes, along with the types they can be generated for.
+const BINARY_OPCODES: &[(Opcode, &[Type])] = &[
+    (Opcode::Iadd, INT_TYPES),
+    (Opcode::Isub, INT_TYPES),
+    (Opcode::Imul, INT_TYPES),
+    (Opcode::Udiv, INT_TYPES),
+    (Opcode::Sdiv, INT_TYPES),
+    (Opcode::Urem, INT_TYPES),
+    (Opcode::Srem, INT_TYPES),
+    (Opcode::Band, INT_TYPES),
+    (Opcode::Bor, INT_TYPES),
+    (Opcode::Bxor, INT_TYPES),
+    (Opcode::Ishl, INT_TYPES),
+    (Opcode::Ushr, INT_TYPES),
+    (Opcode::Sshr, INT_TYPES),
+    (Opcode::Rotl, INT_TYPES),
+    (Opcode::Rotr, INT_TYPES),
+];
+
+const UNARY_OPCODES: &[Opcode] = &[Opcode::Bnot, Opcode::Ineg];
+
+const INT_CONDITION_CODES: &[IntCC] = &[
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+/// The kinds of non-terminator instructions the generator knows how to insert.
+#[derive(Clone, Copy)]
+enum InstructionKind {
+    Const,
+    Unary,
+    Binary,
+    Compare,
+    Select,
+    Bint,
+    Extend,
+    Reduce,
+}
+
+const INSTRUCTION_KINDS: &[InstructionKind] = &[
+    InstructionKind::Const,
+    InstructionKind::Unary,
+    InstructionKind::Binary,
+    InstructionKind::Compare,
+    InstructionKind::Select,
+    InstructionKind::Bint,
+    InstructionKind::Extend,
+    InstructionKind::Reduce,
+];
+
+#[derive(Clone, Copy)]
+enum TerminatorKind {
+    Return,
+    Jump,
+    Brz,
+    Brnz,
+}
+
+const TERMINATOR_KINDS: &[TerminatorKind] = &[
+    TerminatorKind::Return,
+    TerminatorKind::Jump,
+    TerminatorKind::Brz,
+    TerminatorKind::Brnz,
+];
+
+/// Generates a single function from the fuzzer's input.
+///
+/// Values are threaded through the function using [Variable]s, leaving it to the
+/// [FunctionBuilder] to place block parameters. Branches only ever target blocks later in the
+/// layout, so every generated function terminates.
+pub struct FunctionGenerator<'r, 'data>
+where
+    'data: 'r,
+{
+    u: &'r mut Unstructured<'data>,
+    config: &'r Config,
+    vars: Vec<(Type, Variable)>,
+    blocks: Vec<Block>,
+}
+
+impl<'r, 'data> FunctionGenerator<'r, 'data>
+where
+    'data: 'r,
+{
+    pub fn new(u: &'r mut Unstructured<'data>, config: &'r Config) -> Self {
+        Self {
+            u,
+            config,
+            vars: vec![],
+            blocks: vec![],
+        }
+    }
+
+    /// Generate a random integer of the given type.
+    pub(crate) fn generate_const(u: &mut Unstructured, ty: Type) -> Result<i64> {
+        Ok(match ty {
+            I8 => u.arbitrary::<i8>()? as i64,
+            I16 => u.arbitrary::<i16>()? as i64,
+            I32 => u.arbitrary::<i32>()? as i64,
+            I64 => u.arbitrary::<i64>()?,
+            _ => unreachable!("unsupported integer type: {}", ty),
+        })
+    }
+
+    /// Generate a function with the given signature.
+    pub fn generate(mut self, signature: Signature) -> Result<Function> {
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), signature);
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut func, &mut builder_context);
+
+        let block_count = self
+            .u
+            .int_in_range(self.config.blocks_per_function.clone())?;
+        self.blocks = (0..block_count.max(1))
+            .map(|_| builder.create_block())
+            .collect();
+
+        let entry = self.blocks[0];
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        self.initialize_variables(&mut builder, entry)?;
+
+        for index in 0..self.blocks.len() {
+            if index > 0 {
+                builder.switch_to_block(self.blocks[index]);
+            }
+            let instructions = self
+                .u
+                .int_in_range(self.config.instructions_per_block.clone())?;
+            for _ in 0..instructions {
+                self.generate_instruction(&mut builder)?;
+            }
+            self.generate_terminator(&mut builder, index)?;
+        }
+
+        builder.seal_all_blocks();
+        builder.finalize();
+        Ok(func)
+    }
+
+    /// Declare the function's variables: one for each parameter, followed by a random number of
+    /// constant-initialized variables of every type.
+    fn initialize_variables(&mut self, builder: &mut FunctionBuilder, entry: Block) -> Result<()> {
+        let params = builder.block_params(entry).to_vec();
+        for param in params {
+            let ty = builder.func.dfg.value_type(param);
+            let var = self.declare_variable(builder, ty);
+            builder.def_var(var, param);
+        }
+
+        for &ty in INT_TYPES.iter().chain(&[B1]) {
+            let count = self.u.int_in_range(self.config.vars_per_type.clone())?;
+            for _ in 0..count.max(1) {
+                let value = self.generate_value(builder, ty)?;
+                let var = self.declare_variable(builder, ty);
+                builder.def_var(var, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn declare_variable(&mut self, builder: &mut FunctionBuilder, ty: Type) -> Variable {
+        let var = Variable::new(self.vars.len());
+        builder.declare_var(var, ty);
+        self.vars.push((ty, var));
+        var
+    }
+
+    /// Insert a constant of the given type.
+    fn generate_value(&mut self, builder: &mut FunctionBuilder, ty: Type) -> Result<Value> {
+        Ok(if ty == B1 {
+            let imm = self.u.arbitrary::<bool>()?;
+            builder.ins().bconst(B1, imm)
+        } else {
+            let imm = Self::generate_const(self.u, ty)?;
+            builder.ins().iconst(ty, imm)
+        })
+    }
+
+    /// Pick one of the declared variables of the given type.
+    fn get_variable_of_type(&mut self, ty: Type) -> Result<Variable> {
+        let candidates = self
+            .vars
+            .iter()
+            .filter(|(var_ty, _)| *var_ty == ty)
+            .map(|(_, var)| *var)
+            .collect::<Vec<_>>();
+        Ok(*self.u.choose(&candidates)?)
+    }
+
+    fn use_random_var(&mut self, builder: &mut FunctionBuilder, ty: Type) -> Result<Value> {
+        let var = self.get_variable_of_type(ty)?;
+        Ok(builder.use_var(var))
+    }
+
+    fn def_random_var(&mut self, builder: &mut FunctionBuilder, value: Value) -> Result<()> {
+        let ty = builder.func.dfg.value_type(value);
+        let var = self.get_variable_of_type(ty)?;
+        builder.def_var(var, value);
+        Ok(())
+    }
+
+    /// Pick two distinct integer types, returning the narrower one first.
+    fn generate_type_pair(&mut self) -> Result<(Type, Type)> {
+        let narrow = self.u.int_in_range(0..=INT_TYPES.len() - 2)?;
+        let wide = self.u.int_in_range(narrow + 1..=INT_TYPES.len() - 1)?;
+        Ok((INT_TYPES[narrow], INT_TYPES[wide]))
+    }
+
+    fn generate_instruction(&mut self, builder: &mut FunctionBuilder) -> Result<()> {
+        let kind = *self.u.choose(INSTRUCTION_KINDS)?;
+        let ty = *self.u.choose(INT_TYPES)?;
+        let result = match kind {
+            InstructionKind::Const => self.generate_value(builder, ty)?,
+            InstructionKind::Unary => {
+                let opcode = *self.u.choose(UNARY_OPCODES)?;
+                let arg = self.use_random_var(builder, ty)?;
+                let (inst, dfg) = builder.ins().Unary(opcode, ty, arg);
+                dfg.first_result(inst)
+            }
+            InstructionKind::Binary => {
+                let (opcode, types) = *self.u.choose(BINARY_OPCODES)?;
+                let ty = *self.u.choose(types)?;
+                let lhs = self.use_random_var(builder, ty)?;
+                let rhs = self.use_random_var(builder, ty)?;
+                let (inst, dfg) = builder.ins().Binary(opcode, ty, lhs, rhs);
+                dfg.first_result(inst)
+            }
+            InstructionKind::Compare => {
+                let cc = *self.u.choose(INT_CONDITION_CODES)?;
+                let lhs = self.use_random_var(builder, ty)?;
+                let rhs = self.use_random_var(builder, ty)?;
+                builder.ins().icmp(cc, lhs, rhs)
+            }
+            InstructionKind::Select => {
+                let condition = self.use_random_var(builder, B1)?;
+                let if_true = self.use_random_var(builder, ty)?;
+                let if_false = self.use_random_var(builder, ty)?;
+                builder.ins().select(condition, if_true, if_false)
+            }
+            InstructionKind::Bint => {
+                let arg = self.use_random_var(builder, B1)?;
+                builder.ins().bint(ty, arg)
+            }
+            InstructionKind::Extend => {
+                let (from, to) = self.generate_type_pair()?;
+                let arg = self.use_random_var(builder, from)?;
+                if self.u.arbitrary()? {
+                    builder.ins().sextend(to, arg)
+                } else {
+                    builder.ins().uextend(to, arg)
+                }
+            }
+            InstructionKind::Reduce => {
+                let (to, from) = self.generate_type_pair()?;
+                let arg = self.use_random_var(builder, from)?;
+                builder.ins().ireduce(to, arg)
+            }
+        };
+        self.def_random_var(builder, result)
+    }
+
+    /// Pick a block later in the layout than the block at `index`.
+    fn generate_target(&mut self, index: usize) -> Result<Block> {
+        let target = self.u.int_in_range(index + 1..=self.blocks.len() - 1)?;
+        Ok(self.blocks[target])
+    }
+
+    fn generate_terminator(&mut self, builder: &mut FunctionBuilder, index: usize) -> Result<()> {
+        let is_last = index == self.blocks.len() - 1;
+        let kind = if is_last {
+            TerminatorKind::Return
+        } else {
+            *self.u.choose(TERMINATOR_KINDS)?
+        };
+
+        match kind {
+            TerminatorKind::Return => {
+                let types = builder
+                    .func
+                    .signature
+                    .returns
+                    .iter()
+                    .map(|ret| ret.value_type)
+                    .collect::<Vec<_>>();
+                let mut values = Vec::with_capacity(types.len());
+                for ty in types {
+                    values.push(self.use_random_var(builder, ty)?);
+                }
+                builder.ins().return_(&values);
+            }
+            TerminatorKind::Jump => {
+                let target = self.generate_target(index)?;
+                builder.ins().jump(target, &[]);
+            }
+            TerminatorKind::Brz | TerminatorKind::Brnz => {
+                let ty = *self.u.choose(&[B1, I8, I16, I32, I64])?;
+                let condition = self.use_random_var(builder, ty)?;
+                let taken = self.generate_target(index)?;
+                let not_taken = self.generate_target(index)?;
+                if let TerminatorKind::Brz = kind {
+                    builder.ins().brz(condition, taken, &[]);
+                } else {
+                    builder.ins().brnz(condition, taken, &[]);
+                }
+                builder.ins().jump(not_taken, &[]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//! Generate random Cranelift IR functions, and inputs to run them with, for differential fuzzing.
+//!
+//! The generated functions only use instructions that both the native backends and
+//! `cranelift-interpreter` support, so that the results of compiling and running a function can
+//! be compared against the results of interpreting it. The exceptions are listed in
+//! [KNOWN_FAILURES].
+
+#![deny(missing_docs, trivial_numeric_casts, unused_extern_crates)]
+
+use crate::function_generator::{FunctionGenerator, INT_TYPES};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use cranelift_codegen::data_value::{write_data_value_list, DataValue};
+use cranelift_codegen::ir::types::*;
+use cranelift_codegen::ir::{AbiParam, Function, Opcode, Signature, Type};
+use cranelift_codegen::isa::{CallConv, TargetIsa};
+use std::fmt;
+use target_lexicon::Triple;
+
+mod config;
+mod function_generator;
+
+pub use crate::config::Config;
+
+/// A set of arguments to call the generated function with.
+pub type TestCaseInput = Vec<DataValue>;
+
+/// An instruction that a native backend is known to miscompile, or to fail to compile, when
+/// controlled by some of the types the generator uses.
+#[derive(Debug)]
+pub struct KnownFailure {
+    /// The backend, as named by [TargetIsa::name].
+    pub isa: &'static str,
+    /// The failing instruction.
+    pub opcode: Opcode,
+    /// The controlling types that the instruction fails with.
+    pub types: &'static [Type],
+    /// What goes wrong.
+    pub reason: &'static str,
+}
+
+/// The known failures of the native backends.
+///
+/// The generator still uses these instructions with every type; fuzz targets are expected to skip
+/// the test cases that hit one of these with the backend they compile for. Remove an entry once
+/// the backend is fixed.
+pub const KNOWN_FAILURES: &[KnownFailure] = &[
+    KnownFailure {
+        isa: "x86",
+        opcode: Opcode::Ishl,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+    KnownFailure {
+        isa: "x86",
+        opcode: Opcode::Ushr,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+    KnownFailure {
+        isa: "x86",
+        opcode: Opcode::Sshr,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+    KnownFailure {
+        isa: "x86",
+        opcode: Opcode::Rotl,
+        types: &[I8, I16],
+        reason: "narrow rotates are not legalized",
+    },
+    KnownFailure {
+        isa: "x86",
+        opcode: Opcode::Rotr,
+        types: &[I8, I16],
+        reason: "narrow rotates are not legalized",
+    },
+    KnownFailure {
+        isa: "x64",
+        opcode: Opcode::Ishl,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+    KnownFailure {
+        isa: "x64",
+        opcode: Opcode::Ushr,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+    KnownFailure {
+        isa: "x64",
+        opcode: Opcode::Sshr,
+        types: &[I8, I16],
+        reason: "the shift amount is masked to 5 bits instead of the type's width",
+    },
+];
+
+/// A generated function along with the inputs it should be called with.
+pub struct TestCase {
+    /// The generated function; it uses the host's default calling convention.
+    pub func: Function,
+    /// The sets of arguments to call `func` with.
+    pub inputs: Vec<TestCaseInput>,
+}
+
+impl TestCase {
+    /// Find an instruction of the generated function that `isa` is known to get wrong.
+    pub fn known_failure(&self, isa: &dyn TargetIsa) -> Option<&'static KnownFailure> {
+        let dfg = &self.func.dfg;
+        self.func
+            .layout
+            .blocks()
+            .flat_map(|block| self.func.layout.block_insts(block))
+            .find_map(|inst| {
+                let opcode = dfg[inst].opcode();
+                let ty = dfg.ctrl_typevar(inst);
+                KNOWN_FAILURES.iter().find(|failure| {
+                    failure.isa == isa.name()
+                        && failure.opcode == opcode
+                        && failure.types.contains(&ty)
+                })
+            })
+    }
+}
+
+impl fmt::Debug for TestCase {
+    /// Print the test case as a CLIF file that can be run with `clif-util test`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, ";; Fuzzgen test case\n")?;
+        writeln!(f, "test interpret")?;
+        writeln!(f, "test run\n")?;
+        writeln!(f, "{}", self.func)?;
+        for input in &self.inputs {
+            write!(f, "; print: {}(", self.func.name)?;
+            write_data_value_list(f, input)?;
+            writeln!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Arbitrary for TestCase {
+    fn arbitrary(u: &mut Unstructured) -> Result<Self> {
+        FuzzGen::new(u).generate_test()
+    }
+}
+
+/// Drives the generation of a [TestCase] from the fuzzer's input.
+pub struct FuzzGen<'r, 'data>
+where
+    'data: 'r,
+{
+    u: &'r mut Unstructured<'data>,
+    config: Config,
+}
+
+impl<'r, 'data> FuzzGen<'r, 'data>
+where
+    'data: 'r,
+{
+    /// Create a generator using the default [Config].
+    pub fn new(u: &'r mut Unstructured<'data>) -> Self {
+        Self::with_config(u, Config::default())
+    }
+
+    /// Create a generator using the given [Config].
+    pub fn with_config(u: &'r mut Unstructured<'data>, config: Config) -> Self {
+        Self { u, config }
+    }
+
+    fn generate_type(&mut self) -> Result<Type> {
+        Ok(*self.u.choose(INT_TYPES)?)
+    }
+
+    fn generate_signature(&mut self) -> Result<Signature> {
+        let mut signature = Signature::new(CallConv::triple_default(&Triple::host()));
+
+        let params = self.u.int_in_range(self.config.signature_params.clone())?;
+        for _ in 0..params {
+            let ty = self.generate_type()?;
+            signature.params.push(AbiParam::new(ty));
+        }
+
+        let rets = self.u.int_in_range(self.config.signature_rets.clone())?;
+        for _ in 0..rets {
+            let ty = self.generate_type()?;
+            signature.returns.push(AbiParam::new(ty));
+        }
+
+        Ok(signature)
+    }
+
+    fn generate_datavalue(&mut self, ty: Type) -> Result<DataValue> {
+        let imm = FunctionGenerator::generate_const(self.u, ty)?;
+        Ok(match ty {
+            I8 => DataValue::I8(imm as i8),
+            I16 => DataValue::I16(imm as i16),
+            I32 => DataValue::I32(imm as i32),
+            I64 => DataValue::I64(imm),
+            _ => unreachable!("unsupported parameter type: {}", ty),
+        })
+    }
+
+    fn generate_test_inputs(&mut self, signature: &Signature) -> Result<Vec<TestCaseInput>> {
+        let count = self.u.int_in_range(self.config.test_case_inputs.clone())?;
+        let mut inputs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let input = signature
+                .params
+                .iter()
+                .map(|param| self.generate_datavalue(param.value_type))
+                .collect::<Result<TestCaseInput>>()?;
+            inputs.push(input);
+        }
+        Ok(inputs)
+    }
+
+    /// Generate a function and the inputs to call it with.
+    pub fn generate_test(mut self) -> Result<TestCase> {
+        let signature = self.generate_signature()?;
+        let inputs = self.generate_test_inputs(&signature)?;
+        let func = FunctionGenerator::new(self.u, &self.config).generate(signature)?;
+        Ok(TestCase { func, inputs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::InstBuilder;
+    use cranelift_codegen::verifier::verify_function;
+    use cranelift_codegen::{isa, settings};
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+
+    /// Produce a deterministic stream of bytes to stand in for the fuzzer's input.
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_functions_verify() {
+        let flags = settings::Flags::new(settings::builder());
+        for seed in 0..100 {
+            let data = bytes(seed, 4096);
+            let mut u = Unstructured::new(&data);
+            let testcase = TestCase::arbitrary(&mut u).unwrap();
+            if let Err(errors) = verify_function(&testcase.func, &flags) {
+                panic!("{:?}\n{}", testcase, errors);
+            }
+            for input in &testcase.inputs {
+                assert_eq!(input.len(), testcase.func.signature.params.len());
+            }
+        }
+    }
+
+    /// Make a test case whose function shifts a value of type `ty`.
+    fn shift_testcase(ty: Type) -> TestCase {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(ty));
+        sig.returns.push(AbiParam::new(ty));
+        let mut func = Function::with_name_signature(Default::default(), sig);
+        let mut ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut func, &mut ctx);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        let x = builder.block_params(block)[0];
+        let y = builder.ins().ishl(x, x);
+        builder.ins().return_(&[y]);
+        builder.seal_all_blocks();
+        builder.finalize();
+        TestCase {
+            func,
+            inputs: vec![],
+        }
+    }
+
+    #[test]
+    fn known_failures() {
+        let isa = isa::lookup(Triple::host())
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let has_entry = |ty| {
+            KNOWN_FAILURES.iter().any(|failure| {
+                failure.isa == isa.name()
+                    && failure.opcode == Opcode::Ishl
+                    && failure.types.contains(&ty)
+            })
+        };
+        for &ty in INT_TYPES {
+            let failure = shift_testcase(ty).known_failure(&*isa);
+            assert_eq!(failure.is_some(), has_entry(ty), "{}", ty);
+        }
+    }
+}
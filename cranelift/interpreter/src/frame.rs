@@ -16,6 +16,8 @@ pub struct Frame<'a> {
     pub function: &'a Function,
     /// The current mapping of SSA value-references to their actual values.
     registers: HashMap<ValueRef, DataValue>,
+    /// The offset of this frame's stack slots in the interpreter's stack memory.
+    stack_base: usize,
 }
 
 impl<'a> Frame<'a> {
@@ -27,15 +29,31 @@ impl<'a> Frame<'a> {
         Self {
             function,
             registers: HashMap::with_capacity(function.dfg.num_values()),
+            stack_base: 0,
         }
     }
 
-    /// Retrieve the actual value associated with an SSA reference.
+    /// Place this frame's stack slots at `stack_base` in the interpreter's stack memory.
+    pub fn with_stack_base(self, stack_base: usize) -> Self {
+        Self { stack_base, ..self }
+    }
+
+    /// Retrieve the offset of this frame's stack slots in the interpreter's stack memory.
+    pub fn stack_base(&self) -> usize {
+        self.stack_base
+    }
+
+    /// Retrieve the actual value associated with an SSA reference. Aliases (e.g. those left behind
+    /// by `cranelift-frontend`) are resolved to the value they refer to.
     #[inline]
     pub fn get(&self, name: ValueRef) -> &DataValue {
         trace!("Get {}", name);
         self.registers
             .get(&name)
+            .or_else(|| {
+                let original = self.function.dfg.resolve_aliases(name);
+                self.registers.get(&original)
+            })
             .unwrap_or_else(|| panic!("unknown value: {}", name))
     }
 
@@ -79,7 +97,7 @@ impl<'a> Frame<'a> {
 mod tests {
     use super::*;
     use cranelift_codegen::data_value::DataValue;
-    use cranelift_codegen::ir::InstBuilder;
+    use cranelift_codegen::ir::{types, InstBuilder};
     use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
 
     /// Build an empty function with a single return.
@@ -120,4 +138,19 @@ mod tests {
         let a = ValueRef::with_number(1).unwrap();
         frame.get(a);
     }
+
+    #[test]
+    fn aliases() {
+        let mut func = empty_function();
+        let block = func.layout.entry_block().unwrap();
+        let original = func.dfg.append_block_param(block, types::I32);
+        let alias = func.dfg.append_block_param(block, types::I32);
+        func.dfg.remove_block_param(alias);
+        func.dfg.change_to_alias(alias, original);
+
+        let mut frame = Frame::new(&func);
+        let fortytwo = DataValue::I32(42);
+        frame.set(original, fortytwo.clone());
+        assert_eq!(frame.get(alias), &fortytwo);
+    }
 }
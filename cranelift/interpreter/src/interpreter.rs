@@ -6,40 +6,65 @@ use crate::environment::FunctionStore;
 use crate::frame::Frame;
 use crate::instruction::DfgInstructionContext;
 use crate::state::{MemoryError, State};
-use crate::step::{step, ControlFlow, StepError};
+use crate::step::{step, ControlFlow, CraneliftTrap, StepError};
 use crate::value::ValueError;
 use cranelift_codegen::data_value::DataValue;
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
-use cranelift_codegen::ir::{Block, FuncRef, Function, Type, Value as ValueRef};
+use cranelift_codegen::ir::{Block, FuncRef, Function, TrapCode, Type, Value as ValueRef};
 use log::trace;
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ptr;
 use thiserror::Error;
 
 /// The Cranelift interpreter; this contains some high-level functions to control the interpreter's
 /// flow. The interpreter state is defined separately (see [InterpreterState]) as the execution
-/// semantics for each Cranelift instruction (see [step]).
-pub struct Interpreter<'a> {
-    state: InterpreterState<'a>,
+/// semantics for each Cranelift instruction (see [step]). Any other [State] implementation can be
+/// used in its place, e.g. to run functions against an embedder's memory.
+pub struct Interpreter<'a, S: State<'a, DataValue> = InterpreterState<'a>> {
+    state: S,
+    call_depth: usize,
+    max_call_depth: Option<usize>,
+    _marker: PhantomData<&'a Function>,
 }
 
-impl<'a> Interpreter<'a> {
-    pub fn new(state: InterpreterState<'a>) -> Self {
-        Self { state }
+impl<'a, S: State<'a, DataValue>> Interpreter<'a, S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            state,
+            call_depth: 0,
+            max_call_depth: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Limit the number of nested calls; a call beyond this depth traps with
+    /// [TrapCode::StackOverflow] instead of exhausting the host's stack.
+    pub fn with_max_call_depth(self, max_call_depth: usize) -> Self {
+        Self {
+            max_call_depth: Some(max_call_depth),
+            ..self
+        }
     }
 
-    /// Call a function by name; this is a helpful proxy for [Interpreter::call_by_index].
+    /// Retrieve the interpreter's state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Call a function by name; this is a helpful proxy for [Interpreter::call].
     pub fn call_by_name(
         &mut self,
         func_name: &str,
         arguments: &[DataValue],
     ) -> Result<ControlFlow<'a, DataValue>, InterpreterError> {
-        let func_ref = self
+        let (_, function) = self
             .state
-            .functions
-            .index_of(func_name)
+            .get_function_by_name(func_name)
             .ok_or_else(|| InterpreterError::UnknownFunctionName(func_name.to_string()))?;
-        self.call_by_index(func_ref, arguments)
+        self.call(function, arguments)
     }
 
     /// Call a function by its index in the [FunctionStore]; this is a proxy for [Interpreter::call].
@@ -55,12 +80,19 @@ impl<'a> Interpreter<'a> {
     }
 
     /// Interpret a call to a [Function] given its [DataValue] arguments.
-    fn call(
+    pub fn call(
         &mut self,
         function: &'a Function,
         arguments: &[DataValue],
     ) -> Result<ControlFlow<'a, DataValue>, InterpreterError> {
         trace!("Call: {}({:?})", function.name, arguments);
+        if let Some(max_call_depth) = self.max_call_depth {
+            if self.call_depth >= max_call_depth {
+                return Ok(ControlFlow::Trap(CraneliftTrap::User(
+                    TrapCode::StackOverflow,
+                )));
+            }
+        }
         let first_block = function
             .layout
             .blocks()
@@ -68,41 +100,39 @@ impl<'a> Interpreter<'a> {
             .expect("to have a first block");
         let parameters = function.dfg.block_params(first_block);
         self.state.push_frame(function);
-        self.state
-            .current_frame_mut()
-            .set_all(parameters, arguments.to_vec());
-        self.block(first_block)
+        self.set_values(parameters, arguments.iter().cloned());
+        self.call_depth += 1;
+        let result = self.block(first_block);
+        self.call_depth -= 1;
+        result
     }
 
     /// Interpret a [Block] in a [Function]. This drives the interpretation over sequences of
     /// instructions, which may continue in other blocks, until the function returns.
     fn block(&mut self, block: Block) -> Result<ControlFlow<'a, DataValue>, InterpreterError> {
         trace!("Block: {}", block);
-        let function = self.state.current_frame_mut().function;
+        let function = self.state.get_current_function();
         let layout = &function.layout;
         let mut maybe_inst = layout.first_inst(block);
         while let Some(inst) = maybe_inst {
             let inst_context = DfgInstructionContext::new(inst, &function.dfg);
             match step(&mut self.state, inst_context)? {
                 ControlFlow::Assign(values) => {
-                    self.state
-                        .current_frame_mut()
-                        .set_all(function.dfg.inst_results(inst), values.to_vec());
+                    self.set_values(function.dfg.inst_results(inst), values);
                     maybe_inst = layout.next_inst(inst)
                 }
                 ControlFlow::Continue => maybe_inst = layout.next_inst(inst),
                 ControlFlow::ContinueAt(block, block_arguments) => {
                     trace!("Block: {}", block);
-                    self.state
-                        .current_frame_mut()
-                        .set_all(function.dfg.block_params(block), block_arguments.to_vec());
+                    self.set_values(function.dfg.block_params(block), block_arguments);
                     maybe_inst = layout.first_inst(block)
                 }
-                ControlFlow::Call(function, arguments) => {
-                    let returned_arguments = self.call(function, &arguments)?.unwrap_return();
-                    self.state
-                        .current_frame_mut()
-                        .set_all(function.dfg.inst_results(inst), returned_arguments);
+                ControlFlow::Call(called_function, arguments) => {
+                    let returned_arguments = match self.call(called_function, &arguments)? {
+                        ControlFlow::Trap(trap) => return Ok(ControlFlow::Trap(trap)),
+                        control_flow => control_flow.unwrap_return(),
+                    };
+                    self.set_values(function.dfg.inst_results(inst), returned_arguments);
                     maybe_inst = layout.next_inst(inst)
                 }
                 ControlFlow::Return(returned_values) => {
@@ -114,6 +144,13 @@ impl<'a> Interpreter<'a> {
         }
         Err(InterpreterError::Unreachable)
     }
+
+    /// Assign `values` to the value references in `names` in the current frame.
+    fn set_values(&mut self, names: &[ValueRef], values: impl IntoIterator<Item = DataValue>) {
+        for (name, value) in names.iter().zip(values) {
+            self.state.set_value(*name, value);
+        }
+    }
 }
 
 /// The ways interpretation can fail.
@@ -132,10 +169,15 @@ pub enum InterpreterError {
 }
 
 /// Maintains the [Interpreter]'s state, implementing the [State] trait.
+///
+/// Memory is a single address space: the `heap` occupies the addresses starting at zero and the
+/// `stack` follows it, so that the addresses of stack slots (e.g. from `stack_addr`) can be used
+/// by regular loads and stores.
 pub struct InterpreterState<'a> {
     pub functions: FunctionStore<'a>,
     pub frame_stack: Vec<Frame<'a>>,
     pub heap: Vec<u8>,
+    pub stack: Vec<u8>,
     pub iflags: HashSet<IntCC>,
     pub fflags: HashSet<FloatCC>,
 }
@@ -146,6 +188,7 @@ impl Default for InterpreterState<'_> {
             functions: FunctionStore::default(),
             frame_stack: vec![],
             heap: vec![0; 1024],
+            stack: vec![],
             iflags: HashSet::new(),
             fflags: HashSet::new(),
         }
@@ -172,17 +215,65 @@ impl<'a> InterpreterState<'a> {
             _ => &self.frame_stack[num_frames - 1],
         }
     }
+
+    /// Retrieve `size` bytes of memory at `address`, which may lie in either the heap or the
+    /// stack.
+    fn memory(&self, address: usize, size: usize) -> Result<&[u8], MemoryError> {
+        let limit = self.heap.len() + self.stack.len();
+        match address.checked_add(size) {
+            Some(end) if end <= self.heap.len() => Ok(&self.heap[address..end]),
+            Some(end) if address >= self.heap.len() && end <= limit => {
+                Ok(&self.stack[address - self.heap.len()..end - self.heap.len()])
+            }
+            _ => Err(MemoryError::InsufficientMemory(address, limit)),
+        }
+    }
+
+    /// Mutable version of `memory`.
+    fn memory_mut(&mut self, address: usize, size: usize) -> Result<&mut [u8], MemoryError> {
+        let heap_size = self.heap.len();
+        let limit = heap_size + self.stack.len();
+        match address.checked_add(size) {
+            Some(end) if end <= heap_size => Ok(&mut self.heap[address..end]),
+            Some(end) if address >= heap_size && end <= limit => {
+                Ok(&mut self.stack[address - heap_size..end - heap_size])
+            }
+            _ => Err(MemoryError::InsufficientMemory(address, limit)),
+        }
+    }
 }
 
 impl<'a> State<'a, DataValue> for InterpreterState<'a> {
     fn get_function(&self, func_ref: FuncRef) -> Option<&'a Function> {
         self.functions.get_by_func_ref(func_ref)
     }
+    fn get_function_by_name(&self, name: &str) -> Option<(u64, &'a Function)> {
+        let func_ref = self.functions.index_of(name)?;
+        let function = self.functions.get_by_func_ref(func_ref)?;
+        Some((func_ref.as_u32() as u64, function))
+    }
+    fn get_function_by_address(&self, address: u64) -> Option<&'a Function> {
+        let func_ref = FuncRef::with_number(u32::try_from(address).ok()?)?;
+        self.functions.get_by_func_ref(func_ref)
+    }
+    fn get_current_function(&self) -> &'a Function {
+        self.current_frame().function
+    }
     fn push_frame(&mut self, function: &'a Function) {
-        self.frame_stack.push(Frame::new(function));
+        let stack_base = self.stack.len();
+        let stack_size: usize = function
+            .stack_slots
+            .values()
+            .map(|slot| slot.size as usize)
+            .sum();
+        self.stack.resize(stack_base + stack_size, 0);
+        self.frame_stack
+            .push(Frame::new(function).with_stack_base(stack_base));
     }
     fn pop_frame(&mut self) {
-        self.frame_stack.pop();
+        if let Some(frame) = self.frame_stack.pop() {
+            self.stack.truncate(frame.stack_base());
+        }
     }
 
     fn get_value(&self, name: ValueRef) -> Option<DataValue> {
@@ -215,29 +306,45 @@ impl<'a> State<'a, DataValue> for InterpreterState<'a> {
     }
 
     fn load_heap(&self, offset: usize, ty: Type) -> Result<DataValue, MemoryError> {
-        if offset + 16 < self.heap.len() {
-            let pointer = self.heap[offset..offset + 16].as_ptr() as *const _ as *const u128;
-            Ok(unsafe { DataValue::read_value_from(pointer, ty) })
-        } else {
-            Err(MemoryError::InsufficientMemory(offset, self.heap.len()))
+        let bytes = self.memory(offset, ty.bytes() as usize)?;
+        // Copy into an aligned buffer, since memory accesses need not be aligned.
+        let mut buffer = 0u128;
+        let pointer = &mut buffer as *mut u128;
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), pointer as *mut u8, bytes.len());
+            Ok(DataValue::read_value_from(pointer, ty))
         }
     }
 
     fn store_heap(&mut self, offset: usize, v: DataValue) -> Result<(), MemoryError> {
-        if offset + 16 < self.heap.len() {
-            let pointer = self.heap[offset..offset + 16].as_mut_ptr() as *mut _ as *mut u128;
-            Ok(unsafe { v.write_value_to(pointer) })
-        } else {
-            Err(MemoryError::InsufficientMemory(offset, self.heap.len()))
+        let bytes = self.memory_mut(offset, v.ty().bytes() as usize)?;
+        let mut buffer = 0u128;
+        let pointer = &mut buffer as *mut u128;
+        unsafe {
+            v.write_value_to(pointer);
+            ptr::copy_nonoverlapping(pointer as *const u8, bytes.as_mut_ptr(), bytes.len());
         }
+        Ok(())
     }
 
-    fn load_stack(&self, _offset: usize, _ty: Type) -> Result<DataValue, MemoryError> {
-        unimplemented!()
+    fn load_stack(&self, offset: usize, ty: Type) -> Result<DataValue, MemoryError> {
+        self.load_heap(self.stack_address(offset)?, ty)
     }
 
-    fn store_stack(&mut self, _offset: usize, _v: DataValue) -> Result<(), MemoryError> {
-        unimplemented!()
+    fn store_stack(&mut self, offset: usize, v: DataValue) -> Result<(), MemoryError> {
+        self.store_heap(self.stack_address(offset)?, v)
+    }
+
+    fn stack_address(&self, offset: usize) -> Result<usize, MemoryError> {
+        let address = self.heap.len() + self.current_frame().stack_base() + offset;
+        if address < self.heap.len() + self.stack.len() {
+            Ok(address)
+        } else {
+            Err(MemoryError::InsufficientMemory(
+                address,
+                self.heap.len() + self.stack.len(),
+            ))
+        }
     }
 }
 
@@ -273,6 +380,117 @@ mod tests {
         assert_eq!(result, vec![DataValue::B(true)])
     }
 
+    #[test]
+    fn trap() {
+        let code = "function %test(i32) -> i32 {
+        block0(v0: i32):
+            v1 = iconst.i32 42
+            v2 = udiv v1, v0
+            return v2
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = FunctionStore::default();
+        env.add(func.name.to_string(), &func);
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .call_by_name("%test", &[DataValue::I32(0)])
+            .unwrap();
+
+        match result {
+            ControlFlow::Trap(CraneliftTrap::User(TrapCode::IntegerDivisionByZero)) => {}
+            _ => panic!("expected a division by zero trap"),
+        }
+    }
+
+    #[test]
+    fn stack_slots() {
+        let code = "function %test() -> i64 {
+            ss0 = explicit_slot 8
+            ss1 = explicit_slot 8
+
+        block0:
+            v0 = iconst.i64 40
+            v1 = iconst.i64 2
+            stack_store v0, ss0
+            stack_store v1, ss1
+            v2 = stack_addr.i64 ss1
+            v3 = load.i64 v2
+            v4 = stack_load.i64 ss0
+            v5 = iadd v3, v4
+            return v5
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = FunctionStore::default();
+        env.add(func.name.to_string(), &func);
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .call_by_name("%test", &[])
+            .unwrap()
+            .unwrap_return();
+
+        assert_eq!(result, vec![DataValue::I64(42)])
+    }
+
+    #[test]
+    fn call_indirect() {
+        let code = "function %callee(i32) -> i32 {
+        block0(v0: i32):
+            v1 = iadd_imm v0, 1
+            return v1
+        }
+
+        function %caller() -> i32 {
+            sig0 = (i32) -> i32
+            fn0 = %callee(i32) -> i32
+
+        block0:
+            v0 = func_addr.i64 fn0
+            v1 = iconst.i32 41
+            v2 = call_indirect sig0, v0(v1)
+            return v2
+        }";
+
+        let functions = parse_functions(code).unwrap();
+        let mut env = FunctionStore::default();
+        for func in functions.iter() {
+            env.add(func.name.to_string(), func);
+        }
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .call_by_name("%caller", &[])
+            .unwrap()
+            .unwrap_return();
+
+        assert_eq!(result, vec![DataValue::I32(42)])
+    }
+
+    #[test]
+    fn max_call_depth() {
+        let code = "function %recurse(i32) -> i32 {
+            fn0 = %recurse(i32) -> i32
+
+        block0(v0: i32):
+            v1 = call fn0(v0)
+            return v1
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = FunctionStore::default();
+        env.add(func.name.to_string(), &func);
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .with_max_call_depth(100)
+            .call_by_name("%recurse", &[DataValue::I32(0)])
+            .unwrap();
+
+        match result {
+            ControlFlow::Trap(CraneliftTrap::User(TrapCode::StackOverflow)) => {}
+            _ => panic!("expected a stack overflow trap"),
+        }
+    }
+
     #[test]
     fn state_heap_roundtrip() -> Result<(), MemoryError> {
         let mut state = InterpreterState::default();
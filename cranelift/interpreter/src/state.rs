@@ -1,7 +1,8 @@
 //! Cranelift instructions modify the state of the machine; the [State] trait describes these
 //! ways this can happen.
+use crate::step::CraneliftTrap;
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
-use cranelift_codegen::ir::{FuncRef, Function, Type, Value};
+use cranelift_codegen::ir::{FuncRef, Function, Signature, Type, Value};
 use cranelift_entity::PrimaryMap;
 use smallvec::SmallVec;
 use thiserror::Error;
@@ -19,6 +20,26 @@ use thiserror::Error;
 pub trait State<'a, V> {
     /// Retrieve a reference to a [Function].
     fn get_function(&self, func_ref: FuncRef) -> Option<&'a Function>;
+    /// Retrieve a reference to a [Function] by its name, along with the address that `func_addr`
+    /// produces for it.
+    fn get_function_by_name(&self, name: &str) -> Option<(u64, &'a Function)>;
+    /// Retrieve a reference to a [Function] by an address produced by `func_addr`, e.g. for
+    /// `call_indirect`.
+    fn get_function_by_address(&self, address: u64) -> Option<&'a Function>;
+    /// Retrieve a reference to the currently executing [Function].
+    fn get_current_function(&self) -> &'a Function;
+    /// Call a function at `address` that is not known to the interpreter (i.e.
+    /// `get_function_by_address` did not find it), passing `arguments` according to `signature`.
+    /// This is how interpreted code can call into native code, such as a runtime's libcalls.
+    /// Returns `None` if the state is unable to make the call, which is the default.
+    fn call_native(
+        &mut self,
+        _address: u64,
+        _signature: &Signature,
+        _arguments: &[V],
+    ) -> Option<Result<SmallVec<[V; 1]>, CraneliftTrap>> {
+        None
+    }
     /// Record that an interpreter has called into a new [Function].
     fn push_frame(&mut self, function: &'a Function);
     /// Record that an interpreter has returned from a called [Function].
@@ -68,6 +89,9 @@ pub trait State<'a, V> {
     /// Store a value `V` on the stack at the given `offset`. The [Type] of `V` will determine
     /// the number of bytes stored.
     fn store_stack(&mut self, offset: usize, v: V) -> Result<(), MemoryError>;
+    /// Compute the address of the stack location at the given `offset`, such that it can be used
+    /// with `load_heap` and `store_heap`; this is how `stack_addr` results are dereferenced.
+    fn stack_address(&self, offset: usize) -> Result<usize, MemoryError>;
 }
 
 #[derive(Error, Debug)]
@@ -92,6 +116,18 @@ where
         None
     }
 
+    fn get_function_by_name(&self, _name: &str) -> Option<(u64, &'a Function)> {
+        None
+    }
+
+    fn get_function_by_address(&self, _address: u64) -> Option<&'a Function> {
+        None
+    }
+
+    fn get_current_function(&self) -> &'a Function {
+        unimplemented!()
+    }
+
     fn push_frame(&mut self, _function: &'a Function) {
         unimplemented!()
     }
@@ -137,4 +173,8 @@ where
     fn store_stack(&mut self, _offset: usize, _v: V) -> Result<(), MemoryError> {
         unimplemented!()
     }
+
+    fn stack_address(&self, _offset: usize) -> Result<usize, MemoryError> {
+        unimplemented!()
+    }
 }
@@ -5,10 +5,13 @@ use crate::state::{MemoryError, State};
 use crate::value::{Value, ValueConversionKind, ValueError, ValueResult};
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::{
-    types, Block, FuncRef, Function, InstructionData, Opcode, TrapCode, Value as ValueRef,
+    types, AtomicRmwOp, Block, ExternalName, FuncRef, Function, GlobalValue, GlobalValueData,
+    HeapStyle, InstructionData, JumpTable, Opcode, TrapCode, Type, Value as ValueRef,
 };
+use cranelift_codegen::ir::{ArgumentPurpose, StackSlot};
 use log::trace;
 use smallvec::{smallvec, SmallVec};
+use std::convert::TryFrom;
 use std::ops::RangeFrom;
 use thiserror::Error;
 
@@ -59,6 +62,10 @@ where
         Ok(SmallVec::<[V; 1]>::from(&args()?[indexes]))
     };
 
+    // Retrieve the type of an instruction argument.
+    let arg_ty =
+        |index: usize| -> Type { inst_context.type_of(inst_context.args()[index]).unwrap() };
+
     // Retrieve the immediate value for an instruction, expecting it to exist.
     let imm = || -> V { V::from(inst.imm_value().unwrap()) };
 
@@ -66,30 +73,55 @@ where
     // instruction. For example, since `InstructionData` stores all integer immediates in a 64-bit
     // size, this will attempt to convert `iconst.i8 ...` to an 8-bit size.
     let imm_as_ctrl_ty =
-        || -> Result<V, ValueError> { V::convert(imm(), ValueConversionKind::Exact(ctrl_ty)) };
+        || -> Result<V, ValueError> { V::convert(imm(), ValueConversionKind::Truncate(ctrl_ty)) };
+
+    // Retrieve the lane index immediate of `extractlane` and `insertlane`.
+    let lane_index = || -> usize {
+        match &inst {
+            InstructionData::BinaryImm8 { imm, .. } | InstructionData::TernaryImm8 { imm, .. } => {
+                *imm as usize
+            }
+            _ => unreachable!(),
+        }
+    };
 
     // Indicate that the result of a step is to assign a single value to an instruction's results.
     let assign = |value: V| ControlFlow::Assign(smallvec![value]);
 
+    // Interpret a unary instruction with the given `op`, applying it to each lane of vectors.
+    let unary = |op: fn(V) -> ValueResult<V>, arg: V| -> ValueResult<ControlFlow<V>> {
+        Ok(assign(unary_arith(arg, ctrl_ty, op)?))
+    };
+
     // Interpret a binary instruction with the given `op`, assigning the resulting value to the
-    // instruction's results.
-    let binary = |op: fn(V, V) -> ValueResult<V>,
-                  left: V,
-                  right: V|
-     -> ValueResult<ControlFlow<V>> { Ok(assign(op(left, right)?)) };
+    // instruction's results. Vectors are operated on lane by lane.
+    let binary =
+        |op: fn(V, V) -> ValueResult<V>, left: V, right: V| -> ValueResult<ControlFlow<V>> {
+            Ok(assign(binary_arith(left, right, ctrl_ty, op, false)?))
+        };
 
     // Same as `binary`, but converts the values to their unsigned form before the operation and
     // back to signed form afterwards. Since Cranelift types have no notion of signedness, this
     // enables operations that depend on sign.
     let binary_unsigned =
         |op: fn(V, V) -> ValueResult<V>, left: V, right: V| -> ValueResult<ControlFlow<V>> {
-            Ok(assign(
-                op(
-                    left.convert(ValueConversionKind::ToUnsigned)?,
-                    right.convert(ValueConversionKind::ToUnsigned)?,
-                )?
-                .convert(ValueConversionKind::ToSigned)?,
-            ))
+            Ok(assign(binary_arith(left, right, ctrl_ty, op, true)?))
+        };
+
+    // Same as `binary` and `binary_unsigned`, but for operations that may trap (e.g. division by
+    // zero): those errors are turned into a `ControlFlow::Trap`.
+    let binary_can_trap = |op: fn(V, V) -> ValueResult<V>,
+                           left: V,
+                           right: V,
+                           unsigned: bool|
+     -> Result<ControlFlow<V>, StepError> {
+        assign_or_trap(binary_arith(left, right, ctrl_ty, op, unsigned))
+    };
+
+    // Shift or rotate `left` by the amount in `right`, which may have a different type.
+    let shift =
+        |op: fn(V, V) -> ValueResult<V>, left: V, right: V| -> ValueResult<ControlFlow<V>> {
+            Ok(assign(shift_arith(left, right, ctrl_ty, op)?))
         };
 
     // Choose whether to assign `left` or `right` to the instruction's result based on a `condition`.
@@ -121,33 +153,76 @@ where
         }
     };
 
-    // Helper for summing a sequence of values.
+    // Helper for summing a sequence of values, e.g. to compute an address.
     fn sum<V: Value>(head: V, tail: SmallVec<[V; 1]>) -> ValueResult<i64> {
-        let mut acc = head;
+        let mut acc = head.into_int()?;
         for t in tail {
-            acc = Value::add(acc, t)?;
+            acc = acc.wrapping_add(t.into_int()?);
         }
-        acc.into_int()
+        Ok(acc)
     }
 
     // Interpret a Cranelift instruction.
     Ok(match inst.opcode() {
         Opcode::Jump | Opcode::Fallthrough => ControlFlow::ContinueAt(branch(), args()?),
-        Opcode::Brz => branch_when(!arg(0)?.into_bool()?)?,
-        Opcode::Brnz => branch_when(arg(0)?.into_bool()?)?,
+        Opcode::Brz => branch_when(!is_true(arg(0)?)?)?,
+        Opcode::Brnz => branch_when(is_true(arg(0)?)?)?,
         Opcode::BrIcmp => branch_when(icmp(inst.cond_code().unwrap(), &arg(1)?, &arg(2)?)?)?,
         Opcode::Brif => branch_when(state.has_iflag(inst.cond_code().unwrap()))?,
         Opcode::Brff => branch_when(state.has_fflag(inst.fp_cond_code().unwrap()))?,
-        Opcode::BrTable => unimplemented!("BrTable"),
-        Opcode::JumpTableEntry => unimplemented!("JumpTableEntry"),
-        Opcode::JumpTableBase => unimplemented!("JumpTableBase"),
-        Opcode::IndirectJumpTableBr => unimplemented!("IndirectJumpTableBr"),
+        Opcode::BrTable => {
+            if let InstructionData::BranchTable {
+                table, destination, ..
+            } = inst
+            {
+                let jump_table = &state.get_current_function().jump_tables[table];
+                let index = unsigned_int(arg(0)?)?;
+                let block = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| jump_table.as_slice().get(i))
+                    .copied()
+                    .unwrap_or(destination);
+                ControlFlow::ContinueAt(block, smallvec![])
+            } else {
+                unreachable!()
+            }
+        }
+        // The legalized form of `br_table` computes the address of a block from a jump table; the
+        // interpreter uses the number of a block as its address and places jump tables at zero.
+        Opcode::JumpTableEntry => {
+            if let InstructionData::BranchTableEntry { table, .. } = inst {
+                let jump_table = &state.get_current_function().jump_tables[table];
+                let index = unsigned_int(arg(0)?)?;
+                let block = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| jump_table.as_slice().get(i))
+                    .ok_or(StepError::InvalidJumpTableEntry(table, index))?;
+                assign(Value::int(block.as_u32() as i64, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::JumpTableBase => assign(Value::int(0, ctrl_ty)?),
+        Opcode::IndirectJumpTableBr => {
+            let address = unsigned_int(arg(0)?)?;
+            let block = u32::try_from(address)
+                .ok()
+                .map(Block::from_u32)
+                .filter(|block| {
+                    state
+                        .get_current_function()
+                        .layout
+                        .is_block_inserted(*block)
+                })
+                .ok_or(StepError::UnknownBlockAddress(address))?;
+            ControlFlow::ContinueAt(block, smallvec![])
+        }
         Opcode::Trap => ControlFlow::Trap(CraneliftTrap::User(trap_code())),
         Opcode::Debugtrap => ControlFlow::Trap(CraneliftTrap::Debug),
         Opcode::ResumableTrap => ControlFlow::Trap(CraneliftTrap::Resumable),
-        Opcode::Trapz => trap_when(!arg(0)?.into_bool()?, CraneliftTrap::User(trap_code())),
-        Opcode::Trapnz => trap_when(arg(0)?.into_bool()?, CraneliftTrap::User(trap_code())),
-        Opcode::ResumableTrapnz => trap_when(arg(0)?.into_bool()?, CraneliftTrap::Resumable),
+        Opcode::Trapz => trap_when(!is_true(arg(0)?)?, CraneliftTrap::User(trap_code())),
+        Opcode::Trapnz => trap_when(is_true(arg(0)?)?, CraneliftTrap::User(trap_code())),
+        Opcode::ResumableTrapnz => trap_when(is_true(arg(0)?)?, CraneliftTrap::Resumable),
         Opcode::Trapif => trap_when(
             state.has_iflag(inst.cond_code().unwrap()),
             CraneliftTrap::User(trap_code()),
@@ -160,16 +235,48 @@ where
         Opcode::FallthroughReturn => ControlFlow::Return(args()?),
         Opcode::Call => {
             if let InstructionData::Call { func_ref, .. } = inst {
-                let function = state
-                    .get_function(func_ref)
+                let name = &state.get_current_function().dfg.ext_funcs[func_ref].name;
+                let (_, function) = state
+                    .get_function_by_name(&name.to_string())
                     .ok_or(StepError::UnknownFunction(func_ref))?;
                 ControlFlow::Call(function, args()?)
             } else {
                 unreachable!()
             }
         }
-        Opcode::CallIndirect => unimplemented!("CallIndirect"),
-        Opcode::FuncAddr => unimplemented!("FuncAddr"),
+        Opcode::CallIndirect => {
+            let address = unsigned_int(arg(0)?)?;
+            match state.get_function_by_address(address) {
+                Some(function) => ControlFlow::Call(function, args_range(1..)?),
+                None => {
+                    // The callee is not known to the interpreter; the state may still be able to
+                    // call it, e.g. if it is native code.
+                    let signature = match inst {
+                        InstructionData::CallIndirect { sig_ref, .. } => {
+                            &state.get_current_function().dfg.signatures[sig_ref]
+                        }
+                        _ => unreachable!(),
+                    };
+                    let arguments = args_range(1..)?;
+                    match state.call_native(address, signature, &arguments) {
+                        Some(Ok(results)) => ControlFlow::Assign(results),
+                        Some(Err(trap)) => ControlFlow::Trap(trap),
+                        None => return Err(StepError::UnknownFunctionAddress(address)),
+                    }
+                }
+            }
+        }
+        Opcode::FuncAddr => {
+            if let InstructionData::FuncAddr { func_ref, .. } = inst {
+                let name = &state.get_current_function().dfg.ext_funcs[func_ref].name;
+                let (address, _) = state
+                    .get_function_by_name(&name.to_string())
+                    .ok_or(StepError::UnknownFunction(func_ref))?;
+                assign(Value::int(address as i64, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
         Opcode::Load
         | Opcode::LoadComplex
         | Opcode::Uload8
@@ -183,19 +290,7 @@ where
         | Opcode::Uload32
         | Opcode::Uload32Complex
         | Opcode::Sload32
-        | Opcode::Sload32Complex
-        | Opcode::Uload8x8
-        | Opcode::Uload8x8Complex
-        | Opcode::Sload8x8
-        | Opcode::Sload8x8Complex
-        | Opcode::Uload16x4
-        | Opcode::Uload16x4Complex
-        | Opcode::Sload16x4
-        | Opcode::Sload16x4Complex
-        | Opcode::Uload32x2
-        | Opcode::Uload32x2Complex
-        | Opcode::Sload32x2
-        | Opcode::Sload32x2Complex => {
+        | Opcode::Sload32Complex => {
             let address = sum(imm(), args()?)? as usize;
             let ctrl_ty = inst_context.controlling_type().unwrap();
             let (load_ty, kind) = match inst.opcode() {
@@ -218,18 +313,6 @@ where
                 Opcode::Sload32 | Opcode::Sload32Complex => {
                     (types::I32, Some(ValueConversionKind::SignExtend(ctrl_ty)))
                 }
-                Opcode::Uload8x8
-                | Opcode::Uload8x8Complex
-                | Opcode::Sload8x8
-                | Opcode::Sload8x8Complex
-                | Opcode::Uload16x4
-                | Opcode::Uload16x4Complex
-                | Opcode::Sload16x4
-                | Opcode::Sload16x4Complex
-                | Opcode::Uload32x2
-                | Opcode::Uload32x2Complex
-                | Opcode::Sload32x2
-                | Opcode::Sload32x2Complex => unimplemented!(),
                 _ => unreachable!(),
             };
             let loaded = state.load_heap(address, load_ty)?;
@@ -240,6 +323,43 @@ where
             };
             ControlFlow::Assign(smallvec!(extended))
         }
+        Opcode::Uload8x8
+        | Opcode::Uload8x8Complex
+        | Opcode::Sload8x8
+        | Opcode::Sload8x8Complex
+        | Opcode::Uload16x4
+        | Opcode::Uload16x4Complex
+        | Opcode::Sload16x4
+        | Opcode::Sload16x4Complex
+        | Opcode::Uload32x2
+        | Opcode::Uload32x2Complex
+        | Opcode::Sload32x2
+        | Opcode::Sload32x2Complex => {
+            let address = sum(imm(), args()?)? as usize;
+            let (lane_ty, vector_ty, signed) = match inst.opcode() {
+                Opcode::Uload8x8 | Opcode::Uload8x8Complex => (types::I8, types::I16X8, false),
+                Opcode::Sload8x8 | Opcode::Sload8x8Complex => (types::I8, types::I16X8, true),
+                Opcode::Uload16x4 | Opcode::Uload16x4Complex => (types::I16, types::I32X4, false),
+                Opcode::Sload16x4 | Opcode::Sload16x4Complex => (types::I16, types::I32X4, true),
+                Opcode::Uload32x2 | Opcode::Uload32x2Complex => (types::I32, types::I64X2, false),
+                Opcode::Sload32x2 | Opcode::Sload32x2Complex => (types::I32, types::I64X2, true),
+                _ => unreachable!(),
+            };
+            // Load the 64 bits of narrow lanes and extend each of them.
+            let loaded = state.load_heap(address, types::I64)?.into_int()?;
+            let lane_bits = lane_ty.bits() as u32;
+            let lanes = (0..vector_ty.lane_count() as u32)
+                .map(|i| {
+                    let lane = V::int(loaded.wrapping_shr(i * lane_bits), lane_ty)?;
+                    lane.convert(if signed {
+                        ValueConversionKind::SignExtend(vector_ty.lane_type())
+                    } else {
+                        ValueConversionKind::ZeroExtend(vector_ty.lane_type())
+                    })
+                })
+                .collect::<ValueResult<SimdVec<V>>>()?;
+            assign(vectorizelanes(&lanes, vector_ty)?)
+        }
         Opcode::Store
         | Opcode::StoreComplex
         | Opcode::Istore8
@@ -271,61 +391,146 @@ where
             ControlFlow::Continue
         }
         Opcode::StackLoad => {
-            let address = sum(imm(), args_range(1..)?)? as usize;
-            let load_ty = inst_context.controlling_type().unwrap();
-            let loaded = state.load_stack(address, load_ty)?;
-            ControlFlow::Assign(smallvec!(loaded))
+            if let InstructionData::StackLoad { stack_slot, .. } = inst {
+                let function = state.get_current_function();
+                let offset = stack_slot_offset(function, stack_slot) + imm().into_int()?;
+                let loaded = state.load_stack(offset as usize, ctrl_ty)?;
+                ControlFlow::Assign(smallvec!(loaded))
+            } else {
+                unreachable!()
+            }
         }
         Opcode::StackStore => {
-            let address = sum(imm(), args_range(1..)?)? as usize;
-            let arg0 = arg(0)?;
-            state.store_stack(address, arg0)?;
-            ControlFlow::Continue
+            if let InstructionData::StackStore { stack_slot, .. } = inst {
+                let function = state.get_current_function();
+                let offset = stack_slot_offset(function, stack_slot) + imm().into_int()?;
+                let arg0 = arg(0)?;
+                state.store_stack(offset as usize, arg0)?;
+                ControlFlow::Continue
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::StackAddr => {
+            if let InstructionData::StackLoad { stack_slot, .. } = inst {
+                let function = state.get_current_function();
+                let offset = stack_slot_offset(function, stack_slot) + imm().into_int()?;
+                let address = state.stack_address(offset as usize)?;
+                assign(Value::int(address as i64, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::GlobalValue | Opcode::SymbolValue => {
+            if let InstructionData::UnaryGlobalValue { global_value, .. } = inst {
+                assign(evaluate_global_value(state, global_value, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::TlsValue => return Err(StepError::UnsupportedInstruction(inst.opcode())),
+        Opcode::HeapAddr => {
+            if let InstructionData::HeapAddr { heap, imm, .. } = inst {
+                let function = state.get_current_function();
+                let heap_data = &function.heaps[heap];
+                let index = unsigned_int(arg(0)?)?;
+                let access_size = u64::from(Into::<u32>::into(imm));
+                let bound = match heap_data.style {
+                    HeapStyle::Static { bound } => bound.into(),
+                    HeapStyle::Dynamic { bound_gv } => unsigned_int(evaluate_global_value(
+                        state,
+                        bound_gv,
+                        heap_data.index_type,
+                    )?)?,
+                };
+                match index.checked_add(access_size) {
+                    Some(end) if end <= bound => {
+                        let base = evaluate_global_value(state, heap_data.base, ctrl_ty)?;
+                        assign(Value::add(base, Value::int(index as i64, ctrl_ty)?)?)
+                    }
+                    _ => ControlFlow::Trap(CraneliftTrap::User(TrapCode::HeapOutOfBounds)),
+                }
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::TableAddr => {
+            if let InstructionData::TableAddr { table, offset, .. } = inst {
+                let function = state.get_current_function();
+                let table_data = &function.tables[table];
+                let index = unsigned_int(arg(0)?)?;
+                let bound = unsigned_int(evaluate_global_value(
+                    state,
+                    table_data.bound_gv,
+                    table_data.index_type,
+                )?)?;
+                if index < bound {
+                    let base = evaluate_global_value(state, table_data.base_gv, ctrl_ty)?;
+                    let element_size: u64 = table_data.element_size.into();
+                    let offset: i64 = offset.into();
+                    let element_offset = (index.wrapping_mul(element_size) as i64) + offset;
+                    assign(Value::add(base, Value::int(element_offset, ctrl_ty)?)?)
+                } else {
+                    ControlFlow::Trap(CraneliftTrap::User(TrapCode::TableOutOfBounds))
+                }
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::GetPinnedReg | Opcode::SetPinnedReg => {
+            return Err(StepError::UnsupportedInstruction(inst.opcode()))
         }
-        Opcode::StackAddr => unimplemented!("StackAddr"),
-        Opcode::GlobalValue => unimplemented!("GlobalValue"),
-        Opcode::SymbolValue => unimplemented!("SymbolValue"),
-        Opcode::TlsValue => unimplemented!("TlsValue"),
-        Opcode::HeapAddr => unimplemented!("HeapAddr"),
-        Opcode::GetPinnedReg => unimplemented!("GetPinnedReg"),
-        Opcode::SetPinnedReg => unimplemented!("SetPinnedReg"),
-        Opcode::TableAddr => unimplemented!("TableAddr"),
         Opcode::Iconst => assign(Value::int(imm().into_int()?, ctrl_ty)?),
         Opcode::F32const => assign(imm()),
         Opcode::F64const => assign(imm()),
         Opcode::Bconst => assign(imm()),
-        Opcode::Vconst => unimplemented!("Vconst"),
-        Opcode::ConstAddr => unimplemented!("ConstAddr"),
-        Opcode::Null => unimplemented!("Null"),
+        Opcode::Vconst => {
+            if let InstructionData::UnaryConst {
+                constant_handle, ..
+            } = inst
+            {
+                let constant = state
+                    .get_current_function()
+                    .dfg
+                    .constants
+                    .get(constant_handle);
+                assign(Value::vector(to_array(constant.as_slice())?, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::ConstAddr => return Err(StepError::UnsupportedInstruction(inst.opcode())),
+        Opcode::Null => assign(Value::int(0, reference_as_int(ctrl_ty))?),
         Opcode::Nop => ControlFlow::Continue,
-        Opcode::Select => choose(arg(0)?.into_bool()?, arg(1)?, arg(2)?),
-        Opcode::Selectif => choose(state.has_iflag(inst.cond_code().unwrap()), arg(1)?, arg(2)?),
-        Opcode::SelectifSpectreGuard => unimplemented!("SelectifSpectreGuard"),
+        Opcode::Select => choose(is_true(arg(0)?)?, arg(1)?, arg(2)?),
+        Opcode::Selectif | Opcode::SelectifSpectreGuard => {
+            choose(state.has_iflag(inst.cond_code().unwrap()), arg(1)?, arg(2)?)
+        }
         Opcode::Bitselect => {
             let mask_a = Value::and(arg(0)?, arg(1)?)?;
             let mask_b = Value::and(Value::not(arg(0)?)?, arg(2)?)?;
             assign(Value::or(mask_a, mask_b)?)
         }
         Opcode::Copy => assign(arg(0)?),
-        Opcode::Spill => unimplemented!("Spill"),
-        Opcode::Fill => unimplemented!("Fill"),
-        Opcode::FillNop => assign(arg(0)?),
-        Opcode::DummySargT => unimplemented!("DummySargT"),
-        Opcode::Regmove => ControlFlow::Continue,
-        Opcode::CopySpecial => ControlFlow::Continue,
-        Opcode::CopyToSsa => assign(arg(0)?),
-        Opcode::CopyNop => unimplemented!("CopyNop"),
-        Opcode::AdjustSpDown => unimplemented!("AdjustSpDown"),
-        Opcode::AdjustSpUpImm => unimplemented!("AdjustSpUpImm"),
-        Opcode::AdjustSpDownImm => unimplemented!("AdjustSpDownImm"),
-        Opcode::IfcmpSp => unimplemented!("IfcmpSp"),
-        Opcode::Regspill => unimplemented!("Regspill"),
-        Opcode::Regfill => unimplemented!("Regfill"),
-        Opcode::Safepoint => unimplemented!("Safepoint"),
-        Opcode::Icmp => assign(Value::bool(
-            icmp(inst.cond_code().unwrap(), &arg(0)?, &arg(1)?)?,
-            ctrl_ty.as_bool(),
-        )?),
+        Opcode::Spill | Opcode::Fill | Opcode::FillNop | Opcode::CopyNop | Opcode::CopyToSsa => {
+            assign(arg(0)?)
+        }
+        // The following instructions only move values between locations chosen by the register
+        // allocator, which the interpreter does not model.
+        Opcode::Regmove
+        | Opcode::CopySpecial
+        | Opcode::Regspill
+        | Opcode::Regfill
+        | Opcode::AdjustSpDown
+        | Opcode::AdjustSpUpImm
+        | Opcode::AdjustSpDownImm
+        | Opcode::Safepoint => ControlFlow::Continue,
+        Opcode::DummySargT | Opcode::IfcmpSp => {
+            return Err(StepError::UnsupportedInstruction(inst.opcode()))
+        }
+        Opcode::Icmp => assign(compare(arg(0)?, arg(1)?, ctrl_ty, |l, r| {
+            icmp(inst.cond_code().unwrap(), l, r)
+        })?),
         Opcode::IcmpImm => assign(Value::bool(
             icmp(inst.cond_code().unwrap(), &arg(0)?, &imm_as_ctrl_ty()?)?,
             ctrl_ty.as_bool(),
@@ -349,6 +554,8 @@ where
                 IntCC::UnsignedGreaterThanOrEqual,
                 IntCC::UnsignedGreaterThan,
                 IntCC::UnsignedLessThanOrEqual,
+                IntCC::Overflow,
+                IntCC::NotOverflow,
             ] {
                 if icmp(*f, &arg0, &arg1)? {
                     state.set_iflag(*f);
@@ -356,65 +563,126 @@ where
             }
             ControlFlow::Continue
         }
-        Opcode::Imin => choose(Value::gt(&arg(1)?, &arg(0)?)?, arg(0)?, arg(1)?),
-        Opcode::Umin => choose(
-            Value::gt(
-                &arg(1)?.convert(ValueConversionKind::ToUnsigned)?,
-                &arg(0)?.convert(ValueConversionKind::ToUnsigned)?,
-            )?,
-            arg(0)?,
-            arg(1)?,
-        ),
-        Opcode::Imax => choose(Value::gt(&arg(0)?, &arg(1)?)?, arg(0)?, arg(1)?),
-        Opcode::Umax => choose(
-            Value::gt(
-                &arg(0)?.convert(ValueConversionKind::ToUnsigned)?,
-                &arg(1)?.convert(ValueConversionKind::ToUnsigned)?,
-            )?,
+        Opcode::Imin => binary(min, arg(0)?, arg(1)?)?,
+        Opcode::Umin => binary_unsigned(min, arg(0)?, arg(1)?)?,
+        Opcode::Imax => binary(max, arg(0)?, arg(1)?)?,
+        Opcode::Umax => binary_unsigned(max, arg(0)?, arg(1)?)?,
+        Opcode::AvgRound => binary(
+            |a, b| {
+                let ty = a.ty();
+                let sum = unsigned_int(a)? as u128 + unsigned_int(b)? as u128 + 1;
+                Value::int((sum >> 1) as i64, ty)
+            },
             arg(0)?,
             arg(1)?,
-        ),
-        Opcode::AvgRound => {
-            let sum = Value::add(arg(0)?, arg(1)?)?;
-            let one = Value::int(1, arg(0)?.ty())?;
-            let inc = Value::add(sum, one)?;
-            let two = Value::int(2, arg(0)?.ty())?;
-            binary(Value::div, inc, two)?
-        }
+        )?,
         Opcode::Iadd => binary(Value::add, arg(0)?, arg(1)?)?,
-        Opcode::UaddSat => unimplemented!("UaddSat"),
-        Opcode::SaddSat => unimplemented!("SaddSat"),
+        Opcode::UaddSat => binary_unsigned(Value::add_sat, arg(0)?, arg(1)?)?,
+        Opcode::SaddSat => binary(Value::add_sat, arg(0)?, arg(1)?)?,
         Opcode::Isub => binary(Value::sub, arg(0)?, arg(1)?)?,
-        Opcode::UsubSat => unimplemented!("UsubSat"),
-        Opcode::SsubSat => unimplemented!("SsubSat"),
-        Opcode::Ineg => binary(Value::sub, Value::int(0, ctrl_ty)?, arg(0)?)?,
-        Opcode::Iabs => unimplemented!("Iabs"),
+        Opcode::UsubSat => binary_unsigned(Value::sub_sat, arg(0)?, arg(1)?)?,
+        Opcode::SsubSat => binary(Value::sub_sat, arg(0)?, arg(1)?)?,
+        Opcode::Ineg => unary(|a| Value::sub(Value::int(0, a.ty())?, a), arg(0)?)?,
+        Opcode::Iabs => unary(
+            |a| {
+                let zero = Value::int(0, a.ty())?;
+                if Value::lt(&a, &zero)? {
+                    Value::sub(zero, a)
+                } else {
+                    Ok(a)
+                }
+            },
+            arg(0)?,
+        )?,
         Opcode::Imul => binary(Value::mul, arg(0)?, arg(1)?)?,
-        Opcode::Umulhi => unimplemented!("Umulhi"),
-        Opcode::Smulhi => unimplemented!("Smulhi"),
-        Opcode::Udiv => binary_unsigned(Value::div, arg(0)?, arg(1)?)?,
-        Opcode::Sdiv => binary(Value::div, arg(0)?, arg(1)?)?,
-        Opcode::Urem => binary_unsigned(Value::rem, arg(0)?, arg(1)?)?,
-        Opcode::Srem => binary(Value::rem, arg(0)?, arg(1)?)?,
+        Opcode::Umulhi => binary(
+            |a, b| {
+                let ty = a.ty();
+                let product = unsigned_int(a)? as u128 * unsigned_int(b)? as u128;
+                Value::int((product >> ty.bits()) as i64, ty)
+            },
+            arg(0)?,
+            arg(1)?,
+        )?,
+        Opcode::Smulhi => binary(
+            |a, b| {
+                let ty = a.ty();
+                let product = a.into_int()? as i128 * b.into_int()? as i128;
+                Value::int((product >> ty.bits()) as i64, ty)
+            },
+            arg(0)?,
+            arg(1)?,
+        )?,
+        Opcode::Udiv => binary_can_trap(Value::div, arg(0)?, arg(1)?, true)?,
+        Opcode::Sdiv => binary_can_trap(Value::div, arg(0)?, arg(1)?, false)?,
+        Opcode::Urem => binary_can_trap(Value::rem, arg(0)?, arg(1)?, true)?,
+        Opcode::Srem => binary_can_trap(Value::rem, arg(0)?, arg(1)?, false)?,
         Opcode::IaddImm => binary(Value::add, arg(0)?, imm_as_ctrl_ty()?)?,
         Opcode::ImulImm => binary(Value::mul, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::UdivImm => binary_unsigned(Value::div, arg(0)?, imm())?,
-        Opcode::SdivImm => binary(Value::div, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::UremImm => binary_unsigned(Value::rem, arg(0)?, imm())?,
-        Opcode::SremImm => binary(Value::rem, arg(0)?, imm_as_ctrl_ty()?)?,
+        Opcode::UdivImm => binary_can_trap(Value::div, arg(0)?, imm_as_ctrl_ty()?, true)?,
+        Opcode::SdivImm => binary_can_trap(Value::div, arg(0)?, imm_as_ctrl_ty()?, false)?,
+        Opcode::UremImm => binary_can_trap(Value::rem, arg(0)?, imm_as_ctrl_ty()?, true)?,
+        Opcode::SremImm => binary_can_trap(Value::rem, arg(0)?, imm_as_ctrl_ty()?, false)?,
         Opcode::IrsubImm => binary(Value::sub, imm_as_ctrl_ty()?, arg(0)?)?,
-        Opcode::IaddCin => unimplemented!("IaddCin"),
-        Opcode::IaddIfcin => unimplemented!("IaddIfcin"),
-        Opcode::IaddCout => unimplemented!("IaddCout"),
-        Opcode::IaddIfcout => unimplemented!("IaddIfcout"),
-        Opcode::IaddCarry => unimplemented!("IaddCarry"),
-        Opcode::IaddIfcarry => unimplemented!("IaddIfcarry"),
-        Opcode::IsubBin => unimplemented!("IsubBin"),
-        Opcode::IsubIfbin => unimplemented!("IsubIfbin"),
-        Opcode::IsubBout => unimplemented!("IsubBout"),
-        Opcode::IsubIfbout => unimplemented!("IsubIfbout"),
-        Opcode::IsubBorrow => unimplemented!("IsubBorrow"),
-        Opcode::IsubIfborrow => unimplemented!("IsubIfborrow"),
+        // The carry and borrow flags of the `*_if*` variants are represented by the
+        // `IntCC::UnsignedLessThan` flag: this is the condition that holds after comparing two
+        // integers when the subtraction borrows.
+        Opcode::IaddCin => {
+            let (sum, _) = add_with_carry(arg(0)?, arg(1)?, arg(2)?.into_bool()?)?;
+            assign(sum)
+        }
+        Opcode::IaddIfcin => {
+            let carry = state.has_iflag(IntCC::UnsignedLessThan);
+            let (sum, _) = add_with_carry(arg(0)?, arg(1)?, carry)?;
+            assign(sum)
+        }
+        Opcode::IaddCout => {
+            let (sum, carry) = add_with_carry(arg(0)?, arg(1)?, false)?;
+            ControlFlow::Assign(smallvec![sum, Value::bool(carry, types::B1)?])
+        }
+        Opcode::IaddIfcout => {
+            let (sum, carry) = add_with_carry(arg(0)?, arg(1)?, false)?;
+            set_carry_flag(state, carry);
+            ControlFlow::Assign(smallvec![sum, Value::bool(carry, types::B1)?])
+        }
+        Opcode::IaddCarry => {
+            let (sum, carry) = add_with_carry(arg(0)?, arg(1)?, arg(2)?.into_bool()?)?;
+            ControlFlow::Assign(smallvec![sum, Value::bool(carry, types::B1)?])
+        }
+        Opcode::IaddIfcarry => {
+            let carry_in = state.has_iflag(IntCC::UnsignedLessThan);
+            let (sum, carry) = add_with_carry(arg(0)?, arg(1)?, carry_in)?;
+            set_carry_flag(state, carry);
+            ControlFlow::Assign(smallvec![sum, Value::bool(carry, types::B1)?])
+        }
+        Opcode::IsubBin => {
+            let (difference, _) = sub_with_borrow(arg(0)?, arg(1)?, arg(2)?.into_bool()?)?;
+            assign(difference)
+        }
+        Opcode::IsubIfbin => {
+            let borrow = state.has_iflag(IntCC::UnsignedLessThan);
+            let (difference, _) = sub_with_borrow(arg(0)?, arg(1)?, borrow)?;
+            assign(difference)
+        }
+        Opcode::IsubBout => {
+            let (difference, borrow) = sub_with_borrow(arg(0)?, arg(1)?, false)?;
+            ControlFlow::Assign(smallvec![difference, Value::bool(borrow, types::B1)?])
+        }
+        Opcode::IsubIfbout => {
+            let (difference, borrow) = sub_with_borrow(arg(0)?, arg(1)?, false)?;
+            set_carry_flag(state, borrow);
+            ControlFlow::Assign(smallvec![difference, Value::bool(borrow, types::B1)?])
+        }
+        Opcode::IsubBorrow => {
+            let (difference, borrow) = sub_with_borrow(arg(0)?, arg(1)?, arg(2)?.into_bool()?)?;
+            ControlFlow::Assign(smallvec![difference, Value::bool(borrow, types::B1)?])
+        }
+        Opcode::IsubIfborrow => {
+            let borrow_in = state.has_iflag(IntCC::UnsignedLessThan);
+            let (difference, borrow) = sub_with_borrow(arg(0)?, arg(1)?, borrow_in)?;
+            set_carry_flag(state, borrow);
+            ControlFlow::Assign(smallvec![difference, Value::bool(borrow, types::B1)?])
+        }
         Opcode::Band => binary(Value::and, arg(0)?, arg(1)?)?,
         Opcode::Bor => binary(Value::or, arg(0)?, arg(1)?)?,
         Opcode::Bxor => binary(Value::xor, arg(0)?, arg(1)?)?,
@@ -425,25 +693,34 @@ where
         Opcode::BandImm => binary(Value::and, arg(0)?, imm_as_ctrl_ty()?)?,
         Opcode::BorImm => binary(Value::or, arg(0)?, imm_as_ctrl_ty()?)?,
         Opcode::BxorImm => binary(Value::xor, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::Rotl => binary(Value::rotl, arg(0)?, arg(1)?)?,
-        Opcode::Rotr => binary(Value::rotr, arg(0)?, arg(1)?)?,
-        Opcode::RotlImm => binary(Value::rotl, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::RotrImm => binary(Value::rotr, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::Ishl => binary(Value::shl, arg(0)?, arg(1)?)?,
-        Opcode::Ushr => binary(Value::ushr, arg(0)?, arg(1)?)?,
-        Opcode::Sshr => binary(Value::ishr, arg(0)?, arg(1)?)?,
-        Opcode::IshlImm => binary(Value::shl, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::UshrImm => binary(Value::ushr, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::SshrImm => binary(Value::ishr, arg(0)?, imm_as_ctrl_ty()?)?,
-        Opcode::Bitrev => unimplemented!("Bitrev"),
-        Opcode::Clz => unimplemented!("Clz"),
-        Opcode::Cls => unimplemented!("Cls"),
-        Opcode::Ctz => unimplemented!("Ctz"),
-        Opcode::Popcnt => unimplemented!("Popcnt"),
-        Opcode::Fcmp => assign(Value::bool(
-            fcmp(inst.fp_cond_code().unwrap(), &arg(0)?, &arg(1)?)?,
-            ctrl_ty.as_bool(),
-        )?),
+        Opcode::Rotl => shift(Value::rotl, arg(0)?, arg(1)?)?,
+        Opcode::Rotr => shift(Value::rotr, arg(0)?, arg(1)?)?,
+        Opcode::RotlImm => shift(Value::rotl, arg(0)?, imm())?,
+        Opcode::RotrImm => shift(Value::rotr, arg(0)?, imm())?,
+        Opcode::Ishl => shift(Value::shl, arg(0)?, arg(1)?)?,
+        Opcode::Ushr => shift(Value::ushr, arg(0)?, arg(1)?)?,
+        Opcode::Sshr => shift(Value::ishr, arg(0)?, arg(1)?)?,
+        Opcode::IshlImm => shift(Value::shl, arg(0)?, imm())?,
+        Opcode::UshrImm => shift(Value::ushr, arg(0)?, imm())?,
+        Opcode::SshrImm => shift(Value::ishr, arg(0)?, imm())?,
+        Opcode::Bitrev => unary(Value::reverse_bits, arg(0)?)?,
+        Opcode::Clz => unary(Value::leading_zeros, arg(0)?)?,
+        Opcode::Cls => unary(
+            |a| {
+                // Count the leading zeros of `a` with its sign bit cleared (or, for negative
+                // numbers, its ones), not including the sign bit itself.
+                let ty = a.ty();
+                let sign = Value::ishr(a.clone(), Value::int(ty.bits() as i64 - 1, ty)?)?;
+                let leading = Value::leading_zeros(Value::xor(a, sign)?)?;
+                Value::sub(leading, Value::int(1, ty)?)
+            },
+            arg(0)?,
+        )?,
+        Opcode::Ctz => unary(Value::trailing_zeros, arg(0)?)?,
+        Opcode::Popcnt => unary(Value::count_ones, arg(0)?)?,
+        Opcode::Fcmp => assign(compare(arg(0)?, arg(1)?, ctrl_ty, |l, r| {
+            fcmp(inst.fp_cond_code().unwrap(), l, r)
+        })?),
         Opcode::Ffcmp => {
             let arg0 = arg(0)?;
             let arg1 = arg(1)?;
@@ -474,29 +751,76 @@ where
         Opcode::Fsub => binary(Value::sub, arg(0)?, arg(1)?)?,
         Opcode::Fmul => binary(Value::mul, arg(0)?, arg(1)?)?,
         Opcode::Fdiv => binary(Value::div, arg(0)?, arg(1)?)?,
-        Opcode::Sqrt => unimplemented!("Sqrt"),
-        Opcode::Fma => unimplemented!("Fma"),
-        Opcode::Fneg => binary(Value::sub, Value::float(0, ctrl_ty)?, arg(0)?)?,
-        Opcode::Fabs => unimplemented!("Fabs"),
-        Opcode::Fcopysign => unimplemented!("Fcopysign"),
-        Opcode::Fmin => choose(
-            Value::is_nan(&arg(0)?)? || Value::lt(&arg(0)?, &arg(1)?)?,
+        Opcode::Sqrt => unary(Value::sqrt, arg(0)?)?,
+        Opcode::Fma => {
+            let (a, b, c) = (arg(0)?, arg(1)?, arg(2)?);
+            if ctrl_ty.is_vector() {
+                let lanes = extractlanes(&a, ctrl_ty)?
+                    .into_iter()
+                    .zip(extractlanes(&b, ctrl_ty)?)
+                    .zip(extractlanes(&c, ctrl_ty)?)
+                    .map(|((a, b), c)| Value::fma(a, b, c))
+                    .collect::<ValueResult<SimdVec<V>>>()?;
+                assign(vectorizelanes(&lanes, ctrl_ty)?)
+            } else {
+                assign(Value::fma(a, b, c)?)
+            }
+        }
+        Opcode::Fneg => unary(Value::neg, arg(0)?)?,
+        Opcode::Fabs => unary(Value::abs, arg(0)?)?,
+        Opcode::Fcopysign => binary(Value::copysign, arg(0)?, arg(1)?)?,
+        Opcode::Fmin => binary(
+            |a, b| {
+                if Value::is_nan(&a)? {
+                    Ok(a)
+                } else if Value::is_nan(&b)? {
+                    Ok(b)
+                } else if Value::eq(&a, &b)? {
+                    // Only differs from `a` for `fmin(0.0, -0.0)`, which is `-0.0`.
+                    Value::or(a, b)
+                } else if Value::lt(&a, &b)? {
+                    Ok(a)
+                } else {
+                    Ok(b)
+                }
+            },
             arg(0)?,
             arg(1)?,
-        ),
-        Opcode::FminPseudo => unimplemented!("FminPseudo"),
-        Opcode::Fmax => choose(
-            Value::is_nan(&arg(0)?)? || Value::gt(&arg(0)?, &arg(1)?)?,
+        )?,
+        Opcode::FminPseudo => binary(
+            |a, b| Ok(if Value::lt(&b, &a)? { b } else { a }),
             arg(0)?,
             arg(1)?,
-        ),
-        Opcode::FmaxPseudo => unimplemented!("FmaxPseudo"),
-        Opcode::Ceil => unimplemented!("Ceil"),
-        Opcode::Floor => unimplemented!("Floor"),
-        Opcode::Trunc => unimplemented!("Trunc"),
-        Opcode::Nearest => unimplemented!("Nearest"),
-        Opcode::IsNull => unimplemented!("IsNull"),
-        Opcode::IsInvalid => unimplemented!("IsInvalid"),
+        )?,
+        Opcode::Fmax => binary(
+            |a, b| {
+                if Value::is_nan(&a)? {
+                    Ok(a)
+                } else if Value::is_nan(&b)? {
+                    Ok(b)
+                } else if Value::eq(&a, &b)? {
+                    // Only differs from `a` for `fmax(-0.0, 0.0)`, which is `0.0`.
+                    Value::and(a, b)
+                } else if Value::gt(&a, &b)? {
+                    Ok(a)
+                } else {
+                    Ok(b)
+                }
+            },
+            arg(0)?,
+            arg(1)?,
+        )?,
+        Opcode::FmaxPseudo => binary(
+            |a, b| Ok(if Value::lt(&a, &b)? { b } else { a }),
+            arg(0)?,
+            arg(1)?,
+        )?,
+        Opcode::Ceil => unary(Value::ceil, arg(0)?)?,
+        Opcode::Floor => unary(Value::floor, arg(0)?)?,
+        Opcode::Trunc => unary(Value::trunc, arg(0)?)?,
+        Opcode::Nearest => unary(Value::nearest, arg(0)?)?,
+        Opcode::IsNull => assign(Value::bool(arg(0)?.into_int()? == 0, types::B1)?),
+        Opcode::IsInvalid => assign(Value::bool(arg(0)?.into_int()? == -1, types::B1)?),
         Opcode::Trueif => choose(
             state.has_iflag(inst.cond_code().unwrap()),
             Value::bool(true, ctrl_ty)?,
@@ -507,71 +831,289 @@ where
             Value::bool(true, ctrl_ty)?,
             Value::bool(false, ctrl_ty)?,
         ),
-        Opcode::Bitcast
-        | Opcode::RawBitcast
-        | Opcode::ScalarToVector
-        | Opcode::Breduce
-        | Opcode::Bextend
-        | Opcode::Bint
-        | Opcode::Bmask
-        | Opcode::Ireduce => assign(Value::convert(
-            arg(0)?,
-            ValueConversionKind::Exact(ctrl_ty),
-        )?),
-        Opcode::Snarrow => assign(Value::convert(
+        Opcode::Bitcast | Opcode::RawBitcast | Opcode::Breduce | Opcode::Bextend => assign(
+            Value::convert(arg(0)?, ValueConversionKind::Exact(ctrl_ty))?,
+        ),
+        Opcode::ScalarToVector => {
+            let lane_type = ctrl_ty.lane_type();
+            let mut lanes: SimdVec<V> = smallvec![arg(0)?];
+            for _ in 1..ctrl_ty.lane_count() {
+                lanes.push(zero(lane_type)?);
+            }
+            assign(vectorizelanes(&lanes, ctrl_ty)?)
+        }
+        Opcode::Bint => assign(Value::int(arg(0)?.into_bool()? as i64, ctrl_ty)?),
+        Opcode::Bmask => assign(Value::int(-(arg(0)?.into_bool()? as i64), ctrl_ty)?),
+        Opcode::Ireduce => assign(Value::convert(
             arg(0)?,
             ValueConversionKind::Truncate(ctrl_ty),
         )?),
-        Opcode::Sextend => assign(Value::convert(
-            arg(0)?,
-            ValueConversionKind::SignExtend(ctrl_ty),
-        )?),
-        Opcode::Unarrow => assign(Value::convert(
+        Opcode::Snarrow | Opcode::Unarrow => {
+            // The narrowed lanes of `x` are followed by those of `y`, saturating each of them.
+            let narrow_ty = ctrl_ty.split_lanes().unwrap();
+            let lane_type = narrow_ty.lane_type();
+            let (min, max) = if inst.opcode() == Opcode::Snarrow {
+                let half = 1i64 << (lane_type.bits() - 1);
+                (-half, half - 1)
+            } else {
+                (0, (1i64 << lane_type.bits()) - 1)
+            };
+            let lanes = extractlanes(&arg(0)?, ctrl_ty)?
+                .into_iter()
+                .chain(extractlanes(&arg(1)?, ctrl_ty)?)
+                .map(|lane| Value::int(lane.into_int()?.max(min).min(max), lane_type))
+                .collect::<ValueResult<SimdVec<V>>>()?;
+            assign(vectorizelanes(&lanes, narrow_ty)?)
+        }
+        Opcode::Sextend => assign(convert_lanes(
             arg(0)?,
-            ValueConversionKind::Truncate(ctrl_ty),
+            arg_ty(0),
+            ctrl_ty,
+            ValueConversionKind::SignExtend(ctrl_ty.lane_type()),
         )?),
-        Opcode::Uextend => assign(Value::convert(
+        Opcode::Uextend => assign(convert_lanes(
             arg(0)?,
-            ValueConversionKind::ZeroExtend(ctrl_ty),
+            arg_ty(0),
+            ctrl_ty,
+            ValueConversionKind::ZeroExtend(ctrl_ty.lane_type()),
         )?),
-        Opcode::Fpromote => assign(Value::convert(
+        Opcode::Fpromote => assign(convert_lanes(
             arg(0)?,
-            ValueConversionKind::Exact(ctrl_ty),
+            arg_ty(0),
+            ctrl_ty,
+            ValueConversionKind::Exact(ctrl_ty.lane_type()),
         )?),
-        Opcode::Fdemote => assign(Value::convert(
+        Opcode::Fdemote => assign(convert_lanes(
             arg(0)?,
-            ValueConversionKind::RoundNearestEven(ctrl_ty),
+            arg_ty(0),
+            ctrl_ty,
+            ValueConversionKind::RoundNearestEven(ctrl_ty.lane_type()),
         )?),
-        Opcode::Shuffle => unimplemented!("Shuffle"),
-        Opcode::Swizzle => unimplemented!("Swizzle"),
-        Opcode::Splat => unimplemented!("Splat"),
-        Opcode::LoadSplat => unimplemented!("LoadSplat"),
-        Opcode::Insertlane => unimplemented!("Insertlane"),
-        Opcode::Extractlane => unimplemented!("Extractlane"),
-        Opcode::VhighBits => unimplemented!("VhighBits"),
-        Opcode::Vsplit => unimplemented!("Vsplit"),
-        Opcode::Vconcat => unimplemented!("Vconcat"),
-        Opcode::Vselect => unimplemented!("Vselect"),
-        Opcode::VanyTrue => unimplemented!("VanyTrue"),
-        Opcode::VallTrue => unimplemented!("VallTrue"),
-        Opcode::SwidenLow => unimplemented!("SwidenLow"),
-        Opcode::SwidenHigh => unimplemented!("SwidenHigh"),
-        Opcode::UwidenLow => unimplemented!("UwidenLow"),
-        Opcode::UwidenHigh => unimplemented!("UwidenHigh"),
-        Opcode::FcvtToUint => unimplemented!("FcvtToUint"),
-        Opcode::FcvtToUintSat => unimplemented!("FcvtToUintSat"),
-        Opcode::FcvtToSint => unimplemented!("FcvtToSint"),
-        Opcode::FcvtToSintSat => unimplemented!("FcvtToSintSat"),
-        Opcode::FcvtFromUint => unimplemented!("FcvtFromUint"),
-        Opcode::FcvtFromSint => unimplemented!("FcvtFromSint"),
-        Opcode::Isplit => unimplemented!("Isplit"),
-        Opcode::Iconcat => unimplemented!("Iconcat"),
-        Opcode::AtomicRmw => unimplemented!("AtomicRmw"),
-        Opcode::AtomicCas => unimplemented!("AtomicCas"),
-        Opcode::AtomicLoad => unimplemented!("AtomicLoad"),
-        Opcode::AtomicStore => unimplemented!("AtomicStore"),
-        Opcode::Fence => unimplemented!("Fence"),
-        Opcode::WideningPairwiseDotProductS => unimplemented!("WideningPairwiseDotProductS"),
+        Opcode::Shuffle => {
+            if let InstructionData::Shuffle { mask, .. } = inst {
+                let mask = &state.get_current_function().dfg.immediates[mask];
+                let a = arg(0)?.into_array()?;
+                let b = arg(1)?.into_array()?;
+                let mut shuffled = [0; 16];
+                for (i, &index) in mask.as_slice().iter().enumerate().take(16) {
+                    shuffled[i] = match index as usize {
+                        i @ 0..=15 => a[i],
+                        i @ 16..=31 => b[i - 16],
+                        _ => 0,
+                    };
+                }
+                assign(Value::vector(shuffled, ctrl_ty)?)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::Swizzle => {
+            let x = arg(0)?.into_array()?;
+            let indexes = arg(1)?.into_array()?;
+            let mut swizzled = [0; 16];
+            for (i, &index) in indexes.iter().enumerate() {
+                swizzled[i] = x.get(index as usize).copied().unwrap_or(0);
+            }
+            assign(Value::vector(swizzled, ctrl_ty)?)
+        }
+        Opcode::Splat => {
+            let lanes: SimdVec<V> = smallvec![arg(0)?; ctrl_ty.lane_count() as usize];
+            assign(vectorizelanes(&lanes, ctrl_ty)?)
+        }
+        Opcode::LoadSplat => {
+            let address = sum(imm(), args()?)? as usize;
+            let loaded = state.load_heap(address, ctrl_ty.lane_type())?;
+            let lanes: SimdVec<V> = smallvec![loaded; ctrl_ty.lane_count() as usize];
+            assign(vectorizelanes(&lanes, ctrl_ty)?)
+        }
+        Opcode::Insertlane => {
+            let mut lanes = extractlanes(&arg(0)?, ctrl_ty)?;
+            let index = lane_index();
+            if index >= lanes.len() {
+                return Err(ValueError::InvalidValue(ctrl_ty).into());
+            }
+            lanes[index] = arg(1)?;
+            assign(vectorizelanes(&lanes, ctrl_ty)?)
+        }
+        Opcode::Extractlane => {
+            let lanes = extractlanes(&arg(0)?, ctrl_ty)?;
+            let lane = lanes
+                .get(lane_index())
+                .cloned()
+                .ok_or(ValueError::InvalidValue(ctrl_ty))?;
+            assign(lane)
+        }
+        Opcode::VhighBits => {
+            let vector_ty = arg_ty(0);
+            let lane_bits = vector_ty.lane_bits() as usize;
+            let bytes = arg(0)?.into_array()?;
+            let mut result = 0i64;
+            for (i, lane) in bytes.chunks(lane_bits / 8).enumerate() {
+                let high_bit = lane[lane.len() - 1] >> 7;
+                result |= (high_bit as i64) << i;
+            }
+            assign(Value::int(result, ctrl_ty)?)
+        }
+        Opcode::Vsplit => {
+            let lanes = extractlanes(&arg(0)?, ctrl_ty)?;
+            let half = ctrl_ty.half_vector().unwrap();
+            let (low, high) = lanes.split_at(lanes.len() / 2);
+            ControlFlow::Assign(smallvec![
+                vectorizelanes_or_scalar(low, half)?,
+                vectorizelanes_or_scalar(high, half)?,
+            ])
+        }
+        Opcode::Vconcat => {
+            let (x, y) = (arg(0)?, arg(1)?);
+            let double = ctrl_ty.by(2).unwrap();
+            let mut lanes = extractlanes_or_scalar(&x, ctrl_ty)?;
+            lanes.extend(extractlanes_or_scalar(&y, ctrl_ty)?);
+            assign(vectorizelanes(&lanes, double)?)
+        }
+        Opcode::Vselect => {
+            let lanes = extractlanes(&arg(0)?, ctrl_ty.as_bool())?
+                .into_iter()
+                .zip(extractlanes(&arg(1)?, ctrl_ty)?)
+                .zip(extractlanes(&arg(2)?, ctrl_ty)?)
+                .map(|((c, x), y)| Ok(if c.into_bool()? { x } else { y }))
+                .collect::<ValueResult<SimdVec<V>>>()?;
+            assign(vectorizelanes(&lanes, ctrl_ty)?)
+        }
+        Opcode::VanyTrue | Opcode::VallTrue => {
+            let lane_size = ctrl_ty.lane_type().bytes() as usize;
+            let bytes = arg(0)?.into_array()?;
+            let mut lanes = bytes
+                .chunks(lane_size)
+                .map(|lane| lane.iter().any(|b| *b != 0));
+            let result = if inst.opcode() == Opcode::VanyTrue {
+                lanes.any(|lane| lane)
+            } else {
+                lanes.all(|lane| lane)
+            };
+            assign(Value::bool(result, types::B1)?)
+        }
+        Opcode::SwidenLow | Opcode::SwidenHigh | Opcode::UwidenLow | Opcode::UwidenHigh => {
+            let wide_ty = ctrl_ty.merge_lanes().unwrap();
+            let lanes = extractlanes(&arg(0)?, ctrl_ty)?;
+            let (low, high) = lanes.split_at(lanes.len() / 2);
+            let (half, kind) = match inst.opcode() {
+                Opcode::SwidenLow => (low, ValueConversionKind::SignExtend as fn(Type) -> _),
+                Opcode::SwidenHigh => (high, ValueConversionKind::SignExtend as fn(Type) -> _),
+                Opcode::UwidenLow => (low, ValueConversionKind::ZeroExtend as fn(Type) -> _),
+                Opcode::UwidenHigh => (high, ValueConversionKind::ZeroExtend as fn(Type) -> _),
+                _ => unreachable!(),
+            };
+            let widened = half
+                .iter()
+                .map(|lane| lane.clone().convert(kind(wide_ty.lane_type())))
+                .collect::<ValueResult<SimdVec<V>>>()?;
+            assign(vectorizelanes(&widened, wide_ty)?)
+        }
+        Opcode::FcvtToUint | Opcode::FcvtToSint => {
+            let signed = inst.opcode() == Opcode::FcvtToSint;
+            let converted = map_lanes(arg(0)?, arg_ty(0), ctrl_ty, |lane, ty| {
+                fcvt_to_int(lane, ty, signed, false)
+            });
+            assign_or_trap(converted)?
+        }
+        Opcode::FcvtToUintSat | Opcode::FcvtToSintSat => {
+            let signed = inst.opcode() == Opcode::FcvtToSintSat;
+            assign(map_lanes(arg(0)?, arg_ty(0), ctrl_ty, |lane, ty| {
+                fcvt_to_int(lane, ty, signed, true)
+            })?)
+        }
+        Opcode::FcvtFromUint | Opcode::FcvtFromSint => {
+            let signed = inst.opcode() == Opcode::FcvtFromSint;
+            assign(map_lanes(arg(0)?, arg_ty(0), ctrl_ty, |lane, ty| {
+                let n = if signed {
+                    lane.into_int()? as f64
+                } else {
+                    unsigned_int(lane)? as f64
+                };
+                // Convert from the integer directly where possible to avoid double rounding.
+                let bits = match ty {
+                    types::F32 if signed => (lane_int_as_f32(n, true)).to_bits() as u64,
+                    types::F32 => (lane_int_as_f32(n, false)).to_bits() as u64,
+                    _ => n.to_bits(),
+                };
+                Value::float(bits, ty)
+            })?)
+        }
+        Opcode::Isplit => {
+            let x = arg(0)?;
+            let half = ctrl_ty.half_width().unwrap();
+            let n = x.into_int()?;
+            ControlFlow::Assign(smallvec![
+                Value::int(n, half)?,
+                Value::int(n >> half.bits(), half)?,
+            ])
+        }
+        Opcode::Iconcat => {
+            let double = ctrl_ty.double_width().unwrap();
+            let low = unsigned_int(arg(0)?)?;
+            let high = unsigned_int(arg(1)?)?;
+            if double.bits() > 64 {
+                return Err(
+                    ValueError::InvalidType(crate::value::ValueTypeClass::Integer, double).into(),
+                );
+            }
+            assign(Value::int((high << ctrl_ty.bits() | low) as i64, double)?)
+        }
+        // The interpreter runs on a single thread, so atomic operations are plain memory accesses.
+        Opcode::AtomicRmw => {
+            if let InstructionData::AtomicRmw { op, .. } = inst {
+                let address = unsigned_int(arg(0)?)? as usize;
+                let operand = arg(1)?;
+                let loaded = state.load_heap(address, ctrl_ty)?;
+                let updated = match op {
+                    AtomicRmwOp::Add => Value::add(loaded.clone(), operand)?,
+                    AtomicRmwOp::Sub => Value::sub(loaded.clone(), operand)?,
+                    AtomicRmwOp::And => Value::and(loaded.clone(), operand)?,
+                    AtomicRmwOp::Or => Value::or(loaded.clone(), operand)?,
+                    AtomicRmwOp::Xor => Value::xor(loaded.clone(), operand)?,
+                    AtomicRmwOp::Xchg => operand,
+                };
+                state.store_heap(address, updated)?;
+                assign(loaded)
+            } else {
+                unreachable!()
+            }
+        }
+        Opcode::AtomicCas => {
+            let address = unsigned_int(arg(0)?)? as usize;
+            let expected = arg(1)?;
+            let replacement = arg(2)?;
+            let loaded = state.load_heap(address, ctrl_ty)?;
+            if Value::eq(&loaded, &expected)? {
+                state.store_heap(address, replacement)?;
+            }
+            assign(loaded)
+        }
+        Opcode::AtomicLoad => {
+            let address = unsigned_int(arg(0)?)? as usize;
+            assign(state.load_heap(address, ctrl_ty)?)
+        }
+        Opcode::AtomicStore => {
+            let value = arg(0)?;
+            let address = unsigned_int(arg(1)?)? as usize;
+            state.store_heap(address, value)?;
+            ControlFlow::Continue
+        }
+        Opcode::Fence => ControlFlow::Continue,
+        Opcode::WideningPairwiseDotProductS => {
+            let x = extractlanes(&arg(0)?, types::I16X8)?;
+            let y = extractlanes(&arg(1)?, types::I16X8)?;
+            let products = x
+                .into_iter()
+                .zip(y)
+                .map(|(x, y)| Ok(x.into_int()? * y.into_int()?))
+                .collect::<ValueResult<SmallVec<[i64; 8]>>>()?;
+            let lanes = products
+                .chunks(2)
+                .map(|pair| Value::int(pair[0] + pair[1], types::I32))
+                .collect::<ValueResult<SimdVec<V>>>()?;
+            assign(vectorizelanes(&lanes, types::I32X4)?)
+        }
 
         // TODO: these instructions should be removed once the new backend makes these obsolete
         // (see https://github.com/bytecodealliance/wasmtime/issues/1936); additionally, the
@@ -610,7 +1152,9 @@ where
         | Opcode::X86Pminu
         | Opcode::X86Palignr
         | Opcode::X86ElfTlsGetAddr
-        | Opcode::X86MachoTlsGetAddr => unimplemented!("x86 instruction: {}", inst.opcode()),
+        | Opcode::X86MachoTlsGetAddr => {
+            return Err(StepError::UnsupportedInstruction(inst.opcode()))
+        }
     })
 }
 
@@ -620,6 +1164,18 @@ pub enum StepError {
     UnknownValue(ValueRef),
     #[error("unable to find the following function: {0}")]
     UnknownFunction(FuncRef),
+    #[error("unable to find a function at address {0}")]
+    UnknownFunctionAddress(u64),
+    #[error("unable to find a block at address {0}")]
+    UnknownBlockAddress(u64),
+    #[error("unable to resolve the following symbol: {0}")]
+    UnknownSymbol(ExternalName),
+    #[error("the function has no vmctx parameter")]
+    MissingVMContext,
+    #[error("jump table {0} has no entry {1}")]
+    InvalidJumpTableEntry(JumpTable, u64),
+    #[error("unable to interpret instruction: {0}")]
+    UnsupportedInstruction(Opcode),
     #[error("cannot step with these values")]
     ValueError(#[from] ValueError),
     #[error("failed to access memory")]
@@ -671,6 +1227,19 @@ pub enum CraneliftTrap {
     Resumable,
 }
 
+/// Assign the result of an operation which may trap, turning the [ValueError]s that correspond to
+/// traps into a [ControlFlow::Trap].
+fn assign_or_trap<'a, V>(result: ValueResult<V>) -> Result<ControlFlow<'a, V>, StepError> {
+    let code = match result {
+        Ok(value) => return Ok(ControlFlow::Assign(smallvec![value])),
+        Err(ValueError::IntegerDivisionByZero) => TrapCode::IntegerDivisionByZero,
+        Err(ValueError::IntegerOverflow) => TrapCode::IntegerOverflow,
+        Err(ValueError::BadConversionToInteger) => TrapCode::BadConversionToInteger,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(ControlFlow::Trap(CraneliftTrap::User(code)))
+}
+
 /// Compare two values using the given integer condition `code`.
 fn icmp<V>(code: IntCC, left: &V, right: &V) -> ValueResult<bool>
 where
@@ -699,8 +1268,13 @@ where
             &left.clone().convert(ValueConversionKind::ToUnsigned)?,
             &right.clone().convert(ValueConversionKind::ToUnsigned)?,
         )?,
-        IntCC::Overflow => unimplemented!("IntCC::Overflow"),
-        IntCC::NotOverflow => unimplemented!("IntCC::NotOverflow"),
+        // Whether the signed subtraction `left - right` overflows.
+        IntCC::Overflow | IntCC::NotOverflow => {
+            let bits = left.ty().bits();
+            let difference = left.clone().into_int()? as i128 - right.clone().into_int()? as i128;
+            let overflow = difference < -(1i128 << (bits - 1)) || difference >= 1i128 << (bits - 1);
+            overflow == (code == IntCC::Overflow)
+        }
     })
 }
 
@@ -734,3 +1308,386 @@ where
         }
     })
 }
+
+/// The lanes of a SIMD vector.
+type SimdVec<V> = SmallVec<[V; 4]>;
+
+/// Split a vector value of type `vector_type` into its lanes.
+fn extractlanes<V: Value>(x: &V, vector_type: Type) -> ValueResult<SimdVec<V>> {
+    let lane_type = vector_type.lane_type();
+    let lane_size = lane_type.bytes() as usize;
+    let bytes = x.clone().into_array()?;
+    bytes
+        .chunks(lane_size)
+        .take(vector_type.lane_count() as usize)
+        .map(|lane| {
+            let mut buffer = [0; 8];
+            buffer[..lane_size].copy_from_slice(lane);
+            let bits = u64::from_le_bytes(buffer);
+            if lane_type.is_int() {
+                V::int(bits as i64, lane_type)
+            } else if lane_type.is_float() {
+                V::float(bits, lane_type)
+            } else {
+                V::bool(bits != 0, lane_type)
+            }
+        })
+        .collect()
+}
+
+/// Combine `lanes` into a vector value of type `vector_type`; the inverse of [extractlanes].
+fn vectorizelanes<V: Value>(lanes: &[V], vector_type: Type) -> ValueResult<V> {
+    let lane_type = vector_type.lane_type();
+    let lane_size = lane_type.bytes() as usize;
+    if lanes.len() * lane_size > 16 {
+        return Err(ValueError::InvalidValue(vector_type));
+    }
+    let mut bytes = [0; 16];
+    for (i, lane) in lanes.iter().enumerate() {
+        let bits = if lane_type.is_float() {
+            let int_type = Type::int(lane_type.bits()).unwrap();
+            lane.clone()
+                .convert(ValueConversionKind::Exact(int_type))?
+                .into_int()? as u64
+        } else if lane_type.is_bool() {
+            if lane.clone().into_bool()? {
+                u64::max_value()
+            } else {
+                0
+            }
+        } else {
+            lane.clone().into_int()? as u64
+        };
+        bytes[i * lane_size..(i + 1) * lane_size].copy_from_slice(&bits.to_le_bytes()[..lane_size]);
+    }
+    V::vector(bytes, vector_type)
+}
+
+/// Like [extractlanes], but a scalar is considered a vector with a single lane.
+fn extractlanes_or_scalar<V: Value>(x: &V, ty: Type) -> ValueResult<SimdVec<V>> {
+    if ty.is_vector() {
+        extractlanes(x, ty)
+    } else {
+        Ok(smallvec![x.clone()])
+    }
+}
+
+/// Like [vectorizelanes], but a single lane of a scalar `ty` is returned as is.
+fn vectorizelanes_or_scalar<V: Value>(lanes: &[V], ty: Type) -> ValueResult<V> {
+    if ty.is_vector() {
+        vectorizelanes(lanes, ty)
+    } else {
+        Ok(lanes[0].clone())
+    }
+}
+
+/// Apply `op` to `x`, or to each of its lanes if `ty` is a vector type.
+fn unary_arith<V: Value>(x: V, ty: Type, op: fn(V) -> ValueResult<V>) -> ValueResult<V> {
+    if ty.is_vector() {
+        let lanes = extractlanes(&x, ty)?
+            .into_iter()
+            .map(op)
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, ty)
+    } else {
+        op(x)
+    }
+}
+
+/// Apply `op` to `x` and `y`, or to each pair of their lanes if `ty` is a vector type. If
+/// `unsigned` is set, the operands are converted to their unsigned form first.
+fn binary_arith<V: Value>(
+    x: V,
+    y: V,
+    ty: Type,
+    op: fn(V, V) -> ValueResult<V>,
+    unsigned: bool,
+) -> ValueResult<V> {
+    if ty.is_vector() {
+        let lanes = extractlanes(&x, ty)?
+            .into_iter()
+            .zip(extractlanes(&y, ty)?)
+            .map(|(x, y)| binary_arith(x, y, ty.lane_type(), op, unsigned))
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, ty)
+    } else if unsigned {
+        op(
+            x.convert(ValueConversionKind::ToUnsigned)?,
+            y.convert(ValueConversionKind::ToUnsigned)?,
+        )?
+        .convert(ValueConversionKind::ToSigned)
+    } else {
+        op(x, y)
+    }
+}
+
+/// Shift or rotate `x` (or each of its lanes, if `ty` is a vector type) by `amount`, which is
+/// first converted to the type of `x`'s lanes.
+fn shift_arith<V: Value>(
+    x: V,
+    amount: V,
+    ty: Type,
+    op: fn(V, V) -> ValueResult<V>,
+) -> ValueResult<V> {
+    let amount = V::int(amount.into_int()?, ty.lane_type())?;
+    if ty.is_vector() {
+        let lanes = extractlanes(&x, ty)?
+            .into_iter()
+            .map(|lane| op(lane, amount.clone()))
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, ty)
+    } else {
+        op(x, amount)
+    }
+}
+
+/// Compare `x` and `y` (or each pair of their lanes, if `ty` is a vector type) with `cmp`,
+/// returning a boolean (or a boolean vector).
+fn compare<V: Value>(
+    x: V,
+    y: V,
+    ty: Type,
+    cmp: impl Fn(&V, &V) -> ValueResult<bool>,
+) -> ValueResult<V> {
+    if ty.is_vector() {
+        let bool_ty = ty.as_bool();
+        let lanes = extractlanes(&x, ty)?
+            .into_iter()
+            .zip(extractlanes(&y, ty)?)
+            .map(|(x, y)| V::bool(cmp(&x, &y)?, bool_ty.lane_type()))
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, bool_ty)
+    } else {
+        V::bool(cmp(&x, &y)?, ty.as_bool())
+    }
+}
+
+/// Apply `f` to `x`, a value of type `from`, to produce a value of type `to`; vectors are converted
+/// lane by lane. `f` receives the lane type to convert to.
+fn map_lanes<V: Value>(
+    x: V,
+    from: Type,
+    to: Type,
+    f: impl Fn(V, Type) -> ValueResult<V>,
+) -> ValueResult<V> {
+    if from.is_vector() {
+        let lanes = extractlanes(&x, from)?
+            .into_iter()
+            .map(|lane| f(lane, to.lane_type()))
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, to)
+    } else {
+        f(x, to)
+    }
+}
+
+/// Convert `x` (or each of its lanes) using a [ValueConversionKind].
+fn convert_lanes<V: Value>(
+    x: V,
+    from: Type,
+    to: Type,
+    kind: ValueConversionKind,
+) -> ValueResult<V> {
+    if from.is_vector() {
+        let lanes = extractlanes(&x, from)?
+            .into_iter()
+            .map(|lane| lane.convert(clone_kind(&kind)))
+            .collect::<ValueResult<SimdVec<V>>>()?;
+        vectorizelanes(&lanes, to)
+    } else {
+        x.convert(kind)
+    }
+}
+
+/// [ValueConversionKind] is not `Clone`; rebuild it for each lane.
+fn clone_kind(kind: &ValueConversionKind) -> ValueConversionKind {
+    match kind {
+        ValueConversionKind::Exact(ty) => ValueConversionKind::Exact(*ty),
+        ValueConversionKind::Truncate(ty) => ValueConversionKind::Truncate(*ty),
+        ValueConversionKind::SignExtend(ty) => ValueConversionKind::SignExtend(*ty),
+        ValueConversionKind::ZeroExtend(ty) => ValueConversionKind::ZeroExtend(*ty),
+        ValueConversionKind::ToUnsigned => ValueConversionKind::ToUnsigned,
+        ValueConversionKind::ToSigned => ValueConversionKind::ToSigned,
+        ValueConversionKind::RoundNearestEven(ty) => ValueConversionKind::RoundNearestEven(*ty),
+    }
+}
+
+/// Whether a value that Cranelift tests for truthiness (e.g. in `brz`) is true: booleans are used
+/// as is and integers are true when they are not zero.
+fn is_true<V: Value>(x: V) -> ValueResult<bool> {
+    if x.ty().is_bool() {
+        x.into_bool()
+    } else {
+        Ok(x.into_int()? != 0)
+    }
+}
+
+/// Retrieve the bits of an integer value, without sign extension.
+fn unsigned_int<V: Value>(x: V) -> ValueResult<u64> {
+    let bits = x.ty().bits();
+    let n = x.into_int()? as u64;
+    Ok(if bits >= 64 { n } else { n & ((1 << bits) - 1) })
+}
+
+/// The zero value of a lane type.
+fn zero<V: Value>(ty: Type) -> ValueResult<V> {
+    if ty.is_float() {
+        V::float(0, ty)
+    } else if ty.is_bool() {
+        V::bool(false, ty)
+    } else {
+        V::int(0, ty)
+    }
+}
+
+/// The smaller of two values; see [max].
+fn min<V: Value>(a: V, b: V) -> ValueResult<V> {
+    Ok(if Value::gt(&b, &a)? { a } else { b })
+}
+
+/// The larger of two values; since this is only used for integers, unsigned comparisons are made
+/// by converting the operands to their unsigned form first.
+fn max<V: Value>(a: V, b: V) -> ValueResult<V> {
+    Ok(if Value::gt(&a, &b)? { a } else { b })
+}
+
+/// Add `x`, `y` and a carry bit, returning the (wrapping) sum and the carry out of it.
+fn add_with_carry<V: Value>(x: V, y: V, carry: bool) -> ValueResult<(V, bool)> {
+    let ty = x.ty();
+    let sum = unsigned_int(x)? as u128 + unsigned_int(y)? as u128 + carry as u128;
+    Ok((V::int(sum as i64, ty)?, sum >> ty.bits() != 0))
+}
+
+/// Subtract `y` and a borrow bit from `x`, returning the (wrapping) difference and whether the
+/// subtraction borrowed.
+fn sub_with_borrow<V: Value>(x: V, y: V, borrow: bool) -> ValueResult<(V, bool)> {
+    let ty = x.ty();
+    let x = unsigned_int(x)? as u128;
+    let y = unsigned_int(y)? as u128 + borrow as u128;
+    Ok((V::int(x.wrapping_sub(y) as i64, ty)?, x < y))
+}
+
+/// Record the carry (or borrow) out of an addition (or subtraction) in the integer flags.
+fn set_carry_flag<'a, V>(state: &mut dyn State<'a, V>, carry: bool) {
+    state.clear_flags();
+    if carry {
+        state.set_iflag(IntCC::UnsignedLessThan);
+    }
+}
+
+/// Convert a float to an integer of type `ty`, rounding towards zero. Unless `saturate` is set,
+/// NaNs and values out of the range of `ty` are errors, which correspond to traps.
+fn fcvt_to_int<V: Value>(x: V, ty: Type, signed: bool, saturate: bool) -> ValueResult<V> {
+    let f = x.into_float()?;
+    let bits = ty.bits() as i32;
+    let (min, max) = if signed {
+        (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1))
+    } else {
+        (0.0, 2f64.powi(bits))
+    };
+    if f.is_nan() {
+        return if saturate {
+            V::int(0, ty)
+        } else {
+            Err(ValueError::BadConversionToInteger)
+        };
+    }
+    let truncated = f.trunc();
+    if !saturate && !(min..max).contains(&truncated) {
+        return Err(ValueError::IntegerOverflow);
+    }
+    // Casts from floats to integers saturate in Rust.
+    let n = match (signed, bits) {
+        (true, 8) => truncated as i8 as i64,
+        (true, 16) => truncated as i16 as i64,
+        (true, 32) => truncated as i32 as i64,
+        (true, _) => truncated as i64,
+        (false, 8) => truncated as u8 as i64,
+        (false, 16) => truncated as u16 as i64,
+        (false, 32) => truncated as u32 as i64,
+        (false, _) => truncated as u64 as i64,
+    };
+    V::int(n, ty)
+}
+
+/// Round an integer, held exactly in `n`, to the nearest `f32`.
+fn lane_int_as_f32(n: f64, signed: bool) -> f32 {
+    // Integers up to 64 bits may not be exact in an `f64`; go through the integer types so that
+    // the value is only rounded once.
+    if signed {
+        (n as i64) as f32
+    } else {
+        (n as u64) as f32
+    }
+}
+
+/// Reinterpret a reference type as the integer type of the same width; references are integers to
+/// the interpreter.
+fn reference_as_int(ty: Type) -> Type {
+    Type::int(ty.bits()).unwrap_or(ty)
+}
+
+/// Copy a 128-bit constant into an array.
+fn to_array(bytes: &[u8]) -> ValueResult<[u8; 16]> {
+    let mut array = [0; 16];
+    if bytes.len() != 16 {
+        return Err(ValueError::InvalidValue(types::I8X16));
+    }
+    array.copy_from_slice(bytes);
+    Ok(array)
+}
+
+/// Compute the offset of a stack slot within its function's frame: slots are laid out one after
+/// the other, in order.
+fn stack_slot_offset(function: &Function, slot: StackSlot) -> i64 {
+    function
+        .stack_slots
+        .iter()
+        .take_while(|(s, _)| *s != slot)
+        .map(|(_, data)| data.size as i64)
+        .sum()
+}
+
+/// Compute the value of a global value; symbols (and only symbols) take the type `ty`.
+fn evaluate_global_value<'a, V: Value>(
+    state: &dyn State<'a, V>,
+    global_value: GlobalValue,
+    ty: Type,
+) -> Result<V, StepError> {
+    let function = state.get_current_function();
+    match &function.global_values[global_value] {
+        GlobalValueData::VMContext => {
+            let vmctx = function
+                .special_param(ArgumentPurpose::VMContext)
+                .ok_or(StepError::MissingVMContext)?;
+            state.get_value(vmctx).ok_or(StepError::UnknownValue(vmctx))
+        }
+        GlobalValueData::Load {
+            base,
+            offset,
+            global_type,
+            ..
+        } => {
+            let base = evaluate_global_value(state, *base, ty)?.into_int()?;
+            let offset: i64 = (*offset).into();
+            let address = base.wrapping_add(offset) as usize;
+            Ok(state.load_heap(address, *global_type)?)
+        }
+        GlobalValueData::IAddImm {
+            base,
+            offset,
+            global_type,
+        } => {
+            let base = evaluate_global_value(state, *base, *global_type)?;
+            let offset: i64 = (*offset).into();
+            Ok(Value::add(base, V::int(offset, *global_type)?)?)
+        }
+        GlobalValueData::Symbol { name, offset, .. } => {
+            let (address, _) = state
+                .get_function_by_name(&name.to_string())
+                .ok_or_else(|| StepError::UnknownSymbol(name.clone()))?;
+            let offset: i64 = (*offset).into();
+            Ok(V::int((address as i64).wrapping_add(offset), ty)?)
+        }
+    }
+}
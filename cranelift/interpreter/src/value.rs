@@ -22,6 +22,7 @@ pub trait Value: Clone + From<DataValue> {
     fn bool(b: bool, ty: Type) -> ValueResult<Self>;
     fn into_bool(self) -> ValueResult<bool>;
     fn vector(v: [u8; 16], ty: Type) -> ValueResult<Self>;
+    fn into_array(self) -> ValueResult<[u8; 16]>;
     fn convert(self, kind: ValueConversionKind) -> ValueResult<Self>;
 
     // Comparison.
@@ -44,6 +45,19 @@ pub trait Value: Clone + From<DataValue> {
     fn mul(self, other: Self) -> ValueResult<Self>;
     fn div(self, other: Self) -> ValueResult<Self>;
     fn rem(self, other: Self) -> ValueResult<Self>;
+    fn add_sat(self, other: Self) -> ValueResult<Self>;
+    fn sub_sat(self, other: Self) -> ValueResult<Self>;
+
+    // Floating point.
+    fn sqrt(self) -> ValueResult<Self>;
+    fn fma(self, a: Self, b: Self) -> ValueResult<Self>;
+    fn abs(self) -> ValueResult<Self>;
+    fn neg(self) -> ValueResult<Self>;
+    fn copysign(self, sign: Self) -> ValueResult<Self>;
+    fn ceil(self) -> ValueResult<Self>;
+    fn floor(self) -> ValueResult<Self>;
+    fn trunc(self) -> ValueResult<Self>;
+    fn nearest(self) -> ValueResult<Self>;
 
     // Bitwise.
     fn shl(self, other: Self) -> ValueResult<Self>;
@@ -55,6 +69,10 @@ pub trait Value: Clone + From<DataValue> {
     fn or(self, other: Self) -> ValueResult<Self>;
     fn xor(self, other: Self) -> ValueResult<Self>;
     fn not(self) -> ValueResult<Self>;
+    fn count_ones(self) -> ValueResult<Self>;
+    fn leading_zeros(self) -> ValueResult<Self>;
+    fn trailing_zeros(self) -> ValueResult<Self>;
+    fn reverse_bits(self) -> ValueResult<Self>;
 }
 
 #[derive(Error, Debug)]
@@ -65,6 +83,14 @@ pub enum ValueError {
     InvalidValue(Type),
     #[error("unable to convert to primitive integer")]
     InvalidInteger(#[from] std::num::TryFromIntError),
+    #[error("unable to operate on values of types {0} and {1}")]
+    MismatchedTypes(Type, Type),
+    #[error("integer division by zero")]
+    IntegerDivisionByZero,
+    #[error("integer overflow")]
+    IntegerOverflow,
+    #[error("unable to convert NaN to an integer")]
+    BadConversionToInteger,
 }
 
 #[derive(Debug)]
@@ -72,6 +98,7 @@ pub enum ValueTypeClass {
     Integer,
     Boolean,
     Float,
+    Vector,
 }
 
 impl Display for ValueTypeClass {
@@ -80,6 +107,7 @@ impl Display for ValueTypeClass {
             ValueTypeClass::Integer => write!(f, "integer"),
             ValueTypeClass::Boolean => write!(f, "boolean"),
             ValueTypeClass::Float => write!(f, "float"),
+            ValueTypeClass::Vector => write!(f, "vector"),
         }
     }
 }
@@ -87,7 +115,8 @@ impl Display for ValueTypeClass {
 #[derive(Debug)]
 pub enum ValueConversionKind {
     /// Throw a [ValueError] if an exact conversion to [Type] is not possible; e.g. in `i32` to
-    /// `i16`, convert `0x00001234` to `0x1234`.
+    /// `i16`, convert `0x00001234` to `0x1234`. Conversions between integers and floats of the same
+    /// size reinterpret the bits, as in `bitcast`.
     Exact(Type),
     /// Truncate the value to fit into the specified [Type]; e.g. in `i16` to `i8`, `0x1234` becomes
     /// `0x34`.
@@ -111,27 +140,30 @@ pub enum ValueConversionKind {
 
 /// Helper for creating match expressions over [DataValue].
 macro_rules! unary_match {
+    ( $op:ident($arg1:expr); [ $( $data_value_ty:ident ),* ] ) => {
+        match $arg1 {
+            $( DataValue::$data_value_ty(a) => { Ok(DataValue::$data_value_ty(a.$op() as _)) } )*
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Integer, $arg1.ty())),
+        }
+    };
     ( $op:tt($arg1:expr); [ $( $data_value_ty:ident ),* ] ) => {
         match $arg1 {
             $( DataValue::$data_value_ty(a) => { Ok(DataValue::$data_value_ty($op a)) } )*
-            _ => unimplemented!()
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Integer, $arg1.ty())),
         }
     };
 }
 macro_rules! binary_match {
-    ( $op:tt($arg1:expr, $arg2:expr); [ $( $data_value_ty:ident ),* ] ) => {
+    ( $op:ident($arg1:expr, $arg2:expr); [ $( $data_value_ty:ident ),* ] ) => {
         match ($arg1, $arg2) {
-            $( (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => { Ok(DataValue::$data_value_ty(a $op b)) } )*
-            _ => unimplemented!()
+            $( (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => { Ok(DataValue::$data_value_ty(a.$op(*b))) } )*
+            _ => Err(ValueError::MismatchedTypes(($arg1).ty(), ($arg2).ty())),
         }
     };
-    ( $op:tt($arg1:expr, $arg2:expr); unsigned integers ) => {
+    ( $op:tt($arg1:expr, $arg2:expr); [ $( $data_value_ty:ident ),* ] ) => {
         match ($arg1, $arg2) {
-            (DataValue::I8(a), DataValue::I8(b)) => { Ok(DataValue::I8((u8::try_from(*a)? $op u8::try_from(*b)?) as i8)) }
-            (DataValue::I16(a), DataValue::I16(b)) => { Ok(DataValue::I16((u16::try_from(*a)? $op u16::try_from(*b)?) as i16)) }
-            (DataValue::I32(a), DataValue::I32(b)) => { Ok(DataValue::I32((u32::try_from(*a)? $op u32::try_from(*b)?) as i32)) }
-            (DataValue::I64(a), DataValue::I64(b)) => { Ok(DataValue::I64((u64::try_from(*a)? $op u64::try_from(*b)?) as i64)) }
-            _ => { Err(ValueError::InvalidType(ValueTypeClass::Integer, if !($arg1).ty().is_int() { ($arg1).ty() } else { ($arg2).ty() })) }
+            $( (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => { Ok(DataValue::$data_value_ty(a $op b)) } )*
+            _ => Err(ValueError::MismatchedTypes(($arg1).ty(), ($arg2).ty())),
         }
     };
 }
@@ -139,11 +171,99 @@ macro_rules! comparison_match {
     ( $op:path[$arg1:expr, $arg2:expr]; [ $( $data_value_ty:ident ),* ] ) => {
         match ($arg1, $arg2) {
             $( (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => { Ok($op(a, b)) } )*
-            _ => unimplemented!("comparison: {:?}, {:?}", $arg1, $arg2)
+            (DataValue::F32(a), DataValue::F32(b)) => { Ok($op(&f32::from_bits(a.bits()), &f32::from_bits(b.bits()))) }
+            (DataValue::F64(a), DataValue::F64(b)) => { Ok($op(&f64::from_bits(a.bits()), &f64::from_bits(b.bits()))) }
+            _ => Err(ValueError::MismatchedTypes(($arg1).ty(), ($arg2).ty())),
+        }
+    };
+}
+
+/// Helper for applying an operation to the native Rust float underlying an `F32` or `F64`
+/// [DataValue]; the operation is written once but expanded for both `f32` and `f64`.
+macro_rules! float_match {
+    ( $arg1:expr $(, $b:ident = $arg2:expr)*; $a:ident => $body:expr ) => {
+        match $arg1 {
+            DataValue::F32(a) => {
+                let $a = f32::from_bits(a.bits());
+                $( let $b = match $arg2 {
+                    DataValue::F32(b) => f32::from_bits(b.bits()),
+                    other => return Err(ValueError::MismatchedTypes(types::F32, other.ty())),
+                }; )*
+                Ok(DataValue::F32(Ieee32::with_float($body)))
+            }
+            DataValue::F64(a) => {
+                let $a = f64::from_bits(a.bits());
+                $( let $b = match $arg2 {
+                    DataValue::F64(b) => f64::from_bits(b.bits()),
+                    other => return Err(ValueError::MismatchedTypes(types::F64, other.ty())),
+                }; )*
+                Ok(DataValue::F64(Ieee64::with_float($body)))
+            }
+            other => Err(ValueError::InvalidType(ValueTypeClass::Float, other.ty())),
         }
     };
 }
 
+/// Helper for checked integer division and remainder: division by zero and, for signed division,
+/// overflow (e.g. `i32::MIN / -1`) are reported as [ValueError]s.
+macro_rules! division_match {
+    ( $op:ident($arg1:expr, $arg2:expr); [ $( $data_value_ty:ident ),* ] ) => {
+        match ($arg1, $arg2) {
+            $( (DataValue::$data_value_ty(_), DataValue::$data_value_ty(0)) => Err(ValueError::IntegerDivisionByZero),
+               (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => {
+                   a.$op(*b).map(DataValue::$data_value_ty).ok_or(ValueError::IntegerOverflow)
+               } )*
+            (DataValue::F32(_), DataValue::F32(_)) | (DataValue::F64(_), DataValue::F64(_)) => {
+                float_match!($arg1, b = $arg2; a => a / b)
+            }
+            _ => Err(ValueError::MismatchedTypes(($arg1).ty(), ($arg2).ty())),
+        }
+    };
+}
+
+/// Helper for shifts and rotates: the amount is interpreted modulo the bit width of the shifted
+/// value, as Cranelift specifies.
+macro_rules! shift_match {
+    ( $op:ident($arg1:expr, $arg2:expr); [ $( $data_value_ty:ident: $unsigned:ty ),* ] ) => {
+        match ($arg1, $arg2) {
+            $( (DataValue::$data_value_ty(a), DataValue::$data_value_ty(b)) => {
+                Ok(DataValue::$data_value_ty((*a as $unsigned).$op(*b as u32) as _))
+            } )*
+            _ => Err(ValueError::MismatchedTypes(($arg1).ty(), ($arg2).ty())),
+        }
+    };
+}
+
+/// Rounding to the nearest integer with ties to even, which `std` does not provide.
+trait RoundNearestEven {
+    fn round_nearest_even(self) -> Self;
+}
+
+macro_rules! round_nearest_even_impl {
+    ( $ty:ty ) => {
+        impl RoundNearestEven for $ty {
+            fn round_nearest_even(self) -> Self {
+                if (self - self.trunc()).abs() == 0.5 {
+                    2.0 * (self / 2.0).round()
+                } else {
+                    self.round()
+                }
+            }
+        }
+    };
+}
+round_nearest_even_impl!(f32);
+round_nearest_even_impl!(f64);
+
+/// Bit patterns of all zeroes and all ones, used for boolean vector lanes.
+fn mask(bits: u16) -> u64 {
+    if bits >= 64 {
+        u64::max_value()
+    } else {
+        (1 << bits) - 1
+    }
+}
+
 impl Value for DataValue {
     fn ty(&self) -> Type {
         self.ty()
@@ -163,6 +283,10 @@ impl Value for DataValue {
             DataValue::I16(n) => Ok(n as i64),
             DataValue::I32(n) => Ok(n as i64),
             DataValue::I64(n) => Ok(n),
+            DataValue::U8(n) => Ok(n as i64),
+            DataValue::U16(n) => Ok(n as i64),
+            DataValue::U32(n) => Ok(n as i64),
+            DataValue::U64(n) => Ok(n as i64),
             _ => Err(ValueError::InvalidType(ValueTypeClass::Integer, self.ty())),
         }
     }
@@ -176,7 +300,11 @@ impl Value for DataValue {
     }
 
     fn into_float(self) -> ValueResult<f64> {
-        unimplemented!()
+        match self {
+            DataValue::F32(f) => Ok(f32::from_bits(f.bits()) as f64),
+            DataValue::F64(f) => Ok(f64::from_bits(f.bits())),
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Float, self.ty())),
+        }
     }
 
     fn is_nan(&self) -> ValueResult<bool> {
@@ -188,8 +316,11 @@ impl Value for DataValue {
     }
 
     fn bool(b: bool, ty: Type) -> ValueResult<Self> {
-        assert!(ty.is_bool());
-        Ok(DataValue::B(b))
+        if ty.is_bool() {
+            Ok(DataValue::B(b))
+        } else {
+            Err(ValueError::InvalidType(ValueTypeClass::Boolean, ty))
+        }
     }
 
     fn into_bool(self) -> ValueResult<bool> {
@@ -199,72 +330,100 @@ impl Value for DataValue {
         }
     }
 
-    fn vector(_v: [u8; 16], _ty: Type) -> ValueResult<Self> {
-        unimplemented!()
+    fn vector(v: [u8; 16], ty: Type) -> ValueResult<Self> {
+        if ty.is_vector() && ty.bytes() == 16 {
+            Ok(DataValue::V128(v))
+        } else {
+            Err(ValueError::InvalidType(ValueTypeClass::Vector, ty))
+        }
+    }
+
+    fn into_array(self) -> ValueResult<[u8; 16]> {
+        match self {
+            DataValue::V128(v) => Ok(v),
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Vector, self.ty())),
+        }
     }
 
     fn convert(self, kind: ValueConversionKind) -> ValueResult<Self> {
         Ok(match kind {
             ValueConversionKind::Exact(ty) => match (self, ty) {
-                // TODO a lot to do here: from bmask to ireduce to raw_bitcast...
-                (DataValue::I64(n), types::I32) => DataValue::I32(i32::try_from(n)?),
                 (DataValue::B(b), t) if t.is_bool() => DataValue::B(b),
-                (dv, _) => unimplemented!("conversion: {} -> {:?}", dv.ty(), kind),
-            },
-            ValueConversionKind::Truncate(ty) => match (self.ty(), ty) {
-                (types::I64, types::I32) => unimplemented!(),
-                (types::I64, types::I16) => unimplemented!(),
-                (types::I64, types::I8) => unimplemented!(),
-                (types::I32, types::I16) => unimplemented!(),
-                (types::I32, types::I8) => unimplemented!(),
-                (types::I16, types::I8) => unimplemented!(),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
-            },
-            ValueConversionKind::SignExtend(ty) => match (self.ty(), ty) {
-                (types::I8, types::I16) => unimplemented!(),
-                (types::I8, types::I32) => unimplemented!(),
-                (types::I8, types::I64) => unimplemented!(),
-                (types::I16, types::I32) => unimplemented!(),
-                (types::I16, types::I64) => unimplemented!(),
-                (types::I32, types::I64) => unimplemented!(),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
-            },
-            ValueConversionKind::ZeroExtend(ty) => match (self.ty(), ty) {
-                (types::I8, types::I16) => unimplemented!(),
-                (types::I8, types::I32) => unimplemented!(),
-                (types::I8, types::I64) => unimplemented!(),
-                (types::I16, types::I32) => unimplemented!(),
-                (types::I16, types::I64) => unimplemented!(),
-                (types::I32, types::I64) => unimplemented!(),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
+                (DataValue::V128(v), t) if t.is_vector() && t.bytes() == 16 => DataValue::V128(v),
+                (DataValue::I32(n), types::F32) => DataValue::F32(Ieee32::with_bits(n as u32)),
+                (DataValue::I64(n), types::F64) => DataValue::F64(Ieee64::with_bits(n as u64)),
+                (DataValue::F32(f), types::I32) => DataValue::I32(f.bits() as i32),
+                (DataValue::F64(f), types::I64) => DataValue::I64(f.bits() as i64),
+                (DataValue::F32(f), types::F32) => DataValue::F32(f),
+                (DataValue::F64(f), types::F64) => DataValue::F64(f),
+                (DataValue::F32(f), types::F64) => {
+                    DataValue::F64(Ieee64::with_float(f32::from_bits(f.bits()) as f64))
+                }
+                (dv, t) if t.is_int() && !t.is_vector() => {
+                    let n = dv.into_int()?;
+                    let converted = Self::int(n, t)?;
+                    if converted.clone().into_int()? != n {
+                        return Err(ValueError::InvalidValue(t));
+                    }
+                    converted
+                }
+                (_, t) => return Err(ValueError::InvalidValue(t)),
             },
+            ValueConversionKind::Truncate(ty) => {
+                if ty.bits() > self.ty().bits() {
+                    return Err(ValueError::InvalidValue(ty));
+                }
+                Self::int(self.into_int()?, ty)?
+            }
+            ValueConversionKind::SignExtend(ty) => {
+                if ty.bits() < self.ty().bits() {
+                    return Err(ValueError::InvalidValue(ty));
+                }
+                Self::int(self.into_int()?, ty)?
+            }
+            ValueConversionKind::ZeroExtend(ty) => {
+                if ty.bits() < self.ty().bits() {
+                    return Err(ValueError::InvalidValue(ty));
+                }
+                let bits = self.ty().bits();
+                Self::int((self.into_int()? as u64 & mask(bits)) as i64, ty)?
+            }
             ValueConversionKind::ToUnsigned => match self {
                 DataValue::I8(n) => DataValue::U8(n as u8),
                 DataValue::I16(n) => DataValue::U16(n as u16),
                 DataValue::I32(n) => DataValue::U32(n as u32),
                 DataValue::I64(n) => DataValue::U64(n as u64),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
+                DataValue::U8(_) | DataValue::U16(_) | DataValue::U32(_) | DataValue::U64(_) => {
+                    self
+                }
+                _ => return Err(ValueError::InvalidType(ValueTypeClass::Integer, self.ty())),
             },
             ValueConversionKind::ToSigned => match self {
                 DataValue::U8(n) => DataValue::I8(n as i8),
                 DataValue::U16(n) => DataValue::I16(n as i16),
                 DataValue::U32(n) => DataValue::I32(n as i32),
                 DataValue::U64(n) => DataValue::I64(n as i64),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
+                DataValue::I8(_) | DataValue::I16(_) | DataValue::I32(_) | DataValue::I64(_) => {
+                    self
+                }
+                _ => return Err(ValueError::InvalidType(ValueTypeClass::Integer, self.ty())),
             },
-            ValueConversionKind::RoundNearestEven(ty) => match (self.ty(), ty) {
-                (types::F64, types::F32) => unimplemented!(),
-                _ => unimplemented!("conversion: {} -> {:?}", self.ty(), kind),
+            ValueConversionKind::RoundNearestEven(ty) => match (self, ty) {
+                (DataValue::F64(f), types::F32) => {
+                    DataValue::F32(Ieee32::with_float(f64::from_bits(f.bits()) as f32))
+                }
+                (DataValue::F32(f), types::F32) => DataValue::F32(f),
+                (_, t) => return Err(ValueError::InvalidValue(t)),
             },
         })
     }
 
     fn eq(&self, other: &Self) -> ValueResult<bool> {
-        comparison_match!(PartialEq::eq[&self, &other]; [I8, I16, I32, I64, U8, U16, U32, U64, F32, F64])
+        comparison_match!(PartialEq::eq[&self, &other]; [I8, I16, I32, I64, U8, U16, U32, U64, B])
     }
 
     fn gt(&self, other: &Self) -> ValueResult<bool> {
-        comparison_match!(PartialOrd::gt[&self, &other]; [I8, I16, I32, I64, U8, U16, U32, U64, F32, F64])
+        comparison_match!(PartialOrd::gt[&self, &other]; [I8, I16, I32, I64, U8, U16, U32, U64])
     }
 
     fn uno(&self, other: &Self) -> ValueResult<bool> {
@@ -272,58 +431,195 @@ impl Value for DataValue {
     }
 
     fn add(self, other: Self) -> ValueResult<Self> {
-        binary_match!(+(&self, &other); [I8, I16, I32, I64]) // TODO: floats must handle NaNs, +/-0
+        match (&self, &other) {
+            (DataValue::F32(_), _) | (DataValue::F64(_), _) => {
+                float_match!(&self, b = &other; a => a + b)
+            }
+            _ => binary_match!(wrapping_add(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64]),
+        }
     }
 
     fn sub(self, other: Self) -> ValueResult<Self> {
-        binary_match!(-(&self, &other); [I8, I16, I32, I64]) // TODO: floats must handle NaNs, +/-0
+        match (&self, &other) {
+            (DataValue::F32(_), _) | (DataValue::F64(_), _) => {
+                float_match!(&self, b = &other; a => a - b)
+            }
+            _ => binary_match!(wrapping_sub(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64]),
+        }
     }
 
     fn mul(self, other: Self) -> ValueResult<Self> {
-        binary_match!(*(&self, &other); [I8, I16, I32, I64])
+        match (&self, &other) {
+            (DataValue::F32(_), _) | (DataValue::F64(_), _) => {
+                float_match!(&self, b = &other; a => a * b)
+            }
+            _ => binary_match!(wrapping_mul(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64]),
+        }
     }
 
     fn div(self, other: Self) -> ValueResult<Self> {
-        binary_match!(/(&self, &other); [I8, I16, I32, I64])
+        division_match!(checked_div(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64])
     }
 
     fn rem(self, other: Self) -> ValueResult<Self> {
-        binary_match!(%(&self, &other); [I8, I16, I32, I64])
+        // The remainder of `MIN / -1` is zero; only the quotient overflows.
+        let wrapping_rem = |a: &Self, b: &Self| -> ValueResult<Self> {
+            binary_match!(wrapping_rem(a, b); [I8, I16, I32, I64, U8, U16, U32, U64])
+        };
+        match division_match!(checked_rem(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64]) {
+            Err(ValueError::IntegerOverflow) => wrapping_rem(&self, &other),
+            result => result,
+        }
+    }
+
+    fn add_sat(self, other: Self) -> ValueResult<Self> {
+        binary_match!(saturating_add(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+
+    fn sub_sat(self, other: Self) -> ValueResult<Self> {
+        binary_match!(saturating_sub(&self, &other); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+
+    fn sqrt(self) -> ValueResult<Self> {
+        float_match!(&self; a => a.sqrt())
+    }
+
+    fn fma(self, a: Self, b: Self) -> ValueResult<Self> {
+        float_match!(&self, y = &a, z = &b; x => x.mul_add(y, z))
+    }
+
+    fn abs(self) -> ValueResult<Self> {
+        // Operate on the bits directly so that NaN payloads are preserved.
+        match self {
+            DataValue::F32(f) => Ok(DataValue::F32(Ieee32::with_bits(f.bits() & !(1 << 31)))),
+            DataValue::F64(f) => Ok(DataValue::F64(Ieee64::with_bits(f.bits() & !(1 << 63)))),
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Float, self.ty())),
+        }
+    }
+
+    fn neg(self) -> ValueResult<Self> {
+        match self {
+            DataValue::F32(f) => Ok(DataValue::F32(f.neg())),
+            DataValue::F64(f) => Ok(DataValue::F64(f.neg())),
+            _ => Err(ValueError::InvalidType(ValueTypeClass::Float, self.ty())),
+        }
+    }
+
+    fn copysign(self, sign: Self) -> ValueResult<Self> {
+        match (&self, &sign) {
+            (DataValue::F32(a), DataValue::F32(b)) => Ok(DataValue::F32(Ieee32::with_bits(
+                (a.bits() & !(1 << 31)) | (b.bits() & (1 << 31)),
+            ))),
+            (DataValue::F64(a), DataValue::F64(b)) => Ok(DataValue::F64(Ieee64::with_bits(
+                (a.bits() & !(1 << 63)) | (b.bits() & (1 << 63)),
+            ))),
+            _ => Err(ValueError::MismatchedTypes(self.ty(), sign.ty())),
+        }
+    }
+
+    fn ceil(self) -> ValueResult<Self> {
+        float_match!(&self; a => a.ceil())
+    }
+
+    fn floor(self) -> ValueResult<Self> {
+        float_match!(&self; a => a.floor())
+    }
+
+    fn trunc(self) -> ValueResult<Self> {
+        float_match!(&self; a => a.trunc())
+    }
+
+    fn nearest(self) -> ValueResult<Self> {
+        float_match!(&self; a => a.round_nearest_even())
     }
 
     fn shl(self, other: Self) -> ValueResult<Self> {
-        binary_match!(<<(&self, &other); [I8, I16, I32, I64])
+        shift_match!(wrapping_shl(&self, &other); [I8: u8, I16: u16, I32: u32, I64: u64, U8: u8, U16: u16, U32: u32, U64: u64])
     }
 
     fn ushr(self, other: Self) -> ValueResult<Self> {
-        binary_match!(>>(&self, &other); unsigned integers)
+        shift_match!(wrapping_shr(&self, &other); [I8: u8, I16: u16, I32: u32, I64: u64, U8: u8, U16: u16, U32: u32, U64: u64])
     }
 
     fn ishr(self, other: Self) -> ValueResult<Self> {
-        binary_match!(>>(&self, &other); [I8, I16, I32, I64])
+        shift_match!(wrapping_shr(&self, &other); [I8: i8, I16: i16, I32: i32, I64: i64, U8: i8, U16: i16, U32: i32, U64: i64])
     }
 
-    fn rotl(self, _other: Self) -> ValueResult<Self> {
-        unimplemented!()
+    fn rotl(self, other: Self) -> ValueResult<Self> {
+        shift_match!(rotate_left(&self, &other); [I8: u8, I16: u16, I32: u32, I64: u64, U8: u8, U16: u16, U32: u32, U64: u64])
     }
 
-    fn rotr(self, _other: Self) -> ValueResult<Self> {
-        unimplemented!()
+    fn rotr(self, other: Self) -> ValueResult<Self> {
+        shift_match!(rotate_right(&self, &other); [I8: u8, I16: u16, I32: u32, I64: u64, U8: u8, U16: u16, U32: u32, U64: u64])
     }
 
     fn and(self, other: Self) -> ValueResult<Self> {
-        binary_match!(&(&self, &other); [I8, I16, I32, I64])
+        bitwise(self, other, |a, b| a & b)
     }
 
     fn or(self, other: Self) -> ValueResult<Self> {
-        binary_match!(|(&self, &other); [I8, I16, I32, I64])
+        bitwise(self, other, |a, b| a | b)
     }
 
     fn xor(self, other: Self) -> ValueResult<Self> {
-        binary_match!(^(&self, &other); [I8, I16, I32, I64])
+        bitwise(self, other, |a, b| a ^ b)
     }
 
     fn not(self) -> ValueResult<Self> {
-        unary_match!(!(&self); [I8, I16, I32, I64])
+        match self {
+            DataValue::B(b) => Ok(DataValue::B(!b)),
+            DataValue::F32(f) => Ok(DataValue::F32(Ieee32::with_bits(!f.bits()))),
+            DataValue::F64(f) => Ok(DataValue::F64(Ieee64::with_bits(!f.bits()))),
+            DataValue::V128(mut v) => {
+                v.iter_mut().for_each(|b| *b = !*b);
+                Ok(DataValue::V128(v))
+            }
+            _ => unary_match!(!(&self); [I8, I16, I32, I64, U8, U16, U32, U64]),
+        }
     }
+
+    fn count_ones(self) -> ValueResult<Self> {
+        unary_match!(count_ones(&self); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+
+    fn leading_zeros(self) -> ValueResult<Self> {
+        unary_match!(leading_zeros(&self); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+
+    fn trailing_zeros(self) -> ValueResult<Self> {
+        unary_match!(trailing_zeros(&self); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+
+    fn reverse_bits(self) -> ValueResult<Self> {
+        unary_match!(reverse_bits(&self); [I8, I16, I32, I64, U8, U16, U32, U64])
+    }
+}
+
+/// Apply a bitwise operation to two values of the same type: integers, booleans, floats (on their
+/// bits) and vectors (byte by byte) are all accepted.
+fn bitwise(left: DataValue, right: DataValue, op: fn(u64, u64) -> u64) -> ValueResult<DataValue> {
+    Ok(match (&left, &right) {
+        (DataValue::B(a), DataValue::B(b)) => DataValue::B(op(*a as u64, *b as u64) & 1 == 1),
+        (DataValue::F32(a), DataValue::F32(b)) => {
+            DataValue::F32(Ieee32::with_bits(
+                op(a.bits() as u64, b.bits() as u64) as u32
+            ))
+        }
+        (DataValue::F64(a), DataValue::F64(b)) => {
+            DataValue::F64(Ieee64::with_bits(op(a.bits(), b.bits())))
+        }
+        (DataValue::V128(a), DataValue::V128(b)) => {
+            let mut v = [0; 16];
+            for (i, byte) in v.iter_mut().enumerate() {
+                *byte = op(a[i] as u64, b[i] as u64) as u8;
+            }
+            DataValue::V128(v)
+        }
+        _ if left.ty() == right.ty() && left.ty().is_int() => {
+            let ty = left.ty();
+            let result = op(left.into_int()? as u64, right.into_int()? as u64);
+            DataValue::int(result as i64, ty)?
+        }
+        _ => return Err(ValueError::MismatchedTypes(left.ty(), right.ty())),
+    })
 }
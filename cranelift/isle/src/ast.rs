@@ -0,0 +1,306 @@
+//! Abstract syntax tree of ISLE source, as produced by the parser.
+
+use crate::lexer::Pos;
+
+/// A sequence of top-level definitions, possibly from several files.
+#[derive(Clone, Debug, Default)]
+pub struct Defs {
+    /// The definitions, in source order.
+    pub defs: Vec<Def>,
+    /// The names of the files the definitions come from.
+    pub filenames: Vec<String>,
+}
+
+/// A top-level definition.
+#[derive(Clone, Debug)]
+pub enum Def {
+    /// A type definition.
+    Type(Type),
+    /// A term declaration.
+    Decl(Decl),
+    /// A rule.
+    Rule(Rule),
+    /// An extractor macro.
+    Extractor(Extractor),
+    /// An external constructor or extractor.
+    Extern(Extern),
+    /// An implicit conversion.
+    Converter(Converter),
+}
+
+/// An identifier: a type, term or variable name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ident(pub String, pub Pos);
+
+/// A type definition: `(type Name [extern] value)`.
+#[derive(Clone, Debug)]
+pub struct Type {
+    /// The name of the type.
+    pub name: Ident,
+    /// Whether the type is already defined in Rust.
+    pub is_extern: bool,
+    /// The definition of the type.
+    pub ty: TypeValue,
+    /// The position of the definition.
+    pub pos: Pos,
+}
+
+/// The definition of a type.
+#[derive(Clone, Debug)]
+pub enum TypeValue {
+    /// `(primitive RustType)`: an opaque Rust type.
+    Primitive(Ident),
+    /// `(enum variants...)`: an enum.
+    Enum(Vec<Variant>),
+}
+
+/// An enum variant: a bare name, or `(Name (field Type)...)`.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    /// The name of the variant.
+    pub name: Ident,
+    /// The fields of the variant.
+    pub fields: Vec<Field>,
+}
+
+/// A field of an enum variant.
+#[derive(Clone, Debug)]
+pub struct Field {
+    /// The name of the field.
+    pub name: Ident,
+    /// The type of the field.
+    pub ty: Ident,
+}
+
+/// A term declaration: `(decl [partial] Name (ArgTypes...) RetType)`.
+#[derive(Clone, Debug)]
+pub struct Decl {
+    /// The name of the term.
+    pub term: Ident,
+    /// The types of the arguments.
+    pub arg_tys: Vec<Ident>,
+    /// The type of the result.
+    pub ret_ty: Ident,
+    /// Whether the term's constructor may fail.
+    pub partial: bool,
+    /// The position of the declaration.
+    pub pos: Pos,
+}
+
+/// A rule: `(rule [prio] pattern (if-let pattern expr)... expr)`.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// The left-hand side.
+    pub pattern: Pattern,
+    /// Additional conditions, matched in order after the left-hand side.
+    pub iflets: Vec<IfLet>,
+    /// The right-hand side.
+    pub expr: Expr,
+    /// The priority; rules with higher priorities are tried first.
+    pub prio: i64,
+    /// The position of the rule.
+    pub pos: Pos,
+}
+
+/// An `(if-let pattern expr)` clause of a rule.
+#[derive(Clone, Debug)]
+pub struct IfLet {
+    /// The pattern the value of the expression has to match.
+    pub pattern: Pattern,
+    /// The expression to evaluate.
+    pub expr: Expr,
+    /// The position of the clause.
+    pub pos: Pos,
+}
+
+/// An extractor macro: `(extractor (Name params...) template)`.
+#[derive(Clone, Debug)]
+pub struct Extractor {
+    /// The term being defined.
+    pub term: Ident,
+    /// The names of the parameters, which stand for argument patterns in the template.
+    pub args: Vec<Ident>,
+    /// The pattern the term expands to.
+    pub template: Pattern,
+    /// The position of the definition.
+    pub pos: Pos,
+}
+
+/// A pattern, on the left-hand side of a rule or in an `if-let` clause.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// A variable: binds the value on first use, and matches only equal
+    /// values afterwards.
+    Var {
+        /// The variable.
+        var: Ident,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// `var @ pattern`: binds the value to a variable and matches it with a
+    /// subpattern.
+    BindPattern {
+        /// The variable.
+        var: Ident,
+        /// The subpattern.
+        subpat: Box<Pattern>,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// An integer constant.
+    ConstInt {
+        /// The value.
+        val: i128,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// `$Name`: a constant defined in Rust.
+    ConstPrim {
+        /// The Rust expression of the constant.
+        val: Ident,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// A term application, matched with the term's extractor.
+    Term {
+        /// The term.
+        sym: Ident,
+        /// The argument patterns.
+        args: Vec<Pattern>,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// `_`: matches anything.
+    Wildcard {
+        /// The position of the pattern.
+        pos: Pos,
+    },
+    /// `(and patterns...)`: matches values that match all the subpatterns.
+    And {
+        /// The subpatterns.
+        subpats: Vec<Pattern>,
+        /// The position of the pattern.
+        pos: Pos,
+    },
+}
+
+impl Pattern {
+    /// The position of the pattern.
+    pub fn pos(&self) -> &Pos {
+        match self {
+            Pattern::Var { pos, .. }
+            | Pattern::BindPattern { pos, .. }
+            | Pattern::ConstInt { pos, .. }
+            | Pattern::ConstPrim { pos, .. }
+            | Pattern::Term { pos, .. }
+            | Pattern::Wildcard { pos }
+            | Pattern::And { pos, .. } => pos,
+        }
+    }
+}
+
+/// An expression, on the right-hand side of a rule or in an `if-let` clause.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    /// A term application, evaluated with the term's constructor.
+    Term {
+        /// The term.
+        sym: Ident,
+        /// The arguments.
+        args: Vec<Expr>,
+        /// The position of the expression.
+        pos: Pos,
+    },
+    /// A variable bound by the pattern or a `let`.
+    Var {
+        /// The variable.
+        name: Ident,
+        /// The position of the expression.
+        pos: Pos,
+    },
+    /// An integer constant.
+    ConstInt {
+        /// The value.
+        val: i128,
+        /// The position of the expression.
+        pos: Pos,
+    },
+    /// `$Name`: a constant defined in Rust.
+    ConstPrim {
+        /// The Rust expression of the constant.
+        val: Ident,
+        /// The position of the expression.
+        pos: Pos,
+    },
+    /// `(let ((var Type expr)...) body)`.
+    Let {
+        /// The bindings, evaluated in order.
+        defs: Vec<LetDef>,
+        /// The body, in which the bindings are visible.
+        body: Box<Expr>,
+        /// The position of the expression.
+        pos: Pos,
+    },
+}
+
+impl Expr {
+    /// The position of the expression.
+    pub fn pos(&self) -> &Pos {
+        match self {
+            Expr::Term { pos, .. }
+            | Expr::Var { pos, .. }
+            | Expr::ConstInt { pos, .. }
+            | Expr::ConstPrim { pos, .. }
+            | Expr::Let { pos, .. } => pos,
+        }
+    }
+}
+
+/// A binding of a `let` expression.
+#[derive(Clone, Debug)]
+pub struct LetDef {
+    /// The variable, or `_` to evaluate the expression for its side effects only.
+    pub var: Ident,
+    /// The type of the variable.
+    pub ty: Ident,
+    /// The value.
+    pub val: Box<Expr>,
+    /// The position of the binding.
+    pub pos: Pos,
+}
+
+/// An external constructor or extractor, implemented in Rust.
+#[derive(Clone, Debug)]
+pub enum Extern {
+    /// `(extern constructor Term rust_fn)`.
+    Constructor {
+        /// The term.
+        term: Ident,
+        /// The name of the Rust method.
+        func: Ident,
+        /// The position of the definition.
+        pos: Pos,
+    },
+    /// `(extern extractor Term rust_fn)`.
+    Extractor {
+        /// The term.
+        term: Ident,
+        /// The name of the Rust method.
+        func: Ident,
+        /// The position of the definition.
+        pos: Pos,
+    },
+}
+
+/// An implicit conversion: `(convert From To term)`.
+#[derive(Clone, Debug)]
+pub struct Converter {
+    /// The type converted from.
+    pub inner_ty: Ident,
+    /// The type converted to.
+    pub outer_ty: Ident,
+    /// The conversion term, with signature `(From) To`.
+    pub term: Ident,
+    /// The position of the definition.
+    pub pos: Pos,
+}
@@ -0,0 +1,435 @@
+//! Generation of Rust code from type-checked ISLE rules.
+//!
+//! Each term with an internal constructor becomes a function
+//! `constructor_<term>` that tries the rules defining the term in priority
+//! order. Each rule is compiled to nested `if`/`if let` statements matching
+//! its left-hand side and `if-let` clauses, followed by the evaluation of its
+//! right-hand side. Expressions are flattened into a sequence of `let`
+//! statements, so that calls taking the context never nest.
+
+use crate::sema::{
+    ConstructorKind, Expr, ExtractorKind, Pattern, Rule, Term, TermEnv, TermId, TermKind, Type,
+    TypeEnv, TypeId, VarId,
+};
+use std::fmt::Write;
+
+/// Generates the Rust code for a type-checked program.
+pub fn codegen(tyenv: &TypeEnv, termenv: &TermEnv, filenames: &[String]) -> String {
+    let gen = Codegen { tyenv, termenv };
+    let mut code = String::new();
+    gen.generate_header(&mut code, filenames);
+    gen.generate_ctx_trait(&mut code);
+    gen.generate_internal_types(&mut code);
+    gen.generate_constructors(&mut code);
+    code
+}
+
+struct Codegen<'a> {
+    tyenv: &'a TypeEnv,
+    termenv: &'a TermEnv,
+}
+
+/// The state of the code generation of one rule.
+struct RuleBody<'a> {
+    rule: &'a Rule,
+    code: String,
+    indent: usize,
+    open_blocks: usize,
+    next_temp: usize,
+}
+
+impl<'a> RuleBody<'a> {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+
+    /// Emits a line opening a block that stays open until the end of the rule.
+    fn open(&mut self, line: &str) {
+        self.line(line);
+        self.indent += 1;
+        self.open_blocks += 1;
+    }
+
+    fn temp(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.next_temp);
+        self.next_temp += 1;
+        name
+    }
+
+    fn var(&self, var: VarId) -> String {
+        let name: String = self.rule.vars[var.0]
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("v_{}_{}", name, var.0)
+    }
+}
+
+/// Formats a list of values as a tuple, without parentheses for a single value.
+fn tuple(items: &[String]) -> String {
+    match items.len() {
+        1 => items[0].clone(),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+/// The name of the Rust function implementing a term's internal constructor.
+fn constructor_name(term: &Term) -> String {
+    let name: String = term
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("constructor_{}", name)
+}
+
+impl<'a> Codegen<'a> {
+    fn ty(&self, ty: TypeId) -> &str {
+        self.tyenv.types[ty.0].rust_name()
+    }
+
+    fn term(&self, term: TermId) -> &Term {
+        &self.termenv.terms[term.0]
+    }
+
+    fn generate_header(&self, code: &mut String, filenames: &[String]) {
+        writeln!(code, "// GENERATED BY ISLE. DO NOT EDIT!").unwrap();
+        writeln!(code, "//").unwrap();
+        writeln!(
+            code,
+            "// Generated automatically from the instruction-selection DSL code in:"
+        )
+        .unwrap();
+        for file in filenames {
+            writeln!(code, "// - {}", file).unwrap();
+        }
+    }
+
+    fn generate_ctx_trait(&self, code: &mut String) {
+        writeln!(code).unwrap();
+        writeln!(
+            code,
+            "/// Context during lowering: an implementation of this trait must be provided"
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "/// with all external constructors and extractors. A mutable borrow is passed"
+        )
+        .unwrap();
+        writeln!(code, "/// along through all the lowering logic.").unwrap();
+        writeln!(code, "pub trait Context {{").unwrap();
+        for term in &self.termenv.terms {
+            let (constructor, extractor) = match &term.kind {
+                TermKind::Decl {
+                    constructor,
+                    extractor,
+                    ..
+                } => (constructor, extractor),
+                TermKind::EnumVariant { .. } => continue,
+            };
+            if let Some(ConstructorKind::External { name }) = constructor {
+                let args: Vec<String> = term
+                    .arg_tys
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &ty)| format!("arg{}: {}", i, self.ty(ty)))
+                    .collect();
+                let ret = if term.is_fallible_constructor() {
+                    format!("Option<{}>", self.ty(term.ret_ty))
+                } else {
+                    self.ty(term.ret_ty).to_string()
+                };
+                writeln!(
+                    code,
+                    "    fn {}(&mut self, {}) -> {};",
+                    name,
+                    args.join(", "),
+                    ret
+                )
+                .unwrap();
+            }
+            if let Some(ExtractorKind::External { name }) = extractor {
+                let rets: Vec<String> = term
+                    .arg_tys
+                    .iter()
+                    .map(|&ty| self.ty(ty).to_string())
+                    .collect();
+                writeln!(
+                    code,
+                    "    fn {}(&mut self, arg0: {}) -> Option<{}>;",
+                    name,
+                    self.ty(term.ret_ty),
+                    tuple(&rets)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(code, "}}").unwrap();
+    }
+
+    fn generate_internal_types(&self, code: &mut String) {
+        for ty in &self.tyenv.types {
+            let (name, variants, pos) = match ty {
+                Type::Enum {
+                    name,
+                    is_extern: false,
+                    variants,
+                    pos,
+                } => (name, variants, pos),
+                _ => continue,
+            };
+            writeln!(code).unwrap();
+            writeln!(code, "/// Internal type {}: defined at {}.", name, pos).unwrap();
+            writeln!(code, "#[derive(Clone, Debug)]").unwrap();
+            writeln!(code, "pub enum {} {{", name).unwrap();
+            for variant in variants {
+                if variant.fields.is_empty() {
+                    writeln!(code, "    {},", variant.name).unwrap();
+                    continue;
+                }
+                writeln!(code, "    {} {{", variant.name).unwrap();
+                for field in &variant.fields {
+                    writeln!(code, "        {}: {},", field.name, self.ty(field.ty)).unwrap();
+                }
+                writeln!(code, "    }},").unwrap();
+            }
+            writeln!(code, "}}").unwrap();
+        }
+    }
+
+    fn generate_constructors(&self, code: &mut String) {
+        for (idx, term) in self.termenv.terms.iter().enumerate() {
+            match &term.kind {
+                TermKind::Decl {
+                    constructor: Some(ConstructorKind::Internal),
+                    ..
+                } => {}
+                _ => continue,
+            }
+
+            // Higher priorities first; the sort is stable, so rules of equal
+            // priority keep their source order.
+            let mut rules: Vec<&Rule> = self
+                .termenv
+                .rules
+                .iter()
+                .filter(|r| r.root_term == TermId(idx))
+                .collect();
+            rules.sort_by_key(|r| -r.prio);
+
+            let args: Vec<String> = term
+                .arg_tys
+                .iter()
+                .enumerate()
+                .map(|(i, &ty)| format!(", arg{}: {}", i, self.ty(ty)))
+                .collect();
+            writeln!(code).unwrap();
+            writeln!(
+                code,
+                "// Generated as internal constructor for term {}.",
+                term.name
+            )
+            .unwrap();
+            writeln!(
+                code,
+                "pub fn {}<C: Context>(ctx: &mut C{}) -> Option<{}> {{",
+                constructor_name(term),
+                args.concat(),
+                self.ty(term.ret_ty)
+            )
+            .unwrap();
+            for rule in rules {
+                code.push_str(&self.generate_rule(rule));
+            }
+            writeln!(code, "    None").unwrap();
+            writeln!(code, "}}").unwrap();
+        }
+    }
+
+    fn generate_rule(&self, rule: &Rule) -> String {
+        let mut body = RuleBody {
+            rule,
+            code: String::new(),
+            indent: 1,
+            open_blocks: 0,
+            next_temp: 0,
+        };
+        body.line(&format!("// Rule at {}.", rule.pos));
+        body.open("{");
+        for (i, arg) in rule.args.iter().enumerate() {
+            self.generate_pattern(&mut body, arg, &format!("arg{}", i));
+        }
+        for iflet in &rule.iflets {
+            let value = self.generate_expr(&mut body, &iflet.rhs);
+            let name = body.temp("pattern");
+            body.line(&format!("let {} = {};", name, value));
+            self.generate_pattern(&mut body, &iflet.lhs, &name);
+        }
+        let value = self.generate_expr(&mut body, &rule.rhs);
+        body.line(&format!("return Some({});", value));
+        while body.open_blocks > 0 {
+            body.indent -= 1;
+            body.open_blocks -= 1;
+            body.line("}");
+        }
+        body.code
+    }
+
+    /// Emits the code matching the value in the local `value` against a
+    /// pattern; the rest of the rule is emitted inside the opened blocks.
+    fn generate_pattern(&self, body: &mut RuleBody, pat: &Pattern, value: &str) {
+        match pat {
+            Pattern::Wildcard(_) => {}
+            Pattern::BindPattern(_, var, subpat) => {
+                let var = body.var(*var);
+                body.line(&format!("let {} = {}.clone();", var, value));
+                self.generate_pattern(body, subpat, &var);
+            }
+            Pattern::Var(_, var) => {
+                let var = body.var(*var);
+                body.open(&format!("if {} == {} {{", value, var));
+            }
+            Pattern::ConstInt(_, val) => body.open(&format!("if {} == {} {{", value, val)),
+            Pattern::ConstPrim(_, val) => body.open(&format!("if {} == {} {{", value, val)),
+            Pattern::And(_, subpats) => {
+                for subpat in subpats {
+                    self.generate_pattern(body, subpat, value);
+                }
+            }
+            Pattern::Term(_, term, args) => {
+                let term = self.term(*term);
+                let names: Vec<String> = args.iter().map(|_| body.temp("pattern")).collect();
+                match &term.kind {
+                    TermKind::EnumVariant { ty, variant } => {
+                        let (enum_name, variant) = match &self.tyenv.types[ty.0] {
+                            Type::Enum { name, variants, .. } => (name, &variants[*variant]),
+                            _ => unreachable!(),
+                        };
+                        if variant.fields.is_empty() {
+                            body.open(&format!(
+                                "if let {}::{} = &{} {{",
+                                enum_name, variant.name, value
+                            ));
+                        } else {
+                            let fields: Vec<String> = variant
+                                .fields
+                                .iter()
+                                .zip(&names)
+                                .map(|(f, n)| format!("{}: {}", f.name, n))
+                                .collect();
+                            body.open(&format!(
+                                "if let {}::{} {{ {} }} = &{} {{",
+                                enum_name,
+                                variant.name,
+                                fields.join(", "),
+                                value
+                            ));
+                            for name in &names {
+                                body.line(&format!("let {} = {}.clone();", name, name));
+                            }
+                        }
+                    }
+                    TermKind::Decl {
+                        extractor: Some(ExtractorKind::External { name }),
+                        ..
+                    } => {
+                        let binding = if names.is_empty() {
+                            "()".to_string()
+                        } else {
+                            tuple(&names)
+                        };
+                        body.open(&format!(
+                            "if let Some({}) = ctx.{}({}.clone()) {{",
+                            binding, name, value
+                        ));
+                    }
+                    _ => unreachable!("extractor macros are expanded during type checking"),
+                }
+                for (arg, name) in args.iter().zip(&names) {
+                    self.generate_pattern(body, arg, name);
+                }
+            }
+        }
+    }
+
+    /// Emits the code evaluating an expression, and returns a Rust expression
+    /// for its value. A failing constructor makes the rule not match, so that
+    /// the next one is tried.
+    fn generate_expr(&self, body: &mut RuleBody, expr: &Expr) -> String {
+        match expr {
+            Expr::Var(_, var) => format!("{}.clone()", body.var(*var)),
+            Expr::ConstInt(_, val) => val.to_string(),
+            Expr::ConstPrim(_, val) => val.clone(),
+            Expr::Let(_, bindings, value) => {
+                for (var, ty, val) in bindings {
+                    let val = self.generate_expr(body, val);
+                    match var {
+                        Some(var) => {
+                            let var = body.var(*var);
+                            body.line(&format!("let {}: {} = {};", var, self.ty(*ty), val));
+                        }
+                        None => body.line(&format!("let _ = {};", val)),
+                    }
+                }
+                self.generate_expr(body, value)
+            }
+            Expr::Term(_, term_id, args) => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.generate_expr(body, arg))
+                    .collect();
+                let term = self.term(*term_id);
+                let call = match &term.kind {
+                    TermKind::EnumVariant { ty, variant } => {
+                        let (enum_name, variant) = match &self.tyenv.types[ty.0] {
+                            Type::Enum { name, variants, .. } => (name, &variants[*variant]),
+                            _ => unreachable!(),
+                        };
+                        if variant.fields.is_empty() {
+                            format!("{}::{}", enum_name, variant.name)
+                        } else {
+                            let fields: Vec<String> = variant
+                                .fields
+                                .iter()
+                                .zip(&args)
+                                .map(|(f, a)| format!("{}: {}", f.name, a))
+                                .collect();
+                            format!(
+                                "{}::{} {{ {} }}",
+                                enum_name,
+                                variant.name,
+                                fields.join(", ")
+                            )
+                        }
+                    }
+                    TermKind::Decl {
+                        constructor: Some(ConstructorKind::External { name }),
+                        ..
+                    } => format!("ctx.{}({})", name, args.join(", ")),
+                    TermKind::Decl {
+                        constructor: Some(ConstructorKind::Internal),
+                        ..
+                    } => {
+                        let mut all_args = vec!["ctx".to_string()];
+                        all_args.extend(args);
+                        format!("{}({})", constructor_name(term), all_args.join(", "))
+                    }
+                    _ => unreachable!("checked during type checking"),
+                };
+                let name = body.temp("expr");
+                if term.is_fallible_constructor() {
+                    body.open(&format!("if let Some({}) = {} {{", name, call));
+                } else {
+                    body.line(&format!("let {} = {};", name, call));
+                }
+                name
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Compilation of ISLE source files to Rust code.
+
+use crate::ast::Defs;
+use crate::codegen::codegen;
+use crate::error::{Error, Result};
+use crate::lexer::{lex, Pos};
+use crate::parser::parse;
+use crate::sema::{TermEnv, TypeEnv};
+use std::path::Path;
+
+/// Parses the source of one ISLE file.
+pub fn parse_str(filename: &str, text: &str) -> Result<Defs> {
+    let tokens = lex(filename, text)?;
+    let eof = match tokens.last() {
+        Some((pos, _)) => pos.clone(),
+        None => Pos {
+            file: filename.into(),
+            line: 1,
+            col: 1,
+        },
+    };
+    Ok(Defs {
+        defs: parse(tokens, eof)?,
+        filenames: vec![filename.to_string()],
+    })
+}
+
+/// Type-checks parsed definitions and generates the corresponding Rust code.
+pub fn compile(defs: &Defs) -> Result<String> {
+    let mut tyenv = TypeEnv::from_ast(defs)?;
+    let termenv = TermEnv::from_ast(&mut tyenv, defs)?;
+    Ok(codegen(&tyenv, &termenv, &defs.filenames))
+}
+
+/// Compiles a set of ISLE source files, which share a single namespace, to
+/// Rust code. The file names are recorded in the generated code as given.
+pub fn compile_files<P: AsRef<Path>>(inputs: &[P]) -> Result<String> {
+    let mut all = Defs::default();
+    for input in inputs {
+        let path = input.as_ref();
+        let filename = path.display().to_string();
+        let text = std::fs::read_to_string(path).map_err(|e| Error::Io {
+            filename: filename.clone(),
+            msg: e.to_string(),
+        })?;
+        let defs = parse_str(&filename, &text)?;
+        all.defs.extend(defs.defs);
+        all.filenames.extend(defs.filenames);
+    }
+    compile(&all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_rules() {
+        let defs = parse_str(
+            "test.isle",
+            "(type u32 (primitive u32))
+             (type Cmp (enum Eq (Ult (bits u32))))
+             (decl partial small (u32) u32)
+             (extern constructor small small_imm)
+             (decl is_zero () u32)
+             (extern extractor is_zero is_zero)
+             (decl cmp (u32 u32) Cmp)
+             (rule 1 (cmp x x) (Cmp.Eq))
+             (rule (cmp (is_zero) y) (if-let z (small y)) (Cmp.Ult z))
+             (rule 2 (cmp 0 y) (let ((a u32 (small y))) (Cmp.Ult a)))",
+        )
+        .unwrap();
+        let code = compile(&defs).unwrap();
+
+        assert!(code.contains("fn small_imm(&mut self, arg0: u32) -> Option<u32>;"));
+        assert!(code.contains("fn is_zero(&mut self, arg0: u32) -> Option<()>;"));
+        assert!(code.contains("pub enum Cmp {"));
+        assert!(code.contains(
+            "pub fn constructor_cmp<C: Context>(ctx: &mut C, arg0: u32, arg1: u32) -> Option<Cmp> {"
+        ));
+        // Rules are tried by decreasing priority.
+        let prio2 = code.find("test.isle:10").unwrap();
+        let prio1 = code.find("test.isle:8").unwrap();
+        let prio0 = code.find("test.isle:9").unwrap();
+        assert!(prio2 < prio1 && prio1 < prio0);
+        // A failing constructor, on the right-hand side as in an `if-let`
+        // clause, only makes the rule not match.
+        assert!(!code.contains("?;"));
+        assert_eq!(
+            code.matches("if let Some(expr0) = ctx.small_imm(v_y_0.clone()) {")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn failing_internal_constructor_falls_through() {
+        let defs = parse_str(
+            "test.isle",
+            "(type u32 (primitive u32))
+             (decl g (u32) u32)
+             (rule (g 0) 1)
+             (decl f (u32) u32)
+             (rule 1 (f x) (g x))
+             (rule (f x) x)",
+        )
+        .unwrap();
+        let code = compile(&defs).unwrap();
+
+        let f = &code[code.find("pub fn constructor_f").unwrap()..];
+        let first = f.find("test.isle:5").unwrap();
+        let call = f
+            .find("if let Some(expr0) = constructor_g(ctx, v_x_0.clone()) {")
+            .unwrap();
+        let second = f.find("test.isle:6").unwrap();
+        assert!(first < call && call < second);
+    }
+
+    #[test]
+    fn compile_errors() {
+        let defs = parse_str(
+            "test.isle",
+            "(type u32 (primitive u32))
+             (decl f (u32) u32)
+             (rule (f x) y)
+             (rule (f x) (g x))",
+        )
+        .unwrap();
+        match compile(&defs) {
+            Err(Error::Errors(errors)) => assert_eq!(errors.len(), 2),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
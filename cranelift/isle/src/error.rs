@@ -0,0 +1,56 @@
+//! Error types.
+
+use crate::lexer::Pos;
+use std::fmt;
+
+/// An error encountered while compiling ISLE source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An I/O error while reading a source file.
+    Io {
+        /// The file being read.
+        filename: String,
+        /// The description of the underlying error.
+        msg: String,
+    },
+    /// A lexing or parsing error.
+    Parse {
+        /// The error message.
+        msg: String,
+        /// The location of the error.
+        pos: Pos,
+    },
+    /// A type or semantic error.
+    Type {
+        /// The error message.
+        msg: String,
+        /// The location of the error.
+        pos: Pos,
+    },
+    /// Several errors.
+    Errors(Vec<Error>),
+}
+
+/// A result of an ISLE compilation step.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { filename, msg } => write!(f, "{}: {}", filename, msg),
+            Error::Parse { msg, pos } => write!(f, "{}: parse error: {}", pos, msg),
+            Error::Type { msg, pos } => write!(f, "{}: type error: {}", pos, msg),
+            Error::Errors(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
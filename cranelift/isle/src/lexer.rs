@@ -0,0 +1,180 @@
+//! Lexer for the ISLE language.
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::rc::Rc;
+
+/// A source position.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pos {
+    /// The name of the source file.
+    pub file: Rc<str>,
+    /// The line number, starting at 1.
+    pub line: usize,
+    /// The column number, starting at 1.
+    pub col: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// A token of ISLE source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `@`
+    At,
+    /// A symbol: a term, type or variable name, or a keyword.
+    Symbol(String),
+    /// An integer literal.
+    Int(i128),
+}
+
+/// Returns true if `c` can appear in a symbol.
+fn is_sym_char(c: char) -> bool {
+    match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' => true,
+        '_' | '.' | '$' | '-' | '+' | '*' | '/' | '<' | '>' | '=' | '!' | '?' | '&' | '|' | '%'
+        | '^' | '~' | ':' => true,
+        _ => false,
+    }
+}
+
+/// Splits ISLE source text into tokens, each paired with its position.
+pub fn lex(filename: &str, text: &str) -> Result<Vec<(Pos, Token)>> {
+    let file: Rc<str> = filename.into();
+    let mut tokens = vec![];
+    let chars: Vec<char> = text.chars().collect();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+
+    while i < chars.len() {
+        let pos = Pos {
+            file: file.clone(),
+            line,
+            col,
+        };
+        let c = chars[i];
+        match c {
+            '\n' => {
+                i += 1;
+                line += 1;
+                col = 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                i += 1;
+                col += 1;
+                continue;
+            }
+            ';' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '(' => tokens.push((pos, Token::LParen)),
+            ')' => tokens.push((pos, Token::RParen)),
+            '@' => tokens.push((pos, Token::At)),
+            c if is_sym_char(c) => {
+                let start = i;
+                while i < chars.len() && is_sym_char(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                col += i - start;
+                tokens.push((pos.clone(), classify_word(word, pos)?));
+                continue;
+            }
+            c => {
+                return Err(Error::Parse {
+                    msg: format!("unexpected character '{}'", c),
+                    pos,
+                })
+            }
+        }
+        i += 1;
+        col += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Distinguishes integer literals from symbols.
+fn classify_word(word: String, pos: Pos) -> Result<Token> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, &word[..]),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Token::Symbol(word));
+    }
+    let digits = digits.replace('_', "");
+    let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i128::from_str_radix(bin, 2)
+    } else {
+        digits.parse::<i128>()
+    };
+    match parsed {
+        Ok(value) => Ok(Token::Int(if negative { -value } else { value })),
+        Err(_) => Err(Error::Parse {
+            msg: format!("invalid integer literal '{}'", word),
+            pos,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        lex("test.isle", text)
+            .unwrap()
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect()
+    }
+
+    #[test]
+    fn lex_basic() {
+        assert_eq!(
+            tokens("(rule 1 (a x @ $I64) ; comment\n -2 0x10 0b11 b.c)"),
+            vec![
+                Token::LParen,
+                Token::Symbol("rule".to_string()),
+                Token::Int(1),
+                Token::LParen,
+                Token::Symbol("a".to_string()),
+                Token::Symbol("x".to_string()),
+                Token::At,
+                Token::Symbol("$I64".to_string()),
+                Token::RParen,
+                Token::Int(-2),
+                Token::Int(16),
+                Token::Int(3),
+                Token::Symbol("b.c".to_string()),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_positions() {
+        let toks = lex("f", "(a\n  b)").unwrap();
+        assert_eq!((toks[2].0.line, toks[2].0.col), (2, 3));
+    }
+
+    #[test]
+    fn lex_error() {
+        assert!(lex("f", "(a #)").is_err());
+        assert!(lex("f", "0xzz").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! ISLE: Instruction Selection / Lowering Expressions.
+//!
+//! ISLE is a small term-rewriting language in which Cranelift backends write
+//! their lowering rules: patterns over CLIF instructions on the left-hand
+//! side, and the machine instructions to produce on the right-hand side. The
+//! ISLE compiler in this crate checks the rules and translates them into Rust
+//! source code, which the backend then includes; it is meant to be run from a
+//! build script.
+//!
+//! # Language overview
+//!
+//! An ISLE program is a sequence of S-expression definitions. Comments start
+//! with `;` and run to the end of the line.
+//!
+//! ## Types
+//!
+//! ```text
+//! (type u32 (primitive u32))
+//! (type ALUOp extern (enum Add32 Add64))
+//! (type Operand (enum (Reg (reg Reg)) (Imm (imm u64))))
+//! ```
+//!
+//! Primitive types name a Rust type directly. Enums are either generated by
+//! the compiler, or `extern`, in which case they must already exist on the
+//! Rust side (only the variants used by the rules need to be listed). All
+//! types must implement `Clone`; types used in equality patterns must also
+//! implement `PartialEq`.
+//!
+//! ## Terms
+//!
+//! Every operation is a *term*, declared with its argument and result types:
+//!
+//! ```text
+//! (decl iadd (Value Value) Inst)
+//! (decl partial imm12_from_u64 (u64) Imm12)
+//! ```
+//!
+//! A term can be used in two directions. As a *constructor*, it builds its
+//! result from its arguments, on the right-hand side of a rule. As an
+//! *extractor*, it takes a value of the result type apart into the
+//! arguments, on the left-hand side; extractors may fail to match.
+//!
+//! Terms are given a meaning with:
+//!
+//! - `(extern constructor term rust_fn)` and `(extern extractor term
+//!   rust_fn)`, which become methods of the generated `Context` trait. Extern
+//!   constructors of `partial` terms return an `Option`.
+//! - `(extractor (term args...) pattern)`, an extractor macro which is
+//!   expanded in place wherever the term is used in a pattern.
+//! - `(rule ...)` definitions (see below), which make the term an internal
+//!   constructor, compiled to a Rust function `constructor_<term>`.
+//!
+//! Each enum variant is also a term named `Enum.Variant`, usable in both
+//! directions.
+//!
+//! ## Rules
+//!
+//! ```text
+//! (rule (lower (has_type ty (iadd x (imm12_from_value y))))
+//!       (alu_rr_imm12 (add_op ty) x y))
+//! (rule 1 (term ...) (if-let pat expr) ... expr)
+//! ```
+//!
+//! A rule has an optional priority (defaulting to zero), a left-hand side
+//! pattern whose root is the term being defined, optional `if-let` clauses,
+//! and a right-hand side expression. Rules of a term are tried in order of
+//! decreasing priority, and in source order among equal priorities. A rule
+//! applies when its left-hand side and all its `if-let` clauses match, and
+//! all the fallible constructors (internal ones, and `partial` external ones)
+//! invoked on its right-hand side succeed; otherwise the next rule is tried,
+//! and the term fails if none applies. Constructors invoked before a failing
+//! one are not undone, so a right-hand side should only call fallible
+//! constructors before any constructor with side effects.
+//!
+//! Patterns are `_`, a variable (binding on first use, and checking equality
+//! afterwards), `var @ pattern`, integer literals, `$Name` constants (which
+//! name a Rust constant or value verbatim), `(and pattern...)`, and term
+//! applications. Expressions are variables, integer literals, `$Name`
+//! constants, term applications and `(let ((var Type expr)...) body)`.
+//!
+//! ## Implicit conversions
+//!
+//! `(convert From To term)` declares that the term, of signature `(From)
+//! To`, converts implicitly between the two types: as a constructor when an
+//! expression of type `From` is used where a `To` is expected, and as an
+//! extractor when a pattern of type `From` is used to match a `To` value.
+
+#![deny(missing_docs, trivial_numeric_casts, unused_extern_crates)]
+#![warn(unused_import_braces)]
+
+pub mod ast;
+pub mod codegen;
+pub mod compile;
+pub mod error;
+pub mod lexer;
+pub mod parser;
+pub mod sema;
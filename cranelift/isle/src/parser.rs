@@ -0,0 +1,518 @@
+//! Parser for the ISLE language.
+
+use crate::ast::*;
+use crate::error::{Error, Result};
+use crate::lexer::{Pos, Token};
+
+/// Parses a token stream, as produced by `lexer::lex`, into definitions.
+pub fn parse(tokens: Vec<(Pos, Token)>, eof: Pos) -> Result<Vec<Def>> {
+    let mut parser = Parser {
+        tokens,
+        idx: 0,
+        eof,
+    };
+    let mut defs = vec![];
+    while !parser.is_eof() {
+        defs.push(parser.parse_def()?);
+    }
+    Ok(defs)
+}
+
+struct Parser {
+    tokens: Vec<(Pos, Token)>,
+    idx: usize,
+    eof: Pos,
+}
+
+impl Parser {
+    fn is_eof(&self) -> bool {
+        self.idx >= self.tokens.len()
+    }
+
+    fn pos(&self) -> Pos {
+        match self.tokens.get(self.idx) {
+            Some((pos, _)) => pos.clone(),
+            None => self.eof.clone(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(_, t)| t)
+    }
+
+    fn error<T>(&self, msg: impl Into<String>) -> Result<T> {
+        Err(Error::Parse {
+            msg: msg.into(),
+            pos: self.pos(),
+        })
+    }
+
+    fn is_lparen(&self) -> bool {
+        self.peek() == Some(&Token::LParen)
+    }
+
+    fn is_rparen(&self) -> bool {
+        self.peek() == Some(&Token::RParen)
+    }
+
+    fn is_at(&self) -> bool {
+        self.peek() == Some(&Token::At)
+    }
+
+    fn is_int(&self) -> bool {
+        match self.peek() {
+            Some(Token::Int(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) => s == sym,
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<()> {
+        if self.peek() == Some(&token) {
+            self.idx += 1;
+            Ok(())
+        } else {
+            self.error(format!("expected {}", what))
+        }
+    }
+
+    fn lparen(&mut self) -> Result<()> {
+        self.expect(Token::LParen, "'('")
+    }
+
+    fn rparen(&mut self) -> Result<()> {
+        self.expect(Token::RParen, "')'")
+    }
+
+    fn symbol(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Symbol(s)) => {
+                let s = s.clone();
+                self.idx += 1;
+                Ok(s)
+            }
+            _ => self.error("expected a symbol"),
+        }
+    }
+
+    fn int(&mut self) -> Result<i128> {
+        match self.peek() {
+            Some(&Token::Int(i)) => {
+                self.idx += 1;
+                Ok(i)
+            }
+            _ => self.error("expected an integer"),
+        }
+    }
+
+    fn keyword(&mut self, kw: &str) -> Result<()> {
+        if self.is_sym(kw) {
+            self.idx += 1;
+            Ok(())
+        } else {
+            self.error(format!("expected '{}'", kw))
+        }
+    }
+
+    /// Parses an identifier that is not a keyword, a constant or a wildcard.
+    fn ident(&mut self) -> Result<Ident> {
+        let pos = self.pos();
+        let s = self.symbol()?;
+        if s == "_" || s.starts_with('$') || s.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(Error::Parse {
+                msg: format!("'{}' is not a valid identifier", s),
+                pos,
+            });
+        }
+        Ok(Ident(s, pos))
+    }
+
+    fn parse_def(&mut self) -> Result<Def> {
+        self.lparen()?;
+        let pos = self.pos();
+        let def = match &self.symbol()?[..] {
+            "type" => Def::Type(self.parse_type(pos)?),
+            "decl" => Def::Decl(self.parse_decl(pos)?),
+            "rule" => Def::Rule(self.parse_rule(pos)?),
+            "extractor" => Def::Extractor(self.parse_extractor(pos)?),
+            "extern" => Def::Extern(self.parse_extern(pos)?),
+            "convert" => Def::Converter(self.parse_converter(pos)?),
+            s => {
+                return Err(Error::Parse {
+                    msg: format!("unknown definition '{}'", s),
+                    pos,
+                })
+            }
+        };
+        self.rparen()?;
+        Ok(def)
+    }
+
+    fn parse_type(&mut self, pos: Pos) -> Result<Type> {
+        let name = self.ident()?;
+        let is_extern = self.is_sym("extern");
+        if is_extern {
+            self.idx += 1;
+        }
+        self.lparen()?;
+        let ty = if self.is_sym("primitive") {
+            self.idx += 1;
+            TypeValue::Primitive(self.ident()?)
+        } else if self.is_sym("enum") {
+            self.idx += 1;
+            let mut variants = vec![];
+            while !self.is_rparen() {
+                variants.push(self.parse_variant()?);
+            }
+            TypeValue::Enum(variants)
+        } else {
+            return self.error("expected 'primitive' or 'enum'");
+        };
+        self.rparen()?;
+        Ok(Type {
+            name,
+            is_extern,
+            ty,
+            pos,
+        })
+    }
+
+    fn parse_variant(&mut self) -> Result<Variant> {
+        if !self.is_lparen() {
+            let name = self.ident()?;
+            return Ok(Variant {
+                name,
+                fields: vec![],
+            });
+        }
+        self.lparen()?;
+        let name = self.ident()?;
+        let mut fields = vec![];
+        while !self.is_rparen() {
+            self.lparen()?;
+            let name = self.ident()?;
+            let ty = self.ident()?;
+            self.rparen()?;
+            fields.push(Field { name, ty });
+        }
+        self.rparen()?;
+        Ok(Variant { name, fields })
+    }
+
+    fn parse_decl(&mut self, pos: Pos) -> Result<Decl> {
+        let partial = self.is_sym("partial");
+        if partial {
+            self.idx += 1;
+        }
+        let term = self.ident()?;
+        self.lparen()?;
+        let mut arg_tys = vec![];
+        while !self.is_rparen() {
+            arg_tys.push(self.ident()?);
+        }
+        self.rparen()?;
+        let ret_ty = self.ident()?;
+        Ok(Decl {
+            term,
+            arg_tys,
+            ret_ty,
+            partial,
+            pos,
+        })
+    }
+
+    fn parse_rule(&mut self, pos: Pos) -> Result<Rule> {
+        let prio = if self.is_int() {
+            let prio = self.int()?;
+            if prio < i64::MIN as i128 || prio > i64::MAX as i128 {
+                return self.error("rule priority out of range");
+            }
+            prio as i64
+        } else {
+            0
+        };
+        let pattern = self.parse_pattern()?;
+        let mut iflets = vec![];
+        loop {
+            if self.is_rparen() {
+                return self.error("expected the right-hand side of the rule");
+            }
+            if self.is_lparen()
+                && self.tokens.get(self.idx + 1).map(|(_, t)| t)
+                    == Some(&Token::Symbol("if-let".to_string()))
+            {
+                iflets.push(self.parse_iflet()?);
+            } else {
+                break;
+            }
+        }
+        let expr = self.parse_expr()?;
+        Ok(Rule {
+            pattern,
+            iflets,
+            expr,
+            prio,
+            pos,
+        })
+    }
+
+    fn parse_iflet(&mut self) -> Result<IfLet> {
+        self.lparen()?;
+        let pos = self.pos();
+        self.keyword("if-let")?;
+        let pattern = self.parse_pattern()?;
+        let expr = self.parse_expr()?;
+        self.rparen()?;
+        Ok(IfLet { pattern, expr, pos })
+    }
+
+    fn parse_extractor(&mut self, pos: Pos) -> Result<Extractor> {
+        self.lparen()?;
+        let term = self.ident()?;
+        let mut args = vec![];
+        while !self.is_rparen() {
+            args.push(self.ident()?);
+        }
+        self.rparen()?;
+        let template = self.parse_pattern()?;
+        Ok(Extractor {
+            term,
+            args,
+            template,
+            pos,
+        })
+    }
+
+    fn parse_extern(&mut self, pos: Pos) -> Result<Extern> {
+        if self.is_sym("constructor") {
+            self.idx += 1;
+            let term = self.ident()?;
+            let func = self.ident()?;
+            Ok(Extern::Constructor { term, func, pos })
+        } else if self.is_sym("extractor") {
+            self.idx += 1;
+            let term = self.ident()?;
+            let func = self.ident()?;
+            Ok(Extern::Extractor { term, func, pos })
+        } else {
+            self.error("expected 'constructor' or 'extractor'")
+        }
+    }
+
+    fn parse_converter(&mut self, pos: Pos) -> Result<Converter> {
+        let inner_ty = self.ident()?;
+        let outer_ty = self.ident()?;
+        let term = self.ident()?;
+        Ok(Converter {
+            inner_ty,
+            outer_ty,
+            term,
+            pos,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let pos = self.pos();
+        if self.is_int() {
+            return Ok(Pattern::ConstInt {
+                val: self.int()?,
+                pos,
+            });
+        }
+        if self.is_lparen() {
+            self.lparen()?;
+            let pat = if self.is_sym("and") {
+                self.idx += 1;
+                let mut subpats = vec![];
+                while !self.is_rparen() {
+                    subpats.push(self.parse_pattern()?);
+                }
+                Pattern::And { subpats, pos }
+            } else {
+                let sym = self.ident()?;
+                let mut args = vec![];
+                while !self.is_rparen() {
+                    args.push(self.parse_pattern()?);
+                }
+                Pattern::Term { sym, args, pos }
+            };
+            self.rparen()?;
+            return Ok(pat);
+        }
+        let s = self.symbol()?;
+        if s == "_" {
+            return Ok(Pattern::Wildcard { pos });
+        }
+        if let Some(val) = s.strip_prefix('$') {
+            return Ok(Pattern::ConstPrim {
+                val: Ident(val.to_string(), pos.clone()),
+                pos,
+            });
+        }
+        let var = Ident(s, pos.clone());
+        if self.is_at() {
+            self.idx += 1;
+            let subpat = Box::new(self.parse_pattern()?);
+            Ok(Pattern::BindPattern { var, subpat, pos })
+        } else {
+            Ok(Pattern::Var { var, pos })
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        let pos = self.pos();
+        if self.is_int() {
+            return Ok(Expr::ConstInt {
+                val: self.int()?,
+                pos,
+            });
+        }
+        if self.is_lparen() {
+            self.lparen()?;
+            let expr = if self.is_sym("let") {
+                self.idx += 1;
+                self.lparen()?;
+                let mut defs = vec![];
+                while !self.is_rparen() {
+                    defs.push(self.parse_letdef()?);
+                }
+                self.rparen()?;
+                let body = Box::new(self.parse_expr()?);
+                Expr::Let { defs, body, pos }
+            } else {
+                let sym = self.ident()?;
+                let mut args = vec![];
+                while !self.is_rparen() {
+                    args.push(self.parse_expr()?);
+                }
+                Expr::Term { sym, args, pos }
+            };
+            self.rparen()?;
+            return Ok(expr);
+        }
+        let s = self.symbol()?;
+        if let Some(val) = s.strip_prefix('$') {
+            return Ok(Expr::ConstPrim {
+                val: Ident(val.to_string(), pos.clone()),
+                pos,
+            });
+        }
+        if s == "_" {
+            return Err(Error::Parse {
+                msg: "'_' is not a valid expression".to_string(),
+                pos,
+            });
+        }
+        Ok(Expr::Var {
+            name: Ident(s, pos.clone()),
+            pos,
+        })
+    }
+
+    fn parse_letdef(&mut self) -> Result<LetDef> {
+        self.lparen()?;
+        let pos = self.pos();
+        let var = if self.is_sym("_") {
+            self.idx += 1;
+            Ident("_".to_string(), pos.clone())
+        } else {
+            self.ident()?
+        };
+        let ty = self.ident()?;
+        let val = Box::new(self.parse_expr()?);
+        self.rparen()?;
+        Ok(LetDef { var, ty, val, pos })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    fn parse_str(text: &str) -> Result<Vec<Def>> {
+        let eof = Pos {
+            file: "test.isle".into(),
+            line: 0,
+            col: 0,
+        };
+        parse(lex("test.isle", text)?, eof)
+    }
+
+    #[test]
+    fn parse_defs() {
+        let defs = parse_str(
+            "(type u32 (primitive u32))
+             (type Op extern (enum Add (Imm (val u32))))
+             (decl partial f (u32 Op) u32)
+             (extern extractor g g_impl)
+             (extractor (h x) (g x _))
+             (convert u32 Op op_from_u32)
+             (rule 2 (f x @ 1 (Op.Imm $C))
+                   (if-let (and y z) (g x))
+                   (let ((a u32 (f x y)) (_ u32 x)) a))",
+        )
+        .unwrap();
+        assert_eq!(defs.len(), 7);
+        match &defs[1] {
+            Def::Type(Type {
+                is_extern: true,
+                ty: TypeValue::Enum(variants),
+                ..
+            }) => {
+                assert_eq!(variants.len(), 2);
+                assert_eq!(variants[1].fields[0].name.0, "val");
+            }
+            d => panic!("unexpected definition {:?}", d),
+        }
+        match &defs[2] {
+            Def::Decl(decl) => {
+                assert!(decl.partial);
+                assert_eq!(decl.arg_tys.len(), 2);
+            }
+            d => panic!("unexpected definition {:?}", d),
+        }
+        match &defs[6] {
+            Def::Rule(rule) => {
+                assert_eq!(rule.prio, 2);
+                assert_eq!(rule.iflets.len(), 1);
+                match &rule.pattern {
+                    Pattern::Term { sym, args, .. } => {
+                        assert_eq!(sym.0, "f");
+                        match &args[0] {
+                            Pattern::BindPattern { var, subpat, .. } => {
+                                assert_eq!(var.0, "x");
+                                match **subpat {
+                                    Pattern::ConstInt { val: 1, .. } => {}
+                                    ref p => panic!("unexpected pattern {:?}", p),
+                                }
+                            }
+                            p => panic!("unexpected pattern {:?}", p),
+                        }
+                    }
+                    p => panic!("unexpected pattern {:?}", p),
+                }
+                match &rule.expr {
+                    Expr::Let { defs, .. } => assert_eq!(defs[1].var.0, "_"),
+                    e => panic!("unexpected expression {:?}", e),
+                }
+            }
+            d => panic!("unexpected definition {:?}", d),
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_str("(type)").is_err());
+        assert!(parse_str("(frobnicate x)").is_err());
+        assert!(parse_str("(rule (f x))").is_err());
+        assert!(parse_str("(decl f (u32) u32").is_err());
+        assert!(parse_str("(rule (f _) _)").is_err());
+    }
+}
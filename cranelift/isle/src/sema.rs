@@ -0,0 +1,1070 @@
+//! Semantic analysis: name resolution, type checking, extractor macro
+//! expansion and insertion of implicit conversions.
+
+use crate::ast;
+use crate::error::{Error, Result};
+use crate::lexer::Pos;
+use std::collections::HashMap;
+
+/// The index of a type in `TypeEnv::types`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeId(pub usize);
+
+/// The index of a term in `TermEnv::terms`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TermId(pub usize);
+
+/// The index of a variable in `Rule::vars`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarId(pub usize);
+
+/// All types defined by a program.
+#[derive(Clone, Debug, Default)]
+pub struct TypeEnv {
+    /// The types, indexed by `TypeId`.
+    pub types: Vec<Type>,
+    /// The types, by name.
+    pub type_map: HashMap<String, TypeId>,
+}
+
+/// A type.
+#[derive(Clone, Debug)]
+pub enum Type {
+    /// An opaque Rust type.
+    Primitive {
+        /// The ISLE name of the type.
+        name: String,
+        /// The name of the type in Rust.
+        rust_name: String,
+        /// The position of the definition.
+        pos: Pos,
+    },
+    /// An enum.
+    Enum {
+        /// The name of the type, in both ISLE and Rust.
+        name: String,
+        /// Whether the type is defined in Rust, rather than generated.
+        is_extern: bool,
+        /// The variants.
+        variants: Vec<Variant>,
+        /// The position of the definition.
+        pos: Pos,
+    },
+}
+
+impl Type {
+    /// The ISLE name of the type.
+    pub fn name(&self) -> &str {
+        match self {
+            Type::Primitive { name, .. } | Type::Enum { name, .. } => name,
+        }
+    }
+
+    /// The name of the type in Rust.
+    pub fn rust_name(&self) -> &str {
+        match self {
+            Type::Primitive { rust_name, .. } => rust_name,
+            Type::Enum { name, .. } => name,
+        }
+    }
+}
+
+/// An enum variant.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    /// The name of the variant.
+    pub name: String,
+    /// The fields of the variant.
+    pub fields: Vec<Field>,
+    /// The term constructing and matching this variant, named `Enum.Variant`.
+    pub term: TermId,
+}
+
+/// A field of an enum variant.
+#[derive(Clone, Debug)]
+pub struct Field {
+    /// The name of the field.
+    pub name: String,
+    /// The type of the field.
+    pub ty: TypeId,
+}
+
+/// All terms and rules defined by a program.
+#[derive(Clone, Debug, Default)]
+pub struct TermEnv {
+    /// The terms, indexed by `TermId`.
+    pub terms: Vec<Term>,
+    /// The terms, by name.
+    pub term_map: HashMap<String, TermId>,
+    /// The rules, in source order.
+    pub rules: Vec<Rule>,
+    /// The implicit conversions, by (from, to) types.
+    pub converters: HashMap<(TypeId, TypeId), TermId>,
+}
+
+/// A term.
+#[derive(Clone, Debug)]
+pub struct Term {
+    /// The name of the term.
+    pub name: String,
+    /// The types of the arguments.
+    pub arg_tys: Vec<TypeId>,
+    /// The type of the result.
+    pub ret_ty: TypeId,
+    /// How the term is defined.
+    pub kind: TermKind,
+    /// The position of the declaration.
+    pub pos: Pos,
+}
+
+/// How a term is defined.
+#[derive(Clone, Debug)]
+pub enum TermKind {
+    /// A variant of an enum type.
+    EnumVariant {
+        /// The enum type.
+        ty: TypeId,
+        /// The index of the variant.
+        variant: usize,
+    },
+    /// A declared term.
+    Decl {
+        /// Whether the constructor may fail.
+        partial: bool,
+        /// The constructor, if any.
+        constructor: Option<ConstructorKind>,
+        /// The extractor, if any.
+        extractor: Option<ExtractorKind>,
+    },
+}
+
+/// How a term's constructor is defined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstructorKind {
+    /// By rules.
+    Internal,
+    /// By a method of the `Context` trait.
+    External {
+        /// The name of the method.
+        name: String,
+    },
+}
+
+/// How a term's extractor is defined.
+#[derive(Clone, Debug)]
+pub enum ExtractorKind {
+    /// By an extractor macro.
+    Internal {
+        /// The macro definition.
+        template: ast::Extractor,
+    },
+    /// By a method of the `Context` trait.
+    External {
+        /// The name of the method.
+        name: String,
+    },
+}
+
+impl Term {
+    /// Whether the term can be used in an expression.
+    pub fn has_constructor(&self) -> bool {
+        match &self.kind {
+            TermKind::EnumVariant { .. } => true,
+            TermKind::Decl { constructor, .. } => constructor.is_some(),
+        }
+    }
+
+    /// Whether the term can be used in a pattern.
+    pub fn has_extractor(&self) -> bool {
+        match &self.kind {
+            TermKind::EnumVariant { .. } => true,
+            TermKind::Decl { extractor, .. } => extractor.is_some(),
+        }
+    }
+
+    /// Whether the term's constructor returns an `Option`.
+    pub fn is_fallible_constructor(&self) -> bool {
+        match &self.kind {
+            TermKind::EnumVariant { .. } => false,
+            TermKind::Decl {
+                partial,
+                constructor,
+                ..
+            } => match constructor {
+                Some(ConstructorKind::Internal) => true,
+                Some(ConstructorKind::External { .. }) => *partial,
+                None => false,
+            },
+        }
+    }
+}
+
+/// A rule, with all names resolved.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// The term this rule defines.
+    pub root_term: TermId,
+    /// The patterns matching the term's arguments.
+    pub args: Vec<Pattern>,
+    /// The `if-let` clauses.
+    pub iflets: Vec<IfLet>,
+    /// The right-hand side.
+    pub rhs: Expr,
+    /// The variables used by the rule.
+    pub vars: Vec<Var>,
+    /// The priority.
+    pub prio: i64,
+    /// The position of the rule.
+    pub pos: Pos,
+}
+
+/// An `if-let` clause.
+#[derive(Clone, Debug)]
+pub struct IfLet {
+    /// The pattern.
+    pub lhs: Pattern,
+    /// The expression.
+    pub rhs: Expr,
+}
+
+/// A variable of a rule.
+#[derive(Clone, Debug)]
+pub struct Var {
+    /// The name of the variable.
+    pub name: String,
+    /// The type of the variable.
+    pub ty: TypeId,
+}
+
+/// A type-checked pattern. Each pattern carries the type of the value it
+/// matches.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Binds the value to a new variable, then matches the subpattern.
+    BindPattern(TypeId, VarId, Box<Pattern>),
+    /// Matches values equal to a bound variable.
+    Var(TypeId, VarId),
+    /// Matches an integer constant.
+    ConstInt(TypeId, i128),
+    /// Matches a Rust constant.
+    ConstPrim(TypeId, String),
+    /// Matches with a term's extractor, then matches the results with the
+    /// subpatterns.
+    Term(TypeId, TermId, Vec<Pattern>),
+    /// Matches anything.
+    Wildcard(TypeId),
+    /// Matches all the subpatterns.
+    And(TypeId, Vec<Pattern>),
+}
+
+/// A type-checked expression.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    /// Applies a term's constructor.
+    Term(TypeId, TermId, Vec<Expr>),
+    /// A variable.
+    Var(TypeId, VarId),
+    /// An integer constant.
+    ConstInt(TypeId, i128),
+    /// A Rust constant.
+    ConstPrim(TypeId, String),
+    /// Evaluates bindings in order, then the body. A binding without a
+    /// variable is evaluated for its side effects only.
+    Let(TypeId, Vec<(Option<VarId>, TypeId, Box<Expr>)>, Box<Expr>),
+}
+
+impl Pattern {
+    /// The type of the values matched by the pattern.
+    pub fn ty(&self) -> TypeId {
+        match self {
+            Pattern::BindPattern(ty, ..)
+            | Pattern::Var(ty, ..)
+            | Pattern::ConstInt(ty, ..)
+            | Pattern::ConstPrim(ty, ..)
+            | Pattern::Term(ty, ..)
+            | Pattern::Wildcard(ty)
+            | Pattern::And(ty, ..) => *ty,
+        }
+    }
+}
+
+impl Expr {
+    /// The type of the expression.
+    pub fn ty(&self) -> TypeId {
+        match self {
+            Expr::Term(ty, ..)
+            | Expr::Var(ty, ..)
+            | Expr::ConstInt(ty, ..)
+            | Expr::ConstPrim(ty, ..)
+            | Expr::Let(ty, ..) => *ty,
+        }
+    }
+}
+
+/// Extractor macros can expand to other macros, but not indefinitely.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+fn type_error<T>(msg: impl Into<String>, pos: &Pos) -> Result<T> {
+    Err(Error::Type {
+        msg: msg.into(),
+        pos: pos.clone(),
+    })
+}
+
+/// Returns true if `s` can be used as a Rust identifier.
+fn is_rust_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl TypeEnv {
+    /// Collects the type definitions of a program.
+    pub fn from_ast(defs: &ast::Defs) -> Result<TypeEnv> {
+        let mut env = TypeEnv::default();
+
+        // Assign ids first, so that types can refer to each other in any order.
+        let type_defs = defs.defs.iter().filter_map(|def| match def {
+            ast::Def::Type(ty) => Some(ty),
+            _ => None,
+        });
+        for ty in type_defs.clone() {
+            if env.type_map.contains_key(&ty.name.0) {
+                return type_error(format!("type '{}' is already defined", ty.name.0), &ty.pos);
+            }
+            env.type_map
+                .insert(ty.name.0.clone(), TypeId(env.type_map.len()));
+        }
+
+        for ty in type_defs {
+            let def = match &ty.ty {
+                ast::TypeValue::Primitive(rust_name) => {
+                    if ty.is_extern {
+                        return type_error("primitive types cannot be 'extern'", &ty.pos);
+                    }
+                    Type::Primitive {
+                        name: ty.name.0.clone(),
+                        rust_name: rust_name.0.clone(),
+                        pos: ty.pos.clone(),
+                    }
+                }
+                ast::TypeValue::Enum(variants) => {
+                    let mut sema_variants: Vec<Variant> = vec![];
+                    for variant in variants {
+                        if sema_variants.iter().any(|v| v.name == variant.name.0) {
+                            return type_error(
+                                format!("duplicate variant '{}'", variant.name.0),
+                                &variant.name.1,
+                            );
+                        }
+                        let mut fields: Vec<Field> = vec![];
+                        for field in &variant.fields {
+                            if fields.iter().any(|f| f.name == field.name.0) {
+                                return type_error(
+                                    format!("duplicate field '{}'", field.name.0),
+                                    &field.name.1,
+                                );
+                            }
+                            fields.push(Field {
+                                name: field.name.0.clone(),
+                                ty: env.lookup(&field.ty)?,
+                            });
+                        }
+                        sema_variants.push(Variant {
+                            name: variant.name.0.clone(),
+                            fields,
+                            // Filled in when the terms are collected.
+                            term: TermId(usize::MAX),
+                        });
+                    }
+                    Type::Enum {
+                        name: ty.name.0.clone(),
+                        is_extern: ty.is_extern,
+                        variants: sema_variants,
+                        pos: ty.pos.clone(),
+                    }
+                }
+            };
+            env.types.push(def);
+        }
+
+        Ok(env)
+    }
+
+    /// Resolves a type name.
+    pub fn lookup(&self, name: &ast::Ident) -> Result<TypeId> {
+        match self.type_map.get(&name.0) {
+            Some(&id) => Ok(id),
+            None => type_error(format!("unknown type '{}'", name.0), &name.1),
+        }
+    }
+
+    fn name(&self, ty: TypeId) -> &str {
+        self.types[ty.0].name()
+    }
+}
+
+/// The variables in scope while translating a rule.
+#[derive(Default)]
+struct Bindings {
+    vars: Vec<Var>,
+    in_scope: HashMap<String, VarId>,
+}
+
+impl Bindings {
+    fn add(&mut self, name: &ast::Ident, ty: TypeId) -> Result<VarId> {
+        if self.in_scope.contains_key(&name.0) {
+            return type_error(format!("variable '{}' is already bound", name.0), &name.1);
+        }
+        let id = VarId(self.vars.len());
+        self.vars.push(Var {
+            name: name.0.clone(),
+            ty,
+        });
+        self.in_scope.insert(name.0.clone(), id);
+        Ok(id)
+    }
+}
+
+impl TermEnv {
+    /// Collects and type-checks the terms and rules of a program.
+    pub fn from_ast(tyenv: &mut TypeEnv, defs: &ast::Defs) -> Result<TermEnv> {
+        let mut env = TermEnv::default();
+        env.collect_variant_terms(tyenv)?;
+        env.collect_decls(tyenv, defs)?;
+        env.collect_definitions(defs)?;
+        env.collect_converters(tyenv, defs)?;
+        env.collect_rules(tyenv, defs)?;
+        Ok(env)
+    }
+
+    fn add_term(&mut self, term: Term) -> Result<TermId> {
+        if self.term_map.contains_key(&term.name) {
+            return type_error(
+                format!("term '{}' is already declared", term.name),
+                &term.pos,
+            );
+        }
+        let id = TermId(self.terms.len());
+        self.term_map.insert(term.name.clone(), id);
+        self.terms.push(term);
+        Ok(id)
+    }
+
+    fn collect_variant_terms(&mut self, tyenv: &mut TypeEnv) -> Result<()> {
+        for ty_idx in 0..tyenv.types.len() {
+            let (name, variants, pos) = match &tyenv.types[ty_idx] {
+                Type::Enum {
+                    name,
+                    variants,
+                    pos,
+                    ..
+                } => (name.clone(), variants.clone(), pos.clone()),
+                Type::Primitive { .. } => continue,
+            };
+            for (variant_idx, variant) in variants.iter().enumerate() {
+                let id = self.add_term(Term {
+                    name: format!("{}.{}", name, variant.name),
+                    arg_tys: variant.fields.iter().map(|f| f.ty).collect(),
+                    ret_ty: TypeId(ty_idx),
+                    kind: TermKind::EnumVariant {
+                        ty: TypeId(ty_idx),
+                        variant: variant_idx,
+                    },
+                    pos: pos.clone(),
+                })?;
+                if let Type::Enum { variants, .. } = &mut tyenv.types[ty_idx] {
+                    variants[variant_idx].term = id;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_decls(&mut self, tyenv: &TypeEnv, defs: &ast::Defs) -> Result<()> {
+        for def in &defs.defs {
+            if let ast::Def::Decl(decl) = def {
+                let arg_tys = decl
+                    .arg_tys
+                    .iter()
+                    .map(|ty| tyenv.lookup(ty))
+                    .collect::<Result<Vec<_>>>()?;
+                let ret_ty = tyenv.lookup(&decl.ret_ty)?;
+                self.add_term(Term {
+                    name: decl.term.0.clone(),
+                    arg_tys,
+                    ret_ty,
+                    kind: TermKind::Decl {
+                        partial: decl.partial,
+                        constructor: None,
+                        extractor: None,
+                    },
+                    pos: decl.pos.clone(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &ast::Ident) -> Result<TermId> {
+        match self.term_map.get(&name.0) {
+            Some(&id) => Ok(id),
+            None => type_error(format!("unknown term '{}'", name.0), &name.1),
+        }
+    }
+
+    /// Attaches external constructors and extractors, extractor macros and
+    /// rule-defined constructors to the declared terms.
+    fn collect_definitions(&mut self, defs: &ast::Defs) -> Result<()> {
+        let mut extern_names: HashMap<String, Pos> = HashMap::new();
+        for def in &defs.defs {
+            let (term, pos, new_constructor, new_extractor) = match def {
+                ast::Def::Extern(ast::Extern::Constructor { term, func, pos }) => {
+                    let name = self.check_extern_name(&mut extern_names, func)?;
+                    (term, pos, Some(ConstructorKind::External { name }), None)
+                }
+                ast::Def::Extern(ast::Extern::Extractor { term, func, pos }) => {
+                    let name = self.check_extern_name(&mut extern_names, func)?;
+                    (term, pos, None, Some(ExtractorKind::External { name }))
+                }
+                ast::Def::Extractor(template) => {
+                    Self::check_template(template)?;
+                    let kind = ExtractorKind::Internal {
+                        template: template.clone(),
+                    };
+                    (&template.term, &template.pos, None, Some(kind))
+                }
+                ast::Def::Rule(rule) => match &rule.pattern {
+                    ast::Pattern::Term { sym, pos, .. } => {
+                        let id = self.lookup(sym)?;
+                        match &self.terms[id.0].kind {
+                            TermKind::Decl {
+                                constructor: Some(ConstructorKind::Internal),
+                                ..
+                            } => continue,
+                            _ => (sym, pos, Some(ConstructorKind::Internal), None),
+                        }
+                    }
+                    pat => {
+                        return type_error("the left-hand side of a rule must be a term", pat.pos())
+                    }
+                },
+                _ => continue,
+            };
+
+            let id = self.lookup(term)?;
+            match &mut self.terms[id.0].kind {
+                TermKind::EnumVariant { .. } => {
+                    return type_error(
+                        format!("enum variant '{}' cannot be redefined", term.0),
+                        pos,
+                    )
+                }
+                TermKind::Decl {
+                    constructor,
+                    extractor,
+                    ..
+                } => {
+                    if new_constructor.is_some() {
+                        if constructor.is_some() {
+                            return type_error(
+                                format!("term '{}' already has a constructor", term.0),
+                                pos,
+                            );
+                        }
+                        *constructor = new_constructor;
+                    }
+                    if new_extractor.is_some() {
+                        if extractor.is_some() {
+                            return type_error(
+                                format!("term '{}' already has an extractor", term.0),
+                                pos,
+                            );
+                        }
+                        *extractor = new_extractor;
+                    }
+                }
+            }
+        }
+
+        for term in &self.terms {
+            if let TermKind::Decl {
+                extractor: Some(ExtractorKind::Internal { template }),
+                ..
+            } = &term.kind
+            {
+                if template.args.len() != term.arg_tys.len() {
+                    return type_error(
+                        format!(
+                            "extractor '{}' has {} parameters but the term has {} arguments",
+                            term.name,
+                            template.args.len(),
+                            term.arg_tys.len()
+                        ),
+                        &template.pos,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_extern_name(
+        &self,
+        names: &mut HashMap<String, Pos>,
+        func: &ast::Ident,
+    ) -> Result<String> {
+        if !is_rust_ident(&func.0) {
+            return type_error(
+                format!("'{}' is not a valid Rust method name", func.0),
+                &func.1,
+            );
+        }
+        if let Some(prev) = names.insert(func.0.clone(), func.1.clone()) {
+            return type_error(
+                format!("external method '{}' is already used at {}", func.0, prev),
+                &func.1,
+            );
+        }
+        Ok(func.0.clone())
+    }
+
+    /// Checks that an extractor macro only refers to its own parameters, so
+    /// that expanding it cannot capture the variables of a rule.
+    fn check_template(template: &ast::Extractor) -> Result<()> {
+        fn check(pat: &ast::Pattern, params: &[ast::Ident]) -> Result<()> {
+            match pat {
+                ast::Pattern::Var { var, .. } => {
+                    if params.iter().all(|p| p.0 != var.0) {
+                        return type_error(
+                            format!("'{}' is not a parameter of the extractor", var.0),
+                            &var.1,
+                        );
+                    }
+                    Ok(())
+                }
+                ast::Pattern::BindPattern { var, .. } => {
+                    type_error("extractor templates cannot bind variables", &var.1)
+                }
+                ast::Pattern::Term { args: subpats, .. } | ast::Pattern::And { subpats, .. } => {
+                    subpats.iter().try_for_each(|p| check(p, params))
+                }
+                ast::Pattern::ConstInt { .. }
+                | ast::Pattern::ConstPrim { .. }
+                | ast::Pattern::Wildcard { .. } => Ok(()),
+            }
+        }
+        check(&template.template, &template.args)
+    }
+
+    fn collect_converters(&mut self, tyenv: &TypeEnv, defs: &ast::Defs) -> Result<()> {
+        for def in &defs.defs {
+            if let ast::Def::Converter(conv) = def {
+                let inner = tyenv.lookup(&conv.inner_ty)?;
+                let outer = tyenv.lookup(&conv.outer_ty)?;
+                let id = self.lookup(&conv.term)?;
+                let term = &self.terms[id.0];
+                if term.arg_tys != [inner] || term.ret_ty != outer {
+                    return type_error(
+                        format!(
+                            "converter term '{}' must have signature ({}) {}",
+                            conv.term.0, conv.inner_ty.0, conv.outer_ty.0
+                        ),
+                        &conv.pos,
+                    );
+                }
+                if let TermKind::Decl {
+                    extractor: Some(ExtractorKind::Internal { .. }),
+                    ..
+                } = term.kind
+                {
+                    return type_error("converter terms cannot be extractor macros", &conv.pos);
+                }
+                if self.converters.insert((inner, outer), id).is_some() {
+                    return type_error(
+                        format!(
+                            "a conversion from {} to {} is already defined",
+                            conv.inner_ty.0, conv.outer_ty.0
+                        ),
+                        &conv.pos,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_rules(&mut self, tyenv: &TypeEnv, defs: &ast::Defs) -> Result<()> {
+        let mut errors = vec![];
+        for def in &defs.defs {
+            if let ast::Def::Rule(rule) = def {
+                match self.translate_rule(tyenv, rule) {
+                    Ok(rule) => self.rules.push(rule),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(Error::Errors(errors)),
+        }
+    }
+
+    fn translate_rule(&self, tyenv: &TypeEnv, rule: &ast::Rule) -> Result<Rule> {
+        let (sym, args) = match &rule.pattern {
+            ast::Pattern::Term { sym, args, .. } => (sym, args),
+            _ => unreachable!("checked in collect_definitions"),
+        };
+        let root_term = self.lookup(sym)?;
+        let term = &self.terms[root_term.0];
+        if args.len() != term.arg_tys.len() {
+            return type_error(
+                format!(
+                    "term '{}' takes {} arguments, got {}",
+                    term.name,
+                    term.arg_tys.len(),
+                    args.len()
+                ),
+                &rule.pos,
+            );
+        }
+
+        let mut bindings = Bindings::default();
+        let args = args
+            .iter()
+            .zip(&term.arg_tys)
+            .map(|(arg, &ty)| self.translate_pattern(tyenv, arg, ty, &mut bindings, 0))
+            .collect::<Result<Vec<_>>>()?;
+        let mut iflets = vec![];
+        for iflet in &rule.iflets {
+            let rhs = self.translate_expr(tyenv, &iflet.expr, None, &mut bindings)?;
+            let lhs = self.translate_pattern(tyenv, &iflet.pattern, rhs.ty(), &mut bindings, 0)?;
+            iflets.push(IfLet { lhs, rhs });
+        }
+        let rhs = self.translate_expr(tyenv, &rule.expr, Some(term.ret_ty), &mut bindings)?;
+
+        Ok(Rule {
+            root_term,
+            args,
+            iflets,
+            rhs,
+            vars: bindings.vars,
+            prio: rule.prio,
+            pos: rule.pos.clone(),
+        })
+    }
+
+    fn translate_pattern(
+        &self,
+        tyenv: &TypeEnv,
+        pat: &ast::Pattern,
+        expected: TypeId,
+        bindings: &mut Bindings,
+        depth: usize,
+    ) -> Result<Pattern> {
+        match pat {
+            ast::Pattern::Wildcard { .. } => Ok(Pattern::Wildcard(expected)),
+            ast::Pattern::ConstInt { val, .. } => Ok(Pattern::ConstInt(expected, *val)),
+            ast::Pattern::ConstPrim { val, .. } => Ok(Pattern::ConstPrim(expected, val.0.clone())),
+            ast::Pattern::Var { var, pos } => match bindings.in_scope.get(&var.0) {
+                Some(&id) => {
+                    let ty = bindings.vars[id.0].ty;
+                    if ty != expected {
+                        return type_error(
+                            format!(
+                                "variable '{}' has type {} but is matched against {}",
+                                var.0,
+                                tyenv.name(ty),
+                                tyenv.name(expected)
+                            ),
+                            pos,
+                        );
+                    }
+                    Ok(Pattern::Var(expected, id))
+                }
+                None => {
+                    let id = bindings.add(var, expected)?;
+                    Ok(Pattern::BindPattern(
+                        expected,
+                        id,
+                        Box::new(Pattern::Wildcard(expected)),
+                    ))
+                }
+            },
+            ast::Pattern::BindPattern { var, subpat, .. } => {
+                let id = bindings.add(var, expected)?;
+                let subpat = self.translate_pattern(tyenv, subpat, expected, bindings, depth)?;
+                Ok(Pattern::BindPattern(expected, id, Box::new(subpat)))
+            }
+            ast::Pattern::And { subpats, .. } => {
+                let subpats = subpats
+                    .iter()
+                    .map(|p| self.translate_pattern(tyenv, p, expected, bindings, depth))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Pattern::And(expected, subpats))
+            }
+            ast::Pattern::Term { sym, args, pos } => {
+                let id = self.lookup(sym)?;
+                let term = &self.terms[id.0];
+                if args.len() != term.arg_tys.len() {
+                    return type_error(
+                        format!(
+                            "term '{}' takes {} arguments, got {}",
+                            term.name,
+                            term.arg_tys.len(),
+                            args.len()
+                        ),
+                        pos,
+                    );
+                }
+                let pat = match &term.kind {
+                    TermKind::Decl {
+                        extractor: Some(ExtractorKind::Internal { template }),
+                        ..
+                    } => {
+                        if depth >= MAX_EXPANSION_DEPTH {
+                            return type_error(
+                                format!("expansion of extractor '{}' is too deep", term.name),
+                                pos,
+                            );
+                        }
+                        let expanded = subst_params(&template.template, &template.args, args);
+                        self.translate_pattern(tyenv, &expanded, term.ret_ty, bindings, depth + 1)?
+                    }
+                    _ if term.has_extractor() => {
+                        let args = args
+                            .iter()
+                            .zip(&term.arg_tys)
+                            .map(|(arg, &ty)| {
+                                self.translate_pattern(tyenv, arg, ty, bindings, depth)
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        Pattern::Term(term.ret_ty, id, args)
+                    }
+                    _ => return type_error(format!("term '{}' has no extractor", term.name), pos),
+                };
+                self.convert_pattern(tyenv, pat, expected, pos)
+            }
+        }
+    }
+
+    /// Wraps a pattern of some type in the conversion extractor needed to
+    /// match values of type `expected`.
+    fn convert_pattern(
+        &self,
+        tyenv: &TypeEnv,
+        pat: Pattern,
+        expected: TypeId,
+        pos: &Pos,
+    ) -> Result<Pattern> {
+        let ty = pat.ty();
+        if ty == expected {
+            return Ok(pat);
+        }
+        match self.converters.get(&(ty, expected)) {
+            Some(&conv) if self.terms[conv.0].has_extractor() => {
+                Ok(Pattern::Term(expected, conv, vec![pat]))
+            }
+            _ => type_error(
+                format!(
+                    "pattern of type {} cannot match a value of type {}",
+                    tyenv.name(ty),
+                    tyenv.name(expected)
+                ),
+                pos,
+            ),
+        }
+    }
+
+    fn translate_expr(
+        &self,
+        tyenv: &TypeEnv,
+        expr: &ast::Expr,
+        expected: Option<TypeId>,
+        bindings: &mut Bindings,
+    ) -> Result<Expr> {
+        let translated = match expr {
+            ast::Expr::ConstInt { val, pos } => match expected {
+                Some(ty) => Expr::ConstInt(ty, *val),
+                None => return type_error("cannot infer the type of the constant", pos),
+            },
+            ast::Expr::ConstPrim { val, pos } => match expected {
+                Some(ty) => Expr::ConstPrim(ty, val.0.clone()),
+                None => return type_error("cannot infer the type of the constant", pos),
+            },
+            ast::Expr::Var { name, pos } => match bindings.in_scope.get(&name.0) {
+                Some(&id) => Expr::Var(bindings.vars[id.0].ty, id),
+                None => return type_error(format!("unknown variable '{}'", name.0), pos),
+            },
+            ast::Expr::Term { sym, args, pos } => {
+                let id = self.lookup(sym)?;
+                let term = &self.terms[id.0];
+                if !term.has_constructor() {
+                    return type_error(format!("term '{}' has no constructor", term.name), pos);
+                }
+                if args.len() != term.arg_tys.len() {
+                    return type_error(
+                        format!(
+                            "term '{}' takes {} arguments, got {}",
+                            term.name,
+                            term.arg_tys.len(),
+                            args.len()
+                        ),
+                        pos,
+                    );
+                }
+                let args = args
+                    .iter()
+                    .zip(&term.arg_tys)
+                    .map(|(arg, &ty)| self.translate_expr(tyenv, arg, Some(ty), bindings))
+                    .collect::<Result<Vec<_>>>()?;
+                Expr::Term(term.ret_ty, id, args)
+            }
+            ast::Expr::Let { defs, body, .. } => {
+                let mut let_bindings = vec![];
+                let mut added = vec![];
+                for def in defs {
+                    let ty = tyenv.lookup(&def.ty)?;
+                    let val = self.translate_expr(tyenv, &def.val, Some(ty), bindings)?;
+                    let var = if def.var.0 == "_" {
+                        None
+                    } else {
+                        let id = bindings.add(&def.var, ty)?;
+                        added.push(def.var.0.clone());
+                        Some(id)
+                    };
+                    let_bindings.push((var, ty, Box::new(val)));
+                }
+                let body = self.translate_expr(tyenv, body, expected, bindings)?;
+                for name in added {
+                    bindings.in_scope.remove(&name);
+                }
+                Expr::Let(body.ty(), let_bindings, Box::new(body))
+            }
+        };
+
+        let expected = match expected {
+            Some(ty) => ty,
+            None => return Ok(translated),
+        };
+        let ty = translated.ty();
+        if ty == expected {
+            return Ok(translated);
+        }
+        match self.converters.get(&(ty, expected)) {
+            Some(&conv) if self.terms[conv.0].has_constructor() => {
+                Ok(Expr::Term(expected, conv, vec![translated]))
+            }
+            _ => type_error(
+                format!(
+                    "expression of type {} used where {} is expected",
+                    tyenv.name(ty),
+                    tyenv.name(expected)
+                ),
+                expr.pos(),
+            ),
+        }
+    }
+}
+
+/// Substitutes the arguments of an extractor macro for its parameters.
+fn subst_params(pat: &ast::Pattern, params: &[ast::Ident], args: &[ast::Pattern]) -> ast::Pattern {
+    match pat {
+        ast::Pattern::Var { var, .. } => {
+            let idx = params.iter().position(|p| p.0 == var.0).unwrap();
+            args[idx].clone()
+        }
+        ast::Pattern::Term {
+            sym,
+            args: subpats,
+            pos,
+        } => ast::Pattern::Term {
+            sym: sym.clone(),
+            args: subpats
+                .iter()
+                .map(|p| subst_params(p, params, args))
+                .collect(),
+            pos: pos.clone(),
+        },
+        ast::Pattern::And { subpats, pos } => ast::Pattern::And {
+            subpats: subpats
+                .iter()
+                .map(|p| subst_params(p, params, args))
+                .collect(),
+            pos: pos.clone(),
+        },
+        ast::Pattern::BindPattern { .. } => unreachable!("rejected by check_template"),
+        ast::Pattern::ConstInt { .. }
+        | ast::Pattern::ConstPrim { .. }
+        | ast::Pattern::Wildcard { .. } => pat.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::parse_str;
+
+    fn check(text: &str) -> Result<(TypeEnv, TermEnv)> {
+        let defs = parse_str("test.isle", text)?;
+        let mut tyenv = TypeEnv::from_ast(&defs)?;
+        let termenv = TermEnv::from_ast(&mut tyenv, &defs)?;
+        Ok((tyenv, termenv))
+    }
+
+    const PRELUDE: &str = "
+        (type u32 (primitive u32))
+        (type Reg (primitive Reg))
+        (type Inst (primitive Inst))
+        (type Op extern (enum Add Sub))
+        (decl op_of (Op u32 u32) Inst)
+        (extern extractor op_of op_of)
+        (decl add (u32 u32) Inst)
+        (extractor (add x y) (op_of (Op.Add) x y))
+        (decl put_in_reg (u32) Reg)
+        (extern constructor put_in_reg put_in_reg)
+        (convert u32 Reg put_in_reg)
+        (decl lower (Inst) Reg)
+    ";
+
+    #[test]
+    fn expand_and_convert() {
+        let (_, termenv) = check(&format!("{} (rule (lower (add x x)) x)", PRELUDE)).unwrap();
+        let rule = &termenv.rules[0];
+        match &rule.args[0] {
+            Pattern::Term(_, _, args) => {
+                assert!(matches!(args[0], Pattern::Term(_, _, ref a) if a.is_empty()));
+                assert!(matches!(args[1], Pattern::BindPattern(..)));
+                assert!(matches!(args[2], Pattern::Var(..)));
+            }
+            p => panic!("unexpected pattern {:?}", p),
+        }
+        match &rule.rhs {
+            Expr::Term(_, conv, args) => {
+                assert_eq!(termenv.terms[conv.0].name, "put_in_reg");
+                assert!(matches!(args[0], Expr::Var(..)));
+            }
+            e => panic!("unexpected expression {:?}", e),
+        }
+    }
+
+    #[test]
+    fn type_errors() {
+        // Wrong argument count.
+        assert!(check(&format!("{} (rule (lower (add x)) x)", PRELUDE)).is_err());
+        // Unknown variable.
+        assert!(check(&format!("{} (rule (lower (add x y)) z)", PRELUDE)).is_err());
+        // No conversion from Inst to Reg.
+        assert!(check(&format!("{} (rule (lower i) i)", PRELUDE)).is_err());
+        // Extern constructor and rules for the same term.
+        assert!(check(&format!("{} (rule (put_in_reg x) x)", PRELUDE)).is_err());
+        // Extractor template capturing a variable.
+        assert!(check(&format!(
+            "{} (decl sub (u32) Inst) (extractor (sub x) (op_of (Op.Sub) x y))",
+            PRELUDE
+        ))
+        .is_err());
+        // Constant without a known type.
+        assert!(check(&format!("{} (rule (lower i) (if-let x 1) x)", PRELUDE)).is_err());
+    }
+}
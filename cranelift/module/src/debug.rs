@@ -0,0 +1,301 @@
+//! Debug information for functions defined in a `Module`.
+//!
+//! A frontend describes its source files, the mapping from the `SourceLoc`s it attached to
+//! instructions to lines and columns in those files, and its variables, each tied to the
+//! `ValueLabel` it gave to their values with `set_val_label`. The module backend then collects,
+//! for every function it compiles, the code offsets of these source locations and the locations
+//! of the labeled values, and emits them in its own debug info format.
+
+use crate::module::FuncId;
+use crate::HashMap;
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::entity::{entity_impl, PrimaryMap};
+use cranelift_codegen::ir::{SourceLoc, ValueLabel, ValueLoc};
+use cranelift_codegen::isa::{RegUnit, TargetIsa};
+use cranelift_codegen::{CodegenResult, Context};
+use std::collections::BTreeMap;
+use std::string::String;
+use std::vec::Vec;
+
+/// A source file registered in a `DebugContext`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+entity_impl!(FileId, "file");
+
+/// A variable type registered in a `DebugContext`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeId(u32);
+entity_impl!(TypeId, "type");
+
+/// A source file.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    /// The name of the file, relative to `directory`.
+    pub name: String,
+    /// The directory of the file. Relative directories are relative to the compilation
+    /// directory of the `DebugContext`.
+    pub directory: String,
+}
+
+/// A position in a source file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SourceLocation {
+    /// The file.
+    pub file: FileId,
+    /// The line, starting at 1.
+    pub line: u32,
+    /// The column, starting at 1, or 0 if unknown.
+    pub column: u32,
+}
+
+/// How the bits of a value of a `TypeDescription` are interpreted.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TypeEncoding {
+    /// A signed integer.
+    Signed,
+    /// An unsigned integer.
+    Unsigned,
+    /// An IEEE 754 floating point number.
+    Float,
+    /// A boolean: zero is false, anything else true.
+    Boolean,
+    /// A pointer.
+    Address,
+}
+
+/// A scalar type of variables.
+#[derive(Clone, Debug)]
+pub struct TypeDescription {
+    /// The name of the type in the source language.
+    pub name: String,
+    /// The size of a value of the type, in bytes.
+    pub size: u8,
+    /// How a value of the type is interpreted.
+    pub encoding: TypeEncoding,
+}
+
+/// A variable of a function.
+#[derive(Clone, Debug)]
+pub struct VariableDescription {
+    /// The name of the variable.
+    pub name: String,
+    /// The type of the variable.
+    pub ty: TypeId,
+    /// The label given to the values of the variable with `set_val_label`.
+    pub label: ValueLabel,
+    /// Where the variable is declared.
+    pub location: Option<SourceLocation>,
+    /// Is the variable a parameter of the function?
+    pub is_parameter: bool,
+}
+
+/// The debug information of a function, as described by the frontend.
+#[derive(Clone, Debug, Default)]
+pub struct FunctionDebugInfo {
+    /// The name of the function in the source language. The name of its declaration is used if
+    /// this is `None`.
+    pub name: Option<String>,
+    /// Where the function is declared.
+    pub location: Option<SourceLocation>,
+    /// The positions in the source files of the `SourceLoc`s of the function's instructions.
+    /// Instructions whose `SourceLoc` isn't in this map aren't attributed to any position.
+    pub srclocs: HashMap<SourceLoc, SourceLocation>,
+    /// The variables of the function.
+    pub variables: Vec<VariableDescription>,
+}
+
+impl FunctionDebugInfo {
+    /// Create a new, empty, `FunctionDebugInfo`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the position in the source files of `srcloc`.
+    pub fn set_srcloc(&mut self, srcloc: SourceLoc, location: SourceLocation) {
+        self.srclocs.insert(srcloc, location);
+    }
+
+    /// Add a variable.
+    pub fn add_variable(&mut self, variable: VariableDescription) {
+        self.variables.push(variable);
+    }
+}
+
+/// Where a variable is stored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VariableLocation {
+    /// In a register, identified by its register unit; see `TargetIsa::map_dwarf_register`.
+    Reg(RegUnit),
+    /// In memory, at this offset from the canonical frame address: the value of the stack
+    /// pointer before the call to the function.
+    Stack(i32),
+}
+
+/// A range of code in which a variable is stored in the same location.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct VariableLocationRange {
+    /// The start of the range, as an offset from the start of the function.
+    pub start: CodeOffset,
+    /// The end of the range, exclusive.
+    pub end: CodeOffset,
+    /// The location of the variable in the range.
+    pub location: VariableLocation,
+}
+
+/// The debug information collected from a compiled function.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledFunctionDebugInfo {
+    /// The size of the function's code.
+    pub size: CodeOffset,
+    /// The code offsets at which the `SourceLoc` changes, in increasing order.
+    pub srclocs: Vec<(CodeOffset, SourceLoc)>,
+    /// The location ranges of the labeled values, in increasing order.
+    pub value_labels: HashMap<ValueLabel, Vec<VariableLocationRange>>,
+}
+
+impl CompiledFunctionDebugInfo {
+    /// Collect the debug information of the function compiled by `ctx`.
+    ///
+    /// The locations of labeled values are currently only computed by the legacy backends; with
+    /// the other ones, `value_labels` is always empty.
+    pub fn collect(ctx: &Context, isa: &dyn TargetIsa, size: CodeOffset) -> CodegenResult<Self> {
+        let func = &ctx.func;
+        let mut srclocs = Vec::new();
+        let mut push_srcloc = |offset, srcloc: SourceLoc| {
+            if srclocs.last().map(|&(_, last)| last) != Some(srcloc) {
+                srclocs.push((offset, srcloc));
+            }
+        };
+        if let Some(result) = &ctx.mach_compile_result {
+            for srcloc in result.buffer.get_srclocs_sorted() {
+                push_srcloc(srcloc.start, srcloc.loc);
+            }
+        } else {
+            let encinfo = isa.encoding_info();
+            let mut blocks = func.layout.blocks().collect::<Vec<_>>();
+            blocks.sort_by_key(|block| func.offsets[*block]);
+            for block in blocks {
+                for (offset, inst, _) in func.inst_offsets(block, &encinfo) {
+                    push_srcloc(offset, func.srclocs[inst]);
+                }
+            }
+        }
+
+        let mut value_labels = HashMap::new();
+        for (label, ranges) in ctx.build_value_labels_ranges(isa)? {
+            let ranges = ranges
+                .iter()
+                .filter_map(|range| {
+                    let location = match range.loc {
+                        ValueLoc::Reg(reg) => VariableLocation::Reg(reg),
+                        ValueLoc::Stack(ss) => {
+                            VariableLocation::Stack(func.stack_slots[ss].offset?)
+                        }
+                        ValueLoc::Unassigned => return None,
+                    };
+                    Some(VariableLocationRange {
+                        start: range.start,
+                        end: range.end,
+                        location,
+                    })
+                })
+                .collect();
+            value_labels.insert(label, ranges);
+        }
+
+        Ok(Self {
+            size,
+            srclocs,
+            value_labels,
+        })
+    }
+}
+
+/// The debug information of a module.
+///
+/// The frontend registers its source files, types and functions; the module backend adds the
+/// information it collects from the compiled functions.
+#[derive(Clone, Debug)]
+pub struct DebugContext {
+    producer: String,
+    name: String,
+    directory: String,
+    files: PrimaryMap<FileId, SourceFile>,
+    types: PrimaryMap<TypeId, TypeDescription>,
+    functions: BTreeMap<FuncId, FunctionDebugInfo>,
+    compiled_functions: BTreeMap<FuncId, CompiledFunctionDebugInfo>,
+}
+
+impl DebugContext {
+    /// Create a new `DebugContext` for the compilation of the source file `name`, in
+    /// `directory`, by the compiler `producer`.
+    pub fn new(producer: &str, name: &str, directory: &str) -> Self {
+        Self {
+            producer: producer.into(),
+            name: name.into(),
+            directory: directory.into(),
+            files: PrimaryMap::new(),
+            types: PrimaryMap::new(),
+            functions: BTreeMap::new(),
+            compiled_functions: BTreeMap::new(),
+        }
+    }
+
+    /// The name of the compiler.
+    pub fn producer(&self) -> &str {
+        &self.producer
+    }
+
+    /// The name of the compiled source file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The compilation directory.
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
+    /// Register a source file.
+    pub fn add_file(&mut self, name: &str, directory: &str) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            directory: directory.into(),
+        })
+    }
+
+    /// The registered source files.
+    pub fn files(&self) -> &PrimaryMap<FileId, SourceFile> {
+        &self.files
+    }
+
+    /// Register a variable type.
+    pub fn add_type(&mut self, ty: TypeDescription) -> TypeId {
+        self.types.push(ty)
+    }
+
+    /// The registered variable types.
+    pub fn types(&self) -> &PrimaryMap<TypeId, TypeDescription> {
+        &self.types
+    }
+
+    /// Register the debug information of the function `func_id`.
+    pub fn define_function(&mut self, func_id: FuncId, info: FunctionDebugInfo) {
+        self.functions.insert(func_id, info);
+    }
+
+    /// Add the debug information collected from the compiled function `func_id`.
+    pub fn add_compiled_function(&mut self, func_id: FuncId, info: CompiledFunctionDebugInfo) {
+        self.compiled_functions.insert(func_id, info);
+    }
+
+    /// The functions that are both registered and compiled, in `FuncId` order.
+    pub fn functions(
+        &self,
+    ) -> impl Iterator<Item = (FuncId, &FunctionDebugInfo, &CompiledFunctionDebugInfo)> {
+        self.functions.iter().filter_map(move |(&func_id, info)| {
+            let compiled = self.compiled_functions.get(&func_id)?;
+            Some((func_id, info, compiled))
+        })
+    }
+}
@@ -0,0 +1,425 @@
+//! Inlining of small functions into their callers.
+//!
+//! An `Inliner` keeps a copy of the bodies of the functions that are small enough to be inlined,
+//! and replaces the `call`s to them in other functions with copies of those bodies.
+
+use super::HashMap;
+use crate::module::FuncId;
+use cranelift_codegen::entity::packed_option::ReservedValue;
+use cranelift_codegen::entity::{EntityRef, SecondaryMap};
+use cranelift_codegen::ir::{self, InstBuilder, InstructionData, Opcode};
+use std::vec::Vec;
+
+/// The default maximum size, in instructions, of the functions an `Inliner` inlines.
+pub const DEFAULT_MAX_INLINE_SIZE: usize = 16;
+
+/// Inlines calls to small functions defined in the same module.
+///
+/// Functions are registered with `add_function`, which keeps a copy of their body if it can be
+/// inlined. Only direct `call`s of registered functions are inlined; calls in the inlined bodies
+/// are not inlined again, so recursion is bounded.
+pub struct Inliner {
+    max_size: usize,
+    bodies: HashMap<FuncId, ir::Function>,
+}
+
+impl Inliner {
+    /// Create an inliner for functions of at most `DEFAULT_MAX_INLINE_SIZE` instructions.
+    pub fn new() -> Self {
+        Self::with_max_size(DEFAULT_MAX_INLINE_SIZE)
+    }
+
+    /// Create an inliner for functions of at most `max_size` instructions.
+    pub fn with_max_size(max_size: usize) -> Self {
+        Self {
+            max_size,
+            bodies: HashMap::new(),
+        }
+    }
+
+    /// Register the body of the function `id`, so that calls to it can be inlined.
+    ///
+    /// The function must not have been compiled yet. The caller is responsible for only
+    /// registering functions whose definition can't be replaced at link time.
+    ///
+    /// Returns whether the function can be inlined.
+    pub fn add_function(&mut self, id: FuncId, func: &ir::Function) -> bool {
+        if is_inlinable(func, self.max_size) {
+            self.bodies.insert(id, func.clone());
+            true
+        } else {
+            self.bodies.remove(&id);
+            false
+        }
+    }
+
+    /// Inline the calls to registered functions in `func`.
+    ///
+    /// Returns the number of inlined calls.
+    pub fn inline_calls(&self, func: &mut ir::Function) -> usize {
+        let mut calls = Vec::new();
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+                    if let Some(callee) = self.get_callee(func, func_ref) {
+                        calls.push((inst, callee));
+                    }
+                }
+            }
+        }
+
+        let count = calls.len();
+        for (call, callee) in calls {
+            inline_call(func, call, callee);
+        }
+        count
+    }
+
+    /// Inline the calls between the functions of `functions`, and to the functions registered
+    /// earlier.
+    ///
+    /// Callees are processed before their callers, so that calls through several small
+    /// functions are inlined completely. All the functions that can be inlined are registered.
+    ///
+    /// Returns the number of inlined calls.
+    pub fn inline_functions(&mut self, functions: &mut [(FuncId, ir::Function)]) -> usize {
+        let mut count = 0;
+        for index in callees_first(functions) {
+            let (id, func) = &mut functions[index];
+            count += self.inline_calls(func);
+            self.add_function(*id, func);
+        }
+        count
+    }
+
+    /// Get the body of the function called through `func_ref` in `func`, if it can be inlined.
+    fn get_callee(&self, func: &ir::Function, func_ref: ir::FuncRef) -> Option<&ir::Function> {
+        let ext_func = &func.dfg.ext_funcs[func_ref];
+        let callee = self.bodies.get(&callee_id(ext_func)?)?;
+
+        // Only inline calls whose signature matches the definition of the callee.
+        let sig = &func.dfg.signatures[ext_func.signature];
+        if value_types(&sig.params).eq(value_types(&callee.signature.params))
+            && value_types(&sig.returns).eq(value_types(&callee.signature.returns))
+        {
+            Some(callee)
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the `FuncId` of a function declared by a `Module`.
+fn callee_id(ext_func: &ir::ExtFuncData) -> Option<FuncId> {
+    match ext_func.name {
+        ir::ExternalName::User {
+            namespace: 0,
+            index,
+        } => Some(FuncId::from_u32(index)),
+        _ => None,
+    }
+}
+
+fn value_types(params: &[ir::AbiParam]) -> impl Iterator<Item = ir::Type> + '_ {
+    params.iter().map(|param| param.value_type)
+}
+
+/// Can `func` be inlined into other functions?
+fn is_inlinable(func: &ir::Function, max_size: usize) -> bool {
+    if func.layout.entry_block().is_none() {
+        return false;
+    }
+
+    // A `vmctx` global value refers to the VM context parameter of the function itself.
+    if func
+        .global_values
+        .values()
+        .any(|gv| matches!(gv, ir::GlobalValueData::VMContext))
+    {
+        return false;
+    }
+
+    // Other kinds of stack slots belong to the frame of the function, or are only created by
+    // the compilation.
+    if func
+        .stack_slots
+        .values()
+        .any(|ss| ss.kind != ir::StackSlotKind::ExplicitSlot)
+    {
+        return false;
+    }
+
+    let size = func
+        .layout
+        .blocks()
+        .map(|block| func.layout.block_insts(block).count())
+        .sum::<usize>();
+    size <= max_size
+}
+
+/// Order the indices of `functions` so that the functions they call come before them, except
+/// in cycles of recursive calls.
+fn callees_first(functions: &[(FuncId, ir::Function)]) -> Vec<usize> {
+    let indices: HashMap<FuncId, usize> = functions
+        .iter()
+        .enumerate()
+        .map(|(index, (id, _))| (*id, index))
+        .collect();
+
+    let mut order = Vec::with_capacity(functions.len());
+    let mut visited = vec![false; functions.len()];
+    for root in 0..functions.len() {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+
+        // A depth-first search of the call graph, which adds functions in post-order. Each
+        // entry of the stack holds the function and its callees that are still to be visited.
+        let mut stack = vec![(root, called_functions(&functions[root].1, &indices))];
+        while let Some((index, callees)) = stack.last_mut() {
+            match callees.pop() {
+                Some(callee) if !visited[callee] => {
+                    visited[callee] = true;
+                    let callees = called_functions(&functions[callee].1, &indices);
+                    stack.push((callee, callees));
+                }
+                Some(_) => {}
+                None => {
+                    order.push(*index);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    order
+}
+
+/// Get the indices of the functions of `indices` that `func` calls.
+fn called_functions(func: &ir::Function, indices: &HashMap<FuncId, usize>) -> Vec<usize> {
+    func.dfg
+        .ext_funcs
+        .values()
+        .filter_map(|ext_func| indices.get(&callee_id(ext_func)?).copied())
+        .collect()
+}
+
+/// Replace the `call` instruction `call` in `func` with a copy of the body of `callee`.
+fn inline_call(func: &mut ir::Function, call: ir::Inst, callee: &ir::Function) {
+    let srcloc = func.srclocs[call];
+    let args = func.dfg.inst_args(call).to_vec();
+
+    // The rest of the calling block moves to a new block, whose parameters are the results of
+    // the call. The returns of the callee jump to it.
+    let return_block = func.dfg.make_block();
+    let next_inst = func
+        .layout
+        .next_inst(call)
+        .expect("a call can't terminate its block");
+    func.layout.split_block(return_block, next_inst);
+    let results = func.dfg.detach_results(call);
+    let results = results.as_slice(&func.dfg.value_lists).to_vec();
+    for result in results {
+        func.dfg.attach_block_param(return_block, result);
+    }
+
+    // Copy the entities the body of the callee refers to.
+    let sig_refs: Vec<_> = callee
+        .dfg
+        .signatures
+        .values()
+        .map(|sig| func.import_signature(sig.clone()))
+        .collect();
+    let func_refs: Vec<_> = callee
+        .dfg
+        .ext_funcs
+        .values()
+        .map(|ext_func| {
+            func.import_function(ir::ExtFuncData {
+                name: ext_func.name.clone(),
+                signature: sig_refs[ext_func.signature.index()],
+                colocated: ext_func.colocated,
+            })
+        })
+        .collect();
+
+    // Global values may refer to the ones after them, so they are all created at once.
+    let first_gv = func.global_values.len();
+    let map_gv = |gv: ir::GlobalValue| ir::GlobalValue::new(first_gv + gv.index());
+    for data in callee.global_values.values() {
+        let mut data = data.clone();
+        match &mut data {
+            ir::GlobalValueData::Load { base, .. } | ir::GlobalValueData::IAddImm { base, .. } => {
+                *base = map_gv(*base)
+            }
+            _ => {}
+        }
+        func.create_global_value(data);
+    }
+    let heaps: Vec<_> = callee
+        .heaps
+        .values()
+        .map(|data| {
+            let mut data = data.clone();
+            data.base = map_gv(data.base);
+            if let ir::HeapStyle::Dynamic { bound_gv } = &mut data.style {
+                *bound_gv = map_gv(*bound_gv);
+            }
+            func.create_heap(data)
+        })
+        .collect();
+    let tables: Vec<_> = callee
+        .tables
+        .values()
+        .map(|data| {
+            let mut data = data.clone();
+            data.base_gv = map_gv(data.base_gv);
+            data.bound_gv = map_gv(data.bound_gv);
+            func.create_table(data)
+        })
+        .collect();
+    let stack_slots: Vec<_> = callee
+        .stack_slots
+        .values()
+        .map(|data| func.create_stack_slot(data.clone()))
+        .collect();
+
+    // Copy the blocks, placed between the calling block and the return block.
+    let mut blocks = SecondaryMap::with_default(ir::Block::reserved_value());
+    let mut values = SecondaryMap::with_default(ir::Value::reserved_value());
+    for block in callee.layout.blocks() {
+        let new_block = func.dfg.make_block();
+        func.layout.insert_block(new_block, return_block);
+        blocks[block] = new_block;
+        for &param in callee.dfg.block_params(block) {
+            values[param] = func
+                .dfg
+                .append_block_param(new_block, callee.dfg.value_type(param));
+        }
+    }
+    let jump_tables: Vec<_> = callee
+        .jump_tables
+        .values()
+        .map(|data| {
+            let mut data = data.clone();
+            for dest in data.iter_mut() {
+                *dest = blocks[*dest];
+            }
+            func.create_jump_table(data)
+        })
+        .collect();
+
+    // Values live across the call are also live across every call in the inlined body, so the
+    // user stack maps of those calls include the entries of the call.
+    let call_stack_map = func.dfg.take_user_stack_map_entries(call);
+
+    // Copy the instructions. Their arguments are remapped once all the values are created, as
+    // a value can be used before its definition in the layout.
+    let mut new_insts = Vec::new();
+    for block in callee.layout.blocks() {
+        for inst in callee.layout.block_insts(block) {
+            let mut data = callee.dfg[inst].clone();
+            if let Some(list) = data.take_value_list() {
+                let list = ir::ValueList::from_slice(
+                    list.as_slice(&callee.dfg.value_lists),
+                    &mut func.dfg.value_lists,
+                );
+                data.put_value_list(list);
+            }
+
+            match &mut data {
+                InstructionData::Call { func_ref, .. }
+                | InstructionData::FuncAddr { func_ref, .. } => {
+                    *func_ref = func_refs[func_ref.index()]
+                }
+                InstructionData::CallIndirect { sig_ref, .. } => {
+                    *sig_ref = sig_refs[sig_ref.index()]
+                }
+                InstructionData::UnaryGlobalValue { global_value, .. } => {
+                    *global_value = map_gv(*global_value)
+                }
+                InstructionData::HeapAddr { heap, .. } => *heap = heaps[heap.index()],
+                InstructionData::TableAddr { table, .. } => *table = tables[table.index()],
+                InstructionData::StackLoad { stack_slot, .. }
+                | InstructionData::StackStore { stack_slot, .. } => {
+                    *stack_slot = stack_slots[stack_slot.index()]
+                }
+                InstructionData::BranchTable {
+                    destination, table, ..
+                } => {
+                    *destination = blocks[*destination];
+                    *table = jump_tables[table.index()];
+                }
+                InstructionData::BranchTableEntry { table, .. }
+                | InstructionData::BranchTableBase { table, .. }
+                | InstructionData::IndirectJump { table, .. } => {
+                    *table = jump_tables[table.index()]
+                }
+                InstructionData::UnaryConst {
+                    constant_handle, ..
+                } => {
+                    let constant = callee.dfg.constants.get(*constant_handle).clone();
+                    *constant_handle = func.dfg.constants.insert(constant);
+                }
+                InstructionData::Shuffle { mask, .. } => {
+                    *mask = func
+                        .dfg
+                        .immediates
+                        .push(callee.dfg.immediates[*mask].clone())
+                }
+                _ => {}
+            }
+            if let Some(dest) = data.branch_destination_mut() {
+                *dest = blocks[*dest];
+            }
+
+            // A return passes the returned values to the return block.
+            if data.opcode().is_return() {
+                data = InstructionData::Jump {
+                    opcode: Opcode::Jump,
+                    args: data.take_value_list().unwrap_or_default(),
+                    destination: return_block,
+                };
+            }
+
+            let new_inst = func.dfg.make_inst(data);
+            func.dfg
+                .make_inst_results(new_inst, callee.dfg.ctrl_typevar(inst));
+            for (&result, &new_result) in callee
+                .dfg
+                .inst_results(inst)
+                .iter()
+                .zip(func.dfg.inst_results(new_inst))
+            {
+                values[result] = new_result;
+            }
+            if func.dfg[new_inst].opcode().is_call() {
+                let entries = callee.dfg.user_stack_map_entries(inst).unwrap_or_default();
+                for entry in entries {
+                    func.dfg.append_user_stack_map_entry(
+                        new_inst,
+                        ir::UserStackMapEntry {
+                            slot: stack_slots[entry.slot.index()],
+                            ..*entry
+                        },
+                    );
+                }
+                for entry in &call_stack_map {
+                    func.dfg.append_user_stack_map_entry(new_inst, *entry);
+                }
+            }
+            func.layout.append_inst(new_inst, blocks[block]);
+            func.srclocs[new_inst] = srcloc;
+            new_insts.push(new_inst);
+        }
+    }
+    for new_inst in new_insts {
+        for arg in func.dfg.inst_args_mut(new_inst) {
+            *arg = values[callee.dfg.resolve_aliases(*arg)];
+        }
+    }
+
+    // Finally, the call becomes a jump to the copy of the entry block.
+    let entry = blocks[callee.layout.entry_block().unwrap()];
+    func.dfg.replace(call).jump(entry, &args);
+}
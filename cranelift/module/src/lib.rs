@@ -37,10 +37,18 @@ use std::string::String;
 use cranelift_codegen::ir;
 
 mod data_context;
+mod debug;
+mod inline;
 mod module;
 mod traps;
 
 pub use crate::data_context::{DataContext, DataDescription, Init};
+pub use crate::debug::{
+    CompiledFunctionDebugInfo, DebugContext, FileId, FunctionDebugInfo, SourceFile, SourceLocation,
+    TypeDescription, TypeEncoding, TypeId, VariableDescription, VariableLocation,
+    VariableLocationRange,
+};
+pub use crate::inline::{Inliner, DEFAULT_MAX_INLINE_SIZE};
 pub use crate::module::{
     DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleCompiledFunction, ModuleDeclarations,
     ModuleError, ModuleResult, RelocRecord,
@@ -59,6 +67,10 @@ pub fn default_libcall_names() -> Box<dyn Fn(ir::LibCall) -> String + Send + Syn
         ir::LibCall::SdivI64 => "__divdi3".to_owned(),
         ir::LibCall::UremI64 => "__umoddi3".to_owned(),
         ir::LibCall::SremI64 => "__moddi3".to_owned(),
+        ir::LibCall::UdivI128 => "__udivti3".to_owned(),
+        ir::LibCall::SdivI128 => "__divti3".to_owned(),
+        ir::LibCall::UremI128 => "__umodti3".to_owned(),
+        ir::LibCall::SremI128 => "__modti3".to_owned(),
         ir::LibCall::IshlI64 => "__ashldi3".to_owned(),
         ir::LibCall::UshrI64 => "__lshrdi3".to_owned(),
         ir::LibCall::SshrI64 => "__ashrdi3".to_owned(),
@@ -7,11 +7,13 @@
 
 use super::HashMap;
 use crate::data_context::DataContext;
+use crate::inline::Inliner;
 use cranelift_codegen::binemit;
 use cranelift_codegen::entity::{entity_impl, PrimaryMap};
 use cranelift_codegen::{ir, isa, CodegenError, Context};
 use std::borrow::ToOwned;
 use std::string::String;
+use std::vec::Vec;
 use thiserror::Error;
 
 /// A function identifier for use in the `Module` interface.
@@ -334,6 +336,10 @@ impl ModuleDeclarations {
 pub struct ModuleCompiledFunction {
     /// The size of the compiled function.
     pub size: binemit::CodeOffset,
+    /// The user stack maps of the function, keyed by the offset of the return address of the
+    /// call they describe. Values are only included in user stack maps when declared with
+    /// `FunctionBuilder::declare_value_needs_stack_map`.
+    pub user_stack_maps: Vec<(binemit::CodeOffset, binemit::UserStackMap)>,
 }
 
 /// A record of a relocation to perform.
@@ -473,6 +479,29 @@ pub trait Module {
     where
         TS: binemit::TrapSink;
 
+    /// Define a function like `define_function`, after inlining the calls to the small
+    /// functions that were defined with `inliner` before.
+    ///
+    /// The function is then registered with `inliner` itself, unless its definition can be
+    /// preempted, so defining callees before their callers inlines the most calls.
+    fn define_function_with_inliner<TS>(
+        &mut self,
+        func: FuncId,
+        ctx: &mut Context,
+        inliner: &mut Inliner,
+        trap_sink: &mut TS,
+    ) -> ModuleResult<ModuleCompiledFunction>
+    where
+        TS: binemit::TrapSink,
+    {
+        inliner.inline_calls(&mut ctx.func);
+        let linkage = self.declarations().get_function_decl(func).linkage;
+        if linkage.is_final() {
+            inliner.add_function(func, &ctx.func);
+        }
+        self.define_function(func, ctx, trap_sink)
+    }
+
     /// Define a function, taking the function body from the given `bytes`.
     ///
     /// This function is generally only useful if you need to precisely specify
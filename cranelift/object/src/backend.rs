@@ -1,20 +1,26 @@
 //! Defines `ObjectModule`.
 
+use crate::debug::{build_debug_sections, DebugRelocTarget};
 use anyhow::anyhow;
 use cranelift_codegen::entity::SecondaryMap;
+use cranelift_codegen::isa::unwind::{systemv, UnwindInfo};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, ir};
 use cranelift_codegen::{
-    binemit::{Addend, CodeInfo, CodeOffset, NullStackMapSink, Reloc, RelocSink, TrapSink},
+    binemit::{
+        Addend, CodeInfo, CodeOffset, NullStackMapSink, Reloc, RelocSink, TrapSink, UserStackMap,
+    },
     CodegenError,
 };
 use cranelift_module::{
-    DataContext, DataDescription, DataId, FuncId, Init, Linkage, Module, ModuleCompiledFunction,
-    ModuleDeclarations, ModuleError, ModuleResult, RelocRecord,
+    CompiledFunctionDebugInfo, DataContext, DataDescription, DataId, DebugContext, FuncId, Init,
+    Linkage, Module, ModuleCompiledFunction, ModuleDeclarations, ModuleError, ModuleResult,
+    RelocRecord,
 };
 use log::info;
 use object::write::{
-    Object, Relocation, SectionId, StandardSection, Symbol, SymbolId, SymbolSection,
+    Object, Relocation, SectionId, StandardSection, StandardSegment, Symbol, SymbolId,
+    SymbolSection,
 };
 use object::{
     RelocationEncoding, RelocationKind, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
@@ -22,7 +28,7 @@ use object::{
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::mem;
-use target_lexicon::PointerWidth;
+use target_lexicon::{Endianness, PointerWidth};
 
 /// A builder for `ObjectModule`.
 pub struct ObjectBuilder {
@@ -34,6 +40,7 @@ pub struct ObjectBuilder {
     libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
     function_alignment: u64,
     per_function_section: bool,
+    debug_context: Option<DebugContext>,
 }
 
 impl ObjectBuilder {
@@ -93,6 +100,7 @@ impl ObjectBuilder {
             libcall_names,
             function_alignment: 1,
             per_function_section: false,
+            debug_context: None,
         })
     }
 
@@ -107,6 +115,12 @@ impl ObjectBuilder {
         self.per_function_section = per_function_section;
         self
     }
+
+    /// Emit DWARF debug information for the functions registered in `debug_context`.
+    pub fn debug_context(&mut self, debug_context: DebugContext) -> &mut Self {
+        self.debug_context = Some(debug_context);
+        self
+    }
 }
 
 /// An `ObjectModule` implements `Module` and emits ".o" files using the `object` library.
@@ -123,6 +137,9 @@ pub struct ObjectModule {
     libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
     function_alignment: u64,
     per_function_section: bool,
+    debug_context: Option<DebugContext>,
+    unwind_infos: Vec<(FuncId, systemv::UnwindInfo)>,
+    user_stack_maps: Vec<(FuncId, Vec<(CodeOffset, UserStackMap)>)>,
 }
 
 impl ObjectModule {
@@ -141,8 +158,17 @@ impl ObjectModule {
             libcall_names: builder.libcall_names,
             function_alignment: builder.function_alignment,
             per_function_section: builder.per_function_section,
+            debug_context: builder.debug_context,
+            unwind_infos: Vec::new(),
+            user_stack_maps: Vec::new(),
         }
     }
+
+    /// Get the `DebugContext` passed to `ObjectBuilder::debug_context`, to register the debug
+    /// information of functions before defining them.
+    pub fn debug_context_mut(&mut self) -> Option<&mut DebugContext> {
+        self.debug_context.as_mut()
+    }
 }
 
 impl Module for ObjectModule {
@@ -259,7 +285,32 @@ impl Module for ObjectModule {
             )
         };
 
-        self.define_function_bytes(func_id, &code, &reloc_sink.relocs)
+        let mut compiled = self.define_function_bytes(func_id, &code, &reloc_sink.relocs)?;
+        compiled.user_stack_maps =
+            ctx.mach_compile_result
+                .as_ref()
+                .map_or_else(Vec::new, |result| {
+                    result
+                        .buffer
+                        .user_stack_maps()
+                        .iter()
+                        .map(|map| (map.offset, map.stack_map.clone()))
+                        .collect()
+                });
+        if !compiled.user_stack_maps.is_empty() {
+            self.user_stack_maps
+                .push((func_id, compiled.user_stack_maps.clone()));
+        }
+
+        if let Some(debug_context) = &mut self.debug_context {
+            let info = CompiledFunctionDebugInfo::collect(ctx, &*self.isa, code_size)?;
+            debug_context.add_compiled_function(func_id, info);
+            if let Some(UnwindInfo::SystemV(unwind_info)) = ctx.create_unwind_info(&*self.isa)? {
+                self.unwind_infos.push((func_id, unwind_info));
+            }
+        }
+
+        Ok(compiled)
     }
 
     fn define_function_bytes(
@@ -316,7 +367,10 @@ impl Module for ObjectModule {
             });
         }
 
-        Ok(ModuleCompiledFunction { size: total_size })
+        Ok(ModuleCompiledFunction {
+            size: total_size,
+            user_stack_maps: Vec::new(),
+        })
     }
 
     fn define_data(&mut self, data_id: DataId, data_ctx: &DataContext) -> ModuleResult<()> {
@@ -443,6 +497,14 @@ impl ObjectModule {
             }
         }
 
+        if !self.user_stack_maps.is_empty() {
+            self.emit_user_stack_maps_section();
+        }
+
+        if let Some(debug_context) = self.debug_context.take() {
+            self.emit_debug_sections(&debug_context);
+        }
+
         // Indicate that this object has a non-executable stack.
         if self.object.format() == object::BinaryFormat::Elf {
             self.object.add_section(
@@ -459,6 +521,127 @@ impl ObjectModule {
         }
     }
 
+    /// Add the `.user_stack_maps` section describing the user stack maps of the defined
+    /// functions.
+    ///
+    /// The section contains one record per call with a user stack map, each aligned to the
+    /// pointer size and laid out as follows, in the target's endianness:
+    ///
+    /// - the pointer-sized address of the return address of the call,
+    /// - a `u32` count of entries, and
+    /// - for each entry, a `u32` offset from the stack pointer at the return address and a `u32`
+    ///   size in bytes of the value stored there.
+    fn emit_user_stack_maps_section(&mut self) {
+        let pointer_bytes = self.isa.pointer_bytes();
+        let big_endian = self.isa.triple().endianness() == Ok(Endianness::Big);
+        let write_u32 = |data: &mut Vec<u8>, value: u32| {
+            if big_endian {
+                data.extend_from_slice(&value.to_be_bytes());
+            } else {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+
+        let mut data = Vec::new();
+        let mut relocs = Vec::new();
+        for (func_id, stack_maps) in &self.user_stack_maps {
+            let symbol = self.functions[*func_id].unwrap().0;
+            for (offset, stack_map) in stack_maps {
+                relocs.push(Relocation {
+                    offset: data.len() as u64,
+                    size: pointer_bytes * 8,
+                    kind: RelocationKind::Absolute,
+                    encoding: RelocationEncoding::Generic,
+                    symbol,
+                    addend: i64::from(*offset),
+                });
+                data.resize(data.len() + usize::from(pointer_bytes), 0);
+                write_u32(&mut data, stack_map.entries().len() as u32);
+                for &(ty, sp_offset) in stack_map.entries() {
+                    write_u32(&mut data, sp_offset);
+                    write_u32(&mut data, ty.bytes());
+                }
+                let aligned = (data.len() + usize::from(pointer_bytes) - 1)
+                    & !(usize::from(pointer_bytes) - 1);
+                data.resize(aligned, 0);
+            }
+        }
+
+        let segment = self.object.segment_name(StandardSegment::Data).to_vec();
+        let name = if self.object.format() == object::BinaryFormat::MachO {
+            "__user_stackmaps"
+        } else {
+            ".user_stack_maps"
+        };
+        let section = self
+            .object
+            .add_section(segment, name.as_bytes().to_vec(), SectionKind::Data);
+        self.object
+            .append_section_data(section, &data, u64::from(pointer_bytes));
+        for reloc in relocs {
+            self.object.add_relocation(section, reloc).unwrap();
+        }
+    }
+
+    /// Add the DWARF sections describing the defined functions.
+    fn emit_debug_sections(&mut self, debug_context: &DebugContext) {
+        // The debug info is built from consistent module state, so writing it can't fail.
+        let debug_sections = build_debug_sections(
+            &*self.isa,
+            debug_context,
+            &self.declarations,
+            &self.unwind_infos,
+        )
+        .expect("failed to write debug info");
+
+        let segment = self.object.segment_name(StandardSegment::Debug).to_vec();
+        let section_ids = debug_sections
+            .iter()
+            .map(|section| {
+                let name = if self.object.format() == object::BinaryFormat::MachO {
+                    section.id.name().replace('.', "__")
+                } else {
+                    section.id.name().to_string()
+                };
+                let section_id =
+                    self.object
+                        .add_section(segment.clone(), name.into_bytes(), SectionKind::Debug);
+                self.object
+                    .append_section_data(section_id, &section.data, 1);
+                (section.id, section_id)
+            })
+            .collect::<HashMap<_, _>>();
+
+        for section in &debug_sections {
+            for reloc in &section.relocs {
+                let symbol = match reloc.target {
+                    DebugRelocTarget::Func(func_id) => self.functions[func_id].unwrap().0,
+                    // Mach-O debug sections refer to each other with plain offsets, which are
+                    // already written in the section data.
+                    DebugRelocTarget::Section(_)
+                        if self.object.format() == object::BinaryFormat::MachO =>
+                    {
+                        continue
+                    }
+                    DebugRelocTarget::Section(id) => self.object.section_symbol(section_ids[&id]),
+                };
+                self.object
+                    .add_relocation(
+                        section_ids[&section.id],
+                        Relocation {
+                            offset: u64::from(reloc.offset),
+                            size: reloc.size * 8,
+                            kind: RelocationKind::Absolute,
+                            encoding: RelocationEncoding::Generic,
+                            symbol,
+                            addend: reloc.addend,
+                        },
+                    )
+                    .unwrap();
+            }
+        }
+    }
+
     /// This should only be called during finish because it creates
     /// symbols for missing libcalls.
     fn get_symbol(&mut self, name: &ir::ExternalName) -> SymbolId {
@@ -540,6 +723,37 @@ impl ObjectModule {
                     32,
                 )
             }
+            Reloc::Arm64Call
+            | Reloc::Arm64AdrPrelPgHi21
+            | Reloc::Arm64AddAbsLo12Nc
+            | Reloc::Arm64AdrGotPage21
+            | Reloc::Arm64Ld64GotLo12Nc
+            | Reloc::ElfAarch64TlsDescAdrPage21
+            | Reloc::ElfAarch64TlsDescLd64Lo12
+            | Reloc::ElfAarch64TlsDescAddLo12
+            | Reloc::ElfAarch64TlsDescCall => {
+                // These are patched into instructions, which only works with the explicit
+                // addends of ELF relocations.
+                assert_eq!(
+                    self.object.format(),
+                    object::BinaryFormat::Elf,
+                    "{:?} is not supported for this file format",
+                    record.reloc
+                );
+                let r_type = match record.reloc {
+                    Reloc::Arm64Call => object::elf::R_AARCH64_CALL26,
+                    Reloc::Arm64AdrPrelPgHi21 => object::elf::R_AARCH64_ADR_PREL_PG_HI21,
+                    Reloc::Arm64AddAbsLo12Nc => object::elf::R_AARCH64_ADD_ABS_LO12_NC,
+                    Reloc::Arm64AdrGotPage21 => object::elf::R_AARCH64_ADR_GOT_PAGE,
+                    Reloc::Arm64Ld64GotLo12Nc => object::elf::R_AARCH64_LD64_GOT_LO12_NC,
+                    Reloc::ElfAarch64TlsDescAdrPage21 => object::elf::R_AARCH64_TLSDESC_ADR_PAGE21,
+                    Reloc::ElfAarch64TlsDescLd64Lo12 => object::elf::R_AARCH64_TLSDESC_LD64_LO12,
+                    Reloc::ElfAarch64TlsDescAddLo12 => object::elf::R_AARCH64_TLSDESC_ADD_LO12,
+                    Reloc::ElfAarch64TlsDescCall => object::elf::R_AARCH64_TLSDESC_CALL,
+                    _ => unreachable!(),
+                };
+                (RelocationKind::Elf(r_type), RelocationEncoding::Generic, 32)
+            }
             // FIXME
             _ => unimplemented!(),
         };
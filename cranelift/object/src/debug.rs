@@ -0,0 +1,370 @@
+//! Emission of DWARF debug information for `ObjectModule`.
+
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::isa::unwind::systemv;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_module::{
+    DebugContext, FuncId, Linkage, ModuleDeclarations, SourceLocation, TypeEncoding,
+    VariableLocation,
+};
+use gimli::write::{
+    Address, AttributeValue, DebuggingInformationEntry, DwarfUnit, EndianVec, Expression, FileId,
+    FrameTable, LineProgram, LineString, Location, LocationList, Range, RangeList, Sections,
+    Writer,
+};
+use gimli::{constants, Encoding, Format, LineEncoding, Register, RunTimeEndian, SectionId};
+use std::mem;
+
+/// The target of a relocation in a debug section.
+#[derive(Clone, Copy)]
+pub(crate) enum DebugRelocTarget {
+    /// The address of a function.
+    Func(FuncId),
+    /// The start of another debug section.
+    Section(SectionId),
+}
+
+/// A relocation in a debug section.
+#[derive(Clone)]
+pub(crate) struct DebugReloc {
+    pub offset: u32,
+    pub size: u8,
+    pub target: DebugRelocTarget,
+    pub addend: i64,
+}
+
+/// A `gimli::write::Writer` which records the relocations of the addresses and section offsets
+/// it writes.
+#[derive(Clone)]
+struct WriterRelocate {
+    relocs: Vec<DebugReloc>,
+    writer: EndianVec<RunTimeEndian>,
+}
+
+impl WriterRelocate {
+    fn new(endian: RunTimeEndian) -> Self {
+        Self {
+            relocs: Vec::new(),
+            writer: EndianVec::new(endian),
+        }
+    }
+}
+
+impl Writer for WriterRelocate {
+    type Endian = RunTimeEndian;
+
+    fn endian(&self) -> Self::Endian {
+        self.writer.endian()
+    }
+
+    fn len(&self) -> usize {
+        self.writer.len()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.writer.write(bytes)
+    }
+
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.writer.write_at(offset, bytes)
+    }
+
+    fn write_address(&mut self, address: Address, size: u8) -> gimli::write::Result<()> {
+        match address {
+            Address::Constant(val) => self.write_udata(val, size),
+            Address::Symbol { symbol, addend } => {
+                let offset = self.len() as u32;
+                self.relocs.push(DebugReloc {
+                    offset,
+                    size,
+                    target: DebugRelocTarget::Func(FuncId::from_u32(symbol as u32)),
+                    addend,
+                });
+                self.write_udata(addend as u64, size)
+            }
+        }
+    }
+
+    fn write_offset(
+        &mut self,
+        val: usize,
+        section: SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        let offset = self.len() as u32;
+        self.relocs.push(DebugReloc {
+            offset,
+            size,
+            target: DebugRelocTarget::Section(section),
+            addend: val as i64,
+        });
+        self.write_udata(val as u64, size)
+    }
+
+    fn write_offset_at(
+        &mut self,
+        offset: usize,
+        val: usize,
+        section: SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        self.relocs.push(DebugReloc {
+            offset: offset as u32,
+            size,
+            target: DebugRelocTarget::Section(section),
+            addend: val as i64,
+        });
+        self.write_udata_at(offset, val as u64, size)
+    }
+}
+
+/// A debug section to add to the object.
+pub(crate) struct DebugSection {
+    pub id: SectionId,
+    pub data: Vec<u8>,
+    pub relocs: Vec<DebugReloc>,
+}
+
+/// The address of the function `func_id`, as written to the debug sections.
+fn func_address(func_id: FuncId, addend: i64) -> Address {
+    Address::Symbol {
+        symbol: func_id.as_u32() as usize,
+        addend,
+    }
+}
+
+/// Set the `DW_AT_decl_file` and `DW_AT_decl_line` attributes of `entry`.
+fn set_decl_location(
+    entry: &mut DebuggingInformationEntry,
+    location: SourceLocation,
+    file_ids: &[FileId],
+) {
+    entry.set(
+        constants::DW_AT_decl_file,
+        AttributeValue::FileIndex(Some(file_ids[location.file.index()])),
+    );
+    entry.set(
+        constants::DW_AT_decl_line,
+        AttributeValue::Udata(location.line.into()),
+    );
+}
+
+/// Build the DWARF sections describing the functions of `debug_context`, and the `.debug_frame`
+/// section describing the functions of `unwind_infos`.
+pub(crate) fn build_debug_sections(
+    isa: &dyn TargetIsa,
+    debug_context: &DebugContext,
+    declarations: &ModuleDeclarations,
+    unwind_infos: &[(FuncId, systemv::UnwindInfo)],
+) -> gimli::write::Result<Vec<DebugSection>> {
+    let endian = match isa.triple().endianness().unwrap() {
+        target_lexicon::Endianness::Little => RunTimeEndian::Little,
+        target_lexicon::Endianness::Big => RunTimeEndian::Big,
+    };
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: isa.pointer_bytes(),
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+
+    let comp_dir = LineString::new(debug_context.directory(), encoding, &mut dwarf.line_strings);
+    let comp_name = LineString::new(debug_context.name(), encoding, &mut dwarf.line_strings);
+    let mut line_program =
+        LineProgram::new(encoding, LineEncoding::default(), comp_dir, comp_name, None);
+    let file_ids = debug_context
+        .files()
+        .values()
+        .map(|file| {
+            let directory = if file.directory.is_empty() {
+                line_program.default_directory()
+            } else {
+                let directory =
+                    LineString::new(file.directory.as_str(), encoding, &mut dwarf.line_strings);
+                line_program.add_directory(directory)
+            };
+            let name = LineString::new(file.name.as_str(), encoding, &mut dwarf.line_strings);
+            line_program.add_file(name, directory, None)
+        })
+        .collect::<Vec<_>>();
+
+    let root = dwarf.unit.root();
+    let producer = dwarf.strings.add(debug_context.producer());
+    let name = dwarf.strings.add(debug_context.name());
+    let directory = dwarf.strings.add(debug_context.directory());
+    let entry = dwarf.unit.get_mut(root);
+    entry.set(
+        constants::DW_AT_producer,
+        AttributeValue::StringRef(producer),
+    );
+    entry.set(constants::DW_AT_name, AttributeValue::StringRef(name));
+    entry.set(
+        constants::DW_AT_comp_dir,
+        AttributeValue::StringRef(directory),
+    );
+    entry.set(
+        constants::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+
+    let type_ids = debug_context
+        .types()
+        .values()
+        .map(|ty| {
+            let name = dwarf.strings.add(ty.name.as_str());
+            let type_id = dwarf.unit.add(root, constants::DW_TAG_base_type);
+            let entry = dwarf.unit.get_mut(type_id);
+            entry.set(constants::DW_AT_name, AttributeValue::StringRef(name));
+            entry.set(constants::DW_AT_byte_size, AttributeValue::Data1(ty.size));
+            let ate = match ty.encoding {
+                TypeEncoding::Signed => constants::DW_ATE_signed,
+                TypeEncoding::Unsigned => constants::DW_ATE_unsigned,
+                TypeEncoding::Float => constants::DW_ATE_float,
+                TypeEncoding::Boolean => constants::DW_ATE_boolean,
+                TypeEncoding::Address => constants::DW_ATE_address,
+            };
+            entry.set(constants::DW_AT_encoding, AttributeValue::Encoding(ate));
+            type_id
+        })
+        .collect::<Vec<_>>();
+
+    let mut ranges = Vec::new();
+    for (func_id, info, compiled) in debug_context.functions() {
+        let decl = declarations.get_function_decl(func_id);
+        let low_pc = func_address(func_id, 0);
+        ranges.push(Range::StartLength {
+            begin: low_pc,
+            length: compiled.size.into(),
+        });
+
+        let name = dwarf
+            .strings
+            .add(info.name.as_deref().unwrap_or(&decl.name));
+        let mut frame_base = Expression::new();
+        frame_base.op(constants::DW_OP_call_frame_cfa);
+        let subprogram = dwarf.unit.add(root, constants::DW_TAG_subprogram);
+        let entry = dwarf.unit.get_mut(subprogram);
+        entry.set(constants::DW_AT_name, AttributeValue::StringRef(name));
+        if let Linkage::Export | Linkage::Preemptible = decl.linkage {
+            entry.set(constants::DW_AT_external, AttributeValue::FlagPresent);
+        }
+        entry.set(constants::DW_AT_low_pc, AttributeValue::Address(low_pc));
+        entry.set(
+            constants::DW_AT_high_pc,
+            AttributeValue::Udata(compiled.size.into()),
+        );
+        entry.set(
+            constants::DW_AT_frame_base,
+            AttributeValue::Exprloc(frame_base),
+        );
+        if let Some(location) = info.location {
+            set_decl_location(entry, location, &file_ids);
+        }
+
+        for variable in &info.variables {
+            // Ranges whose register has no DWARF number are left out.
+            let locations = compiled
+                .value_labels
+                .get(&variable.label)
+                .into_iter()
+                .flatten()
+                .filter_map(|range| {
+                    let mut expr = Expression::new();
+                    match range.location {
+                        VariableLocation::Reg(reg) => {
+                            expr.op_reg(Register(isa.map_dwarf_register(reg).ok()?))
+                        }
+                        VariableLocation::Stack(offset) => expr.op_fbreg(offset.into()),
+                    }
+                    Some(Location::StartLength {
+                        begin: func_address(func_id, range.start.into()),
+                        length: (range.end - range.start).into(),
+                        data: expr,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let tag = if variable.is_parameter {
+                constants::DW_TAG_formal_parameter
+            } else {
+                constants::DW_TAG_variable
+            };
+            let name = dwarf.strings.add(variable.name.as_str());
+            let location_list = if locations.is_empty() {
+                None
+            } else {
+                Some(dwarf.unit.locations.add(LocationList(locations)))
+            };
+            let var = dwarf.unit.add(subprogram, tag);
+            let entry = dwarf.unit.get_mut(var);
+            entry.set(constants::DW_AT_name, AttributeValue::StringRef(name));
+            entry.set(
+                constants::DW_AT_type,
+                AttributeValue::UnitRef(type_ids[variable.ty.index()]),
+            );
+            if let Some(location) = variable.location {
+                set_decl_location(entry, location, &file_ids);
+            }
+            if let Some(location_list) = location_list {
+                entry.set(
+                    constants::DW_AT_location,
+                    AttributeValue::LocationListRef(location_list),
+                );
+            }
+        }
+
+        line_program.begin_sequence(Some(low_pc));
+        for &(offset, srcloc) in &compiled.srclocs {
+            let row = line_program.row();
+            row.address_offset = offset.into();
+            // Code without a known position is attributed to line 0.
+            match info.srclocs.get(&srcloc) {
+                Some(location) => {
+                    row.file = file_ids[location.file.index()];
+                    row.line = location.line.into();
+                    row.column = location.column.into();
+                }
+                None => {
+                    row.line = 0;
+                    row.column = 0;
+                }
+            }
+            line_program.generate_row();
+        }
+        line_program.end_sequence(compiled.size.into());
+    }
+
+    let ranges = dwarf.unit.ranges.add(RangeList(ranges));
+    dwarf.unit.get_mut(root).set(
+        constants::DW_AT_ranges,
+        AttributeValue::RangeListRef(ranges),
+    );
+    dwarf.unit.line_program = line_program;
+
+    let mut sections = Sections::new(WriterRelocate::new(endian));
+    dwarf.write(&mut sections)?;
+
+    if !unwind_infos.is_empty() {
+        if let Some(cie) = isa.create_systemv_cie() {
+            let mut frame_table = FrameTable::default();
+            let cie_id = frame_table.add_cie(cie);
+            for (func_id, unwind_info) in unwind_infos {
+                frame_table.add_fde(cie_id, unwind_info.to_fde(func_address(*func_id, 0)));
+            }
+            frame_table.write_debug_frame(&mut sections.debug_frame)?;
+        }
+    }
+
+    let mut debug_sections = Vec::new();
+    sections.for_each_mut(|id, section| {
+        if !section.writer.slice().is_empty() {
+            debug_sections.push(DebugSection {
+                id,
+                data: section.writer.take(),
+                relocs: mem::take(&mut section.relocs),
+            });
+        }
+        Ok(())
+    })?;
+    Ok(debug_sections)
+}
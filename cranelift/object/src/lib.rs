@@ -25,6 +25,7 @@
 )]
 
 mod backend;
+mod debug;
 
 pub use crate::backend::{ObjectBuilder, ObjectModule, ObjectProduct};
 
@@ -197,3 +197,258 @@ fn libcall_function() {
 
     module.finish();
 }
+
+#[test]
+fn aarch64_pic_relocations() {
+    use object::read::{Object, ObjectSection};
+    use object::RelocationKind;
+    use settings::Configurable;
+
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_pic").unwrap();
+    flag_builder.set("tls_model", "elf_gd").unwrap();
+    let isa_builder = cranelift_codegen::isa::lookup_by_name("aarch64-unknown-linux-gnu").unwrap();
+    let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    let mut module =
+        ObjectModule::new(ObjectBuilder::new(isa, "foo", default_libcall_names()).unwrap());
+
+    let local_func = define_simple_function(&mut module);
+    let sig = Signature {
+        params: vec![],
+        returns: vec![AbiParam::new(types::I64)],
+        call_conv: CallConv::SystemV,
+    };
+    let import_func = module
+        .declare_function("import", Linkage::Import, &module.make_signature())
+        .unwrap();
+    let local_data = module
+        .declare_data("local_data", Linkage::Local, true, false)
+        .unwrap();
+    let import_data = module
+        .declare_data("import_data", Linkage::Import, true, false)
+        .unwrap();
+    let tls_data = module
+        .declare_data("tls_data", Linkage::Import, true, true)
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(8);
+    module.define_data(local_data, &data_ctx).unwrap();
+
+    let func_id = module
+        .declare_function("function", Linkage::Export, &sig)
+        .unwrap();
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.switch_to_block(block);
+
+        let local_callee = module.declare_func_in_func(local_func, &mut bcx.func);
+        bcx.ins().call(local_callee, &[]);
+        let import_callee = module.declare_func_in_func(import_func, &mut bcx.func);
+        bcx.ins().call(import_callee, &[]);
+
+        let mut sum = bcx.ins().iconst(types::I64, 0);
+        for &data_id in &[local_data, import_data, tls_data] {
+            let gv = module.declare_data_in_func(data_id, &mut bcx.func);
+            let addr = bcx.ins().global_value(types::I64, gv);
+            sum = bcx.ins().iadd(sum, addr);
+        }
+        bcx.ins().return_(&[sum]);
+    }
+
+    let mut trap_sink = NullTrapSink {};
+    module
+        .define_function(func_id, &mut ctx, &mut trap_sink)
+        .unwrap();
+
+    let bytes = module.finish().emit().unwrap();
+    let file = object::File::parse(&bytes).unwrap();
+    let text = file.section_by_name(".text").unwrap();
+    let r_types: Vec<u32> = text
+        .relocations()
+        .map(|(_, reloc)| match reloc.kind() {
+            RelocationKind::Elf(r_type) => r_type,
+            kind => panic!("unexpected relocation kind {:?}", kind),
+        })
+        .collect();
+    assert_eq!(
+        r_types,
+        vec![
+            object::elf::R_AARCH64_CALL26,
+            object::elf::R_AARCH64_ADR_GOT_PAGE,
+            object::elf::R_AARCH64_LD64_GOT_LO12_NC,
+            object::elf::R_AARCH64_ADR_PREL_PG_HI21,
+            object::elf::R_AARCH64_ADD_ABS_LO12_NC,
+            object::elf::R_AARCH64_ADR_GOT_PAGE,
+            object::elf::R_AARCH64_LD64_GOT_LO12_NC,
+            object::elf::R_AARCH64_TLSDESC_ADR_PAGE21,
+            object::elf::R_AARCH64_TLSDESC_LD64_LO12,
+            object::elf::R_AARCH64_TLSDESC_ADD_LO12,
+            object::elf::R_AARCH64_TLSDESC_CALL,
+        ]
+    );
+}
+
+#[test]
+fn debug_info_sections() {
+    use object::read::{Object, ObjectSection, ObjectSymbol, RelocationTarget};
+
+    let flag_builder = settings::builder();
+    let isa_builder = cranelift_codegen::isa::lookup_by_name("x86_64-unknown-linux-gnu").unwrap();
+    let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    let mut debug_context = DebugContext::new("test", "foo.c", "/tmp");
+    let file = debug_context.add_file("foo.c", "");
+    let int = debug_context.add_type(TypeDescription {
+        name: "int".into(),
+        size: 4,
+        encoding: TypeEncoding::Signed,
+    });
+    let mut builder = ObjectBuilder::new(isa, "foo", default_libcall_names()).unwrap();
+    builder.debug_context(debug_context);
+    let mut module = ObjectModule::new(builder);
+
+    let sig = Signature {
+        params: vec![AbiParam::new(types::I32)],
+        returns: vec![AbiParam::new(types::I32)],
+        call_conv: CallConv::SystemV,
+    };
+    let func_id = module
+        .declare_function("inc", Linkage::Export, &sig)
+        .unwrap();
+
+    let mut info = FunctionDebugInfo::new();
+    info.location = Some(SourceLocation {
+        file,
+        line: 1,
+        column: 1,
+    });
+    info.set_srcloc(
+        SourceLoc::new(1),
+        SourceLocation {
+            file,
+            line: 2,
+            column: 5,
+        },
+    );
+    info.add_variable(VariableDescription {
+        name: "x".into(),
+        ty: int,
+        label: ValueLabel::new(0),
+        location: info.location,
+        is_parameter: true,
+    });
+    module
+        .debug_context_mut()
+        .unwrap()
+        .define_function(func_id, info);
+
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig);
+    ctx.func.collect_debug_info();
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.append_block_params_for_function_params(block);
+        bcx.switch_to_block(block);
+        let x = bcx.block_params(block)[0];
+        bcx.set_srcloc(SourceLoc::new(1));
+        bcx.set_val_label(x, ValueLabel::new(0));
+        let one = bcx.ins().iconst(types::I32, 1);
+        bcx.set_srcloc(SourceLoc::new(2));
+        let sum = bcx.ins().iadd(x, one);
+        bcx.ins().return_(&[sum]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+
+    let mut trap_sink = NullTrapSink {};
+    module
+        .define_function(func_id, &mut ctx, &mut trap_sink)
+        .unwrap();
+
+    let bytes = module.finish().emit().unwrap();
+    let file = object::File::parse(&bytes).unwrap();
+    for name in &[
+        ".debug_abbrev",
+        ".debug_info",
+        ".debug_line",
+        ".debug_loc",
+        ".debug_ranges",
+        ".debug_str",
+        ".debug_frame",
+    ] {
+        assert!(file.section_by_name(name).is_some(), "missing {}", name);
+    }
+
+    // The line program starts at the address of the function, in `.text`.
+    let text = file.section_by_name(".text").unwrap();
+    let debug_line = file.section_by_name(".debug_line").unwrap();
+    let sections: Vec<_> = debug_line
+        .relocations()
+        .map(|(_, reloc)| match reloc.target() {
+            RelocationTarget::Symbol(index) => file.symbol_by_index(index).unwrap().section_index(),
+            target => panic!("unexpected relocation target {:?}", target),
+        })
+        .collect();
+    assert_eq!(sections, vec![Some(text.index())]);
+}
+
+#[test]
+fn aarch64_user_stack_maps() {
+    use object::read::{Object, ObjectSection};
+
+    let flag_builder = settings::builder();
+    let isa_builder = cranelift_codegen::isa::lookup_by_name("aarch64-unknown-linux-gnu").unwrap();
+    let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    let mut module =
+        ObjectModule::new(ObjectBuilder::new(isa, "foo", default_libcall_names()).unwrap());
+
+    let callee = define_simple_function(&mut module);
+    let sig = Signature {
+        params: vec![AbiParam::new(types::I64)],
+        returns: vec![AbiParam::new(types::I64)],
+        call_conv: CallConv::SystemV,
+    };
+    let func_id = module
+        .declare_function("function", Linkage::Export, &sig)
+        .unwrap();
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.append_block_params_for_function_params(block);
+        bcx.switch_to_block(block);
+        bcx.seal_block(block);
+        let gc_ref = bcx.block_params(block)[0];
+        bcx.declare_value_needs_stack_map(gc_ref);
+        let callee = module.declare_func_in_func(callee, &mut bcx.func);
+        bcx.ins().call(callee, &[]);
+        bcx.ins().return_(&[gc_ref]);
+        bcx.finalize();
+    }
+
+    let mut trap_sink = NullTrapSink {};
+    let compiled = module
+        .define_function(func_id, &mut ctx, &mut trap_sink)
+        .unwrap();
+    assert_eq!(compiled.user_stack_maps.len(), 1);
+    let (offset, stack_map) = &compiled.user_stack_maps[0];
+    assert!(*offset > 0 && *offset <= compiled.size);
+    assert_eq!(stack_map.entries(), &[(types::I64, 0)]);
+
+    let bytes = module.finish().emit().unwrap();
+    let file = object::File::parse(&bytes).unwrap();
+    let section = file.section_by_name(".user_stack_maps").unwrap();
+    // The address of the return address, followed by one entry of 8 bytes at `[sp]`.
+    assert_eq!(
+        section.data().unwrap(),
+        &[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(section.relocations().count(), 1);
+}
@@ -118,6 +118,25 @@ pub fn parse_test<'a>(text: &'a str, options: ParseOptions<'a>) -> ParseResult<T
         }
     }
 
+    #[cfg(feature = "experimental_riscv64")]
+    {
+        // Likewise, tests of the old backend that mention riscv64 may use settings or
+        // encodings the new riscv64 backend doesn't know about.
+        if let isaspec::IsaSpec::Some(ref isas) = isa_spec {
+            if isas.iter().any(|isa| isa.name() == "riscv64")
+                && !features.contains(&Feature::With("experimental_riscv64"))
+            {
+                return Ok(TestFile {
+                    commands,
+                    isa_spec,
+                    features,
+                    preamble_comments: Vec::new(),
+                    functions: Vec::new(),
+                });
+            }
+        }
+    }
+
     // Decide between using the calling convention passed in the options or using the
     // host's calling convention--if any tests are to be run on the host we should default to the
     // host's calling convention.
@@ -926,6 +945,49 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume an i128 immediate.
+    fn match_imm128(&mut self, err_msg: &str) -> ParseResult<i128> {
+        if let Some(Token::Integer(text)) = self.token() {
+            self.consume();
+            let negative = text.starts_with('-');
+            let positive = text.starts_with('+');
+            let text = if negative || positive {
+                // Strip sign prefix.
+                &text[1..]
+            } else {
+                text
+            };
+
+            // Parse the text value; the lexer gives us raw text that looks like an integer.
+            let value = if text.starts_with("0x") {
+                // Skip underscores.
+                let text = text.replace("_", "");
+                // Parse it as a i128 in hexadecimal form.
+                u128::from_str_radix(&text[2..], 16)
+                    .map_err(|_| self.error("unable to parse i128 as a hexadecimal immediate"))?
+            } else {
+                // Parse it as a i128 to check for overflow and other issues.
+                text.parse()
+                    .map_err(|_| self.error("expected i128 decimal immediate"))?
+            };
+
+            // Apply sign if necessary.
+            let signed = if negative {
+                let value = value.wrapping_neg() as i128;
+                if value > 0 {
+                    return Err(self.error("negative number too small"));
+                }
+                value
+            } else {
+                value as i128
+            };
+
+            Ok(signed)
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
     // Match and consume an optional offset32 immediate.
     //
     // Note that this will match an empty string as an empty offset, and that if an offset is
@@ -2042,7 +2104,7 @@ impl<'a> Parser<'a> {
     // Parse a basic block, add contents to `ctx`.
     //
     // extended-basic-block ::= * block-header { instruction }
-    // block-header           ::= Block(block) [block-params] ":"
+    // block-header           ::= Block(block) [block-params] ["cold"] ":"
     //
     fn parse_basic_block(&mut self, ctx: &mut Context) -> ParseResult<()> {
         // Collect comments for the next block.
@@ -2056,8 +2118,14 @@ impl<'a> Parser<'a> {
         }
 
         if !self.optional(Token::Colon) {
-            // block-header ::= Block(block) [ * block-params ] ":"
-            self.parse_block_params(ctx, block)?;
+            // block-header ::= Block(block) [ * block-params ] ["cold"] ":"
+            if self.token() != Some(Token::Identifier("cold")) {
+                self.parse_block_params(ctx, block)?;
+            }
+            // block-header ::= Block(block) [block-params] [ * "cold" ] ":"
+            if self.optional(Token::Identifier("cold")) {
+                ctx.function.layout.set_cold(block);
+            }
             self.match_token(Token::Colon, "expected ':' after block parameters")?;
         }
 
@@ -2700,6 +2768,7 @@ impl<'a> Parser<'a> {
             I16 => DataValue::from(self.match_imm16("expected an i16")?),
             I32 => DataValue::from(self.match_imm32("expected an i32")?),
             I64 => DataValue::from(Into::<i64>::into(self.match_imm64("expected an i64")?)),
+            I128 => DataValue::from(self.match_imm128("expected an i128")?),
             F32 => DataValue::from(self.match_ieee32("expected an f32")?),
             F64 => DataValue::from(self.match_ieee64("expected an f64")?),
             _ if ty.is_vector() => {
@@ -3451,6 +3520,25 @@ mod tests {
         assert_eq!(func.dfg.value_type(block4_args[0]), types::I32);
     }
 
+    #[test]
+    fn cold_blocks() {
+        let code = "function %blocks() system_v {
+                    block0:
+                    block1 cold:
+                    block2(v0: i32) cold:
+                    }";
+        let (func, _) = Parser::new(code).parse_function(None).unwrap();
+        let blocks = func.layout.blocks().collect::<Vec<_>>();
+        assert!(!func.layout.is_cold(blocks[0]));
+        assert!(func.layout.is_cold(blocks[1]));
+        assert!(func.layout.is_cold(blocks[2]));
+        assert_eq!(func.dfg.block_params(blocks[2]).len(), 1);
+
+        let text = func.display(None).to_string();
+        assert!(text.contains("block1 cold:"));
+        assert!(text.contains("block2(v0: i32) cold:"));
+    }
+
     #[test]
     fn duplicate_block() {
         let ParseError {
@@ -3983,6 +4071,12 @@ mod tests {
         assert_eq!(parse("16", I16).to_string(), "16");
         assert_eq!(parse("32", I32).to_string(), "32");
         assert_eq!(parse("64", I64).to_string(), "64");
+        assert_eq!(parse("128", I128).to_string(), "128");
+        assert_eq!(parse("-1", I128).to_string(), "-1");
+        assert_eq!(
+            parse("0x01234567_89abcdef_01234567_89abcdef", I128).to_string(),
+            "1512366075204170929049582354406559215"
+        );
         assert_eq!(parse("0x32.32", F32).to_string(), "0x1.919000p5");
         assert_eq!(parse("0x64.64", F64).to_string(), "0x1.9190000000000p6");
         assert_eq!(parse("true", B1).to_string(), "true");
@@ -3992,4 +4086,30 @@ mod tests {
             "0x00000003000000020000000100000000"
         );
     }
+
+    #[test]
+    fn table_addr_round_trip() {
+        let func = parse_functions(
+            "function %f(i64 vmctx, i32) -> i64 {
+                gv0 = vmctx
+                gv1 = load.i64 notrap aligned gv0
+                gv2 = load.i32 notrap aligned gv0+8
+                table0 = dynamic gv1, min 0, bound gv2, element_size 8, index_type i32
+
+            block0(v0: i64, v1: i32):
+                v2 = table_addr.i64 table0, v1, +0
+                v3 = table_addr.i64 table0, v1, +16
+                v4 = iadd v2, v3
+                return v4
+            }",
+        )
+        .unwrap()
+        .remove(0);
+
+        let text = func.display(None).to_string();
+        assert!(text.contains("v2 = table_addr.i64 table0, v1, +0"));
+        assert!(text.contains("v3 = table_addr.i64 table0, v1, +16"));
+        let reparsed = parse_functions(&text).unwrap().remove(0);
+        assert_eq!(reparsed.display(None).to_string(), text);
+    }
 }
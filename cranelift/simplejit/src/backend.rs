@@ -21,8 +21,9 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::CString;
 use std::io::Write;
+use std::mem;
 use std::ptr;
-use target_lexicon::PointerWidth;
+use target_lexicon::{Architecture, PointerWidth};
 #[cfg(windows)]
 use winapi;
 
@@ -30,11 +31,17 @@ const EXECUTABLE_DATA_ALIGNMENT: u64 = 0x10;
 const WRITABLE_DATA_ALIGNMENT: u64 = 0x8;
 const READONLY_DATA_ALIGNMENT: u64 = 0x1;
 
+/// The code of a PLT entry: `jmp *got_entry(%rip)`, followed by the 32-bit displacement of the
+/// GOT entry.
+const PLT_ENTRY_CODE: [u8; 2] = [0xff, 0x25];
+const PLT_ENTRY_SIZE: usize = 6;
+
 /// A builder for `SimpleJITModule`.
 pub struct SimpleJITBuilder {
     isa: Box<dyn TargetIsa>,
     symbols: HashMap<String, *const u8>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
+    hotswap_enabled: bool,
 }
 
 impl SimpleJITBuilder {
@@ -79,6 +86,7 @@ impl SimpleJITBuilder {
             isa,
             symbols,
             libcall_names,
+            hotswap_enabled: false,
         }
     }
 
@@ -117,6 +125,24 @@ impl SimpleJITBuilder {
         }
         self
     }
+
+    /// Enable or disable hotswapping, which allows a function freed with
+    /// `SimpleJITModule::free_function` to be defined again while other functions keep calling
+    /// it.
+    ///
+    /// With hotswapping, all the references to functions defined in the module go through a
+    /// PLT entry, which jumps to the current definition of the function through its GOT entry.
+    /// The GOT entry is updated when a new definition is finalized.
+    ///
+    /// Hotswapping is only supported on x86-64.
+    pub fn hotswap(&mut self, enabled: bool) -> &Self {
+        assert!(
+            !enabled || self.isa.triple().architecture == Architecture::X86_64,
+            "hotswapping is only supported on x86-64"
+        );
+        self.hotswap_enabled = enabled;
+        self
+    }
 }
 
 /// A `SimpleJITModule` implements `Module` and emits code and data into memory where it can be
@@ -133,6 +159,9 @@ pub struct SimpleJITModule {
     compiled_data_objects: SecondaryMap<DataId, Option<CompiledBlob>>,
     functions_to_finalize: Vec<FuncId>,
     data_objects_to_finalize: Vec<DataId>,
+    hotswap_enabled: bool,
+    function_got_entries: SecondaryMap<FuncId, Option<*mut *const u8>>,
+    function_plt_entries: SecondaryMap<FuncId, Option<*const u8>>,
 }
 
 /// A handle to allow freeing memory allocated by the `Module`.
@@ -157,6 +186,71 @@ impl SimpleJITModule {
         self.memory.writable.free_memory();
     }
 
+    /// Free the memory of a defined function, so that it can be defined again.
+    ///
+    /// The memory is reused for later definitions once all the functions it is shared with are
+    /// freed too. With hotswapping enabled (see `SimpleJITBuilder::hotswap`), the calls from
+    /// other functions go to the new definition of the function once it is finalized.
+    ///
+    /// # Safety
+    ///
+    /// The function must not be executing, and pointers to it must not be called afterwards.
+    /// This includes calls from other functions, unless hotswapping is enabled and the function
+    /// is defined again and finalized first.
+    pub unsafe fn free_function(&mut self, func_id: FuncId) {
+        let compiled = self.compiled_functions[func_id]
+            .take()
+            .expect("function must be defined before it can be freed");
+        self.functions_to_finalize.retain(|&id| id != func_id);
+        self.memory.code.free(compiled.ptr, compiled.size);
+    }
+
+    /// Free the memory of a defined data object, so that it can be defined again.
+    ///
+    /// # Safety
+    ///
+    /// The data object must not be accessed afterwards, either through pointers to it or by
+    /// the functions that refer to it.
+    pub unsafe fn free_data_object(&mut self, data_id: DataId) {
+        let compiled = self.compiled_data_objects[data_id]
+            .take()
+            .expect("data object must be defined before it can be freed");
+        self.data_objects_to_finalize.retain(|&id| id != data_id);
+        if self.declarations.get_data_decl(data_id).writable {
+            self.memory.writable.free(compiled.ptr, compiled.size);
+        } else {
+            self.memory.readonly.free(compiled.ptr, compiled.size);
+        }
+    }
+
+    /// Allocate the GOT and PLT entries of a function, for hotswapping.
+    fn allocate_plt_entry(&mut self, func_id: FuncId) {
+        let got_entry = self
+            .memory
+            .writable
+            .allocate(
+                mem::size_of::<*const u8>(),
+                mem::align_of::<*const u8>() as u64,
+            )
+            .expect("TODO: handle OOM etc.") as *mut *const u8;
+        let plt_entry = self
+            .memory
+            .code
+            .allocate(PLT_ENTRY_SIZE, EXECUTABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.");
+        unsafe {
+            ptr::write(got_entry, ptr::null());
+            ptr::copy_nonoverlapping(PLT_ENTRY_CODE.as_ptr(), plt_entry, PLT_ENTRY_CODE.len());
+            // TODO: Handle overflow.
+            let pcrel =
+                (got_entry as isize - (plt_entry as isize + PLT_ENTRY_SIZE as isize)) as i32;
+            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+            ptr::write_unaligned(plt_entry.add(PLT_ENTRY_CODE.len()) as *mut i32, pcrel);
+        }
+        self.function_got_entries[func_id] = Some(got_entry);
+        self.function_plt_entries[func_id] = Some(plt_entry);
+    }
+
     fn lookup_symbol(&self, name: &str) -> Option<*const u8> {
         self.symbols
             .get(name)
@@ -169,6 +263,9 @@ impl SimpleJITModule {
             ir::ExternalName::User { .. } => {
                 let (name, linkage) = if ModuleDeclarations::is_function(name) {
                     let func_id = FuncId::from_name(name);
+                    if let Some(plt_entry) = self.function_plt_entries[func_id] {
+                        return plt_entry;
+                    }
                     match &self.compiled_functions[func_id] {
                         Some(compiled) => return compiled.ptr,
                         None => {
@@ -256,10 +353,13 @@ impl SimpleJITModule {
         for func in std::mem::take(&mut self.functions_to_finalize) {
             let decl = self.declarations.get_function_decl(func);
             debug_assert!(decl.linkage.is_definable());
-            let func = self.compiled_functions[func]
+            let compiled = self.compiled_functions[func]
                 .as_ref()
                 .expect("function must be compiled before it can be finalized");
-            func.perform_relocations(|name| self.get_definition(name));
+            compiled.perform_relocations(|name| self.get_definition(name));
+            if let Some(got_entry) = self.function_got_entries[func] {
+                unsafe { ptr::write(got_entry, compiled.ptr as *const u8) };
+            }
         }
         for data in std::mem::take(&mut self.data_objects_to_finalize) {
             let decl = self.declarations.get_data_decl(data);
@@ -293,6 +393,9 @@ impl SimpleJITModule {
             compiled_data_objects: SecondaryMap::new(),
             functions_to_finalize: Vec::new(),
             data_objects_to_finalize: Vec::new(),
+            hotswap_enabled: builder.hotswap_enabled,
+            function_got_entries: SecondaryMap::new(),
+            function_plt_entries: SecondaryMap::new(),
         }
     }
 }
@@ -312,9 +415,15 @@ impl<'simple_jit_backend> Module for SimpleJITModule {
         linkage: Linkage,
         signature: &ir::Signature,
     ) -> ModuleResult<FuncId> {
-        let (id, _decl) = self
+        let (id, decl) = self
             .declarations
             .declare_function(name, linkage, signature)?;
+        if self.hotswap_enabled
+            && decl.linkage.is_definable()
+            && self.function_plt_entries[id].is_none()
+        {
+            self.allocate_plt_entry(id);
+        }
         Ok(id)
     }
 
@@ -383,7 +492,22 @@ impl<'simple_jit_backend> Module for SimpleJITModule {
         });
         self.functions_to_finalize.push(id);
 
-        Ok(ModuleCompiledFunction { size: code_size })
+        let user_stack_maps = ctx
+            .mach_compile_result
+            .as_ref()
+            .map_or_else(Vec::new, |result| {
+                result
+                    .buffer
+                    .user_stack_maps()
+                    .iter()
+                    .map(|map| (map.offset, map.stack_map.clone()))
+                    .collect()
+            });
+
+        Ok(ModuleCompiledFunction {
+            size: code_size,
+            user_stack_maps,
+        })
     }
 
     fn define_function_bytes(
@@ -426,7 +550,10 @@ impl<'simple_jit_backend> Module for SimpleJITModule {
         });
         self.functions_to_finalize.push(id);
 
-        Ok(ModuleCompiledFunction { size: total_size })
+        Ok(ModuleCompiledFunction {
+            size: total_size,
+            user_stack_maps: Vec::new(),
+        })
     }
 
     fn define_data(&mut self, id: DataId, data: &DataContext) -> ModuleResult<()> {
@@ -11,7 +11,7 @@ pub(crate) struct CompiledBlob {
 
 impl CompiledBlob {
     pub(crate) fn perform_relocations(&self, get_definition: impl Fn(&ExternalName) -> *const u8) {
-        use std::ptr::write_unaligned;
+        use std::ptr::{read_unaligned, write_unaligned};
 
         for &RelocRecord {
             reloc,
@@ -48,6 +48,22 @@ impl CompiledBlob {
                     };
                 }
                 Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
+                Reloc::RiscvCallPlt => {
+                    // TODO: Handle overflow.
+                    // Split the offset between the `auipc` and the `jalr`; the high part is
+                    // rounded since the low part is sign-extended.
+                    let pcrel = ((what as isize) - (at as isize)) as u32;
+                    let hi20 = pcrel.wrapping_add(0x800) & 0xffff_f000;
+                    let lo12 = pcrel << 20;
+                    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                    unsafe {
+                        let auipc = read_unaligned(at as *const u32);
+                        write_unaligned(at as *mut u32, (auipc & 0xfff) | hi20);
+                        let jalr_at = at.offset(4);
+                        let jalr = read_unaligned(jalr_at as *const u32);
+                        write_unaligned(jalr_at as *mut u32, (jalr & 0xf_ffff) | lo12);
+                    };
+                }
                 _ => unimplemented!(),
             }
         }
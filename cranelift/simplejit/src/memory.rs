@@ -126,10 +126,18 @@ impl Drop for PtrLen {
 /// accessible memory. Memory will be leaked by default to have
 /// function pointers remain valid for the remainder of the
 /// program's life.
+///
+/// Individual allocations can be freed; the pages they are in are
+/// reused once all the allocations in them are freed.
 pub(crate) struct Memory {
     allocations: Vec<PtrLen>,
+    /// The number of allocations that are not freed yet in each of `allocations`.
+    live_allocations: Vec<usize>,
+    /// Pages whose allocations were all freed, ready to be reused.
+    free_allocations: Vec<PtrLen>,
     executable: usize,
     current: PtrLen,
+    current_live_allocations: usize,
     position: usize,
 }
 
@@ -137,18 +145,46 @@ impl Memory {
     pub(crate) fn new() -> Self {
         Self {
             allocations: Vec::new(),
+            live_allocations: Vec::new(),
+            free_allocations: Vec::new(),
             executable: 0,
             current: PtrLen::new(),
+            current_live_allocations: 0,
             position: 0,
         }
     }
 
     fn finish_current(&mut self) {
-        self.allocations
-            .push(mem::replace(&mut self.current, PtrLen::new()));
+        let current = mem::replace(&mut self.current, PtrLen::new());
+        let live = mem::replace(&mut self.current_live_allocations, 0);
+        if live != 0 {
+            self.allocations.push(current);
+            self.live_allocations.push(live);
+        } else if current.len != 0 {
+            self.free_allocations.push(current);
+        }
         self.position = 0;
     }
 
+    /// Take pages for at least `size` bytes, reusing freed ones if possible.
+    fn take_pages(&mut self, size: usize) -> Result<PtrLen, String> {
+        match self
+            .free_allocations
+            .iter()
+            .position(|pages| pages.len >= size)
+        {
+            Some(index) => {
+                let pages = self.free_allocations.swap_remove(index);
+                unsafe {
+                    region::protect(pages.ptr, pages.len, region::Protection::READ_WRITE)
+                        .expect("unable to make memory writable");
+                }
+                Ok(pages)
+            }
+            None => PtrLen::with_size(size),
+        }
+    }
+
     /// TODO: Use a proper error type.
     pub(crate) fn allocate(&mut self, size: usize, align: u64) -> Result<*mut u8, String> {
         let align = usize::try_from(align).expect("alignment too big");
@@ -161,17 +197,53 @@ impl Memory {
             // TODO: Ensure overflow is not possible.
             let ptr = unsafe { self.current.ptr.add(self.position) };
             self.position += size;
+            if size != 0 {
+                self.current_live_allocations += 1;
+            }
             return Ok(ptr);
         }
 
         self.finish_current();
 
         // TODO: Allocate more at a time.
-        self.current = PtrLen::with_size(size)?;
+        self.current = self.take_pages(size)?;
         self.position = size;
+        self.current_live_allocations = 1;
         Ok(self.current.ptr)
     }
 
+    /// Free an allocation of `size` bytes returned by `allocate`.
+    ///
+    /// The memory is only reused once all the other allocations in the same
+    /// pages are freed too, so that the protection of the pages can be changed.
+    pub(crate) unsafe fn free(&mut self, ptr: *mut u8, size: usize) {
+        if size == 0 {
+            return;
+        }
+        let contains = |pages: &PtrLen| ptr >= pages.ptr && ptr < pages.ptr.add(pages.len);
+
+        if contains(&self.current) && self.current_live_allocations != 0 {
+            self.current_live_allocations -= 1;
+            if self.current_live_allocations == 0 {
+                // The pages aren't protected yet, so they can be reused right away.
+                self.position = 0;
+            }
+            return;
+        }
+
+        let index = self
+            .allocations
+            .iter()
+            .position(contains)
+            .expect("freeing memory that wasn't allocated");
+        self.live_allocations[index] -= 1;
+        if self.live_allocations[index] == 0 {
+            self.live_allocations.swap_remove(index);
+            let pages = self.allocations.swap_remove(index);
+            self.free_allocations.push(pages);
+        }
+    }
+
     /// Set all memory allocated in this `Memory` up to now as readable and executable.
     pub(crate) fn set_readable_and_executable(&mut self) {
         self.finish_current();
@@ -234,6 +306,8 @@ impl Memory {
     /// Likely to invalidate existing function pointers, causing unsafety.
     pub(crate) unsafe fn free_memory(&mut self) {
         self.allocations.clear();
+        self.live_allocations.clear();
+        self.free_allocations.clear();
     }
 }
 
@@ -257,4 +331,28 @@ mod tests {
         assert_eq!(round_up_to_page_size(4096, 4096), 4096);
         assert_eq!(round_up_to_page_size(4097, 4096), 8192);
     }
+
+    #[test]
+    fn test_reuse_freed_memory() {
+        let mut memory = Memory::new();
+        let a = memory.allocate(16, 16).unwrap();
+        let b = memory.allocate(16, 16).unwrap();
+        unsafe {
+            memory.free(a, 16);
+            memory.free(b, 16);
+        }
+        // The pages are all free, so they are reused from the start.
+        assert_eq!(memory.allocate(16, 16).unwrap(), a);
+
+        // Protected pages are reused once they are all free too.
+        memory.set_readable_and_executable();
+        let c = memory.allocate(16, 16).unwrap();
+        assert_ne!(c, a);
+        unsafe { memory.free(a, 16) };
+        memory.set_readable_and_executable();
+        let d = memory.allocate(16, 16).unwrap();
+        assert_eq!(d, a);
+        // The reused pages are writable again.
+        unsafe { ptr::write_bytes(d, 0, 16) };
+    }
 }
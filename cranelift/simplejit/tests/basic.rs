@@ -188,3 +188,236 @@ fn libcall_function() {
 
     module.finalize_definitions();
 }
+
+/// Build `fn abs_diff(a: i32, b: i32) -> i32`, which has several blocks and returns.
+fn build_abs_diff(func: &mut Function) {
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut bcx: FunctionBuilder = FunctionBuilder::new(func, &mut func_ctx);
+    let block = bcx.create_block();
+    let negative = bcx.create_block();
+    let positive = bcx.create_block();
+    bcx.append_block_params_for_function_params(block);
+    bcx.switch_to_block(block);
+    let a = bcx.block_params(block)[0];
+    let b = bcx.block_params(block)[1];
+    let diff = bcx.ins().isub(a, b);
+    let is_negative = bcx
+        .ins()
+        .icmp_imm(condcodes::IntCC::SignedLessThan, diff, 0);
+    bcx.ins().brnz(is_negative, negative, &[]);
+    bcx.ins().jump(positive, &[]);
+
+    bcx.switch_to_block(positive);
+    bcx.ins().return_(&[diff]);
+
+    bcx.switch_to_block(negative);
+    let neg = bcx.ins().ineg(diff);
+    bcx.ins().return_(&[neg]);
+
+    bcx.seal_all_blocks();
+    bcx.finalize();
+}
+
+/// Build `fn caller(x: i32) -> i32 { abs_diff(x, 10) * abs_diff(x, 20) }`.
+fn build_abs_diff_caller(func: &mut Function, abs_diff: FuncRef) {
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut bcx: FunctionBuilder = FunctionBuilder::new(func, &mut func_ctx);
+    let block = bcx.create_block();
+    bcx.append_block_params_for_function_params(block);
+    bcx.switch_to_block(block);
+    let x = bcx.block_params(block)[0];
+    let ten = bcx.ins().iconst(types::I32, 10);
+    let twenty = bcx.ins().iconst(types::I32, 20);
+    let call = bcx.ins().call(abs_diff, &[x, ten]);
+    let a = bcx.inst_results(call)[0];
+    let call = bcx.ins().call(abs_diff, &[x, twenty]);
+    let b = bcx.inst_results(call)[0];
+    let product = bcx.ins().imul(a, b);
+    bcx.ins().return_(&[product]);
+
+    bcx.seal_all_blocks();
+    bcx.finalize();
+}
+
+#[test]
+fn define_function_with_inliner() {
+    let mut module: SimpleJITModule =
+        SimpleJITModule::new(SimpleJITBuilder::new(default_libcall_names()));
+    let mut inliner = Inliner::new();
+    let mut trap_sink = NullTrapSink {};
+
+    let mut abs_diff_sig = module.make_signature();
+    abs_diff_sig.params.push(AbiParam::new(types::I32));
+    abs_diff_sig.params.push(AbiParam::new(types::I32));
+    abs_diff_sig.returns.push(AbiParam::new(types::I32));
+    let abs_diff_id = module
+        .declare_function("abs_diff", Linkage::Local, &abs_diff_sig)
+        .unwrap();
+    let mut ctx = module.make_context();
+    ctx.func.signature = abs_diff_sig;
+    build_abs_diff(&mut ctx.func);
+    module
+        .define_function_with_inliner(abs_diff_id, &mut ctx, &mut inliner, &mut trap_sink)
+        .unwrap();
+
+    let mut caller_sig = module.make_signature();
+    caller_sig.params.push(AbiParam::new(types::I32));
+    caller_sig.returns.push(AbiParam::new(types::I32));
+    let caller_id = module
+        .declare_function("caller", Linkage::Local, &caller_sig)
+        .unwrap();
+    module.clear_context(&mut ctx);
+    ctx.func.signature = caller_sig;
+    let abs_diff = module.declare_func_in_func(abs_diff_id, &mut ctx.func);
+    build_abs_diff_caller(&mut ctx.func, abs_diff);
+    module
+        .define_function_with_inliner(caller_id, &mut ctx, &mut inliner, &mut trap_sink)
+        .unwrap();
+
+    module.finalize_definitions();
+    let caller = module.get_finalized_function(caller_id);
+    let caller = unsafe { std::mem::transmute::<_, extern "C" fn(i32) -> i32>(caller) };
+    assert_eq!(caller(15), 25);
+    assert_eq!(caller(0), 200);
+    assert_eq!(caller(30), 200);
+}
+
+#[test]
+fn inline_functions() {
+    use cranelift_codegen::settings;
+
+    let mut sig = Signature::new(CallConv::SystemV);
+    sig.params.push(AbiParam::new(types::I32));
+    sig.params.push(AbiParam::new(types::I32));
+    sig.returns.push(AbiParam::new(types::I32));
+    let abs_diff_id = FuncId::from_u32(0);
+    let mut abs_diff = Function::with_name_signature(abs_diff_id.into(), sig.clone());
+    build_abs_diff(&mut abs_diff);
+
+    sig.params.pop();
+    let caller_id = FuncId::from_u32(1);
+    let mut caller = Function::with_name_signature(caller_id.into(), sig);
+    let abs_diff_sig = caller.import_signature(abs_diff.signature.clone());
+    let abs_diff_ref = caller.import_function(ExtFuncData {
+        name: abs_diff_id.into(),
+        signature: abs_diff_sig,
+        colocated: true,
+    });
+    build_abs_diff_caller(&mut caller, abs_diff_ref);
+
+    // The caller comes first, but its callee is still inlined into it.
+    let mut functions = vec![(caller_id, caller), (abs_diff_id, abs_diff)];
+    assert_eq!(Inliner::new().inline_functions(&mut functions), 2);
+
+    let (_, caller) = &functions[0];
+    let flags = settings::Flags::new(settings::builder());
+    if let Err(err) = cranelift_codegen::verify_function(caller, &flags) {
+        panic!("{}", err);
+    }
+    for block in caller.layout.blocks() {
+        for inst in caller.layout.block_insts(block) {
+            assert_ne!(caller.dfg[inst].opcode(), Opcode::Call);
+        }
+    }
+}
+
+/// Define `func_id` as a function that returns `value`.
+fn define_constant_function(module: &mut SimpleJITModule, func_id: FuncId, value: i64) {
+    let mut ctx = module.make_context();
+    ctx.func.signature = module
+        .declarations()
+        .get_function_decl(func_id)
+        .signature
+        .clone();
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.switch_to_block(block);
+        let value = bcx.ins().iconst(types::I32, value);
+        bcx.ins().return_(&[value]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+
+    let mut trap_sink = NullTrapSink {};
+    module
+        .define_function(func_id, &mut ctx, &mut trap_sink)
+        .unwrap();
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn free_and_redefine_function() {
+    let mut builder = SimpleJITBuilder::new(default_libcall_names());
+    builder.hotswap(true);
+    let mut module = SimpleJITModule::new(builder);
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let callee_id = module
+        .declare_function("callee", Linkage::Local, &sig)
+        .unwrap();
+    let caller_id = module
+        .declare_function("caller", Linkage::Local, &sig)
+        .unwrap();
+
+    define_constant_function(&mut module, callee_id, 1);
+
+    let mut ctx = module.make_context();
+    ctx.func.signature = sig;
+    let callee = module.declare_func_in_func(callee_id, &mut ctx.func);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.switch_to_block(block);
+        let call = bcx.ins().call(callee, &[]);
+        let result = bcx.inst_results(call)[0];
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    let mut trap_sink = NullTrapSink {};
+    module
+        .define_function(caller_id, &mut ctx, &mut trap_sink)
+        .unwrap();
+
+    module.finalize_definitions();
+    let caller = module.get_finalized_function(caller_id);
+    let caller = unsafe { std::mem::transmute::<_, extern "C" fn() -> i32>(caller) };
+    assert_eq!(caller(), 1);
+
+    // The caller isn't compiled again, but calls the new definition of the callee.
+    unsafe { module.free_function(callee_id) };
+    define_constant_function(&mut module, callee_id, 2);
+    module.finalize_definitions();
+    assert_eq!(caller(), 2);
+}
+
+#[test]
+fn free_and_redefine_data_object() {
+    let mut module: SimpleJITModule =
+        SimpleJITModule::new(SimpleJITBuilder::new(default_libcall_names()));
+    let data_id = module
+        .declare_data("data", Linkage::Local, false, false)
+        .unwrap();
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(vec![1, 2, 3, 4].into_boxed_slice());
+    module.define_data(data_id, &data_ctx).unwrap();
+    module.finalize_definitions();
+    let (ptr, size) = module.get_finalized_data(data_id);
+    assert_eq!(
+        unsafe { std::slice::from_raw_parts(ptr, size) },
+        &[1, 2, 3, 4]
+    );
+
+    unsafe { module.free_data_object(data_id) };
+    data_ctx.clear();
+    data_ctx.define(vec![5, 6].into_boxed_slice());
+    module.define_data(data_id, &data_ctx).unwrap();
+    module.finalize_definitions();
+    let (ptr, size) = module.get_finalized_data(data_id);
+    assert_eq!(unsafe { std::slice::from_raw_parts(ptr, size) }, &[5, 6]);
+}
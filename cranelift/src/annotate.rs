@@ -0,0 +1,260 @@
+//! Annotated disassembly, interleaving machine code with the CLIF instructions it was generated
+//! from.
+//!
+//! Before compilation, every instruction gets its own number as source location. The source
+//! locations of the emitted code then tell which instruction each range of machine code came
+//! from, for MachInst backends through the `MachSrcLoc` ranges of the `MachBuffer`, and for the
+//! legacy backends through the encoded instructions of the compiled function. Code without a
+//! source location was inserted by the compiler, mostly by the register allocator.
+
+use crate::disasm::disassemble;
+use anyhow::Result;
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::ir::{Block, Function, Inst, Opcode, SourceLoc};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{Context, ValueLabelsRanges};
+use cranelift_entity::{EntityRef, SecondaryMap};
+use std::collections::BTreeMap;
+
+/// Where a range of machine code came from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// Code generated for an instruction of the original function.
+    Inst(Inst),
+    /// The code at the start of the function that sets up its frame.
+    Prologue,
+    /// A spill or fill inserted by the register allocator of a legacy backend.
+    Spill,
+    /// Any other code inserted by the compiler, such as the spills, reloads and moves inserted by
+    /// the register allocator of a MachInst backend.
+    Compiler,
+}
+
+struct CodeRange {
+    start: CodeOffset,
+    end: CodeOffset,
+    origin: Origin,
+}
+
+/// Traces the machine code of a function back to its CLIF instructions.
+pub struct Annotator {
+    /// The function as it was before compilation.
+    original: Function,
+    ranges: Vec<CodeRange>,
+}
+
+impl Annotator {
+    /// Remember `func` as it is, then use the number of each of its instructions as source
+    /// location.
+    pub fn new(func: &mut Function) -> Self {
+        let original = func.clone();
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                func.srclocs[inst] = SourceLoc::new(inst.as_u32());
+            }
+        }
+        Self {
+            original,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// Record where the code compiled in `context` came from, then give the compiled instructions
+    /// their original source locations back.
+    pub fn collect(&mut self, context: &mut Context, isa: &dyn TargetIsa) {
+        self.ranges.clear();
+        if let Some(result) = &context.mach_compile_result {
+            for range in result.buffer.get_srclocs_sorted() {
+                let origin = self.origin(range.loc);
+                self.push_range(range.start, range.end, origin);
+            }
+        } else {
+            let func = &context.func;
+            let encinfo = isa.encoding_info();
+            for block in func.layout.blocks() {
+                for (offset, inst, size) in func.inst_offsets(block, &encinfo) {
+                    if size == 0 {
+                        continue;
+                    }
+                    let origin = match func.dfg[inst].opcode() {
+                        Opcode::Spill | Opcode::Fill | Opcode::Regspill | Opcode::Regfill => {
+                            Origin::Spill
+                        }
+                        _ => self.origin(func.srclocs[inst]),
+                    };
+                    self.push_range(offset, offset + size, origin);
+                }
+            }
+        }
+
+        let func = &mut context.func;
+        for block in func.layout.blocks() {
+            for inst in func.layout.block_insts(block) {
+                func.srclocs[inst] = match self.original_inst(func.srclocs[inst]) {
+                    Some(orig) => self.original.srclocs[orig],
+                    None => SourceLoc::default(),
+                };
+            }
+        }
+    }
+
+    /// The instruction of the original function tagged with `srcloc`, if any.
+    fn original_inst(&self, srcloc: SourceLoc) -> Option<Inst> {
+        if srcloc.is_default() {
+            return None;
+        }
+        let inst = Inst::new(srcloc.bits() as usize);
+        if inst.index() < self.original.dfg.num_insts()
+            && self.original.layout.inst_block(inst).is_some()
+        {
+            Some(inst)
+        } else {
+            None
+        }
+    }
+
+    /// The origin of the next range of code, with source location `srcloc`.
+    fn origin(&self, srcloc: SourceLoc) -> Origin {
+        match self.original_inst(srcloc) {
+            Some(inst) => Origin::Inst(inst),
+            // Compiler-inserted code before any instruction is the prologue.
+            None if self
+                .ranges
+                .last()
+                .map_or(true, |range| range.origin == Origin::Prologue) =>
+            {
+                Origin::Prologue
+            }
+            None => Origin::Compiler,
+        }
+    }
+
+    fn push_range(&mut self, start: CodeOffset, end: CodeOffset, origin: Origin) {
+        if let Some(last) = self.ranges.last_mut() {
+            if last.end == start && last.origin == origin {
+                last.end = end;
+                return;
+            }
+        }
+        self.ranges.push(CodeRange { start, end, origin });
+    }
+
+    /// Print the disassembly of the first `code_size` bytes of `mem`, interleaved with the
+    /// instructions they came from, followed by a summary per block.
+    ///
+    /// `describe_srcloc` can add a description of the original source location of an
+    /// instruction, and the locations of `value_ranges` are printed where they start.
+    pub fn print(
+        &self,
+        isa: &dyn TargetIsa,
+        mem: &[u8],
+        code_size: u32,
+        describe_srcloc: &dyn Fn(SourceLoc) -> Option<String>,
+        value_ranges: Option<&ValueLabelsRanges>,
+    ) -> Result<()> {
+        let insns = disassemble(isa, &mem[..code_size as usize])?;
+
+        let regs = isa.register_info();
+        let mut value_starts = BTreeMap::<CodeOffset, Vec<String>>::new();
+        for (label, ranges) in value_ranges.into_iter().flatten() {
+            for range in ranges {
+                value_starts.entry(range.start).or_default().push(format!(
+                    "{} in {}",
+                    label,
+                    range.loc.display(&regs)
+                ));
+            }
+        }
+        let mut value_starts = value_starts.into_iter().peekable();
+
+        // The number of instructions and spills of each block.
+        let mut summary = SecondaryMap::<Block, (usize, usize)>::new();
+        let mut block = self.original.layout.entry_block();
+        if let Some(block) = block {
+            println!("\n{}:", block);
+        }
+
+        let mut ranges = self.ranges.iter().peekable();
+        let mut last_origin = None;
+        for insn in &insns {
+            while ranges
+                .peek()
+                .map_or(false, |range| range.end <= insn.offset)
+            {
+                ranges.next();
+            }
+            let origin = match ranges.peek() {
+                Some(range) if range.start <= insn.offset => range.origin,
+                _ => Origin::Compiler,
+            };
+
+            if last_origin != Some(origin) {
+                match origin {
+                    Origin::Inst(inst) => {
+                        let inst_block = self.original.layout.inst_block(inst);
+                        if inst_block != block {
+                            block = inst_block;
+                            println!("\n{}:", block.unwrap());
+                        }
+                        let mut line =
+                            format!("    ; {}", self.original.dfg.display_inst(inst, isa));
+                        let srcloc = self.original.srclocs[inst];
+                        if !srcloc.is_default() {
+                            line += &format!("  ; {}", srcloc);
+                            if let Some(description) = describe_srcloc(srcloc) {
+                                line += &format!(" {}", description);
+                            }
+                        }
+                        println!("{}", line);
+                    }
+                    Origin::Prologue => println!("    ; <prologue>"),
+                    Origin::Spill => println!("    ; <spill or fill>"),
+                    Origin::Compiler => println!("    ; <inserted by the compiler>"),
+                }
+                last_origin = Some(origin);
+            }
+
+            while value_starts
+                .peek()
+                .map_or(false, |&(start, _)| start <= insn.offset)
+            {
+                for label in value_starts.next().unwrap().1 {
+                    println!("    ; {}", label);
+                }
+            }
+
+            println!("    {:4x}:  {:23}  {}", insn.offset, insn.bytes, insn.text);
+
+            if let Some(block) = block {
+                let counts = &mut summary[block];
+                counts.0 += 1;
+                let is_spill = match origin {
+                    Origin::Spill => true,
+                    Origin::Compiler => accesses_stack(&insn.text),
+                    _ => false,
+                };
+                if is_spill {
+                    counts.1 += 1;
+                }
+            }
+        }
+
+        println!("\nBlock summary:");
+        for block in self.original.layout.blocks() {
+            let (insts, spills) = summary[block];
+            if insts > 0 {
+                println!(
+                    "    {}: {} instructions, {} spills/reloads",
+                    block, insts, spills
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Does the disassembled instruction `text` access memory relative to the stack pointer, as
+/// the spills and reloads of the register allocator do?
+fn accesses_stack(text: &str) -> bool {
+    text.contains("[sp") || text.contains("[rsp") || text.contains("[esp")
+}
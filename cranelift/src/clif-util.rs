@@ -16,6 +16,7 @@ use cranelift_codegen::dbg::LOG_FILENAME_PREFIX;
 use std::{option::Option, path::PathBuf};
 use structopt::StructOpt;
 
+mod annotate;
 mod bugpoint;
 mod cat;
 mod compile;
@@ -1,7 +1,8 @@
 //! CLI tool to read Cranelift IR files and compile them into native code.
 
+use crate::annotate::Annotator;
 use crate::disasm::{print_all, PrintRelocs, PrintStackMaps, PrintTraps};
-use crate::utils::{parse_sets_and_triple, read_to_string};
+use crate::utils::{compile_stats_json, parse_sets_and_triple, read_to_string};
 use anyhow::{Context as _, Result};
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::settings::FlagsOrIsa;
@@ -27,6 +28,15 @@ pub struct Options {
     #[structopt(short("D"), long("disasm"))]
     disasm: bool,
 
+    /// Print machine code disassembly annotated with the instructions it was compiled from, and
+    /// a summary of the instruction and spill counts of each block
+    #[structopt(long("annotate"))]
+    annotate: bool,
+
+    /// Print the compilation statistics of each function and their total, in the given format
+    #[structopt(long("compile-stats"), possible_values(&["json"]))]
+    compile_stats: Option<String>,
+
     /// Configure Cranelift settings
     #[structopt(long("set"))]
     settings: Vec<String>,
@@ -67,6 +77,7 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
         anyhow::bail!("compilation requires a target isa");
     };
 
+    let mut compile_stats = Vec::new();
     for (func, _) in test_file.functions {
         let mut relocs = PrintRelocs::new(options.print);
         let mut traps = PrintTraps::new(options.print);
@@ -76,6 +87,11 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
             let mut context = Context::new();
             context.func = func;
             let mut mem = vec![];
+            let mut annotator = if options.annotate {
+                Some(Annotator::new(&mut context.func))
+            } else {
+                None
+            };
 
             // Compile and encode the result to machine code.
             let code_info = context
@@ -84,6 +100,15 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
                     anyhow::anyhow!("{}", pretty_error(&context.func, Some(isa), err))
                 })?;
 
+            if let Some(annotator) = &mut annotator {
+                annotator.collect(&mut context, isa);
+            }
+
+            if options.compile_stats.is_some() {
+                let stats = context.stats.take().expect("compiled function has stats");
+                compile_stats.push((context.func.name.to_string(), stats));
+            }
+
             if options.print {
                 println!("{}", context.func.display(isa));
             }
@@ -99,9 +124,17 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
                     &stack_maps,
                 )?;
             }
+
+            if let Some(annotator) = &annotator {
+                annotator.print(isa, &mem, code_info.code_size, &|_| None, None)?;
+            }
         }
     }
 
+    if options.compile_stats.is_some() {
+        println!("{}", compile_stats_json(&compile_stats));
+    }
+
     if options.report_times {
         print!("{}", timing::take_current());
     }
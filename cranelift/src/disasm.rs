@@ -104,6 +104,16 @@ impl binemit::StackMapSink for PrintStackMaps {
     }
 }
 
+/// A disassembled machine instruction.
+pub struct DisasInsn {
+    /// The offset of the instruction from the start of the function.
+    pub offset: binemit::CodeOffset,
+    /// The bytes of the instruction, in hex.
+    pub bytes: String,
+    /// The mnemonic and operands of the instruction.
+    pub text: String,
+}
+
 cfg_if! {
     if #[cfg(feature = "disas")] {
         use capstone::prelude::*;
@@ -159,6 +169,35 @@ cfg_if! {
             Ok(cs)
         }
 
+        pub fn disassemble(isa: &dyn TargetIsa, mem: &[u8]) -> Result<Vec<DisasInsn>> {
+            let cs = get_disassembler(isa)?;
+            let insns = cs.disasm_all(&mem, 0x0).map_err(map_caperr)?;
+            Ok(insns
+                .iter()
+                .map(|i| {
+                    let mut bytes = String::new();
+                    for b in i.bytes() {
+                        if !bytes.is_empty() {
+                            bytes.push(' ');
+                        }
+                        write!(&mut bytes, "{:02x}", b).unwrap();
+                    }
+                    let text = match (i.mnemonic(), i.op_str()) {
+                        (Some(mnemonic), Some(ops)) if !ops.is_empty() => {
+                            format!("{}\t{}", mnemonic, ops)
+                        }
+                        (Some(mnemonic), _) => mnemonic.to_string(),
+                        (None, _) => String::new(),
+                    };
+                    DisasInsn {
+                        offset: i.address() as binemit::CodeOffset,
+                        bytes,
+                        text,
+                    }
+                })
+                .collect())
+        }
+
         pub fn print_disassembly(isa: &dyn TargetIsa, mem: &[u8]) -> Result<()> {
             let cs = get_disassembler(isa)?;
 
@@ -202,6 +241,10 @@ cfg_if! {
             anyhow::format_err!("{}", err)
         }
     } else {
+        pub fn disassemble(_: &dyn TargetIsa, _: &[u8]) -> Result<Vec<DisasInsn>> {
+            anyhow::bail!("No disassembly available.")
+        }
+
         pub fn print_disassembly(_: &dyn TargetIsa, _: &[u8]) -> Result<()> {
             println!("\nNo disassembly available.");
             Ok(())
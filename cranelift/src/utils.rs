@@ -4,6 +4,7 @@ use anyhow::Context;
 use cranelift_codegen::isa;
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::settings::{self, FlagsOrIsa};
+use cranelift_codegen::stats::CompileStats;
 use cranelift_reader::{parse_options, Location, ParseError, ParseOptionError};
 use std::fs::File;
 use std::io::{self, Read};
@@ -109,6 +110,25 @@ pub fn parse_sets_and_triple(
     }
 }
 
+/// Render the compilation statistics of each named function, and their total, as a JSON object.
+pub fn compile_stats_json(functions: &[(String, CompileStats)]) -> String {
+    let mut total = CompileStats::default();
+    let mut entries = Vec::new();
+    for (name, stats) in functions {
+        total.add(stats);
+        entries.push(format!(
+            "{{\"name\":{:?},\"stats\":{}}}",
+            name,
+            stats.to_json()
+        ));
+    }
+    format!(
+        "{{\"functions\":[{}],\"total\":{}}}",
+        entries.join(","),
+        total.to_json()
+    )
+}
+
 /// Iterate over all of the files passed as arguments, recursively iterating through directories.
 pub fn iterate_files<'a>(files: &'a [PathBuf]) -> impl Iterator<Item = PathBuf> + 'a {
     files
@@ -7,15 +7,17 @@
     allow(clippy::too_many_arguments, clippy::cognitive_complexity)
 )]
 
+use crate::annotate::Annotator;
 use crate::disasm::{print_all, PrintRelocs, PrintStackMaps, PrintTraps};
-use crate::utils::parse_sets_and_triple;
+use crate::utils::{compile_stats_json, parse_sets_and_triple};
 use anyhow::{Context as _, Result};
-use cranelift_codegen::ir::DisplayFunctionAnnotations;
+use cranelift_codegen::ir::{DisplayFunctionAnnotations, SourceLoc};
 use cranelift_codegen::print_errors::{pretty_error, pretty_verifier_error};
 use cranelift_codegen::settings::FlagsOrIsa;
 use cranelift_codegen::timing;
 use cranelift_codegen::Context;
 use cranelift_entity::EntityRef;
+use cranelift_wasm::wasmparser::BinaryReader;
 use cranelift_wasm::{translate_module, DummyEnvironment, FuncIndex, ReturnMode};
 use std::io::Read;
 use std::path::Path;
@@ -79,6 +81,15 @@ pub struct Options {
     #[structopt(short("D"), long("disasm"))]
     disasm: bool,
 
+    /// Print machine code disassembly annotated with the instructions it was compiled from, and
+    /// a summary of the instruction and spill counts of each block
+    #[structopt(long("annotate"))]
+    annotate: bool,
+
+    /// Print the compilation statistics of each function and their total, in the given format
+    #[structopt(long("compile-stats"), possible_values(&["json"]))]
+    compile_stats: Option<String>,
+
     /// Configure Cranelift settings
     #[structopt(long("set"))]
     settings: Vec<String>,
@@ -250,8 +261,16 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
         vprintln!(options.verbose, "");
     }
 
+    // Source locations of translated wasm code are offsets in the module.
+    let describe_srcloc = |srcloc: SourceLoc| {
+        let offset = srcloc.bits() as usize;
+        let mut reader = BinaryReader::new_with_offset(module_binary.get(offset..)?, offset);
+        reader.read_operator().ok().map(|op| format!("{:?}", op))
+    };
+
     let num_func_imports = dummy_environ.get_num_func_imports();
     let mut total_module_code_size = 0;
+    let mut compile_stats = Vec::new();
     let mut context = Context::new();
     for (def_index, func) in dummy_environ.info.function_bodies.iter() {
         context.func = func.clone();
@@ -262,6 +281,7 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
         let mut relocs = PrintRelocs::new(options.print);
         let mut traps = PrintTraps::new(options.print);
         let mut stack_maps = PrintStackMaps::new(options.print);
+        let mut annotator = None;
         if options.check_translation {
             if let Err(errors) = context.verify(fisa) {
                 anyhow::bail!(
@@ -270,6 +290,9 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
                 );
             }
         } else {
+            if options.annotate {
+                annotator = Some(Annotator::new(&mut context.func));
+            }
             let code_info = context
                 .compile_and_emit(isa, &mut mem, &mut relocs, &mut traps, &mut stack_maps)
                 .map_err(|err| anyhow::anyhow!("{}", pretty_error(&context.func, fisa.isa, err)))?;
@@ -287,7 +310,16 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
                 );
             }
 
-            if options.disasm {
+            if let Some(annotator) = &mut annotator {
+                annotator.collect(&mut context, isa);
+            }
+
+            if options.compile_stats.is_some() {
+                let stats = context.stats.take().expect("compiled function has stats");
+                compile_stats.push((context.func.name.to_string(), stats));
+            }
+
+            if options.disasm || options.annotate {
                 saved_sizes = Some((
                     code_info.code_size,
                     code_info.jumptables_size + code_info.rodata_size,
@@ -327,15 +359,36 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
         }
 
         if let Some((code_size, rodata_size)) = saved_sizes {
-            print_all(
-                isa,
-                &mem,
-                code_size,
-                rodata_size,
-                &relocs,
-                &traps,
-                &stack_maps,
-            )?;
+            if options.disasm {
+                print_all(
+                    isa,
+                    &mem,
+                    code_size,
+                    rodata_size,
+                    &relocs,
+                    &traps,
+                    &stack_maps,
+                )?;
+            }
+
+            if let Some(annotator) = &annotator {
+                let value_ranges = if options.value_ranges {
+                    Some(
+                        context
+                            .build_value_labels_ranges(isa)
+                            .expect("value location ranges"),
+                    )
+                } else {
+                    None
+                };
+                annotator.print(
+                    isa,
+                    &mem,
+                    code_size,
+                    &describe_srcloc,
+                    value_ranges.as_ref(),
+                )?;
+            }
         }
 
         context.clear();
@@ -347,6 +400,10 @@ fn handle_module(options: &Options, path: &Path, name: &str, fisa: FlagsOrIsa) -
         println!("Total module bytecode size: {} bytes", total_bytecode_size);
     }
 
+    if !options.check_translation && options.compile_stats.is_some() {
+        println!("{}", compile_stats_json(&compile_stats));
+    }
+
     if options.report_times {
         println!("{}", timing::take_current());
     }
@@ -405,6 +405,61 @@ mod tests {
         ctx.verify(&flags).unwrap();
     }
 
+    #[test]
+    fn unreachable_is_cold() {
+        // The block ending with the trap of `unreachable` is marked cold.
+        let wasm = wat::parse_str(
+            "
+                (module
+                    (func $unreachable (param i32) (result i32)
+                        (if (get_local 0) (then unreachable))
+                        (i32.const 1)
+                    )
+                )
+            ",
+        )
+        .unwrap();
+
+        let mut trans = FuncTranslator::new();
+        let flags = settings::Flags::new(settings::builder());
+        let runtime = DummyEnvironment::new(
+            isa::TargetFrontendConfig {
+                default_call_conv: isa::CallConv::Fast,
+                pointer_width: PointerWidth::U64,
+            },
+            ReturnMode::NormalReturns,
+            false,
+        );
+
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase("unreachable");
+        ctx.func.signature.params.push(ir::AbiParam::new(I32));
+        ctx.func.signature.returns.push(ir::AbiParam::new(I32));
+
+        let (body, mut validator) = extract_func(&wasm);
+        trans
+            .translate_body(&mut validator, body, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(&flags).unwrap();
+
+        let mut cold_blocks = ctx
+            .func
+            .layout
+            .blocks()
+            .filter(|&block| ctx.func.layout.is_cold(block));
+        let cold_block = cold_blocks.next().unwrap();
+        assert!(cold_blocks.next().is_none());
+        let last_inst = ctx.func.layout.last_inst(cold_block).unwrap();
+        assert_eq!(
+            ctx.func.dfg[last_inst].opcode(),
+            ir::Opcode::Trap,
+            "{}",
+            ctx.func.display(None)
+        );
+    }
+
     fn extract_func(wat: &[u8]) -> (FunctionBody<'_>, FuncValidator<ValidatorResources>) {
         let mut validator = Validator::new();
         for payload in Parser::new(0).parse_all(wat) {
@@ -94,6 +94,18 @@ WASI_API_EXTERN void wasi_config_inherit_env(wasi_config_t* config);
  */
 WASI_API_EXTERN bool wasi_config_set_stdin_file(wasi_config_t* config, const char* path);
 
+/**
+ * \brief Configures standard input to be read from the specified bytes.
+ *
+ * The guest reads the contents of `binary` from stdin, after which it sees
+ * end-of-file. This function takes ownership of the contents of `binary`,
+ * leaving it empty.
+ *
+ * This is only supported by `wasi_snapshot_preview1` instances; creating a
+ * `wasi_unstable` instance from this configuration fails.
+ */
+WASI_API_EXTERN void wasi_config_set_stdin_bytes(wasi_config_t* config, wasm_byte_vec_t* binary);
+
 /**
  * \brief Configures this process's own stdin stream to be used as stdin for
  * this WASI configuration.
@@ -111,6 +123,17 @@ WASI_API_EXTERN void wasi_config_inherit_stdin(wasi_config_t* config);
  */
 WASI_API_EXTERN bool wasi_config_set_stdout_file(wasi_config_t* config, const char* path);
 
+/**
+ * \brief Configures standard output to be captured into an in-memory buffer.
+ *
+ * Everything the guest writes to stdout is kept in memory and can be retrieved
+ * with #wasi_instance_take_stdout.
+ *
+ * This is only supported by `wasi_snapshot_preview1` instances; creating a
+ * `wasi_unstable` instance from this configuration fails.
+ */
+WASI_API_EXTERN void wasi_config_capture_stdout(wasi_config_t* config);
+
 /**
  * \brief Configures this process's own stdout stream to be used as stdout for
  * this WASI configuration.
@@ -128,6 +151,17 @@ WASI_API_EXTERN void wasi_config_inherit_stdout(wasi_config_t* config);
  */
 WASI_API_EXTERN bool wasi_config_set_stderr_file(wasi_config_t* config, const char* path);
 
+/**
+ * \brief Configures standard error to be captured into an in-memory buffer.
+ *
+ * Everything the guest writes to stderr is kept in memory and can be retrieved
+ * with #wasi_instance_take_stderr.
+ *
+ * This is only supported by `wasi_snapshot_preview1` instances; creating a
+ * `wasi_unstable` instance from this configuration fails.
+ */
+WASI_API_EXTERN void wasi_config_capture_stderr(wasi_config_t* config);
+
 /**
  * \brief Configures this process's own stderr stream to be used as stderr for
  * this WASI configuration.
@@ -146,6 +180,70 @@ WASI_API_EXTERN void wasi_config_inherit_stderr(wasi_config_t* config);
  */
 WASI_API_EXTERN bool wasi_config_preopen_dir(wasi_config_t* config, const char* path, const char* guest_path);
 
+/**
+ * \typedef wasi_virtual_dir_t
+ * \brief Convenience alias for #wasi_virtual_dir_t
+ *
+ * \struct wasi_virtual_dir_t
+ * \brief Opaque type describing an in-memory directory tree which can be
+ * preopened with #wasi_config_preopen_virtual_dir.
+ *
+ * \fn void wasi_virtual_dir_delete(own wasi_virtual_dir_t *);
+ * \brief Deletes a virtual directory.
+ */
+WASI_DECLARE_OWN(virtual_dir)
+
+/**
+ * \brief Creates a new, empty virtual directory.
+ *
+ * The caller is expected to deallocate the returned directory, or pass it to
+ * #wasi_config_preopen_virtual_dir.
+ */
+WASI_API_EXTERN own wasi_virtual_dir_t* wasi_virtual_dir_new();
+
+/**
+ * \brief Adds an in-memory file to a virtual directory.
+ *
+ * The `path` is relative to `dir` and uses `/` as a separator. Any missing
+ * parent directories are created. If a file already exists at `path` it is
+ * replaced.
+ *
+ * The contents are copied out of `contents`, so it only needs to stay alive
+ * for this function call. Changes the guest makes to the file are not visible
+ * to the embedder.
+ *
+ * Returns `false` if `path` is not valid UTF-8, contains `..`, names a
+ * directory, or if one of its parents is a file. Otherwise `true` is returned.
+ */
+WASI_API_EXTERN bool wasi_virtual_dir_add_file(wasi_virtual_dir_t* dir, const char* path, const wasm_byte_vec_t* contents);
+
+/**
+ * \brief Adds an empty directory to a virtual directory.
+ *
+ * The `path` is relative to `dir` and uses `/` as a separator. Any missing
+ * parent directories are created as well, and existing directories are left
+ * untouched.
+ *
+ * Returns `false` if `path` is not valid UTF-8, contains `..`, or if it or one
+ * of its parents is a file. Otherwise `true` is returned.
+ */
+WASI_API_EXTERN bool wasi_virtual_dir_add_dir(wasi_virtual_dir_t* dir, const char* path);
+
+/**
+ * \brief Configures a virtual directory to be available to WASI APIs as a
+ * "preopened directory".
+ *
+ * This is like #wasi_config_preopen_dir except that the directory lives
+ * entirely in memory and nothing on the host filesystem is accessed. The
+ * `guest_path` is the name by which the directory will be known in wasm.
+ *
+ * This function takes ownership of `dir` whether it succeeds or not.
+ *
+ * This is only supported by `wasi_snapshot_preview1` instances; creating a
+ * `wasi_unstable` instance from this configuration fails.
+ */
+WASI_API_EXTERN bool wasi_config_preopen_virtual_dir(wasi_config_t* config, own wasi_virtual_dir_t* dir, const char* guest_path);
+
 /**
  * \typedef wasi_instance_t
  * \brief Convenience alias for #wasi_instance_t
@@ -204,6 +302,31 @@ WASI_API_EXTERN const wasm_extern_t* wasi_instance_bind_import(
   const wasm_importtype_t* import
 );
 
+/**
+ * \brief Takes the output the guest has written to a captured stdout so far.
+ *
+ * If stdout was configured with #wasi_config_capture_stdout, this moves the
+ * bytes written since the last call into `out` and returns `true`. The caller
+ * owns `out` afterwards and must delete it with #wasm_byte_vec_delete.
+ *
+ * Returns `false`, leaving `out` untouched, if stdout is not captured.
+ */
+WASI_API_EXTERN bool wasi_instance_take_stdout(
+  const wasi_instance_t* instance,
+  own wasm_byte_vec_t* out
+);
+
+/**
+ * \brief Takes the output the guest has written to a captured stderr so far.
+ *
+ * This is the same as #wasi_instance_take_stdout, but for stderr configured
+ * with #wasi_config_capture_stderr.
+ */
+WASI_API_EXTERN bool wasi_instance_take_stderr(
+  const wasi_instance_t* instance,
+  own wasm_byte_vec_t* out
+);
+
 #undef own
 
 #ifdef __cplusplus
@@ -310,6 +310,48 @@ WASM_API_EXTERN own wasmtime_error_t* wasmtime_wat2wasm(
  */
 WASM_API_EXTERN void wasmtime_store_gc(wasm_store_t* store);
 
+/**
+ * \brief Associates host-defined data with the given store.
+ *
+ * The `data` pointer can later be retrieved with #wasmtime_store_get_data, or
+ * from within host functions with #wasmtime_caller_get_data. If `finalizer` is
+ * not `NULL` it is called with `data` when the store is deleted or when the
+ * data is replaced by another call to this function.
+ *
+ * The `store` argument must not be NULL.
+ */
+WASM_API_EXTERN void wasmtime_store_set_data(
+    wasm_store_t* store,
+    void* data,
+    void (*finalizer)(void*)
+);
+
+/**
+ * \brief Returns the data previously associated with the given store by
+ * #wasmtime_store_set_data, or `NULL` if there is none.
+ */
+WASM_API_EXTERN void* wasmtime_store_get_data(const wasm_store_t* store);
+
+/**
+ * \brief Limits the growth of memories and tables within the given store.
+ *
+ * After this call no linear memory in `store` may grow beyond `memory_pages`
+ * WebAssembly pages, and no table beyond `table_elements` elements. A negative
+ * value leaves the corresponding resource unlimited. The limits apply to all
+ * instances in the store, including ones created before this call, and replace
+ * any previously configured limits.
+ *
+ * Growth beyond a limit fails as if the memory or table had reached its
+ * declared maximum: `memory.grow` and `table.grow` return -1 and
+ * #wasm_memory_grow and #wasm_table_grow return `false`. Memories and tables
+ * which are already larger than the limit are not shrunk.
+ */
+WASM_API_EXTERN void wasmtime_store_limiter(
+    wasm_store_t* store,
+    int64_t memory_pages,
+    int64_t table_elements
+);
+
 /**
  * \typedef wasmtime_linker_t
  * \brief Convenience alias for #wasmtime_linker_t
@@ -588,6 +630,12 @@ WASM_API_EXTERN own wasm_func_t* wasmtime_funcref_as_func(const wasm_val_t* val)
  */
 WASM_API_EXTERN own wasm_extern_t* wasmtime_caller_export_get(const wasmtime_caller_t* caller, const wasm_name_t* name);
 
+/**
+ * \brief Returns the data associated with the caller's store by
+ * #wasmtime_store_set_data, or `NULL` if there is none.
+ */
+WASM_API_EXTERN void* wasmtime_caller_get_data(const wasmtime_caller_t* caller);
+
 /**
  * \typedef wasmtime_interrupt_handle_t
  * \brief Convenience alias for #wasmtime_interrupt_handle_t
@@ -696,6 +744,106 @@ WASM_API_EXTERN own wasmtime_error_t *wasmtime_func_call(
     own wasm_trap_t **trap
 );
 
+/**
+ * \typedef wasmtime_val_raw_t
+ * \brief Convenience alias for #wasmtime_val_raw_t
+ *
+ * \union wasmtime_val_raw_t
+ * \brief An untyped WebAssembly value used by #wasmtime_func_call_unchecked.
+ *
+ * Which member is valid is determined by the type signature of the function
+ * being called. Each value occupies 16 bytes and is 16-byte aligned.
+ */
+#if defined(_MSC_VER)
+#define WASMTIME_VAL_RAW_ALIGN __declspec(align(16))
+#else
+#define WASMTIME_VAL_RAW_ALIGN __attribute__((aligned(16)))
+#endif
+typedef union WASMTIME_VAL_RAW_ALIGN wasmtime_val_raw_t {
+  /// Value of type `i32`.
+  int32_t i32;
+  /// Value of type `i64`.
+  int64_t i64;
+  /// Value of type `f32`.
+  float32_t f32;
+  /// Value of type `f64`.
+  float64_t f64;
+  /// Value of type `v128`, in little-endian byte order.
+  uint8_t v128[16];
+} wasmtime_val_raw_t;
+
+/**
+ * \brief Call a WebAssembly function without any type checking or allocation.
+ *
+ * This function is a faster alternative to #wasmtime_func_call intended for
+ * embeddings which call the same functions many times. The `args_and_results`
+ * array must have room for the larger of #wasm_func_param_arity and
+ * #wasm_func_result_arity values. On entry it holds the arguments to the
+ * function, and after a successful call it holds the results.
+ *
+ * If the function traps then the trap is written to `trap` and the contents of
+ * `args_and_results` are unspecified. Otherwise `NULL` is written to `trap`.
+ * The `trap` pointer cannot be `NULL`.
+ *
+ * This function is unsafe: no checks are made that the arguments have the
+ * types the function expects, or that `args_and_results` is large enough.
+ * Checking the signature is the embedder's job, for example by calling
+ * #wasmtime_func_typed once and then #wasmtime_typed_func_call. Functions
+ * whose parameters or results include `externref` or `funcref` values are not
+ * supported and must be called with #wasmtime_func_call.
+ */
+WASM_API_EXTERN void wasmtime_func_call_unchecked(
+    wasm_func_t *func,
+    wasmtime_val_raw_t *args_and_results,
+    own wasm_trap_t **trap
+);
+
+/**
+ * \typedef wasmtime_typed_func_t
+ * \brief Convenience alias for #wasmtime_typed_func_t
+ *
+ * \struct wasmtime_typed_func_t
+ * \brief A function whose signature has been checked, created with
+ * #wasmtime_func_typed.
+ *
+ * \fn void wasmtime_typed_func_delete(own wasmtime_typed_func_t *);
+ * \brief Deletes a typed function.
+ */
+WASMTIME_DECLARE_OWN(typed_func)
+
+/**
+ * \brief Checks the signature of a function once, for repeated calls with
+ * #wasmtime_typed_func_call.
+ *
+ * \param func the function to check, which isn't consumed.
+ * \param ty the type the embedder expects the function to have.
+ * \param typed where the typed function is written to on success.
+ *
+ * Returns an error if the type of `func` isn't `ty`, or if any of its
+ * parameters or results is an `externref` or `funcref`, which can't be passed
+ * as #wasmtime_val_raw_t values.
+ */
+WASM_API_EXTERN own wasmtime_error_t *wasmtime_func_typed(
+    const wasm_func_t *func,
+    const wasm_functype_t *ty,
+    own wasmtime_typed_func_t **typed
+);
+
+/**
+ * \brief Call a function whose signature was checked by #wasmtime_func_typed,
+ * without any further checks or allocation.
+ *
+ * The `args_and_results` and `trap` parameters behave as for
+ * #wasmtime_func_call_unchecked. The arguments must have the types passed to
+ * #wasmtime_func_typed, and `args_and_results` must have room for the larger of
+ * the number of parameters and results.
+ */
+WASM_API_EXTERN void wasmtime_typed_func_call(
+    const wasmtime_typed_func_t *func,
+    wasmtime_val_raw_t *args_and_results,
+    own wasm_trap_t **trap
+);
+
 /**
  * \brief Creates a new global value.
  *
@@ -1,12 +1,13 @@
+use crate::{handle_result, wasm_name_t, wasm_trap_t, wasmtime_error_t};
 use crate::{wasm_extern_t, wasm_functype_t, wasm_store_t, wasm_val_t};
-use crate::{wasm_name_t, wasm_trap_t, wasmtime_error_t};
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use std::any::Any;
 use std::ffi::c_void;
 use std::mem::MaybeUninit;
 use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use std::str;
-use wasmtime::{Caller, Extern, Func, Trap, Val};
+use wasmtime::{Caller, Extern, Func, FuncType, Trap, Val, ValType};
 
 #[derive(Clone)]
 #[repr(transparent)]
@@ -232,20 +233,106 @@ fn _wasmtime_func_call(
             Err(err) => Some(Box::new(err.into())),
         },
         Err(panic) => {
-            let trap = if let Some(msg) = panic.downcast_ref::<String>() {
-                Trap::new(msg)
-            } else if let Some(msg) = panic.downcast_ref::<&'static str>() {
-                Trap::new(*msg)
-            } else {
-                Trap::new("rust panic happened")
-            };
-            let trap = Box::new(wasm_trap_t::new(trap));
+            let trap = Box::new(wasm_trap_t::new(panic_to_trap(panic)));
             *trap_ptr = Box::into_raw(trap);
             None
         }
     }
 }
 
+fn panic_to_trap(panic: Box<dyn Any + Send>) -> Trap {
+    if let Some(msg) = panic.downcast_ref::<String>() {
+        Trap::new(msg)
+    } else if let Some(msg) = panic.downcast_ref::<&'static str>() {
+        Trap::new(*msg)
+    } else {
+        Trap::new("rust panic happened")
+    }
+}
+
+// Aligned like the `u128` slots `Func::call_unchecked` takes, so that arrays of these can be
+// passed to it directly.
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub union wasmtime_val_raw_t {
+    pub i32: i32,
+    pub i64: i64,
+    pub f32: f32,
+    pub f64: f64,
+    pub v128: [u8; 16],
+}
+
+const _: [(); std::mem::size_of::<u128>()] = [(); std::mem::size_of::<wasmtime_val_raw_t>()];
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_func_call_unchecked(
+    func: &wasm_func_t,
+    args_and_results: *mut wasmtime_val_raw_t,
+    trap_ptr: &mut *mut wasm_trap_t,
+) {
+    call_raw(func.func(), args_and_results, trap_ptr)
+}
+
+/// A function whose signature was checked once by `wasmtime_func_typed`, so that it can be
+/// called with raw values without further checks.
+pub struct wasmtime_typed_func_t {
+    func: Func,
+}
+
+wasmtime_c_api_macros::declare_own!(wasmtime_typed_func_t);
+
+#[no_mangle]
+pub extern "C" fn wasmtime_func_typed(
+    func: &wasm_func_t,
+    ty: &wasm_functype_t,
+    typed: &mut *mut wasmtime_typed_func_t,
+) -> Option<Box<wasmtime_error_t>> {
+    let func = func.func();
+    handle_result(check_raw_signature(func, &ty.ty().ty), |()| {
+        *typed = Box::into_raw(Box::new(wasmtime_typed_func_t { func: func.clone() }));
+    })
+}
+
+fn check_raw_signature(func: &Func, expected: &FuncType) -> anyhow::Result<()> {
+    let actual = func.ty();
+    if actual != *expected {
+        bail!(
+            "function has type {:?} but {:?} was expected",
+            actual,
+            expected
+        );
+    }
+    let is_ref = |ty: ValType| ty == ValType::ExternRef || ty == ValType::FuncRef;
+    if actual.params().any(is_ref) || actual.results().any(is_ref) {
+        bail!("functions with reference type parameters or results can't take raw values");
+    }
+    Ok(())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_typed_func_call(
+    func: &wasmtime_typed_func_t,
+    args_and_results: *mut wasmtime_val_raw_t,
+    trap_ptr: &mut *mut wasm_trap_t,
+) {
+    call_raw(&func.func, args_and_results, trap_ptr)
+}
+
+unsafe fn call_raw(
+    func: &Func,
+    args_and_results: *mut wasmtime_val_raw_t,
+    trap_ptr: &mut *mut wasm_trap_t,
+) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        func.call_unchecked(args_and_results as *mut u128)
+    }));
+    *trap_ptr = match result {
+        Ok(Ok(())) => ptr::null_mut(),
+        Ok(Err(trap)) => Box::into_raw(Box::new(wasm_trap_t::new(trap))),
+        Err(panic) => Box::into_raw(Box::new(wasm_trap_t::new(panic_to_trap(panic)))),
+    };
+}
+
 #[no_mangle]
 pub extern "C" fn wasm_func_type(f: &wasm_func_t) -> Box<wasm_functype_t> {
     Box::new(wasm_functype_t::new(f.func().ty()))
@@ -266,6 +353,11 @@ pub extern "C" fn wasm_func_as_extern(f: &mut wasm_func_t) -> &mut wasm_extern_t
     &mut (*f).ext
 }
 
+#[no_mangle]
+pub extern "C" fn wasmtime_caller_get_data(caller: &wasmtime_caller_t) -> *mut c_void {
+    crate::store::StoreData::get(&caller.caller.store())
+}
+
 #[no_mangle]
 pub extern "C" fn wasmtime_caller_export_get(
     caller: &wasmtime_caller_t,
@@ -1,5 +1,9 @@
 use crate::wasm_engine_t;
-use wasmtime::{InterruptHandle, Store};
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::ptr;
+use wasmtime::{InterruptHandle, ResourceLimiter, Store};
 
 #[repr(C)]
 #[derive(Clone)]
@@ -42,3 +46,83 @@ pub extern "C" fn wasmtime_interrupt_handle_new(
 pub extern "C" fn wasmtime_interrupt_handle_interrupt(handle: &wasmtime_interrupt_handle_t) {
     handle.handle.interrupt();
 }
+
+/// Host data attached to a store through `wasmtime_store_set_data`.
+#[derive(Default)]
+pub(crate) struct StoreData {
+    data: Cell<*mut c_void>,
+    finalizer: Cell<Option<extern "C" fn(*mut c_void)>>,
+}
+
+impl StoreData {
+    pub(crate) fn get(store: &Store) -> *mut c_void {
+        store
+            .get::<StoreData>()
+            .map_or(ptr::null_mut(), |d| d.data.get())
+    }
+}
+
+impl Drop for StoreData {
+    fn drop(&mut self) {
+        if let Some(f) = self.finalizer.get() {
+            f(self.data.get());
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_store_set_data(
+    store: &wasm_store_t,
+    data: *mut c_void,
+    finalizer: Option<extern "C" fn(*mut c_void)>,
+) {
+    let store = &store.store;
+    if store.get::<StoreData>().is_none() {
+        drop(store.set(StoreData::default()));
+    }
+    let slot = store.get::<StoreData>().unwrap();
+    let prev_data = slot.data.replace(data);
+    if let Some(f) = slot.finalizer.replace(finalizer) {
+        f(prev_data);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_store_get_data(store: &wasm_store_t) -> *mut c_void {
+    StoreData::get(&store.store)
+}
+
+/// Limiter installed by `wasmtime_store_limiter`, where `None` means unlimited.
+struct Limits {
+    memory_pages: Option<u32>,
+    table_elements: Option<u32>,
+}
+
+impl ResourceLimiter for Limits {
+    fn memory_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        self.memory_pages.map_or(true, |max| desired <= max)
+    }
+
+    fn table_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        self.table_elements.map_or(true, |max| desired <= max)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_store_limiter(
+    store: &wasm_store_t,
+    memory_pages: i64,
+    table_elements: i64,
+) {
+    let limit = |n: i64| {
+        if n < 0 {
+            None
+        } else {
+            Some(u32::try_from(n).unwrap_or(u32::max_value()))
+        }
+    };
+    store.store.set_limiter(Limits {
+        memory_pages: limit(memory_pages),
+        table_elements: limit(table_elements),
+    });
+}
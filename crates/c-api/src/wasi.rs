@@ -1,15 +1,18 @@
 //! The WASI embedding API definitions for Wasmtime.
-use crate::{wasm_extern_t, wasm_importtype_t, wasm_store_t, wasm_trap_t};
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::{wasm_byte_vec_t, wasm_extern_t, wasm_importtype_t, wasm_store_t, wasm_trap_t};
+use anyhow::{bail, Result};
+use std::collections::hash_map::{Entry, HashMap};
 use std::ffi::CStr;
 use std::fs::File;
 use std::os::raw::{c_char, c_int};
 use std::path::{Path, PathBuf};
 use std::slice;
 use std::str;
+use std::sync::{Arc, RwLock};
+use wasi_common::virtfs::pipe::{ReadPipe, WritePipe};
+use wasi_common::virtfs::VecFileContents;
 use wasi_common::{
-    old::snapshot_0::WasiCtxBuilder as WasiSnapshot0CtxBuilder, preopen_dir,
+    old::snapshot_0::WasiCtxBuilder as WasiSnapshot0CtxBuilder, preopen_dir, VirtualDirEntry,
     WasiCtxBuilder as WasiPreview1CtxBuilder,
 };
 use wasmtime::{Extern, Linker, Store, Trap};
@@ -34,15 +37,29 @@ pub enum WasiModule {
 
 impl WasiModule {}
 
+enum WasiInput {
+    File(File),
+    Bytes(Vec<u8>),
+}
+
+enum WasiOutput {
+    File(File),
+    Captured,
+}
+
+/// A buffer that a captured stdout or stderr stream is written to.
+type CapturedOutput = Arc<RwLock<Vec<u8>>>;
+
 #[repr(C)]
 #[derive(Default)]
 pub struct wasi_config_t {
     args: Vec<Vec<u8>>,
     env: Vec<(Vec<u8>, Vec<u8>)>,
-    stdin: Option<File>,
-    stdout: Option<File>,
-    stderr: Option<File>,
+    stdin: Option<WasiInput>,
+    stdout: Option<WasiOutput>,
+    stderr: Option<WasiOutput>,
     preopens: Vec<(File, PathBuf)>,
+    virtual_preopens: Vec<(HashMap<String, VirtualDirEntry>, PathBuf)>,
     inherit_args: bool,
     inherit_env: bool,
     inherit_stdin: bool,
@@ -115,12 +132,21 @@ pub unsafe extern "C" fn wasi_config_set_stdin_file(
         None => return false,
     };
 
-    config.stdin = Some(file);
+    config.stdin = Some(WasiInput::File(file));
     config.inherit_stdin = false;
 
     true
 }
 
+#[no_mangle]
+pub extern "C" fn wasi_config_set_stdin_bytes(
+    config: &mut wasi_config_t,
+    binary: &mut wasm_byte_vec_t,
+) {
+    config.stdin = Some(WasiInput::Bytes(binary.take()));
+    config.inherit_stdin = false;
+}
+
 #[no_mangle]
 pub extern "C" fn wasi_config_inherit_stdin(config: &mut wasi_config_t) {
     config.stdin = None;
@@ -137,12 +163,18 @@ pub unsafe extern "C" fn wasi_config_set_stdout_file(
         None => return false,
     };
 
-    config.stdout = Some(file);
+    config.stdout = Some(WasiOutput::File(file));
     config.inherit_stdout = false;
 
     true
 }
 
+#[no_mangle]
+pub extern "C" fn wasi_config_capture_stdout(config: &mut wasi_config_t) {
+    config.stdout = Some(WasiOutput::Captured);
+    config.inherit_stdout = false;
+}
+
 #[no_mangle]
 pub extern "C" fn wasi_config_inherit_stdout(config: &mut wasi_config_t) {
     config.stdout = None;
@@ -159,12 +191,18 @@ pub unsafe extern "C" fn wasi_config_set_stderr_file(
         None => return false,
     };
 
-    (*config).stderr = Some(file);
+    (*config).stderr = Some(WasiOutput::File(file));
     (*config).inherit_stderr = false;
 
     true
 }
 
+#[no_mangle]
+pub extern "C" fn wasi_config_capture_stderr(config: &mut wasi_config_t) {
+    config.stderr = Some(WasiOutput::Captured);
+    config.inherit_stderr = false;
+}
+
 #[no_mangle]
 pub extern "C" fn wasi_config_inherit_stderr(config: &mut wasi_config_t) {
     config.stderr = None;
@@ -195,6 +233,122 @@ pub unsafe extern "C" fn wasi_config_preopen_dir(
     true
 }
 
+#[repr(C)]
+#[derive(Default)]
+pub struct wasi_virtual_dir_t {
+    entries: HashMap<String, VirtualDirEntry>,
+}
+
+impl wasi_virtual_dir_t {
+    /// Returns the entries of the directory at `path`, creating any missing
+    /// directories along the way, or `None` if a component of `path` is a file
+    /// or the path escapes this directory.
+    fn dir_entries<'a>(
+        &mut self,
+        path: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&mut HashMap<String, VirtualDirEntry>> {
+        let mut entries = &mut self.entries;
+        for component in path {
+            let entry = entries
+                .entry(component.to_string())
+                .or_insert_with(VirtualDirEntry::empty_directory);
+            entries = match entry {
+                VirtualDirEntry::Directory(entries) => entries,
+                VirtualDirEntry::File(_) => return None,
+            };
+        }
+        Some(entries)
+    }
+}
+
+/// Splits a `/`-separated path relative to a virtual directory into its
+/// components, skipping empty and `.` components. Returns `None` for paths that
+/// aren't valid UTF-8 or that contain `..`.
+unsafe fn virtual_path_components<'a>(path: *const c_char) -> Option<Vec<&'a str>> {
+    let path = CStr::from_ptr(path).to_str().ok()?;
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            component => components.push(component),
+        }
+    }
+    Some(components)
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_virtual_dir_new() -> Box<wasi_virtual_dir_t> {
+    Box::new(wasi_virtual_dir_t::default())
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_virtual_dir_delete(_dir: Box<wasi_virtual_dir_t>) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_virtual_dir_add_file(
+    dir: &mut wasi_virtual_dir_t,
+    path: *const c_char,
+    contents: &wasm_byte_vec_t,
+) -> bool {
+    let mut components = match virtual_path_components(path) {
+        Some(components) => components,
+        None => return false,
+    };
+    let name = match components.pop() {
+        Some(name) => name,
+        None => return false,
+    };
+    let entries = match dir.dir_entries(components) {
+        Some(entries) => entries,
+        None => return false,
+    };
+    let file = VirtualDirEntry::File(Box::new(VecFileContents::with_content(
+        contents.as_slice().to_vec(),
+    )));
+    match entries.entry(name.to_string()) {
+        Entry::Occupied(mut entry) => match entry.get() {
+            VirtualDirEntry::Directory(_) => return false,
+            VirtualDirEntry::File(_) => {
+                entry.insert(file);
+            }
+        },
+        Entry::Vacant(entry) => {
+            entry.insert(file);
+        }
+    }
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_virtual_dir_add_dir(
+    dir: &mut wasi_virtual_dir_t,
+    path: *const c_char,
+) -> bool {
+    match virtual_path_components(path) {
+        Some(components) => dir.dir_entries(components).is_some(),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_config_preopen_virtual_dir(
+    config: &mut wasi_config_t,
+    dir: Box<wasi_virtual_dir_t>,
+    guest_path: *const c_char,
+) -> bool {
+    let guest_path = match cstr_to_path(guest_path) {
+        Some(p) => p,
+        None => return false,
+    };
+
+    config
+        .virtual_preopens
+        .push((dir.entries, guest_path.to_owned()));
+
+    true
+}
+
 enum WasiInstance {
     Preview1(WasiPreview1),
     Snapshot0(WasiSnapshot0),
@@ -214,29 +368,46 @@ fn create_snapshot0_instance(store: &Store, config: wasi_config_t) -> Result<Was
     }
     if config.inherit_stdin {
         builder.inherit_stdin();
-    } else if let Some(file) = config.stdin {
-        builder.stdin(file);
+    } else if let Some(stdin) = config.stdin {
+        match stdin {
+            WasiInput::File(file) => builder.stdin(file),
+            WasiInput::Bytes(_) => bail!("stdin from bytes requires wasi_snapshot_preview1"),
+        };
     }
     if config.inherit_stdout {
         builder.inherit_stdout();
-    } else if let Some(file) = config.stdout {
-        builder.stdout(file);
+    } else if let Some(stdout) = config.stdout {
+        match stdout {
+            WasiOutput::File(file) => builder.stdout(file),
+            WasiOutput::Captured => bail!("capturing stdout requires wasi_snapshot_preview1"),
+        };
     }
     if config.inherit_stderr {
         builder.inherit_stderr();
-    } else if let Some(file) = config.stderr {
-        builder.stderr(file);
+    } else if let Some(stderr) = config.stderr {
+        match stderr {
+            WasiOutput::File(file) => builder.stderr(file),
+            WasiOutput::Captured => bail!("capturing stderr requires wasi_snapshot_preview1"),
+        };
     }
     for preopen in config.preopens {
         builder.preopened_dir(preopen.0, preopen.1);
     }
+    if !config.virtual_preopens.is_empty() {
+        bail!("virtual directories require wasi_snapshot_preview1");
+    }
     Ok(WasiInstance::Snapshot0(WasiSnapshot0::new(
         store,
         builder.build()?,
     )))
 }
 
-fn create_preview1_instance(store: &Store, config: wasi_config_t) -> Result<WasiInstance> {
+fn create_preview1_instance(
+    store: &Store,
+    config: wasi_config_t,
+    stdout: &mut Option<CapturedOutput>,
+    stderr: &mut Option<CapturedOutput>,
+) -> Result<WasiInstance> {
     use std::convert::TryFrom;
     use wasi_common::OsFile;
     let mut builder = WasiPreview1CtxBuilder::new();
@@ -252,32 +423,52 @@ fn create_preview1_instance(store: &Store, config: wasi_config_t) -> Result<Wasi
     }
     if config.inherit_stdin {
         builder.inherit_stdin();
-    } else if let Some(file) = config.stdin {
-        builder.stdin(OsFile::try_from(file)?);
+    } else if let Some(stdin) = config.stdin {
+        match stdin {
+            WasiInput::File(file) => builder.stdin(OsFile::try_from(file)?),
+            WasiInput::Bytes(bytes) => builder.stdin(ReadPipe::from(bytes)),
+        };
     }
     if config.inherit_stdout {
         builder.inherit_stdout();
-    } else if let Some(file) = config.stdout {
-        builder.stdout(OsFile::try_from(file)?);
+    } else if let Some(output) = config.stdout {
+        match output {
+            WasiOutput::File(file) => builder.stdout(OsFile::try_from(file)?),
+            WasiOutput::Captured => builder.stdout(capture_output(stdout)),
+        };
     }
     if config.inherit_stderr {
         builder.inherit_stderr();
-    } else if let Some(file) = config.stderr {
-        builder.stderr(OsFile::try_from(file)?);
+    } else if let Some(output) = config.stderr {
+        match output {
+            WasiOutput::File(file) => builder.stderr(OsFile::try_from(file)?),
+            WasiOutput::Captured => builder.stderr(capture_output(stderr)),
+        };
     }
     for preopen in config.preopens {
         builder.preopened_dir(preopen.0, preopen.1);
     }
+    for (entries, guest_path) in config.virtual_preopens {
+        builder.preopened_virt(VirtualDirEntry::Directory(entries), guest_path);
+    }
     Ok(WasiInstance::Preview1(WasiPreview1::new(
         store,
         builder.build()?,
     )))
 }
 
+fn capture_output(buffer: &mut Option<CapturedOutput>) -> WritePipe<Vec<u8>> {
+    let shared = Arc::new(RwLock::new(Vec::new()));
+    *buffer = Some(shared.clone());
+    WritePipe::from_shared(shared)
+}
+
 #[repr(C)]
 pub struct wasi_instance_t {
     wasi: WasiInstance,
     export_cache: HashMap<String, Box<wasm_extern_t>>,
+    stdout: Option<CapturedOutput>,
+    stderr: Option<CapturedOutput>,
 }
 
 impl wasi_instance_t {
@@ -297,10 +488,13 @@ pub unsafe extern "C" fn wasi_instance_new(
     trap: &mut *mut wasm_trap_t,
 ) -> Option<Box<wasi_instance_t>> {
     let store = &store.store;
+    let mut stdout = None;
+    let mut stderr = None;
 
     let result = match CStr::from_ptr(name).to_str().unwrap_or("") {
         "wasi_snapshot_preview1" => {
-            create_preview1_instance(store, *config).map_err(|e| e.to_string())
+            create_preview1_instance(store, *config, &mut stdout, &mut stderr)
+                .map_err(|e| e.to_string())
         }
         "wasi_unstable" => create_snapshot0_instance(store, *config).map_err(|e| e.to_string()),
         _ => Err("unsupported WASI version".into()),
@@ -310,6 +504,8 @@ pub unsafe extern "C" fn wasi_instance_new(
         Ok(wasi) => Some(Box::new(wasi_instance_t {
             wasi,
             export_cache: HashMap::new(),
+            stdout,
+            stderr,
         })),
         Err(e) => {
             *trap = Box::into_raw(Box::new(wasm_trap_t { trap: Trap::new(e) }));
@@ -360,3 +556,29 @@ pub extern "C" fn wasi_instance_bind_import<'a>(
         });
     Some(entry)
 }
+
+#[no_mangle]
+pub extern "C" fn wasi_instance_take_stdout(
+    instance: &wasi_instance_t,
+    out: &mut wasm_byte_vec_t,
+) -> bool {
+    take_captured_output(&instance.stdout, out)
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_instance_take_stderr(
+    instance: &wasi_instance_t,
+    out: &mut wasm_byte_vec_t,
+) -> bool {
+    take_captured_output(&instance.stderr, out)
+}
+
+fn take_captured_output(buffer: &Option<CapturedOutput>, out: &mut wasm_byte_vec_t) -> bool {
+    match buffer {
+        Some(buffer) => {
+            out.set_buffer(std::mem::take(&mut *buffer.write().unwrap()));
+            true
+        }
+        None => false,
+    }
+}
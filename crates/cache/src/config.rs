@@ -98,6 +98,8 @@ pub struct CacheConfig {
 struct CacheState {
     hits: AtomicUsize,
     misses: AtomicUsize,
+    function_hits: AtomicUsize,
+    function_misses: AtomicUsize,
 }
 
 /// Creates a new configuration file at specified path, or default path if None is passed.
@@ -385,6 +387,16 @@ impl CacheConfig {
         self.state.misses.load(SeqCst)
     }
 
+    /// Returns the number of function cache hits seen so far
+    pub fn function_cache_hits(&self) -> usize {
+        self.state.function_hits.load(SeqCst)
+    }
+
+    /// Returns the number of function cache misses seen so far
+    pub fn function_cache_misses(&self) -> usize {
+        self.state.function_misses.load(SeqCst)
+    }
+
     pub(crate) fn on_cache_get_async(&self, path: impl AsRef<Path>) {
         self.state.hits.fetch_add(1, SeqCst);
         self.worker().on_cache_get_async(path)
@@ -395,6 +407,16 @@ impl CacheConfig {
         self.worker().on_cache_update_async(path)
     }
 
+    pub(crate) fn on_function_cache_get_async(&self, path: impl AsRef<Path>) {
+        self.state.function_hits.fetch_add(1, SeqCst);
+        self.worker().on_cache_get_async(path)
+    }
+
+    pub(crate) fn on_function_cache_update_async(&self, path: impl AsRef<Path>) {
+        self.state.function_misses.fetch_add(1, SeqCst);
+        self.worker().on_cache_update_async(path)
+    }
+
     fn load_and_parse_file(config_file: Option<&Path>) -> Result<Self> {
         // get config file path
         let (config_file, user_custom_file) = match config_file {
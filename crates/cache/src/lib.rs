@@ -17,6 +17,16 @@ use worker::Worker;
 /// Module level cache entry.
 pub struct ModuleCacheEntry<'config>(Option<ModuleCacheEntryInner<'config>>);
 
+/// Function level cache entry.
+///
+/// Functions are cached individually, keyed on their own state and on the
+/// context shared by all the functions of a module, so that changing a few
+/// functions of a big module only requires recompiling these functions.
+pub struct FunctionCacheEntry<'config> {
+    inner: Option<ModuleCacheEntryInner<'config>>,
+    context_hash: [u8; 32],
+}
+
 struct ModuleCacheEntryInner<'config> {
     root_path: PathBuf,
     cache_config: &'config CacheConfig,
@@ -29,6 +39,7 @@ impl<'config> ModuleCacheEntry<'config> {
     pub fn new<'data>(compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
         if cache_config.enabled() {
             Self(Some(ModuleCacheEntryInner::new(
+                "modules",
                 compiler_name,
                 cache_config,
             )))
@@ -50,9 +61,7 @@ impl<'config> ModuleCacheEntry<'config> {
     {
         let mut hasher = Sha256Hasher(Sha256::new());
         state.hash(&mut hasher);
-        let hash: [u8; 32] = hasher.0.finalize().into();
-        // standard encoding uses '/' which can't be used for filename
-        let hash = base64::encode_config(&hash, base64::URL_SAFE_NO_PAD);
+        let hash = hasher.finish_base64();
 
         let inner = match &self.0 {
             Some(inner) => inner,
@@ -73,8 +82,72 @@ impl<'config> ModuleCacheEntry<'config> {
     }
 }
 
+impl<'config> FunctionCacheEntry<'config> {
+    /// Create the cache entry for the functions sharing the given `context`.
+    pub fn new<C: Hash>(
+        compiler_name: &str,
+        cache_config: &'config CacheConfig,
+        context: C,
+    ) -> Self {
+        let mut hasher = Sha256Hasher(Sha256::new());
+        context.hash(&mut hasher);
+        let context_hash = hasher.0.finalize().into();
+        let inner = if cache_config.enabled() {
+            Some(ModuleCacheEntryInner::new(
+                "functions",
+                compiler_name,
+                cache_config,
+            ))
+        } else {
+            None
+        };
+        Self {
+            inner,
+            context_hash,
+        }
+    }
+
+    /// Gets cached data if both the context and the state match, otherwise
+    /// calls the `compute`.
+    pub fn get_data<T, U, E>(
+        &self,
+        state: T,
+        compute: impl FnOnce(T) -> Result<U, E>,
+    ) -> Result<U, E>
+    where
+        T: Hash,
+        U: Serialize + for<'a> Deserialize<'a>,
+    {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return compute(state),
+        };
+
+        let mut hasher = Sha256Hasher(Sha256::new());
+        hasher.write(&self.context_hash);
+        state.hash(&mut hasher);
+        let hash = hasher.finish_base64();
+
+        if let Some(cached_val) = inner.get_data(&hash) {
+            let func_cache_path = inner.root_path.join(&hash);
+            inner
+                .cache_config
+                .on_function_cache_get_async(&func_cache_path); // call on success
+            return Ok(cached_val);
+        }
+        let val_to_cache = compute(state)?;
+        if inner.update_data(&hash, &val_to_cache).is_some() {
+            let func_cache_path = inner.root_path.join(&hash);
+            inner
+                .cache_config
+                .on_function_cache_update_async(&func_cache_path); // call on success
+        }
+        Ok(val_to_cache)
+    }
+}
+
 impl<'config> ModuleCacheEntryInner<'config> {
-    fn new<'data>(compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
+    fn new<'data>(kind: &str, compiler_name: &str, cache_config: &'config CacheConfig) -> Self {
         // If debug assertions are enabled then assume that we're some sort of
         // local build. We don't want local builds to stomp over caches between
         // builds, so just use a separate cache directory based on the mtime of
@@ -108,7 +181,7 @@ impl<'config> ModuleCacheEntryInner<'config> {
                 comp_ver = env!("GIT_REV"),
             )
         };
-        let root_path = cache_config.directory().join("modules").join(compiler_dir);
+        let root_path = cache_config.directory().join(kind).join(compiler_dir);
 
         Self {
             root_path,
@@ -175,6 +248,14 @@ impl<'config> ModuleCacheEntryInner<'config> {
     }
 }
 
+impl Sha256Hasher {
+    fn finish_base64(self) -> String {
+        let hash: [u8; 32] = self.0.finalize().into();
+        // standard encoding uses '/' which can't be used for filename
+        base64::encode_config(&hash, base64::URL_SAFE_NO_PAD)
+    }
+}
+
 impl Hasher for Sha256Hasher {
     fn finish(&self) -> u64 {
         panic!("Sha256Hasher doesn't support finish!");
@@ -62,8 +62,16 @@ fn test_write_read_cache() {
     let compiler1 = "test-1";
     let compiler2 = "test-2";
 
-    let entry1 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(compiler1, &cache_config));
-    let entry2 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(compiler2, &cache_config));
+    let entry1 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "modules",
+        compiler1,
+        &cache_config,
+    ));
+    let entry2 = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "modules",
+        compiler2,
+        &cache_config,
+    ));
 
     entry1.get_data::<_, i32, i32>(1, |_| Ok(100)).unwrap();
     entry1.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
@@ -90,3 +98,47 @@ fn test_write_read_cache() {
     entry1.get_data::<_, i32, i32>(4, |_| panic!()).unwrap();
     entry2.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
 }
+
+#[test]
+fn test_write_read_function_cache() {
+    let (_tempdir, cache_dir, config_path) = test_prolog();
+    let cache_config = load_config!(
+        config_path,
+        "[cache]\n\
+         enabled = true\n\
+         directory = {cache_dir}\n\
+         baseline-compression-level = 3\n",
+        cache_dir
+    );
+    assert!(cache_config.enabled());
+
+    let compiler = "test-1";
+
+    let entry1 = FunctionCacheEntry::new(compiler, &cache_config, "context-1");
+    let entry2 = FunctionCacheEntry::new(compiler, &cache_config, "context-2");
+
+    entry1.get_data::<_, i32, i32>(1, |_| Ok(100)).unwrap();
+    entry1.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
+    entry1.get_data::<_, i32, i32>(2, |_| Ok(100)).unwrap();
+    entry1.get_data::<_, i32, i32>(2, |_| panic!()).unwrap();
+
+    // The same state in another context is another entry.
+    entry2.get_data::<_, i32, i32>(1, |_| Ok(100)).unwrap();
+    entry2.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
+    entry1.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
+
+    // Function entries don't collide with module entries.
+    let module_entry = ModuleCacheEntry::from_inner(ModuleCacheEntryInner::new(
+        "modules",
+        compiler,
+        &cache_config,
+    ));
+    module_entry
+        .get_data::<_, i32, i32>(1, |_| Ok(100))
+        .unwrap();
+
+    assert_eq!(cache_config.function_cache_hits(), 4);
+    assert_eq!(cache_config.function_cache_misses(), 3);
+    assert_eq!(cache_config.cache_hits(), 0);
+    assert_eq!(cache_config.cache_misses(), 1);
+}
@@ -4,7 +4,7 @@ use cranelift_codegen::ir::condcodes::*;
 use cranelift_codegen::ir::immediates::{Offset32, Uimm64};
 use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{AbiParam, ArgumentPurpose, Function, InstBuilder, Signature};
-use cranelift_codegen::isa::{self, TargetFrontendConfig};
+use cranelift_codegen::isa::{self, TargetFrontendConfig, TargetIsa};
 use cranelift_entity::{EntityRef, PrimaryMap};
 use cranelift_frontend::FunctionBuilder;
 use cranelift_wasm::{
@@ -121,15 +121,21 @@ pub struct FuncEnvironment<'module_environment> {
     pub(crate) offsets: VMOffsets,
 
     tunables: &'module_environment Tunables,
+
+    /// Whether table accesses are hardened against Spectre attacks, according to the
+    /// `enable_table_access_spectre_mitigation` flag; this covers the signature checks of
+    /// indirect calls too.
+    spectre_mitigation: bool,
 }
 
 impl<'module_environment> FuncEnvironment<'module_environment> {
     pub fn new(
-        target_config: TargetFrontendConfig,
+        isa: &dyn TargetIsa,
         module: &'module_environment Module,
         native_signatures: &'module_environment PrimaryMap<SignatureIndex, ir::Signature>,
         tunables: &'module_environment Tunables,
     ) -> Self {
+        let target_config = isa.frontend_config();
         let builtin_function_signatures = BuiltinFunctionSignatures::new(
             target_config.pointer_type(),
             match target_config.pointer_type() {
@@ -147,6 +153,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             builtin_function_signatures,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             tunables,
+            spectre_mitigation: isa.flags().enable_table_access_spectre_mitigation(),
         }
     }
 
@@ -1056,7 +1063,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
 
         // Dereference anyfunc pointer to get the function address.
         let mem_flags = ir::MemFlags::trusted();
-        let func_addr = pos.ins().load(
+        let mut func_addr = pos.ins().load(
             pointer_type,
             mem_flags,
             anyfunc_ptr,
@@ -1090,6 +1097,20 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                 // Check that they match.
                 let cmp = pos.ins().icmp(IntCC::Equal, callee_sig_id, caller_sig_id);
                 pos.ins().trapz(cmp, ir::TrapCode::BadSignature);
+
+                // Even if the check is mispredicted, never speculatively call a function with
+                // another signature: call a null pointer instead.
+                if self.spectre_mitigation {
+                    let zero = pos.ins().iconst(pointer_type, 0);
+                    let flags = pos.ins().ifcmp(callee_sig_id, caller_sig_id);
+                    func_addr = pos.ins().selectif_spectre_guard(
+                        pointer_type,
+                        IntCC::NotEqual,
+                        flags,
+                        zero,
+                        func_addr,
+                    );
+                }
             }
         }
 
@@ -1427,3 +1448,67 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Cranelift;
+    use cranelift_codegen::ir::{self, Opcode};
+    use cranelift_codegen::isa;
+    use cranelift_codegen::settings::{self, Configurable};
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::{wasmparser::WasmFeatures, DefinedFuncIndex};
+    use wasmtime_environ::{ModuleEnvironment, Tunables};
+
+    /// Translates the only function of `wat` with the given value of the
+    /// `enable_table_access_spectre_mitigation` setting.
+    fn translate(wat: &str, spectre_mitigation: bool) -> ir::Function {
+        let wasm = wat::parse_str(wat).unwrap();
+        let mut flags = settings::builder();
+        flags
+            .set(
+                "enable_table_access_spectre_mitigation",
+                &spectre_mitigation.to_string(),
+            )
+            .unwrap();
+        let isa = isa::lookup_by_name("x86_64")
+            .unwrap()
+            .finish(settings::Flags::new(flags));
+        let tunables = Tunables::default();
+        let mut translations =
+            ModuleEnvironment::new(isa.frontend_config(), &tunables, &WasmFeatures::default())
+                .translate(&wasm)
+                .unwrap();
+        let mut translation = translations.pop().unwrap();
+        let mut inputs = std::mem::take(&mut translation.function_body_inputs);
+        let index = DefinedFuncIndex::new(0);
+        Cranelift::default()
+            .translate_function(&translation, index, &mut inputs[index], &*isa, &tunables)
+            .unwrap()
+    }
+
+    fn count_spectre_guards(func: &ir::Function) -> usize {
+        func.layout
+            .blocks()
+            .flat_map(|block| func.layout.block_insts(block))
+            .filter(|&inst| func.dfg[inst].opcode() == Opcode::SelectifSpectreGuard)
+            .count()
+    }
+
+    #[test]
+    fn call_indirect_signature_guard() {
+        let wat = r#"
+            (module
+                (type $t (func (param i32) (result i32)))
+                (table 1 funcref)
+                (func (param i32) (result i32)
+                    (call_indirect (type $t) (local.get 0) (local.get 0))))
+        "#;
+
+        // The signature check is followed by a guard on the callee address.
+        let func = translate(wat, true);
+        assert_eq!(count_spectre_guards(&func), 1, "{}", func.display(None));
+
+        let func = translate(wat, false);
+        assert_eq!(count_spectre_guards(&func), 0, "{}", func.display(None));
+    }
+}
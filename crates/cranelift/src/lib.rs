@@ -340,27 +340,33 @@ impl Cranelift {
     }
 }
 
-impl Compiler for Cranelift {
-    fn compile_function(
+impl Cranelift {
+    /// Translates the body of the function at `func_index` to Cranelift IR,
+    /// without compiling it any further.
+    ///
+    /// This is the first half of `compile_function`; it's exposed separately
+    /// for execution strategies which work with the IR directly, such as
+    /// interpreting it.
+    pub fn translate_function(
         &self,
         translation: &ModuleTranslation<'_>,
         func_index: DefinedFuncIndex,
-        mut input: FunctionBodyData<'_>,
+        input: &mut FunctionBodyData<'_>,
         isa: &dyn isa::TargetIsa,
         tunables: &Tunables,
-    ) -> Result<CompiledFunction, CompileError> {
+    ) -> Result<ir::Function, CompileError> {
         let module = &translation.module;
         let func_index = module.func_index(func_index);
-        let mut context = Context::new();
-        context.func.name = get_func_name(func_index);
+        let mut func = ir::Function::new();
+        func.name = get_func_name(func_index);
         let sig_index = module.functions[func_index];
-        context.func.signature = translation.native_signatures[sig_index].clone();
+        func.signature = translation.native_signatures[sig_index].clone();
         if tunables.debug_info {
-            context.func.collect_debug_info();
+            func.collect_debug_info();
         }
 
         let mut func_env = FuncEnvironment::new(
-            isa.frontend_config(),
+            isa,
             module,
             &translation.native_signatures,
             tunables,
@@ -379,10 +385,8 @@ impl Compiler for Cranelift {
         //
         // For more information about interrupts and stack checks, see the
         // top of this file.
-        let vmctx = context
-            .func
-            .create_global_value(ir::GlobalValueData::VMContext);
-        let interrupts_ptr = context.func.create_global_value(ir::GlobalValueData::Load {
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        let interrupts_ptr = func.create_global_value(ir::GlobalValueData::Load {
             base: vmctx,
             offset: i32::try_from(func_env.offsets.vmctx_interrupts())
                 .unwrap()
@@ -390,7 +394,7 @@ impl Compiler for Cranelift {
             global_type: isa.pointer_type(),
             readonly: true,
         });
-        let stack_limit = context.func.create_global_value(ir::GlobalValueData::Load {
+        let stack_limit = func.create_global_value(ir::GlobalValueData::Load {
             base: interrupts_ptr,
             offset: i32::try_from(func_env.offsets.vminterrupts_stack_limit())
                 .unwrap()
@@ -398,18 +402,34 @@ impl Compiler for Cranelift {
             global_type: isa.pointer_type(),
             readonly: false,
         });
-        context.func.stack_limit = Some(stack_limit);
+        func.stack_limit = Some(stack_limit);
         let mut func_translator = self.take_translator();
         let result = func_translator.translate_body(
             &mut input.validator,
             input.body.clone(),
-            &mut context.func,
+            &mut func,
             &mut func_env,
         );
         if result.is_ok() {
             self.save_translator(func_translator);
         }
         result?;
+        Ok(func)
+    }
+}
+
+impl Compiler for Cranelift {
+    fn compile_function(
+        &self,
+        translation: &ModuleTranslation<'_>,
+        func_index: DefinedFuncIndex,
+        mut input: FunctionBodyData<'_>,
+        isa: &dyn isa::TargetIsa,
+        tunables: &Tunables,
+    ) -> Result<CompiledFunction, CompileError> {
+        let func = self.translate_function(translation, func_index, &mut input, isa, tunables)?;
+        let func_index = translation.module.func_index(func_index);
+        let mut context = Context::for_function(func);
 
         let mut code_buf: Vec<u8> = Vec::new();
         let mut reloc_sink = RelocSink::new(func_index);
@@ -453,6 +473,7 @@ impl Compiler for Cranelift {
             traps: trap_sink.traps,
             unwind_info,
             stack_maps: stack_map_sink.finish(),
+            stats: context.stats.unwrap_or_default(),
         })
     }
 }
@@ -39,7 +39,7 @@ fn relocate_dwarf_sections(
 ) -> Result<(), Error> {
     use object::read::{File, Object, ObjectSection, ObjectSymbol, RelocationTarget};
 
-    let obj = File::parse(bytes)?;
+    let obj = File::parse(&*bytes)?;
     let mut func_symbols = HashMap::new();
     for sym in obj.symbols() {
         match (sym.name(), sym.section_index()) {
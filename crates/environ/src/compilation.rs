@@ -2,7 +2,7 @@
 //! module.
 
 use crate::{FunctionAddressMap, FunctionBodyData, ModuleTranslation, Tunables};
-use cranelift_codegen::{binemit, ir, isa, isa::unwind::UnwindInfo};
+use cranelift_codegen::{binemit, ir, isa, isa::unwind::UnwindInfo, stats::CompileStats};
 use cranelift_entity::PrimaryMap;
 use cranelift_wasm::{DefinedFuncIndex, FuncIndex, WasmError};
 use serde::{Deserialize, Serialize};
@@ -30,6 +30,9 @@ pub struct CompiledFunction {
     pub stack_slots: ir::StackSlots,
     pub traps: Vec<TrapInformation>,
     pub stack_maps: Vec<StackMapInformation>,
+
+    /// Statistics about the compilation of this function.
+    pub stats: CompileStats,
 }
 
 /// A record of a relocation to perform.
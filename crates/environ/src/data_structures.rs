@@ -19,6 +19,10 @@ pub mod isa {
     };
 }
 
+pub mod stats {
+    pub use cranelift_codegen::stats::{CompileStats, PassStats};
+}
+
 pub mod entity {
     pub use cranelift_entity::{packed_option, BoxedSlice, EntityRef, PrimaryMap};
 }
@@ -0,0 +1,18 @@
+use std::env;
+
+fn main() {
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap();
+    let file = match (family.as_str(), arch.as_str()) {
+        ("windows", _) => "src/arch/windows.c",
+        (_, "x86_64") => "src/arch/x86_64.S",
+        (_, "aarch64") => "src/arch/aarch64.S",
+        _ => panic!("fibers are not supported on the `{}` architecture", arch),
+    };
+    println!("cargo:rerun-if-changed={}", file);
+    println!("cargo:rerun-if-changed=src/arch/header.h");
+    cc::Build::new()
+        .warnings(true)
+        .file(file)
+        .compile("wasmtime-fiber");
+}
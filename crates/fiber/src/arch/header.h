@@ -0,0 +1,20 @@
+// Helpers for declaring the assembly functions in this directory portably
+// across the object formats of the Unix platforms that we support.
+
+#ifdef __APPLE__
+#define GLOBL(fnname) .globl _##fnname
+#define HIDDEN(fnname) .private_extern _##fnname
+#define TYPE(fnname)
+#define FUNCTION(fnname) _##fnname
+#define SIZE(fnname)
+#define FOOTER
+#else
+#define GLOBL(fnname) .globl fnname
+#define HIDDEN(fnname) .hidden fnname
+#define TYPE(fnname) .type fnname,@function
+#define FUNCTION(fnname) fnname
+#define SIZE(fnname) .size fnname,.-fnname
+// Mark the stack as non-executable, which is otherwise the default for
+// assembly files on Linux.
+#define FOOTER .section .note.GNU-stack,"",%progbits
+#endif
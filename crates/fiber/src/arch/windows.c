@@ -0,0 +1,7 @@
+#include <windows.h>
+
+// `GetCurrentFiber` is an inline function in the Windows headers, so it's
+// wrapped here to be callable from Rust.
+LPVOID wasmtime_fiber_get_current() {
+  return GetCurrentFiber();
+}
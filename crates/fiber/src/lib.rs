@@ -0,0 +1,249 @@
+//! Native stack switching for Wasmtime.
+//!
+//! A [`Fiber`] runs a closure on a separate native stack, and that closure
+//! can [`Suspend::suspend`] itself at any point to transfer control back to
+//! whoever last called [`Fiber::resume`]. This is what allows WebAssembly,
+//! which can't be turned into a Rust future itself, to be paused in the middle
+//! of an async host function.
+
+#![deny(missing_docs)]
+
+use std::any::Any;
+use std::cell::Cell;
+use std::io;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+use unix as imp;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+use windows as imp;
+
+/// A closure running on its own native stack.
+///
+/// `Resume` is the type of the values passed in each time the fiber is
+/// resumed, `Yield` is the type of the values it hands back each time it
+/// suspends, and `Return` is the type of its final result.
+pub struct Fiber<'a, Resume, Yield, Return> {
+    inner: imp::Fiber,
+    done: Cell<bool>,
+    _phantom: PhantomData<&'a (Resume, Yield, Return)>,
+}
+
+/// A handle given to the closure running on a [`Fiber`], used to suspend it.
+pub struct Suspend<Resume, Yield, Return> {
+    inner: imp::Suspend,
+    _phantom: PhantomData<(Resume, Yield, Return)>,
+}
+
+/// The message passed between the two sides of a stack switch, stored on the
+/// stack of whoever called `Fiber::resume`.
+enum RunResult<Resume, Yield, Return> {
+    Executing,
+    Resuming(Resume),
+    Yield(Yield),
+    Returned(Return),
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl<'a, Resume, Yield, Return> Fiber<'a, Resume, Yield, Return> {
+    /// Creates a new fiber which will execute `func` on a new native stack of
+    /// at least `stack_size` bytes.
+    ///
+    /// Nothing is executed until the fiber is first resumed, at which point
+    /// `func` is called with the value given to [`Fiber::resume`].
+    pub fn new(
+        stack_size: usize,
+        func: impl FnOnce(Resume, &Suspend<Resume, Yield, Return>) -> Return + 'a,
+    ) -> io::Result<Self> {
+        Ok(Fiber {
+            inner: imp::Fiber::new(stack_size, func)?,
+            done: Cell::new(false),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Resumes execution of this fiber with `val`, running it until it either
+    /// suspends or finishes.
+    ///
+    /// Returns `Ok` with the closure's result once it has finished, or `Err`
+    /// with the value it was suspended with otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this fiber has already finished. If the closure itself
+    /// panics then the panic is propagated to this caller, and the fiber is
+    /// considered finished.
+    pub fn resume(&self, val: Resume) -> Result<Return, Yield> {
+        assert!(!self.done.replace(true), "cannot resume a finished fiber");
+        let result = Cell::new(RunResult::Resuming(val));
+        self.inner.resume(&result);
+        match result.into_inner() {
+            RunResult::Resuming(_) | RunResult::Executing => unreachable!(),
+            RunResult::Yield(y) => {
+                self.done.set(false);
+                Err(y)
+            }
+            RunResult::Returned(r) => Ok(r),
+            RunResult::Panicked(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Returns whether this fiber has finished executing.
+    pub fn done(&self) -> bool {
+        self.done.get()
+    }
+}
+
+impl<A, B, C> Drop for Fiber<'_, A, B, C> {
+    fn drop(&mut self) {
+        // Whatever is still live on a suspended fiber's stack is leaked rather
+        // than dropped, so owners are expected to run their fibers to
+        // completion.
+        debug_assert!(self.done.get(), "fiber dropped without finishing");
+    }
+}
+
+impl<Resume, Yield, Return> Suspend<Resume, Yield, Return> {
+    /// Suspends the currently running fiber, handing `value` back to the
+    /// caller of [`Fiber::resume`].
+    ///
+    /// Returns the value given to [`Fiber::resume`] once the fiber is resumed
+    /// again.
+    pub fn suspend(&self, value: Yield) -> Resume {
+        self.inner
+            .switch::<Resume, Yield, Return>(RunResult::Yield(value))
+    }
+
+    /// Runs `func` on the fiber's stack, and then switches away for the last
+    /// time with its result.
+    fn execute(
+        inner: imp::Suspend,
+        initial: Resume,
+        func: impl FnOnce(Resume, &Suspend<Resume, Yield, Return>) -> Return,
+    ) {
+        let suspend = Suspend {
+            inner,
+            _phantom: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| func(initial, &suspend)));
+        suspend.inner.switch::<Resume, Yield, Return>(match result {
+            Ok(result) => RunResult::Returned(result),
+            Err(panic) => RunResult::Panicked(panic),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fiber;
+    use std::cell::Cell;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[test]
+    fn small_stacks() {
+        Fiber::<(), (), ()>::new(0, |_, _| {})
+            .unwrap()
+            .resume(())
+            .unwrap();
+        Fiber::<(), (), ()>::new(1, |_, _| {})
+            .unwrap()
+            .resume(())
+            .unwrap();
+    }
+
+    #[test]
+    fn smoke() {
+        let hit = Rc::new(Cell::new(false));
+        let hit2 = hit.clone();
+        let fiber = Fiber::<(), (), ()>::new(1024 * 1024, move |_, _| {
+            hit2.set(true);
+        })
+        .unwrap();
+        assert!(!hit.get());
+        fiber.resume(()).unwrap();
+        assert!(hit.get());
+        assert!(fiber.done());
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        let hit = Rc::new(Cell::new(false));
+        let hit2 = hit.clone();
+        let fiber = Fiber::<(), (), ()>::new(1024 * 1024, move |_, s| {
+            s.suspend(());
+            hit2.set(true);
+            s.suspend(());
+        })
+        .unwrap();
+        assert!(!hit.get());
+        assert!(fiber.resume(()).is_err());
+        assert!(!hit.get());
+        assert!(fiber.resume(()).is_err());
+        assert!(hit.get());
+        assert!(fiber.resume(()).is_ok());
+        assert!(fiber.done());
+    }
+
+    #[test]
+    fn values_in_and_out() {
+        let fiber = Fiber::<i32, String, u64>::new(1024 * 1024, |first, s| {
+            assert_eq!(first, 1);
+            let second = s.suspend(format!("got {}", first));
+            assert_eq!(second, 2);
+            let third = s.suspend(format!("got {}", second));
+            u64::from(third as u32) << 32
+        })
+        .unwrap();
+        assert_eq!(fiber.resume(1).unwrap_err(), "got 1");
+        assert_eq!(fiber.resume(2).unwrap_err(), "got 2");
+        assert_eq!(fiber.resume(3).unwrap(), 3 << 32);
+    }
+
+    #[test]
+    fn panics_propagated() {
+        let a = Rc::new(Cell::new(false));
+        let b = SetOnDrop(a.clone());
+        let fiber = Fiber::<(), (), ()>::new(1024 * 1024, move |_, _s| {
+            let _b = &b;
+            panic!("oh no");
+        })
+        .unwrap();
+        let err = panic::catch_unwind(AssertUnwindSafe(|| fiber.resume(()))).unwrap_err();
+        assert_eq!(err.downcast_ref::<&str>(), Some(&"oh no"));
+        assert!(fiber.done());
+        assert!(a.get(), "closure's captures should be dropped by the panic");
+
+        struct SetOnDrop(Rc<Cell<bool>>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+    }
+
+    #[test]
+    fn nested() {
+        let outer = Fiber::<(), u32, ()>::new(1024 * 1024, |_, s| {
+            let inner = Fiber::<(), u32, ()>::new(1024 * 1024, |_, s| {
+                s.suspend(1);
+                s.suspend(2);
+            })
+            .unwrap();
+            while let Err(n) = inner.resume(()) {
+                s.suspend(n * 10);
+            }
+        })
+        .unwrap();
+        assert_eq!(outer.resume(()).unwrap_err(), 10);
+        assert_eq!(outer.resume(()).unwrap_err(), 20);
+        assert!(outer.resume(()).is_ok());
+    }
+}
@@ -0,0 +1,153 @@
+//! Fibers for Unix platforms, switching stacks with the assembly in `arch`.
+//!
+//! Each fiber owns an `mmap`'d stack with a guard page at its low end. The
+//! top 16 bytes of the stack are reserved for communication between the two
+//! sides of a switch:
+//!
+//! ```text
+//! 0xB000 +-----------------------+   <- top_of_stack
+//! 0xAff8 | &Cell<RunResult>      |
+//! 0xAff0 | saved stack pointer   |
+//! 0xAfe8 | ...                   |
+//!        ~ ...                   ~
+//! 0x1000 +-----------------------+
+//! 0x0000 | guard page            |
+//!        +-----------------------+
+//! ```
+//!
+//! The saved stack pointer is the one to switch to on the next call to
+//! `wasmtime_fiber_switch`: while the fiber is suspended it's the fiber's own,
+//! and while it's running it's that of whoever resumed it. The `RunResult`
+//! pointer is written before each resumption and points to a value on the
+//! resumer's stack, through which values are passed in both directions.
+
+use crate::RunResult;
+use std::cell::Cell;
+use std::io;
+use std::ptr;
+
+pub struct Fiber {
+    mmap: *mut libc::c_void,
+    mmap_len: usize,
+}
+
+pub struct Suspend {
+    top_of_stack: *mut u8,
+}
+
+extern "C" {
+    fn wasmtime_fiber_init(
+        top_of_stack: *mut u8,
+        entry: extern "C" fn(*mut u8, *mut u8),
+        entry_arg0: *mut u8,
+    );
+    fn wasmtime_fiber_switch(top_of_stack: *mut u8);
+}
+
+extern "C" fn fiber_start<F, A, B, C>(arg0: *mut u8, top_of_stack: *mut u8)
+where
+    F: FnOnce(A, &super::Suspend<A, B, C>) -> C,
+{
+    unsafe {
+        let inner = Suspend { top_of_stack };
+        let initial = inner.take_resume::<A, B, C>();
+        super::Suspend::<A, B, C>::execute(inner, initial, Box::from_raw(arg0.cast::<F>()))
+    }
+}
+
+impl Fiber {
+    pub fn new<F, A, B, C>(stack_size: usize, func: F) -> io::Result<Fiber>
+    where
+        F: FnOnce(A, &super::Suspend<A, B, C>) -> C,
+    {
+        let fiber = Fiber::alloc_with_stack(stack_size)?;
+        unsafe {
+            let data = Box::into_raw(Box::new(func)).cast();
+            wasmtime_fiber_init(fiber.top_of_stack(), fiber_start::<F, A, B, C>, data);
+        }
+        Ok(fiber)
+    }
+
+    fn alloc_with_stack(stack_size: usize) -> io::Result<Fiber> {
+        unsafe {
+            // Round the requested size up to whole pages, with at least one
+            // page of usable stack, and then add a guard page beneath it.
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let stack_size = if stack_size == 0 {
+                page_size
+            } else {
+                (stack_size + (page_size - 1)) & !(page_size - 1)
+            };
+            let mmap_len = stack_size + page_size;
+            let mmap = libc::mmap(
+                ptr::null_mut(),
+                mmap_len,
+                libc::PROT_NONE,
+                libc::MAP_ANON | libc::MAP_PRIVATE,
+                -1,
+                0,
+            );
+            if mmap == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let fiber = Fiber { mmap, mmap_len };
+            let res = libc::mprotect(
+                mmap.cast::<u8>().add(page_size).cast(),
+                stack_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+            );
+            if res != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(fiber)
+        }
+    }
+
+    pub(crate) fn resume<A, B, C>(&self, result: &Cell<RunResult<A, B, C>>) {
+        unsafe {
+            let addr = self.top_of_stack().cast::<usize>().offset(-1);
+            addr.write(result as *const _ as usize);
+
+            wasmtime_fiber_switch(self.top_of_stack());
+
+            // Null this out to catch any use of the result after this returns.
+            addr.write(0);
+        }
+    }
+
+    unsafe fn top_of_stack(&self) -> *mut u8 {
+        self.mmap.cast::<u8>().add(self.mmap_len)
+    }
+}
+
+impl Drop for Fiber {
+    fn drop(&mut self) {
+        unsafe {
+            let ret = libc::munmap(self.mmap, self.mmap_len);
+            debug_assert!(ret == 0);
+        }
+    }
+}
+
+impl Suspend {
+    pub(crate) fn switch<A, B, C>(&self, result: RunResult<A, B, C>) -> A {
+        unsafe {
+            (*self.result_location::<A, B, C>()).set(result);
+            wasmtime_fiber_switch(self.top_of_stack);
+            self.take_resume::<A, B, C>()
+        }
+    }
+
+    unsafe fn take_resume<A, B, C>(&self) -> A {
+        match (*self.result_location::<A, B, C>()).replace(RunResult::Executing) {
+            RunResult::Resuming(val) => val,
+            _ => panic!("not in resuming state"),
+        }
+    }
+
+    unsafe fn result_location<A, B, C>(&self) -> *const Cell<RunResult<A, B, C>> {
+        let ret = self.top_of_stack.cast::<*const u8>().offset(-1).read();
+        assert!(!ret.is_null());
+        ret.cast()
+    }
+}
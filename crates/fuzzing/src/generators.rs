@@ -28,8 +28,13 @@ impl DifferentialConfig {
         let mut config = crate::fuzz_default_config(match self.strategy {
             DifferentialStrategy::Cranelift => wasmtime::Strategy::Cranelift,
             DifferentialStrategy::Lightbeam => wasmtime::Strategy::Lightbeam,
+            DifferentialStrategy::Interpreter => wasmtime::Strategy::Interpreter,
         })?;
         config.cranelift_opt_level(self.opt_level.to_wasmtime());
+        if let DifferentialStrategy::Interpreter = self.strategy {
+            // The interpreter doesn't support reference types.
+            config.wasm_reference_types(false);
+        }
         Ok(config)
     }
 }
@@ -38,6 +43,7 @@ impl DifferentialConfig {
 enum DifferentialStrategy {
     Cranelift,
     Lightbeam,
+    Interpreter,
 }
 
 #[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Hash)]
@@ -8,10 +8,18 @@ use rayon::prelude::*;
 use std::hash::{Hash, Hasher};
 use std::mem;
 use wasmparser::WasmFeatures;
+#[cfg(feature = "cache")]
+use wasmtime_cache::{CacheConfig, FunctionCacheEntry};
 use wasmtime_debug::{emit_dwarf, DwarfSection};
-use wasmtime_environ::entity::EntityRef;
+use wasmtime_environ::entity::{EntityRef, PrimaryMap};
+#[cfg(feature = "cache")]
+use wasmtime_environ::ir;
 use wasmtime_environ::isa::{TargetFrontendConfig, TargetIsa};
-use wasmtime_environ::wasm::{DefinedMemoryIndex, MemoryIndex};
+use wasmtime_environ::wasm::{DefinedFuncIndex, DefinedMemoryIndex, MemoryIndex};
+#[cfg(feature = "cache")]
+use wasmtime_environ::wasm::{SignatureIndex, WasmError};
+#[cfg(feature = "cache")]
+use wasmtime_environ::{CompileError, CompiledFunction, FunctionBodyData, ModuleType};
 use wasmtime_environ::{
     CompiledFunctions, Compiler as EnvCompiler, DebugInfoData, Module, ModuleMemoryOffset,
     ModuleTranslation, Tunables, VMOffsets,
@@ -29,6 +37,12 @@ pub enum CompilationStrategy {
     /// Compile all functions with Lightbeam.
     #[cfg(feature = "lightbeam")]
     Lightbeam,
+
+    /// Translate all functions to Cranelift IR but interpret it with
+    /// `cranelift-interpreter` rather than compiling it to native code. No
+    /// executable memory is allocated for the resulting module.
+    #[cfg(feature = "interpreter")]
+    Interpreter,
 }
 
 /// A WebAssembly code JIT compiler.
@@ -45,6 +59,8 @@ pub struct Compiler {
     strategy: CompilationStrategy,
     tunables: Tunables,
     features: WasmFeatures,
+    #[cfg(feature = "cache")]
+    cache_config: CacheConfig,
 }
 
 impl Compiler {
@@ -62,13 +78,30 @@ impl Compiler {
                 CompilationStrategy::Auto | CompilationStrategy::Cranelift => {
                     Box::new(wasmtime_cranelift::Cranelift::default())
                 }
+                // Interpreted functions are still translated by Cranelift,
+                // see `interpreter::compile`.
+                #[cfg(feature = "interpreter")]
+                CompilationStrategy::Interpreter => {
+                    Box::new(wasmtime_cranelift::Cranelift::default())
+                }
                 #[cfg(feature = "lightbeam")]
                 CompilationStrategy::Lightbeam => Box::new(wasmtime_lightbeam::Lightbeam),
             },
             tunables,
             features,
+            #[cfg(feature = "cache")]
+            cache_config: CacheConfig::new_cache_disabled(),
         }
     }
+
+    /// Set the configuration of the cache in which compiled functions are
+    /// looked up before compiling them.
+    ///
+    /// By default functions aren't cached.
+    #[cfg(feature = "cache")]
+    pub fn set_cache_config(&mut self, cache_config: CacheConfig) {
+        self.cache_config = cache_config;
+    }
 }
 
 fn _assert_compiler_send_sync() {
@@ -100,6 +133,9 @@ pub struct Compilation {
     pub obj: Object,
     pub unwind_info: Vec<ObjectUnwindInfo>,
     pub funcs: CompiledFunctions,
+    /// The Cranelift IR, in textual form, of each function which is to be
+    /// interpreted rather than run natively.
+    pub clif: Option<PrimaryMap<DefinedFuncIndex, String>>,
 }
 
 impl Compiler {
@@ -123,18 +159,45 @@ impl Compiler {
         &self.features
     }
 
+    /// Return the compilation strategy in use by this engine.
+    pub fn strategy(&self) -> CompilationStrategy {
+        self.strategy
+    }
+
     /// Compile the given function bodies.
     pub fn compile<'data>(
         &self,
         translation: &mut ModuleTranslation,
     ) -> Result<Compilation, SetupError> {
+        #[cfg(feature = "interpreter")]
+        {
+            if let CompilationStrategy::Interpreter = self.strategy {
+                return crate::interpreter::compile(self, translation);
+            }
+        }
+
         let functions = mem::take(&mut translation.function_body_inputs);
         let functions = functions.into_iter().collect::<Vec<_>>();
+        #[cfg(feature = "cache")]
+        let cache_entry = FunctionCacheEntry::new(
+            "wasmtime",
+            &self.cache_config,
+            FunctionCacheContext {
+                compiler: self,
+                module: &translation.module,
+            },
+        );
+        #[cfg(feature = "cache")]
+        let compile = |(index, func): (DefinedFuncIndex, FunctionBodyData)| {
+            self.compile_function_cached(&cache_entry, translation, index, func)
+        };
+        #[cfg(not(feature = "cache"))]
+        let compile = |(index, func)| {
+            self.compiler
+                .compile_function(translation, index, func, &*self.isa, &self.tunables)
+        };
         let funcs = maybe_parallel!(functions.(into_iter | into_par_iter))
-            .map(|(index, func)| {
-                self.compiler
-                    .compile_function(translation, index, func, &*self.isa, &self.tunables)
-            })
+            .map(compile)
             .collect::<Result<Vec<_>, _>>()?
             .into_iter()
             .collect::<CompiledFunctions>();
@@ -156,8 +219,151 @@ impl Compiler {
             obj,
             unwind_info,
             funcs,
+            clif: None,
         })
     }
+
+    /// Compile the given function, or get its compiled code from the cache.
+    ///
+    /// Functions are looked up by their body and signature, so the positions
+    /// in the wasm module recorded in their address map are made relative to
+    /// the start of the body in the cache. Bodies found in the cache are
+    /// still validated, since whether they are valid depends on parts of the
+    /// module which don't change the compiled code.
+    #[cfg(feature = "cache")]
+    fn compile_function_cached(
+        &self,
+        cache_entry: &FunctionCacheEntry,
+        translation: &ModuleTranslation,
+        index: DefinedFuncIndex,
+        func: FunctionBodyData,
+    ) -> Result<CompiledFunction, CompileError> {
+        let mut reader = func.body.get_binary_reader();
+        let body_offset = reader.original_position() as u32;
+        let body = reader
+            .read_bytes(reader.bytes_remaining())
+            .map_err(WasmError::from)?;
+        let module = &translation.module;
+        let signature = &module.signatures[module.functions[module.func_index(index)]];
+
+        let mut func = Some(func);
+        let mut compiled = cache_entry.get_data((body, signature), |_| {
+            let func = func.take().unwrap();
+            let mut compiled = self.compiler.compile_function(
+                translation,
+                index,
+                func,
+                &*self.isa,
+                &self.tunables,
+            )?;
+            rebase_srclocs(&mut compiled, |offset| offset - body_offset);
+            Ok::<_, CompileError>(compiled)
+        })?;
+        if let Some(func) = func {
+            validate_function(func)?;
+        }
+        rebase_srclocs(&mut compiled, |offset| offset + body_offset);
+        Ok(compiled)
+    }
+}
+
+/// The context shared by the functions of a module which their compiled code
+/// depends on.
+#[cfg(feature = "cache")]
+struct FunctionCacheContext<'a> {
+    compiler: &'a Compiler,
+    module: &'a Module,
+}
+
+#[cfg(feature = "cache")]
+impl Hash for FunctionCacheContext<'_> {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        let Module {
+            signatures,
+            types,
+            num_imported_funcs,
+            num_imported_tables,
+            num_imported_memories,
+            num_imported_globals,
+            functions,
+            table_plans,
+            memory_plans,
+            globals,
+            // Names, exports, segments, and nested modules and instances
+            // aren't used when compiling functions.
+            id: _,
+            name: _,
+            imports: _,
+            exports: _,
+            start_func: _,
+            table_elements: _,
+            passive_elements: _,
+            passive_data: _,
+            func_names: _,
+            instances: _,
+            modules: _,
+        } = self.module;
+
+        self.compiler.hash(hasher);
+        signatures.hash(hasher);
+        types.len().hash(hasher);
+        for ty in types.values() {
+            match ty {
+                ModuleType::Function(sig) => Some(sig).hash(hasher),
+                ModuleType::Module { .. } | ModuleType::Instance { .. } => {
+                    None::<&SignatureIndex>.hash(hasher)
+                }
+            }
+        }
+        num_imported_funcs.hash(hasher);
+        num_imported_tables.hash(hasher);
+        num_imported_memories.hash(hasher);
+        num_imported_globals.hash(hasher);
+        functions.hash(hasher);
+        table_plans.hash(hasher);
+        memory_plans.hash(hasher);
+        globals.hash(hasher);
+    }
+}
+
+/// Apply `rebase` to the wasm offsets recorded in the address map of `func`.
+#[cfg(feature = "cache")]
+fn rebase_srclocs(func: &mut CompiledFunction, rebase: impl Fn(u32) -> u32) {
+    let rebase_srcloc = |srcloc: &mut ir::SourceLoc| {
+        if !srcloc.is_default() {
+            *srcloc = ir::SourceLoc::new(rebase(srcloc.bits()));
+        }
+    };
+    let address_map = &mut func.address_map;
+    for inst in address_map.instructions.iter_mut() {
+        rebase_srcloc(&mut inst.srcloc);
+    }
+    rebase_srcloc(&mut address_map.start_srcloc);
+    rebase_srcloc(&mut address_map.end_srcloc);
+}
+
+/// Validate the body of a function without translating it.
+#[cfg(feature = "cache")]
+fn validate_function(func: FunctionBodyData) -> Result<(), WasmError> {
+    let FunctionBodyData {
+        body,
+        mut validator,
+    } = func;
+    let mut reader = body.get_binary_reader();
+    let local_count = reader.read_var_u32()?;
+    for _ in 0..local_count {
+        let pos = reader.original_position();
+        let count = reader.read_var_u32()?;
+        let ty = reader.read_type()?;
+        validator.define_locals(pos, count, ty)?;
+    }
+    while !reader.eof() {
+        let pos = reader.original_position();
+        let op = reader.read_operator()?;
+        validator.op(pos, &op)?;
+    }
+    validator.finish(reader.original_position())?;
+    Ok(())
 }
 
 impl Hash for Compiler {
@@ -168,6 +374,8 @@ impl Hash for Compiler {
             isa,
             tunables,
             features,
+            #[cfg(feature = "cache")]
+                cache_config: _,
         } = self;
 
         // Hash compiler's flags: compilation strategy, isa, frontend config,
@@ -12,11 +12,14 @@ use object::File as ObjectFile;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::any::Any;
+use std::ptr;
+use std::rc::Rc;
 use std::sync::Arc;
 use thiserror::Error;
 use wasmtime_debug::create_gdbjit_image;
 use wasmtime_environ::entity::PrimaryMap;
 use wasmtime_environ::isa::TargetIsa;
+use wasmtime_environ::stats::CompileStats;
 use wasmtime_environ::wasm::{DefinedFuncIndex, SignatureIndex};
 use wasmtime_environ::{
     CompileError, DataInitializer, DataInitializerLocation, FunctionAddressMap, Module,
@@ -24,9 +27,9 @@ use wasmtime_environ::{
 };
 use wasmtime_profiling::ProfilingAgent;
 use wasmtime_runtime::{
-    GdbJitImageRegistration, Imports, InstanceHandle, InstantiationError, RuntimeMemoryCreator,
-    StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody, VMInterrupts,
-    VMSharedSignatureIndex, VMTrampoline,
+    GdbJitImageRegistration, Imports, InstanceHandle, InstantiationError, ResourceLimiter,
+    RuntimeMemoryCreator, StackMapRegistry, VMExternRefActivationsTable, VMFunctionBody,
+    VMInterrupts, VMSharedSignatureIndex, VMTrampoline,
 };
 
 /// An error condition while setting up a wasm instance, be it validation,
@@ -69,6 +72,9 @@ pub struct CompilationArtifacts {
     /// Descriptions of compiled functions
     funcs: PrimaryMap<DefinedFuncIndex, FunctionInfo>,
 
+    /// Cranelift IR of the functions, if they are to be interpreted.
+    clif: Option<PrimaryMap<DefinedFuncIndex, String>>,
+
     /// Debug info presence flags.
     debug_info: bool,
 }
@@ -93,6 +99,7 @@ impl CompilationArtifacts {
                     obj,
                     unwind_info,
                     funcs,
+                    clif,
                 } = compiler.compile(&mut translation)?;
 
                 let ModuleTranslation {
@@ -124,9 +131,12 @@ impl CompilationArtifacts {
                             stack_maps: func.stack_maps,
                             traps: func.traps,
                             address_map: func.address_map,
+                            stats: func.stats,
                         })
                         .collect(),
-                    debug_info: compiler.tunables().debug_info,
+                    // Interpreted functions have no native code to describe.
+                    debug_info: compiler.tunables().debug_info && clif.is_none(),
+                    clif,
                 })
             })
             .collect::<Result<Vec<_>, SetupError>>()
@@ -140,6 +150,7 @@ struct FunctionInfo {
     traps: Vec<TrapInformation>,
     address_map: FunctionAddressMap,
     stack_maps: Vec<StackMapInformation>,
+    stats: CompileStats,
 }
 
 unsafe impl Send for FinishedFunctions {}
@@ -150,6 +161,9 @@ pub struct ModuleCode {
     code_memory: CodeMemory,
     #[allow(dead_code)]
     dbg_jit_registration: Option<GdbJitImageRegistration>,
+    #[cfg(feature = "interpreter")]
+    #[allow(dead_code)]
+    interpreted: Option<Box<crate::interpreter::InterpretedCode>>,
 }
 
 /// A compiled wasm module, ready to be instantiated.
@@ -181,19 +195,49 @@ impl CompiledModule {
         profiler: &dyn ProfilingAgent,
     ) -> Result<Self, SetupError> {
         // Allocate all of the compiled functions into executable memory,
-        // copying over their contents.
-        let (code_memory, code_range, finished_functions, trampolines) = build_code_memory(
-            isa,
-            &artifacts.obj,
-            &artifacts.module,
-            &artifacts.unwind_info,
-        )
-        .map_err(|message| {
-            SetupError::Instantiate(InstantiationError::Resource(format!(
-                "failed to build code memory for functions: {}",
-                message
-            )))
-        })?;
+        // copying over their contents. Interpreted modules have no native
+        // code at all; their functions are filled in below.
+        #[allow(unused_mut)]
+        let (code_memory, code_range, mut finished_functions, trampolines) =
+            if artifacts.clif.is_some() {
+                (
+                    CodeMemory::new(),
+                    (ptr::null(), 0),
+                    PrimaryMap::new(),
+                    PrimaryMap::new(),
+                )
+            } else {
+                build_code_memory(
+                    isa,
+                    &artifacts.obj,
+                    &artifacts.module,
+                    &artifacts.unwind_info,
+                )
+                .map_err(|message| {
+                    SetupError::Instantiate(InstantiationError::Resource(format!(
+                        "failed to build code memory for functions: {}",
+                        message
+                    )))
+                })?
+            };
+
+        #[cfg(feature = "interpreter")]
+        let interpreted = match &artifacts.clif {
+            Some(clif) => {
+                let code = crate::interpreter::load(clif)?;
+                finished_functions = code.finished_functions();
+                Some(code)
+            }
+            None => None,
+        };
+        #[cfg(not(feature = "interpreter"))]
+        {
+            if artifacts.clif.is_some() {
+                return Err(SetupError::Instantiate(InstantiationError::Resource(
+                    "interpreter support wasn't enabled at compile time".to_string(),
+                )));
+            }
+        }
 
         // Register GDB JIT images; initialize profiler and load the wasm module.
         let dbg_jit_registration = if artifacts.debug_info {
@@ -219,6 +263,8 @@ impl CompiledModule {
             code: Arc::new(ModuleCode {
                 code_memory,
                 dbg_jit_registration,
+                #[cfg(feature = "interpreter")]
+                interpreted,
             }),
             finished_functions,
             trampolines,
@@ -243,6 +289,7 @@ impl CompiledModule {
         host_state: Box<dyn Any>,
         externref_activations_table: *mut VMExternRefActivationsTable,
         stack_map_registry: *mut StackMapRegistry,
+        limiter: Option<Rc<dyn ResourceLimiter>>,
     ) -> Result<InstanceHandle, InstantiationError> {
         InstanceHandle::new(
             self.module.clone(),
@@ -254,6 +301,7 @@ impl CompiledModule {
             interrupts,
             externref_activations_table,
             stack_map_registry,
+            limiter,
         )
     }
     /// Extracts `CompilationArtifacts` from the compiled module.
@@ -289,6 +337,9 @@ impl CompiledModule {
     }
 
     /// Returns the per-signature trampolines for this module.
+    ///
+    /// This is empty for interpreted modules, whose functions are all called
+    /// through `interpreted_trampoline` instead.
     pub fn trampolines(&self) -> &PrimaryMap<SignatureIndex, VMTrampoline> {
         &self.trampolines
     }
@@ -327,6 +378,16 @@ impl CompiledModule {
             .map(|((i, alloc), func)| (i, *alloc, func.traps.as_slice(), &func.address_map))
     }
 
+    /// Returns the compilation statistics of all functions defined in this
+    /// module, added up.
+    pub fn compilation_stats(&self) -> CompileStats {
+        let mut stats = CompileStats::default();
+        for func in self.artifacts.funcs.values() {
+            stats.add(&func.stats);
+        }
+        stats
+    }
+
     /// Returns all ranges convered by JIT code.
     pub fn jit_code_ranges<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
         self.code.code_memory.published_ranges()
@@ -0,0 +1,656 @@
+//! Support for running functions with `cranelift-interpreter`.
+//!
+//! With `CompilationStrategy::Interpreter` functions are translated to
+//! Cranelift IR exactly as they are for compilation, but the IR is kept (in
+//! textual form, so that compilation artifacts remain serializable) and
+//! interpreted whenever the function is called. No native code is generated
+//! or loaded for such a module, not even trampolines, so it can run where
+//! executable memory can't be allocated at all.
+//!
+//! Instead of the address of its code, each interpreted function is referred
+//! to -- by tables, imports, exports and `VMCallerCheckedAnyfunc`s -- by the
+//! address of an `InterpretedFunction` describing it. These addresses are
+//! registered globally, so that `interpreted_trampoline` can tell interpreted
+//! functions from native ones, and calling one from the host goes through
+//! that plain Rust trampoline. Interpreted code calls functions of other
+//! interpreted modules directly, and native functions, such as host functions
+//! and builtins (e.g. `memory.grow`), through `call_native_function`.
+//!
+//! Memories are accessed directly through their base pointers, so it's the
+//! explicit bounds checks in the IR which keep accesses in bounds: modules
+//! must use dynamic memories without guard pages.
+
+use crate::compiler::{Compilation, Compiler};
+use crate::instantiate::SetupError;
+use cranelift_codegen::data_value::DataValue;
+use cranelift_codegen::ir;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_interpreter::frame::Frame;
+use cranelift_interpreter::interpreter::Interpreter;
+use cranelift_interpreter::state::{MemoryError, State};
+use cranelift_interpreter::step::{ControlFlow, CraneliftTrap};
+use cranelift_reader::parse_functions;
+use lazy_static::lazy_static;
+#[cfg(feature = "parallel-compilation")]
+use rayon::prelude::*;
+use smallvec::SmallVec;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::RwLock;
+use wasmtime_cranelift::Cranelift;
+use wasmtime_environ::entity::{EntityRef, PrimaryMap};
+use wasmtime_environ::wasm::DefinedFuncIndex;
+use wasmtime_environ::{
+    CompileError, CompiledFunction, CompiledFunctions, ModuleTranslation, INTERRUPTED,
+};
+use wasmtime_obj::{ObjectBuilder, ObjectBuilderTarget};
+use wasmtime_runtime::{
+    raise_lib_trap, resume_panic, Trap, VMContext, VMFunctionBody, VMTrampoline,
+};
+
+/// A conservative estimate of the native stack used by each nested
+/// interpreted call. Together with the stack limit that compiled code checks
+/// in its prologue this bounds how deeply the interpreter may recurse.
+const INTERPRETED_FRAME_SIZE: usize = 4 * 1024;
+
+lazy_static! {
+    /// The address ranges of the `InterpretedFunction`s of every loaded
+    /// module: the end of each range keyed by its start.
+    static ref INTERPRETED_FUNCTIONS: RwLock<BTreeMap<usize, usize>> =
+        RwLock::new(BTreeMap::new());
+}
+
+/// Translates the functions of a module to Cranelift IR for interpretation.
+pub(crate) fn compile(
+    compiler: &Compiler,
+    translation: &mut ModuleTranslation,
+) -> Result<Compilation, SetupError> {
+    let cranelift = Cranelift::default();
+    let functions = mem::take(&mut translation.function_body_inputs);
+    let functions = functions.into_iter().collect::<Vec<_>>();
+    let clif = maybe_parallel!(functions.(into_iter | into_par_iter))
+        .map(|(index, mut input)| {
+            let func = cranelift.translate_function(
+                translation,
+                index,
+                &mut input,
+                compiler.isa(),
+                compiler.tunables(),
+            )?;
+            Ok(func.display(None).to_string())
+        })
+        .collect::<Result<Vec<_>, CompileError>>()?
+        .into_iter()
+        .collect::<PrimaryMap<DefinedFuncIndex, _>>();
+
+    // Nothing is loaded from the object image of an interpreted module, so it
+    // only holds empty functions and no trampolines.
+    let funcs = clif
+        .values()
+        .map(|_| CompiledFunction {
+            body: Vec::new(),
+            jt_offsets: Default::default(),
+            unwind_info: None,
+            relocations: Vec::new(),
+            address_map: Default::default(),
+            value_labels_ranges: Default::default(),
+            stack_slots: Default::default(),
+            traps: Vec::new(),
+            stack_maps: Vec::new(),
+            stats: Default::default(),
+        })
+        .collect::<CompiledFunctions>();
+    let target = ObjectBuilderTarget::new(compiler.isa().triple().architecture)?;
+    let obj = ObjectBuilder::new(target, &translation.module, &funcs).build()?;
+
+    Ok(Compilation {
+        obj,
+        unwind_info: Vec::new(),
+        funcs,
+        clif: Some(clif),
+    })
+}
+
+/// Parses the Cranelift IR of a module's functions, making them callable
+/// through `interpreted_trampoline`.
+pub(crate) fn load(
+    clif: &PrimaryMap<DefinedFuncIndex, String>,
+) -> Result<Box<InterpretedCode>, SetupError> {
+    let functions = clif
+        .values()
+        .map(|text| match parse_functions(text) {
+            Ok(mut functions) if functions.len() == 1 => Ok(functions.remove(0)),
+            Ok(_) => Err(CompileError::Codegen(
+                "expected a single function in the Cranelift IR".to_string(),
+            )),
+            Err(error) => Err(CompileError::Codegen(error.to_string())),
+        })
+        .collect::<Result<PrimaryMap<DefinedFuncIndex, _>, _>>()?;
+    let names = functions
+        .iter()
+        .map(|(index, function)| (function.name.to_string(), index))
+        .collect();
+
+    // The `InterpretedFunction`s refer to the code by address, so it must be
+    // boxed before they are created.
+    let mut code = Box::new(InterpretedCode {
+        functions,
+        names,
+        entries: Box::new([]),
+    });
+    let code_ptr: *const InterpretedCode = &*code;
+    code.entries = code
+        .functions
+        .keys()
+        .map(|index| InterpretedFunction {
+            code: code_ptr,
+            index,
+        })
+        .collect();
+
+    if let Some((start, end)) = code.entries_range() {
+        INTERPRETED_FUNCTIONS.write().unwrap().insert(start, end);
+    }
+    Ok(code)
+}
+
+/// Returns the trampoline to call `function` through if it is an interpreted
+/// function rather than native code.
+///
+/// Interpreted functions have no code of their own, so they can neither be
+/// called directly nor through the trampolines of compiled code.
+pub fn interpreted_trampoline(function: *const VMFunctionBody) -> Option<VMTrampoline> {
+    unsafe { lookup(function) }.map(|_| interpreter_trampoline as VMTrampoline)
+}
+
+/// Finds the interpreted function at the address `function`, if any.
+///
+/// The result is only valid for as long as the module it belongs to is kept
+/// alive.
+unsafe fn lookup<'a>(function: *const VMFunctionBody) -> Option<&'a InterpretedFunction> {
+    let address = function as usize;
+    let ranges = INTERPRETED_FUNCTIONS.read().unwrap();
+    let (_, end) = ranges.range(..=address).next_back()?;
+    if address < *end {
+        Some(&*(address as *const InterpretedFunction))
+    } else {
+        None
+    }
+}
+
+/// The interpreted functions of a loaded module.
+pub(crate) struct InterpretedCode {
+    functions: PrimaryMap<DefinedFuncIndex, ir::Function>,
+    /// The functions by name (e.g. `u0:3`), to resolve direct calls.
+    names: HashMap<String, DefinedFuncIndex>,
+    /// What the address of each function points to.
+    entries: Box<[InterpretedFunction]>,
+}
+
+/// What the address of an interpreted function points to, in place of code.
+struct InterpretedFunction {
+    code: *const InterpretedCode,
+    index: DefinedFuncIndex,
+}
+
+// The code is immutable once loaded, and it outlives its functions since it
+// owns them.
+unsafe impl Send for InterpretedFunction {}
+unsafe impl Sync for InterpretedFunction {}
+
+impl InterpretedCode {
+    /// The addresses of the functions, with which the rest of the runtime
+    /// refers to them. As none of them has any code these are all empty.
+    pub(crate) fn finished_functions(&self) -> PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                ptr::slice_from_raw_parts_mut(
+                    entry as *const InterpretedFunction as *mut VMFunctionBody,
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    /// The address of the function at `index`.
+    fn address(&self, index: DefinedFuncIndex) -> u64 {
+        &self.entries[index.index()] as *const InterpretedFunction as u64
+    }
+
+    /// The range of addresses of the functions, unless there are none.
+    fn entries_range(&self) -> Option<(usize, usize)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let start = self.entries.as_ptr() as usize;
+        let size = self.entries.len() * mem::size_of::<InterpretedFunction>();
+        Some((start, start + size))
+    }
+
+    /// Interprets the function at `index` with the given arguments, the first
+    /// two of which are the callee and caller `vmctx`.
+    unsafe fn call(
+        &self,
+        index: DefinedFuncIndex,
+        arguments: &[DataValue],
+    ) -> Result<SmallVec<[DataValue; 1]>, ir::TrapCode> {
+        let function = &self.functions[index];
+        let vmctx = match arguments[0] {
+            DataValue::I64(vmctx) => vmctx as usize,
+            DataValue::I32(vmctx) => vmctx as u32 as usize,
+            ref other => panic!("unexpected vmctx argument: {}", other),
+        } as *mut VMContext;
+
+        // Honor the same stack limit as compiled code; this is also how
+        // interrupts are signalled.
+        let stack_limit = match function.stack_limit {
+            Some(gv) => global_value(function, gv, vmctx),
+            None => 0,
+        };
+        if stack_limit == INTERRUPTED {
+            return Err(ir::TrapCode::Interrupt);
+        }
+        let stack_pointer = &stack_limit as *const usize as usize;
+        let available_stack = match stack_pointer.checked_sub(stack_limit) {
+            Some(available) => available,
+            None => return Err(ir::TrapCode::StackOverflow),
+        };
+
+        let mut interpreter = Interpreter::new(VMContextState::new(self))
+            .with_max_call_depth(available_stack / INTERPRETED_FRAME_SIZE);
+        match interpreter.call(function, arguments) {
+            Ok(ControlFlow::Return(results)) => Ok(results),
+            Ok(ControlFlow::Trap(CraneliftTrap::User(code))) => Err(code),
+            Ok(ControlFlow::Trap(trap)) => panic!("unexpected trap in {}: {}", function.name, trap),
+            Ok(_) => unreachable!(),
+            Err(error) => panic!("failed to interpret {}: {:?}", function.name, error),
+        }
+    }
+}
+
+impl Drop for InterpretedCode {
+    fn drop(&mut self) {
+        if let Some((start, _)) = self.entries_range() {
+            INTERPRETED_FUNCTIONS.write().unwrap().remove(&start);
+        }
+    }
+}
+
+/// The trampoline through which the host calls every interpreted function.
+///
+/// Like the trampolines of compiled code it reads the arguments of `callee`
+/// from, and writes its results to, `values_vec`.
+unsafe extern "C" fn interpreter_trampoline(
+    vmctx: *mut VMContext,
+    caller_vmctx: *mut VMContext,
+    callee: *const VMFunctionBody,
+    values_vec: *mut u128,
+) {
+    // As with host functions, panics must not unwind past this `extern "C"`
+    // function and there must be no locals with destructors here since
+    // raising a trap doesn't run them.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let function = lookup(callee).expect("not an interpreted function");
+        let code = &*function.code;
+        let signature = &code.functions[function.index].signature;
+
+        let mut arguments = Vec::with_capacity(signature.params.len());
+        arguments.push(pointer_value(vmctx));
+        arguments.push(pointer_value(caller_vmctx));
+        for (i, param) in signature.params.iter().enumerate().skip(2) {
+            arguments.push(DataValue::read_value_from(
+                values_vec.add(i - 2),
+                param.value_type,
+            ));
+        }
+
+        let results = code.call(function.index, &arguments)?;
+        for (i, result) in results.iter().enumerate() {
+            result.write_value_to(values_vec.add(i));
+        }
+        Ok(())
+    }));
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(trap_code)) => raise_lib_trap(Trap::wasm(trap_code)),
+        Err(panic) => resume_panic(panic),
+    }
+}
+
+/// Computes the value of a global value which is (a chain of loads from) the
+/// `vmctx`, such as the stack limit.
+unsafe fn global_value(
+    function: &ir::Function,
+    gv: ir::GlobalValue,
+    vmctx: *mut VMContext,
+) -> usize {
+    match function.global_values[gv] {
+        ir::GlobalValueData::VMContext => vmctx as usize,
+        ir::GlobalValueData::Load { base, offset, .. } => {
+            let base = global_value(function, base, vmctx);
+            let offset: i64 = offset.into();
+            ptr::read((base as i64 + offset) as *const usize)
+        }
+        ref other => panic!("unsupported global value: {}", other),
+    }
+}
+
+fn pointer_value<T>(pointer: *mut T) -> DataValue {
+    if cfg!(target_pointer_width = "64") {
+        DataValue::I64(pointer as i64)
+    } else {
+        DataValue::I32(pointer as i32)
+    }
+}
+
+/// The interpreter's view of a running instance: memories, tables and globals
+/// are all reached through the `vmctx` and accessed in place.
+struct VMContextState<'a> {
+    code: &'a InterpretedCode,
+    frame_stack: Vec<Frame<'a>>,
+    /// The stack slots of each frame.
+    stacks: Vec<Box<[u8]>>,
+    iflags: HashSet<IntCC>,
+    fflags: HashSet<FloatCC>,
+}
+
+impl<'a> VMContextState<'a> {
+    fn new(code: &'a InterpretedCode) -> Self {
+        Self {
+            code,
+            frame_stack: Vec::new(),
+            stacks: Vec::new(),
+            iflags: HashSet::new(),
+            fflags: HashSet::new(),
+        }
+    }
+
+    fn current_frame(&self) -> &Frame<'a> {
+        self.frame_stack
+            .last()
+            .expect("unable to retrieve the current frame because no frames were pushed")
+    }
+
+    /// Compute the address of `size` bytes at `offset` in the current frame's
+    /// stack slots.
+    fn stack_slice(&self, offset: usize, size: usize) -> Result<usize, MemoryError> {
+        let stack = self.stacks.last().map_or(&[][..], |stack| &stack[..]);
+        match offset.checked_add(size) {
+            Some(end) if end <= stack.len() => Ok(stack.as_ptr() as usize + offset),
+            _ => Err(MemoryError::InsufficientMemory(offset, stack.len())),
+        }
+    }
+}
+
+impl<'a> State<'a, DataValue> for VMContextState<'a> {
+    fn get_function(&self, func_ref: ir::FuncRef) -> Option<&'a ir::Function> {
+        let name = &self.get_current_function().dfg.ext_funcs[func_ref].name;
+        self.get_function_by_name(&name.to_string())
+            .map(|(_, function)| function)
+    }
+
+    fn get_function_by_name(&self, name: &str) -> Option<(u64, &'a ir::Function)> {
+        let index = *self.code.names.get(name)?;
+        Some((self.code.address(index), &self.code.functions[index]))
+    }
+
+    fn get_function_by_address(&self, address: u64) -> Option<&'a ir::Function> {
+        // Functions of other modules run in an interpreter of their own, see
+        // `call_native`.
+        let function = unsafe { lookup(address as *const VMFunctionBody)? };
+        if ptr::eq(function.code, self.code) {
+            Some(&self.code.functions[function.index])
+        } else {
+            None
+        }
+    }
+
+    fn get_current_function(&self) -> &'a ir::Function {
+        self.current_frame().function
+    }
+
+    fn call_native(
+        &mut self,
+        address: u64,
+        signature: &ir::Signature,
+        arguments: &[DataValue],
+    ) -> Option<Result<SmallVec<[DataValue; 1]>, CraneliftTrap>> {
+        unsafe {
+            if let Some(function) = lookup(address as *const VMFunctionBody) {
+                let code = &*function.code;
+                return Some(
+                    code.call(function.index, arguments)
+                        .map_err(CraneliftTrap::User),
+                );
+            }
+
+            // Traps raised by native code unwind straight past the interpreter.
+            match call_native_function(address, signature, arguments) {
+                Some(results) => Some(Ok(results)),
+                None => panic!(
+                    "the interpreter can't call native functions with the signature {}",
+                    signature
+                ),
+            }
+        }
+    }
+
+    fn push_frame(&mut self, function: &'a ir::Function) {
+        let stack_size: usize = function
+            .stack_slots
+            .values()
+            .map(|slot| slot.size as usize)
+            .sum();
+        self.frame_stack.push(Frame::new(function));
+        self.stacks.push(vec![0; stack_size].into_boxed_slice());
+    }
+
+    fn pop_frame(&mut self) {
+        self.frame_stack.pop();
+        self.stacks.pop();
+    }
+
+    fn get_value(&self, name: ir::Value) -> Option<DataValue> {
+        Some(self.current_frame().get(name).clone())
+    }
+
+    fn set_value(&mut self, name: ir::Value, value: DataValue) -> Option<DataValue> {
+        self.frame_stack
+            .last_mut()
+            .expect("unable to retrieve the current frame because no frames were pushed")
+            .set(name, value)
+    }
+
+    fn has_iflag(&self, flag: IntCC) -> bool {
+        self.iflags.contains(&flag)
+    }
+
+    fn has_fflag(&self, flag: FloatCC) -> bool {
+        self.fflags.contains(&flag)
+    }
+
+    fn set_iflag(&mut self, flag: IntCC) {
+        self.iflags.insert(flag);
+    }
+
+    fn set_fflag(&mut self, flag: FloatCC) {
+        self.fflags.insert(flag);
+    }
+
+    fn clear_flags(&mut self) {
+        self.iflags.clear();
+        self.fflags.clear();
+    }
+
+    fn load_heap(&self, address: usize, ty: ir::Type) -> Result<DataValue, MemoryError> {
+        // Copy into an aligned buffer, since wasm accesses need not be
+        // aligned.
+        let mut buffer = 0u128;
+        let pointer = &mut buffer as *mut u128;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                address as *const u8,
+                pointer as *mut u8,
+                ty.bytes() as usize,
+            );
+            Ok(DataValue::read_value_from(pointer, ty))
+        }
+    }
+
+    fn store_heap(&mut self, address: usize, v: DataValue) -> Result<(), MemoryError> {
+        let mut buffer = 0u128;
+        let pointer = &mut buffer as *mut u128;
+        unsafe {
+            v.write_value_to(pointer);
+            ptr::copy_nonoverlapping(
+                pointer as *const u8,
+                address as *mut u8,
+                v.ty().bytes() as usize,
+            );
+        }
+        Ok(())
+    }
+
+    fn load_stack(&self, offset: usize, ty: ir::Type) -> Result<DataValue, MemoryError> {
+        self.load_heap(self.stack_slice(offset, ty.bytes() as usize)?, ty)
+    }
+
+    fn store_stack(&mut self, offset: usize, v: DataValue) -> Result<(), MemoryError> {
+        let address = self.stack_slice(offset, v.ty().bytes() as usize)?;
+        self.store_heap(address, v)
+    }
+
+    fn stack_address(&self, offset: usize) -> Result<usize, MemoryError> {
+        self.stack_slice(offset, 0)
+    }
+}
+
+/// The number of integer and of floating-point arguments which native
+/// functions are called with.
+///
+/// On the supported platforms floating-point arguments are all passed in
+/// registers, and so are integer ones except for those after the sixth on
+/// x86-64, which are passed on the stack in order. A callee therefore finds
+/// its arguments in the same places however many more of either kind it's
+/// passed, and it ignores the extra ones.
+const NATIVE_CALL_ARGS: usize = 8;
+
+/// A native function returning an integer, or nothing.
+type IntegerNativeCall = unsafe extern "C" fn(
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> u64;
+
+/// A native function returning a floating-point value.
+type FloatNativeCall = unsafe extern "C" fn(
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> f64;
+
+/// Calls the native function at `address` with the given `signature`, without
+/// generating any code for it: the arguments are spread over the registers
+/// that the calling convention passes them in, as described above.
+///
+/// Returns `None` if the signature has too many parameters or results, or
+/// values which don't fit in a register.
+#[cfg(any(
+    all(target_arch = "x86_64", not(target_os = "windows")),
+    target_arch = "aarch64"
+))]
+unsafe fn call_native_function(
+    address: u64,
+    signature: &ir::Signature,
+    arguments: &[DataValue],
+) -> Option<SmallVec<[DataValue; 1]>> {
+    let mut ints = [0u64; NATIVE_CALL_ARGS];
+    let mut floats = [0f64; NATIVE_CALL_ARGS];
+    let (mut next_int, mut next_float) = (0, 0);
+    for (param, argument) in signature.params.iter().zip(arguments) {
+        if param.value_type.bytes() > 8 {
+            return None;
+        }
+        let mut bits = 0u128;
+        argument.write_value_to(&mut bits);
+        if param.value_type.is_float() {
+            *floats.get_mut(next_float)? = f64::from_bits(bits as u64);
+            next_float += 1;
+        } else {
+            *ints.get_mut(next_int)? = bits as u64;
+            next_int += 1;
+        }
+    }
+
+    let returns_float = match &signature.returns[..] {
+        [] => false,
+        [ret] if ret.value_type.bytes() <= 8 => ret.value_type.is_float(),
+        _ => return None,
+    };
+
+    let [i0, i1, i2, i3, i4, i5, i6, i7] = ints;
+    let [f0, f1, f2, f3, f4, f5, f6, f7] = floats;
+    let bits = if returns_float {
+        let call = mem::transmute::<u64, FloatNativeCall>(address);
+        call(
+            i0, i1, i2, i3, i4, i5, i6, i7, f0, f1, f2, f3, f4, f5, f6, f7,
+        )
+        .to_bits()
+    } else {
+        let call = mem::transmute::<u64, IntegerNativeCall>(address);
+        call(
+            i0, i1, i2, i3, i4, i5, i6, i7, f0, f1, f2, f3, f4, f5, f6, f7,
+        )
+    };
+    let bits = u128::from(bits);
+    Some(
+        signature
+            .returns
+            .iter()
+            .map(|ret| DataValue::read_value_from(&bits, ret.value_type))
+            .collect(),
+    )
+}
+
+/// Calling native functions isn't supported on this platform.
+#[cfg(not(any(
+    all(target_arch = "x86_64", not(target_os = "windows")),
+    target_arch = "aarch64"
+)))]
+unsafe fn call_native_function(
+    _address: u64,
+    _signature: &ir::Signature,
+    _arguments: &[DataValue],
+) -> Option<SmallVec<[DataValue; 1]>> {
+    None
+}
@@ -37,6 +37,8 @@ macro_rules! maybe_parallel {
 mod code_memory;
 mod compiler;
 mod instantiate;
+#[cfg(feature = "interpreter")]
+mod interpreter;
 mod link;
 mod object;
 mod unwind;
@@ -47,6 +49,8 @@ pub mod trampoline;
 pub use crate::code_memory::CodeMemory;
 pub use crate::compiler::{Compilation, CompilationStrategy, Compiler};
 pub use crate::instantiate::{CompilationArtifacts, CompiledModule, SetupError};
+#[cfg(feature = "interpreter")]
+pub use crate::interpreter::interpreted_trampoline;
 pub use crate::link::link_module;
 
 /// Version number of this crate.
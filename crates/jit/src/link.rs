@@ -111,7 +111,7 @@ fn apply_reloc(
             );
             write_unaligned(reloc_address as *mut u32, reloc_delta_u64 as u32);
         },
-        (RelocationKind::Elf(elf::R_AARCH64_CALL26), RelocationEncoding::Generic, 32) => unsafe {
+        (RelocationKind::PltRelative, RelocationEncoding::AArch64Call, 26) => unsafe {
             let reloc_address = body.add(offset as usize) as usize;
             let reloc_addend = r.addend() as isize;
             let reloc_delta = (target_func_address as u64).wrapping_sub(reloc_address as u64);
@@ -128,6 +128,27 @@ fn apply_reloc(
             let new_insn = (insn & 0xfc00_0000) | (delta_bits & 0x03ff_ffff);
             write_unaligned(reloc_address as *mut u32, new_insn);
         },
+        // `object` doesn't give a size for this relocation, which patches two instructions.
+        (RelocationKind::Elf(elf::R_RISCV_CALL_PLT), RelocationEncoding::Generic, _) => unsafe {
+            let reloc_address = body.add(offset as usize) as usize;
+            let reloc_addend = r.addend() as isize;
+            let reloc_delta = (target_func_address as u64)
+                .wrapping_sub(reloc_address as u64)
+                .wrapping_add(reloc_addend as u64);
+            // TODO: as for AArch64, longer calls need a PLT-like solution.
+            assert!((reloc_delta as i64) < (1 << 31) - 0x800);
+            assert!((reloc_delta as i64) >= -(1 << 31) - 0x800);
+            let reloc_delta = reloc_delta as u32;
+            // The offset is split between the `auipc` and the `jalr`; the high part is rounded
+            // as the low part is sign-extended.
+            let hi20 = reloc_delta.wrapping_add(0x800) & 0xffff_f000;
+            let lo12 = reloc_delta << 20;
+            let auipc = read_unaligned(reloc_address as *const u32);
+            write_unaligned(reloc_address as *mut u32, (auipc & 0xfff) | hi20);
+            let jalr_address = reloc_address + 4;
+            let jalr = read_unaligned(jalr_address as *const u32);
+            write_unaligned(jalr_address as *mut u32, (jalr & 0xf_ffff) | lo12);
+        },
         other => panic!("unsupported reloc kind: {:?}", other),
     }
 }
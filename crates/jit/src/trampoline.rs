@@ -161,6 +161,7 @@ pub(crate) fn build_trampoline(
         unwind_info,
         relocations: reloc_sink.relocs,
         stack_maps: Default::default(),
+        stats: Default::default(),
         stack_slots: Default::default(),
         traps: Default::default(),
         value_labels_ranges: Default::default(),
@@ -68,6 +68,7 @@ impl Compiler for Lightbeam {
             // not implemented for lightbeam currently
             unwind_info: None,
             stack_maps: Default::default(),
+            stats: Default::default(),
             stack_slots: Default::default(),
             value_labels_ranges: Default::default(),
             address_map: Default::default(),
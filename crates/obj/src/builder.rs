@@ -21,8 +21,8 @@ use object::write::{
     SymbolSection,
 };
 use object::{
-    elf, Architecture, BinaryFormat, Endianness, RelocationEncoding, RelocationKind, SectionKind,
-    SymbolFlags, SymbolKind, SymbolScope,
+    elf, Architecture, BinaryFormat, Endianness, FileFlags, RelocationEncoding, RelocationKind,
+    SectionKind, SymbolFlags, SymbolKind, SymbolScope,
 };
 use std::collections::HashMap;
 use target_lexicon::Triple;
@@ -80,6 +80,11 @@ fn to_object_relocations<'a>(
                 RelocationEncoding::Generic,
                 32,
             ),
+            Reloc::RiscvCallPlt => (
+                RelocationKind::Elf(elf::R_RISCV_CALL_PLT),
+                RelocationEncoding::Generic,
+                32,
+            ),
             other => unimplemented!("Unimplemented relocation {:?}", other),
         };
         Some(ObjectRelocation {
@@ -102,6 +107,7 @@ fn to_object_architecture(
         X86_64 => Architecture::X86_64,
         Arm(_) => Architecture::Arm,
         Aarch64(_) => Architecture::Aarch64,
+        Riscv64(_) => Architecture::Riscv64,
         architecture => {
             anyhow::bail!("target architecture {:?} is unsupported", architecture,);
         }
@@ -301,6 +307,13 @@ impl<'a> ObjectBuilder<'a> {
             self.target.architecture,
             self.target.endianness,
         );
+        if self.target.architecture == Architecture::Riscv64 {
+            // The code uses the hardware floating-point registers (the LP64D ABI), which the
+            // linker checks against the other objects.
+            obj.flags = FileFlags::Elf {
+                e_flags: elf::EF_RISCV_FLOAT_ABI_DOUBLE,
+            };
+        }
 
         let module = self.module;
 
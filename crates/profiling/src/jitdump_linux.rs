@@ -241,6 +241,7 @@ impl State {
             Architecture::X86_32(_) => elf::EM_386 as u32,
             Architecture::Arm(_) => elf::EM_ARM as u32,
             Architecture::Aarch64(_) => elf::EM_AARCH64 as u32,
+            Architecture::Riscv64(_) => elf::EM_RISCV as u32,
             _ => unimplemented!("unrecognized architecture"),
         }
     }
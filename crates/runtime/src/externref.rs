@@ -502,6 +502,10 @@ pub struct VMExternRefActivationsTable {
     /// inside-a-Wasm-frame roots, and doing a GC could lead to freeing one of
     /// those missed roots, and use after free.
     stack_canary: Cell<Option<NonNull<u8>>>,
+
+    /// The number of native stacks, other than the one that's currently
+    /// running, that have Wasm frames on them. See `switch_stack_canary`.
+    suspended_stacks: Cell<usize>,
 }
 
 impl VMExternRefActivationsTable {
@@ -520,6 +524,7 @@ impl VMExternRefActivationsTable {
             over_approximated_stack_roots: RefCell::new(HashSet::with_capacity(Self::CHUNK_SIZE)),
             precise_stack_roots: RefCell::new(HashSet::with_capacity(Self::CHUNK_SIZE)),
             stack_canary: Cell::new(None),
+            suspended_stacks: Cell::new(0),
         }
     }
 
@@ -756,6 +761,32 @@ impl VMExternRefActivationsTable {
             }
         }
     }
+
+    /// Switches this table to a different native stack on the current thread,
+    /// such as a fiber's, returning the stack canary of the stack that's being
+    /// switched away from.
+    ///
+    /// `canary` must be the value this function returned when the stack being
+    /// switched to was last switched away from, or `None` for a stack that
+    /// hasn't run any Wasm yet.
+    ///
+    /// A stack that's switched away from in the middle of a Wasm call still
+    /// has roots in its Wasm frames, which `gc` can't find by walking the
+    /// stack that's running instead. Therefore no garbage collection sweeps
+    /// the table while any such stack is suspended.
+    ///
+    /// # Unsafety
+    ///
+    /// This must be called on every switch between native stacks that both
+    /// use this table, in both directions, for as long as either of them has
+    /// Wasm frames on it.
+    pub unsafe fn switch_stack_canary(&self, canary: Option<NonNull<u8>>) -> Option<NonNull<u8>> {
+        let prev = self.stack_canary.replace(canary);
+        let suspended = self.suspended_stacks.get() + prev.is_some() as usize;
+        self.suspended_stacks
+            .set(suspended - canary.is_some() as usize);
+        prev
+    }
 }
 
 /// A registry of stack maps for currently active Wasm modules.
@@ -985,7 +1016,10 @@ impl<T> std::ops::DerefMut for DebugOnly<T> {
 /// You must have called `VMExternRefActivationsTable::set_stack_canary` for at
 /// least the oldest host-->Wasm stack frame transition on this thread's stack
 /// (it is idempotent to call it more than once) and keep its return value alive
-/// across the duration of that host-->Wasm call.
+/// across the duration of that host-->Wasm call. If Wasm runs on more than one
+/// native stack on this thread, you must also have called
+/// `VMExternRefActivationsTable::switch_stack_canary` whenever switching
+/// between them.
 ///
 /// Additionally, you must have registered the stack maps for every Wasm module
 /// that has frames on the stack with the given `stack_maps_registry`.
@@ -1009,6 +1043,14 @@ pub unsafe fn gc(
 
     log::debug!("start GC");
 
+    // Wasm frames on suspended stacks might be holding on to references that
+    // we can't discover by walking this stack, so we can't sweep anything
+    // until they've resumed and returned.
+    if externref_activations_table.suspended_stacks.get() > 0 {
+        log::debug!("Wasm stacks are suspended; skipping GC sweep");
+        return;
+    }
+
     debug_assert!({
         // This set is only non-empty within this function. It is built up when
         // walking the stack and interpreting stack maps, and then drained back
@@ -5,6 +5,7 @@
 use crate::export::Export;
 use crate::externref::{StackMapRegistry, VMExternRefActivationsTable};
 use crate::imports::Imports;
+use crate::limits::ResourceLimiter;
 use crate::memory::{DefaultMemoryCreator, RuntimeLinearMemory, RuntimeMemoryCreator};
 use crate::table::{Table, TableElement};
 use crate::traphandlers::Trap;
@@ -22,6 +23,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::ptr::NonNull;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::{mem, ptr, slice};
 use thiserror::Error;
@@ -62,6 +64,9 @@ pub(crate) struct Instance {
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// Consulted before growing any of this instance's memories or tables.
+    limiter: Option<Rc<dyn ResourceLimiter>>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -387,11 +392,21 @@ impl Instance {
     /// Returns `None` if memory can't be grown by the specified amount
     /// of pages.
     pub(crate) fn memory_grow(&self, memory_index: DefinedMemoryIndex, delta: u32) -> Option<u32> {
-        let result = self
+        let memory = self
             .memories
             .get(memory_index)
-            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()))
-            .grow(delta);
+            .unwrap_or_else(|| panic!("no memory for index {}", memory_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = memory.size();
+            let desired = current.checked_add(delta)?;
+            let plan = &self.module.memory_plans[self.module.memory_index(memory_index)];
+            if !limiter.memory_growing(current, desired, plan.memory.maximum) {
+                return None;
+            }
+        }
+
+        let result = memory.grow(delta);
 
         // Keep current the VMContext pointers used by compiled wasm code.
         self.set_memory(memory_index, self.memories[memory_index].vmmemory());
@@ -469,12 +484,22 @@ impl Instance {
         delta: u32,
         init_value: TableElement,
     ) -> Option<u32> {
+        let table = self
+            .tables
+            .get(table_index)
+            .unwrap_or_else(|| panic!("no table for index {}", table_index.index()));
+
+        if let Some(limiter) = &self.limiter {
+            let current = table.size();
+            let desired = current.checked_add(delta)?;
+            let plan = &self.module.table_plans[self.module.table_index(table_index)];
+            if !limiter.table_growing(current, desired, plan.table.maximum) {
+                return None;
+            }
+        }
+
         unsafe {
-            let orig_size = self
-                .tables
-                .get(table_index)
-                .unwrap_or_else(|| panic!("no table for index {}", table_index.index()))
-                .grow(delta, init_value)?;
+            let orig_size = table.grow(delta, init_value)?;
 
             // Keep the `VMContext` pointers used by compiled Wasm code up to
             // date.
@@ -809,6 +834,9 @@ impl InstanceHandle {
     /// It is your responsibility to ensure that the given raw
     /// `externref_activations_table` and `stack_map_registry` outlive this
     /// instance.
+    ///
+    /// If a `limiter` is given it is consulted before growing any memory or
+    /// table defined by this instance.
     pub unsafe fn new(
         module: Arc<Module>,
         finished_functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
@@ -819,6 +847,7 @@ impl InstanceHandle {
         interrupts: *const VMInterrupts,
         externref_activations_table: *mut VMExternRefActivationsTable,
         stack_map_registry: *mut StackMapRegistry,
+        limiter: Option<Rc<dyn ResourceLimiter>>,
     ) -> Result<Self, InstantiationError> {
         debug_assert!(!externref_activations_table.is_null());
         debug_assert!(!stack_map_registry.is_null());
@@ -853,6 +882,7 @@ impl InstanceHandle {
                 passive_elements: Default::default(),
                 passive_data,
                 host_state,
+                limiter,
                 vmctx: VMContext {},
             };
             let layout = instance.alloc_layout();
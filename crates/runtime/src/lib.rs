@@ -25,6 +25,7 @@ mod externref;
 mod imports;
 mod instance;
 mod jit_int;
+mod limits;
 mod memory;
 mod mmap;
 mod table;
@@ -39,6 +40,7 @@ pub use crate::externref::*;
 pub use crate::imports::Imports;
 pub use crate::instance::{InstanceHandle, InstantiationError, LinkError};
 pub use crate::jit_int::GdbJitImageRegistration;
+pub use crate::limits::ResourceLimiter;
 pub use crate::memory::{RuntimeLinearMemory, RuntimeMemoryCreator};
 pub use crate::mmap::Mmap;
 pub use crate::table::{Table, TableElement};
@@ -0,0 +1,23 @@
+//! Hooks that let an embedder limit the resources consumed by instances.
+
+/// Used by hosts to limit the growth of linear memories and tables.
+///
+/// The limiter is consulted every time a memory or table defined by an
+/// instance grows, whether through `memory.grow` and `table.grow` in wasm or
+/// through the embedding API. Growth that the limiter denies fails exactly as
+/// if the memory or table had reached its maximum size.
+pub trait ResourceLimiter {
+    /// Notifies the limiter that a linear memory is about to grow from
+    /// `current` to `desired` pages.
+    ///
+    /// `maximum` is the maximum number of pages declared for the memory, if
+    /// any. Return `false` to deny the growth.
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+
+    /// Notifies the limiter that a table is about to grow from `current` to
+    /// `desired` elements.
+    ///
+    /// `maximum` is the maximum number of elements declared for the table, if
+    /// any. Return `false` to deny the growth.
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool;
+}
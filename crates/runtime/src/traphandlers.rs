@@ -164,6 +164,23 @@ cfg_if::cfg_if! {
                 } else if #[cfg(all(any(target_os = "linux", target_os = "android"), target_arch = "aarch64"))] {
                     let cx = &*(cx as *const libc::ucontext_t);
                     cx.uc_mcontext.pc as *const u8
+                } else if #[cfg(all(target_os = "linux", target_arch = "riscv64"))] {
+                    // The `libc` crate doesn't define `ucontext_t` for riscv64 yet, so mirror
+                    // glibc's layout up to the general registers, where the PC comes first.
+                    #[repr(C)]
+                    struct ucontext_t {
+                        uc_flags: libc::c_ulong,
+                        uc_link: *mut ucontext_t,
+                        uc_stack: libc::stack_t,
+                        uc_sigmask: libc::sigset_t,
+                        uc_mcontext: mcontext_t,
+                    }
+                    #[repr(C, align(16))]
+                    struct mcontext_t {
+                        __gregs: [libc::c_ulong; 32],
+                    }
+                    let cx = &*(cx as *const ucontext_t);
+                    cx.uc_mcontext.__gregs[0] as *const u8
                 } else if #[cfg(target_os = "macos")] {
                     let cx = &*(cx as *const libc::ucontext_t);
                     (*cx.uc_mcontext).__ss.__rip as *const u8
@@ -655,7 +672,8 @@ pub mod tls {
 
 
     thread_local!(
-        /// This is set to pub so it can be preserved during a context swith on the same thread by AsyncWormhole.
+        /// This is set to pub so it can be preserved during a context switch
+        /// on the same thread, such as when suspending a fiber running wasm.
         pub static PTR: Cell<*const CallThreadState<'static>> = Cell::new(ptr::null())
     );
 
@@ -202,8 +202,6 @@ mod wasi_tests {
             fn ignore(testsuite: &str, name: &str) -> bool {
                 if testsuite == "wasi-tests" {
                     match name {
-                        // TODO: virtfs files cannot be poll_oneoff'd yet
-                        "poll_oneoff_virtualfs" => true,
                         // TODO: virtfs does not support filetimes yet.
                         "path_filestat_virtualfs" |
                         "fd_filestat_set_virtualfs" => true,
@@ -236,8 +234,6 @@ mod wasi_tests {
                         "symlink_loop" => true,
                         "truncation_rights" => true,
                         "dangling_fd" => true,
-                        // TODO: virtfs files cannot be poll_oneoff'd yet
-                        "poll_oneoff_virtualfs" => true,
                         // TODO: virtfs does not support filetimes yet.
                         "path_filestat_virtualfs" |
                         "fd_filestat_set_virtualfs" => true,
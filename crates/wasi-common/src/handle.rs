@@ -1,3 +1,4 @@
+use crate::sched::{Eventtype, PollWaker, Readiness};
 pub use crate::wasi::types::{
     Advice, Dircookie, Dirent, Fdflags, Fdstat, Filedelta, Filesize, Filestat, Filetype, Fstflags,
     Lookupflags, Oflags, Prestat, PrestatDir, Rights, Size, Timestamp, Whence,
@@ -157,6 +158,16 @@ pub trait Handle {
     fn write_vectored(&self, _iovs: &[io::IoSlice]) -> Result<usize> {
         Err(Error::Badf)
     }
+    /// Query whether this handle is ready for a `poll_oneoff` subscription of type `ty`, which
+    /// is either `Eventtype::FdRead` or `Eventtype::FdWrite`.
+    ///
+    /// Virtual handles override this to take part in `poll_oneoff`. A handle returning
+    /// `Readiness::Pending` must call `waker.wake()` once its readiness changes, and should drop
+    /// the wakers it keeps once `PollWaker::is_stale` returns `true`. Handles backed by OS
+    /// resources return `None` and are polled by the host OS instead.
+    fn poll_readiness(&self, _ty: Eventtype, _waker: &PollWaker) -> Result<Option<Readiness>> {
+        Ok(None)
+    }
     // TODO perhaps should be a separate trait?
     // PathOps
     fn create_directory(&self, _path: &str) -> Result<()> {
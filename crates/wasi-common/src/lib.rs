@@ -39,6 +39,7 @@ pub mod wasi;
 pub use ctx::{WasiCtx, WasiCtxBuilder, WasiCtxBuilderError};
 pub use error::{Error, Result};
 pub use handle::{Handle, HandleRights};
+pub use sched::{PollWaker, Readiness};
 pub use sys::osdir::OsDir;
 pub use sys::osfile::OsFile;
 pub use sys::osother::OsOther;
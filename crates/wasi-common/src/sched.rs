@@ -1,8 +1,15 @@
 use crate::entry::EntryHandle;
+use crate::handle::Filesize;
+use crate::sys::poll;
 pub use crate::wasi::types::{
     Clockid, Errno, Event, EventFdReadwrite, Eventrwflags, Eventtype, Subclockflags,
     SubscriptionClock, Timestamp, Userdata,
 };
+use crate::Result;
+use std::convert::TryInto;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
 #[derive(Debug, Copy, Clone)]
 pub struct ClockEventData {
     pub delay: u128, // delay is expressed in nanoseconds
@@ -15,3 +22,271 @@ pub struct FdEventData {
     pub r#type: Eventtype,
     pub userdata: Userdata,
 }
+
+/// Readiness of a virtual `Handle` for a `poll_oneoff` subscription, as reported by
+/// `Handle::poll_readiness`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Readiness {
+    /// The handle is ready. For read subscriptions `nbytes` is the number of bytes available to
+    /// be read without blocking; for write subscriptions it may be zero.
+    Ready { nbytes: Filesize },
+    /// The other end of the handle has been closed: reads will report end-of-file, and writes
+    /// will fail.
+    Hangup,
+    /// The handle is not ready yet. The handle is responsible for calling `PollWaker::wake` on
+    /// the waker it was queried with once its readiness changes.
+    Pending,
+}
+
+/// A waker that virtual handles use to interrupt a `poll_oneoff` call which is blocked waiting on
+/// them.
+///
+/// Handles returning `Readiness::Pending` keep a clone of the waker and call `wake` when data
+/// arrives or the handle is closed. A waker only refers weakly to its `poll_oneoff` call: once the
+/// call has returned, waking it does nothing and `is_stale` returns `true`, so handles can drop it.
+#[derive(Debug, Clone)]
+pub struct PollWaker {
+    inner: Weak<PollerShared>,
+}
+
+impl PollWaker {
+    /// Wake up the poller waiting on this waker, if any.
+    pub fn wake(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            let mut state = inner.state.lock().unwrap();
+            state.woken = true;
+            if let Some(wakeup) = &state.wakeup {
+                wakeup.wake();
+            }
+            inner.cond.notify_all();
+        }
+    }
+
+    /// Returns `true` if `other` refers to the same waker as `self`.
+    pub fn will_wake(&self, other: &Self) -> bool {
+        Weak::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Returns `true` once the `poll_oneoff` call this waker belongs to has returned.
+    pub fn is_stale(&self) -> bool {
+        self.inner.strong_count() == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct PollerState {
+    woken: bool,
+    /// Set once the poller blocks on OS-backed handles too, so that waking it also interrupts
+    /// the host `poll`.
+    wakeup: Option<Arc<poll::Wakeup>>,
+}
+
+#[derive(Debug, Default)]
+struct PollerShared {
+    state: Mutex<PollerState>,
+    cond: Condvar,
+}
+
+/// The `poll_oneoff` end of a set of `PollWaker`s, which lives as long as the call does.
+#[derive(Debug, Default)]
+pub(crate) struct Poller {
+    inner: Arc<PollerShared>,
+}
+
+impl Poller {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a waker for virtual handles to wake this poller up with.
+    pub(crate) fn waker(&self) -> PollWaker {
+        PollWaker {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Block until a waker is woken up or `timeout` elapses, clearing the signal.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) {
+        let mut state = self.inner.state.lock().unwrap();
+        match timeout {
+            Some(timeout) => {
+                let deadline = Instant::now() + timeout;
+                while !state.woken {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = self
+                        .inner
+                        .cond
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+            }
+            None => {
+                while !state.woken {
+                    state = self.inner.cond.wait(state).unwrap();
+                }
+            }
+        }
+        state.woken = false;
+    }
+
+    /// Block in the host `poll` until one of `os_events` is ready, `timeout` elapses or a waker
+    /// is woken up, pushing an event for each OS-backed handle that is ready. Returns whether any
+    /// event was pushed.
+    fn poll_os(
+        &self,
+        os_events: &[FdEventData],
+        timeout: Option<Duration>,
+        events: &mut Vec<Event>,
+    ) -> Result<bool> {
+        let wakeup = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.woken {
+                // Woken up since the virtual handles were last queried, so query them again
+                // before blocking.
+                state.woken = false;
+                return Ok(false);
+            }
+            match &state.wakeup {
+                Some(wakeup) => wakeup.clone(),
+                None => {
+                    let wakeup = Arc::new(poll::Wakeup::new()?);
+                    state.wakeup = Some(wakeup.clone());
+                    wakeup
+                }
+            }
+        };
+        poll_os_events(os_events, timeout, Some(&wakeup), events)
+    }
+}
+
+/// Wait for any of the `fd_events` (or `timeout`) to become ready, pushing the resulting events
+/// to `events`.
+///
+/// Virtual handles are queried through `Handle::poll_readiness`, while handles backed by OS
+/// resources are forwarded to the host-specific `poll::oneoff`. When both are present, the host
+/// `poll` also waits on a `poll::Wakeup` which the virtual handles' wakers trigger.
+pub(crate) fn oneoff(
+    timeout: Option<ClockEventData>,
+    fd_events: Vec<FdEventData>,
+    events: &mut Vec<Event>,
+) -> Result<()> {
+    // Dropping `poller` when returning makes the wakers handed out below stale.
+    let poller = Poller::new();
+    let waker = poller.waker();
+    let mut os_events = Vec::new();
+    let mut virtual_events = Vec::new();
+    for event in fd_events {
+        match event.handle.poll_readiness(event.r#type, &waker)? {
+            Some(_) => virtual_events.push(event),
+            None => os_events.push(event),
+        }
+    }
+
+    if virtual_events.is_empty() {
+        return poll::oneoff(timeout, os_events, None, events);
+    }
+
+    let deadline = timeout.and_then(|timeout| {
+        let delay: u64 = timeout.delay.try_into().unwrap_or(u64::max_value());
+        Instant::now().checked_add(Duration::from_nanos(delay))
+    });
+
+    loop {
+        if push_virtual_events(&virtual_events, &waker, events)? {
+            poll_os_events(&os_events, Some(Duration::from_secs(0)), None, events)?;
+            return Ok(());
+        }
+
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::from_secs(0)) {
+            // `deadline` is only set when `timeout` is.
+            handle_timeout_event(timeout.unwrap(), events);
+            return Ok(());
+        }
+
+        if os_events.is_empty() {
+            poller.wait(remaining);
+        } else if poller.poll_os(&os_events, remaining, events)? {
+            // Pick up any virtual handles that became ready in the meantime, too.
+            push_virtual_events(&virtual_events, &waker, events)?;
+            return Ok(());
+        }
+    }
+}
+
+/// Query the readiness of all `virtual_events`, pushing an event for each one that is ready.
+/// Returns whether any event was pushed.
+fn push_virtual_events(
+    virtual_events: &[FdEventData],
+    waker: &PollWaker,
+    events: &mut Vec<Event>,
+) -> Result<bool> {
+    let mut any_ready = false;
+    for event in virtual_events {
+        let readiness = event
+            .handle
+            .poll_readiness(event.r#type, waker)?
+            .unwrap_or(Readiness::Pending);
+        let (nbytes, flags) = match readiness {
+            Readiness::Ready { nbytes } => (nbytes, Eventrwflags::empty()),
+            Readiness::Hangup => (0, Eventrwflags::FD_READWRITE_HANGUP),
+            Readiness::Pending => continue,
+        };
+        events.push(Event {
+            userdata: event.userdata,
+            error: Errno::Success,
+            type_: event.r#type,
+            fd_readwrite: EventFdReadwrite { nbytes, flags },
+        });
+        any_ready = true;
+    }
+    Ok(any_ready)
+}
+
+/// Poll the OS-backed `os_events` for at most `timeout`, or until `wakeup` is triggered, pushing
+/// an event for each one that is ready. Returns whether any event was pushed.
+fn poll_os_events(
+    os_events: &[FdEventData],
+    timeout: Option<Duration>,
+    wakeup: Option<&poll::Wakeup>,
+    events: &mut Vec<Event>,
+) -> Result<bool> {
+    if os_events.is_empty() {
+        return Ok(false);
+    }
+    let fd_events = os_events
+        .iter()
+        .map(|event| FdEventData {
+            handle: event.handle.get(),
+            r#type: event.r#type,
+            userdata: event.userdata,
+        })
+        .collect();
+    let timeout = timeout.map(|timeout| ClockEventData {
+        delay: timeout.as_nanos(),
+        userdata: 0,
+    });
+    let mut os_out = Vec::new();
+    poll::oneoff(timeout, fd_events, wakeup, &mut os_out)?;
+    // The clock subscription is our own and must not be reported to the guest.
+    os_out.retain(|event| event.type_ != Eventtype::Clock);
+    let any_ready = !os_out.is_empty();
+    events.extend(os_out);
+    Ok(any_ready)
+}
+
+fn handle_timeout_event(timeout: ClockEventData, events: &mut Vec<Event>) {
+    events.push(Event {
+        userdata: timeout.userdata,
+        error: Errno::Success,
+        type_: Eventtype::Clock,
+        fd_readwrite: EventFdReadwrite {
+            flags: Eventrwflags::empty(),
+            nbytes: 0,
+        },
+    });
+}
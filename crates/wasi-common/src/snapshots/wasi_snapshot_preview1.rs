@@ -1,6 +1,6 @@
 use crate::entry::{Entry, EntryHandle};
 use crate::handle::{AsBytes, HandleRights};
-use crate::sys::{clock, poll};
+use crate::sys::clock;
 use crate::wasi::types;
 use crate::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
 use crate::{path, sched, Error, Result, WasiCtx};
@@ -770,7 +770,7 @@ impl<'a> WasiSnapshotPreview1 for WasiCtx {
         // The underlying implementation should successfully and immediately return
         // if no events have been passed. Such situation may occur if all provided
         // events have been filtered out as errors in the code above.
-        poll::oneoff(timeout, fd_events, &mut events)?;
+        sched::oneoff(timeout, fd_events, &mut events)?;
         let nevents = events.len().try_into()?;
 
         let out_events = out.as_array(nevents);
@@ -5,14 +5,61 @@ use crate::sched::{
 };
 use crate::sys::AsFile;
 use crate::{Error, Result};
-use std::io;
-use std::{convert::TryInto, os::unix::prelude::AsRawFd};
-use yanix::file::fionread;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::{
+    convert::TryInto,
+    os::unix::prelude::{AsRawFd, FromRawFd},
+};
+use yanix::fcntl;
+use yanix::file::{fionread, FdFlags, OFlags};
 use yanix::poll::{poll, PollFd, PollFlags};
 
+/// A self-pipe which interrupts `oneoff` when written to, letting `sched::PollWaker`s wake up a
+/// host `poll` that also waits on virtual handles.
+#[derive(Debug)]
+pub(crate) struct Wakeup {
+    read: File,
+    write: File,
+}
+
+impl Wakeup {
+    pub(crate) fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        for file in &[&read, &write] {
+            unsafe {
+                fcntl::set_fd_flags(file.as_raw_fd(), FdFlags::CLOEXEC)?;
+                fcntl::set_status_flags(file.as_raw_fd(), OFlags::NONBLOCK)?;
+            }
+        }
+        Ok(Self { read, write })
+    }
+
+    pub(crate) fn wake(&self) {
+        // If the pipe is full, a wakeup is already pending.
+        let _ = (&self.write).write(&[0]);
+    }
+
+    fn drain(&self) {
+        let mut buf = [0; 64];
+        while let Ok(n) = (&self.read).read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+    }
+}
+
+/// Poll the OS-backed `fd_events`, blocking for at most `timeout` (forever if it's `None`), or
+/// until `wakeup` is triggered.
 pub(crate) fn oneoff(
     timeout: Option<ClockEventData>,
     fd_events: Vec<FdEventData>,
+    wakeup: Option<&Wakeup>,
     events: &mut Vec<Event>,
 ) -> Result<()> {
     if fd_events.is_empty() && timeout.is_none() {
@@ -36,6 +83,10 @@ pub(crate) fn oneoff(
         })
         .collect();
     let mut poll_fds = poll_fds?;
+    if let Some(wakeup) = wakeup {
+        // Goes last, so that zipping `fd_events` with `poll_fds` below leaves it out.
+        poll_fds.push(unsafe { PollFd::new(wakeup.read.as_raw_fd(), PollFlags::POLLIN) });
+    }
 
     let poll_timeout = timeout.map_or(-1, |timeout| {
         let delay = timeout.delay / 1_000_000; // poll syscall requires delay to expressed in milliseconds
@@ -59,10 +110,19 @@ pub(crate) fn oneoff(
         }
     };
 
+    if let Some(wakeup) = wakeup {
+        let revents = poll_fds.last().and_then(|fd| fd.revents());
+        if revents.map_or(false, |revents| !revents.is_empty()) {
+            wakeup.drain();
+        }
+    }
+
     Ok(if ready == 0 {
         handle_timeout_event(timeout.expect("timeout should not be None"), events)
     } else {
-        let ready_events = fd_events.into_iter().zip(poll_fds.into_iter()).take(ready);
+        // `ready` counts the descriptors with non-empty `revents`, which aren't necessarily the
+        // first `ready` ones; `handle_fd_event` skips those without any.
+        let ready_events = fd_events.into_iter().zip(poll_fds.into_iter());
         handle_fd_event(ready_events, events)?
     })
 }
@@ -10,14 +10,55 @@ use crate::sys::AsFile;
 use crate::{Error, Result};
 use lazy_static::lazy_static;
 use std::convert::TryInto;
+use std::io;
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
-use std::sync::Mutex;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, trace, warn};
 
+/// Interrupts `oneoff` when triggered, letting `sched::PollWaker`s wake up a host poll that also
+/// waits on virtual handles. The stdin worker thread triggers it too once stdin is ready.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Wakeup {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Wakeup {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub(crate) fn wake(&self) {
+        let (woken, cond) = &*self.inner;
+        *woken.lock().unwrap() = true;
+        cond.notify_all();
+    }
+
+    /// Block until triggered or `timeout` elapses, clearing the signal. Returns whether it was
+    /// triggered.
+    fn wait(&self, timeout: Option<Duration>) -> bool {
+        let (woken, cond) = &*self.inner;
+        let mut guard = woken.lock().unwrap();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while !*guard {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    guard = cond.wait_timeout(guard, deadline - now).unwrap().0;
+                }
+                None => guard = cond.wait(guard).unwrap(),
+            }
+        }
+        std::mem::replace(&mut *guard, false)
+    }
+}
+
 struct StdinPoll {
-    request_tx: Sender<()>,
+    request_tx: Sender<Option<Wakeup>>,
     notify_rx: Receiver<PollState>,
 }
 
@@ -38,7 +79,7 @@ impl StdinPoll {
     // This function should not be used directly
     // Correctness of this function crucially depends on the fact that
     // mpsc::Receiver is !Sync.
-    fn poll(&self, wait_mode: WaitMode) -> PollState {
+    fn poll(&self, wait_mode: WaitMode, wakeup: Option<&Wakeup>) -> PollState {
         // Clean up possible unread result from the previous poll
         match self.notify_rx.try_recv() {
             Ok(_) | Err(TryRecvError::Empty) => {}
@@ -46,7 +87,28 @@ impl StdinPoll {
         }
 
         // Notify the worker thread that we want to poll stdin
-        self.request_tx.send(()).expect("request_tx channel closed");
+        self.request_tx
+            .send(wakeup.cloned())
+            .expect("request_tx channel closed");
+
+        // With a wakeup, the worker thread triggers it once stdin is ready, so wait on that
+        // instead of the channel in order to also return when a virtual handle is ready.
+        if let Some(wakeup) = wakeup {
+            let timeout = match wait_mode {
+                WaitMode::Timeout(timeout) => Some(timeout),
+                WaitMode::Infinite => None,
+                WaitMode::Immediate => Some(Duration::from_secs(0)),
+            };
+            let woken = wakeup.wait(timeout);
+            return self.notify_rx.try_recv().unwrap_or_else(|e| match e {
+                TryRecvError::Disconnected => panic!("notify_rx channel closed"),
+                TryRecvError::Empty if woken => PollState::NotReady,
+                TryRecvError::Empty => match wait_mode {
+                    WaitMode::Immediate => PollState::NotReady,
+                    _ => PollState::TimedOut,
+                },
+            });
+        }
 
         // Wait for the worker thread to send a readiness notification
         let pollret = match wait_mode {
@@ -68,11 +130,11 @@ impl StdinPoll {
         pollret
     }
 
-    fn event_loop(request_rx: Receiver<()>, notify_tx: Sender<PollState>) -> ! {
+    fn event_loop(request_rx: Receiver<Option<Wakeup>>, notify_tx: Sender<PollState>) -> ! {
         use std::io::BufRead;
         loop {
             // Wait for the request to poll stdin
-            request_rx.recv().expect("request_rx channel closed");
+            let wakeup = request_rx.recv().expect("request_rx channel closed");
 
             // Wait for data to appear in stdin.
             // If `fill_buf` returns any slice, then it means that either
@@ -87,6 +149,9 @@ impl StdinPoll {
             // Notify the requestor about data in stdin. They may have already timed out,
             // then the next requestor will have to clean the channel.
             notify_tx.send(resp).expect("notify_tx channel closed");
+            if let Some(wakeup) = wakeup {
+                wakeup.wake();
+            }
         }
     }
 }
@@ -131,8 +196,21 @@ fn make_timeout_event(timeout: &ClockEventData) -> Event {
     }
 }
 
-fn handle_timeout(timeout_event: ClockEventData, timeout: Duration, events: &mut Vec<Event>) {
-    thread::sleep(timeout);
+fn handle_timeout(
+    timeout_event: ClockEventData,
+    timeout: Duration,
+    wakeup: Option<&Wakeup>,
+    events: &mut Vec<Event>,
+) {
+    match wakeup {
+        // Being woken up before the timeout elapses isn't an event of its own.
+        Some(wakeup) => {
+            if wakeup.wait(Some(timeout)) {
+                return;
+            }
+        }
+        None => thread::sleep(timeout),
+    }
     handle_timeout_event(timeout_event, events);
 }
 
@@ -176,9 +254,12 @@ fn handle_error_event(event: FdEventData, error: Errno, out_events: &mut Vec<Eve
     out_events.push(new_event);
 }
 
+/// Poll the OS-backed `fd_events`, blocking for at most `timeout` (forever if it's `None`), or
+/// until `wakeup` is triggered.
 pub(crate) fn oneoff(
     timeout: Option<ClockEventData>,
     fd_events: Vec<FdEventData>,
+    wakeup: Option<&Wakeup>,
     events: &mut Vec<Event>,
 ) -> Result<()> {
     let timeout = timeout
@@ -194,7 +275,7 @@ pub(crate) fn oneoff(
     // With no events to listen, poll_oneoff just becomes a sleep.
     if fd_events.is_empty() {
         match timeout {
-            Some((event, dur)) => return Ok(handle_timeout(event, dur, events)),
+            Some((event, dur)) => return Ok(handle_timeout(event, dur, wakeup, events)),
             // The implementation has to return Ok(()) in this case,
             // cf. the comment in src/hostcalls_impl/misc.rs
             None => return Ok(()),
@@ -282,7 +363,7 @@ pub(crate) fn oneoff(
                 None => WaitMode::Infinite,
             }
         };
-        let state = STDIN_POLL.lock().unwrap().poll(waitmode);
+        let state = STDIN_POLL.lock().unwrap().poll(waitmode, wakeup);
         for event in stdin_events {
             match state {
                 PollState::Ready => handle_rw_event(event, events),
@@ -300,12 +381,18 @@ pub(crate) fn oneoff(
                 // In the tests stdin is replaced with a dummy pipe, so for now
                 // we just time out. Support for pipes will be decided later on.
                 warn!("Polling pipes not supported on Windows, will just time out.");
-                handle_timeout(event, dur, events);
-            }
-            None => {
-                error!("Polling only pipes with no timeout not supported on Windows.");
-                return Err(Error::Notsup);
+                handle_timeout(event, dur, wakeup, events);
             }
+            None => match wakeup {
+                Some(wakeup) => {
+                    warn!("Polling pipes not supported on Windows, will wait for virtual handles.");
+                    wakeup.wait(None);
+                }
+                None => {
+                    error!("Polling only pipes with no timeout not supported on Windows.");
+                    return Err(Error::Notsup);
+                }
+            },
         }
     }
 
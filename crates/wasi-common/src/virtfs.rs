@@ -2,7 +2,7 @@ use crate::handle::{
     Advice, Dircookie, Dirent, Fdflags, Filesize, Filestat, Filetype, Fstflags, Handle,
     HandleRights, Oflags, Rights, RightsExt, Size, DIRCOOKIE_START,
 };
-use crate::sched::Timestamp;
+use crate::sched::{Eventtype, PollWaker, Readiness, Timestamp};
 use crate::{Error, Result};
 use std::any::Any;
 use std::cell::{Cell, RefCell};
@@ -341,6 +341,15 @@ impl Handle for InMemoryFile {
 
         Ok(written)
     }
+    fn poll_readiness(&self, ty: Eventtype, _waker: &PollWaker) -> Result<Option<Readiness>> {
+        // Reads and writes on in-memory files never block.
+        let nbytes = if ty == Eventtype::FdRead {
+            self.data.borrow().size().saturating_sub(self.cursor.get())
+        } else {
+            0
+        };
+        Ok(Some(Readiness::Ready { nbytes }))
+    }
     // PathOps
     fn create_directory(&self, _path: &str) -> Result<()> {
         Err(Error::Notdir)
@@ -502,6 +511,9 @@ impl Handle for VirtualDir {
         };
         Ok(stat)
     }
+    fn poll_readiness(&self, _ty: Eventtype, _waker: &PollWaker) -> Result<Option<Readiness>> {
+        Ok(Some(Readiness::Ready { nbytes: 0 }))
+    }
     fn readdir(
         &self,
         cookie: Dircookie,
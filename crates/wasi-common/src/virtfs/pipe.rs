@@ -7,15 +7,20 @@
 //! Some convenience constructors are included for common backing types like `Vec<u8>` and `String`,
 //! but the virtual pipes can be instantiated with any `Read` or `Write` type.
 //!
-//! Note that `poll_oneoff` is not supported for these types, so they do not match the behavior of
-//! real pipes exactly.
+//! Connected in-process pipes can be created with [`pipe`]. These take part in `poll_oneoff` like
+//! real pipes do: a guest polling the read end is woken up as soon as data is written to the write
+//! end. Pipes backed by other `Read` or `Write` types are always reported as ready, except for
+//! `io::Cursor`s, which report hangup once they have been read to the end.
 use crate::handle::{
     Advice, Fdflags, Filesize, Filestat, Filetype, Handle, HandleRights, Oflags, Rights,
 };
+use crate::sched::{Eventtype, PollWaker, Readiness};
 use crate::{Error, Result};
 use std::any::Any;
+use std::collections::VecDeque;
+use std::convert::TryInto;
 use std::io::{self, Read, Write};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 
 /// A virtual pipe read end.
 ///
@@ -173,6 +178,23 @@ impl<R: Read + Any> Handle for ReadPipe<R> {
         Ok(self.reader.write().unwrap().read_vectored(iovs)?)
     }
 
+    fn poll_readiness(&self, _ty: Eventtype, waker: &PollWaker) -> Result<Option<Readiness>> {
+        let reader = self.reader.read().unwrap();
+        let reader: &dyn Any = &*reader;
+        let readiness = if let Some(pipe) = reader.downcast_ref::<PipeReader>() {
+            pipe.readiness(waker)
+        } else if let Some(cursor) = reader.downcast_ref::<io::Cursor<Vec<u8>>>() {
+            cursor_readiness(cursor.get_ref().len(), cursor.position())?
+        } else if let Some(cursor) = reader.downcast_ref::<io::Cursor<String>>() {
+            cursor_readiness(cursor.get_ref().len(), cursor.position())?
+        } else {
+            // There's no way to tell whether an arbitrary reader would block, so report it as
+            // ready and let the read itself decide.
+            Readiness::Ready { nbytes: 0 }
+        };
+        Ok(Some(readiness))
+    }
+
     fn create_directory(&self, _path: &str) -> Result<()> {
         Err(Error::Notdir)
     }
@@ -223,6 +245,17 @@ impl<R: Read + Any> Handle for ReadPipe<R> {
     }
 }
 
+fn cursor_readiness(len: usize, position: u64) -> Result<Readiness> {
+    let len: u64 = len.try_into()?;
+    if position >= len {
+        Ok(Readiness::Hangup)
+    } else {
+        Ok(Readiness::Ready {
+            nbytes: len - position,
+        })
+    }
+}
+
 /// A virtual pipe write end.
 #[derive(Debug)]
 pub struct WritePipe<W: Write + Any> {
@@ -352,6 +385,16 @@ impl<W: Write + Any> Handle for WritePipe<W> {
         Ok(self.writer.write().unwrap().write_vectored(iovs)?)
     }
 
+    fn poll_readiness(&self, _ty: Eventtype, waker: &PollWaker) -> Result<Option<Readiness>> {
+        let writer = self.writer.read().unwrap();
+        let writer: &dyn Any = &*writer;
+        let readiness = match writer.downcast_ref::<PipeWriter>() {
+            Some(pipe) => pipe.readiness(waker),
+            None => Readiness::Ready { nbytes: 0 },
+        };
+        Ok(Some(readiness))
+    }
+
     fn create_directory(&self, _path: &str) -> Result<()> {
         Err(Error::Notdir)
     }
@@ -401,3 +444,256 @@ impl<W: Write + Any> Handle for WritePipe<W> {
         Err(Error::Notdir)
     }
 }
+
+/// Create a connected pair of in-process pipe ends.
+///
+/// Bytes written to the `WritePipe` can be read from the `ReadPipe`. Unlike pipes backed by other
+/// `Read` types, reading from an empty pipe blocks until data is written or all clones of the write
+/// end are dropped, and a guest polling the read end with `poll_oneoff` is woken up as soon as data
+/// arrives. For example, to feed a guest's stdin from another thread:
+///
+/// ```
+/// # use std::io::IoSlice;
+/// # use wasi_common::{Handle, WasiCtxBuilder};
+/// # use wasi_common::virtfs::pipe::pipe;
+/// let (stdin, stdin_writer) = pipe();
+/// let mut ctx = WasiCtxBuilder::new();
+/// ctx.stdin(stdin);
+/// std::thread::spawn(move || {
+///     let message = IoSlice::new(b"hello from another thread!");
+///     stdin_writer.write_vectored(&[message]).unwrap();
+/// });
+/// ```
+pub fn pipe() -> (ReadPipe<PipeReader>, WritePipe<PipeWriter>) {
+    let shared = Arc::new(PipeShared::default());
+    let reader = PipeReader {
+        shared: shared.clone(),
+    };
+    let writer = PipeWriter { shared };
+    (ReadPipe::new(reader), WritePipe::new(writer))
+}
+
+#[derive(Debug, Default)]
+struct PipeState {
+    buffer: VecDeque<u8>,
+    reader_closed: bool,
+    writer_closed: bool,
+    wakers: Vec<PollWaker>,
+}
+
+impl PipeState {
+    fn register(&mut self, waker: &PollWaker) {
+        // Wakers of `poll_oneoff` calls which returned without this pipe becoming ready would
+        // otherwise pile up for as long as the pipe stays empty.
+        self.wakers.retain(|w| !w.is_stale());
+        if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PipeShared {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+/// The read end of an in-process pipe created with [`pipe`].
+#[derive(Debug)]
+pub struct PipeReader {
+    shared: Arc<PipeShared>,
+}
+
+impl PipeReader {
+    fn readiness(&self, waker: &PollWaker) -> Readiness {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.buffer.is_empty() {
+            Readiness::Ready {
+                nbytes: state.buffer.len() as Filesize,
+            }
+        } else if state.writer_closed {
+            Readiness::Hangup
+        } else {
+            state.register(waker);
+            Readiness::Pending
+        }
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.buffer.is_empty() && !state.writer_closed && !buf.is_empty() {
+            state = self.shared.readable.wait(state).unwrap();
+        }
+        let count = std::cmp::min(buf.len(), state.buffer.len());
+        for (dst, src) in buf.iter_mut().zip(state.buffer.drain(..count)) {
+            *dst = src;
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.reader_closed = true;
+        state.wake_all();
+    }
+}
+
+/// The write end of an in-process pipe created with [`pipe`].
+#[derive(Debug)]
+pub struct PipeWriter {
+    shared: Arc<PipeShared>,
+}
+
+impl PipeWriter {
+    fn readiness(&self, _waker: &PollWaker) -> Readiness {
+        // The pipe buffer is unbounded, so writing only fails once the read end is gone.
+        if self.shared.state.lock().unwrap().reader_closed {
+            Readiness::Hangup
+        } else {
+            Readiness::Ready { nbytes: 0 }
+        }
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.reader_closed {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        state.buffer.extend(buf);
+        state.wake_all();
+        self.shared.readable.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.writer_closed = true;
+        state.wake_all();
+        self.shared.readable.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sched::Poller;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn pipe_readiness() {
+        let (reader, writer) = pipe();
+        let poller = Poller::new();
+        let waker = poller.waker();
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Pending)
+        );
+        assert_eq!(
+            writer.poll_readiness(Eventtype::FdWrite, &waker).unwrap(),
+            Some(Readiness::Ready { nbytes: 0 })
+        );
+
+        writer
+            .write_vectored(&[io::IoSlice::new(b"hello")])
+            .unwrap();
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Ready { nbytes: 5 })
+        );
+
+        let mut buf = [0; 5];
+        reader
+            .read_vectored(&mut [io::IoSliceMut::new(&mut buf)])
+            .unwrap();
+        assert_eq!(&buf, b"hello");
+
+        drop(writer);
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Hangup)
+        );
+    }
+
+    #[test]
+    fn pipe_write_wakes_poller() {
+        let (reader, writer) = pipe();
+        let poller = Poller::new();
+        let waker = poller.waker();
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Pending)
+        );
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.write_vectored(&[io::IoSlice::new(b"x")]).unwrap();
+        });
+        // Only returns once the writer has woken us up.
+        poller.wait(None);
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Ready { nbytes: 1 })
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn cursor_pipe_readiness() {
+        let reader = ReadPipe::from("abc");
+        let poller = Poller::new();
+        let waker = poller.waker();
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Ready { nbytes: 3 })
+        );
+        let mut buf = [0; 3];
+        reader
+            .read_vectored(&mut [io::IoSliceMut::new(&mut buf)])
+            .unwrap();
+        assert_eq!(
+            reader.poll_readiness(Eventtype::FdRead, &waker).unwrap(),
+            Some(Readiness::Hangup)
+        );
+    }
+
+    #[test]
+    fn stale_wakers_are_dropped() {
+        let (reader, _writer) = pipe();
+        for _ in 0..10 {
+            let poller = Poller::new();
+            assert_eq!(
+                reader
+                    .poll_readiness(Eventtype::FdRead, &poller.waker())
+                    .unwrap(),
+                Some(Readiness::Pending)
+            );
+        }
+        let poller = Poller::new();
+        let waker = poller.waker();
+        reader.poll_readiness(Eventtype::FdRead, &waker).unwrap();
+        reader.poll_readiness(Eventtype::FdRead, &waker).unwrap();
+        let pipe = reader.reader.read().unwrap();
+        let state = pipe.shared.state.lock().unwrap();
+        assert_eq!(state.wakers.len(), 1);
+        assert!(state.wakers[0].will_wake(&waker));
+    }
+}
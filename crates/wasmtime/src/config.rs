@@ -32,6 +32,7 @@ pub struct Config {
     pub(crate) memory_creator: Option<MemoryCreatorProxy>,
     pub(crate) max_wasm_stack: usize,
     pub(crate) features: WasmFeatures,
+    pub(crate) async_support: bool,
 }
 
 impl Config {
@@ -77,6 +78,7 @@ impl Config {
                 multi_value: true,
                 ..WasmFeatures::default()
             },
+            async_support: false,
         }
     }
 
@@ -120,6 +122,32 @@ impl Config {
         self
     }
 
+    /// Configures whether stores of this configuration support async host
+    /// functions and calling WebAssembly asynchronously.
+    ///
+    /// With async support enabled, WebAssembly in such stores always runs on a
+    /// separate native stack, a fiber, so that it can be suspended whenever an
+    /// async host function defined with
+    /// [`Func::wrap_async`](crate::Func::wrap_async) isn't ready yet. Instances
+    /// must then be created with
+    /// [`Instance::new_async`](crate::Instance::new_async) and functions called
+    /// with [`Func::call_async`](crate::Func::call_async), which return futures
+    /// that resolve once the WebAssembly has finished. The synchronous
+    /// [`Instance::new`](crate::Instance::new) and
+    /// [`Func::call`](crate::Func::call) panic in these stores, except within
+    /// host functions that are already running on a fiber.
+    ///
+    /// Each fiber's stack has room for [`Config::max_wasm_stack`] bytes of
+    /// WebAssembly plus another 1 MB for the host functions it calls.
+    ///
+    /// This method is only available when the `async` feature of this crate is
+    /// enabled. By default this option is `false`.
+    #[cfg(feature = "async")]
+    pub fn async_support(&mut self, enable: bool) -> &mut Self {
+        self.async_support = enable;
+        self
+    }
+
     /// Configures whether the WebAssembly threads proposal will be enabled for
     /// compilation.
     ///
@@ -285,6 +313,12 @@ impl Config {
             Strategy::Lightbeam => {
                 anyhow::bail!("lightbeam compilation strategy wasn't enabled at compile time");
             }
+            #[cfg(feature = "interpreter")]
+            Strategy::Interpreter => CompilationStrategy::Interpreter,
+            #[cfg(not(feature = "interpreter"))]
+            Strategy::Interpreter => {
+                anyhow::bail!("interpreter strategy wasn't enabled at compile time");
+            }
         };
         Ok(self)
     }
@@ -353,6 +387,48 @@ impl Config {
         self
     }
 
+    /// Configures whether Cranelift should harden generated code against
+    /// Spectre attacks.
+    ///
+    /// With this enabled, the bounds checks of linear memories and tables,
+    /// and the signature checks of indirect calls, are followed by
+    /// conditional moves that replace the accessed address or the called
+    /// function with a null pointer when the check fails, even if the check
+    /// was mispredicted. This is recommended when mutually distrusting
+    /// WebAssembly modules run in the same process.
+    ///
+    /// The default value for this is `true`.
+    pub fn cranelift_spectre_mitigations(&mut self, enable: bool) -> &mut Self {
+        let val = if enable { "true" } else { "false" };
+        for flag in &[
+            "enable_heap_access_spectre_mitigation",
+            "enable_table_access_spectre_mitigation",
+        ] {
+            self.flags.set(flag, val).expect("should be valid flag");
+        }
+        self
+    }
+
+    /// Configures whether Cranelift should generate branchless code for
+    /// operations on values that may be secret.
+    ///
+    /// With this enabled, selects, integer and float minimum and maximum,
+    /// and saturating arithmetic are compiled to conditional moves instead
+    /// of branches, so that their timing doesn't depend on the operands.
+    /// Only the new backends support this: on x86_64 this requires the
+    /// `experimental_x64` feature, and compiling a module fails otherwise.
+    /// Branches to traps, such as the division overflow checks, are still
+    /// emitted.
+    ///
+    /// The default value for this is `false`.
+    pub fn cranelift_constant_time(&mut self, enable: bool) -> &mut Self {
+        let val = if enable { "true" } else { "false" };
+        self.flags
+            .set("enable_constant_time", val)
+            .expect("should be valid flag");
+        self
+    }
+
     /// Allows settings another Cranelift flag defined by a flag name and value. This allows
     /// fine-tuning of Cranelift settings.
     ///
@@ -619,7 +695,24 @@ impl Config {
 
     pub(crate) fn build_compiler(&self) -> Compiler {
         let isa = self.target_isa();
-        Compiler::new(isa, self.strategy, self.tunables.clone(), self.features)
+        #[allow(unused_mut)]
+        let mut tunables = self.tunables.clone();
+        #[cfg(feature = "interpreter")]
+        {
+            // Interpreted code accesses memory directly and relies on explicit
+            // bounds checks rather than guard pages, so force all memories to
+            // be dynamic and unguarded.
+            if let CompilationStrategy::Interpreter = self.strategy {
+                tunables.static_memory_bound = 0;
+                tunables.static_memory_offset_guard_size = 0;
+                tunables.dynamic_memory_offset_guard_size = 0;
+            }
+        }
+        #[allow(unused_mut)]
+        let mut compiler = Compiler::new(isa, self.strategy, tunables, self.features);
+        #[cfg(feature = "cache")]
+        compiler.set_cache_config(self.cache_config.clone());
+        compiler
     }
 }
 
@@ -683,6 +776,26 @@ pub enum Strategy {
     /// To successfully pass this argument to [`Config::strategy`] the
     /// `lightbeam` feature of this crate must be enabled.
     Lightbeam,
+
+    /// Translates wasm to Cranelift IR like [`Strategy::Cranelift`], but
+    /// interprets the IR with `cranelift-interpreter` instead of compiling it
+    /// to native code.
+    ///
+    /// This is far slower than compiling and is intended for testing and
+    /// fuzzing the rest of Cranelift against. Memories always use dynamic
+    /// bounds checks, and neither reference types nor debug information are
+    /// supported.
+    ///
+    /// Loading and calling interpreted modules doesn't allocate any
+    /// executable memory, so this strategy also works where W^X is enforced.
+    /// Host functions defined with [`Func::new`](crate::Func::new) still
+    /// compile a native stub, though. Interpreted code can call native
+    /// functions only on x86_64 (except Windows) and aarch64 hosts, and only
+    /// with at most 8 integer and 8 float arguments and at most one result.
+    ///
+    /// To successfully pass this argument to [`Config::strategy`] the
+    /// `interpreter` feature of this crate must be enabled.
+    Interpreter,
 }
 
 /// Possible optimization levels for the Cranelift codegen backend.
@@ -712,3 +825,26 @@ pub enum ProfilingStrategy {
     /// Collect profiling info using the "ittapi", used with `VTune` on Linux.
     VTune,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn spectre_mitigations_set_both_flags() {
+        let mut config = Config::new();
+        let isa = config.target_isa();
+        assert!(isa.flags().enable_heap_access_spectre_mitigation());
+        assert!(isa.flags().enable_table_access_spectre_mitigation());
+
+        config.cranelift_spectre_mitigations(false);
+        let isa = config.target_isa();
+        assert!(!isa.flags().enable_heap_access_spectre_mitigation());
+        assert!(!isa.flags().enable_table_access_spectre_mitigation());
+
+        config.cranelift_spectre_mitigations(true);
+        let isa = config.target_isa();
+        assert!(isa.flags().enable_heap_access_spectre_mitigation());
+        assert!(isa.flags().enable_table_access_spectre_mitigation());
+    }
+}
@@ -145,4 +145,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn cache_reuses_unchanged_functions() -> Result<()> {
+        let td = TempDir::new()?;
+        let config_path = td.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            &format!(
+                "
+                    [cache]
+                    enabled = true
+                    directory = '{}'
+                ",
+                td.path().join("cache").display()
+            ),
+        )?;
+        let mut cfg = Config::new();
+        cfg.cache_config_load(&config_path)?;
+        let engine = Engine::new(&cfg);
+        let cache_config = &engine.config().cache_config;
+        let types = "(type (func)) (type (func (result i32)))";
+        Module::new(
+            &engine,
+            &format!("(module {} (func) (func (result i32) i32.const 1))", types),
+        )?;
+        assert_eq!(cache_config.function_cache_hits(), 0);
+        assert_eq!(cache_config.function_cache_misses(), 2);
+
+        // Only the functions which changed are compiled again, even when the
+        // others move in the module.
+        Module::new(
+            &engine,
+            &format!(
+                "(module {} (func) (func (result i32) i32.const 1000))",
+                types
+            ),
+        )?;
+        assert_eq!(cache_config.cache_hits(), 0);
+        assert_eq!(cache_config.cache_misses(), 2);
+        assert_eq!(cache_config.function_cache_hits(), 1);
+        assert_eq!(cache_config.function_cache_misses(), 3);
+        Module::new(
+            &engine,
+            &format!(
+                "(module {} (func nop) (func (result i32) i32.const 1000))",
+                types
+            ),
+        )?;
+        assert_eq!(cache_config.function_cache_hits(), 2);
+        assert_eq!(cache_config.function_cache_misses(), 4);
+
+        // Cached functions are still validated against their module.
+        Module::new(&engine, "(module (func data.drop 0) (data \"\"))")?;
+        assert_eq!(cache_config.function_cache_hits(), 2);
+        assert_eq!(cache_config.function_cache_misses(), 5);
+        assert!(Module::new(&engine, "(module (func data.drop 0))").is_err());
+        assert_eq!(cache_config.function_cache_hits(), 3);
+        assert_eq!(cache_config.function_cache_misses(), 5);
+
+        Ok(())
+    }
 }
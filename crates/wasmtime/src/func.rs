@@ -5,8 +5,12 @@ use anyhow::{bail, ensure, Context as _, Result};
 use smallvec::{smallvec, SmallVec};
 use std::cmp::max;
 use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
 use std::mem;
 use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "async")]
+use std::pin::Pin;
 use std::ptr::{self, NonNull};
 use std::rc::Weak;
 use wasmtime_runtime::{
@@ -183,6 +187,8 @@ macro_rules! getters {
             // call it.
             let instance = self.instance.clone();
             let anyfunc = self.export.anyfunc;
+            let interpreted =
+                interpreted_trampoline(unsafe { anyfunc.as_ref().func_ptr.as_ptr() });
 
             // ... and then once we've passed the typechecks we can hand out our
             // object since our `transmute` below should be safe!
@@ -215,12 +221,31 @@ macro_rules! getters {
                         let $args = $args.into_abi_for_arg(weak_store);
                     )*
 
+                    // Interpreted functions have no code to jump into, so
+                    // they're called through their trampoline after all.
+                    let mut values_vec: Vec<u128> = Vec::new();
+                    if interpreted.is_some() {
+                        $( values_vec.push(abi_slot($args)); )*
+                        values_vec.resize(max(values_vec.len(), 1), 0);
+                    }
+
                     invoke_wasm_and_catch_traps(anyfunc.as_ref().vmctx, &instance.store, || {
-                        ret = Some(fnptr(
-                            anyfunc.as_ref().vmctx,
-                            ptr::null_mut(),
-                            $( $args, )*
-                        ));
+                        ret = Some(match interpreted {
+                            Some(trampoline) => {
+                                trampoline(
+                                    anyfunc.as_ref().vmctx,
+                                    ptr::null_mut(),
+                                    anyfunc.as_ref().func_ptr.as_ptr(),
+                                    values_vec.as_mut_ptr(),
+                                );
+                                ptr::read(values_vec.as_ptr() as *const R::Abi)
+                            }
+                            None => fnptr(
+                                anyfunc.as_ref().vmctx,
+                                ptr::null_mut(),
+                                $( $args, )*
+                            ),
+                        });
                     })?;
 
                     Ok(R::from_abi(ret.unwrap(), weak_store))
@@ -532,6 +557,69 @@ impl Func {
         func.into_func(store)
     }
 
+    /// Creates a new `Func` from the given Rust closure, which returns a
+    /// future that resolves to the function's result.
+    ///
+    /// This is the same as [`Func::wrap`] except that the closure returns a
+    /// [`Future`] of what it would otherwise return, which lets it wait for
+    /// I/O or other events without blocking the thread. While the future isn't
+    /// ready, the wasm calling this function is suspended, and the future
+    /// returned by [`Func::call_async`] or [`Instance::new_async`] that's
+    /// running it is pending. Once it's ready, the wasm is resumed with its
+    /// result.
+    ///
+    /// The returned future must be `'static`, so it can't borrow the closure's
+    /// [`Caller`]: anything needed from the caller, such as its memory, has to
+    /// be looked up before the future is created.
+    ///
+    /// If the future running the wasm is dropped while this function is
+    /// waiting, then its future is dropped as well and a trap is raised in the
+    /// wasm that called it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the store doesn't have
+    /// [`Config::async_support`](crate::Config::async_support) enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use wasmtime::*;
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let engine = Engine::new(Config::new().async_support(true));
+    /// let store = Store::new(&engine);
+    /// let sleep = Func::wrap_async(&store, |ms: u32| async move {
+    ///     // Wait for a timer of the host's executor here, without blocking
+    ///     // the thread.
+    ///     # let _ = ms;
+    /// });
+    /// let module = Module::new(
+    ///     &engine,
+    ///     r#"
+    ///         (module
+    ///             (import "" "sleep" (func $sleep (param i32)))
+    ///             (func (export "run")
+    ///                 i32.const 100
+    ///                 call $sleep))
+    ///     "#,
+    /// )?;
+    /// let instance = Instance::new_async(&store, &module, &[sleep.into()]).await?;
+    /// instance.get_func("run").unwrap().call_async(&[]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn wrap_async<Params, Results>(
+        store: &Store,
+        func: impl IntoAsyncFunc<Params, Results>,
+    ) -> Func {
+        assert!(
+            store.async_support(),
+            "cannot use `wrap_async` without enabling async support in the config"
+        );
+        func.into_async_func(store)
+    }
+
     pub(crate) fn sig_index(&self) -> VMSharedSignatureIndex {
         unsafe { self.export.anyfunc.as_ref().type_index }
     }
@@ -578,6 +666,12 @@ impl Func {
     ///
     /// This function should not panic unless the underlying function itself
     /// initiates a panic.
+    ///
+    /// # Panics
+    ///
+    /// In stores with [`Config::async_support`](crate::Config::async_support)
+    /// enabled this panics unless it's called from a host function that wasm
+    /// called. Use [`Func::call_async`] instead.
     pub fn call(&self, params: &[Val]) -> Result<Box<[Val]>> {
         // We need to perform a dynamic check that the arguments given to us
         // match the signature of this function and are appropriate to pass to
@@ -638,6 +732,61 @@ impl Func {
         Ok(results.into())
     }
 
+    /// Invokes this function with the `params` given, returning a future that
+    /// resolves to the results, or to any trap that occurs.
+    ///
+    /// This is the same as [`Func::call`], except for stores with
+    /// [`Config::async_support`](crate::Config::async_support) enabled. The
+    /// wasm runs on a fiber whenever the returned future is polled, until it
+    /// either finishes or has to wait for an async host function defined with
+    /// [`Func::wrap_async`], in which case the future is pending.
+    ///
+    /// Dropping the returned future before it has resolved raises a trap in
+    /// any pending async host function, unwinding the wasm that called it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the store doesn't have async support enabled.
+    #[cfg(feature = "async")]
+    pub async fn call_async(&self, params: &[Val]) -> Result<Box<[Val]>> {
+        assert!(
+            self.store().async_support(),
+            "cannot use `call_async` without enabling async support in the config"
+        );
+        self.store().on_fiber(|| self.call(params)).await?
+    }
+
+    /// Invokes this function with raw, untyped arguments, writing the results
+    /// back into the same buffer.
+    ///
+    /// `params_and_returns` must point to a buffer of
+    /// `max(self.param_arity(), self.result_arity())` slots. On entry the
+    /// first `param_arity` slots hold the arguments, and on successful return
+    /// the first `result_arity` slots hold the results. Each value is stored
+    /// in the low bytes of its slot, in the same little-endian layout used for
+    /// `i32`, `i64`, `f32`, `f64`, and `v128` values by [`Func::call`].
+    ///
+    /// Unlike [`Func::call`] this performs no type checking and no allocation,
+    /// which makes it suitable for calling small functions at a high rate.
+    ///
+    /// # Unsafety
+    ///
+    /// The caller must ensure that the buffer is large enough and that every
+    /// argument slot holds a value of the parameter type the function expects.
+    /// Functions with `externref` or `funcref` parameters or results are not
+    /// supported by this method and must be called with [`Func::call`].
+    pub unsafe fn call_unchecked(&self, params_and_returns: *mut u128) -> Result<(), Trap> {
+        let anyfunc = self.export.anyfunc.as_ref();
+        invoke_wasm_and_catch_traps(anyfunc.vmctx, &self.instance.store, || {
+            (self.trampoline)(
+                anyfunc.vmctx,
+                ptr::null_mut(),
+                anyfunc.func_ptr.as_ptr(),
+                params_and_returns,
+            )
+        })
+    }
+
     pub(crate) fn caller_checked_anyfunc(
         &self,
     ) -> NonNull<wasmtime_runtime::VMCallerCheckedAnyfunc> {
@@ -650,14 +799,18 @@ impl Func {
     ) -> Self {
         // Each function signature in a module should have a trampoline stored
         // on that module as well, so unwrap the result here since otherwise
-        // it's a bug in wasmtime.
-        let trampoline = instance
-            .store
-            .signatures()
-            .borrow()
-            .lookup_shared(unsafe { export.anyfunc.as_ref().type_index })
-            .expect("failed to retrieve trampoline from module")
-            .1;
+        // it's a bug in wasmtime. Interpreted functions are the exception: they
+        // all share a trampoline of their own.
+        let anyfunc = unsafe { export.anyfunc.as_ref() };
+        let trampoline = interpreted_trampoline(anyfunc.func_ptr.as_ptr()).unwrap_or_else(|| {
+            instance
+                .store
+                .signatures()
+                .borrow()
+                .lookup_shared(anyfunc.type_index)
+                .and_then(|(_, trampoline)| trampoline)
+                .expect("failed to retrieve trampoline from module")
+        });
 
         Func {
             instance,
@@ -814,11 +967,37 @@ impl fmt::Debug for Func {
     }
 }
 
+/// Returns the trampoline through which the function at `func_ptr` must be
+/// called if it's interpreted, in which case it has no code to call directly.
+#[cfg(feature = "interpreter")]
+pub(crate) fn interpreted_trampoline(func_ptr: *const VMFunctionBody) -> Option<VMTrampoline> {
+    wasmtime_jit::interpreted_trampoline(func_ptr)
+}
+
+#[cfg(not(feature = "interpreter"))]
+pub(crate) fn interpreted_trampoline(_func_ptr: *const VMFunctionBody) -> Option<VMTrampoline> {
+    None
+}
+
+/// Stores `abi` in the low bytes of a `values_vec` slot, the same way
+/// `Val::write_value_to` stores values.
+fn abi_slot<T: Copy>(abi: T) -> u128 {
+    assert!(mem::size_of::<T>() <= mem::size_of::<u128>());
+    let mut slot = 0u128;
+    unsafe { ptr::write(&mut slot as *mut u128 as *mut T, abi) };
+    slot
+}
+
 pub(crate) fn invoke_wasm_and_catch_traps(
     vmctx: *mut VMContext,
     store: &Store,
     closure: impl FnMut(),
 ) -> Result<(), Trap> {
+    assert!(
+        store.can_call_synchronously(),
+        "wasm must be called with `Func::call_async` or `Instance::new_async` \
+         in stores with async support enabled"
+    );
     let signalhandler = store.signal_handler();
     unsafe {
         let canary = 0;
@@ -1425,6 +1604,58 @@ where
     }
 }
 
+/// The result of an async host function, which raises a trap in its caller
+/// instead if the future running the wasm was dropped while it was pending.
+#[cfg(feature = "async")]
+struct AsyncResult<R>(Result<R, Trap>);
+
+#[cfg(feature = "async")]
+unsafe impl<R> WasmRet for AsyncResult<R>
+where
+    R: WasmRet,
+{
+    type Abi = R::Abi;
+
+    #[inline]
+    fn compatible_with_store<'a>(&self, store: WeakStore<'a>) -> bool {
+        match &self.0 {
+            Ok(x) => x.compatible_with_store(store),
+            Err(_) => true,
+        }
+    }
+
+    #[inline]
+    unsafe fn into_abi_for_ret<'a>(self, store: WeakStore<'a>) -> Self::Abi {
+        match self.0 {
+            Ok(val) => val.into_abi_for_ret(store),
+            Err(trap) => raise_user_trap(trap.into()),
+        }
+    }
+
+    #[inline]
+    unsafe fn from_abi<'a>(abi: Self::Abi, store: WeakStore<'a>) -> Self {
+        AsyncResult(Ok(R::from_abi(abi, store)))
+    }
+
+    fn valtype() -> Option<ValType> {
+        R::valtype()
+    }
+
+    fn matches(tys: impl Iterator<Item = ValType>) -> anyhow::Result<()> {
+        R::matches(tys)
+    }
+
+    #[inline]
+    unsafe fn load_from_args(ptr: &mut *const u128) -> Self::Abi {
+        R::load_from_args(ptr)
+    }
+
+    #[inline]
+    unsafe fn store_to_args(abi: Self::Abi, ptr: *mut u128) {
+        R::store_to_args(abi, ptr);
+    }
+}
+
 /// Internal trait implemented for all arguments that can be passed to
 /// [`Func::wrap`].
 ///
@@ -1435,6 +1666,17 @@ pub trait IntoFunc<Params, Results> {
     fn into_func(self, store: &Store) -> Func;
 }
 
+/// Internal trait implemented for all arguments that can be passed to
+/// [`Func::wrap_async`].
+///
+/// This trait should not be implemented by external users, it's only intended
+/// as an implementation detail of this crate.
+#[cfg(feature = "async")]
+pub trait IntoAsyncFunc<Params, Results> {
+    #[doc(hidden)]
+    fn into_async_func(self, store: &Store) -> Func;
+}
+
 /// A structure representing the *caller's* context when creating a function
 /// via [`Func::wrap`].
 ///
@@ -1691,6 +1933,51 @@ macro_rules! impl_into_func {
     )*)
 }
 
+#[cfg(feature = "async")]
+macro_rules! impl_into_async_func {
+    ($(
+        ($($args:ident)*)
+    )*) => ($(
+        // Implement for functions without a leading `&Caller` parameter,
+        // delegating to the implementation below which does have the leading
+        // `Caller` parameter.
+        impl<F, Fut, $($args,)* R> IntoAsyncFunc<($($args,)*), R> for F
+        where
+            F: Fn($($args),*) -> Fut + 'static,
+            Fut: Future<Output = R> + 'static,
+            $($args: WasmTy,)*
+            R: WasmRet,
+        {
+            #[allow(non_snake_case)]
+            fn into_async_func(self, store: &Store) -> Func {
+                Func::wrap_async(store, move |_: Caller<'_>, $($args:$args),*| {
+                    self($($args),*)
+                })
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<F, Fut, $($args,)* R> IntoAsyncFunc<(Caller<'_>, $($args,)*), R> for F
+        where
+            F: Fn(Caller<'_>, $($args),*) -> Fut + 'static,
+            Fut: Future<Output = R> + 'static,
+            $($args: WasmTy,)*
+            R: WasmRet,
+        {
+            fn into_async_func(self, store: &Store) -> Func {
+                // Async host functions are synchronous host functions which
+                // block their fiber on the future.
+                Func::wrap(store, move |caller: Caller<'_>, $($args:$args),*| {
+                    let store = caller.store();
+                    let mut future = self(caller, $($args),*);
+                    let future = unsafe { Pin::new_unchecked(&mut future) };
+                    AsyncResult(store.block_on(future))
+                })
+            }
+        }
+    )*)
+}
+
 impl_into_func! {
     ()
     (A1)
@@ -1711,6 +1998,27 @@ impl_into_func! {
     (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15 A16)
 }
 
+#[cfg(feature = "async")]
+impl_into_async_func! {
+    ()
+    (A1)
+    (A1 A2)
+    (A1 A2 A3)
+    (A1 A2 A3 A4)
+    (A1 A2 A3 A4 A5)
+    (A1 A2 A3 A4 A5 A6)
+    (A1 A2 A3 A4 A5 A6 A7)
+    (A1 A2 A3 A4 A5 A6 A7 A8)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15)
+    (A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12 A13 A14 A15 A16)
+}
+
 #[test]
 fn wasm_ty_roundtrip() -> Result<(), anyhow::Error> {
     use crate::*;
@@ -26,6 +26,7 @@ fn instantiate(
             host,
             store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
             store.stack_map_registry() as *const StackMapRegistry as *mut _,
+            Some(store.limiter()),
         )?;
 
         // After we've created the `InstanceHandle` we still need to run
@@ -65,13 +66,19 @@ fn instantiate(
         };
         let vmctx_ptr = instance.handle.vmctx_ptr();
         unsafe {
-            super::func::invoke_wasm_and_catch_traps(vmctx_ptr, store, || {
-                mem::transmute::<
+            let anyfunc = f.anyfunc.as_ref();
+            let interpreted = super::func::interpreted_trampoline(anyfunc.func_ptr.as_ptr());
+            super::func::invoke_wasm_and_catch_traps(vmctx_ptr, store, || match interpreted {
+                Some(trampoline) => trampoline(
+                    anyfunc.vmctx,
+                    vmctx_ptr,
+                    anyfunc.func_ptr.as_ptr(),
+                    [0u128].as_mut_ptr(),
+                ),
+                None => mem::transmute::<
                     *const VMFunctionBody,
                     unsafe extern "C" fn(*mut VMContext, *mut VMContext),
-                >(f.anyfunc.as_ref().func_ptr.as_ptr())(
-                    f.anyfunc.as_ref().vmctx, vmctx_ptr
-                )
+                >(anyfunc.func_ptr.as_ptr())(anyfunc.vmctx, vmctx_ptr),
             })?;
         }
     }
@@ -160,7 +167,18 @@ impl Instance {
     /// [inst]: https://webassembly.github.io/spec/core/exec/modules.html#exec-instantiation
     /// [issue]: https://github.com/bytecodealliance/wasmtime/issues/727
     /// [`ExternType`]: crate::ExternType
+    ///
+    /// ## Panics
+    ///
+    /// In stores with [`Config::async_support`](crate::Config::async_support)
+    /// enabled this panics unless it's called from a host function that wasm
+    /// called. Use [`Instance::new_async`] instead.
     pub fn new(store: &Store, module: &Module, imports: &[Extern]) -> Result<Instance, Error> {
+        assert!(
+            store.can_call_synchronously(),
+            "cannot use `Instance::new` in stores with async support enabled, \
+             use `Instance::new_async` instead"
+        );
         if !Engine::same(store.engine(), module.engine()) {
             bail!("cross-`Engine` instantiation is not currently supported");
         }
@@ -178,6 +196,32 @@ impl Instance {
         })
     }
 
+    /// Creates a new [`Instance`] like [`Instance::new`], for stores with
+    /// [`Config::async_support`](crate::Config::async_support) enabled.
+    ///
+    /// The module's start function, if any, runs on a fiber as with
+    /// [`Func::call_async`](crate::Func::call_async), so that it can call
+    /// async host functions. The returned future resolves once instantiation
+    /// has finished.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the store doesn't have async support enabled.
+    #[cfg(feature = "async")]
+    pub async fn new_async(
+        store: &Store,
+        module: &Module,
+        imports: &[Extern],
+    ) -> Result<Instance, Error> {
+        assert!(
+            store.async_support(),
+            "cannot use `new_async` without enabling async support in the config"
+        );
+        store
+            .on_fiber(|| Instance::new(store, module, imports))
+            .await?
+    }
+
     /// Returns the associated [`Store`] that this `Instance` is compiled into.
     ///
     /// This is the [`Store`] that generally serves as a sort of global cache
@@ -263,6 +263,7 @@ pub use crate::store::*;
 pub use crate::trap::*;
 pub use crate::types::*;
 pub use crate::values::*;
+pub use wasmtime_environ::stats::{CompileStats, PassStats};
 
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
@@ -581,6 +581,18 @@ impl Linker {
         Instance::new(&self.store, module, &imports)
     }
 
+    /// Attempts to instantiate the `module` provided like
+    /// [`Linker::instantiate`], for stores with
+    /// [`Config::async_support`](crate::Config::async_support) enabled.
+    ///
+    /// See [`Instance::new_async`] for more information.
+    #[cfg(feature = "async")]
+    pub async fn instantiate_async(&self, module: &Module) -> Result<Instance> {
+        let imports = self.compute_imports(module)?;
+
+        Instance::new_async(&self.store, module, &imports).await
+    }
+
     fn compute_imports(&self, module: &Module) -> Result<Vec<Extern>> {
         module
             .imports()
@@ -9,6 +9,7 @@ use std::sync::{Arc, Mutex};
 use wasmparser::Validator;
 #[cfg(feature = "cache")]
 use wasmtime_cache::ModuleCacheEntry;
+use wasmtime_environ::stats::CompileStats;
 use wasmtime_jit::{CompilationArtifacts, CompiledModule};
 
 /// A compiled WebAssembly module, ready to be instantiated.
@@ -369,6 +370,29 @@ impl Module {
         self.compiled_module().module().name.as_deref()
     }
 
+    /// Returns statistics about the compilation of this [`Module`]'s
+    /// functions, added up over all of them.
+    ///
+    /// This includes the time spent in each compilation pass, the size of the
+    /// generated code and the number of spills, reloads and moves the register
+    /// allocator inserted. Functions loaded from the cache report the
+    /// statistics of their original compilation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmtime::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let engine = Engine::default();
+    /// let module = Module::new(&engine, "(module (func) (func))")?;
+    /// assert_eq!(module.compilation_stats().functions, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compilation_stats(&self) -> CompileStats {
+        self.compiled_module().compilation_stats()
+    }
+
     /// Returns the list of imports that this [`Module`] has and must be
     /// satisfied.
     ///
@@ -25,24 +25,34 @@ pub struct SignatureRegistry {
 struct Entry {
     // The WebAssembly type signature, using wasm types.
     wasm: WasmFuncType,
-    // The native trampoline used to invoke this type signature from `Func`.
-    // Note that the code memory for this trampoline is not owned by this
-    // type, but instead it's expected to be owned by the store that this
-    // registry lives within.
-    trampoline: VMTrampoline,
+    // The native trampoline used to invoke this type signature from `Func`,
+    // if any has been registered yet: interpreted modules have none. Note
+    // that the code memory for this trampoline is not owned by this type, but
+    // instead it's expected to be owned by the store that this registry lives
+    // within.
+    trampoline: Option<VMTrampoline>,
 }
 
 impl SignatureRegistry {
     /// Register a signature and return its unique index.
+    ///
+    /// The `trampoline` is recorded unless the signature already has one.
     pub fn register(
         &mut self,
         wasm: &WasmFuncType,
-        trampoline: VMTrampoline,
+        trampoline: Option<VMTrampoline>,
     ) -> VMSharedSignatureIndex {
         let len = self.wasm2index.len();
 
         match self.wasm2index.entry(wasm.clone()) {
-            hash_map::Entry::Occupied(entry) => *entry.get(),
+            hash_map::Entry::Occupied(entry) => {
+                let index = *entry.get();
+                let entry = &mut self.index_map[index.bits() as usize];
+                if entry.trampoline.is_none() {
+                    entry.trampoline = trampoline;
+                }
+                index
+            }
             hash_map::Entry::Vacant(entry) => {
                 // Keep `signature_hash` len under 2**32 -- VMSharedSignatureIndex::new(std::u32::MAX)
                 // is reserved for VMSharedSignatureIndex::default().
@@ -74,7 +84,7 @@ impl SignatureRegistry {
     pub fn lookup_shared(
         &self,
         idx: VMSharedSignatureIndex,
-    ) -> Option<(&WasmFuncType, VMTrampoline)> {
+    ) -> Option<(&WasmFuncType, Option<VMTrampoline>)> {
         self.index_map
             .get(idx.bits() as usize)
             .map(|e| (&e.wasm, e.trampoline))
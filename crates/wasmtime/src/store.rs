@@ -2,18 +2,36 @@ use crate::sig_registry::SignatureRegistry;
 use crate::trampoline::StoreInstanceHandle;
 use crate::Engine;
 use crate::Module;
+#[cfg(feature = "async")]
+use crate::Trap;
 use anyhow::{bail, Result};
+use std::any::{Any, TypeId};
+#[cfg(feature = "async")]
+use std::cell::Cell;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
 use std::hash::{Hash, Hasher};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::ptr::{self, NonNull};
 use std::rc::{Rc, Weak};
+#[cfg(feature = "async")]
+use std::sync::atomic::Ordering::SeqCst;
 use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
 use wasmtime_environ::wasm;
 use wasmtime_runtime::{
     InstanceHandle, RuntimeMemoryCreator, SignalHandler, StackMapRegistry, VMExternRef,
     VMExternRefActivationsTable, VMInterrupts, VMSharedSignatureIndex,
 };
 
+pub use wasmtime_runtime::ResourceLimiter;
+
 /// A `Store` is a collection of WebAssembly instances and host-defined items.
 ///
 /// All WebAssembly instances and items will be attached to and refer to a
@@ -59,6 +77,46 @@ pub(crate) struct StoreInner {
     jit_code_ranges: RefCell<Vec<(usize, usize)>>,
     externref_activations_table: VMExternRefActivationsTable,
     stack_map_registry: StackMapRegistry,
+    limiter: Rc<StoreLimiter>,
+    data: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+    /// The fiber that's currently running wasm, if any.
+    #[cfg(feature = "async")]
+    current_suspend: Cell<*const FiberSuspend>,
+    /// The context of the future whose `poll` most recently resumed a fiber,
+    /// for as long as that `poll` is running.
+    #[cfg(feature = "async")]
+    current_poll_cx: Cell<*mut Context<'static>>,
+}
+
+#[cfg(feature = "async")]
+type FiberSuspend = wasmtime_fiber::Suspend<Result<(), Trap>, (), Result<(), Trap>>;
+
+/// Native stack space given to the host functions on a fiber, in addition to
+/// `Config::max_wasm_stack`.
+#[cfg(feature = "async")]
+const FIBER_HOST_STACK_SIZE: usize = 1 << 20;
+
+/// The limiter shared by every instance in a store, forwarding to whatever
+/// limiter was most recently configured with [`Store::set_limiter`].
+#[derive(Default)]
+struct StoreLimiter {
+    limiter: RefCell<Option<Box<dyn ResourceLimiter>>>,
+}
+
+impl ResourceLimiter for StoreLimiter {
+    fn memory_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        match &*self.limiter.borrow() {
+            Some(limiter) => limiter.memory_growing(current, desired, maximum),
+            None => true,
+        }
+    }
+
+    fn table_growing(&self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        match &*self.limiter.borrow() {
+            Some(limiter) => limiter.table_growing(current, desired, maximum),
+            None => true,
+        }
+    }
 }
 
 struct HostInfoKey(VMExternRef);
@@ -100,6 +158,12 @@ impl Store {
                 jit_code_ranges: RefCell::new(Vec::new()),
                 externref_activations_table: VMExternRefActivationsTable::new(),
                 stack_map_registry: StackMapRegistry::default(),
+                limiter: Default::default(),
+                data: RefCell::new(HashMap::new()),
+                #[cfg(feature = "async")]
+                current_suspend: Cell::new(ptr::null()),
+                #[cfg(feature = "async")]
+                current_poll_cx: Cell::new(ptr::null_mut()),
             }),
         }
     }
@@ -122,6 +186,12 @@ impl Store {
             .map(|x| x as _)
     }
 
+    /// Returns the limiter which all instances in this store consult before
+    /// growing their memories and tables.
+    pub(crate) fn limiter(&self) -> Rc<dyn ResourceLimiter> {
+        self.inner.limiter.clone()
+    }
+
     pub(crate) fn signatures(&self) -> &RefCell<SignatureRegistry> {
         &self.inner.signatures
     }
@@ -209,7 +279,7 @@ impl Store {
         let module = module.compiled_module().module();
         let mut signatures = self.signatures().borrow_mut();
         for (index, wasm) in module.signatures.iter() {
-            signatures.register(wasm, trampolines[index]);
+            signatures.register(wasm, trampolines.get(index).cloned());
         }
     }
 
@@ -257,6 +327,26 @@ impl Store {
         &self.inner.interrupts
     }
 
+    /// Returns whether this store's config has
+    /// [`Config::async_support`](crate::Config::async_support) enabled.
+    pub(crate) fn async_support(&self) -> bool {
+        self.engine().config().async_support
+    }
+
+    /// Returns whether wasm can be called synchronously in this store at this
+    /// point, which is always the case unless async support is enabled. Async
+    /// stores only run wasm on their fibers, so that async host functions can
+    /// suspend it.
+    pub(crate) fn can_call_synchronously(&self) -> bool {
+        #[cfg(feature = "async")]
+        {
+            if self.async_support() {
+                return !self.inner.current_suspend.get().is_null();
+            }
+        }
+        true
+    }
+
     /// Returns whether the stores `a` and `b` refer to the same underlying
     /// `Store`.
     ///
@@ -359,6 +449,77 @@ impl Store {
         }
     }
 
+    /// Configures a [`ResourceLimiter`] to limit the growth of memories and
+    /// tables within this `Store`.
+    ///
+    /// The limiter applies to all memories and tables in this store, including
+    /// those of instances created before it was configured, and replaces any
+    /// previously configured limiter. Growth denied by the limiter fails in the
+    /// same way as growth beyond the declared maximum: `memory.grow` and
+    /// `table.grow` return -1, and [`Memory::grow`](crate::Memory::grow) and
+    /// [`Table::grow`](crate::Table::grow) return an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within one of the limiter's own callbacks.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// # use wasmtime::*;
+    /// # fn main() -> Result<()> {
+    /// struct MemoryLimit(u32);
+    ///
+    /// impl ResourceLimiter for MemoryLimit {
+    ///     fn memory_growing(&self, _current: u32, desired: u32, _max: Option<u32>) -> bool {
+    ///         desired <= self.0
+    ///     }
+    ///
+    ///     fn table_growing(&self, _current: u32, _desired: u32, _max: Option<u32>) -> bool {
+    ///         true
+    ///     }
+    /// }
+    ///
+    /// let store = Store::default();
+    /// store.set_limiter(MemoryLimit(2));
+    ///
+    /// let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)));
+    /// assert_eq!(memory.grow(1)?, 1);
+    /// assert!(memory.grow(1).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_limiter(&self, limiter: impl ResourceLimiter + 'static) {
+        *self.inner.limiter.limiter.borrow_mut() = Some(Box::new(limiter));
+    }
+
+    /// Associates a piece of host data of type `T` with this `Store`.
+    ///
+    /// Only one value of each type can be stored. If a value of type `T` has
+    /// already been set then `data` is handed back in the `Err` variant.
+    ///
+    /// Values are dropped when the `Store` itself is dropped.
+    pub fn set<T: Any>(&self, data: T) -> Result<(), T> {
+        let mut map = self.inner.data.borrow_mut();
+        if map.contains_key(&TypeId::of::<T>()) {
+            return Err(data);
+        }
+        map.insert(TypeId::of::<T>(), Box::new(data));
+        Ok(())
+    }
+
+    /// Returns the host data of type `T` previously associated with this
+    /// `Store` through [`Store::set`], if any.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        let map = self.inner.data.borrow();
+        let data = map.get(&TypeId::of::<T>())?.downcast_ref::<T>()?;
+        // Values are boxed and never removed or replaced until the store is
+        // dropped, so the reference stays valid after the `RefCell` guard
+        // goes away.
+        Some(unsafe { &*(data as *const T) })
+    }
+
     pub(crate) fn externref_activations_table(&self) -> &VMExternRefActivationsTable {
         &self.inner.externref_activations_table
     }
@@ -381,6 +542,208 @@ impl Store {
     }
 }
 
+#[cfg(feature = "async")]
+impl Store {
+    /// Runs `func` on a new fiber, returning a future which resolves to its
+    /// result once it has finished.
+    ///
+    /// Whenever an async host function called on the fiber is waiting for its
+    /// own future, the fiber is suspended and the returned future is pending.
+    /// If the returned future is dropped before it has resolved, the fiber is
+    /// resumed one last time, with each pending async host function raising a
+    /// trap, so that all of its wasm frames are unwound.
+    pub(crate) async fn on_fiber<R>(&self, func: impl FnOnce() -> R) -> Result<R, Trap> {
+        debug_assert!(self.async_support());
+        let mut slot = None;
+        let stack_size = self.engine().config().max_wasm_stack + FIBER_HOST_STACK_SIZE;
+        let fiber = wasmtime_fiber::Fiber::new(stack_size, |keep_going, suspend| {
+            // The future may have been dropped before it was ever polled.
+            keep_going?;
+            self.inner.current_suspend.set(suspend);
+            slot = Some(func());
+            Ok(())
+        })
+        .map_err(|e| Trap::from(anyhow::Error::from(e).context("failed to create a fiber")))?;
+
+        FiberFuture {
+            fiber,
+            store: self,
+            stack: StackState::new(),
+        }
+        .await?;
+        Ok(slot.unwrap())
+    }
+
+    /// Runs `future` to completion on the current fiber, suspending the fiber
+    /// whenever the future isn't ready.
+    ///
+    /// Returns an error if the future running the fiber was dropped in the
+    /// meantime, in which case the caller must unwind its wasm with a trap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this isn't called on one of this store's fibers.
+    pub(crate) fn block_on<F: Future>(&self, mut future: Pin<&mut F>) -> Result<F::Output, Trap> {
+        let suspend = self.inner.current_suspend.get();
+        assert!(
+            !suspend.is_null(),
+            "async host functions can only be called on a fiber"
+        );
+        loop {
+            // The context is only missing if the fiber is being resumed to be
+            // unwound, and a host function carries on regardless.
+            let cx = self.inner.current_poll_cx.get();
+            if cx.is_null() {
+                return Err(Trap::new("future dropped"));
+            }
+            unsafe {
+                if let Poll::Ready(ret) = future.as_mut().poll(&mut *cx) {
+                    return Ok(ret);
+                }
+                (*suspend).suspend(())?;
+            }
+        }
+    }
+}
+
+/// A future which resumes a fiber whenever it's polled.
+#[cfg(feature = "async")]
+struct FiberFuture<'a> {
+    fiber: wasmtime_fiber::Fiber<'a, Result<(), Trap>, (), Result<(), Trap>>,
+    store: &'a Store,
+    /// The state of the fiber's stack while it's not running, and of the stack
+    /// that resumed it while it's running.
+    stack: StackState,
+}
+
+#[cfg(feature = "async")]
+impl FiberFuture<'_> {
+    /// Resumes the fiber with `val` until it either finishes or suspends, in
+    /// which case `Err` is returned.
+    fn resume(&mut self, val: Result<(), Trap>) -> Result<Result<(), Trap>, ()> {
+        let interrupts = self.store.interrupts();
+        unsafe {
+            self.stack.swap(self.store);
+        }
+        // An interrupt that was delivered while the fiber wasn't running is
+        // for whatever wasm runs next, which is the fiber's. The stack that's
+        // resuming it only keeps it too if wasm is running there as well.
+        if self.stack.stack_limit == wasmtime_environ::INTERRUPTED {
+            interrupts
+                .stack_limit
+                .store(wasmtime_environ::INTERRUPTED, SeqCst);
+            if self.stack.tls.is_null() {
+                self.stack.stack_limit = usize::max_value();
+            }
+        }
+
+        // Swap the fiber's state back out once it suspends or finishes, even if
+        // that's by panicking.
+        struct Restore<'a, 'b>(&'a mut FiberFuture<'b>);
+
+        impl Drop for Restore<'_, '_> {
+            fn drop(&mut self) {
+                let future = &mut *self.0;
+                unsafe {
+                    future.stack.swap(future.store);
+                }
+                // Likewise, a pending interrupt that arrived after the fiber's
+                // wasm has all returned is for whatever wasm runs next.
+                if future.fiber.done() && future.stack.stack_limit == wasmtime_environ::INTERRUPTED
+                {
+                    future
+                        .store
+                        .interrupts()
+                        .stack_limit
+                        .store(wasmtime_environ::INTERRUPTED, SeqCst);
+                }
+            }
+        }
+
+        let restore = Restore(self);
+        restore.0.fiber.resume(val)
+    }
+}
+
+#[cfg(feature = "async")]
+impl Future for FiberFuture<'_> {
+    type Output = Result<(), Trap>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let cx = unsafe { std::mem::transmute::<&mut Context<'_>, *mut Context<'static>>(cx) };
+        let store = self.store;
+        let current_poll_cx = &store.inner.current_poll_cx;
+        let _reset = Reset(current_poll_cx, current_poll_cx.replace(cx));
+        match self.resume(Ok(())) {
+            Ok(ret) => Poll::Ready(ret),
+            Err(()) => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for FiberFuture<'_> {
+    fn drop(&mut self) {
+        if self.fiber.done() {
+            return;
+        }
+        // Resume the fiber with an error so that the async host function it's
+        // suspended in raises a trap, unwinding all of its wasm frames.
+        let store = self.store;
+        let current_poll_cx = &store.inner.current_poll_cx;
+        let _reset = Reset(current_poll_cx, current_poll_cx.replace(ptr::null_mut()));
+        let result = self.resume(Err(Trap::new("future dropped")));
+        debug_assert!(result.is_ok(), "fiber suspended again while unwinding");
+    }
+}
+
+/// The state that the runtime keeps about the native stack that wasm is
+/// running on, which is per-thread, and so needs to be swapped out whenever a
+/// fiber switches stacks.
+#[cfg(feature = "async")]
+struct StackState {
+    suspend: *const FiberSuspend,
+    tls: *const wasmtime_runtime::traphandlers::CallThreadState<'static>,
+    stack_limit: usize,
+    stack_canary: Option<NonNull<u8>>,
+}
+
+#[cfg(feature = "async")]
+impl StackState {
+    /// The state of a stack that isn't running any wasm.
+    fn new() -> StackState {
+        StackState {
+            suspend: ptr::null(),
+            tls: ptr::null(),
+            stack_limit: usize::max_value(),
+            stack_canary: None,
+        }
+    }
+
+    /// Swaps this state with that of the stack that's currently running.
+    unsafe fn swap(&mut self, store: &Store) {
+        self.suspend = store.inner.current_suspend.replace(self.suspend);
+        self.tls = wasmtime_runtime::traphandlers::tls::PTR.with(|p| p.replace(self.tls));
+        self.stack_limit = store
+            .interrupts()
+            .stack_limit
+            .swap(self.stack_limit, SeqCst);
+        self.stack_canary = store
+            .externref_activations_table()
+            .switch_stack_canary(self.stack_canary);
+    }
+}
+
+#[cfg(feature = "async")]
+struct Reset<'a, T: Copy>(&'a Cell<T>, T);
+
+#[cfg(feature = "async")]
+impl<T: Copy> Drop for Reset<'_, T> {
+    fn drop(&mut self) {
+        self.0.set(self.1);
+    }
+}
+
 impl Default for Store {
     fn default() -> Store {
         Store::new(&Engine::default())
@@ -36,6 +36,7 @@ pub(crate) fn create_handle(
             store.interrupts(),
             store.externref_activations_table() as *const VMExternRefActivationsTable as *mut _,
             store.stack_map_registry() as *const StackMapRegistry as *mut _,
+            Some(store.limiter()),
         )?;
         Ok(store.add_instance(handle))
     }
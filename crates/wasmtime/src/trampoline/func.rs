@@ -195,6 +195,7 @@ fn make_trampoline(
             relocations: Default::default(),
             address_map: Default::default(),
             stack_maps: Default::default(),
+            stats: Default::default(),
             stack_slots: Default::default(),
             traps: Default::default(),
             value_labels_ranges: Default::default(),
@@ -241,7 +242,10 @@ pub fn create_handle_with_function(
         &sig,
         mem::size_of::<u128>(),
     )?;
-    store.signatures().borrow_mut().register(wft, trampoline);
+    store
+        .signatures()
+        .borrow_mut()
+        .register(wft, Some(trampoline));
 
     // Next up we wrap everything up into an `InstanceHandle` by publishing our
     // code memory (makes it executable) and ensuring all our various bits of
@@ -276,7 +280,10 @@ pub unsafe fn create_handle_with_raw_function(
         .exports
         .insert(String::new(), wasm::EntityIndex::Function(func_id));
     finished_functions.push(func);
-    store.signatures().borrow_mut().register(wft, trampoline);
+    store
+        .signatures()
+        .borrow_mut()
+        .register(wft, Some(trampoline));
 
     create_handle(module, store, finished_functions, state, &[])
 }
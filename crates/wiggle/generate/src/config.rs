@@ -1,8 +1,13 @@
 use {
     proc_macro2::Span,
-    std::{collections::HashMap, iter::FromIterator, path::PathBuf},
+    std::{
+        collections::{HashMap, HashSet},
+        iter::FromIterator,
+        path::PathBuf,
+    },
     syn::{
         braced, bracketed,
+        ext::IdentExt,
         parse::{Parse, ParseStream},
         punctuated::Punctuated,
         Error, Ident, LitStr, Result, Token,
@@ -14,6 +19,7 @@ pub struct Config {
     pub witx: WitxConf,
     pub ctx: CtxConf,
     pub errors: ErrorConf,
+    pub async_: AsyncConf,
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +27,7 @@ pub enum ConfigField {
     Witx(WitxConf),
     Ctx(CtxConf),
     Error(ErrorConf),
+    Async(AsyncConf),
 }
 
 mod kw {
@@ -49,6 +56,10 @@ impl Parse for ConfigField {
             input.parse::<kw::errors>()?;
             input.parse::<Token![:]>()?;
             Ok(ConfigField::Error(input.parse()?))
+        } else if lookahead.peek(Token![async]) {
+            input.parse::<Token![async]>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Async(input.parse()?))
         } else {
             Err(lookahead.error())
         }
@@ -60,6 +71,7 @@ impl Config {
         let mut witx = None;
         let mut ctx = None;
         let mut errors = None;
+        let mut async_ = None;
         for f in fields {
             match f {
                 ConfigField::Witx(c) => {
@@ -80,6 +92,12 @@ impl Config {
                     }
                     errors = Some(c);
                 }
+                ConfigField::Async(c) => {
+                    if async_.is_some() {
+                        return Err(Error::new(err_loc, "duplicate `async` field"));
+                    }
+                    async_ = Some(c);
+                }
             }
         }
         Ok(Config {
@@ -90,6 +108,7 @@ impl Config {
                 .take()
                 .ok_or_else(|| Error::new(err_loc, "`ctx` field required"))?,
             errors: errors.take().unwrap_or_default(),
+            async_: async_.take().unwrap_or_default(),
         })
     }
 
@@ -113,6 +132,56 @@ impl Parse for Config {
     }
 }
 
+/// Configuration for guest bindings, which only takes the `witx` (or `witx_literal`) field.
+#[derive(Debug, Clone)]
+pub struct GuestConfig {
+    pub witx: WitxConf,
+}
+
+impl GuestConfig {
+    pub fn build(fields: impl Iterator<Item = ConfigField>, err_loc: Span) -> Result<Self> {
+        let mut witx = None;
+        for f in fields {
+            match f {
+                ConfigField::Witx(c) => {
+                    if witx.is_some() {
+                        return Err(Error::new(err_loc, "duplicate `witx` field"));
+                    }
+                    witx = Some(c);
+                }
+                _ => {
+                    return Err(Error::new(
+                        err_loc,
+                        "only the `witx` field is supported for guest bindings",
+                    ))
+                }
+            }
+        }
+        Ok(GuestConfig {
+            witx: witx.ok_or_else(|| Error::new(err_loc, "`witx` field required"))?,
+        })
+    }
+
+    /// Load the `witx` document for the configuration.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the paths given in the `witx` field were not valid documents.
+    pub fn load_document(&self) -> witx::Document {
+        self.witx.load_document()
+    }
+}
+
+impl Parse for GuestConfig {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let contents;
+        let _lbrace = braced!(contents in input);
+        let fields: Punctuated<ConfigField, Token![,]> =
+            contents.parse_terminated(ConfigField::parse)?;
+        Ok(GuestConfig::build(fields.into_iter(), input.span())?)
+    }
+}
+
 /// The witx document(s) that will be loaded from a [`Config`](struct.Config.html).
 ///
 /// A witx interface definition can be provided either as a collection of relative paths to
@@ -294,3 +363,83 @@ impl Parse for ErrorConfField {
         })
     }
 }
+
+/// The set of functions, per module, whose module trait methods are generated as `async fn`.
+///
+/// Given as `async: { module::{func_a, func_b}, other_module::{func_c} }`, using the witx
+/// names of the modules and functions.
+#[derive(Clone, Default, Debug)]
+pub struct AsyncConf(HashMap<String, HashSet<String>>);
+
+impl AsyncConf {
+    /// Returns whether the function `func` in module `module` was configured to be async.
+    pub fn is_async(&self, module: &str, func: &str) -> bool {
+        self.0
+            .get(module)
+            .map(|funcs| funcs.contains(func))
+            .unwrap_or(false)
+    }
+
+    /// Returns whether any function in module `module` was configured to be async.
+    pub fn contains_module(&self, module: &str) -> bool {
+        self.0.contains_key(module)
+    }
+
+    /// Check that every configured module and function exists in the witx document.
+    pub fn validate(&self, doc: &witx::Document) -> anyhow::Result<()> {
+        for (module_name, funcs) in self.0.iter() {
+            let module = doc
+                .module(&witx::Id::new(module_name))
+                .ok_or_else(|| anyhow::anyhow!("async module `{}` not found", module_name))?;
+            for func in funcs {
+                if module.func(&witx::Id::new(func)).is_none() {
+                    anyhow::bail!("async function `{}::{}` not found", module_name, func);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Parse for AsyncConf {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        let _ = braced!(content in input);
+        let items: Punctuated<AsyncConfField, Token![,]> =
+            content.parse_terminated(Parse::parse)?;
+        let mut m: HashMap<String, HashSet<String>> = HashMap::new();
+        for i in items {
+            let funcs = m.entry(i.module.to_string()).or_default();
+            for f in i.funcs {
+                if !funcs.insert(f.to_string()) {
+                    return Err(Error::new(
+                        f.span(),
+                        format!("duplicate async function `{}::{}`", i.module, f),
+                    ));
+                }
+            }
+        }
+        Ok(AsyncConf(m))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AsyncConfField {
+    module: Ident,
+    funcs: Vec<Ident>,
+}
+
+impl Parse for AsyncConfField {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // Witx names may collide with Rust keywords, so accept those too.
+        let module = Ident::parse_any(input)?;
+        let _colons: Token![::] = input.parse()?;
+        let content;
+        let _ = braced!(content in input);
+        let funcs: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse_any)?;
+        Ok(AsyncConfField {
+            module,
+            funcs: funcs.into_iter().collect(),
+        })
+    }
+}
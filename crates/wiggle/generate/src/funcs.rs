@@ -1,6 +1,7 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
+use crate::config::AsyncConf;
 use crate::error_transform::ErrorTransform;
 use crate::lifetimes::anon_lifetime;
 use crate::module_trait::passed_by_reference;
@@ -12,8 +13,10 @@ pub fn define_func(
     module: &witx::Module,
     func: &witx::InterfaceFunc,
     errxform: &ErrorTransform,
+    async_conf: &AsyncConf,
 ) -> TokenStream {
     let funcname = func.name.as_str();
+    let is_async = async_conf.is_async(module.name.as_str(), funcname);
 
     let ident = names.func(&func.name);
     let rt = names.runtime_mod();
@@ -171,7 +174,13 @@ pub fn define_func(
     let mod_name = &module.name.as_str();
     let func_name = &func.name.as_str();
 
-    quote!(pub fn #ident(#abi_args) -> #abi_ret {
+    let (asyncness, await_) = if is_async {
+        (quote!(async), quote!(.await))
+    } else {
+        (quote!(), quote!())
+    };
+
+    quote!(pub #asyncness fn #ident(#abi_args) -> #abi_ret {
         let _span = #rt::tracing::span!(
             #rt::tracing::Level::TRACE,
             "wiggle abi",
@@ -183,7 +192,7 @@ pub fn define_func(
         #(#marshal_args)*
         #(#marshal_rets_pre)*
         #log_marshalled_args
-        let #trait_bindings  = match #trait_name::#ident(ctx, #(#trait_args),*) {
+        let #trait_bindings  = match #trait_name::#ident(ctx, #(#trait_args),*) #await_ {
             Ok(#trait_bindings) => { #trait_rets },
             Err(e) => { #ret_err },
         };
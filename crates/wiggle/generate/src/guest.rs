@@ -0,0 +1,641 @@
+//! Generation of guest-side bindings.
+//!
+//! The guest bindings mirror the host side generated by [`generate`](crate::generate): the same
+//! witx document produces `#[repr(C)]` type definitions with the layout `wiggle::GuestType`
+//! expects, raw `#[link(wasm_import_module)]` declarations of each import, and wrappers which
+//! lower idiomatic Rust arguments to the core wasm ABI.
+//!
+//! Wrappers are safe to call unless they take a `pointer` the host writes through. Functions whose
+//! results include strings or arrays, which the host side can't return either, or whose error
+//! result isn't an enum, only get the raw import.
+
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+use std::convert::TryFrom;
+use witx::Layout;
+
+use crate::names::Names;
+
+pub fn generate_guest(doc: &witx::Document, names: &Names) -> TokenStream {
+    let types = doc.typenames().map(|t| define_guest_datatype(names, &t));
+    let modules = doc.modules().map(|m| define_guest_module(names, &m));
+    quote! {
+        pub mod types {
+            /// A pointer and length pair: the representation of witx strings and arrays inside
+            /// structs, unions and other arrays.
+            ///
+            /// The length is always 32 bits wide, like on the host side, so this only matches
+            /// the host's layout where pointers are 32 bits wide too, i.e. on `wasm32`.
+            #[repr(C)]
+            #[derive(Copy, Clone, Debug)]
+            pub struct RawSlice<T> {
+                pub ptr: *const T,
+                pub len: u32,
+            }
+
+            impl<T> RawSlice<T> {
+                /// Borrows `slice` as a `RawSlice`. The result must not outlive `slice`.
+                ///
+                /// # Panics
+                ///
+                /// Panics if the length of `slice` doesn't fit in a `u32`.
+                pub fn new(slice: &[T]) -> RawSlice<T> {
+                    RawSlice {
+                        ptr: slice.as_ptr(),
+                        len: ::core::convert::TryFrom::try_from(slice.len())
+                            .expect("slice is too long for a guest"),
+                    }
+                }
+            }
+
+            impl RawSlice<u8> {
+                /// Borrows `s` as a `RawSlice`. The result must not outlive `s`.
+                pub fn from_str(s: &str) -> RawSlice<u8> {
+                    RawSlice::new(s.as_bytes())
+                }
+            }
+
+            #(#types)*
+        }
+        #(#modules)*
+    }
+}
+
+/// Reports a witx construct the guest bindings can't represent. The error is emitted in place of
+/// the item or type, so it points at the macro invocation rather than panicking inside it.
+fn unsupported(msg: String) -> TokenStream {
+    quote!(compile_error! { #msg })
+}
+
+fn docs(docs: &str) -> TokenStream {
+    let docs = docs.trim();
+    if docs.is_empty() {
+        quote!()
+    } else {
+        quote!(#[doc = #docs])
+    }
+}
+
+/// Whether the guest representation of `tref` borrows from the caller, which is the case for
+/// strings and arrays.
+fn needs_lifetime(tref: &witx::TypeRef) -> bool {
+    match &*tref.type_() {
+        witx::Type::Builtin(witx::BuiltinType::String) | witx::Type::Array(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether the host may write through a pointer in `tref`, which makes passing it to the host
+/// `unsafe`: nothing ties the pointer to memory the guest owns, or bounds how much is written.
+fn contains_mut_pointer(tref: &witx::TypeRef) -> bool {
+    match &*tref.type_() {
+        witx::Type::Pointer(_) => true,
+        witx::Type::ConstPointer(pointee) | witx::Type::Array(pointee) => {
+            contains_mut_pointer(pointee)
+        }
+        witx::Type::Struct(s) => s.members.iter().any(|m| contains_mut_pointer(&m.tref)),
+        witx::Type::Union(u) => u
+            .variants
+            .iter()
+            .filter_map(|v| v.tref.as_ref())
+            .any(contains_mut_pointer),
+        _ => false,
+    }
+}
+
+/// Whether the guest representation of `tref` contains a Rust `union`, and so can't derive
+/// `Debug`.
+fn contains_union(tref: &witx::TypeRef) -> bool {
+    match &*tref.type_() {
+        witx::Type::Union(u) => u.variants.iter().any(|v| v.tref.is_some()),
+        witx::Type::Struct(s) => s.members.iter().any(|m| contains_union(&m.tref)),
+        _ => false,
+    }
+}
+
+fn guest_builtin_type(b: witx::BuiltinType, lifetime: &TokenStream) -> TokenStream {
+    match b {
+        witx::BuiltinType::String => quote!(&#lifetime str),
+        witx::BuiltinType::U8 | witx::BuiltinType::Char8 => quote!(u8),
+        witx::BuiltinType::U16 => quote!(u16),
+        witx::BuiltinType::U32 => quote!(u32),
+        witx::BuiltinType::U64 => quote!(u64),
+        witx::BuiltinType::S8 => quote!(i8),
+        witx::BuiltinType::S16 => quote!(i16),
+        witx::BuiltinType::S32 => quote!(i32),
+        witx::BuiltinType::S64 => quote!(i64),
+        witx::BuiltinType::F32 => quote!(f32),
+        witx::BuiltinType::F64 => quote!(f64),
+        witx::BuiltinType::USize => quote!(usize),
+    }
+}
+
+fn guest_type_ref(names: &Names, tref: &witx::TypeRef, lifetime: &TokenStream) -> TokenStream {
+    match tref {
+        witx::TypeRef::Name(nt) => {
+            let ident = names.type_(&nt.name);
+            if needs_lifetime(tref) {
+                quote!(#ident<#lifetime>)
+            } else {
+                quote!(#ident)
+            }
+        }
+        witx::TypeRef::Value(ty) => match &**ty {
+            witx::Type::Builtin(b) => guest_builtin_type(*b, lifetime),
+            witx::Type::Pointer(pointee) => {
+                let pointee = guest_type_ref(names, pointee, lifetime);
+                quote!(*mut #pointee)
+            }
+            witx::Type::ConstPointer(pointee) => {
+                let pointee = guest_type_ref(names, pointee, lifetime);
+                quote!(*const #pointee)
+            }
+            witx::Type::Array(elem) => {
+                let elem = guest_member_type(names, elem);
+                quote!(&#lifetime [#elem])
+            }
+            _ => unsupported(format!(
+                "guest bindings don't support anonymous type {:?}",
+                tref
+            )),
+        },
+    }
+}
+
+/// The type of `tref` when stored in guest memory as part of a struct, union or array, where
+/// strings and arrays can't be borrowed slices.
+fn guest_member_type(names: &Names, tref: &witx::TypeRef) -> TokenStream {
+    match &*tref.type_() {
+        witx::Type::Builtin(witx::BuiltinType::String) => quote!(RawSlice<u8>),
+        witx::Type::Array(elem) => {
+            let elem = guest_member_type(names, elem);
+            quote!(RawSlice<#elem>)
+        }
+        _ => guest_type_ref(names, tref, &quote!('static)),
+    }
+}
+
+fn int_repr_tokens(int_repr: witx::IntRepr) -> TokenStream {
+    match int_repr {
+        witx::IntRepr::U8 => quote!(u8),
+        witx::IntRepr::U16 => quote!(u16),
+        witx::IntRepr::U32 => quote!(u32),
+        witx::IntRepr::U64 => quote!(u64),
+    }
+}
+
+fn define_guest_datatype(names: &Names, namedtype: &witx::NamedType) -> TokenStream {
+    let ident = names.type_(&namedtype.name);
+    let docs = docs(&namedtype.docs);
+    let lifetime = quote!('a);
+    let generics = if needs_lifetime(&namedtype.tref) {
+        quote!(<'a>)
+    } else {
+        quote!()
+    };
+    let def = match &namedtype.tref {
+        witx::TypeRef::Name(alias_to) => {
+            let rhs = names.type_(&alias_to.name);
+            quote!(pub type #ident #generics = #rhs #generics;)
+        }
+        witx::TypeRef::Value(v) => match &**v {
+            witx::Type::Enum(e) => define_guest_enum(names, &namedtype.name, e),
+            witx::Type::Flags(f) => define_guest_flags(names, &namedtype.name, f),
+            witx::Type::Int(i) => define_guest_int(names, &namedtype.name, i),
+            witx::Type::Handle(_) => quote!(pub type #ident = u32;),
+            witx::Type::Struct(s) => define_guest_struct(names, &namedtype.name, s),
+            witx::Type::Union(u) => define_guest_union(names, &namedtype.name, u),
+            witx::Type::Builtin(_)
+            | witx::Type::Pointer(_)
+            | witx::Type::ConstPointer(_)
+            | witx::Type::Array(_) => {
+                let rhs = guest_type_ref(names, &namedtype.tref, &lifetime);
+                quote!(pub type #ident #generics = #rhs;)
+            }
+        },
+    };
+    quote! {
+        #docs
+        #def
+    }
+}
+
+fn define_guest_enum(names: &Names, name: &witx::Id, e: &witx::EnumDatatype) -> TokenStream {
+    let ident = names.type_(name);
+    let repr = int_repr_tokens(e.repr);
+
+    let mut consts = vec![];
+    let mut name_cases = vec![];
+    let mut message_cases = vec![];
+    for (n, variant) in e.variants.iter().enumerate() {
+        let const_ident = names.guest_const(name, &variant.name);
+        let value = Literal::usize_unsuffixed(n);
+        let docs = docs(&variant.docs);
+        consts.push(quote! {
+            #docs
+            pub const #const_ident: #ident = #ident(#value);
+        });
+        let variant_str = variant.name.as_str().to_uppercase();
+        name_cases.push(quote!(#value => #variant_str));
+        let message = variant.docs.trim();
+        message_cases.push(quote!(#value => #message));
+    }
+
+    quote! {
+        #[repr(transparent)]
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+        pub struct #ident(#repr);
+
+        #(#consts)*
+
+        impl #ident {
+            /// Creates a value from its raw representation, which need not be one of the
+            /// defined variants.
+            pub const fn from_raw(raw: #repr) -> #ident {
+                #ident(raw)
+            }
+
+            /// Returns the raw representation of this value.
+            pub const fn raw(&self) -> #repr {
+                self.0
+            }
+
+            /// Returns the witx name of this value.
+            pub fn name(&self) -> &'static str {
+                match self.0 {
+                    #(#name_cases,)*
+                    _ => "<unknown>",
+                }
+            }
+
+            /// Returns the documentation of this value.
+            pub fn message(&self) -> &'static str {
+                match self.0 {
+                    #(#message_cases,)*
+                    _ => "",
+                }
+            }
+        }
+
+        impl ::core::fmt::Debug for #ident {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_struct(stringify!(#ident))
+                    .field("code", &self.0)
+                    .field("name", &self.name())
+                    .field("message", &self.message())
+                    .finish()
+            }
+        }
+
+        impl ::core::fmt::Display for #ident {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                write!(f, "{} ({})", self.name(), self.0)
+            }
+        }
+    }
+}
+
+fn define_guest_flags(names: &Names, name: &witx::Id, f: &witx::FlagsDatatype) -> TokenStream {
+    let ident = names.type_(name);
+    let repr = int_repr_tokens(f.repr);
+    let consts = f.flags.iter().enumerate().map(|(i, flag)| {
+        let const_ident = names.guest_const(name, &flag.name);
+        let value = 1u128
+            .checked_shl(u32::try_from(i).expect("flag value overflow"))
+            .expect("flag value overflow");
+        let value = Literal::u128_unsuffixed(value);
+        let docs = docs(&flag.docs);
+        quote! {
+            #docs
+            pub const #const_ident: #ident = #value;
+        }
+    });
+    quote! {
+        pub type #ident = #repr;
+        #(#consts)*
+    }
+}
+
+fn define_guest_int(names: &Names, name: &witx::Id, i: &witx::IntDatatype) -> TokenStream {
+    let ident = names.type_(name);
+    let repr = int_repr_tokens(i.repr);
+    let consts = i.consts.iter().map(|c| {
+        let const_ident = names.guest_const(name, &c.name);
+        let value = Literal::u64_unsuffixed(c.value);
+        let docs = docs(&c.docs);
+        quote! {
+            #docs
+            pub const #const_ident: #ident = #value;
+        }
+    });
+    quote! {
+        pub type #ident = #repr;
+        #(#consts)*
+    }
+}
+
+fn define_guest_struct(names: &Names, name: &witx::Id, s: &witx::StructDatatype) -> TokenStream {
+    let ident = names.type_(name);
+    let members = s.members.iter().map(|m| {
+        let member = names.struct_member(&m.name);
+        let ty = guest_member_type(names, &m.tref);
+        let docs = docs(&m.docs);
+        quote! {
+            #docs
+            pub #member: #ty
+        }
+    });
+    let derive_debug = if s.members.iter().any(|m| contains_union(&m.tref)) {
+        quote!()
+    } else {
+        quote!(, Debug)
+    };
+    let layout_assertion = layout_assertion(&ident, s.mem_size_align());
+    quote! {
+        #[repr(C)]
+        #[derive(Copy, Clone #derive_debug)]
+        pub struct #ident {
+            #(#members),*
+        }
+
+        #layout_assertion
+    }
+}
+
+/// Checks at compile time that `ident` has the size and alignment the host expects. Pointers and
+/// `RawSlice`s only have the host's layout on 32-bit targets, so it's limited to those.
+fn layout_assertion(ident: &TokenStream, layout: witx::SizeAlign) -> TokenStream {
+    let size = Literal::usize_unsuffixed(layout.size);
+    let align = Literal::usize_unsuffixed(layout.align);
+    quote! {
+        #[cfg(target_pointer_width = "32")]
+        const _: [(); #size] = [(); ::core::mem::size_of::<#ident>()];
+        #[cfg(target_pointer_width = "32")]
+        const _: [(); #align] = [(); ::core::mem::align_of::<#ident>()];
+    }
+}
+
+fn define_guest_union(names: &Names, name: &witx::Id, u: &witx::UnionDatatype) -> TokenStream {
+    let ident = names.type_(name);
+    let tag = names.type_(&u.tag.name);
+    let fields = u
+        .variants
+        .iter()
+        .filter_map(|v| {
+            let tref = v.tref.as_ref()?;
+            let field = names.struct_member(&v.name);
+            let ty = guest_member_type(names, tref);
+            let docs = docs(&v.docs);
+            Some(quote! {
+                #docs
+                pub #field: #ty
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let layout_assertion = layout_assertion(&ident, u.mem_size_align());
+    if fields.is_empty() {
+        return quote! {
+            #[repr(C)]
+            #[derive(Copy, Clone, Debug)]
+            pub struct #ident {
+                pub tag: #tag,
+            }
+
+            #layout_assertion
+        };
+    }
+
+    // `Names::type_` returns tokens rather than an `Ident`, so go through its string form.
+    let contents = format_ident!("{}U", ident.to_string());
+    let contents_docs = format!("The contents of a [`{}`], selected by its tag.", ident);
+    quote! {
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        pub struct #ident {
+            pub tag: #tag,
+            pub u: #contents,
+        }
+
+        #[doc = #contents_docs]
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        pub union #contents {
+            #(#fields),*
+        }
+
+        #layout_assertion
+    }
+}
+
+fn define_guest_module(names: &Names, module: &witx::Module) -> TokenStream {
+    let modname = names.module(&module.name);
+    let import_module = module.name.as_str();
+    let docs = docs(&module.docs);
+    let raw_decls = module.funcs().map(|f| define_raw_import(names, &f));
+    let wrappers = module.funcs().map(|f| define_guest_func(names, &f));
+    quote! {
+        #docs
+        pub mod #modname {
+            #[allow(unused_imports)]
+            use super::types::*;
+
+            /// Raw imports, using the core WebAssembly types of the ABI.
+            pub mod raw {
+                #[link(wasm_import_module = #import_module)]
+                extern "C" {
+                    #(#raw_decls)*
+                }
+            }
+
+            #(#wrappers)*
+        }
+    }
+}
+
+fn core_ret(names: &Names, func: &witx::InterfaceFunc) -> TokenStream {
+    let coretype = func.core_type();
+    if let Some(ret) = &coretype.ret {
+        match ret.signifies {
+            witx::CoreParamSignifies::Value(atom) => names.atom_type(atom),
+            _ => unreachable!("ret should always be passed by value"),
+        }
+    } else if func.noreturn {
+        quote!(!)
+    } else {
+        quote!(())
+    }
+}
+
+fn define_raw_import(names: &Names, func: &witx::InterfaceFunc) -> TokenStream {
+    let ident = names.func(&func.name);
+    let link_name = func.name.as_str();
+    let coretype = func.core_type();
+    let params = coretype.args.iter().map(|arg| {
+        let name = names.func_core_arg(arg);
+        let atom = names.atom_type(arg.repr());
+        quote!(#name: #atom)
+    });
+    let ret = core_ret(names, func);
+    quote! {
+        #[link_name = #link_name]
+        pub fn #ident(#(#params),*) -> #ret;
+    }
+}
+
+/// The type of a parameter in a guest wrapper function.
+fn guest_param_type(names: &Names, tref: &witx::TypeRef) -> TokenStream {
+    let ty = guest_type_ref(names, tref, &quote!('_));
+    match &*tref.type_() {
+        witx::Type::Struct(_) | witx::Type::Union(_) => quote!(&#ty),
+        _ => ty,
+    }
+}
+
+/// Converts the wrapper parameter `param` to the form it's lowered from, if that differs: strings
+/// and arrays are passed as a `RawSlice`, which checks that their length fits in a `u32`.
+fn prepare_param(names: &Names, param: &witx::InterfaceFuncParam) -> TokenStream {
+    let name = names.func_param(&param.name);
+    match &*param.tref.type_() {
+        witx::Type::Builtin(witx::BuiltinType::String) => {
+            quote!(let #name = RawSlice::from_str(#name);)
+        }
+        witx::Type::Array(_) => quote!(let #name = RawSlice::new(#name);),
+        _ => quote!(),
+    }
+}
+
+/// Lowers the wrapper parameter `param`, as converted by `prepare_param`, into its core ABI
+/// arguments.
+fn lower_param(names: &Names, param: &witx::InterfaceFuncParam) -> TokenStream {
+    let name = names.func_param(&param.name);
+    match &*param.tref.type_() {
+        witx::Type::Builtin(witx::BuiltinType::String) | witx::Type::Array(_) => {
+            quote!(#name.ptr as i32, #name.len as i32)
+        }
+        witx::Type::Pointer(_) | witx::Type::ConstPointer(_) => quote!(#name as i32),
+        witx::Type::Struct(_) | witx::Type::Union(_) => {
+            let ty = guest_type_ref(names, &param.tref, &quote!('_));
+            quote!(#name as *const #ty as i32)
+        }
+        ty => {
+            let atom = match ty.passed_by() {
+                witx::TypePassedBy::Value(atom) => names.atom_type(atom),
+                _ => unreachable!("{:?} should be passed by value", ty),
+            };
+            match ty {
+                witx::Type::Enum(_) => quote!(#name.raw() as #atom),
+                _ => quote!(#name as #atom),
+            }
+        }
+    }
+}
+
+fn define_guest_func(names: &Names, func: &witx::InterfaceFunc) -> TokenStream {
+    let ident = names.func(&func.name);
+    let docs = docs(&func.docs);
+
+    let params = func.params.iter().map(|p| {
+        let name = names.func_param(&p.name);
+        let ty = guest_param_type(names, &p.tref);
+        quote!(#name: #ty)
+    });
+    let prepared_params = func.params.iter().map(|p| prepare_param(names, p));
+    let lowered_params = func.params.iter().map(|p| lower_param(names, p));
+
+    let (unsafety, safety_docs) = if func.params.iter().any(|p| contains_mut_pointer(&p.tref)) {
+        (
+            quote!(#[allow(unused_unsafe)] pub unsafe),
+            quote! {
+                ///
+                /// # Safety
+                ///
+                /// The host may write through the pointers passed to this function, so they must
+                /// be valid for the writes the import is documented to perform.
+            },
+        )
+    } else {
+        (quote!(pub), quote!())
+    };
+
+    if func.noreturn {
+        return quote! {
+            #docs
+            #safety_docs
+            #unsafety fn #ident(#(#params),*) -> ! {
+                #(#prepared_params)*
+                unsafe { raw::#ident(#(#lowered_params),*) }
+            }
+        };
+    }
+
+    let err = match func.results.get(0) {
+        Some(err) => err,
+        None => {
+            return quote! {
+                #docs
+                #safety_docs
+                #unsafety fn #ident(#(#params),*) {
+                    #(#prepared_params)*
+                    unsafe { raw::#ident(#(#lowered_params),*) }
+                }
+            };
+        }
+    };
+    // Only the raw import is available for functions with results the wrapper can't represent.
+    let err_type = guest_type_ref(names, &err.tref, &quote!('static));
+    let err_repr = match &*err.tref.type_() {
+        witx::Type::Enum(e) => int_repr_tokens(e.repr),
+        _ => return quote!(),
+    };
+
+    // Results past the first are written by the host through pointers passed as trailing
+    // arguments.
+    let rets = func.results.iter().skip(1).collect::<Vec<_>>();
+    if rets.iter().any(|r| needs_lifetime(&r.tref)) {
+        return quote!();
+    }
+    let ret_names = rets
+        .iter()
+        .map(|r| names.func_param(&r.name))
+        .collect::<Vec<_>>();
+    let ret_types = rets
+        .iter()
+        .map(|r| guest_type_ref(names, &r.tref, &quote!('static)))
+        .collect::<Vec<_>>();
+
+    // Avoid parenthesizing a single result, which would trigger `unused_parens` in user crates,
+    // and an `unsafe` block without results, which would trigger `unused_unsafe`. The host wrote
+    // every result before returning success.
+    let (ok_type, ok_val) = match ret_names.len() {
+        0 => (quote!(()), quote!(())),
+        1 => {
+            let (name, ty) = (&ret_names[0], &ret_types[0]);
+            (quote!(#ty), quote!(unsafe { #name.assume_init() }))
+        }
+        _ => (
+            quote!((#(#ret_types),*)),
+            quote!(unsafe { (#(#ret_names.assume_init()),*) }),
+        ),
+    };
+
+    quote! {
+        #docs
+        #safety_docs
+        #unsafety fn #ident(#(#params),*) -> Result<#ok_type, #err_type> {
+            #(#prepared_params)*
+            #(let mut #ret_names = ::core::mem::MaybeUninit::<#ret_types>::uninit();)*
+            let ret = unsafe {
+                raw::#ident(
+                    #(#lowered_params,)*
+                    #(#ret_names.as_mut_ptr() as i32),*
+                )
+            };
+            // By convention the first variant of an error enum signifies success.
+            if ret != 0 {
+                return Err(#err_type::from_raw(ret as #err_repr));
+            }
+            Ok(#ok_val)
+        }
+    }
+}
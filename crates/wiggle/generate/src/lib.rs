@@ -1,6 +1,7 @@
 pub mod config;
 mod error_transform;
 mod funcs;
+mod guest;
 mod lifetimes;
 mod module_trait;
 mod names;
@@ -11,14 +12,20 @@ use quote::quote;
 
 use lifetimes::anon_lifetime;
 
-pub use config::Config;
+pub use config::{AsyncConf, Config, GuestConfig};
 pub use error_transform::{ErrorTransform, UserErrorType};
 pub use funcs::define_func;
+pub use guest::generate_guest;
 pub use module_trait::define_module_trait;
 pub use names::Names;
 pub use types::define_datatype;
 
-pub fn generate(doc: &witx::Document, names: &Names, errs: &ErrorTransform) -> TokenStream {
+pub fn generate(
+    doc: &witx::Document,
+    names: &Names,
+    errs: &ErrorTransform,
+    async_conf: &AsyncConf,
+) -> TokenStream {
     // TODO at some point config should grow more ability to configure name
     // overrides.
     let rt = names.runtime_mod();
@@ -51,8 +58,19 @@ pub fn generate(doc: &witx::Document, names: &Names, errs: &ErrorTransform) -> T
         let modname = names.module(&module.name);
         let fs = module
             .funcs()
-            .map(|f| define_func(&names, &module, &f, &errs));
-        let modtrait = define_module_trait(&names, &module, &errs);
+            .map(|f| define_func(&names, &module, &f, &errs, &async_conf));
+        let modtrait = define_module_trait(&names, &module, &errs, &async_conf);
+        // Other generators, such as wasmtime-wiggle's, are configured with
+        // their own `async` list: these markers let them check at compile
+        // time that it matches this one.
+        let asyncness = module.funcs().map(|f| {
+            let ident = names.func(&f.name);
+            if async_conf.is_async(module.name.as_str(), f.name.as_str()) {
+                quote!(pub const #ident: #rt::AsyncFunc = #rt::AsyncFunc;)
+            } else {
+                quote!(pub const #ident: #rt::SyncFunc = #rt::SyncFunc;)
+            }
+        });
         let ctx_type = names.ctx_type();
         quote!(
             pub mod #modname {
@@ -61,6 +79,12 @@ pub fn generate(doc: &witx::Document, names: &Names, errs: &ErrorTransform) -> T
                 #(#fs)*
 
                 #modtrait
+
+                #[doc(hidden)]
+                #[allow(non_upper_case_globals)]
+                pub mod asyncness {
+                    #(#asyncness)*
+                }
             }
         )
     });
@@ -1,6 +1,7 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
+use crate::config::AsyncConf;
 use crate::error_transform::ErrorTransform;
 use crate::lifetimes::{anon_lifetime, LifetimeExt};
 use crate::names::Names;
@@ -21,7 +22,13 @@ pub fn passed_by_reference(ty: &witx::Type) -> bool {
     }
 }
 
-pub fn define_module_trait(names: &Names, m: &Module, errxform: &ErrorTransform) -> TokenStream {
+pub fn define_module_trait(
+    names: &Names,
+    m: &Module,
+    errxform: &ErrorTransform,
+    async_conf: &AsyncConf,
+) -> TokenStream {
+    let rt = names.runtime_mod();
     let traitname = names.trait_name(&m.name);
     let traitmethods = m.funcs().map(|f| {
         // Check if we're returning an entity anotated with a lifetime,
@@ -38,6 +45,11 @@ pub fn define_module_trait(names: &Names, m: &Module, errxform: &ErrorTransform)
             (anon_lifetime(), true)
         };
         let funcname = names.func(&f.name);
+        let asyncness = if async_conf.is_async(m.name.as_str(), f.name.as_str()) {
+            quote!(async)
+        } else {
+            quote!()
+        };
         let args = f.params.iter().map(|arg| {
             let arg_name = names.func_param(&arg.name);
             let arg_typename = names.type_ref(&arg.tref, lifetime.clone());
@@ -67,12 +79,20 @@ pub fn define_module_trait(names: &Names, m: &Module, errxform: &ErrorTransform)
             .unwrap_or(quote!(()));
 
         if is_anonymous {
-            quote!(fn #funcname(&self, #(#args),*) -> Result<(#(#rets),*), #err>;)
+            quote!(#asyncness fn #funcname(&self, #(#args),*) -> Result<(#(#rets),*), #err>;)
         } else {
-            quote!(fn #funcname<#lifetime>(&self, #(#args),*) -> Result<(#(#rets),*), #err>;)
+            quote!(#asyncness fn #funcname<#lifetime>(&self, #(#args),*) -> Result<(#(#rets),*), #err>;)
         }
     });
+    // Traits with `async fn` methods are expanded by `async_trait`. The generated futures
+    // borrow guest memory, which is not `Send`, so neither are the futures.
+    let async_attr = if async_conf.contains_module(m.name.as_str()) {
+        quote!(#[#rt::async_trait(?Send)])
+    } else {
+        quote!()
+    };
     quote! {
+        #async_attr
         pub trait #traitname {
             #(#traitmethods)*
         }
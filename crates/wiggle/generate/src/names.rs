@@ -104,6 +104,16 @@ impl Names {
         format_ident!("{}", id.as_str().to_shouty_snake_case())
     }
 
+    /// For constants of enum, flags and int types in guest bindings, which are prefixed with
+    /// the type name, e.g. `ERRNO_SUCCESS`.
+    pub fn guest_const(&self, ty: &Id, member: &Id) -> Ident {
+        format_ident!(
+            "{}_{}",
+            ty.as_str().to_shouty_snake_case(),
+            member.as_str().to_shouty_snake_case()
+        )
+    }
+
     /// Convert a struct member from its [`Id`][witx] name to its Rust [`Ident`][id] representation.
     ///
     /// [id]: https://docs.rs/proc-macro2/*/proc_macro2/struct.Ident.html
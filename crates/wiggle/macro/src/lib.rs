@@ -30,6 +30,14 @@ use syn::parse_macro_input;
 ///   CARGO_MANIFEST_DIR of the crate where the macro is invoked.
 /// * `ctx` takes a type name. This type must implement all of the module
 ///    traits
+/// * `errors` optionally maps witx error types to rich error types returned
+///    by the module trait methods.
+/// * `async` optionally lists functions whose module trait methods should be
+///    `async fn`, e.g. `async: { module_name::{func_a, func_b} }`, using the
+///    witx names of the module and functions. A module trait with any async
+///    methods is declared with `#[wiggle::async_trait(?Send)]`, and its
+///    implementations must carry the same attribute. The abi-level functions
+///    for these methods are `async fn` too.
 ///
 /// ## Example
 ///
@@ -97,8 +105,12 @@ pub fn from_witx(args: TokenStream) -> TokenStream {
 
     let error_transform = wiggle_generate::ErrorTransform::new(&config.errors, &doc)
         .expect("validating error transform");
+    config
+        .async_
+        .validate(&doc)
+        .expect("validating async functions");
 
-    let code = wiggle_generate::generate(&doc, &names, &error_transform);
+    let code = wiggle_generate::generate(&doc, &names, &error_transform, &config.async_);
     let metadata = if cfg!(feature = "wiggle_metadata") {
         wiggle_generate::generate_metadata(&doc, &names)
     } else {
@@ -107,3 +119,56 @@ pub fn from_witx(args: TokenStream) -> TokenStream {
 
     TokenStream::from(quote! { #code #metadata })
 }
+
+/// This macro expands to guest-side bindings for a witx document, for use by
+/// code compiled to WebAssembly which imports the interface:
+///
+/// * The `types` module contains a definition for each `typename` declared in
+///   the witx document, with the same memory layout as the host side uses.
+///   Structs and unions are `#[repr(C)]`, enums are transparent wrappers of
+///   their integer representation, and flags, ints, handles and other aliases
+///   are type aliases. Constants for enum variants, flags and int constants
+///   are prefixed with the type name, e.g. `ERRNO_SUCCESS`.
+///
+/// * For each `module` defined in the witx document, a Rust module is defined
+///   with a `raw` submodule holding the `#[link(wasm_import_module)]` import
+///   declarations, which take core WebAssembly types, and a wrapper function
+///   per import. Wrappers take strings as `&str`, arrays as slices, structs
+///   and unions by reference, and return `Result<($return_types), $error_type>`,
+///   where the first variant of the error enum signifies success. They're safe
+///   to call, except for those taking a `pointer` (directly, or inside a
+///   struct, union or array) which the host may write through.
+///
+/// Strings and arrays inside structs, unions and arrays are `RawSlice`s, whose
+/// length is a `u32`. On 32-bit targets the size and alignment of every struct
+/// and union is checked at compile time against the layout the host uses.
+///
+/// The following functions have no wrapper, only a `raw` import:
+///
+/// * Functions which return strings or arrays. The host bindings can't return
+///   them either.
+/// * Functions whose error result isn't an enum.
+///
+/// Only the `witx` (or `witx_literal`) argument is accepted.
+///
+/// ## Example
+///
+/// ```
+/// wiggle::guest_from_witx!({
+///     witx: ["../tests/atoms.witx"],
+/// });
+///
+/// # #[cfg(target_arch = "wasm32")]
+/// fn double(x: u32) -> Result<f32, types::Errno> {
+///     atoms::double_int_return_float(x)
+/// }
+/// # fn main() {}
+/// ```
+#[proc_macro]
+pub fn guest_from_witx(args: TokenStream) -> TokenStream {
+    let config = parse_macro_input!(args as wiggle_generate::GuestConfig);
+    let doc = config.load_document();
+    // Guest bindings never refer to a context type or the wiggle runtime.
+    let names = wiggle_generate::Names::new(&quote::format_ident!("GuestCtx"), quote!(wiggle));
+    TokenStream::from(wiggle_generate::generate_guest(&doc, &names))
+}
@@ -0,0 +1,62 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, Thread};
+
+/// Runs `future` to completion on the current thread, parking the thread
+/// whenever the future is not ready to make progress.
+///
+/// This is how embeddings without support for async host functions can call
+/// the `async fn` methods generated for functions listed in the `async` field
+/// of `from_witx!`. The calling thread is blocked
+/// until the future completes, so the future must be driven by wakeups from
+/// some other thread, for example an I/O reactor running on a separate
+/// executor.
+///
+/// Don't call this, or wasm code which calls such host functions, from a
+/// thread owned by an async executor: it ties up that thread for as long as
+/// the future is pending, and deadlocks if the future waits on work that has
+/// to run on the same thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = future;
+    // Safety: `future` is shadowed and never moved again after being pinned.
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    let thread = Arc::new(thread::current());
+    let waker = unsafe { Waker::from_raw(raw_waker(thread)) };
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(val) => return val,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+// A `Waker` which unparks the thread it was created on. The data pointer is an
+// `Arc<Thread>` converted with `Arc::into_raw`.
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+fn raw_waker(thread: Arc<Thread>) -> RawWaker {
+    RawWaker::new(Arc::into_raw(thread) as *const (), &VTABLE)
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    let thread = Arc::from_raw(data as *const Thread);
+    let cloned = thread.clone();
+    std::mem::forget(thread);
+    raw_waker(cloned)
+}
+
+unsafe fn wake(data: *const ()) {
+    let thread = Arc::from_raw(data as *const Thread);
+    thread.unpark();
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    (*(data as *const Thread)).unpark();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    drop(Arc::from_raw(data as *const Thread));
+}
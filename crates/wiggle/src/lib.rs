@@ -6,21 +6,34 @@ use std::slice;
 use std::str;
 use std::sync::Arc;
 
-pub use wiggle_macro::from_witx;
+pub use async_trait::async_trait;
+pub use wiggle_macro::{from_witx, guest_from_witx};
 
 #[cfg(feature = "wiggle_metadata")]
 pub use witx;
 
 mod error;
+mod executor;
 mod guest_type;
 mod region;
 
 pub extern crate tracing;
 
 pub use error::GuestError;
+pub use executor::block_on;
 pub use guest_type::{GuestErrorType, GuestType, GuestTypeTransparent};
 pub use region::Region;
 
+/// The type of the markers `from_witx!` emits, in each module's hidden
+/// `asyncness` module, for the functions listed in its `async` field.
+#[doc(hidden)]
+pub struct AsyncFunc;
+
+/// The type of the markers `from_witx!` emits for functions which aren't
+/// async, see [`AsyncFunc`].
+#[doc(hidden)]
+pub struct SyncFunc;
+
 /// A trait which abstracts how to get at the region of host memory taht
 /// contains guest memory.
 ///
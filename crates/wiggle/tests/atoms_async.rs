@@ -0,0 +1,97 @@
+use proptest::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wiggle::GuestMemory;
+use wiggle_test::{impl_errno, HostMemory, MemArea, WasiCtx};
+
+wiggle::from_witx!({
+    witx: ["$CARGO_MANIFEST_DIR/tests/atoms.witx"],
+    ctx: WasiCtx,
+    async: { atoms::{double_int_return_float} },
+});
+
+impl_errno!(types::Errno, types::GuestErrorConversion);
+
+// Embedders' own `async` lists are checked against these markers.
+const _: wiggle::AsyncFunc = atoms::asyncness::double_int_return_float;
+const _: wiggle::SyncFunc = atoms::asyncness::int_float_args;
+
+#[wiggle::async_trait(?Send)]
+impl<'a> atoms::Atoms for WasiCtx<'a> {
+    fn int_float_args(&self, _an_int: u32, _an_float: f32) -> Result<(), types::Errno> {
+        Ok(())
+    }
+    async fn double_int_return_float(
+        &self,
+        an_int: u32,
+    ) -> Result<types::AliasToFloat, types::Errno> {
+        YieldOnce(false).await;
+        Ok((an_int as f32) * 2.0)
+    }
+}
+
+/// A future which is pending the first time it is polled, to make sure the
+/// generated code copes with suspension.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DoubleIntExercise {
+    pub input: u32,
+    pub return_loc: MemArea,
+}
+
+impl DoubleIntExercise {
+    pub fn test(&self) {
+        let ctx = WasiCtx::new();
+        let host_memory = HostMemory::new();
+
+        let e = wiggle::block_on(atoms::double_int_return_float(
+            &ctx,
+            &host_memory,
+            self.input as i32,
+            self.return_loc.ptr as i32,
+        ));
+
+        let return_val = host_memory
+            .ptr::<types::AliasToFloat>(self.return_loc.ptr)
+            .read()
+            .expect("failed to read return");
+        assert_eq!(e, types::Errno::Ok.into(), "errno");
+        assert_eq!(return_val, (self.input as f32) * 2.0, "return val");
+    }
+
+    pub fn strat() -> BoxedStrategy<Self> {
+        (prop::num::u32::ANY, HostMemory::mem_area_strat(4))
+            .prop_map(|(input, return_loc)| DoubleIntExercise { input, return_loc })
+            .boxed()
+    }
+}
+
+proptest! {
+    #[test]
+    fn double_int_return_float(e in DoubleIntExercise::strat()) {
+        e.test()
+    }
+}
+
+#[test]
+fn sync_funcs_stay_sync() {
+    let ctx = WasiCtx::new();
+    let host_memory = HostMemory::new();
+    let e = atoms::int_float_args(&ctx, &host_memory, 1, 2.0);
+    assert_eq!(e, types::Errno::Ok.into(), "int_float_args error");
+}
@@ -0,0 +1,183 @@
+use std::mem::{align_of, size_of};
+use wiggle::GuestType;
+use wiggle_test::{HostMemory, WasiCtx};
+
+mod structs {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/structs.witx"],
+    });
+}
+
+mod union {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/union.witx"],
+    });
+}
+
+// The wrappers of these modules aren't called, but they cover the lowering of strings, arrays
+// and pointers.
+mod strings {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/strings.witx"],
+    });
+}
+
+mod arrays {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/arrays.witx"],
+    });
+}
+
+mod pointers {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/pointers.witx"],
+    });
+}
+
+mod guest {
+    wiggle::guest_from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/guest.witx"],
+    });
+}
+
+mod host {
+    use wiggle_test::{impl_errno, WasiCtx};
+
+    wiggle::from_witx!({
+        witx: ["$CARGO_MANIFEST_DIR/tests/guest.witx"],
+        ctx: WasiCtx,
+    });
+
+    impl_errno!(types::Errno, types::GuestErrorConversion);
+
+    impl<'a> guest_example::GuestExample for WasiCtx<'a> {
+        fn guest_check_args(
+            &self,
+            fd: types::Fd,
+            config: types::CarConfig,
+            excuse: types::Excuse,
+            weight: f64,
+        ) -> Result<(), types::Errno> {
+            if fd == types::Fd::from(7)
+                && config == types::CarConfig::AWD | types::CarConfig::SUV
+                && excuse == types::Excuse::Traffic
+                && weight == 1.5
+            {
+                Ok(())
+            } else {
+                Err(types::Errno::DontWantTo)
+            }
+        }
+    }
+}
+
+// Stands in for the host import of the guest bindings in `guest::guest_example::raw`, forwarding
+// the core wasm arguments to the host bindings.
+#[export_name = "guest_check_args"]
+extern "C" fn guest_check_args(fd: i32, config: i32, excuse: i32, weight: f64) -> i32 {
+    let ctx = WasiCtx::new();
+    let host_memory = HostMemory::new();
+    host::guest_example::guest_check_args(&ctx, &host_memory, fd, config, excuse, weight)
+}
+
+macro_rules! assert_same_layout {
+    ($guest:ty, $host:ty) => {
+        assert_eq!(
+            size_of::<$guest>(),
+            <$host as GuestType>::guest_size() as usize,
+            "size of {}",
+            stringify!($guest)
+        );
+        assert_eq!(
+            align_of::<$guest>(),
+            <$host as GuestType>::guest_align(),
+            "alignment of {}",
+            stringify!($guest)
+        );
+    };
+}
+
+#[test]
+fn enums() {
+    use union::types::*;
+    assert_eq!(size_of::<Errno>(), 4);
+    assert_eq!(size_of::<Excuse>(), 1);
+    assert_eq!(ERRNO_OK.raw(), 0);
+    assert_eq!(ERRNO_PICKET_LINE, Errno::from_raw(4));
+    assert_eq!(ERRNO_PICKET_LINE.name(), "PICKET_LINE");
+    assert_eq!(
+        ERRNO_PICKET_LINE.message(),
+        "Well, that's a picket line alright!"
+    );
+    assert_eq!(EXCUSE_TRAFFIC.to_string(), "TRAFFIC (1)");
+}
+
+#[test]
+fn structs() {
+    use structs::types::*;
+    assert_eq!(size_of::<PairInts>(), 8);
+    assert_eq!(align_of::<PairInts>(), 4);
+    let bytes = [1u8, 2, 3];
+    let s = StructOfArray {
+        arr: RawSlice::new(&bytes),
+    };
+    assert_eq!(s.arr.ptr, bytes.as_ptr());
+    assert_eq!(s.arr.len, 3);
+}
+
+#[test]
+fn unions() {
+    use union::types::*;
+    assert_eq!(size_of::<Reason>(), 8);
+    let r = Reason {
+        tag: EXCUSE_TRAFFIC,
+        u: ReasonU { traffic: -1 },
+    };
+    assert_eq!(r.tag, EXCUSE_TRAFFIC);
+    assert_eq!(unsafe { r.u.traffic }, -1);
+}
+
+#[test]
+fn layouts_match_host() {
+    assert_same_layout!(guest::types::Errno, host::types::Errno);
+    assert_same_layout!(guest::types::Excuse, host::types::Excuse);
+    assert_same_layout!(guest::types::Fd, host::types::Fd);
+    assert_same_layout!(guest::types::CarConfig, host::types::CarConfig);
+    assert_same_layout!(guest::types::PairInts, host::types::PairInts);
+    assert_same_layout!(guest::types::Reason, host::types::Reason);
+}
+
+// Pointers and `RawSlice`s only have the host's layout when the guest is 32-bit.
+#[cfg(target_pointer_width = "32")]
+#[test]
+fn pointer_layouts_match_host() {
+    assert_same_layout!(guest::types::RawSlice<u8>, host::types::SomeBytes);
+    assert_same_layout!(guest::types::BytesAndPtr, host::types::BytesAndPtr);
+    assert_same_layout!(guest::types::BytesReason, host::types::BytesReason);
+}
+
+#[test]
+fn constants_match_host() {
+    use guest::types::*;
+    assert_eq!(
+        ERRNO_PICKET_LINE.raw(),
+        u32::from(host::types::Errno::PicketLine)
+    );
+    assert_eq!(
+        EXCUSE_SLEEPING.raw(),
+        u8::from(host::types::Excuse::Sleeping)
+    );
+    assert_eq!(CAR_CONFIG_SUV, u8::from(host::types::CarConfig::SUV));
+}
+
+#[test]
+fn call_wrappers() {
+    use guest::types::*;
+    guest::guest_example::guest_check_args(7, CAR_CONFIG_AWD | CAR_CONFIG_SUV, EXCUSE_TRAFFIC, 1.5)
+        .expect("arguments are lowered as the host expects");
+    let err =
+        guest::guest_example::guest_check_args(7, CAR_CONFIG_AWD, EXCUSE_TRAFFIC, 1.5).unwrap_err();
+    assert_eq!(err, ERRNO_DONT_WANT_TO);
+    let err = guest::guest_example::guest_check_args(8, 0, EXCUSE_SLEEPING, 0.0).unwrap_err();
+    assert_eq!(err, ERRNO_DONT_WANT_TO);
+}
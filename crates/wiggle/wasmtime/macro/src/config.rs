@@ -7,7 +7,7 @@ use {
         punctuated::Punctuated,
         Error, Ident, Path, Result, Token,
     },
-    wiggle_generate::config::{CtxConf, WitxConf},
+    wiggle_generate::config::{AsyncConf, CtxConf, WitxConf},
 };
 
 #[derive(Debug, Clone)]
@@ -17,6 +17,7 @@ pub struct Config {
     pub ctx: CtxConf,
     pub modules: ModulesConf,
     pub missing_memory: MissingMemoryConf,
+    pub async_: AsyncConf,
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +27,7 @@ pub enum ConfigField {
     Ctx(CtxConf),
     Modules(ModulesConf),
     MissingMemory(MissingMemoryConf),
+    Async(AsyncConf),
 }
 
 mod kw {
@@ -67,6 +69,10 @@ impl Parse for ConfigField {
             input.parse::<kw::missing_memory>()?;
             input.parse::<Token![:]>()?;
             Ok(ConfigField::MissingMemory(input.parse()?))
+        } else if lookahead.peek(Token![async]) {
+            input.parse::<Token![async]>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Async(input.parse()?))
         } else {
             Err(lookahead.error())
         }
@@ -80,6 +86,7 @@ impl Config {
         let mut ctx = None;
         let mut modules = None;
         let mut missing_memory = None;
+        let mut async_ = None;
         for f in fields {
             match f {
                 ConfigField::Target(c) => {
@@ -112,6 +119,12 @@ impl Config {
                     }
                     missing_memory = Some(c);
                 }
+                ConfigField::Async(c) => {
+                    if async_.is_some() {
+                        return Err(Error::new(err_loc, "duplicate `async` field"));
+                    }
+                    async_ = Some(c);
+                }
             }
         }
         Ok(Config {
@@ -121,6 +134,7 @@ impl Config {
             modules: modules.ok_or_else(|| Error::new(err_loc, "`modules` field required"))?,
             missing_memory: missing_memory
                 .ok_or_else(|| Error::new(err_loc, "`missing_memory` field required"))?,
+            async_: async_.unwrap_or_default(),
         })
     }
 
@@ -2,7 +2,7 @@ use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 use syn::parse_macro_input;
-use wiggle_generate::Names;
+use wiggle_generate::{AsyncConf, Names};
 
 mod config;
 
@@ -44,11 +44,21 @@ use config::{MissingMemoryConf, ModuleConf, TargetConf};
 /// * `missing_memory`: Describes the error value to return in case the calling module does not
 ///   export a Memory as `"memory"`. This value is given in braces, e.g. `missing_memory: {
 ///   wasi_common::wasi::Errno::Inval }`.
+/// * `async`: Optional, must list the same functions as the `async` field of the
+///   `wasmtime_wiggle::from_witx` invocation at `target`, and it's a compile error if the
+///   two differ. These functions are defined with `wasmtime::Func::wrap_async`, so the
+///   `wasmtime::Store` given to the generated constructor must have
+///   `wasmtime::Config::async_support` enabled, and wasm calling them must be run with
+///   `wasmtime::Func::call_async` or `wasmtime::Instance::new_async`.
 ///
 #[proc_macro]
 pub fn wasmtime_integration(args: TokenStream) -> TokenStream {
     let config = parse_macro_input!(args as config::Config);
     let doc = config.load_document();
+    config
+        .async_
+        .validate(&doc)
+        .expect("validating async functions");
     let names = Names::new(&config.ctx.name, quote!(wasmtime_wiggle));
 
     let modules = config.modules.iter().map(|(name, module_conf)| {
@@ -61,6 +71,7 @@ pub fn wasmtime_integration(args: TokenStream) -> TokenStream {
             &names,
             &config.target,
             &config.missing_memory,
+            &config.async_,
         )
     });
     quote!( #(#modules)* ).into()
@@ -72,6 +83,7 @@ fn generate_module(
     names: &Names,
     target_conf: &TargetConf,
     missing_mem_conf: &MissingMemoryConf,
+    async_conf: &AsyncConf,
 ) -> TokenStream2 {
     let fields = module.funcs().map(|f| {
         let name_ident = names.func(&f.name);
@@ -103,7 +115,8 @@ fn generate_module(
             let name_ident = names.func(&f.name);
             quote! { let #name_ident = wasmtime::Func::wrap(store, #func_override); }
         } else {
-            generate_func(&f, names, missing_mem_conf, &target_module)
+            let is_async = async_conf.is_async(module.name.as_str(), f.name.as_str());
+            generate_func(&f, names, missing_mem_conf, &target_module, is_async)
         }
     });
 
@@ -113,7 +126,7 @@ fn generate_module(
         .as_ref()
         .map(|docs| quote!( #[doc = #docs] ))
         .unwrap_or_default();
-    let constructor_docs = format!(
+    let mut constructor_docs = format!(
         "Creates a new [`{}`] instance.
 
 External values are allocated into the `store` provided and
@@ -121,6 +134,20 @@ configuration of the wasi instance itself should be all
 contained in the `cx` parameter.",
         module_conf.name.to_string()
     );
+    let async_funcs = module
+        .funcs()
+        .filter(|f| async_conf.is_async(module.name.as_str(), f.name.as_str()))
+        .map(|f| format!("`{}`", f.name.as_str()))
+        .collect::<Vec<_>>();
+    if !async_funcs.is_empty() {
+        constructor_docs.push_str(&format!(
+            "
+
+The functions {} are async, so this panics if the `store`
+doesn't have `wasmtime::Config::async_support` enabled.",
+            async_funcs.join(", ")
+        ));
+    }
 
     let ctx_type = names.ctx_type();
 
@@ -167,6 +194,7 @@ fn generate_func(
     names: &Names,
     missing_mem_conf: &MissingMemoryConf,
     target_module: &TokenStream2,
+    is_async: bool,
 ) -> TokenStream2 {
     let missing_mem_err = &missing_mem_conf.err;
     let name_ident = names.func(&func.name);
@@ -195,28 +223,66 @@ fn generate_func(
 
     let runtime = names.runtime_mod();
 
-    quote! {
-        let my_cx = cx.clone();
-        let #name_ident = wasmtime::Func::wrap(
-            store,
-            move |caller: wasmtime::Caller<'_> #(,#arg_decls)*| -> #ret_ty {
-                unsafe {
-                    let mem = match caller.get_export("memory") {
-                        Some(wasmtime::Extern::Memory(m)) => m,
-                        _ => {
-                            wasmtime_wiggle::tracing::warn!("callee does not export a memory as \"memory\"");
-                            let e = { #missing_mem_err };
-                            #handle_early_error
-                        }
-                    };
-                    let mem = #runtime::WasmtimeGuestMemory::new(mem);
+    // A mismatch between the `async` lists given to this macro and to
+    // `from_witx!` is reported as a type error here.
+    let asyncness = if is_async {
+        quote!(#runtime::AsyncFunc)
+    } else {
+        quote!(#runtime::SyncFunc)
+    };
+
+    let get_mem = quote! {
+        let mem = match mem {
+            Some(wasmtime::Extern::Memory(m)) => m,
+            _ => {
+                wasmtime_wiggle::tracing::warn!("callee does not export a memory as \"memory\"");
+                let e = { #missing_mem_err };
+                #handle_early_error
+            }
+        };
+        let mem = #runtime::WasmtimeGuestMemory::new(mem);
+    };
+
+    let wrap = if is_async {
+        quote! {
+            wasmtime::Func::wrap_async(
+                store,
+                move |caller: wasmtime::Caller<'_> #(,#arg_decls)*| {
+                    // The future can't borrow the caller, so everything it
+                    // needs is taken out of it up front.
+                    let mem = caller.get_export("memory");
+                    let my_cx = my_cx.clone();
+                    async move {
+                        #get_mem
+                        #target_module::#name_ident(
+                            &my_cx.borrow(),
+                            &mem,
+                            #(#arg_names),*
+                        ).await
+                    }
+                }
+            )
+        }
+    } else {
+        quote! {
+            wasmtime::Func::wrap(
+                store,
+                move |caller: wasmtime::Caller<'_> #(,#arg_decls)*| -> #ret_ty {
+                    let mem = caller.get_export("memory");
+                    #get_mem
                     #target_module::#name_ident(
-                        &mut my_cx.borrow_mut(),
+                        &my_cx.borrow(),
                         &mem,
                         #(#arg_names),*
                     )
                 }
-            }
-        );
+            )
+        }
+    };
+
+    quote! {
+        const _: #asyncness = #target_module::asyncness::#name_ident;
+        let my_cx = cx.clone();
+        let #name_ident = #wrap;
     }
 }
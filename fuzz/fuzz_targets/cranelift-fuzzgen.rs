@@ -0,0 +1,204 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cranelift_codegen::data_value::DataValue;
+use cranelift_codegen::ir::TrapCode;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_filetests::{CompiledFunction, SingleFunctionCompiler};
+use cranelift_fuzzgen::TestCase;
+use cranelift_interpreter::interpreter::{Interpreter, InterpreterState};
+use cranelift_interpreter::step::{ControlFlow, CraneliftTrap};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+
+/// What calling the test case's function with some arguments results in: the values it returns,
+/// or the code of the trap it hits.
+type Outcome = Result<Vec<DataValue>, TrapCode>;
+
+/// Interpret the test case's function with the given arguments.
+fn interpret(testcase: &TestCase, args: &[DataValue]) -> Outcome {
+    let mut interpreter = Interpreter::new(InterpreterState::default());
+    match interpreter.call(&testcase.func, args) {
+        Ok(ControlFlow::Return(results)) => Ok(results.to_vec()),
+        Ok(ControlFlow::Trap(CraneliftTrap::User(code))) => Err(code),
+        Ok(control_flow) => panic!("unexpected control flow: {:?}", control_flow),
+        Err(e) => panic!("failed to interpret:\n{:?}\n{}", testcase, e),
+    }
+}
+
+/// The message a child process sends when the function returns, followed by the results.
+const RETURNED: u8 = b'R';
+/// The message a child process sends when the function traps, followed by the address of the
+/// trapping instruction.
+const TRAPPED: u8 = b'T';
+/// The message a child process sends when the function traps at an address it can't find.
+const TRAPPED_SOMEWHERE: u8 = b'U';
+
+/// The write end of the pipe a child process sends its message through.
+static mut MESSAGE_FD: libc::c_int = -1;
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn trap_pc(context: *const libc::ucontext_t) -> Option<u64> {
+    Some((*context).uc_mcontext.gregs[libc::REG_RIP as usize] as u64)
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn trap_pc(context: *const libc::ucontext_t) -> Option<u64> {
+    Some((*context).uc_mcontext.pc)
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+unsafe fn trap_pc(_context: *const libc::ucontext_t) -> Option<u64> {
+    None
+}
+
+/// Send the address of the trapping instruction to the parent process, and exit.
+extern "C" fn trap_handler(
+    _signum: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let mut message = [0; 9];
+    let len = match unsafe { trap_pc(context as *const libc::ucontext_t) } {
+        Some(pc) => {
+            message[0] = TRAPPED;
+            message[1..].copy_from_slice(&pc.to_le_bytes());
+            9
+        }
+        None => {
+            message[0] = TRAPPED_SOMEWHERE;
+            1
+        }
+    };
+    unsafe {
+        libc::write(MESSAGE_FD, message.as_ptr() as *const libc::c_void, len);
+        libc::_exit(0);
+    }
+}
+
+/// Call the compiled function in a child process, so that a trap in native code can be observed
+/// instead of taking down the fuzzer.
+///
+/// Returns `Err(None)` if the function trapped but the address of the trap couldn't be found on
+/// this platform.
+fn call_native(
+    testcase: &TestCase,
+    compiled: &CompiledFunction,
+    args: &[DataValue],
+) -> Result<Vec<DataValue>, Option<TrapCode>> {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0, "pipe failed");
+    let [read_fd, write_fd] = fds;
+
+    let child = unsafe { libc::fork() };
+    assert!(child >= 0, "fork failed");
+    if child == 0 {
+        unsafe {
+            libc::close(read_fd);
+            MESSAGE_FD = write_fd;
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = trap_handler as usize;
+            action.sa_flags = libc::SA_SIGINFO;
+            for &signal in &[
+                libc::SIGILL,
+                libc::SIGFPE,
+                libc::SIGSEGV,
+                libc::SIGBUS,
+                libc::SIGTRAP,
+            ] {
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+
+        let results = compiled.call(args);
+        let mut message = vec![RETURNED];
+        for value in &results {
+            let bits: i64 = match *value {
+                DataValue::I8(v) => v.into(),
+                DataValue::I16(v) => v.into(),
+                DataValue::I32(v) => v.into(),
+                DataValue::I64(v) => v,
+                _ => unreachable!("unexpected result {}", value),
+            };
+            message.extend_from_slice(&bits.to_le_bytes());
+        }
+        unsafe {
+            libc::write(
+                write_fd,
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+            );
+            libc::_exit(0);
+        }
+    }
+
+    unsafe { libc::close(write_fd) };
+    let mut message = vec![];
+    unsafe { File::from_raw_fd(read_fd) }
+        .read_to_end(&mut message)
+        .unwrap();
+    let mut status = 0;
+    unsafe { libc::waitpid(child, &mut status, 0) };
+    assert!(
+        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 && !message.is_empty(),
+        "native code crashed with wait status {:#x}",
+        status
+    );
+
+    let payload = &message[1..];
+    match message[0] {
+        RETURNED => Ok(payload
+            .chunks(8)
+            .zip(&testcase.func.signature.returns)
+            .map(|(bits, ret)| {
+                let bits = i64::from_le_bytes(bits.try_into().unwrap());
+                DataValue::from_integer(bits, ret.value_type).unwrap()
+            })
+            .collect()),
+        TRAPPED => {
+            let pc = u64::from_le_bytes(payload.try_into().unwrap()) as usize;
+            match compiled.trap_code_at(pc as *const u8) {
+                Some(code) => Err(Some(code)),
+                None => panic!("native code crashed at {:#x}, which isn't a trap site", pc),
+            }
+        }
+        TRAPPED_SOMEWHERE => Err(None),
+        _ => unreachable!(),
+    }
+}
+
+fuzz_target!(|testcase: TestCase| {
+    // Have native code check for integer overflow in divisions explicitly, as the interpreter
+    // does, instead of relying on the hardware trap that it can't tell from a division by zero.
+    let mut flags = settings::builder();
+    flags.enable("avoid_div_traps").unwrap();
+    let mut compiler = SingleFunctionCompiler::with_host_isa(settings::Flags::new(flags));
+
+    // Don't report the instructions the backend is known to get wrong again.
+    if testcase.known_failure(compiler.isa()).is_some() {
+        return;
+    }
+
+    let compiled = compiler.compile(testcase.func.clone()).unwrap();
+    for args in &testcase.inputs {
+        let expected = interpret(&testcase, args);
+        let actual = call_native(&testcase, &compiled, args);
+        let same = match (&expected, &actual) {
+            (Ok(expected), Ok(actual)) => expected == actual,
+            (Err(expected), Err(Some(actual))) => expected == actual,
+            (Err(_), Err(None)) => true,
+            _ => false,
+        };
+        assert!(
+            same,
+            "outcomes differ when called with {:?}: the interpreter gives {:?}, native code {:?}\n{:?}",
+            args, expected, actual, testcase
+        );
+    }
+});
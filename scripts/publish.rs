@@ -27,6 +27,7 @@ const CRATES_TO_PUBLISH: &[&str] = &[
     "cranelift-entity",
     "cranelift-bforest",
     "cranelift-codegen-shared",
+    "cranelift-isle",
     "cranelift-codegen-meta",
     "cranelift-codegen",
     "cranelift-reader",
@@ -53,6 +54,7 @@ const CRATES_TO_PUBLISH: &[&str] = &[
     // wasmtime
     "lightbeam",
     "wasmtime-environ",
+    "wasmtime-fiber",
     "wasmtime-runtime",
     "wasmtime-debug",
     "wasmtime-profiling",
@@ -108,6 +108,11 @@ pub struct RunCommand {
     )]
     wasm_timeout: Option<Duration>,
 
+    /// Print statistics about the compilation of the main module to stderr,
+    /// in the given format
+    #[structopt(long, value_name = "FORMAT", possible_values = &["json"])]
+    compile_stats: Option<String>,
+
     // NOTE: this must come last for trailing varargs
     /// The arguments to pass to the module
     #[structopt(value_name = "ARGS")]
@@ -248,6 +253,9 @@ impl RunCommand {
         // Read the wasm module binary either as `*.wat` or a raw binary.
         // Use "" as a default module name.
         let module = Module::from_file(linker.store().engine(), &self.module)?;
+        if self.compile_stats.is_some() {
+            eprintln!("{}", module.compilation_stats().to_json());
+        }
         linker
             .module("", &module)
             .context(format!("failed to instantiate {:?}", self.module))?;
@@ -0,0 +1,423 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use wasmtime::*;
+
+fn async_store() -> Store {
+    Store::new(&Engine::new(Config::new().async_support(true)))
+}
+
+/// Polls `future` until it's ready, without any executor.
+fn run<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(val) = future.as_mut().poll(&mut cx) {
+            break val;
+        }
+    }
+}
+
+fn dummy_waker() -> Waker {
+    return unsafe { Waker::from_raw(clone(ptr::null())) };
+
+    use std::ptr;
+
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    unsafe fn clone(ptr: *const ()) -> RawWaker {
+        RawWaker::new(ptr, &VTABLE)
+    }
+
+    unsafe fn noop(_ptr: *const ()) {}
+}
+
+/// A future which is pending the first time it's polled.
+struct PendingOnce {
+    already_polled: bool,
+}
+
+impl PendingOnce {
+    fn new() -> PendingOnce {
+        PendingOnce {
+            already_polled: false,
+        }
+    }
+}
+
+impl Future for PendingOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.already_polled {
+            Poll::Ready(())
+        } else {
+            self.already_polled = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+struct SetFlagOnDrop(Rc<Cell<bool>>);
+
+impl Drop for SetFlagOnDrop {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn smoke() -> anyhow::Result<()> {
+    let store = async_store();
+    let func = Func::wrap_async(&store, || async {});
+    run(func.call_async(&[]))?;
+
+    let func = Func::wrap(&store, || {});
+    run(func.call_async(&[]))?;
+    Ok(())
+}
+
+#[test]
+fn suspends_while_host_function_is_pending() -> anyhow::Result<()> {
+    let store = async_store();
+    let polls = Rc::new(Cell::new(0));
+    let polls2 = polls.clone();
+    let pending = Func::wrap_async(&store, move |a: i32, b: i64| {
+        let polls = polls2.clone();
+        async move {
+            PendingOnce::new().await;
+            polls.set(polls.get() + 1);
+            a as i64 + b
+        }
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $pending (param i32 i64) (result i64)))
+                (func (export "run") (result i64)
+                    i32.const 1
+                    i64.const 2
+                    call $pending
+                    i32.const 3
+                    i64.const 4
+                    call $pending
+                    i64.add))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[pending.into()]))?;
+    let func = instance.get_func("run").unwrap();
+
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(func.call_async(&[]));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(polls.get(), 0);
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(polls.get(), 1);
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(results) => assert_eq!(results?[0].unwrap_i64(), 10),
+        Poll::Pending => panic!("should be ready"),
+    }
+    assert_eq!(polls.get(), 2);
+    Ok(())
+}
+
+#[test]
+fn async_host_function_with_caller() -> anyhow::Result<()> {
+    let store = async_store();
+    let read = Func::wrap_async(&store, |caller: Caller<'_>, ptr: i32| {
+        // The caller can't be used across an `.await`, so its memory is
+        // looked up up front.
+        let mem = caller.get_export("memory").unwrap().into_memory().unwrap();
+        async move {
+            PendingOnce::new().await;
+            unsafe { i32::from(mem.data_unchecked()[ptr as usize]) }
+        }
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $read (param i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 10) "\2a")
+                (func (export "run") (result i32)
+                    i32.const 10
+                    call $read))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[read.into()]))?;
+    let results = run(instance.get_func("run").unwrap().call_async(&[]))?;
+    assert_eq!(results[0].unwrap_i32(), 42);
+    Ok(())
+}
+
+#[test]
+fn async_host_function_traps() -> anyhow::Result<()> {
+    let store = async_store();
+    let func = Func::wrap_async(&store, || async {
+        PendingOnce::new().await;
+        Err::<(), _>(Trap::new("boom"))
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $trap))
+                (func $run (export "run")
+                    call $trap))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[func.into()]))?;
+    let err = run(instance.get_func("run").unwrap().call_async(&[])).unwrap_err();
+    let trap = err.downcast::<Trap>()?;
+    assert!(trap.to_string().contains("boom"));
+    assert_eq!(trap.trace()[0].func_name(), Some("run"));
+    Ok(())
+}
+
+#[test]
+fn start_function_calls_async_host_function() -> anyhow::Result<()> {
+    let store = async_store();
+    let called = Rc::new(Cell::new(false));
+    let called2 = called.clone();
+    let mut linker = Linker::new(&store);
+    linker.define(
+        "",
+        "",
+        Func::wrap_async(&store, move || {
+            let called = called2.clone();
+            async move {
+                PendingOnce::new().await;
+                called.set(true);
+            }
+        }),
+    )?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $pending))
+                (start $pending))
+        "#,
+    )?;
+    run(linker.instantiate_async(&module))?;
+    assert!(called.get());
+    Ok(())
+}
+
+#[test]
+fn sync_host_function_calls_back_into_wasm() -> anyhow::Result<()> {
+    let store = async_store();
+    let pending = Func::wrap_async(&store, || PendingOnce::new());
+    let call_back = Func::wrap(&store, |caller: Caller<'_>| -> Result<i32, Trap> {
+        // Host functions already run on a fiber, so they can call wasm
+        // synchronously, even if it calls async host functions in turn.
+        let inner = caller.get_export("inner").unwrap().into_func().unwrap();
+        let results = inner.call(&[])?;
+        Ok(results[0].unwrap_i32() + 1)
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "pending" (func $pending))
+                (import "" "call_back" (func $call_back (result i32)))
+                (func (export "inner") (result i32)
+                    call $pending
+                    i32.const 41)
+                (func (export "run") (result i32)
+                    call $call_back))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(
+        &store,
+        &module,
+        &[pending.into(), call_back.into()],
+    ))?;
+    let results = run(instance.get_func("run").unwrap().call_async(&[]))?;
+    assert_eq!(results[0].unwrap_i32(), 42);
+    Ok(())
+}
+
+#[test]
+fn dropping_the_future_unwinds_wasm() -> anyhow::Result<()> {
+    let store = async_store();
+    let host_future_dropped = Rc::new(Cell::new(false));
+    let flag = host_future_dropped.clone();
+    let pending = Func::wrap_async(&store, move || {
+        let on_drop = SetFlagOnDrop(flag.clone());
+        async move {
+            PendingOnce::new().await;
+            drop(on_drop);
+            panic!("should not be resumed");
+        }
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $pending))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    if
+                        call $pending
+                    end
+                    i32.const 1))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[pending.into()]))?;
+    let func = instance.get_func("run").unwrap();
+
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let args = [Val::I32(1)];
+    let mut future = Box::pin(func.call_async(&args));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(!host_future_dropped.get());
+    drop(future);
+    assert!(host_future_dropped.get());
+
+    // The store is still usable afterwards.
+    let results = run(func.call_async(&[Val::I32(0)]))?;
+    assert_eq!(results[0].unwrap_i32(), 1);
+    Ok(())
+}
+
+#[test]
+fn interrupt_while_suspended() -> anyhow::Result<()> {
+    let engine = Engine::new(Config::new().async_support(true).interruptable(true));
+    let store = Store::new(&engine);
+    let pending = Func::wrap_async(&store, || PendingOnce::new());
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "" (func $pending))
+                (func (export "run")
+                    call $pending
+                    (loop br 0)))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[pending.into()]))?;
+    let func = instance.get_func("run").unwrap();
+
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(func.call_async(&[]));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    store.interrupt_handle()?.interrupt();
+    let err = match future.as_mut().poll(&mut cx) {
+        Poll::Ready(result) => result.unwrap_err(),
+        Poll::Pending => panic!("should be ready"),
+    };
+    assert!(err.to_string().contains("wasm trap: interrupt"));
+    Ok(())
+}
+
+#[test]
+fn stack_overflow_on_fiber() -> anyhow::Result<()> {
+    let store = async_store();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func $run (export "run")
+                    call $run))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(&store, &module, &[]))?;
+    let err = run(instance.get_func("run").unwrap().call_async(&[])).unwrap_err();
+    assert!(err.to_string().contains("call stack exhausted"));
+    Ok(())
+}
+
+// TODO(#1886): Cranelift only supports reference types on x64.
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn gc_skips_roots_on_suspended_fibers() -> anyhow::Result<()> {
+    let mut config = Config::new();
+    config.async_support(true).wasm_reference_types(true);
+    let store = Store::new(&Engine::new(&config));
+    let dropped = Rc::new(Cell::new(false));
+    let flag = dropped.clone();
+    let make = Func::wrap(&store, move || {
+        Some(ExternRef::new(SetFlagOnDrop(flag.clone())))
+    });
+    let pending = Func::wrap_async(&store, || PendingOnce::new());
+    let flag = dropped.clone();
+    let check = Func::wrap(&store, move |r: Option<ExternRef>| {
+        assert!(r.is_some());
+        assert!(!flag.get());
+    });
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "make" (func $make (result externref)))
+                (import "" "pending" (func $pending))
+                (import "" "check" (func $check (param externref)))
+                (func (export "run") (local externref)
+                    call $make
+                    local.set 0
+                    call $pending
+                    local.get 0
+                    call $check))
+        "#,
+    )?;
+    let instance = run(Instance::new_async(
+        &store,
+        &module,
+        &[make.into(), pending.into(), check.into()],
+    ))?;
+    let func = instance.get_func("run").unwrap();
+
+    let waker = dummy_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(func.call_async(&[]));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+
+    // The only reference is in a wasm frame on the suspended fiber, which a
+    // GC from here can't see.
+    store.gc();
+    assert!(!dropped.get());
+
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(result) => {
+            result?;
+        }
+        Poll::Pending => panic!("should be ready"),
+    }
+    drop(future);
+    store.gc();
+    assert!(dropped.get());
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "with async support enabled")]
+fn sync_call_in_async_store_panics() {
+    let store = async_store();
+    let func = Func::wrap(&store, || {});
+    let _ = func.call(&[]);
+}
+
+#[test]
+#[should_panic(expected = "without enabling async support")]
+fn call_async_in_sync_store_panics() {
+    let store = Store::default();
+    let func = Func::wrap(&store, || {});
+    let _ = run(func.call_async(&[]));
+}
+
+#[test]
+#[should_panic(expected = "without enabling async support")]
+fn wrap_async_in_sync_store_panics() {
+    let store = Store::default();
+    Func::wrap_async(&store, || async {});
+}
@@ -0,0 +1,223 @@
+use anyhow::Result;
+use wasmtime::*;
+
+fn interpreted_store() -> Result<Store> {
+    let mut config = Config::new();
+    config.strategy(Strategy::Interpreter)?;
+    Ok(Store::new(&Engine::new(&config)))
+}
+
+#[test]
+fn arithmetic_and_calls() -> Result<()> {
+    let store = interpreted_store()?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func $fib (export "fib") (param i32) (result i32)
+                    local.get 0
+                    i32.const 2
+                    i32.lt_u
+                    if (result i32)
+                        local.get 0
+                    else
+                        local.get 0
+                        i32.const 1
+                        i32.sub
+                        call $fib
+                        local.get 0
+                        i32.const 2
+                        i32.sub
+                        call $fib
+                        i32.add
+                    end)
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let fib = instance.get_func("fib").unwrap().get1::<i32, i32>()?;
+    assert_eq!(fib(10)?, 55);
+    Ok(())
+}
+
+#[test]
+fn memory_tables_and_imports() -> Result<()> {
+    let store = interpreted_store()?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "double" (func $double (param i64) (result i64)))
+                (memory (export "memory") 1)
+                (table 2 funcref)
+                (elem (i32.const 0) $double $load)
+                (type $i64_to_i64 (func (param i64) (result i64)))
+                (func $load (param i64) (result i64)
+                    i32.const 8
+                    i64.load)
+                (func (export "run") (param i64) (result i64)
+                    i32.const 8
+                    local.get 0
+                    i64.store
+                    local.get 0
+                    i32.const 1
+                    call_indirect (type $i64_to_i64)
+                    i32.const 0
+                    call_indirect (type $i64_to_i64))
+                (func (export "grow") (result i32)
+                    i32.const 1
+                    memory.grow)
+            )
+        "#,
+    )?;
+    let double = Func::wrap(&store, |x: i64| x * 2);
+    let instance = Instance::new(&store, &module, &[double.into()])?;
+
+    let run = instance.get_func("run").unwrap().get1::<i64, i64>()?;
+    assert_eq!(run(21)?, 42);
+    let memory = instance.get_memory("memory").unwrap();
+    assert_eq!(unsafe { memory.data_unchecked()[8] }, 21);
+
+    let grow = instance.get_func("grow").unwrap().get0::<i32>()?;
+    assert_eq!(grow()?, 1);
+    assert_eq!(memory.size(), 2);
+    Ok(())
+}
+
+#[test]
+fn traps() -> Result<()> {
+    let store = interpreted_store()?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory 1)
+                (func (export "oob") (result i32)
+                    i32.const 65536
+                    i32.load)
+                (func (export "div") (param i32) (result i32)
+                    i32.const 1
+                    local.get 0
+                    i32.div_u)
+                (func $recurse (export "recurse")
+                    call $recurse)
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let oob = instance.get_func("oob").unwrap().get0::<i32>()?;
+    let trap = oob().unwrap_err();
+    assert_eq!(trap.trap_code(), Some(TrapCode::MemoryOutOfBounds));
+
+    let div = instance.get_func("div").unwrap().get1::<i32, i32>()?;
+    let trap = div(0).unwrap_err();
+    assert_eq!(trap.trap_code(), Some(TrapCode::IntegerDivisionByZero));
+
+    let recurse = instance.get_func("recurse").unwrap().get0::<()>()?;
+    let trap = recurse().unwrap_err();
+    assert_eq!(trap.trap_code(), Some(TrapCode::StackOverflow));
+    Ok(())
+}
+
+/// Set in the child process which `runs_with_w_xor_x_enforced` spawns.
+#[cfg(target_os = "linux")]
+const W_XOR_X_CHILD: &str = "__WASMTIME_TEST_W_XOR_X_CHILD";
+
+#[test]
+#[cfg(target_os = "linux")]
+fn runs_with_w_xor_x_enforced() -> Result<()> {
+    use std::env;
+    use std::process::Command;
+
+    // Enforcing W^X can't be undone, so do it in a child process which runs
+    // only this test.
+    if env::var_os(W_XOR_X_CHILD).is_none() {
+        let output = Command::new(env::current_exe()?)
+            .args(&["--exact", "interpreter::runs_with_w_xor_x_enforced"])
+            .env(W_XOR_X_CHILD, "1")
+            .output()?;
+        assert!(
+            output.status.success(),
+            "child process failed with {}\nstdout: {}\nstderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Ok(());
+    }
+
+    // Have the kernel refuse to make any memory executable which isn't
+    // already, as a JIT compiler needs to.
+    const PR_SET_MDWE: libc::c_int = 65;
+    const PR_MDWE_REFUSE_EXEC_GAIN: libc::c_ulong = 1;
+    unsafe {
+        if libc::prctl(PR_SET_MDWE, PR_MDWE_REFUSE_EXEC_GAIN, 0, 0, 0) != 0 {
+            // This kernel is too old to enforce W^X, so there's nothing to
+            // test.
+            return Ok(());
+        }
+
+        let page = libc::mmap(
+            std::ptr::null_mut(),
+            4096,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(page, libc::MAP_FAILED);
+        assert_ne!(
+            libc::mprotect(page, 4096, libc::PROT_READ | libc::PROT_EXEC),
+            0,
+            "W^X isn't enforced"
+        );
+        libc::munmap(page, 4096);
+    }
+
+    let store = interpreted_store()?;
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (import "" "add" (func $add (param i32 i32) (result i32)))
+                (global $counter (export "counter") (mut i32) (i32.const 0))
+                (memory (export "memory") 1)
+                (table funcref (elem $add $inc))
+                (type $i32_i32_to_i32 (func (param i32 i32) (result i32)))
+                (func $inc (param i32 i32) (result i32)
+                    global.get $counter
+                    i32.const 1
+                    i32.add
+                    global.set $counter
+                    global.get $counter)
+                (func $start
+                    i32.const 0
+                    i32.const 0
+                    i32.const 1
+                    call_indirect (type $i32_i32_to_i32)
+                    drop)
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    i32.const 0
+                    call_indirect (type $i32_i32_to_i32)
+                    i32.const 1
+                    memory.grow
+                    i32.add)
+                (start $start)
+            )
+        "#,
+    )?;
+    let add = Func::wrap(&store, |a: i32, b: i32| a + b);
+    let instance = Instance::new(&store, &module, &[add.into()])?;
+
+    let counter = instance.get_global("counter").unwrap();
+    assert_eq!(counter.get().unwrap_i32(), 1);
+
+    let run = instance.get_func("run").unwrap();
+    assert_eq!(run.call(&[Val::I32(41)])?[0].unwrap_i32(), 43);
+    assert_eq!(run.get1::<i32, i32>()?(41)?, 44);
+    assert_eq!(instance.get_memory("memory").unwrap().size(), 3);
+    Ok(())
+}
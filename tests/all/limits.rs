@@ -0,0 +1,124 @@
+use anyhow::Result;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasmtime::*;
+
+struct StaticLimits {
+    memory_pages: u32,
+    table_elements: u32,
+}
+
+impl ResourceLimiter for StaticLimits {
+    fn memory_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        desired <= self.memory_pages
+    }
+
+    fn table_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        desired <= self.table_elements
+    }
+}
+
+#[test]
+fn limit_memory_and_table_growth() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (memory (export "m") 1)
+                (table (export "t") 1 funcref)
+                (func (export "grow") (param i32) (result i32)
+                    local.get 0
+                    memory.grow))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+    let memory = instance.get_memory("m").unwrap();
+    let table = instance.get_table("t").unwrap();
+    let grow = instance.get_func("grow").unwrap().get1::<i32, i32>()?;
+
+    // The limiter also applies to instances created before it was set.
+    store.set_limiter(StaticLimits {
+        memory_pages: 3,
+        table_elements: 2,
+    });
+
+    assert_eq!(memory.grow(1)?, 1);
+    assert_eq!(grow(1)?, 2);
+    assert_eq!(grow(1)?, -1);
+    assert!(memory.grow(1).is_err());
+    assert_eq!(memory.size(), 3);
+
+    assert_eq!(table.grow(1, Val::FuncRef(None))?, 1);
+    assert!(table.grow(1, Val::FuncRef(None)).is_err());
+    assert_eq!(table.size(), 2);
+    Ok(())
+}
+
+#[test]
+fn limit_host_memory_growth() -> Result<()> {
+    let store = Store::default();
+    store.set_limiter(StaticLimits {
+        memory_pages: 1,
+        table_elements: 0,
+    });
+    let memory = Memory::new(&store, MemoryType::new(Limits::new(1, None)));
+    assert!(memory.grow(1).is_err());
+    Ok(())
+}
+
+#[test]
+fn store_data() {
+    struct Dropped(Rc<Cell<bool>>);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let store = Store::default();
+    assert!(store.get::<Dropped>().is_none());
+    assert!(store.set(Dropped(dropped.clone())).is_ok());
+    assert!(store.set(Dropped(dropped.clone())).is_err());
+    assert!(store.get::<Dropped>().is_some());
+    assert!(store.set(1u32).is_ok());
+    assert_eq!(store.get::<u32>(), Some(&1));
+
+    // The rejected duplicate above was dropped, so reset the flag.
+    dropped.set(false);
+    drop(store);
+    assert!(dropped.get());
+}
+
+#[test]
+fn call_unchecked() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        store.engine(),
+        r#"
+            (module
+                (func (export "add") (param i32 i64) (result i64)
+                    local.get 0
+                    i64.extend_i32_s
+                    local.get 1
+                    i64.add)
+                (func (export "trap") unreachable))
+        "#,
+    )?;
+    let instance = Instance::new(&store, &module, &[])?;
+
+    let add = instance.get_func("add").unwrap();
+    let mut values = [3u128, 4u128];
+    unsafe {
+        add.call_unchecked(values.as_mut_ptr())?;
+    }
+    assert_eq!(values[0] as i64, 7);
+
+    let trap = instance.get_func("trap").unwrap();
+    let mut values = [0u128];
+    let err = unsafe { trap.call_unchecked(values.as_mut_ptr()).unwrap_err() };
+    assert!(err.to_string().contains("unreachable"));
+    Ok(())
+}
@@ -1,3 +1,4 @@
+mod async_functions;
 mod cli_tests;
 mod custom_signal_handler;
 mod debug;
@@ -9,7 +10,10 @@ mod iloop;
 mod import_calling_export;
 mod import_indexes;
 mod instance;
+#[cfg(feature = "interpreter")]
+mod interpreter;
 mod invoke_func_via_table;
+mod limits;
 mod linker;
 mod memory_creator;
 mod module_linking;
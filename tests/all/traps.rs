@@ -489,3 +489,48 @@ fn heap_out_of_bounds_trap() {
         TrapCode::MemoryOutOfBounds,
     );
 }
+
+#[test]
+fn spectre_mitigations_keep_bounds_and_signature_checks() -> Result<()> {
+    let wat = r#"
+        (module
+            (type $i32_to_i32 (func (param i32) (result i32)))
+            (memory 1)
+            (table 2 funcref)
+            (elem (i32.const 0) $double $nop)
+            (func $double (param i32) (result i32)
+                (i32.mul (local.get 0) (i32.const 2)))
+            (func $nop)
+            (func (export "call") (param i32) (result i32)
+                (call_indirect (type $i32_to_i32) (i32.const 21) (local.get 0)))
+            (func (export "load") (param i32) (result i32)
+                (i32.load (local.get 0)))
+        )
+    "#;
+
+    for &enable in &[true, false] {
+        let mut config = Config::new();
+        config.cranelift_spectre_mitigations(enable);
+        let store = Store::new(&Engine::new(&config));
+        let module = Module::new(store.engine(), wat)?;
+        let instance = Instance::new(&store, &module, &[])?;
+        let call = instance.get_func("call").unwrap().get1::<i32, i32>()?;
+        let load = instance.get_func("load").unwrap().get1::<i32, i32>()?;
+
+        assert_eq!(call(0)?, 42);
+        assert_eq!(
+            call(1).unwrap_err().trap_code(),
+            Some(TrapCode::BadSignature)
+        );
+        assert_eq!(
+            call(2).unwrap_err().trap_code(),
+            Some(TrapCode::TableOutOfBounds)
+        );
+        assert_eq!(load(0)?, 0);
+        assert_eq!(
+            load(65536).unwrap_err().trap_code(),
+            Some(TrapCode::MemoryOutOfBounds)
+        );
+    }
+    Ok(())
+}